@@ -44,6 +44,9 @@ pub trait IoContext {
 
 	/// Persistent peer id
 	fn persistent_peer_id(&self, peer: PeerId) -> Option<NodeId>;
+
+	/// Record a value for a peer in the node table.
+	fn tag_peer(&self, peer: PeerId, key: &str, value: u32);
 }
 
 
@@ -77,6 +80,10 @@ impl<T> IoContext for T where T: ?Sized + NetworkContext {
 	fn persistent_peer_id(&self, peer: PeerId) -> Option<NodeId> {
 		self.session_info(peer).and_then(|info| info.id)
 	}
+
+	fn tag_peer(&self, peer: PeerId, key: &str, value: u32) {
+		self.peer_annotator().tag(peer, key, value)
+	}
 }
 
 /// Basic context for the protocol.
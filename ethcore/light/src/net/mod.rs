@@ -28,6 +28,7 @@ use kvdb::DBValue;
 use parking_lot::{Mutex, RwLock};
 use std::time::{Duration, Instant};
 
+use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::sync::Arc;
@@ -84,6 +85,10 @@ pub const MAX_PROTOCOL_VERSION: u8 = 1;
 /// Packet count for PIP.
 pub const PACKET_COUNT: u8 = 9;
 
+/// Node table tag holding the milliseconds a peer last took to serve a request, so that fast servers can be
+/// preferred after a restart.
+pub const SERVED_MS_TAG: &'static str = "pip_served_ms";
+
 // packet ID definitions.
 mod packet {
 	// the status packet.
@@ -528,7 +533,8 @@ impl LightProtocol {
 	//   - check whether peer exists
 	//   - check whether request was made
 	//   - check whether request kinds match
-	fn pre_verify_response(&self, peer: &PeerId, raw: &UntrustedRlp) -> Result<IdGuard, Error> {
+	// Also returns how long the peer took to respond.
+	fn pre_verify_response(&self, peer: &PeerId, raw: &UntrustedRlp) -> Result<(IdGuard, Option<Duration>), Error> {
 		let req_id = ReqId(raw.val_at(0)?);
 		let cur_credits: U256 = raw.val_at(1)?;
 
@@ -539,7 +545,9 @@ impl LightProtocol {
 			Some(peer_info) => {
 				let mut peer_info = peer_info.lock();
 				let peer_info: &mut Peer = &mut *peer_info;
-				let req_info = peer_info.pending_requests.remove(&req_id, Instant::now());
+				let now = Instant::now();
+				let served = peer_info.pending_requests.sent_at(&req_id).map(|sent| now.duration_since(sent));
+				let req_info = peer_info.pending_requests.remove(&req_id, now);
 				let last_batched = peer_info.pending_requests.is_empty();
 				let flow_info = peer_info.remote_flow.as_mut();
 
@@ -556,7 +564,7 @@ impl LightProtocol {
 
 						if last_batched { peer_info.skip_update = false }
 
-						Ok(())
+						Ok(served)
 					}
 					(None, _) => Err(Error::UnsolicitedResponse),
 					(_, None) => Err(Error::NotServer), // really should be impossible.
@@ -565,7 +573,7 @@ impl LightProtocol {
 			None => Err(Error::UnknownPeer), // probably only occurs in a race of some kind.
 		};
 
-		res.map(|_| IdGuard::new(peers, *peer, req_id))
+		res.map(|served| (IdGuard::new(peers, *peer, req_id), served))
 	}
 
 	/// Handle a packet using the given io context.
@@ -970,8 +978,12 @@ impl LightProtocol {
 	// handle a packet with responses.
 	fn response(&self, peer: &PeerId, io: &IoContext, raw: UntrustedRlp) -> Result<(), Error> {
 		let (req_id, responses) = {
-			let id_guard = self.pre_verify_response(peer, &raw)?;
+			let (id_guard, served) = self.pre_verify_response(peer, &raw)?;
 			let responses: Vec<Response> = raw.list_at(2)?;
+			if let Some(served) = served {
+				let millis = served.as_secs().saturating_mul(1000).saturating_add((served.subsec_nanos() / 1_000_000) as u64);
+				io.tag_peer(*peer, SERVED_MS_TAG, min(millis, u32::max_value() as u64) as u32);
+			}
 			(id_guard.defuse(), responses)
 		};
 
@@ -30,9 +30,9 @@ use request::NetworkRequests as Requests;
 use net::{timeout, ReqId};
 use ethereum_types::U256;
 
-// Request set entry: requests + cost.
+// Request set entry: requests + cost + time sent.
 #[derive(Debug)]
-struct Entry(Requests, U256);
+struct Entry(Requests, U256, Instant);
 
 /// Request set.
 #[derive(Debug)]
@@ -63,7 +63,7 @@ impl RequestSet {
 		self.cumulative_cost = self.cumulative_cost + cost;
 
 		self.ids.insert(req_id, counter);
-		self.reqs.insert(counter, Entry(req, cost));
+		self.reqs.insert(counter, Entry(req, cost, now));
 
 		if self.reqs.keys().next().map_or(true, |x| *x == counter) {
 			self.base = Some(now);
@@ -79,7 +79,7 @@ impl RequestSet {
 			None => return None,
 		};
 
-		let Entry(req, cost) = self.reqs.remove(&id).expect("entry in `ids` implies entry in `reqs`; qed");
+		let Entry(req, cost, _) = self.reqs.remove(&id).expect("entry in `ids` implies entry in `reqs`; qed");
 
 		match self.reqs.keys().next() {
 			Some(k) if *k > id => self.base = Some(now),
@@ -91,6 +91,11 @@ impl RequestSet {
 		Some(req)
 	}
 
+	/// Get the time a set of requests was sent at.
+	pub fn sent_at(&self, req_id: &ReqId) -> Option<Instant> {
+		self.ids.get(req_id).and_then(|id| self.reqs.get(id)).map(|entry| entry.2)
+	}
+
 	/// Check for timeout against the given time. Returns true if
 	/// has timed out, false otherwise.
 	pub fn check_timeout(&self, now: Instant) -> bool {
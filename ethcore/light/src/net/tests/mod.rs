@@ -89,6 +89,8 @@ impl IoContext for Expect {
 	fn persistent_peer_id(&self, _peer: PeerId) -> Option<NodeId> {
 		None
 	}
+
+	fn tag_peer(&self, _peer: PeerId, _key: &str, _value: u32) {}
 }
 
 // can't implement directly for Arc due to cross-crate orphan rules.
@@ -381,6 +381,10 @@ usage! {
 			"--no-discovery",
 			"Disable new peer discovery.",
 
+			FLAG flag_no_persistent_peers: (bool) = false, or |c: &Config| c.network.as_ref()?.persistent_peers.map(|p| !p).clone(),
+			"--no-persistent-peers",
+			"Keep the node table in memory only, do not load or save it.",
+
 			FLAG flag_reserved_only: (bool) = false, or |c: &Config| c.network.as_ref()?.reserved_only.clone(),
 			"--reserved-only",
 			"Connect only to reserved nodes.",
@@ -441,6 +445,174 @@ usage! {
 			"--reserved-peers=[FILE]",
 			"Provide a file containing enodes, one per line. These nodes will always have a reserved slot on top of the normal maximum peers.",
 
+			ARG arg_static_peers: (Option<String>) = None, or |c: &Config| c.network.as_ref()?.static_peers.clone(),
+			"--static-peers=[FILE]",
+			"Provide a file of curated peers, one enode per line, to add to the node table at startup and again on the parity_reloadStaticPeers RPC call. Peers prefixed with ! are reserved.",
+
+			ARG arg_node_table_max_nodes: (usize) = 1024usize, or |c: &Config| c.network.as_ref()?.node_table_max_nodes.clone(),
+			"--node-table-max-nodes=[NUM]",
+			"Keep up to NUM nodes when refreshing or saving the node table.",
+
+			ARG arg_node_table_family_quota: (usize) = 10usize, or |c: &Config| c.network.as_ref()?.node_table_family_quota.clone(),
+			"--node-table-family-quota=[PERCENT]",
+			"Reserve PERCENT percent of the node table for IPv4 and for IPv6 nodes each while both are known. At most 50.",
+
+			ARG arg_node_table_max_file_size: (Option<usize>) = None, or |c: &Config| c.network.as_ref()?.node_table_max_file_size.clone(),
+			"--node-table-max-file-size=[BYTES]",
+			"Leave the worst nodes out when saving the node table so that the file takes at most BYTES bytes.",
+
+			FLAG flag_node_table_encrypt: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_encrypt.clone(),
+			"--node-table-encrypt",
+			"Encrypt the saved node table to the node key. Plain and encrypted tables are both loaded and converted on the next save.",
+
+			ARG arg_node_table_subnet_limit: (Option<usize>) = None, or |c: &Config| c.network.as_ref()?.node_table_subnet_limit.clone(),
+			"--node-table-subnet-limit=[NUM]",
+			"Keep at most NUM nodes from the same /24 (IPv4) or /48 (IPv6) subnet in the node table.",
+
+			ARG arg_node_table_backoff_initial: (u64) = 5u64, or |c: &Config| c.network.as_ref()?.node_table_backoff_initial.clone(),
+			"--node-table-backoff-initial=[SECS]",
+			"Wait SECS seconds before dialing a node again after a failed connection attempt. The delay doubles on each consecutive failure.",
+
+			ARG arg_node_table_backoff_max: (u64) = 600u64, or |c: &Config| c.network.as_ref()?.node_table_backoff_max.clone(),
+			"--node-table-backoff-max=[SECS]",
+			"Wait at most SECS seconds before dialing a failing node again. Capped at one day.",
+
+			ARG arg_node_table_max_age: (Option<u64>) = None, or |c: &Config| c.network.as_ref()?.node_table_max_age.clone(),
+			"--node-table-max-age=[SECS]",
+			"Drop nodes from the node table that have not been contacted successfully for SECS seconds.",
+
+			ARG arg_node_table_autosave: (u64) = 300u64, or |c: &Config| c.network.as_ref()?.node_table_autosave.clone(),
+			"--node-table-autosave=[SECS]",
+			"Refresh and save the node table every SECS seconds.",
+
+			ARG arg_node_table_boot_retry: (u64) = 120u64, or |c: &Config| c.network.as_ref()?.node_table_boot_retry.clone(),
+			"--node-table-boot-retry=[SECS]",
+			"Retry failing boot nodes at least every SECS seconds.",
+
+			ARG arg_node_table_tombstone_grace: (u64) = 600u64, or |c: &Config| c.network.as_ref()?.node_table_tombstone_grace.clone(),
+			"--node-table-tombstone-grace=[SECS]",
+			"Do not let discovery add a removed node again for SECS seconds.",
+
+			ARG arg_node_table_save_timeout: (u64) = 2u64, or |c: &Config| c.network.as_ref()?.node_table_save_timeout.clone(),
+			"--node-table-save-timeout=[SECS]",
+			"Give up saving the node table on shutdown after SECS seconds.",
+
+			ARG arg_node_table_recent_contact: (Option<u64>) = None, or |c: &Config| c.network.as_ref()?.node_table_recent_contact.clone(),
+			"--node-table-recent-contact=[SECS]",
+			"Prefer nodes contacted successfully within the last SECS seconds when dialing, regardless of their failure rate.",
+
+			FLAG flag_node_table_shuffle_ties: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_shuffle_ties.clone(),
+			"--node-table-shuffle-ties",
+			"Dial nodes with the same record in random order instead of by node id.",
+
+			ARG arg_node_table_warm_start: (u64) = 300u64, or |c: &Config| c.network.as_ref()?.node_table_warm_start.clone(),
+			"--node-table-warm-start=[SECS]",
+			"Dial the peers connected at the last clean shutdown first for SECS seconds after starting. 0 disables.",
+
+			FLAG flag_node_table_per_network: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_per_network.clone(),
+			"--node-table-per-network",
+			"Keep the node table of each chain in its own file, so that switching chains on the same data directory keeps the peers of each.",
+
+			ARG arg_node_table_outage_subnets: (usize) = 5usize, or |c: &Config| c.network.as_ref()?.node_table_outage_subnets.clone(),
+			"--node-table-outage-subnets=[NUM]",
+			"Take dial failures in NUM distinct subnets in a row for a lost connection and do not count them against the peers. 0 disables.",
+
+			ARG arg_node_table_outage_window: (u64) = 30u64, or |c: &Config| c.network.as_ref()?.node_table_outage_window.clone(),
+			"--node-table-outage-window=[SECS]",
+			"Only count the dial failures of the last SECS seconds towards --node-table-outage-subnets.",
+
+			ARG arg_node_table_outage_max: (u64) = 600u64, or |c: &Config| c.network.as_ref()?.node_table_outage_max.clone(),
+			"--node-table-outage-max=[SECS]",
+			"Count dial failures against the peers again if there was no successful dial for SECS seconds since a lost connection was detected.",
+
+			FLAG flag_node_table_raw_load: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_raw_load.clone(),
+			"--node-table-raw-load",
+			"Keep saved nodes that break the current --allow-ips, subnet limit, maximum age or --node-table-max-nodes instead of dropping them to nodes.dropped.json at start.",
+
+			FLAG flag_node_table_strict_boot_nodes: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_strict_boot_nodes.clone(),
+			"--node-table-strict-boot-nodes",
+			"Refuse to start if any boot node of the chain specification or --bootnodes is invalid, instead of skipping it with a warning.",
+
+			ARG arg_node_table_export_min_successes: (u32) = 1u32, or |c: &Config| c.network.as_ref()?.node_table_export_min_successes.clone(),
+			"--node-table-export-min-successes=[NUM]",
+			"Only include nodes with at least NUM established sessions in parity_exportNodeList.",
+
+			ARG arg_node_table_export_max_failure: (usize) = 20usize, or |c: &Config| c.network.as_ref()?.node_table_export_max_failure.clone(),
+			"--node-table-export-max-failure=[PERCENT]",
+			"Only include nodes with at most PERCENT failed dials in parity_exportNodeList.",
+
+			ARG arg_node_table_bootstrapped_nodes: (usize) = 30usize, or |c: &Config| c.network.as_ref()?.node_table_bootstrapped_nodes.clone(),
+			"--node-table-bootstrapped-nodes=[NUM]",
+			"Only dial boot nodes while no peers are connected once the node table holds NUM tried nodes with at most --node-table-bootstrapped-max-failure percent failed dials. 0 keeps dialling them.",
+
+			ARG arg_node_table_bootstrapped_max_failure: (usize) = 25usize, or |c: &Config| c.network.as_ref()?.node_table_bootstrapped_max_failure.clone(),
+			"--node-table-bootstrapped-max-failure=[PERCENT]",
+			"See --node-table-bootstrapped-nodes.",
+
+			ARG arg_node_table_ip_port_limit: (Option<usize>) = None, or |c: &Config| c.network.as_ref()?.node_table_ip_port_limit.clone(),
+			"--node-table-ip-port-limit=[NUM]",
+			"Dial at most NUM distinct ports on the same IP within --node-table-ip-port-window, so that many nodes on one host do not look like a port scan. Reserved peers are not limited.",
+
+			ARG arg_node_table_ip_port_window: (u64) = 600u64, or |c: &Config| c.network.as_ref()?.node_table_ip_port_window.clone(),
+			"--node-table-ip-port-window=[SECS]",
+			"See --node-table-ip-port-limit.",
+
+			ARG arg_node_table_siblings: (Option<String>) = None, or |c: &Config| c.network.as_ref()?.node_table_siblings.as_ref().map(|vec| vec.join(",")),
+			"--node-table-siblings=[ADDRESSES]",
+			"Comma-separated TCP addresses of other instances on this host, e.g. running another chain. Nodes at them are never stored or dialled. 0.0.0.0 as the IP matches the port on this host's addresses.",
+
+			FLAG flag_node_table_sibling_registry: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_sibling_registry.clone(),
+			"--node-table-sibling-registry",
+			"Register the listen address in a file in the base path and treat the addresses registered by other instances as --node-table-siblings.",
+
+			FLAG flag_no_node_table_history: (bool) = false, or |c: &Config| c.network.as_ref()?.no_node_table_history.clone(),
+			"--no-node-table-history",
+			"Do not save the hourly connection statistics of the last day with the node table.",
+
+			FLAG flag_node_table_dial_connected_ips: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_dial_connected_ips.clone(),
+			"--node-table-dial-connected-ips",
+			"Select nodes on the IP of a connected peer for dialling, e.g. when many nodes run behind one IP.",
+
+			ARG arg_node_table_update_max_added: (usize) = 256usize, or |c: &Config| c.network.as_ref()?.node_table_update_max_added.clone(),
+			"--node-table-update-max-added=[NUM]",
+			"Take at most NUM new nodes from one discovery update, verified ones first.",
+
+			ARG arg_node_table_update_max_removed: (usize) = 1024usize, or |c: &Config| c.network.as_ref()?.node_table_update_max_removed.clone(),
+			"--node-table-update-max-removed=[NUM]",
+			"Remove at most NUM nodes on one discovery update.",
+
+			ARG arg_node_table_stale_age: (u64) = 604800u64, or |c: &Config| c.network.as_ref()?.node_table_stale_age.clone(),
+			"--node-table-stale-age=[SECS]",
+			"Dial the nodes of a node table saved more than SECS seconds ago by their saved reliability until the most reliable ones answer pings again. 0 disables.",
+
+			ARG arg_node_table_telemetry_interval: (u64) = 3600u64, or |c: &Config| c.network.as_ref()?.node_table_telemetry_interval.clone(),
+			"--node-table-telemetry-interval=[SECS]",
+			"Report the node table quality to a telemetry callback registered by an embedding application every SECS seconds. Only counts are reported. 0 disables.",
+
+			FLAG flag_node_table_operator_clusters: (bool) = false, or |c: &Config| c.network.as_ref()?.node_table_operator_clusters.clone(),
+			"--node-table-operator-clusters",
+			"Dial at most one node at a time of the nodes in one subnet reporting the same client version, likely run by one operator.",
+
+			ARG arg_node_table_useless_cooldown: (u64) = 60u64, or |c: &Config| c.network.as_ref()?.node_table_useless_cooldown.clone(),
+			"--node-table-useless-cooldown=[SECS]",
+			"Only count a node marked as useless again for the same reason within SECS seconds, e.g. for each failed request of a peer. 0 processes every mark.",
+
+			ARG arg_node_table_trusted: (Option<String>) = None, or |c: &Config| c.network.as_ref()?.node_table_trusted.as_ref().map(|vec| vec.join(",")),
+			"--node-table-trusted=[NODES]",
+			"Comma-separated enodes of nodes to take at face value, e.g. your own infrastructure: their announcements are not verified, limited or capped per subnet. Unlike --reserved-peers they are not dialled preferentially.",
+
+			ARG arg_node_table_min_port: (u16) = 1024u16, or |c: &Config| c.network.as_ref()?.node_table_min_port.clone(),
+			"--node-table-min-port=[PORT]",
+			"Ignore new nodes announcing a TCP or UDP port below PORT, e.g. 22 or 25. Reserved and trusted nodes are exempt. Private networks running on low ports may lower it.",
+
+			ARG arg_node_table_maintenance_min: (u64) = 2u64, or |c: &Config| c.network.as_ref()?.node_table_maintenance_min.clone(),
+			"--node-table-maintenance-min=[SECS]",
+			"Maintain the node table every SECS seconds while no peers are connected: dial the nodes whose backoff ended, ping the stale nodes again and save the table more often. The interval grows as peers connect. Refreshing the table keeps to its own interval.",
+
+			ARG arg_node_table_maintenance_max: (u64) = 60u64, or |c: &Config| c.network.as_ref()?.node_table_maintenance_max.clone(),
+			"--node-table-maintenance-max=[SECS]",
+			"Check the node table every SECS seconds once enough peers are connected, to save CPU on small devices.",
+
 		["API and console options – RPC"]
 			FLAG flag_no_jsonrpc: (bool) = false, or |c: &Config| c.rpc.as_ref()?.disable.clone(),
 			"--no-jsonrpc",
@@ -1063,10 +1235,53 @@ struct Network {
 	id: Option<u64>,
 	bootnodes: Option<Vec<String>>,
 	discovery: Option<bool>,
+	persistent_peers: Option<bool>,
 	node_key: Option<String>,
 	reserved_peers: Option<String>,
+	static_peers: Option<String>,
 	reserved_only: Option<bool>,
 	no_serve_light: Option<bool>,
+	node_table_max_nodes: Option<usize>,
+	node_table_family_quota: Option<usize>,
+	node_table_max_file_size: Option<usize>,
+	node_table_encrypt: Option<bool>,
+	node_table_subnet_limit: Option<usize>,
+	node_table_backoff_initial: Option<u64>,
+	node_table_backoff_max: Option<u64>,
+	node_table_max_age: Option<u64>,
+	node_table_autosave: Option<u64>,
+	node_table_boot_retry: Option<u64>,
+	node_table_tombstone_grace: Option<u64>,
+	node_table_save_timeout: Option<u64>,
+	node_table_recent_contact: Option<u64>,
+	node_table_shuffle_ties: Option<bool>,
+	node_table_warm_start: Option<u64>,
+	node_table_per_network: Option<bool>,
+	node_table_outage_subnets: Option<usize>,
+	node_table_outage_window: Option<u64>,
+	node_table_outage_max: Option<u64>,
+	node_table_raw_load: Option<bool>,
+	node_table_strict_boot_nodes: Option<bool>,
+	node_table_export_min_successes: Option<u32>,
+	node_table_export_max_failure: Option<usize>,
+	node_table_bootstrapped_nodes: Option<usize>,
+	node_table_bootstrapped_max_failure: Option<usize>,
+	node_table_ip_port_limit: Option<usize>,
+	node_table_ip_port_window: Option<u64>,
+	node_table_siblings: Option<Vec<String>>,
+	node_table_sibling_registry: Option<bool>,
+	no_node_table_history: Option<bool>,
+	node_table_dial_connected_ips: Option<bool>,
+	node_table_update_max_added: Option<usize>,
+	node_table_update_max_removed: Option<usize>,
+	node_table_stale_age: Option<u64>,
+	node_table_telemetry_interval: Option<u64>,
+	node_table_operator_clusters: Option<bool>,
+	node_table_useless_cooldown: Option<u64>,
+	node_table_trusted: Option<Vec<String>>,
+	node_table_min_port: Option<u16>,
+	node_table_maintenance_min: Option<u64>,
+	node_table_maintenance_max: Option<u64>,
 }
 
 #[derive(Default, Debug, PartialEq, Deserialize)]
@@ -1501,8 +1716,51 @@ mod tests {
 			arg_network_id: Some(1),
 			arg_bootnodes: Some("".into()),
 			flag_no_discovery: false,
+			flag_no_persistent_peers: false,
 			arg_node_key: None,
 			arg_reserved_peers: Some("./path_to_file".into()),
+			arg_static_peers: None,
+			arg_node_table_max_nodes: 1024usize,
+			arg_node_table_family_quota: 10usize,
+			arg_node_table_max_file_size: None,
+			flag_node_table_encrypt: false,
+			arg_node_table_subnet_limit: None,
+			arg_node_table_backoff_initial: 5u64,
+			arg_node_table_backoff_max: 600u64,
+			arg_node_table_max_age: None,
+			arg_node_table_autosave: 300u64,
+			arg_node_table_boot_retry: 120u64,
+			arg_node_table_tombstone_grace: 600u64,
+			arg_node_table_save_timeout: 2u64,
+			arg_node_table_recent_contact: None,
+			flag_node_table_shuffle_ties: false,
+			arg_node_table_warm_start: 300u64,
+			flag_node_table_per_network: false,
+			arg_node_table_outage_subnets: 5usize,
+			arg_node_table_outage_window: 30u64,
+			arg_node_table_outage_max: 600u64,
+			flag_node_table_raw_load: false,
+			flag_node_table_strict_boot_nodes: false,
+			arg_node_table_export_min_successes: 1u32,
+			arg_node_table_export_max_failure: 20usize,
+			arg_node_table_bootstrapped_nodes: 30usize,
+			arg_node_table_bootstrapped_max_failure: 25usize,
+			arg_node_table_ip_port_limit: None,
+			arg_node_table_ip_port_window: 600u64,
+			arg_node_table_siblings: None,
+			flag_node_table_sibling_registry: false,
+			flag_no_node_table_history: false,
+			flag_node_table_dial_connected_ips: false,
+			arg_node_table_update_max_added: 256usize,
+			arg_node_table_update_max_removed: 1024usize,
+			arg_node_table_stale_age: 604800u64,
+			arg_node_table_telemetry_interval: 3600u64,
+			flag_node_table_operator_clusters: false,
+			arg_node_table_useless_cooldown: 60u64,
+			arg_node_table_trusted: None,
+			arg_node_table_min_port: 1024u16,
+			arg_node_table_maintenance_min: 2u64,
+			arg_node_table_maintenance_max: 60u64,
 			flag_reserved_only: false,
 			flag_no_ancient_blocks: false,
 			flag_no_serve_light: false,
@@ -1753,10 +2011,53 @@ mod tests {
 				id: None,
 				bootnodes: None,
 				discovery: Some(true),
+				persistent_peers: None,
 				node_key: None,
 				reserved_peers: Some("./path/to/reserved_peers".into()),
+				static_peers: None,
 				reserved_only: Some(true),
 				no_serve_light: None,
+				node_table_max_nodes: None,
+				node_table_family_quota: None,
+				node_table_max_file_size: None,
+				node_table_encrypt: None,
+				node_table_subnet_limit: None,
+				node_table_backoff_initial: None,
+				node_table_backoff_max: None,
+				node_table_max_age: None,
+				node_table_autosave: None,
+				node_table_boot_retry: None,
+				node_table_tombstone_grace: None,
+				node_table_save_timeout: None,
+				node_table_recent_contact: None,
+				node_table_shuffle_ties: None,
+				node_table_warm_start: None,
+				node_table_per_network: None,
+				node_table_outage_subnets: None,
+				node_table_outage_window: None,
+				node_table_outage_max: None,
+				node_table_raw_load: None,
+				node_table_strict_boot_nodes: None,
+				node_table_export_min_successes: None,
+				node_table_export_max_failure: None,
+				node_table_bootstrapped_nodes: None,
+				node_table_bootstrapped_max_failure: None,
+				node_table_ip_port_limit: None,
+				node_table_ip_port_window: None,
+				node_table_siblings: None,
+				node_table_sibling_registry: None,
+				no_node_table_history: None,
+				node_table_dial_connected_ips: None,
+				node_table_update_max_added: None,
+				node_table_update_max_removed: None,
+				node_table_stale_age: None,
+				node_table_telemetry_interval: None,
+				node_table_operator_clusters: None,
+				node_table_useless_cooldown: None,
+				node_table_trusted: None,
+				node_table_min_port: None,
+				node_table_maintenance_min: None,
+				node_table_maintenance_max: None,
 			}),
 			websockets: Some(Ws {
 				disable: Some(true),
@@ -19,7 +19,7 @@ use std::io::Read;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::collections::BTreeMap;
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::str::FromStr;
 use cli::{Args, ArgsError};
 use hash::keccak;
@@ -27,7 +27,7 @@ use ethereum_types::{U256, H256, Address};
 use parity_version::{version_data, version};
 use bytes::Bytes;
 use ansi_term::Colour;
-use ethsync::{NetworkConfiguration, validate_node_url, self};
+use ethsync::{NetworkConfiguration, NodeTableConfig, validate_node_url, normalize_node_url, self};
 use ethcore::ethstore::ethkey::{Secret, Public};
 use ethcore::client::{VMType};
 use ethcore::miner::{MinerOptions, Banning, StratumOptions};
@@ -55,6 +55,9 @@ use account::{AccountCmd, NewAccount, ListAccounts, ImportAccounts, ImportFromGe
 use snapshot::{self, SnapshotCommand};
 use network::{IpFilter};
 
+/// Upper bound for `--node-table-backoff-max`, in seconds.
+const MAX_NODE_TABLE_BACKOFF: u64 = 24 * 60 * 60;
+
 #[derive(Debug, PartialEq)]
 pub enum Cmd {
 	Run(RunCmd),
@@ -480,6 +483,89 @@ impl Configuration {
 		}
 	}
 
+	fn node_table_config(&self) -> Result<NodeTableConfig, String> {
+		if self.args.arg_node_table_max_nodes == 0 {
+			return Err("Invalid --node-table-max-nodes: the node table must hold at least one node".into());
+		}
+		if self.args.arg_node_table_family_quota > 50 {
+			return Err("Invalid --node-table-family-quota: at most 50 percent can be reserved for each address family".into());
+		}
+		if self.args.arg_node_table_subnet_limit == Some(0) {
+			return Err("Invalid --node-table-subnet-limit: the limit must be at least one node".into());
+		}
+		if self.args.arg_node_table_autosave == 0 {
+			return Err("Invalid --node-table-autosave: the interval must be at least one second".into());
+		}
+		if self.args.arg_node_table_maintenance_min == 0 {
+			return Err("Invalid --node-table-maintenance-min: the interval must be at least one second".into());
+		}
+		if self.args.arg_node_table_maintenance_max < self.args.arg_node_table_maintenance_min {
+			return Err("Invalid --node-table-maintenance-max: the interval must not be shorter than --node-table-maintenance-min".into());
+		}
+		if self.args.arg_node_table_boot_retry == 0 {
+			return Err("Invalid --node-table-boot-retry: the interval must be at least one second".into());
+		}
+		let max_backoff = min(self.args.arg_node_table_backoff_max, MAX_NODE_TABLE_BACKOFF);
+		let sibling_endpoints = match self.args.arg_node_table_siblings {
+			Some(ref siblings) => siblings.split(',').filter(|s| !s.trim().is_empty())
+				.map(|s| s.trim().parse().map_err(|_| format!("Invalid --node-table-siblings: {} is not an IP address and port", s)))
+				.collect::<Result<Vec<SocketAddr>, String>>()?,
+			None => Vec::new(),
+		};
+		let trusted_nodes = match self.args.arg_node_table_trusted {
+			Some(ref nodes) => nodes.split(',').map(str::trim).filter(|s| !s.is_empty())
+				.map(|s| normalize_node_url(s).map_err(|_| format!("Invalid --node-table-trusted: {} is not a valid enode URL", s)))
+				.collect::<Result<Vec<String>, String>>()?,
+			None => Vec::new(),
+		};
+		Ok(NodeTableConfig {
+			max_nodes: self.args.arg_node_table_max_nodes,
+			family_quota: self.args.arg_node_table_family_quota,
+			max_file_bytes: self.args.arg_node_table_max_file_size,
+			encrypt_file: self.args.flag_node_table_encrypt,
+			max_nodes_per_subnet: self.args.arg_node_table_subnet_limit,
+			initial_backoff: Duration::from_secs(min(self.args.arg_node_table_backoff_initial, max_backoff)),
+			max_backoff: Duration::from_secs(max_backoff),
+			max_age: self.args.arg_node_table_max_age.map(Duration::from_secs),
+			autosave_interval: Duration::from_secs(self.args.arg_node_table_autosave),
+			boot_retry_interval: Duration::from_secs(self.args.arg_node_table_boot_retry),
+			tombstone_grace: Duration::from_secs(self.args.arg_node_table_tombstone_grace),
+			final_save_timeout: Duration::from_secs(self.args.arg_node_table_save_timeout),
+			recent_contact: self.args.arg_node_table_recent_contact.map(Duration::from_secs),
+			shuffle_ties: self.args.flag_node_table_shuffle_ties,
+			warm_start: Duration::from_secs(self.args.arg_node_table_warm_start),
+			// set from the chain specification when starting
+			network_id: None,
+			per_network_files: self.args.flag_node_table_per_network,
+			outage_subnets: self.args.arg_node_table_outage_subnets,
+			outage_window: Duration::from_secs(self.args.arg_node_table_outage_window),
+			outage_max: Duration::from_secs(self.args.arg_node_table_outage_max),
+			raw_load: self.args.flag_node_table_raw_load,
+			strict_boot_nodes: self.args.flag_node_table_strict_boot_nodes,
+			export_min_successes: self.args.arg_node_table_export_min_successes,
+			export_max_failure_bucket: self.args.arg_node_table_export_max_failure,
+			bootstrapped_nodes: self.args.arg_node_table_bootstrapped_nodes,
+			bootstrapped_max_failure: self.args.arg_node_table_bootstrapped_max_failure,
+			max_ports_per_ip: self.args.arg_node_table_ip_port_limit,
+			port_window: Duration::from_secs(self.args.arg_node_table_ip_port_window),
+			sibling_endpoints: sibling_endpoints,
+			sibling_registry: self.args.flag_node_table_sibling_registry,
+			save_outcome_history: !self.args.flag_no_node_table_history,
+			static_peers: self.args.arg_static_peers.as_ref().map(PathBuf::from),
+			exclude_connected: !self.args.flag_node_table_dial_connected_ips,
+			max_update_added: self.args.arg_node_table_update_max_added,
+			max_update_removed: self.args.arg_node_table_update_max_removed,
+			stale_file_age: Duration::from_secs(self.args.arg_node_table_stale_age),
+			telemetry_interval: Duration::from_secs(self.args.arg_node_table_telemetry_interval),
+			operator_clusters: self.args.flag_node_table_operator_clusters,
+			useless_cooldown: Duration::from_secs(self.args.arg_node_table_useless_cooldown),
+			trusted_nodes: trusted_nodes,
+			min_port: self.args.arg_node_table_min_port,
+			maintenance_min_interval: Duration::from_secs(self.args.arg_node_table_maintenance_min),
+			maintenance_max_interval: Duration::from_secs(self.args.arg_node_table_maintenance_max),
+		})
+	}
+
 	fn min_peers(&self) -> u32 {
 		self.args.arg_peers.unwrap_or(self.args.arg_min_peers) as u32
 	}
@@ -764,6 +850,8 @@ impl Configuration {
 			Some(Err(err)) => return Err(err),
 		};
 		ret.discovery_enabled = !self.args.flag_no_discovery && !self.args.flag_nodiscover;
+		ret.persist_nodes = !self.args.flag_no_persistent_peers;
+		ret.node_table = self.node_table_config()?;
 		ret.max_peers = self.max_peers();
 		ret.min_peers = self.min_peers();
 		ret.snapshot_peers = self.snapshot_peers();
@@ -1541,6 +1629,125 @@ mod tests {
 		}));
 	}
 
+	#[test]
+	fn should_parse_node_table_config() {
+		let conf = parse(&["parity"]);
+		assert_eq!(conf.net_config().unwrap().node_table, NodeTableConfig::default());
+
+		let trusted = "enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770";
+
+		let conf = parse(&["parity",
+			"--node-table-max-nodes", "100",
+			"--node-table-family-quota", "20",
+			"--node-table-max-file-size", "65536",
+			"--node-table-encrypt",
+			"--node-table-subnet-limit", "4",
+			"--node-table-backoff-initial", "10",
+			"--node-table-backoff-max", "3600",
+			"--node-table-max-age", "86400",
+			"--node-table-autosave", "60",
+			"--node-table-boot-retry", "30",
+			"--node-table-tombstone-grace", "0",
+			"--node-table-save-timeout", "5",
+			"--node-table-recent-contact", "1800",
+			"--node-table-shuffle-ties",
+			"--node-table-warm-start", "60",
+			"--node-table-per-network",
+			"--node-table-outage-subnets", "8",
+			"--node-table-outage-window", "60",
+			"--node-table-outage-max", "300",
+			"--node-table-raw-load",
+			"--node-table-strict-boot-nodes",
+			"--node-table-export-min-successes", "3",
+			"--node-table-export-max-failure", "10",
+			"--node-table-bootstrapped-nodes", "0",
+			"--node-table-bootstrapped-max-failure", "50",
+			"--node-table-ip-port-limit", "4",
+			"--node-table-ip-port-window", "120",
+			"--node-table-siblings", "0.0.0.0:30304, 10.0.0.2:30305",
+			"--node-table-sibling-registry",
+			"--no-node-table-history",
+			"--static-peers", "/etc/parity/static-peers.txt",
+			"--node-table-dial-connected-ips",
+			"--node-table-update-max-added", "64",
+			"--node-table-update-max-removed", "128",
+			"--node-table-stale-age", "86400",
+			"--node-table-telemetry-interval", "600",
+			"--node-table-operator-clusters",
+			"--node-table-useless-cooldown", "300",
+			"--node-table-trusted", trusted,
+			"--node-table-min-port", "100",
+			"--node-table-maintenance-min", "5",
+			"--node-table-maintenance-max", "120",
+		]);
+		let net_config = conf.net_config().unwrap();
+		assert_eq!(net_config.node_table, NodeTableConfig {
+			max_nodes: 100,
+			family_quota: 20,
+			max_file_bytes: Some(65536),
+			encrypt_file: true,
+			max_nodes_per_subnet: Some(4),
+			initial_backoff: Duration::from_secs(10),
+			max_backoff: Duration::from_secs(3600),
+			max_age: Some(Duration::from_secs(86400)),
+			autosave_interval: Duration::from_secs(60),
+			boot_retry_interval: Duration::from_secs(30),
+			tombstone_grace: Duration::from_secs(0),
+			final_save_timeout: Duration::from_secs(5),
+			recent_contact: Some(Duration::from_secs(1800)),
+			shuffle_ties: true,
+			warm_start: Duration::from_secs(60),
+			network_id: None,
+			per_network_files: true,
+			outage_subnets: 8,
+			outage_window: Duration::from_secs(60),
+			outage_max: Duration::from_secs(300),
+			raw_load: true,
+			strict_boot_nodes: true,
+			export_min_successes: 3,
+			export_max_failure_bucket: 10,
+			bootstrapped_nodes: 0,
+			bootstrapped_max_failure: 50,
+			max_ports_per_ip: Some(4),
+			port_window: Duration::from_secs(120),
+			sibling_endpoints: vec!["0.0.0.0:30304".parse().unwrap(), "10.0.0.2:30305".parse().unwrap()],
+			sibling_registry: true,
+			save_outcome_history: false,
+			static_peers: Some(PathBuf::from("/etc/parity/static-peers.txt")),
+			exclude_connected: false,
+			max_update_added: 64,
+			max_update_removed: 128,
+			stale_file_age: Duration::from_secs(86400),
+			telemetry_interval: Duration::from_secs(600),
+			operator_clusters: true,
+			useless_cooldown: Duration::from_secs(300),
+			trusted_nodes: vec![trusted.to_owned()],
+			min_port: 100,
+			maintenance_min_interval: Duration::from_secs(5),
+			maintenance_max_interval: Duration::from_secs(120),
+		});
+		// survives the conversion to the network configuration and back
+		let basic = net_config.clone().into_basic().unwrap();
+		assert_eq!(basic.node_table, net_config.node_table);
+		assert_eq!(NetworkConfiguration::from(basic), net_config);
+
+		// the backoff ceiling is capped
+		let conf = parse(&["parity", "--node-table-backoff-initial", "100000", "--node-table-backoff-max", "1000000"]);
+		let node_table = conf.net_config().unwrap().node_table;
+		assert_eq!(node_table.max_backoff, Duration::from_secs(24 * 60 * 60));
+		assert_eq!(node_table.initial_backoff, Duration::from_secs(24 * 60 * 60));
+
+		assert!(parse(&["parity", "--node-table-max-nodes", "0"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-family-quota", "51"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-subnet-limit", "0"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-autosave", "0"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-boot-retry", "0"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-maintenance-min", "0"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-maintenance-min", "30", "--node-table-maintenance-max", "10"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-siblings", "10.0.0.2"]).net_config().is_err());
+		assert!(parse(&["parity", "--node-table-trusted", "22.99.55.44:7770"]).net_config().is_err());
+	}
+
 	#[test]
 	fn should_parse_rpc_settings_with_geth_compatiblity() {
 		// given
@@ -181,7 +181,7 @@ pub fn to_bootnodes(bootnodes: &Option<String>) -> Result<Vec<String>, String> {
 
 #[cfg(test)]
 pub fn default_network_config() -> ::ethsync::NetworkConfiguration {
-	use ethsync::{NetworkConfiguration};
+	use ethsync::{NetworkConfiguration, NodeTableConfig};
 	use super::network::IpFilter;
 	NetworkConfiguration {
 		config_path: Some(replace_home(&::dir::default_data_path(), "$BASE/network")),
@@ -201,6 +201,9 @@ pub fn default_network_config() -> ::ethsync::NetworkConfiguration {
 		reserved_nodes: Vec::new(),
 		allow_non_reserved: true,
 		client_version: ::parity_version::version(),
+		eviction_margin: Some(25),
+		persist_nodes: true,
+		node_table: NodeTableConfig::default(),
 	}
 }
 
@@ -282,6 +282,7 @@ fn execute_light_impl(cmd: RunCmd, can_restart: bool, logger: Arc<RotatingLogger
 
 	// set network path.
 	net_conf.net_config_path = Some(db_dirs.network_path().to_string_lossy().into_owned());
+	net_conf.node_table.network_id = Some(node_table_network_id(cmd.network_id.unwrap_or(spec.network_id()), &genesis_hash));
 	let sync_params = LightSyncParams {
 		network_config: net_conf.into_basic().map_err(|e| format!("Failed to produce network config: {}", e))?,
 		client: Arc::new(provider),
@@ -606,6 +607,7 @@ pub fn execute_impl(cmd: RunCmd, can_restart: bool, logger: Arc<RotatingLogger>)
 
 	// set network path.
 	net_conf.net_config_path = Some(db_dirs.network_path().to_string_lossy().into_owned());
+	net_conf.node_table.network_id = Some(node_table_network_id(sync_config.network_id, &genesis_hash));
 
 	// create client service.
 	let service = ClientService::start(
@@ -955,6 +957,11 @@ fn print_running_environment(spec_name: &String, dirs: &Directories, db_dirs: &D
 	info!("Path to dapps {}", Colour::White.bold().paint(dapps_conf.dapps_path.to_string_lossy().into_owned()));
 }
 
+/// Network a saved node table belongs to. Chains sharing a network id are told apart by the genesis hash.
+fn node_table_network_id(network_id: u64, genesis_hash: &::ethereum_types::H256) -> String {
+	format!("{}-{:x}", network_id, genesis_hash)
+}
+
 fn prepare_account_provider(spec: &SpecType, dirs: &Directories, data_dir: &str, cfg: AccountsConfig, passwords: &[String]) -> Result<AccountProvider, String> {
 	use ethcore::ethstore::EthStore;
 	use ethcore::ethstore::accounts_dir::RootDiskDirectory;
@@ -132,6 +132,38 @@ pub fn invalid_params<T: fmt::Debug>(param: &str, details: T) -> Error {
 	}
 }
 
+pub fn invalid_node(details: String) -> Error {
+	Error {
+		code: ErrorCode::InvalidParams,
+		message: format!("Invalid node: {}", details),
+		data: None,
+	}
+}
+
+pub fn invalid_node_list(details: String) -> Error {
+	Error {
+		code: ErrorCode::InvalidParams,
+		message: format!("Invalid node list: {}", details),
+		data: None,
+	}
+}
+
+pub fn node_table_command(details: String) -> Error {
+	Error {
+		code: ErrorCode::InvalidParams,
+		message: format!("Invalid node table command: {}", details),
+		data: None,
+	}
+}
+
+pub fn static_peers(details: String) -> Error {
+	Error {
+		code: ErrorCode::InternalError,
+		message: format!("Error loading the static peers: {}", details),
+		data: None,
+	}
+}
+
 pub fn execution<T: fmt::Debug>(data: T) -> Error {
 	Error {
 		code: ErrorCode::ServerError(codes::EXECUTION_ERROR),
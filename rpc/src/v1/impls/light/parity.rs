@@ -23,7 +23,7 @@ use version::version_data;
 use crypto::{ecies, DEFAULT_MAC};
 use ethkey::{Brain, Generator};
 use ethstore::random_phrase;
-use ethsync::LightSyncProvider;
+use ethsync::{LightSyncProvider, ManageNetwork};
 use ethcore::account_provider::AccountProvider;
 use ethcore_logger::RotatingLogger;
 use node_health::{NodeHealth, Health};
@@ -45,7 +45,7 @@ use v1::types::{
 	TransactionStats, LocalTransactionStatus,
 	BlockNumber, ConsensusCapability, VersionInfo,
 	OperationsInfo, DappId, ChainStatus,
-	AccountInfo, HwAccountInfo, Header, RichHeader,
+	AccountInfo, HwAccountInfo, Header, RichHeader, NodeTableEntry, NodeStatus, NetHealth,
 };
 use Host;
 
@@ -204,6 +204,27 @@ impl Parity for ParityClient {
 		Ok(self.settings.network_port)
 	}
 
+	fn node_table(&self, limit: Trailing<Option<usize>>, include_useless: Trailing<bool>) -> Result<Vec<NodeTableEntry>> {
+		let limit = limit.unwrap_or_default().unwrap_or(usize::max_value());
+		Ok(self.light_dispatch.sync.node_table(limit, include_useless.unwrap_or_default()).into_iter().map(Into::into).collect())
+	}
+
+	fn node_status(&self, node: String) -> Result<Option<NodeStatus>> {
+		self.light_dispatch.sync.node_status(node).map(|status| status.map(Into::into)).map_err(errors::invalid_node)
+	}
+
+	fn net_health(&self) -> Result<NetHealth> {
+		self.light_dispatch.sync.net_health().map(Into::into).ok_or_else(errors::network_disabled)
+	}
+
+	fn suggest_bootnodes(&self, limit: usize) -> Result<Vec<String>> {
+		Ok(self.light_dispatch.sync.suggest_bootnodes(limit))
+	}
+
+	fn export_node_list(&self, limit: usize) -> Result<String> {
+		Ok(self.light_dispatch.sync.export_node_list(limit))
+	}
+
 	fn node_name(&self) -> Result<String> {
 		Ok(self.settings.name.clone())
 	}
@@ -49,7 +49,7 @@ use v1::types::{
 	TransactionStats, LocalTransactionStatus,
 	BlockNumber, ConsensusCapability, VersionInfo,
 	OperationsInfo, DappId, ChainStatus,
-	AccountInfo, HwAccountInfo, RichHeader,
+	AccountInfo, HwAccountInfo, RichHeader, NodeTableEntry, NodeStatus, NetHealth,
 	block_number_to_id
 };
 use Host;
@@ -227,6 +227,27 @@ impl<C, M, U, S> Parity for ParityClient<C, M, U> where
 		Ok(self.settings.network_port)
 	}
 
+	fn node_table(&self, limit: Trailing<Option<usize>>, include_useless: Trailing<bool>) -> Result<Vec<NodeTableEntry>> {
+		let limit = limit.unwrap_or_default().unwrap_or(usize::max_value());
+		Ok(self.net.node_table(limit, include_useless.unwrap_or_default()).into_iter().map(Into::into).collect())
+	}
+
+	fn node_status(&self, node: String) -> Result<Option<NodeStatus>> {
+		self.net.node_status(node).map(|status| status.map(Into::into)).map_err(errors::invalid_node)
+	}
+
+	fn net_health(&self) -> Result<NetHealth> {
+		self.net.net_health().map(Into::into).ok_or_else(errors::network_disabled)
+	}
+
+	fn suggest_bootnodes(&self, limit: usize) -> Result<Vec<String>> {
+		Ok(self.net.suggest_bootnodes(limit))
+	}
+
+	fn export_node_list(&self, limit: usize) -> Result<String> {
+		Ok(self.net.export_node_list(limit))
+	}
+
 	fn node_name(&self) -> Result<String> {
 		Ok(self.settings.name.clone())
 	}
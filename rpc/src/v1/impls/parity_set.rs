@@ -17,6 +17,7 @@
 /// Parity-specific rpc interface for operations altering the settings.
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ethcore::miner::MinerService;
 use ethcore::client::MiningBlockChainClient;
@@ -29,10 +30,11 @@ use updater::{Service as UpdateService};
 
 use jsonrpc_core::{BoxFuture, Result};
 use jsonrpc_core::futures::Future;
+use jsonrpc_macros::Trailing;
 use v1::helpers::dapps::DappsService;
 use v1::helpers::errors;
 use v1::traits::ParitySet;
-use v1::types::{Bytes, H160, H256, U256, ReleaseInfo, Transaction, LocalDapp};
+use v1::types::{Bytes, H160, H256, U256, ReleaseInfo, Transaction, LocalDapp, AddedNode, NodeListFormat, ImportedNodes, StaticPeers};
 
 /// Parity-specific rpc interface for operations altering the settings.
 pub struct ParitySetClient<C, M, U, F = fetch::Client> {
@@ -126,13 +128,49 @@ impl<C, M, U, F> ParitySet for ParitySetClient<C, M, U, F> where
 		}
 	}
 
-	fn remove_reserved_peer(&self, peer: String) -> Result<bool> {
-		match self.net.remove_reserved_peer(peer) {
+	fn remove_reserved_peer(&self, peer: String, forget: Trailing<bool>) -> Result<bool> {
+		match self.net.remove_reserved_peer(peer, forget.unwrap_or_default()) {
 			Ok(()) => Ok(true),
 			Err(e) => Err(errors::invalid_params("Peer address", e)),
 		}
 	}
 
+	fn remove_node(&self, node: String) -> Result<bool> {
+		self.net.remove_node(node).map_err(errors::invalid_node)
+	}
+
+	fn ban_node(&self, node: String, seconds: u64) -> Result<bool> {
+		self.net.ban_node(node, Duration::from_secs(seconds)).map_err(errors::invalid_node)
+	}
+
+	fn retry_node(&self, node: String) -> Result<bool> {
+		self.net.retry_node(node).map_err(errors::invalid_node)
+	}
+
+	fn add_node(&self, node: String, connect_now: bool) -> Result<AddedNode> {
+		self.net.add_node(node, connect_now)
+			.map(|(enode, is_new)| AddedNode { enode: enode, is_new: is_new })
+			.map_err(errors::invalid_node)
+	}
+
+	fn import_node_list(&self, source: String, format: NodeListFormat) -> Result<ImportedNodes> {
+		self.net.import_node_list(source, format.into())
+			.map(Into::into)
+			.map_err(errors::invalid_node_list)
+	}
+
+	fn node_table_command(&self, command: String) -> Result<String> {
+		self.net.node_table_command(command).map_err(errors::node_table_command)
+	}
+
+	fn reload_static_peers(&self) -> Result<StaticPeers> {
+		self.net.reload_static_peers().map(Into::into).map_err(errors::static_peers)
+	}
+
+	fn freeze_node_table(&self, seconds: u64) -> Result<bool> {
+		self.net.freeze_node_table(Duration::from_secs(seconds)).map_err(|_| errors::network_disabled())
+	}
+
 	fn drop_non_reserved_peers(&self) -> Result<bool> {
 		self.net.deny_unreserved_peers();
 		Ok(true)
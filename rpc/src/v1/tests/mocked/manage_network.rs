@@ -14,7 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use ethsync::{ManageNetwork, NetworkConfiguration};
+use std::time::{Duration, UNIX_EPOCH};
+use ethereum_types::H512;
+use ethsync::{ManageNetwork, NetworkConfiguration, NodeTableEntry, NodeSource, normalize_node_url, parse_node_id};
+use ethsync::{NetHealth, NetHealthVerdict, NodeTableStats, SessionStats, ListFormat, ListEntryResult, IngestStats, StaticPeersUpdate, EntryStatus, DialExclusion};
 use self::ethcore_network::{ProtocolId, NetworkContext};
 
 extern crate ethcore_network;
@@ -25,10 +28,133 @@ pub struct TestManageNetwork;
 impl ManageNetwork for TestManageNetwork {
 	fn accept_unreserved_peers(&self) { }
 	fn deny_unreserved_peers(&self) { }
-	fn remove_reserved_peer(&self, _peer: String) -> Result<(), String> { Ok(()) }
+	fn remove_reserved_peer(&self, _peer: String, _forget: bool) -> Result<(), String> { Ok(()) }
 	fn add_reserved_peer(&self, _peer: String) -> Result<(), String> { Ok(()) }
 	fn start_network(&self) {}
 	fn stop_network(&self) {}
 	fn network_config(&self) -> NetworkConfiguration { NetworkConfiguration::new_local() }
 	fn with_proto_context(&self, _: ProtocolId, _: &mut FnMut(&NetworkContext)) { }
+	fn node_table(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry> {
+		node_table_fixture().into_iter().filter(|e| include_useless || !e.useless).take(limit).collect()
+	}
+	fn remove_node(&self, node: String) -> Result<bool, String> { fixture_contains(&node) }
+	fn ban_node(&self, node: String, _duration: Duration) -> Result<bool, String> { fixture_contains(&node) }
+	fn retry_node(&self, node: String) -> Result<bool, String> { fixture_contains(&node) }
+	fn node_status(&self, node: String) -> Result<Option<EntryStatus>, String> {
+		let id = parse_node_id(&node).map_err(|e| e.to_string())?;
+		Ok(node_table_fixture().into_iter().find(|e| e.id == id).map(|entry| {
+			let exclusions = match (entry.ban_reason.clone(), entry.useless_reason.clone()) {
+				(Some(reason), _) => vec![DialExclusion::Banned { reason: reason, until: UNIX_EPOCH + Duration::from_secs(1_500_000_600) }],
+				(_, Some(reason)) => vec![DialExclusion::Useless { reason: reason, until: None }],
+				_ => Vec::new(),
+			};
+			EntryStatus {
+				dialable: exclusions.is_empty(),
+				next_eligible: exclusions.iter().map(DialExclusion::until).collect::<Option<Vec<_>>>().and_then(|u| u.into_iter().max()),
+				exclusions: exclusions,
+				entry: Some(entry),
+				..Default::default()
+			}
+		}))
+	}
+	fn add_node(&self, node: String, _connect_now: bool) -> Result<(String, bool), String> {
+		let url = normalize_node_url(&node).map_err(|e| e.to_string())?;
+		fixture_contains(&url).map(|known| (url, !known))
+	}
+	fn import_node_list(&self, source: String, format: ListFormat) -> Result<IngestStats, String> {
+		let mut stats = IngestStats::default();
+		for (index, line) in source.lines().enumerate().filter(|&(_, l)| !l.trim().is_empty()) {
+			let result = match (format, normalize_node_url(line.trim())) {
+				(ListFormat::EnrBase64Lines, _) => ListEntryResult::Unsupported,
+				(_, Err(e)) => ListEntryResult::Invalid(e.to_string()),
+				(_, Ok(ref url)) if fixture_contains(url)? => ListEntryResult::Known,
+				(_, Ok(url)) => ListEntryResult::Added(parse_node_id(&url).map_err(|e| e.to_string())?),
+			};
+			stats.entries.push((index + 1, result));
+		}
+		Ok(stats)
+	}
+	fn suggest_bootnodes(&self, limit: usize) -> Vec<String> {
+		node_table_fixture().into_iter().filter(|e| !e.useless && !e.banned).take(limit).map(|e| e.url).collect()
+	}
+	fn export_node_list(&self, limit: usize) -> String {
+		node_table_fixture().into_iter().filter(|e| e.last_contact.is_some()).take(limit).map(|e| e.url + "\n").collect()
+	}
+	fn node_table_command(&self, command: String) -> Result<String, String> {
+		match command.trim() {
+			"nodetable save" => Ok("Saved 3 nodes\n".into()),
+			_ => Err("Usage: nodetable list [n] | nodetable save".into()),
+		}
+	}
+	fn reload_static_peers(&self) -> Result<StaticPeersUpdate, String> {
+		Ok(StaticPeersUpdate {
+			loaded: 2,
+			added: vec![H512::from(4)],
+			required: vec![H512::from(1)],
+			removed: 1,
+			downgraded: vec![H512::from(2)],
+			errors: Vec::new(),
+		})
+	}
+	fn freeze_node_table(&self, _duration: Duration) -> Result<bool, String> { Ok(true) }
+	fn net_health(&self) -> Option<NetHealth> {
+		Some(NetHealth {
+			verdict: NetHealthVerdict::FewPeers,
+			node_table: NodeTableStats { total: 3, useless: 1, banned: 1, backed_off: 0, filtered: 0, contacted: 1, ipv4: 3, tombstones: 0, rejected: 0, ..Default::default() },
+			discovery: None,
+			sessions: SessionStats { egress: 1, protocols: vec![("eth".to_owned(), 1)].into_iter().collect(), ..Default::default() },
+			ip_filter: "all".into(),
+			metrics: None,
+			skipped_nodes: Vec::new(),
+			recent_evictions: Vec::new(),
+			advertised_endpoint: None,
+			dial_plan: None,
+			sibling_endpoints: Vec::new(),
+		})
+	}
+}
+
+fn fixture_contains(node: &str) -> Result<bool, String> {
+	let id = parse_node_id(node).map_err(|e| e.to_string())?;
+	Ok(node_table_fixture().iter().any(|e| e.id == id))
+}
+
+fn node_table_entry(id: u64, attempts: u32, failures: u32, failure_percentage: usize, source: NodeSource) -> NodeTableEntry {
+	let address = format!("22.99.55.{}:7770", id);
+	NodeTableEntry {
+		id: H512::from(id),
+		url: format!("enode://{:x}@{}", H512::from(id), address),
+		address: address.parse().unwrap(),
+		attempts: attempts,
+		failures: failures,
+		failure_percentage: failure_percentage,
+		useless: false,
+		banned: false,
+		stalled: false,
+		last_contact: None,
+		source: source,
+		candidate_address: None,
+		previous_address: None,
+		ban_reason: None,
+		useless_reason: None,
+		address_history: Vec::new(),
+		p2p_version: None,
+		snappy: None,
+		trusted: false,
+		chain_info: None,
+		other_chain: false,
+	}
+}
+
+/// Node table entries returned by `TestManageNetwork`.
+fn node_table_fixture() -> Vec<NodeTableEntry> {
+	let mut boot = node_table_entry(1, 2, 0, 0, NodeSource::Boot);
+	boot.last_contact = Some(UNIX_EPOCH + Duration::from_secs(1_500_000_000));
+	let mut banned = node_table_entry(2, 2, 1, 50, NodeSource::Discovery);
+	banned.banned = true;
+	banned.ban_reason = Some("invalid block".into());
+	let mut useless = node_table_entry(3, 1, 1, 100, NodeSource::Inbound);
+	useless.useless = true;
+	useless.useless_reason = Some("incompatible protocol".into());
+	vec![boot, banned, useless]
 }
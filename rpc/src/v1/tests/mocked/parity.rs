@@ -337,6 +337,76 @@ fn rpc_parity_net_peers() {
 	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
 }
 
+#[test]
+fn rpc_parity_node_table() {
+	let deps = Dependencies::new();
+	let io = deps.default_client();
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_nodeTable", "params":[], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":[{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.1:7770","address":"22.99.55.1:7770","attempts":2,"failures":0,"failurePercentage":0,"useless":false,"banned":false,"stalled":false,"lastContact":1500000000,"source":"boot","candidateAddress":null,"previousAddress":null,"banReason":null,"uselessReason":null,"trusted":false},{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002@22.99.55.2:7770","address":"22.99.55.2:7770","attempts":2,"failures":1,"failurePercentage":50,"useless":false,"banned":true,"stalled":false,"lastContact":null,"source":"discovery","candidateAddress":null,"previousAddress":null,"banReason":"invalid block","uselessReason":null,"trusted":false}],"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_nodeTable", "params":[null, true], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":[{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.1:7770","address":"22.99.55.1:7770","attempts":2,"failures":0,"failurePercentage":0,"useless":false,"banned":false,"stalled":false,"lastContact":1500000000,"source":"boot","candidateAddress":null,"previousAddress":null,"banReason":null,"uselessReason":null,"trusted":false},{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002@22.99.55.2:7770","address":"22.99.55.2:7770","attempts":2,"failures":1,"failurePercentage":50,"useless":false,"banned":true,"stalled":false,"lastContact":null,"source":"discovery","candidateAddress":null,"previousAddress":null,"banReason":"invalid block","uselessReason":null,"trusted":false},{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003@22.99.55.3:7770","address":"22.99.55.3:7770","attempts":1,"failures":1,"failurePercentage":100,"useless":true,"banned":false,"stalled":false,"lastContact":null,"source":"inbound","candidateAddress":null,"previousAddress":null,"banReason":null,"uselessReason":"incompatible protocol","trusted":false}],"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_nodeTable", "params":[1], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":[{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.1:7770","address":"22.99.55.1:7770","attempts":2,"failures":0,"failurePercentage":0,"useless":false,"banned":false,"stalled":false,"lastContact":1500000000,"source":"boot","candidateAddress":null,"previousAddress":null,"banReason":null,"uselessReason":null,"trusted":false}],"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+}
+
+#[test]
+fn rpc_parity_node_status() {
+	let deps = Dependencies::new();
+	let io = deps.default_client();
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_nodeStatus", "params":["enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002@22.99.55.2:7770"], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":{"entry":{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002@22.99.55.2:7770","address":"22.99.55.2:7770","attempts":2,"failures":1,"failurePercentage":50,"useless":false,"banned":true,"stalled":false,"lastContact":null,"source":"discovery","candidateAddress":null,"previousAddress":null,"banReason":"invalid block","uselessReason":null,"trusted":false},"exclusions":[{"kind":"banned","reason":"invalid block","until":1500000600}],"dialable":false,"nextEligible":1500000600},"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_nodeStatus", "params":["0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000009"], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":null,"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_nodeStatus", "params":["nope"], "id": 1}"#;
+	let response = io.handle_request_sync(request).unwrap();
+	assert!(response.contains(r#""error":"#), "{}", response);
+}
+
+#[test]
+fn rpc_parity_net_health() {
+	let deps = Dependencies::new();
+	let io = deps.default_client();
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_netHealth", "params":[], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":{"verdict":"fewPeers","nodeTable":{"total":3,"useless":1,"banned":1,"backedOff":0,"filtered":0,"contacted":1,"ipv4":3,"ipv6":0,"tombstones":0,"rejected":0,"persistenceError":null,"outage":false,"quarantined":0,"evictions":{},"bootstrapped":false,"portCapped":0,"sibling":0,"siblingRejected":0,"idMismatches":0,"flaggedMismatches":0,"activeSessions":0,"connectedSkipped":0,"connectedIpSkipped":0,"loading":false},"discovery":null,"sessions":{"handshakes":0,"ingress":0,"egress":1,"protocols":{"eth":1}},"ipFilter":"all"},"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+}
+
+#[test]
+fn rpc_parity_suggest_bootnodes() {
+	let deps = Dependencies::new();
+	let io = deps.default_client();
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_suggestBootnodes", "params":[5], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":["enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.1:7770"],"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_suggestBootnodes", "params":[0], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":[],"id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+}
+
+#[test]
+fn rpc_parity_export_node_list() {
+	let deps = Dependencies::new();
+	let io = deps.default_client();
+
+	let request = r#"{"jsonrpc": "2.0", "method": "parity_exportNodeList", "params":[5], "id": 1}"#;
+	let response = r#"{"jsonrpc":"2.0","result":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.1:7770\n","id":1}"#;
+	assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+}
+
 #[test]
 fn rpc_parity_net_port() {
 	let deps = Dependencies::new();
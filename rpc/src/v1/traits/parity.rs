@@ -28,7 +28,7 @@ use v1::types::{
 	TransactionStats, LocalTransactionStatus,
 	BlockNumber, ConsensusCapability, VersionInfo,
 	OperationsInfo, DappId, ChainStatus,
-	AccountInfo, HwAccountInfo, RichHeader,
+	AccountInfo, HwAccountInfo, RichHeader, NodeTableEntry, NodeStatus, NetHealth,
 };
 
 build_rpc_trait! {
@@ -92,6 +92,30 @@ build_rpc_trait! {
 		#[rpc(name = "parity_netPort")]
 		fn net_port(&self) -> Result<u16>;
 
+		/// Returns node table entries with statistics, ordered by failure percentage.
+		/// Takes an optional limit and whether to include nodes marked as useless.
+		#[rpc(name = "parity_nodeTable")]
+		fn node_table(&self, Trailing<Option<usize>>, Trailing<bool>) -> Result<Vec<NodeTableEntry>>;
+
+		/// Returns the node table entry of a node given by its id or enode URL with every reason it is not dialled,
+		/// whether it is dialable and when it will be. Returns null if the node is neither known nor banned.
+		#[rpc(name = "parity_nodeStatus")]
+		fn node_status(&self, String) -> Result<Option<NodeStatus>>;
+
+		/// Returns a summary of the node table, discovery and session state with an overall verdict.
+		#[rpc(name = "parity_netHealth")]
+		fn net_health(&self) -> Result<NetHealth>;
+
+		/// Returns enode URLs of the best public nodes in the node table, for publishing as a boot node list.
+		#[rpc(name = "parity_suggestBootnodes")]
+		fn suggest_bootnodes(&self, usize) -> Result<Vec<String>>;
+
+		/// Returns a newline-separated list of enode URLs of reliable public nodes in the node table, for
+		/// generating published node lists. Nodes are included as set by `--node-table-export-min-successes` and
+		/// `--node-table-export-max-failure`.
+		#[rpc(name = "parity_exportNodeList")]
+		fn export_node_list(&self, usize) -> Result<String>;
+
 		/// Returns rpc settings
 		#[rpc(name = "parity_rpcSettings")]
 		fn rpc_settings(&self) -> Result<RpcSettings>;
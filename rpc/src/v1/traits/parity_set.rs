@@ -17,8 +17,9 @@
 //! Parity-specific rpc interface for operations altering the settings.
 
 use jsonrpc_core::{BoxFuture, Result};
+use jsonrpc_macros::Trailing;
 
-use v1::types::{Bytes, H160, H256, U256, ReleaseInfo, Transaction, LocalDapp};
+use v1::types::{Bytes, H160, H256, U256, ReleaseInfo, Transaction, LocalDapp, AddedNode, NodeListFormat, ImportedNodes, StaticPeers};
 
 build_rpc_trait! {
 	/// Parity-specific rpc interface for operations altering the settings.
@@ -59,9 +60,53 @@ build_rpc_trait! {
 		#[rpc(name = "parity_addReservedPeer")]
 		fn add_reserved_peer(&self, String) -> Result<bool>;
 
-		/// Remove a reserved peer.
+		/// Remove a reserved peer. The peer stays in the node table as an ordinary node unless the second
+		/// parameter is `true`.
 		#[rpc(name = "parity_removeReservedPeer")]
-		fn remove_reserved_peer(&self, String) -> Result<bool>;
+		fn remove_reserved_peer(&self, String, Trailing<bool>) -> Result<bool>;
+
+		/// Remove a node, given as an enode URL or node id, from the node table and disconnect it.
+		/// Returns `false` if the node is not known.
+		#[rpc(name = "parity_removeNode")]
+		fn remove_node(&self, String) -> Result<bool>;
+
+		/// Ban a node, given as an enode URL or node id, for a number of seconds and disconnect it.
+		/// Returns `false` if the node is not known.
+		#[rpc(name = "parity_banNode")]
+		fn ban_node(&self, String, u64) -> Result<bool>;
+
+		/// Clear the useless and banned flags and the dial backoff of a node, given as an enode URL or node id.
+		/// Returns `false` if the node is not known.
+		#[rpc(name = "parity_retryNode")]
+		fn retry_node(&self, String) -> Result<bool>;
+
+		/// Add a node, given as an enode URL, to the node table without making it reserved.
+		/// When the flag is set the node is dialed right away.
+		#[rpc(name = "parity_addNode")]
+		fn add_node(&self, String, bool) -> Result<AddedNode>;
+
+		/// Add the nodes of a list, given as a file path or as the list itself, in the `enode` or `enr` format to the
+		/// node table. Blank lines and lines starting with `#` are skipped. Returns the number of added and known
+		/// nodes and the line number and error of every entry that was not added.
+		#[rpc(name = "parity_importNodeList")]
+		fn import_node_list(&self, String, NodeListFormat) -> Result<ImportedNodes>;
+
+		/// Run a node table command and return its plain-text output. The commands are `nodetable list [n]`,
+		/// `nodetable info <id>`, `nodetable ban <id> <secs>`, `nodetable remove <id>` and `nodetable save`.
+		#[rpc(name = "parity_nodeTableCommand")]
+		fn node_table_command(&self, String) -> Result<String>;
+
+		/// Read the static peers file given with `--static-peers` again. Peers no longer in the file or no longer
+		/// marked as required are made optional but stay in the node table.
+		#[rpc(name = "parity_reloadStaticPeers")]
+		fn reload_static_peers(&self) -> Result<StaticPeers>;
+
+		/// Save the node table and keep it from being saved again for the given number of seconds, at most an hour,
+		/// e.g. while the data directory is backed up. Calling it again before it expired extends the freeze
+		/// without saving, zero ends it and saves the changes made meanwhile. Returns whether the node table file
+		/// is up to date.
+		#[rpc(name = "parity_freezeNodeTable")]
+		fn freeze_node_table(&self, u64) -> Result<bool>;
 
 		/// Drop all non-reserved peers.
 		#[rpc(name = "parity_dropNonReservedPeers")]
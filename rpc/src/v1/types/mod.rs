@@ -31,7 +31,9 @@ mod hash;
 mod histogram;
 mod index;
 mod log;
+mod net_health;
 mod node_kind;
+mod node_table;
 mod provenance;
 mod receipt;
 mod rpc_settings;
@@ -64,7 +66,9 @@ pub use self::hash::{H64, H160, H256, H512, H520, H2048};
 pub use self::histogram::Histogram;
 pub use self::index::Index;
 pub use self::log::Log;
+pub use self::net_health::{NetHealth, NetHealthVerdict, NodeTableStats, DiscoveryStats, SessionStats, SkippedNode};
 pub use self::node_kind::{NodeKind, Availability, Capability};
+pub use self::node_table::{NodeTableEntry, NodeChainInfo, NodeStatus, DialExclusion, DialExclusionKind, NodeSource, AddedNode, NodeListFormat, ImportedNodes, ImportFailure, StaticPeers};
 pub use self::provenance::{Origin, DappId};
 pub use self::receipt::Receipt;
 pub use self::rpc_settings::RpcSettings;
@@ -0,0 +1,438 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Network health summary.
+
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+use ethsync;
+use v1::types::H512;
+
+/// Network health summary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetHealth {
+	/// Overall verdict
+	pub verdict: NetHealthVerdict,
+	/// Node table statistics
+	#[serde(rename="nodeTable")]
+	pub node_table: NodeTableStats,
+	/// Discovery statistics, null if discovery is disabled
+	pub discovery: Option<DiscoveryStats>,
+	/// Current sessions
+	pub sessions: SessionStats,
+	/// Active IP filter in `--allow-ips` form
+	#[serde(rename="ipFilter")]
+	pub ip_filter: String,
+	/// Node table metrics in the Prometheus text format, omitted unless built with the `metrics` feature
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub metrics: Option<String>,
+	/// Saved node table entries skipped on load, omitted if there were none
+	#[serde(rename="skippedNodes", default, skip_serializing_if = "Vec::is_empty")]
+	pub skipped_nodes: Vec<SkippedNode>,
+	/// Latest nodes dropped from the node table, oldest first, omitted if there were none
+	#[serde(rename="recentEvictions", default, skip_serializing_if = "Vec::is_empty")]
+	pub recent_evictions: Vec<Eviction>,
+	/// Endpoint advertised to other nodes, omitted until the network is started
+	#[serde(rename="advertisedEndpoint", default, skip_serializing_if = "Option::is_none")]
+	pub advertised_endpoint: Option<AdvertisedEndpoint>,
+	/// Dial candidates of the last connection round, omitted before the first one
+	#[serde(rename="dialPlan", default, skip_serializing_if = "Option::is_none")]
+	pub dial_plan: Option<DialPlan>,
+	/// Endpoints of other instances on this host whose nodes are excluded, omitted if there are none
+	#[serde(rename="siblingEndpoints", default, skip_serializing_if = "Vec::is_empty")]
+	pub sibling_endpoints: Vec<String>,
+}
+
+impl From<ethsync::NetHealth> for NetHealth {
+	fn from(health: ethsync::NetHealth) -> Self {
+		NetHealth {
+			verdict: health.verdict.into(),
+			node_table: health.node_table.into(),
+			discovery: health.discovery.map(Into::into),
+			sessions: health.sessions.into(),
+			ip_filter: health.ip_filter,
+			metrics: health.metrics,
+			skipped_nodes: health.skipped_nodes.into_iter().map(Into::into).collect(),
+			recent_evictions: health.recent_evictions.into_iter().map(Into::into).collect(),
+			advertised_endpoint: health.advertised_endpoint.map(Into::into),
+			dial_plan: health.dial_plan.map(Into::into),
+			sibling_endpoints: health.sibling_endpoints.iter().map(ToString::to_string).collect(),
+		}
+	}
+}
+
+/// Coarse assessment of the peering state.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NetHealthVerdict {
+	/// Enough peers.
+	#[serde(rename="good")]
+	Good,
+	/// Too few peers although there are nodes to try.
+	#[serde(rename="fewPeers")]
+	FewPeers,
+	/// Too few peers and discovery is disabled or has not found any nodes.
+	#[serde(rename="noDiscovery")]
+	NoDiscovery,
+	/// Too few peers and the IP filter rejects every known node.
+	#[serde(rename="allFiltered")]
+	AllFiltered,
+	/// Too few peers and dials to many subnets failed in a row, the connection of the node is likely down.
+	#[serde(rename="outage")]
+	Outage,
+}
+
+impl From<ethsync::NetHealthVerdict> for NetHealthVerdict {
+	fn from(verdict: ethsync::NetHealthVerdict) -> Self {
+		match verdict {
+			ethsync::NetHealthVerdict::Good => NetHealthVerdict::Good,
+			ethsync::NetHealthVerdict::FewPeers => NetHealthVerdict::FewPeers,
+			ethsync::NetHealthVerdict::NoDiscovery => NetHealthVerdict::NoDiscovery,
+			ethsync::NetHealthVerdict::AllFiltered => NetHealthVerdict::AllFiltered,
+			ethsync::NetHealthVerdict::Outage => NetHealthVerdict::Outage,
+		}
+	}
+}
+
+/// Node table statistics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeTableStats {
+	/// Number of known nodes
+	pub total: usize,
+	/// Nodes marked as useless
+	pub useless: usize,
+	/// Active bans
+	pub banned: usize,
+	/// Nodes waiting for their dial backoff to expire
+	#[serde(rename="backedOff")]
+	pub backed_off: usize,
+	/// Nodes rejected by the IP filter
+	pub filtered: usize,
+	/// Nodes contacted successfully at least once
+	pub contacted: usize,
+	/// Nodes with an IPv4 address
+	pub ipv4: usize,
+	/// Nodes with an IPv6 address
+	pub ipv6: usize,
+	/// Recently removed nodes that discovery may not add again yet
+	pub tombstones: usize,
+	/// Nodes rejected for having the zero id, our own id or our own address
+	pub rejected: usize,
+	/// Last save error if saving the node table was given up, the table is kept in memory only then
+	#[serde(rename="persistenceError")]
+	pub persistence_error: Option<String>,
+	/// The connection of the node is suspected to be down, dial failures are not counted against the peers meanwhile
+	pub outage: bool,
+	/// Nodes with dial failures not counted during the current outage
+	pub quarantined: usize,
+	/// Nodes dropped from the table since the start by reason, `removed`, `discovery`, `expired`, `capacity`, `rotated` or `filtered`
+	#[serde(default)]
+	pub evictions: BTreeMap<String, usize>,
+	/// Enough reliable nodes are known, boot nodes are only dialled while no peers are connected
+	#[serde(default)]
+	pub bootstrapped: bool,
+	/// Times an IP reached the limit of dialled ports, its other nodes were deferred
+	#[serde(rename="portCapped", default)]
+	pub port_capped: usize,
+	/// Known nodes at the endpoint of another instance on this host, skipped when dialling
+	#[serde(default)]
+	pub sibling: usize,
+	/// Nodes rejected for the endpoint of another instance on this host
+	#[serde(rename="siblingRejected", default)]
+	pub sibling_rejected: usize,
+	/// Dials whose peer announced another id in its hello than the one it proved
+	#[serde(rename="idMismatches", default)]
+	pub id_mismatches: usize,
+	/// Nodes whose last dial announced another id, their entries are not changed
+	#[serde(rename="flaggedMismatches", default)]
+	pub flagged_mismatches: usize,
+	/// Dial attempts and connection outcomes of the last 24 hours, one entry per hour, oldest first
+	#[serde(default, skip_serializing_if="Vec::is_empty")]
+	pub history: Vec<OutcomeBucket>,
+	/// Open sessions known to the node table
+	#[serde(rename="activeSessions", default)]
+	pub active_sessions: usize,
+	/// Times a node was not selected for dialling because it is connected
+	#[serde(rename="connectedSkipped", default)]
+	pub connected_skipped: usize,
+	/// Times a node was not selected for dialling because a node on its IP is connected
+	#[serde(rename="connectedIpSkipped", default)]
+	pub connected_ip_skipped: usize,
+	/// The saved node table is still being loaded
+	#[serde(default)]
+	pub loading: bool,
+}
+
+impl From<ethsync::NodeTableStats> for NodeTableStats {
+	fn from(stats: ethsync::NodeTableStats) -> Self {
+		NodeTableStats {
+			total: stats.total,
+			useless: stats.useless,
+			banned: stats.banned,
+			backed_off: stats.backed_off,
+			filtered: stats.filtered,
+			contacted: stats.contacted,
+			ipv4: stats.ipv4,
+			ipv6: stats.ipv6,
+			tombstones: stats.tombstones,
+			rejected: stats.rejected,
+			persistence_error: stats.persistence_error,
+			outage: stats.outage,
+			quarantined: stats.quarantined,
+			evictions: stats.evictions.into_iter().map(|(reason, count)| (reason.to_string(), count)).collect(),
+			bootstrapped: stats.bootstrapped,
+			port_capped: stats.port_capped,
+			sibling: stats.sibling,
+			sibling_rejected: stats.sibling_rejected,
+			id_mismatches: stats.id_mismatches,
+			flagged_mismatches: stats.flagged_mismatches,
+			history: stats.history.into_iter().map(Into::into).collect(),
+			active_sessions: stats.active_sessions,
+			connected_skipped: stats.connected_skipped,
+			connected_ip_skipped: stats.connected_ip_skipped,
+			loading: stats.loading,
+		}
+	}
+}
+
+/// Connection outcomes within one hour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutcomeBucket {
+	/// Start of the hour in seconds since UNIX epoch
+	pub start: u64,
+	/// Dial attempts
+	pub attempts: u32,
+	/// Established sessions
+	pub successes: u32,
+	/// Failed dials and sessions
+	pub failures: u32,
+	/// Failures during a suspected outage, not counted against the peers
+	pub quarantined: u32,
+	/// Sessions that stopped answering pings
+	pub stalled: u32,
+}
+
+impl From<ethsync::OutcomeBucket> for OutcomeBucket {
+	fn from(bucket: ethsync::OutcomeBucket) -> Self {
+		OutcomeBucket {
+			start: bucket.start,
+			attempts: bucket.attempts,
+			successes: bucket.successes,
+			failures: bucket.failures,
+			quarantined: bucket.quarantined,
+			stalled: bucket.stalled,
+		}
+	}
+}
+
+/// Discovery bucket statistics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryStats {
+	/// Number of nodes in all buckets
+	pub nodes: usize,
+	/// Number of non-empty buckets
+	pub buckets: usize,
+	/// Nodes with an unanswered ping
+	pub pinging: usize,
+}
+
+impl From<ethsync::DiscoveryStats> for DiscoveryStats {
+	fn from(stats: ethsync::DiscoveryStats) -> Self {
+		DiscoveryStats {
+			nodes: stats.nodes,
+			buckets: stats.buckets,
+			pinging: stats.pinging,
+		}
+	}
+}
+
+/// Session counts by direction and protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionStats {
+	/// Sessions still in handshake
+	pub handshakes: usize,
+	/// Ready inbound sessions
+	pub ingress: usize,
+	/// Ready outbound sessions
+	pub egress: usize,
+	/// Ready sessions by protocol name
+	pub protocols: BTreeMap<String, usize>,
+}
+
+impl From<ethsync::SessionStats> for SessionStats {
+	fn from(stats: ethsync::SessionStats) -> Self {
+		SessionStats {
+			handshakes: stats.handshakes,
+			ingress: stats.ingress,
+			egress: stats.egress,
+			protocols: stats.protocols,
+		}
+	}
+}
+
+/// Saved node table entry that could not be loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedNode {
+	/// Position of the entry in the saved table
+	pub index: usize,
+	/// Saved URL
+	pub url: String,
+	/// Why the entry was skipped, `invalid-id` or `bad-address`
+	pub reason: String,
+	/// Parse error of the URL
+	pub error: String,
+}
+
+impl From<ethsync::SkippedNode> for SkippedNode {
+	fn from(node: ethsync::SkippedNode) -> Self {
+		SkippedNode {
+			index: node.index,
+			url: node.url,
+			reason: node.reason.to_string(),
+			error: node.error,
+		}
+	}
+}
+
+/// Node dropped from the node table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Eviction {
+	/// Public node id
+	pub id: H512,
+	/// TCP endpoint address
+	pub address: String,
+	/// Why the node was dropped
+	pub reason: String,
+	/// When the node was dropped in seconds since UNIX epoch
+	pub time: u64,
+}
+
+impl From<ethsync::Eviction> for Eviction {
+	fn from(eviction: ethsync::Eviction) -> Self {
+		Eviction {
+			id: eviction.id.into(),
+			address: format!("{}", eviction.endpoint.address),
+			reason: eviction.reason.to_string(),
+			time: eviction.time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+		}
+	}
+}
+
+/// Public endpoint advertised to other nodes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdvertisedEndpoint {
+	/// TCP endpoint address
+	pub address: String,
+	/// Discovery port
+	#[serde(rename="udpPort")]
+	pub udp_port: u16,
+	/// Seconds since it is advertised
+	pub age: u64,
+	/// Changes since the start, e.g. after the NAT mapping changed
+	pub changes: usize,
+}
+
+impl From<ethsync::AdvertisedEndpoint> for AdvertisedEndpoint {
+	fn from(advertised: ethsync::AdvertisedEndpoint) -> Self {
+		AdvertisedEndpoint {
+			address: format!("{}", advertised.endpoint.address),
+			udp_port: advertised.endpoint.udp_port,
+			age: advertised.age.as_secs(),
+			changes: advertised.changes,
+		}
+	}
+}
+
+/// Dial candidates of the last connection round.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DialPlan {
+	/// Seconds since the round
+	pub age: u64,
+	/// Candidates in the order they were considered
+	pub candidates: Vec<DialCandidate>,
+}
+
+impl From<ethsync::DialPlan> for DialPlan {
+	fn from(plan: ethsync::DialPlan) -> Self {
+		DialPlan {
+			age: plan.age.as_secs(),
+			candidates: plan.candidates.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
+/// Node considered for dialling.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DialCandidate {
+	/// Public node id
+	pub id: H512,
+	/// TCP endpoint address, null for a reserved node not in the node table
+	pub address: Option<String>,
+	/// Node table score, null for a reserved node not in the node table
+	pub score: Option<usize>,
+	/// Why the node was selected, `required`, `recently-lost`, `boot-fallback`, `warm`, `quality` or `diverse-fill`
+	pub reason: String,
+	/// Whether a dial was started
+	pub dialled: bool,
+}
+
+impl From<ethsync::DialCandidate> for DialCandidate {
+	fn from(candidate: ethsync::DialCandidate) -> Self {
+		DialCandidate {
+			id: candidate.id.into(),
+			address: candidate.endpoint.map(|e| format!("{}", e.address)),
+			score: candidate.score,
+			reason: candidate.reason.to_string(),
+			dialled: candidate.dialled,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json;
+	use v1::types::H512;
+	use super::{NetHealth, NetHealthVerdict, NodeTableStats, OutcomeBucket, SessionStats, SkippedNode, Eviction, AdvertisedEndpoint, DialPlan, DialCandidate};
+
+	#[test]
+	fn net_health_verdict() {
+		let s = r#"["good","fewPeers","noDiscovery","allFiltered","outage"]"#;
+		let verdicts = vec![NetHealthVerdict::Good, NetHealthVerdict::FewPeers, NetHealthVerdict::NoDiscovery, NetHealthVerdict::AllFiltered, NetHealthVerdict::Outage];
+
+		assert_eq!(serde_json::to_string(&verdicts).unwrap(), s);
+		assert_eq!(serde_json::from_str::<Vec<NetHealthVerdict>>(s).unwrap(), verdicts);
+	}
+
+	#[test]
+	fn net_health() {
+		let health = NetHealth {
+			verdict: NetHealthVerdict::NoDiscovery,
+			node_table: NodeTableStats { total: 3, useless: 1, banned: 0, backed_off: 1, filtered: 0, contacted: 2, ipv4: 2, ipv6: 1, tombstones: 1, rejected: 0, persistence_error: None, outage: false, quarantined: 0, evictions: vec![("expired".to_owned(), 1)].into_iter().collect(), bootstrapped: true, port_capped: 2, sibling: 1, sibling_rejected: 3, id_mismatches: 2, flagged_mismatches: 1, history: vec![OutcomeBucket { start: 1500001200, attempts: 4, successes: 2, failures: 1, quarantined: 0, stalled: 1 }], active_sessions: 2, connected_skipped: 5, connected_ip_skipped: 1, loading: false },
+			discovery: None,
+			sessions: SessionStats { handshakes: 1, ingress: 0, egress: 2, protocols: vec![("eth".to_owned(), 2)].into_iter().collect() },
+			ip_filter: "public".into(),
+			metrics: None,
+			skipped_nodes: vec![SkippedNode { index: 4, url: "enode://00@22.99.55.44:7770".into(), reason: "invalid-id".into(), error: "Invalid node id".into() }],
+			recent_evictions: vec![Eviction { id: H512::from(5), address: "22.99.55.45:7770".into(), reason: "expired".into(), time: 1_500_000_000 }],
+			advertised_endpoint: Some(AdvertisedEndpoint { address: "22.99.55.50:30303".into(), udp_port: 30303, age: 60, changes: 1 }),
+			dial_plan: Some(DialPlan { age: 1, candidates: vec![DialCandidate { id: H512::from(6), address: Some("22.99.55.46:30303".into()), score: Some(120), reason: "quality".into(), dialled: true }] }),
+			sibling_endpoints: vec!["0.0.0.0:30304".into()],
+		};
+		let s = r#"{"verdict":"noDiscovery","nodeTable":{"total":3,"useless":1,"banned":0,"backedOff":1,"filtered":0,"contacted":2,"ipv4":2,"ipv6":1,"tombstones":1,"rejected":0,"persistenceError":null,"outage":false,"quarantined":0,"evictions":{"expired":1},"bootstrapped":true,"portCapped":2,"sibling":1,"siblingRejected":3,"idMismatches":2,"flaggedMismatches":1,"history":[{"start":1500001200,"attempts":4,"successes":2,"failures":1,"quarantined":0,"stalled":1}],"activeSessions":2,"connectedSkipped":5,"connectedIpSkipped":1,"loading":false},"discovery":null,"sessions":{"handshakes":1,"ingress":0,"egress":2,"protocols":{"eth":2}},"ipFilter":"public","skippedNodes":[{"index":4,"url":"enode://00@22.99.55.44:7770","reason":"invalid-id","error":"Invalid node id"}],"recentEvictions":[{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000005","address":"22.99.55.45:7770","reason":"expired","time":1500000000}],"advertisedEndpoint":{"address":"22.99.55.50:30303","udpPort":30303,"age":60,"changes":1},"dialPlan":{"age":1,"candidates":[{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000006","address":"22.99.55.46:30303","score":120,"reason":"quality","dialled":true}]},"siblingEndpoints":["0.0.0.0:30304"]}"#;
+
+		assert_eq!(serde_json::to_string(&health).unwrap(), s);
+		assert_eq!(serde_json::from_str::<NetHealth>(s).unwrap(), health);
+	}
+}
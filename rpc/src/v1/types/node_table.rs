@@ -0,0 +1,466 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Node table entries.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use ethsync::{self, NodeTableEntry as SyncNodeTableEntry, PeerChainInfo};
+use v1::types::{H256, H512};
+
+/// Node table entry with statistics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeTableEntry {
+	/// Public node id
+	pub id: H512,
+	/// Enode URL
+	pub enode: String,
+	/// TCP endpoint address
+	pub address: String,
+	/// Number of connection attempts
+	pub attempts: u32,
+	/// Number of failed connection attempts
+	pub failures: u32,
+	/// Failure percentage, rounded to the nearest integer
+	#[serde(rename="failurePercentage")]
+	pub failure_percentage: usize,
+	/// Marked as useless until the next node table refresh
+	pub useless: bool,
+	/// Temporarily banned
+	pub banned: bool,
+	/// The last session stopped answering pings, cleared on the next successful contact
+	#[serde(default)]
+	pub stalled: bool,
+	/// Last successful contact in seconds since UNIX epoch
+	#[serde(rename="lastContact")]
+	pub last_contact: Option<u64>,
+	/// Where the node was learned from
+	pub source: NodeSource,
+	/// Address reported by discovery that has not been accepted yet
+	#[serde(rename="candidateAddress")]
+	pub candidate_address: Option<String>,
+	/// Address used before the last endpoint change
+	#[serde(rename="previousAddress")]
+	pub previous_address: Option<String>,
+	/// Why the node is banned, kept across restarts
+	#[serde(rename="banReason")]
+	pub ban_reason: Option<String>,
+	/// Why the node is marked as useless, kept across restarts
+	#[serde(rename="uselessReason")]
+	pub useless_reason: Option<String>,
+	/// Whether the claims of the node are taken at face value, e.g. for the own infrastructure of the operator
+	#[serde(default)]
+	pub trusted: bool,
+	/// devp2p version the node announced in its last Hello packet, left out if it was never connected
+	#[serde(rename="p2pVersion", default, skip_serializing_if="Option::is_none")]
+	pub p2p_version: Option<u32>,
+	/// Whether snappy compression worked with the node, `false` if it failed or was not negotiated
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub snappy: Option<bool>,
+	/// Chain the node advertised last, left out if it never did
+	#[serde(rename="chainInfo", default, skip_serializing_if="Option::is_none")]
+	pub chain_info: Option<NodeChainInfo>,
+}
+
+/// Chain advertised by a node in its status message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeChainInfo {
+	/// Network id
+	#[serde(rename="networkId")]
+	pub network_id: u64,
+	/// Genesis block hash
+	pub genesis: H256,
+	/// EIP-2124 fork hash in hex, left out if the protocol does not advertise one
+	#[serde(rename="forkHash", default, skip_serializing_if="Option::is_none")]
+	pub fork_hash: Option<String>,
+	/// Whether it is another chain than ours, such nodes are dialled last until the next fork
+	#[serde(rename="otherChain")]
+	pub other_chain: bool,
+}
+
+impl NodeChainInfo {
+	fn new(info: PeerChainInfo, other_chain: bool) -> Self {
+		NodeChainInfo {
+			network_id: info.network_id,
+			genesis: info.genesis.into(),
+			fork_hash: info.fork_hash.map(|hash| format!("0x{:08x}", hash)),
+			other_chain: other_chain,
+		}
+	}
+}
+
+impl From<SyncNodeTableEntry> for NodeTableEntry {
+	fn from(entry: SyncNodeTableEntry) -> Self {
+		let other_chain = entry.other_chain;
+		NodeTableEntry {
+			id: entry.id.into(),
+			enode: entry.url,
+			address: format!("{}", entry.address),
+			attempts: entry.attempts,
+			failures: entry.failures,
+			failure_percentage: entry.failure_percentage,
+			useless: entry.useless,
+			banned: entry.banned,
+			stalled: entry.stalled,
+			last_contact: entry.last_contact.and_then(unix_secs),
+			source: entry.source.into(),
+			candidate_address: entry.candidate_address.map(|a| format!("{}", a)),
+			previous_address: entry.previous_address.map(|a| format!("{}", a)),
+			ban_reason: entry.ban_reason,
+			useless_reason: entry.useless_reason,
+			trusted: entry.trusted,
+			p2p_version: entry.p2p_version,
+			snappy: entry.snappy,
+			chain_info: entry.chain_info.map(|info| NodeChainInfo::new(info, other_chain)),
+		}
+	}
+}
+
+fn unix_secs(time: SystemTime) -> Option<u64> {
+	time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Node table entry with the reasons it is not dialled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeStatus {
+	/// Node table entry, null for a banned node that is not in the table
+	pub entry: Option<NodeTableEntry>,
+	/// Every reason the node is not dialled
+	pub exclusions: Vec<DialExclusion>,
+	/// Whether the node is dialled when a slot is free
+	pub dialable: bool,
+	/// When the last of the exclusions ends in seconds since UNIX epoch, null if the node is dialable or one of
+	/// them lasts until something changes
+	#[serde(rename="nextEligible")]
+	pub next_eligible: Option<u64>,
+}
+
+impl From<ethsync::EntryStatus> for NodeStatus {
+	fn from(status: ethsync::EntryStatus) -> Self {
+		NodeStatus {
+			entry: status.entry.map(Into::into),
+			exclusions: status.exclusions.into_iter().map(Into::into).collect(),
+			dialable: status.dialable,
+			next_eligible: status.next_eligible.and_then(unix_secs),
+		}
+	}
+}
+
+/// Reason a node is not dialled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DialExclusion {
+	/// Kind of the exclusion
+	pub kind: DialExclusionKind,
+	/// Why the node was banned or marked as useless
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub reason: Option<String>,
+	/// End of the exclusion in seconds since UNIX epoch, left out if it does not end by itself
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub until: Option<u64>,
+	/// `--allow-ips` rule blocking the address of the node
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub rule: Option<String>,
+}
+
+/// Kind of a reason a node is not dialled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DialExclusionKind {
+	/// Marked as useless.
+	#[serde(rename="useless")]
+	Useless,
+	/// Temporarily banned.
+	#[serde(rename="banned")]
+	Banned,
+	/// At our own address.
+	#[serde(rename="ownAddress")]
+	OwnAddress,
+	/// Not required while only reserved peers are dialled.
+	#[serde(rename="reservedOnly")]
+	ReservedOnly,
+	/// Boot node not needed once the node table is bootstrapped.
+	#[serde(rename="bootstrapped")]
+	Bootstrapped,
+	/// Waiting for the dial backoff after failures.
+	#[serde(rename="backoff")]
+	Backoff,
+	/// Kept out by `--allow-ips`.
+	#[serde(rename="filtered")]
+	Filtered,
+	/// Runs none of our protocols.
+	#[serde(rename="capabilities")]
+	Capabilities,
+	/// Connected already.
+	#[serde(rename="connected")]
+	Connected,
+	/// Shares the IP of a connected node.
+	#[serde(rename="connectedIp")]
+	ConnectedIp,
+	/// Too many other ports of the IP dialled recently.
+	#[serde(rename="portCap")]
+	PortCap,
+}
+
+impl From<ethsync::DialExclusion> for DialExclusion {
+	fn from(exclusion: ethsync::DialExclusion) -> Self {
+		use ethsync::DialExclusion as E;
+		let until = exclusion.until().and_then(unix_secs);
+		let (kind, reason, rule) = match exclusion {
+			E::Useless { reason, .. } => (DialExclusionKind::Useless, Some(reason), None),
+			E::Banned { reason, .. } => (DialExclusionKind::Banned, Some(reason), None),
+			E::OwnAddress => (DialExclusionKind::OwnAddress, None, None),
+			E::ReservedOnly => (DialExclusionKind::ReservedOnly, None, None),
+			E::Bootstrapped => (DialExclusionKind::Bootstrapped, None, None),
+			E::Backoff { .. } => (DialExclusionKind::Backoff, None, None),
+			E::Filtered { rule } => (DialExclusionKind::Filtered, None, Some(rule)),
+			E::Capabilities => (DialExclusionKind::Capabilities, None, None),
+			E::Connected => (DialExclusionKind::Connected, None, None),
+			E::ConnectedIp => (DialExclusionKind::ConnectedIp, None, None),
+			E::PortCap => (DialExclusionKind::PortCap, None, None),
+		};
+		DialExclusion {
+			kind: kind,
+			reason: reason,
+			until: until,
+			rule: rule,
+		}
+	}
+}
+
+/// Where a node was learned from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NodeSource {
+	/// Unknown, e.g. loaded from an old node table file.
+	#[serde(rename="unknown")]
+	Unknown,
+	/// Boot node.
+	#[serde(rename="boot")]
+	Boot,
+	/// Reserved node.
+	#[serde(rename="reserved")]
+	Reserved,
+	/// Discovered by the discovery protocol.
+	#[serde(rename="discovery")]
+	Discovery,
+	/// Connected to us.
+	#[serde(rename="inbound")]
+	Inbound,
+	/// Added over RPC.
+	#[serde(rename="rpc")]
+	Rpc,
+	/// Read from a node list.
+	#[serde(rename="imported")]
+	Imported,
+	/// Read from the static peers file.
+	#[serde(rename="staticFile")]
+	StaticFile,
+}
+
+impl From<ethsync::NodeSource> for NodeSource {
+	fn from(source: ethsync::NodeSource) -> Self {
+		match source {
+			ethsync::NodeSource::Unknown => NodeSource::Unknown,
+			ethsync::NodeSource::Boot => NodeSource::Boot,
+			ethsync::NodeSource::Reserved => NodeSource::Reserved,
+			ethsync::NodeSource::Discovery => NodeSource::Discovery,
+			ethsync::NodeSource::Inbound => NodeSource::Inbound,
+			ethsync::NodeSource::Rpc => NodeSource::Rpc,
+			ethsync::NodeSource::Imported => NodeSource::Imported,
+			ethsync::NodeSource::StaticFile => NodeSource::StaticFile,
+		}
+	}
+}
+
+/// Result of adding a node to the node table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddedNode {
+	/// Normalized enode URL
+	pub enode: String,
+	/// Whether the node was not in the table before
+	#[serde(rename="new")]
+	pub is_new: bool,
+}
+
+/// Format of a node list.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NodeListFormat {
+	/// One enode URL per line.
+	#[serde(rename="enode")]
+	Enode,
+	/// One base64 encoded ENR per line.
+	#[serde(rename="enr")]
+	Enr,
+}
+
+impl Into<ethsync::ListFormat> for NodeListFormat {
+	fn into(self) -> ethsync::ListFormat {
+		match self {
+			NodeListFormat::Enode => ethsync::ListFormat::EnodeLines,
+			NodeListFormat::Enr => ethsync::ListFormat::EnrBase64Lines,
+		}
+	}
+}
+
+/// Result of importing a node list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportedNodes {
+	/// Nodes added to the node table
+	pub added: usize,
+	/// Nodes already in the node table
+	pub known: usize,
+	/// Entries that were not added
+	pub failures: Vec<ImportFailure>,
+	/// Whether the list was cut at the maximum number of lines
+	pub truncated: bool,
+}
+
+/// List entry that was not added to the node table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportFailure {
+	/// Line number, starting at 1
+	pub line: usize,
+	/// Why the entry was not added
+	pub error: String,
+}
+
+impl From<ethsync::IngestStats> for ImportedNodes {
+	fn from(stats: ethsync::IngestStats) -> Self {
+		ImportedNodes {
+			added: stats.added().len(),
+			known: stats.entries.iter().filter(|&&(_, ref r)| *r == ethsync::ListEntryResult::Known).count(),
+			failures: stats.failures().into_iter().map(|&(line, ref result)| ImportFailure { line: line, error: result.to_string() }).collect(),
+			truncated: stats.truncated,
+		}
+	}
+}
+
+/// Result of reloading the static peers file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaticPeers {
+	/// Peers in the file
+	pub loaded: usize,
+	/// Peers added to the node table
+	pub added: usize,
+	/// Peers marked as required in the file
+	pub required: usize,
+	/// Peers of the previous load no longer in the file, they are kept in the node table
+	pub removed: usize,
+	/// Peers that are no longer required
+	pub downgraded: usize,
+	/// Entries that could not be parsed
+	pub failures: Vec<ImportFailure>,
+}
+
+impl From<ethsync::StaticPeersUpdate> for StaticPeers {
+	fn from(update: ethsync::StaticPeersUpdate) -> Self {
+		StaticPeers {
+			loaded: update.loaded,
+			added: update.added.len(),
+			required: update.required.len(),
+			removed: update.removed,
+			downgraded: update.downgraded.len(),
+			failures: update.errors.into_iter().map(|(line, e)| ImportFailure { line: line, error: e.to_string() }).collect(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json;
+	use std::time::{Duration, UNIX_EPOCH};
+	use ethsync;
+	use ethsync::PeerChainInfo;
+	use super::{NodeTableEntry, NodeChainInfo, NodeSource, AddedNode, NodeStatus, DialExclusion, DialExclusionKind};
+
+	#[test]
+	fn node_source() {
+		let s = r#"["unknown","boot","reserved","discovery","inbound","rpc","imported","staticFile"]"#;
+		let sources = vec![NodeSource::Unknown, NodeSource::Boot, NodeSource::Reserved, NodeSource::Discovery, NodeSource::Inbound, NodeSource::Rpc, NodeSource::Imported, NodeSource::StaticFile];
+
+		assert_eq!(serde_json::to_string(&sources).unwrap(), s);
+		assert_eq!(serde_json::from_str::<Vec<NodeSource>>(s).unwrap(), sources);
+	}
+
+	#[test]
+	fn node_table_entry() {
+		let entry = NodeTableEntry {
+			id: 1u64.into(),
+			enode: "enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.44:7770".into(),
+			address: "22.99.55.44:7770".into(),
+			attempts: 2,
+			failures: 1,
+			failure_percentage: 50,
+			useless: false,
+			banned: true,
+			stalled: false,
+			last_contact: Some(1_500_000_000),
+			source: NodeSource::Discovery,
+			candidate_address: Some("22.99.55.45:7770".into()),
+			previous_address: None,
+			ban_reason: Some("invalid block".into()),
+			useless_reason: None,
+			trusted: false,
+			p2p_version: None,
+			snappy: None,
+			chain_info: None,
+		};
+		let s = r#"{"id":"0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001","enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.44:7770","address":"22.99.55.44:7770","attempts":2,"failures":1,"failurePercentage":50,"useless":false,"banned":true,"stalled":false,"lastContact":1500000000,"source":"discovery","candidateAddress":"22.99.55.45:7770","previousAddress":null,"banReason":"invalid block","uselessReason":null,"trusted":false}"#;
+
+		assert_eq!(serde_json::to_string(&entry).unwrap(), s);
+		assert_eq!(serde_json::from_str::<NodeTableEntry>(s).unwrap(), entry);
+
+		let entry = NodeTableEntry { stalled: true, trusted: true, p2p_version: Some(5), snappy: Some(false), ..entry };
+		let s = s.replace(r#""stalled":false"#, r#""stalled":true"#);
+		let s = s.replace(r#","trusted":false}"#, r#","trusted":true,"p2pVersion":5,"snappy":false}"#);
+		assert_eq!(serde_json::to_string(&entry).unwrap(), s);
+		assert_eq!(serde_json::from_str::<NodeTableEntry>(&s).unwrap(), entry);
+
+		let chain = PeerChainInfo { network_id: 61, genesis: 5.into(), fork_hash: Some(0xfc64ec04) };
+		let entry = NodeTableEntry { chain_info: Some(NodeChainInfo::new(chain, true)), ..entry };
+		let s = s.replace(r#","snappy":false}"#, r#","snappy":false,"chainInfo":{"networkId":61,"genesis":"0x0000000000000000000000000000000000000000000000000000000000000005","forkHash":"0xfc64ec04","otherChain":true}}"#);
+		assert_eq!(serde_json::to_string(&entry).unwrap(), s);
+		assert_eq!(serde_json::from_str::<NodeTableEntry>(&s).unwrap(), entry);
+	}
+
+	#[test]
+	fn node_status() {
+		let status = ethsync::EntryStatus {
+			exclusions: vec![
+				ethsync::DialExclusion::Banned { reason: "invalid block".into(), until: UNIX_EPOCH + Duration::from_secs(1_500_000_600) },
+				ethsync::DialExclusion::Filtered { rule: "private".into() },
+				ethsync::DialExclusion::Connected,
+			],
+			next_eligible: Some(UNIX_EPOCH + Duration::from_secs(1_500_000_600)),
+			..Default::default()
+		};
+		let status: NodeStatus = status.into();
+		assert_eq!(status.exclusions[1], DialExclusion { kind: DialExclusionKind::Filtered, reason: None, until: None, rule: Some("private".into()) });
+		let s = r#"{"entry":null,"exclusions":[{"kind":"banned","reason":"invalid block","until":1500000600},{"kind":"filtered","rule":"private"},{"kind":"connected"}],"dialable":false,"nextEligible":1500000600}"#;
+
+		assert_eq!(serde_json::to_string(&status).unwrap(), s);
+		assert_eq!(serde_json::from_str::<NodeStatus>(s).unwrap(), status);
+	}
+
+	#[test]
+	fn added_node() {
+		let added = AddedNode {
+			enode: "enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.44:7770".into(),
+			is_new: true,
+		};
+		let s = r#"{"enode":"enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001@22.99.55.44:7770","new":true}"#;
+
+		assert_eq!(serde_json::to_string(&added).unwrap(), s);
+		assert_eq!(serde_json::from_str::<AddedNode>(s).unwrap(), added);
+	}
+}
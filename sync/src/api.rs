@@ -15,12 +15,14 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::collections::{HashMap, BTreeMap};
 use std::io;
+use std::time::Duration;
 use bytes::Bytes;
-use devp2p::{NetworkService, ConnectionFilter};
+use devp2p::{NetworkService, ConnectionFilter, NodeTableEntry, NetHealth, ListFormat, IngestStats, StaticPeersUpdate, EntryStatus};
 use network::{NetworkProtocolHandler, NetworkContext, HostInfo, PeerId, ProtocolId,
-	NetworkConfiguration as BasicNetworkConfiguration, NonReservedPeerMode, Error, ErrorKind};
+	NetworkConfiguration as BasicNetworkConfiguration, NonReservedPeerMode, Error, ErrorKind, PeerChainInfo};
 use ethereum_types::{H256, H512, U256};
 use io::{TimerToken};
 use ethcore::ethstore::ethkey::Secret;
@@ -36,7 +38,7 @@ use chain::{ETH_PACKET_COUNT, SNAPSHOT_SYNC_PACKET_COUNT};
 use light::client::AsLightClient;
 use light::Provider;
 use light::net::{self as light_net, LightProtocol, Params as LightParams, Capabilities, Handler as LightHandler, EventContext};
-use network::IpFilter;
+use network::{IpFilter, NodeTableConfig};
 
 /// Parity sync protocol
 pub const WARP_SYNC_PROTOCOL_ID: ProtocolId = *b"par";
@@ -249,6 +251,12 @@ pub struct EthSync {
 	subprotocol_name: [u8; 3],
 	/// Light subprotocol name.
 	light_subprotocol_name: [u8; 3],
+	/// Fork block checked with peers, if any.
+	fork_block: Option<BlockNumber>,
+	/// Whether the best block is at or past the fork block.
+	fork_passed: AtomicBool,
+	/// Chain we are on, peers that advertise another one are dialled last.
+	local_chain: PeerChainInfo,
 }
 
 impl EthSync {
@@ -288,6 +296,13 @@ impl EthSync {
 			})
 		};
 
+		let fork_block = params.config.fork_block.map(|(number, _)| number);
+		let fork_passed = fork_block.map_or(false, |number| params.chain.chain_info().best_block_number >= number);
+		let local_chain = PeerChainInfo {
+			network_id: params.config.network_id,
+			genesis: params.chain.chain_info().genesis_hash,
+			fork_hash: None,
+		};
 		let chain_sync = ChainSync::new(params.config, &*params.chain);
 		let service = NetworkService::new(params.network_config.clone().into_basic()?, connection_filter)?;
 
@@ -303,6 +318,9 @@ impl EthSync {
 			subprotocol_name: params.config.subprotocol_name,
 			light_subprotocol_name: params.config.light_subprotocol_name,
 			attached_protos: params.attached_protos,
+			fork_block: fork_block,
+			fork_passed: AtomicBool::new(fork_passed),
+			local_chain: local_chain,
 		});
 
 		Ok(sync)
@@ -422,6 +440,14 @@ impl ChainNotify for EthSync {
 				&proposed);
 		});
 
+		// peers found incompatible before the fork may have upgraded since
+		if let Some(fork_block) = self.fork_block {
+			if !self.fork_passed.load(AtomicOrdering::Relaxed) && self.eth_handler.chain.chain_info().best_block_number >= fork_block {
+				self.fork_passed.store(true, AtomicOrdering::Relaxed);
+				self.network.bump_compat_epoch();
+			}
+		}
+
 		self.network.with_context(self.light_subprotocol_name, |context| {
 			let light_proto = match self.light_proto.as_ref() {
 				Some(lp) => lp,
@@ -448,6 +474,7 @@ impl ChainNotify for EthSync {
 			Err(err) => warn!("Error starting network: {}", err),
 			_ => {},
 		}
+		self.network.set_local_chain_info(self.local_chain);
 		self.network.register_protocol(self.eth_handler.clone(), self.subprotocol_name, ETH_PACKET_COUNT, &[62u8, 63u8])
 			.unwrap_or_else(|e| warn!("Error registering ethereum protocol: {:?}", e));
 		// register the warp sync subprotocol
@@ -499,8 +526,8 @@ pub trait ManageNetwork : Send + Sync {
 	fn accept_unreserved_peers(&self);
 	/// Set to deny unreserved peers to connect
 	fn deny_unreserved_peers(&self);
-	/// Remove reservation for the peer
-	fn remove_reserved_peer(&self, peer: String) -> Result<(), String>;
+	/// Remove reservation for the peer, also removing it from the node table if `forget` is set
+	fn remove_reserved_peer(&self, peer: String, forget: bool) -> Result<(), String>;
 	/// Add reserved peer
 	fn add_reserved_peer(&self, peer: String) -> Result<(), String>;
 	/// Start network
@@ -511,6 +538,37 @@ pub trait ManageNetwork : Send + Sync {
 	fn network_config(&self) -> NetworkConfiguration;
 	/// Get network context for protocol.
 	fn with_proto_context(&self, proto: ProtocolId, f: &mut FnMut(&NetworkContext));
+	/// Get at most `limit` node table entries with statistics.
+	fn node_table(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry>;
+	/// Remove a node from the node table and disconnect it. Returns `false` if the node is not known.
+	fn remove_node(&self, node: String) -> Result<bool, String>;
+	/// Ban a node for `duration` and disconnect it. Returns `false` if the node is not known.
+	fn ban_node(&self, node: String, duration: Duration) -> Result<bool, String>;
+	/// Clear the useless and banned flags and the dial backoff of a node. Returns `false` if the node is not known.
+	fn retry_node(&self, node: String) -> Result<bool, String>;
+	/// Returns the statistics of a node and every reason it is not dialled, `None` if the node is neither known nor
+	/// banned, see `NetworkService::node_status`.
+	fn node_status(&self, node: String) -> Result<Option<EntryStatus>, String>;
+	/// Add a node given by an enode URL to the node table, dialing it on the next round when `connect_now` is set.
+	/// Returns the normalized enode URL and whether the node is new.
+	fn add_node(&self, node: String, connect_now: bool) -> Result<(String, bool), String>;
+	/// Add the nodes of a list to the node table. `source` is a file path or the list itself, see
+	/// `NetworkService::import_node_list`.
+	fn import_node_list(&self, source: String, format: ListFormat) -> Result<IngestStats, String>;
+	/// Returns a summary of the node table, discovery and session state, `None` if the network is not running.
+	fn net_health(&self) -> Option<NetHealth>;
+	/// Returns the enode URLs of at most `limit` of the best public nodes, for sharing as boot nodes.
+	fn suggest_bootnodes(&self, limit: usize) -> Vec<String>;
+	/// Returns a newline-separated list of the enode URLs of at most `limit` reliable public nodes, for publishing.
+	fn export_node_list(&self, limit: usize) -> String;
+	/// Run a node table command like `nodetable list 10` and return its plain-text output, see
+	/// `NetworkService::node_table_command`.
+	fn node_table_command(&self, command: String) -> Result<String, String>;
+	/// Read the static peers file again, see `NetworkService::reload_static_peers`.
+	fn reload_static_peers(&self) -> Result<StaticPeersUpdate, String>;
+	/// Save the node table and suppress further saves for `duration`, a zero `duration` ends the freeze. Returns
+	/// `true` if the saved table is up to date, see `NetworkService::freeze_node_table`.
+	fn freeze_node_table(&self, duration: Duration) -> Result<bool, String>;
 }
 
 
@@ -523,8 +581,8 @@ impl ManageNetwork for EthSync {
 		self.network.set_non_reserved_mode(NonReservedPeerMode::Deny);
 	}
 
-	fn remove_reserved_peer(&self, peer: String) -> Result<(), String> {
-		self.network.remove_reserved_peer(&peer).map_err(|e| format!("{:?}", e))
+	fn remove_reserved_peer(&self, peer: String, forget: bool) -> Result<(), String> {
+		self.network.remove_reserved_peer(&peer, forget).map_err(|e| format!("{:?}", e))
 	}
 
 	fn add_reserved_peer(&self, peer: String) -> Result<(), String> {
@@ -555,6 +613,58 @@ impl ManageNetwork for EthSync {
 	fn with_proto_context(&self, proto: ProtocolId, f: &mut FnMut(&NetworkContext)) {
 		self.network.with_context_eval(proto, f);
 	}
+
+	fn node_table(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry> {
+		self.network.node_table_entries(limit, include_useless)
+	}
+
+	fn remove_node(&self, node: String) -> Result<bool, String> {
+		self.network.remove_node(&node).map_err(|e| e.to_string())
+	}
+
+	fn ban_node(&self, node: String, duration: Duration) -> Result<bool, String> {
+		self.network.ban_node(&node, duration).map_err(|e| e.to_string())
+	}
+
+	fn retry_node(&self, node: String) -> Result<bool, String> {
+		self.network.retry_node(&node).map_err(|e| e.to_string())
+	}
+
+	fn node_status(&self, node: String) -> Result<Option<EntryStatus>, String> {
+		self.network.node_status(&node).map_err(|e| e.to_string())
+	}
+
+	fn add_node(&self, node: String, connect_now: bool) -> Result<(String, bool), String> {
+		self.network.add_node(&node, connect_now).map_err(|e| e.to_string())
+	}
+
+	fn import_node_list(&self, source: String, format: ListFormat) -> Result<IngestStats, String> {
+		self.network.import_node_list(&source, format).map_err(|e| e.to_string())
+	}
+
+	fn net_health(&self) -> Option<NetHealth> {
+		self.network.net_health()
+	}
+
+	fn suggest_bootnodes(&self, limit: usize) -> Vec<String> {
+		self.network.best_enodes(limit)
+	}
+
+	fn export_node_list(&self, limit: usize) -> String {
+		self.network.export_node_list(limit)
+	}
+
+	fn node_table_command(&self, command: String) -> Result<String, String> {
+		self.network.node_table_command(&command)
+	}
+
+	fn reload_static_peers(&self) -> Result<StaticPeersUpdate, String> {
+		self.network.reload_static_peers().map_err(|e| e.to_string())
+	}
+
+	fn freeze_node_table(&self, duration: Duration) -> Result<bool, String> {
+		self.network.freeze_node_table(duration)
+	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -594,6 +704,12 @@ pub struct NetworkConfiguration {
 	pub ip_filter: IpFilter,
 	/// Client version string
 	pub client_version: String,
+	/// Minimum score difference for a node table candidate to replace a connected peer. `None` disables eviction.
+	pub eviction_margin: Option<u32>,
+	/// Save the node table to disk.
+	pub persist_nodes: bool,
+	/// Node table tuning.
+	pub node_table: NodeTableConfig,
 }
 
 impl NetworkConfiguration {
@@ -627,6 +743,9 @@ impl NetworkConfiguration {
 			ip_filter: self.ip_filter,
 			non_reserved_mode: if self.allow_non_reserved { NonReservedPeerMode::Accept } else { NonReservedPeerMode::Deny },
 			client_version: self.client_version,
+			eviction_margin: self.eviction_margin,
+			persist_nodes: self.persist_nodes,
+			node_table: self.node_table,
 		})
 	}
 }
@@ -651,6 +770,9 @@ impl From<BasicNetworkConfiguration> for NetworkConfiguration {
 			ip_filter: other.ip_filter,
 			allow_non_reserved: match other.non_reserved_mode { NonReservedPeerMode::Accept => true, _ => false } ,
 			client_version: other.client_version,
+			eviction_margin: other.eviction_margin,
+			persist_nodes: other.persist_nodes,
+			node_table: other.node_table,
 		}
 	}
 }
@@ -793,8 +915,8 @@ impl ManageNetwork for LightSync {
 		self.network.set_non_reserved_mode(NonReservedPeerMode::Deny);
 	}
 
-	fn remove_reserved_peer(&self, peer: String) -> Result<(), String> {
-		self.network.remove_reserved_peer(&peer).map_err(|e| format!("{:?}", e))
+	fn remove_reserved_peer(&self, peer: String, forget: bool) -> Result<(), String> {
+		self.network.remove_reserved_peer(&peer, forget).map_err(|e| format!("{:?}", e))
 	}
 
 	fn add_reserved_peer(&self, peer: String) -> Result<(), String> {
@@ -832,6 +954,58 @@ impl ManageNetwork for LightSync {
 	fn with_proto_context(&self, proto: ProtocolId, f: &mut FnMut(&NetworkContext)) {
 		self.network.with_context_eval(proto, f);
 	}
+
+	fn node_table(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry> {
+		self.network.node_table_entries(limit, include_useless)
+	}
+
+	fn remove_node(&self, node: String) -> Result<bool, String> {
+		self.network.remove_node(&node).map_err(|e| e.to_string())
+	}
+
+	fn ban_node(&self, node: String, duration: Duration) -> Result<bool, String> {
+		self.network.ban_node(&node, duration).map_err(|e| e.to_string())
+	}
+
+	fn retry_node(&self, node: String) -> Result<bool, String> {
+		self.network.retry_node(&node).map_err(|e| e.to_string())
+	}
+
+	fn node_status(&self, node: String) -> Result<Option<EntryStatus>, String> {
+		self.network.node_status(&node).map_err(|e| e.to_string())
+	}
+
+	fn add_node(&self, node: String, connect_now: bool) -> Result<(String, bool), String> {
+		self.network.add_node(&node, connect_now).map_err(|e| e.to_string())
+	}
+
+	fn import_node_list(&self, source: String, format: ListFormat) -> Result<IngestStats, String> {
+		self.network.import_node_list(&source, format).map_err(|e| e.to_string())
+	}
+
+	fn net_health(&self) -> Option<NetHealth> {
+		self.network.net_health()
+	}
+
+	fn suggest_bootnodes(&self, limit: usize) -> Vec<String> {
+		self.network.best_enodes(limit)
+	}
+
+	fn export_node_list(&self, limit: usize) -> String {
+		self.network.export_node_list(limit)
+	}
+
+	fn node_table_command(&self, command: String) -> Result<String, String> {
+		self.network.node_table_command(&command)
+	}
+
+	fn reload_static_peers(&self) -> Result<StaticPeersUpdate, String> {
+		self.network.reload_static_peers().map_err(|e| e.to_string())
+	}
+
+	fn freeze_node_table(&self, duration: Duration) -> Result<bool, String> {
+		self.network.freeze_node_table(duration)
+	}
 }
 
 impl LightSyncProvider for LightSync {
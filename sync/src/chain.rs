@@ -98,7 +98,7 @@ use plain_hasher::H256FastMap;
 use parking_lot::RwLock;
 use bytes::Bytes;
 use rlp::{UntrustedRlp, RlpStream, DecoderError, Encodable};
-use network::{self, PeerId, PacketId};
+use network::{self, PeerId, PacketId, PeerChainInfo};
 use ethcore::header::{BlockNumber, Header as BlockHeader};
 use ethcore::client::{BlockChainClient, BlockStatus, BlockId, BlockChainInfo, BlockImportError, BlockQueueInfo};
 use ethcore::error::*;
@@ -651,6 +651,8 @@ impl ChainSync {
 			debug!(target: "sync", "Unexpected status packet from {}:{}", peer_id, io.peer_info(peer_id));
 			return Ok(());
 		}
+		// recorded before the checks, so that a peer on another chain is dialled last from now on
+		io.set_chain_info(peer_id, PeerChainInfo { network_id: peer.network_id, genesis: peer.genesis, fork_hash: None });
 		let chain_info = io.chain().chain_info();
 		if peer.genesis != chain_info.genesis_hash {
 			io.disable_peer(peer_id);
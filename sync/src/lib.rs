@@ -68,5 +68,8 @@ mod api;
 
 pub use api::*;
 pub use chain::{SyncStatus, SyncState};
-pub use devp2p::{validate_node_url, ConnectionFilter, ConnectionDirection};
-pub use network::{NonReservedPeerMode, Error, ErrorKind};
+pub use devp2p::{validate_node_url, normalize_node_url, parse_node_id, MAX_ENODE_LEN, ConnectionFilter, ConnectionDirection, NodeSource, NodeTableEntry};
+pub use devp2p::{NetHealth, NetHealthVerdict, NodeTableStats, DiscoveryStats, SessionStats, SkippedNode, Eviction, AdvertisedEndpoint};
+pub use devp2p::{DialPlan, DialCandidate, SelectionReason, ListFormat, ListEntryResult, IngestStats, OutcomeBucket, StaticPeersUpdate};
+pub use devp2p::{EntryStatus, DialExclusion};
+pub use network::{NonReservedPeerMode, Error, ErrorKind, NodeTableConfig, PeerChainInfo};
@@ -68,6 +68,8 @@ impl<'a> IoContext for TestIoContext<'a> {
 	fn protocol_version(&self, _peer: PeerId) -> Option<u8> { Some(::light::net::MAX_PROTOCOL_VERSION) }
 
 	fn persistent_peer_id(&self, _peer: PeerId) -> Option<NodeId> { unimplemented!() }
+
+	fn tag_peer(&self, _peer: PeerId, _key: &str, _value: u32) {}
 }
 
 // peer-specific data.
@@ -15,7 +15,7 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
-use network::{NetworkContext, PeerId, PacketId, Error, SessionInfo, ProtocolId};
+use network::{NetworkContext, PeerId, PacketId, Error, SessionInfo, ProtocolId, PeerChainInfo};
 use bytes::Bytes;
 use ethcore::client::BlockChainClient;
 use ethcore::header::BlockNumber;
@@ -58,6 +58,8 @@ pub trait SyncIo {
 	fn is_expired(&self) -> bool;
 	/// Return sync overlay
 	fn chain_overlay(&self) -> &RwLock<HashMap<BlockNumber, Bytes>>;
+	/// Record the chain a peer advertised in the node table
+	fn set_chain_info(&mut self, _peer_id: PeerId, _info: PeerChainInfo) {}
 }
 
 /// Wraps `NetworkContext` and the blockchain client
@@ -135,6 +137,10 @@ impl<'s> SyncIo for NetSyncIo<'s> {
 	fn peer_info(&self, peer_id: PeerId) -> String {
 		self.network.peer_client_version(peer_id)
 	}
+
+	fn set_chain_info(&mut self, peer_id: PeerId, info: PeerChainInfo) {
+		self.network.peer_annotator().set_chain_info(peer_id, info);
+	}
 }
 
 
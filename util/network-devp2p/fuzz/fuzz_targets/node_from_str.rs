@@ -0,0 +1,7 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate ethcore_network_devp2p;
+
+fuzz_target!(|data: &[u8]| {
+	ethcore_network_devp2p::fuzz::fuzz_node_from_str(data);
+});
@@ -0,0 +1,80 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Time source for the node table.
+
+use std::time::{Instant, SystemTime};
+#[cfg(any(test, feature = "test-helpers"))]
+use std::sync::Arc;
+#[cfg(any(test, feature = "test-helpers"))]
+use std::time::Duration;
+#[cfg(any(test, feature = "test-helpers"))]
+use parking_lot::Mutex;
+
+/// Source of monotonic and wall clock time.
+pub trait Clock: Send + Sync {
+	/// Current monotonic time, used for backoffs, bans and other expiring state.
+	fn now_instant(&self) -> Instant;
+	/// Current wall clock time, used for times that are persisted such as the last contact.
+	fn now_system(&self) -> SystemTime;
+}
+
+/// The real clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now_instant(&self) -> Instant {
+		Instant::now()
+	}
+
+	fn now_system(&self) -> SystemTime {
+		SystemTime::now()
+	}
+}
+
+/// A clock that only moves when advanced. Clones share the same time.
+#[cfg(any(test, feature = "test-helpers"))]
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+	now: Arc<Mutex<(Instant, SystemTime)>>,
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl ManualClock {
+	/// Create a clock starting at the current time.
+	pub fn new() -> Self {
+		ManualClock { now: Arc::new(Mutex::new((Instant::now(), SystemTime::now()))) }
+	}
+
+	/// Move the clock forward by `duration`.
+	pub fn advance(&self, duration: Duration) {
+		let mut now = self.now.lock();
+		now.0 += duration;
+		now.1 += duration;
+	}
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl Clock for ManualClock {
+	fn now_instant(&self) -> Instant {
+		self.now.lock().0
+	}
+
+	fn now_system(&self) -> SystemTime {
+		self.now.lock().1
+	}
+}
@@ -25,12 +25,13 @@ use mio::deprecated::{Handler, EventLoop};
 use mio::udp::*;
 use hash::keccak;
 use ethereum_types::{H256, H520};
-use rlp::{UntrustedRlp, RlpStream, encode_list};
+use rlp::{UntrustedRlp, RlpStream, DecoderError, encode_list};
 use node_table::*;
 use network::{Error, ErrorKind};
 use io::{StreamToken, IoContext};
 use ethkey::{Secret, KeyPair, sign, recover};
-use network::IpFilter;
+use network::{AllowIP, IpFilter};
+use ip_utils::SocketAddrExt;
 
 use PROTOCOL_VERSION;
 
@@ -54,6 +55,28 @@ const MAX_NODES_PING: usize = 32; // Max nodes to add/ping at once
 pub struct NodeEntry {
 	pub id: NodeId,
 	pub endpoint: NodeEndpoint,
+	/// Failure bucket of the node in the node table, see `Node::failure_bucket`. This and `last_contact_age` are
+	/// local hints for choosing between nodes, they are never sent to other nodes.
+	pub failure_bucket: Option<usize>,
+	/// Seconds since the last successful contact with the node.
+	pub last_contact_age: Option<u64>,
+}
+
+impl NodeEntry {
+	/// Entry without quality hints, e.g. for a node heard of in a packet.
+	pub fn new(id: NodeId, endpoint: NodeEndpoint) -> NodeEntry {
+		NodeEntry {
+			id: id,
+			endpoint: endpoint,
+			failure_bucket: None,
+			last_contact_age: None,
+		}
+	}
+
+	/// Sort key ranking nodes that worked better for us first, nodes without hints last.
+	fn quality_rank(&self) -> (usize, u64) {
+		(self.failure_bucket.unwrap_or(usize::max_value()), self.last_contact_age.unwrap_or(u64::max_value()))
+	}
 }
 
 pub struct BucketEntry {
@@ -80,6 +103,17 @@ impl NodeBucket {
 	}
 }
 
+/// Summary of the discovery buckets, as returned by `Discovery::stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiscoveryStats {
+	/// Number of nodes in all buckets.
+	pub nodes: usize,
+	/// Number of non-empty buckets.
+	pub buckets: usize,
+	/// Nodes with an unanswered ping.
+	pub pinging: usize,
+}
+
 struct Datagramm {
 	payload: Bytes,
 	address: SocketAddr,
@@ -100,11 +134,15 @@ pub struct Discovery {
 	check_timestamps: bool,
 	adding_nodes: Vec<NodeEntry>,
 	ip_filter: IpFilter,
+	/// Nodes in the node table, nodes it knows at the endpoint we know are not reported again.
+	membership: Option<MembershipHandle>,
 }
 
 pub struct TableUpdates {
 	pub added: HashMap<NodeId, NodeEntry>,
 	pub removed: HashSet<NodeId>,
+	/// Nodes in `added` that answered a ping sent to the reported endpoint.
+	pub verified: HashSet<NodeId>,
 }
 
 impl Discovery {
@@ -125,9 +163,22 @@ impl Discovery {
 			check_timestamps: true,
 			adding_nodes: Vec::new(),
 			ip_filter: ip_filter,
+			membership: None,
 		}
 	}
 
+	/// Check the node table through `membership` so that nodes it already has are not reported again on every
+	/// packet.
+	pub fn set_membership(&mut self, membership: MembershipHandle) {
+		self.membership = Some(membership);
+	}
+
+	/// Advertise `endpoint` in the pings sent from now on, e.g. after the NAT mapping of our external address
+	/// changed.
+	pub fn set_public_endpoint(&mut self, endpoint: NodeEndpoint) {
+		self.public_endpoint = endpoint;
+	}
+
 	/// Add a new node to discovery table. Pings the node.
 	pub fn add_node(&mut self, e: NodeEntry) {
 		if self.is_allowed(&e) {
@@ -143,6 +194,17 @@ impl Discovery {
 		self.update_new_nodes();
 	}
 
+	/// Queue nodes to be pinged after the ones already queued, a few each round. Unlike `add_node_list` the queue is
+	/// kept, nodes already in it are not queued again.
+	pub fn queue_node_list(&mut self, nodes: Vec<NodeEntry>) {
+		let queued: HashSet<NodeId> = self.adding_nodes.iter().map(|n| n.id).collect();
+		// pinged from the back, the new nodes go to the front with the first one last
+		let mut adding: Vec<NodeEntry> = nodes.into_iter().filter(|n| !queued.contains(&n.id)).collect();
+		adding.reverse();
+		adding.extend(self.adding_nodes.drain(..));
+		self.adding_nodes = adding;
+	}
+
 	/// Add a list of known nodes to the table.
 	pub fn init_node_list(&mut self, mut nodes: Vec<NodeEntry>) {
 		for n in nodes.drain(..) {
@@ -152,13 +214,28 @@ impl Discovery {
 		}
 	}
 
+	/// Returns a summary of the bucket contents.
+	pub fn stats(&self) -> DiscoveryStats {
+		let mut stats = DiscoveryStats::default();
+		for bucket in self.node_buckets.iter().filter(|b| !b.nodes.is_empty()) {
+			stats.buckets += 1;
+			stats.nodes += bucket.nodes.len();
+			stats.pinging += bucket.nodes.iter().filter(|n| n.timeout.is_some()).count();
+		}
+		stats
+	}
+
 	fn update_node(&mut self, e: NodeEntry) {
 		trace!(target: "discovery", "Inserting {:?}", &e);
 		let id_hash = keccak(e.id);
 		let ping = {
 			let bucket = &mut self.node_buckets[Discovery::distance(&self.id_hash, &id_hash) as usize];
 			let updated = if let Some(node) = bucket.nodes.iter_mut().find(|n| n.address.id == e.id) {
+				// packets carry no hints, keep the ones from the node table
+				let (failure_bucket, last_contact_age) = (node.address.failure_bucket, node.address.last_contact_age);
 				node.address = e.clone();
+				node.address.failure_bucket = e.failure_bucket.or(failure_bucket);
+				node.address.last_contact_age = e.last_contact_age.or(last_contact_age);
 				node.timeout = None;
 				true
 			} else { false };
@@ -291,11 +368,14 @@ impl Discovery {
 		let mut count = 0;
 		let target_hash = keccak(target);
 
-		// Sort nodes by distance to target
+		// Sort nodes by distance to target, nodes at the same distance by quality
 		for bucket in buckets {
 			for node in &bucket.nodes {
 				let distance = Discovery::distance(&target_hash, &node.id_hash);
-				found.entry(distance).or_insert_with(Vec::new).push(&node.address);
+				let nodes = found.entry(distance).or_insert_with(Vec::new);
+				let rank = node.address.quality_rank();
+				let position = nodes.iter().position(|n| n.quality_rank() > rank).unwrap_or(nodes.len());
+				nodes.insert(position, &node.address);
 				if count == BUCKET_SIZE {
 					// delete the most distant element
 					let remove = {
@@ -406,7 +486,17 @@ impl Discovery {
 	}
 
 	fn is_allowed(&self, entry: &NodeEntry) -> bool {
-		entry.endpoint.is_allowed(&self.ip_filter) && entry.id != self.id
+		entry.endpoint.udp_known() && entry.endpoint.is_allowed(&self.ip_filter) && entry.id != self.id
+	}
+
+	/// Whether the node table has `entry` and it is in the buckets at the same endpoint, so that reporting it again
+	/// would not change the table.
+	fn is_reported(&self, entry: &NodeEntry) -> bool {
+		let in_table = self.membership.as_ref().map_or(false, |m| m.contains(&entry.id));
+		in_table && {
+			let bucket = &self.node_buckets[Discovery::distance(&self.id_hash, &keccak(entry.id)) as usize];
+			bucket.nodes.iter().any(|n| n.address.id == entry.id && n.address.endpoint == entry.endpoint)
+		}
 	}
 
 	fn on_ping(&mut self, rlp: &UntrustedRlp, node: &NodeId, from: &SocketAddr, echo_hash: &[u8]) -> Result<Option<TableUpdates>, Error> {
@@ -416,21 +506,26 @@ impl Discovery {
 		let timestamp: u64 = rlp.val_at(3)?;
 		self.check_timestamp(timestamp)?;
 		let mut added_map = HashMap::new();
-		let entry = NodeEntry { id: node.clone(), endpoint: source.clone() };
+		let entry = NodeEntry::new(node.clone(), source.clone());
 		if !entry.endpoint.is_valid() {
 			debug!(target: "discovery", "Got bad address: {:?}", entry);
 		} else if !self.is_allowed(&entry) {
 			debug!(target: "discovery", "Address not allowed: {:?}", entry);
 		} else {
-			self.update_node(entry.clone());
-			added_map.insert(node.clone(), entry);
+			if !self.is_reported(&entry) {
+				added_map.insert(node.clone(), entry.clone());
+			}
+			self.update_node(entry);
 		}
 		let mut response = RlpStream::new_list(2);
 		dest.to_rlp_list(&mut response);
 		response.append(&echo_hash);
 		self.send_packet(PACKET_PONG, from, &response.drain())?;
 
-		Ok(Some(TableUpdates { added: added_map, removed: HashSet::new() }))
+		if added_map.is_empty() {
+			return Ok(None);
+		}
+		Ok(Some(TableUpdates { added: added_map, removed: HashSet::new(), verified: HashSet::new() }))
 	}
 
 	fn on_pong(&mut self, rlp: &UntrustedRlp, node: &NodeId, from: &SocketAddr) -> Result<Option<TableUpdates>, Error> {
@@ -439,13 +534,22 @@ impl Discovery {
 		let dest = NodeEndpoint::from_rlp(&rlp.at(0)?)?;
 		let timestamp: u64 = rlp.val_at(2)?;
 		self.check_timestamp(timestamp)?;
-		let mut entry = NodeEntry { id: node.clone(), endpoint: dest };
+		let mut entry = NodeEntry::new(node.clone(), dest);
 		if !entry.endpoint.is_valid() {
 			debug!(target: "discovery", "Bad address: {:?}", entry);
 			entry.endpoint.address = from.clone();
 		}
 		self.clear_ping(node);
-		Ok(None)
+		// the pong came from the address we have for the node, which confirms its endpoint
+		let pinged = {
+			let bucket = &self.node_buckets[Discovery::distance(&self.id_hash, &keccak(node)) as usize];
+			bucket.nodes.iter().find(|n| &n.address.id == node && n.address.endpoint.udp_address() == *from).map(|n| n.address.clone())
+		};
+		Ok(pinged.map(|entry| TableUpdates {
+			added: vec![(node.clone(), entry)].into_iter().collect(),
+			removed: HashSet::new(),
+			verified: vec![node.clone()].into_iter().collect(),
+		}))
 	}
 
 	fn on_find_node(&mut self, rlp: &UntrustedRlp, _node: &NodeId, from: &SocketAddr) -> Result<Option<TableUpdates>, Error> {
@@ -453,7 +557,7 @@ impl Discovery {
 		let target: NodeId = rlp.val_at(0)?;
 		let timestamp: u64 = rlp.val_at(1)?;
 		self.check_timestamp(timestamp)?;
-		let nearest = Discovery::nearest_node_entries(&target, &self.node_buckets);
+		let nearest = self.neighbours_for(&target, from);
 		if nearest.is_empty() {
 			return Ok(None);
 		}
@@ -465,6 +569,17 @@ impl Discovery {
 		Ok(None)
 	}
 
+	/// Nodes to answer a FindNode from `requester` with. Public requesters only learn of public addresses, other
+	/// addresses are not reachable for them and would reveal the local network.
+	fn neighbours_for(&self, target: &NodeId, requester: &SocketAddr) -> Vec<NodeEntry> {
+		let mut nearest = Discovery::nearest_node_entries(target, &self.node_buckets);
+		if requester.ip().is_usable_public() {
+			let public = IpFilter { predefined: AllowIP::Public, custom_allow: Vec::new(), custom_block: Vec::new() };
+			nearest.retain(|n| n.endpoint.is_allowed(&public));
+		}
+		nearest
+	}
+
 	fn prepare_neighbours_packets(nearest: &[NodeEntry]) -> Vec<Bytes> {
 		let limit = (MAX_DATAGRAM_SIZE - 109) / 90;
 		let chunks = nearest.chunks(limit);
@@ -486,7 +601,14 @@ impl Discovery {
 		let mut added = HashMap::new();
 		trace!(target: "discovery", "Got {} Neighbours from {:?}", rlp.at(0)?.item_count()?, &from);
 		for r in rlp.at(0)?.iter() {
-			let endpoint = NodeEndpoint::from_rlp(&r)?;
+			let endpoint = match NodeEndpoint::from_rlp_strict(&r) {
+				Ok(endpoint) => endpoint,
+				Err(DecoderError::Custom(reason)) => {
+					debug!(target: "discovery", "Bad neighbour from {:?}: {}", &from, reason);
+					continue;
+				},
+				Err(e) => return Err(e.into()),
+			};
 			if !endpoint.is_valid() {
 				debug!(target: "discovery", "Bad address: {:?}", endpoint);
 				continue;
@@ -495,16 +617,21 @@ impl Discovery {
 			if node_id == self.id {
 				continue;
 			}
-			let entry = NodeEntry { id: node_id.clone(), endpoint: endpoint };
+			let entry = NodeEntry::new(node_id.clone(), endpoint);
 			if !self.is_allowed(&entry) {
 				debug!(target: "discovery", "Address not allowed: {:?}", entry);
 				continue;
 			}
-			added.insert(node_id, entry.clone());
+			if !self.is_reported(&entry) {
+				added.insert(node_id, entry.clone());
+			}
 			self.ping(&entry.endpoint);
 			self.update_node(entry);
 		}
-		Ok(Some(TableUpdates { added: added, removed: HashSet::new() }))
+		if added.is_empty() {
+			return Ok(None);
+		}
+		Ok(Some(TableUpdates { added: added, removed: HashSet::new(), verified: HashSet::new() }))
 	}
 
 	fn check_expired(&mut self, force: bool) -> HashSet<NodeId> {
@@ -531,7 +658,7 @@ impl Discovery {
 		let removed = self.check_expired(false);
 		self.discover();
 		if !removed.is_empty() {
-			Some(TableUpdates { added: HashMap::new(), removed: removed })
+			Some(TableUpdates { added: HashMap::new(), removed: removed, verified: HashSet::new() })
 		} else { None }
 	}
 
@@ -570,7 +697,7 @@ mod tests {
 		let mut nearest = Vec::new();
 		let node = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@127.0.0.1:7770").unwrap();
 		for _ in 0..1000 {
-			nearest.push( NodeEntry { id: node.id.clone(), endpoint: node.endpoint.clone() });
+			nearest.push(NodeEntry::from(&node));
 		}
 
 		let packets = Discovery::prepare_neighbours_packets(&nearest);
@@ -582,6 +709,31 @@ mod tests {
 		assert!(packets.last().unwrap().len() > 0);
 	}
 
+	#[test]
+	fn neighbours_for_public_requester() {
+		let key = Random.generate().unwrap();
+		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40449").unwrap(), udp_port: 40449 };
+		let mut discovery = Discovery::new(&key, ep.address.clone(), ep.clone(), 0, IpFilter::default());
+		let mut table = NodeTable::new(None);
+		for (i, address) in ["22.99.55.44:30303", "10.0.0.1:30303", "22.99.55.45:30303", "192.168.1.1:30303", "127.0.0.1:30303"].iter().enumerate() {
+			table.add_node(Node::new(NodeId::from(i as u64 + 1), NodeEndpoint::from_str(address).unwrap()));
+		}
+		discovery.init_node_list(table.entries_filtered(&IpFilter::default(), usize::max_value()));
+
+		let public = SocketAddr::from_str("22.99.56.1:30303").unwrap();
+		let mut ids: Vec<NodeId> = discovery.neighbours_for(&NodeId::new(), &public).into_iter().map(|n| n.id).collect();
+		ids.sort();
+		assert_eq!(ids, vec![NodeId::from(1), NodeId::from(3)]);
+		assert_eq!(discovery.neighbours_for(&NodeId::new(), &SocketAddr::from_str("10.0.0.2:30303").unwrap()).len(), 5);
+
+		// seeding with a public only filter leaves the private nodes out to begin with
+		let public_only = IpFilter { predefined: AllowIP::Public, custom_allow: Vec::new(), custom_block: Vec::new() };
+		let entries = table.entries_filtered(&public_only, usize::max_value());
+		assert!(entries.iter().all(|e| e.endpoint.address.ip().is_usable_public()));
+		assert_eq!(entries.len(), 2);
+		assert_eq!(table.entries_filtered(&IpFilter::default(), 3).len(), 3);
+	}
+
 	#[test]
 	fn discovery() {
 		let key1 = Random.generate().unwrap();
@@ -593,10 +745,10 @@ mod tests {
 
 		let node1 = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@127.0.0.1:7770").unwrap();
 		let node2 = Node::from_str("enode://b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@127.0.0.1:7771").unwrap();
-		discovery1.add_node(NodeEntry { id: node1.id.clone(), endpoint: node1.endpoint.clone() });
-		discovery1.add_node(NodeEntry { id: node2.id.clone(), endpoint: node2.endpoint.clone() });
+		discovery1.add_node(NodeEntry::from(&node1));
+		discovery1.add_node(NodeEntry::from(&node2));
 
-		discovery2.add_node(NodeEntry { id: key1.public().clone(), endpoint: ep1.clone() });
+		discovery2.add_node(NodeEntry::new(key1.public().clone(), ep1.clone()));
 		discovery2.refresh();
 
 		for _ in 0 .. 10 {
@@ -617,19 +769,57 @@ mod tests {
 		assert_eq!(Discovery::nearest_node_entries(&NodeId::new(), &discovery2.node_buckets).len(), 3)
 	}
 
+	#[test]
+	fn queued_nodes_keep_the_queue() {
+		let key = Random.generate().unwrap();
+		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40451").unwrap(), udp_port: 40451 };
+		let mut discovery = Discovery::new(&key, ep.address.clone(), ep.clone(), 0, IpFilter::default());
+		let entry = |i: u64| {
+			NodeEntry::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i)).unwrap())
+		};
+		let queued = |discovery: &Discovery| -> Vec<u64> {
+			discovery.adding_nodes.iter().rev().map(|n| n.id.low_u64()).collect()
+		};
+
+		// the first nodes are pinged right away, the rest is queued
+		discovery.add_node_list((1..MAX_NODES_PING as u64 + 3).map(&entry).collect());
+		assert_eq!(queued(&discovery), vec![2, 1]);
+
+		// queued nodes are pinged after them, the ones already queued are not queued twice
+		discovery.queue_node_list(vec![entry(100), entry(1), entry(101)]);
+		assert_eq!(queued(&discovery), vec![2, 1, 100, 101]);
+		discovery.add_node_list(vec![entry(102)]);
+		assert!(discovery.adding_nodes.is_empty());
+	}
+
 	#[test]
 	fn removes_expired() {
 		let key = Random.generate().unwrap();
 		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40446").unwrap(), udp_port: 40447 };
 		let mut discovery = Discovery::new(&key, ep.address.clone(), ep.clone(), 0, IpFilter::default());
 		for _ in 0..1200 {
-			discovery.add_node(NodeEntry { id: NodeId::random(), endpoint: ep.clone() });
+			discovery.add_node(NodeEntry::new(NodeId::random(), ep.clone()));
 		}
 		assert!(Discovery::nearest_node_entries(&NodeId::new(), &discovery.node_buckets).len() <= 16);
 		let removed = discovery.check_expired(true).len();
 		assert!(removed > 0);
 	}
 
+	#[test]
+	fn bucket_stats() {
+		let key = Random.generate().unwrap();
+		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40448").unwrap(), udp_port: 40448 };
+		let mut discovery = Discovery::new(&key, ep.address.clone(), ep.clone(), 0, IpFilter::default());
+		assert_eq!(discovery.stats(), DiscoveryStats::default());
+		for _ in 0..1200 {
+			discovery.add_node(NodeEntry::new(NodeId::random(), ep.clone()));
+		}
+		let stats = discovery.stats();
+		assert!(stats.buckets > 1);
+		assert!(stats.nodes > BUCKET_SIZE && stats.nodes <= 1200);
+		assert!(stats.pinging > 0);
+	}
+
 	#[test]
 	fn find_nearest_saturated() {
 		use super::*;
@@ -637,7 +827,7 @@ mod tests {
 		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40447").unwrap(), udp_port: 40447 };
 		for _ in 0..(16 + 10) {
 			buckets[0].nodes.push_back(BucketEntry {
-				address: NodeEntry { id: NodeId::new(), endpoint: ep.clone() },
+				address: NodeEntry::new(NodeId::new(), ep.clone()),
 				timeout: None,
 				id_hash: keccak(NodeId::new()),
 			});
@@ -646,6 +836,44 @@ mod tests {
 		assert_eq!(nearest.len(), 16)
 	}
 
+	#[test]
+	fn find_nearest_prefers_quality() {
+		let mut buckets: Vec<_> = (0..256).map(|_| NodeBucket::new()).collect();
+		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40447").unwrap(), udp_port: 40447 };
+		let entry = |i: u64, failure_bucket: Option<usize>, last_contact_age: Option<u64>| BucketEntry {
+			address: NodeEntry { failure_bucket: failure_bucket, last_contact_age: last_contact_age, ..NodeEntry::new(NodeId::from(i), ep.clone()) },
+			timeout: None,
+			id_hash: keccak(NodeId::new()),
+		};
+		// all at the same distance: good nodes are preferred, nodes without hints come last
+		for i in 0..20 {
+			buckets[0].nodes.push_back(entry(i, None, None));
+		}
+		buckets[0].nodes.push_back(entry(20, Some(50), None));
+		buckets[0].nodes.push_back(entry(21, Some(0), Some(3600)));
+		buckets[0].nodes.push_back(entry(22, Some(0), Some(60)));
+		let nearest: Vec<_> = Discovery::nearest_node_entries(&NodeId::new(), &buckets).into_iter().map(|e| e.id).collect();
+		assert_eq!(nearest.len(), BUCKET_SIZE);
+		assert_eq!(&nearest[..4], &[NodeId::from(22), NodeId::from(21), NodeId::from(20), NodeId::from(0)]);
+
+		// closer nodes still win over better ones
+		buckets[1].nodes.push_back(BucketEntry { id_hash: keccak(NodeId::from(23)), ..entry(23, None, None) });
+		let nearest = Discovery::nearest_node_entries(&NodeId::from(23), &buckets);
+		assert_eq!(nearest[0].id, NodeId::from(23));
+	}
+
+	#[test]
+	fn update_keeps_quality_hints() {
+		let key = Random.generate().unwrap();
+		let ep = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40446").unwrap(), udp_port: 40446 };
+		let mut discovery = Discovery::new(&key, ep.address.clone(), ep.clone(), 0, IpFilter::default());
+		let id = NodeId::random();
+		discovery.init_node_list(vec![NodeEntry { failure_bucket: Some(5), last_contact_age: Some(10), ..NodeEntry::new(id, ep.clone()) }]);
+		discovery.update_node(NodeEntry::new(id, ep.clone()));
+		let entry = Discovery::nearest_node_entries(&id, &discovery.node_buckets).remove(0);
+		assert_eq!((entry.failure_bucket, entry.last_contact_age), (Some(5), Some(10)));
+	}
+
 	#[test]
 	fn packets() {
 		let key = Random.generate().unwrap();
@@ -728,4 +956,49 @@ mod tests {
 		let rlp = UntrustedRlp::new(&data[1..]);
 		assert_eq!(ping_data.payload[0..32], rlp.val_at::<Vec<u8>>(1).unwrap()[..])
 	}
+
+	#[test]
+	fn ping_advertises_new_public_endpoint() {
+		let key = Random.generate().unwrap();
+		let ep1 = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40348").unwrap(), udp_port: 40348 };
+		let ep2 = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40349").unwrap(), udp_port: 40349 };
+		let mut discovery = Discovery::new(&key, ep1.address.clone(), ep1.clone(), 0, IpFilter::default());
+		fn advertised(discovery: &mut Discovery, to: &NodeEndpoint) -> NodeEndpoint {
+			discovery.ping(to);
+			let ping_data = discovery.send_queue.pop_front().unwrap();
+			NodeEndpoint::from_rlp(&UntrustedRlp::new(&ping_data.payload[(32 + 65 + 1)..]).at(1).unwrap()).unwrap()
+		}
+		assert_eq!(advertised(&mut discovery, &ep2), ep1);
+
+		let external = NodeEndpoint { address: SocketAddr::from_str("22.99.55.50:30310").unwrap(), udp_port: 30311 };
+		discovery.set_public_endpoint(external.clone());
+		assert_eq!(advertised(&mut discovery, &ep2), external);
+	}
+
+	#[test]
+	fn known_nodes_not_reported_again() {
+		let key1 = Random.generate().unwrap();
+		let key2 = Random.generate().unwrap();
+		let ep1 = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40346").unwrap(), udp_port: 40346 };
+		let ep2 = NodeEndpoint { address: SocketAddr::from_str("127.0.0.1:40347").unwrap(), udp_port: 40347 };
+		let mut discovery1 = Discovery::new(&key1, ep1.address.clone(), ep1.clone(), 0, IpFilter::default());
+		let mut discovery2 = Discovery::new(&key2, ep2.address.clone(), ep2.clone(), 0, IpFilter::default());
+		let mut table = NodeTable::new(None);
+		table.add_node(Node::new(key1.public().clone(), ep1.clone()));
+		table.refresh_membership();
+		discovery2.set_membership(table.membership());
+
+		// reported until it is in the buckets at the endpoint the table has
+		let mut ping = || {
+			discovery1.ping(&ep2);
+			let ping_data = discovery1.send_queue.pop_front().unwrap();
+			discovery2.on_packet(&ping_data.payload, ep1.address.clone()).unwrap()
+		};
+		assert!(ping().is_some());
+		assert!(ping().is_none());
+
+		table.remove_node(key1.public());
+		table.refresh_membership();
+		assert!(ping().is_some());
+	}
 }
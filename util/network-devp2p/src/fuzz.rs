@@ -0,0 +1,130 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzing entry points for parsing untrusted input, used by the targets in `fuzz/`.
+//!
+//! Each function accepts arbitrary bytes, must not panic, and asserts that anything that parses
+//! is parsed back to the same value after serializing it again.
+
+use std::str;
+use rlp::{UntrustedRlp, RlpStream};
+use node_table::{Node, NodeEndpoint};
+use persistence::{PersistedNode, decode_table, encode_table};
+
+/// Parse an enode URL. Host names are not resolved.
+pub fn fuzz_node_from_str(data: &[u8]) {
+	let s = match str::from_utf8(data) {
+		Ok(s) => s,
+		Err(_) => return,
+	};
+	if let Ok(node) = Node::parse(s, false) {
+		let url = node.to_string();
+		let parsed = Node::parse(&url, false).expect("a displayed node must parse");
+		assert_eq!(parsed.id, node.id);
+		assert_eq!(parsed.endpoint, node.endpoint);
+		assert_eq!(parsed.to_string(), url);
+	}
+}
+
+/// Decode an endpoint as sent in discovery packets.
+pub fn fuzz_endpoint_from_rlp(data: &[u8]) {
+	if let Ok(endpoint) = NodeEndpoint::from_rlp(&UntrustedRlp::new(data)) {
+		let mut rlp = RlpStream::new();
+		endpoint.to_rlp_list(&mut rlp);
+		let encoded = rlp.out();
+		let decoded = NodeEndpoint::from_rlp(&UntrustedRlp::new(&encoded)).expect("an encoded endpoint must decode");
+		assert_eq!(decoded, endpoint);
+	}
+}
+
+/// Load a `nodes.json` file.
+pub fn fuzz_nodes_json(data: &[u8]) {
+	let table = match decode_table(data) {
+		Ok(table) => table,
+		Err(_) => return,
+	};
+	let encoded = encode_table(&table).expect("a decoded table must encode");
+	assert_eq!(decode_table(&encoded).expect("an encoded table must decode"), table);
+
+	// Entries are normalized when loaded, a saved entry must load unchanged.
+	for node in table.nodes.into_iter().filter_map(|n| n.into_node().ok()) {
+		let saved = PersistedNode::from(&node);
+		let loaded = saved.clone().into_node().expect("a saved node must load");
+		assert_eq!(PersistedNode::from(&loaded), saved);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::io::Read;
+	use std::path::Path;
+	use super::*;
+
+	fn run_corpus(name: &str, target: fn(&[u8])) {
+		let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("corpus").join(name);
+		let mut count = 0;
+		for entry in fs::read_dir(&dir).unwrap() {
+			let mut data = Vec::new();
+			fs::File::open(entry.unwrap().path()).unwrap().read_to_end(&mut data).unwrap();
+			target(&data);
+			count += 1;
+		}
+		assert!(count > 0, "empty corpus {}", name);
+	}
+
+	#[test]
+	fn node_from_str_corpus() {
+		run_corpus("node_from_str", fuzz_node_from_str);
+	}
+
+	#[test]
+	fn endpoint_from_rlp_corpus() {
+		run_corpus("endpoint_from_rlp", fuzz_endpoint_from_rlp);
+	}
+
+	#[test]
+	fn nodes_json_corpus() {
+		run_corpus("nodes_json", fuzz_nodes_json);
+	}
+
+	#[test]
+	fn node_from_str_multibyte() {
+		let id = "a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c";
+		fuzz_node_from_str("enode:/é".as_bytes());
+		fuzz_node_from_str(format!("enode://{}é22.99.55.44:7770", &id[..127]).as_bytes());
+		fuzz_node_from_str(format!("enode://{}@22.99.55.44:7770?discport=é", id).as_bytes());
+		assert!(Node::parse(&format!("enode://{}é22.99.55.44:7770", &id[..127]), false).is_err());
+	}
+
+	#[test]
+	fn node_display_discport() {
+		let url = "enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770?discport=30301";
+		let node = Node::parse(url, false).unwrap();
+		assert_eq!(node.endpoint.udp_port, 30301);
+		assert_eq!(node.to_string(), url);
+		fuzz_node_from_str(url.as_bytes());
+	}
+
+	#[test]
+	fn nodes_json_extremes() {
+		let url = "enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@[::1]:7770?discport=7771";
+		let json = format!(r#"{{"nodes":[{{"url":"{}","attempts":1,"failures":4294967295,"last_contact":18446744073709551615,"required_for":["ét","eth"]}}]}}"#, url);
+		fuzz_nodes_json(json.as_bytes());
+		let node = decode_table(json.as_bytes()).unwrap().nodes.remove(0).into_node().unwrap();
+		assert_eq!(node.failure_percentage(), 100);
+	}
+}
@@ -14,8 +14,8 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr};
-use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr, IpAddr};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
@@ -24,6 +24,7 @@ use std::cmp::{min, max};
 use std::path::{Path, PathBuf};
 use std::io::{Read, Write, self};
 use std::fs;
+use std::time::{Duration, Instant};
 use ethkey::{KeyPair, Secret, Random, Generator};
 use hash::keccak;
 use mio::*;
@@ -32,19 +33,24 @@ use mio::tcp::*;
 use ethereum_types::H256;
 use rlp::{RlpStream, Encodable};
 
-use session::{Session, SessionData};
+use session::{Session, SessionData, MIN_COMPRESSION_PROTOCOL_VERSION};
 use io::*;
 use PROTOCOL_VERSION;
 use node_table::*;
-use network::{NetworkConfiguration, NetworkIoMessage, ProtocolId, PeerId, PacketId};
+use shared_table::SharedNodeTable;
+use network::{NetworkConfiguration, NetworkIoMessage, ProtocolId, PeerId, PacketId, IpFilter};
 use network::{NonReservedPeerMode, NetworkContext as NetworkContextTrait};
 use network::HostInfo as HostInfoTrait;
 use network::{SessionInfo, Error, ErrorKind, DisconnectReason, NetworkProtocolHandler};
-use discovery::{Discovery, TableUpdates, NodeEntry};
+use network::{PeerAnnotator, PeerChainInfo, Severity};
+use discovery::{Discovery, DiscoveryStats, TableUpdates, NodeEntry};
 use ip_utils::{map_external_address, select_public_address};
 use path::restrict_permissions_owner;
 use parking_lot::{Mutex, RwLock};
 use connection_filter::{ConnectionFilter, ConnectionDirection};
+use persistence::{NodeTablePersistence, FilePersistence};
+use telemetry::{TelemetrySchedule, TelemetryCallback};
+use table_commands::TableCommand;
 
 type Slab<T> = ::slab::Slab<T, usize>;
 
@@ -72,8 +78,17 @@ const MAINTENANCE_TIMEOUT: u64 = 1000;
 const DISCOVERY_REFRESH_TIMEOUT: u64 = 60_000;
 // for DISCOVERY_ROUND TimerToken
 const DISCOVERY_ROUND_TIMEOUT: u64 = 300;
-// for NODE_TABLE TimerToken
-const NODE_TABLE_TIMEOUT: u64 = 300_000;
+
+// Peers lost within this long are dialled first when less than half of the minimum peers are connected.
+const RECENTLY_LOST_AGE: Duration = Duration::from_secs(10 * 60);
+// Candidates kept in the dial plan of a connection round unless more are dialled.
+const MAX_DIAL_PLAN: usize = 32;
+// Inbound sessions are recorded in the node table once they stay connected for this long.
+const INBOUND_RECORD_DELAY: Duration = Duration::from_secs(30);
+// Connected peers are evicted in favour of better node table candidates at most this often.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(300);
+// Sessions are not evicted before they have been connected for this long.
+const MIN_EVICTION_SESSION_AGE: Duration = Duration::from_secs(120);
 
 #[derive(Debug, PartialEq, Eq)]
 /// Protocol info
@@ -100,6 +115,7 @@ pub struct NetworkContext<'s> {
 	session: Option<SharedSession>,
 	session_id: Option<StreamToken>,
 	_reserved_peers: &'s HashSet<NodeId>,
+	nodes: &'s SharedNodeTable,
 }
 
 impl<'s> NetworkContext<'s> {
@@ -107,7 +123,7 @@ impl<'s> NetworkContext<'s> {
 	fn new(io: &'s IoContext<NetworkIoMessage>,
 		protocol: ProtocolId,
 		session: Option<SharedSession>, sessions: Arc<RwLock<Slab<SharedSession>>>,
-		reserved_peers: &'s HashSet<NodeId>) -> NetworkContext<'s> {
+		reserved_peers: &'s HashSet<NodeId>, nodes: &'s SharedNodeTable) -> NetworkContext<'s> {
 		let id = session.as_ref().map(|s| s.lock().token());
 		NetworkContext {
 			io: io,
@@ -116,6 +132,7 @@ impl<'s> NetworkContext<'s> {
 			session: session,
 			sessions: sessions,
 			_reserved_peers: reserved_peers,
+			nodes: nodes,
 		}
 	}
 
@@ -188,6 +205,84 @@ impl<'s> NetworkContextTrait for NetworkContext<'s> {
 	}
 
 	fn subprotocol_name(&self) -> ProtocolId { self.protocol }
+
+	fn peer_annotator(&self) -> &PeerAnnotator { self }
+}
+
+/// Resolves local peer ids to node ids.
+trait ResolveNodeId {
+	fn node_id(&self, peer: PeerId) -> Option<NodeId>;
+}
+
+impl<'s> ResolveNodeId for NetworkContext<'s> {
+	fn node_id(&self, peer: PeerId) -> Option<NodeId> {
+		self.resolve_session(peer).and_then(|s| s.lock().id().cloned())
+	}
+}
+
+impl<'s> PeerAnnotator for NetworkContext<'s> {
+	fn report_misbehaviour(&self, peer: PeerId, severity: Severity) {
+		NodeAnnotator { resolver: self, nodes: self.nodes }.report_misbehaviour(peer, severity)
+	}
+
+	fn report_useful(&self, peer: PeerId) {
+		NodeAnnotator { resolver: self, nodes: self.nodes }.report_useful(peer)
+	}
+
+	fn report_latency(&self, peer: PeerId, latency: Duration) {
+		NodeAnnotator { resolver: self, nodes: self.nodes }.report_latency(peer, latency)
+	}
+
+	fn tag(&self, peer: PeerId, key: &str, value: u32) {
+		NodeAnnotator { resolver: self, nodes: self.nodes }.tag(peer, key, value)
+	}
+
+	fn set_chain_info(&self, peer: PeerId, info: PeerChainInfo) {
+		NodeAnnotator { resolver: self, nodes: self.nodes }.set_chain_info(peer, info)
+	}
+}
+
+/// Forwards peer quality reports to the node table.
+struct NodeAnnotator<'a, R: 'a> {
+	resolver: &'a R,
+	nodes: &'a SharedNodeTable,
+}
+
+impl<'a, R> PeerAnnotator for NodeAnnotator<'a, R> where R: ResolveNodeId {
+	fn report_misbehaviour(&self, peer: PeerId, severity: Severity) {
+		if let Some(id) = self.resolver.node_id(peer) {
+			debug!(target: "network", "Peer {} ({:?}) misbehaved: {:?}", peer, id, severity);
+			self.nodes.write().note_misbehaviour(&id, severity);
+		}
+	}
+
+	fn report_useful(&self, peer: PeerId) {
+		if let Some(id) = self.resolver.node_id(peer) {
+			self.nodes.try_note_success(&id);
+		}
+	}
+
+	fn report_latency(&self, peer: PeerId, latency: Duration) {
+		if let Some(id) = self.resolver.node_id(peer) {
+			self.nodes.try_note_latency(&id, latency);
+		}
+	}
+
+	fn tag(&self, peer: PeerId, key: &str, value: u32) {
+		if let Some(id) = self.resolver.node_id(peer) {
+			if !self.nodes.write().set_tag(&id, key, value) {
+				trace!(target: "network", "Tag {} of peer {} not recorded", key, peer);
+			}
+		}
+	}
+
+	fn set_chain_info(&self, peer: PeerId, info: PeerChainInfo) {
+		if let Some(id) = self.resolver.node_id(peer) {
+			if !self.nodes.write().set_chain_info(&id, info) {
+				trace!(target: "network", "Chain of peer {} not recorded", peer);
+			}
+		}
+	}
 }
 
 /// Shared host information
@@ -206,6 +301,10 @@ pub struct HostInfo {
 	pub local_endpoint: NodeEndpoint,
 	/// Public address + discovery port
 	pub public_endpoint: Option<NodeEndpoint>,
+	/// When the public endpoint was last set.
+	public_endpoint_since: Option<Instant>,
+	/// Changes of the public endpoint after it was first set.
+	public_endpoint_changes: usize,
 }
 
 impl HostInfoTrait for HostInfo {
@@ -229,6 +328,124 @@ impl HostInfoTrait for HostInfo {
 
 type SharedSession = Arc<Mutex<Session>>;
 
+/// Session counts by direction and protocol.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStats {
+	/// Sessions still in handshake.
+	pub handshakes: usize,
+	/// Ready sessions originated by the remote node.
+	pub ingress: usize,
+	/// Ready sessions originated by us.
+	pub egress: usize,
+	/// Ready sessions by protocol name.
+	pub protocols: BTreeMap<String, usize>,
+}
+
+/// Coarse assessment of the peering state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetHealthVerdict {
+	/// Connected to at least half of the configured minimum number of peers.
+	Good,
+	/// Too few peers although there are nodes to try.
+	FewPeers,
+	/// Too few peers and discovery is disabled or has not found any nodes.
+	NoDiscovery,
+	/// Too few peers and the IP filter rejects every known node.
+	AllFiltered,
+	/// Too few peers and dials to many subnets failed in a row, our own connectivity is likely down.
+	Outage,
+}
+
+/// Network health summary, as returned by `Host::net_health`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetHealth {
+	/// Overall verdict.
+	pub verdict: NetHealthVerdict,
+	/// Node table statistics.
+	pub node_table: NodeTableStats,
+	/// Discovery bucket statistics, `None` if discovery is disabled.
+	pub discovery: Option<DiscoveryStats>,
+	/// Current sessions.
+	pub sessions: SessionStats,
+	/// Active IP filter in the form accepted by `--allow-ips`.
+	pub ip_filter: String,
+	/// Node table metrics in the Prometheus text format, `None` unless built with the `metrics` feature.
+	pub metrics: Option<String>,
+	/// Saved node table entries skipped when the table was loaded.
+	pub skipped_nodes: Vec<SkippedNode>,
+	/// Latest nodes dropped from the node table, oldest first.
+	pub recent_evictions: Vec<Eviction>,
+	/// Endpoint advertised to other nodes, `None` until the network is started.
+	pub advertised_endpoint: Option<AdvertisedEndpoint>,
+	/// Dial candidates of the last connection round, `None` before the first one.
+	pub dial_plan: Option<DialPlan>,
+	/// Endpoints of other instances on this host whose nodes are excluded, see `NodeTableConfig::sibling_endpoints`.
+	pub sibling_endpoints: Vec<SocketAddr>,
+}
+
+/// Public endpoint advertised to other nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdvertisedEndpoint {
+	/// Public address and discovery port.
+	pub endpoint: NodeEndpoint,
+	/// Time since it is advertised.
+	pub age: Duration,
+	/// Changes since the start, e.g. after the NAT mapping changed.
+	pub changes: usize,
+}
+
+/// Dial candidates of the last connection round, in the order they were considered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialPlan {
+	/// Time since the round.
+	pub age: Duration,
+	/// Candidates not connected or connecting at the time.
+	pub candidates: Vec<DialCandidate>,
+}
+
+/// Node considered for dialling, see `DialPlan`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialCandidate {
+	pub id: NodeId,
+	/// Endpoint in the node table, `None` for a reserved node not in the table.
+	pub endpoint: Option<NodeEndpoint>,
+	/// Score in the node table, see `Node::score`.
+	pub score: Option<usize>,
+	pub reason: SelectionReason,
+	/// Whether a dial was started, the rest did not fit the handshake limit.
+	pub dialled: bool,
+}
+
+/// Recent dial failures since the last successful dial, to tell an outage of our own connectivity from failing
+/// nodes.
+#[derive(Default)]
+struct DialFailures {
+	failures: Vec<(Instant, IpAddr)>,
+}
+
+impl DialFailures {
+	/// Record a failed dial to a node in `subnet`. Returns `true` if dials to `subnets` distinct subnets failed within
+	/// `window`, the failures are forgotten then. Zero `subnets` never reports an outage.
+	fn note_failure(&mut self, now: Instant, subnet: IpAddr, subnets: usize, window: Duration) -> bool {
+		if subnets == 0 {
+			return false;
+		}
+		self.failures.retain(|&(time, _)| now.duration_since(time) < window);
+		self.failures.push((now, subnet));
+		let distinct: HashSet<_> = self.failures.iter().map(|&(_, subnet)| subnet).collect();
+		if distinct.len() < subnets {
+			return false;
+		}
+		self.failures.clear();
+		true
+	}
+
+	/// Record a successful dial, failures before it do not count towards an outage.
+	fn note_success(&mut self) {
+		self.failures.clear();
+	}
+}
+
 #[derive(Copy, Clone)]
 struct ProtocolTimer {
 	pub protocol: ProtocolId,
@@ -241,18 +458,26 @@ pub struct Host {
 	tcp_listener: Mutex<TcpListener>,
 	sessions: Arc<RwLock<Slab<SharedSession>>>,
 	discovery: Mutex<Option<Discovery>>,
-	nodes: RwLock<NodeTable>,
+	nodes: SharedNodeTable,
 	handlers: RwLock<HashMap<ProtocolId, Arc<NetworkProtocolHandler + Sync>>>,
 	timers: RwLock<HashMap<TimerToken, ProtocolTimer>>,
 	timer_counter: RwLock<usize>,
 	reserved_nodes: RwLock<HashSet<NodeId>>,
 	stopping: AtomicBool,
 	filter: Option<Arc<ConnectionFilter>>,
+	last_eviction: Mutex<Option<Instant>>,
+	dial_failures: Mutex<DialFailures>,
+	dial_plan: Mutex<Option<(Instant, Vec<DialCandidate>)>>,
+	/// Selected nodes left to dial in the current round, see `TableState::next_candidate`.
+	candidates: Mutex<Option<CandidateStream>>,
+	telemetry: Mutex<TelemetrySchedule>,
+	table_freeze: Mutex<Option<FreezeGuard>>,
 }
 
 impl Host {
-	/// Create a new instance
-	pub fn new(mut config: NetworkConfiguration, filter: Option<Arc<ConnectionFilter>>) -> Result<Host, Error> {
+	/// Create a new instance. The node table is kept in `persistence` if given, otherwise in the network directory
+	/// of `config`.
+	pub fn new(mut config: NetworkConfiguration, filter: Option<Arc<ConnectionFilter>>, persistence: Option<Arc<NodeTablePersistence>>) -> Result<Host, Error> {
 		let mut listen_address = match config.listen_address {
 			None => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), DEFAULT_PORT)),
 			Some(addr) => addr,
@@ -272,17 +497,62 @@ impl Host {
 			|s| KeyPair::from_secret(s).expect("Error creating node secret key"))
 		};
 		let path = config.net_config_path.clone();
+		let persist_nodes = config.persist_nodes;
+		let node_table_config = config.node_table.clone();
+		let mut siblings = node_table_config.sibling_endpoints.clone();
 		// Setup the server socket
 		let tcp_listener = TcpListener::bind(&listen_address)?;
 		listen_address = SocketAddr::new(listen_address.ip(), tcp_listener.local_addr()?.port());
 		debug!(target: "network", "Listening at {:?}", listen_address);
 		let udp_port = config.udp_port.unwrap_or(listen_address.port());
 		let local_endpoint = NodeEndpoint { address: listen_address, udp_port: udp_port };
+		if node_table_config.sibling_registry {
+			if let Some(ref path) = config.config_path {
+				siblings.extend(register_instance(Path::new(path), &listen_address));
+			}
+		}
 
-		let boot_nodes = config.boot_nodes.clone();
+		let (boot_nodes, boot_errors) = parse_bootnodes(&config.boot_nodes);
+		if !boot_errors.is_empty() {
+			let errors: Vec<String> = boot_errors.iter().map(|&(index, ref e)| format!("#{} {}: {}", index, config.boot_nodes[index], e)).collect();
+			if node_table_config.strict_boot_nodes {
+				return Err(ErrorKind::InvalidBootNodes(errors).into());
+			}
+			for e in &errors {
+				warn!("Skipping boot node {}", e);
+			}
+		}
 		let reserved_nodes = config.reserved_nodes.clone();
+		let selection_mode = selection_mode(&config.non_reserved_mode);
 		config.max_handshakes = min(config.max_handshakes, MAX_HANDSHAKES as u32);
 
+		let encrypt_nodes = node_table_config.encrypt_file;
+		let mut nodes = match persistence {
+			Some(persistence) => NodeTable::with_persistence(Box::new(persistence), node_table_config),
+			None => {
+				let file = match path {
+					Some(path) => Some(Arc::new(FilePersistence::for_table(path, &node_table_config).with_key(keys.secret().clone(), encrypt_nodes)?) as Arc<NodeTablePersistence>),
+					None => None,
+				};
+				// a large saved table is read while the first nodes are dialled, see `apply_loaded`
+				match file {
+					Some(file) if persist_nodes => NodeTable::with_backend_streaming(file, node_table_config),
+					file => NodeTable::with_backend(file, persist_nodes, node_table_config),
+				}
+			},
+		};
+		nodes.set_selection_mode(selection_mode);
+		nodes.set_local_id(keys.public().clone());
+		siblings.sort();
+		siblings.dedup();
+		if !siblings.is_empty() {
+			let addresses: Vec<String> = siblings.iter().map(ToString::to_string).collect();
+			info!("Excluding the nodes at the endpoints of other instances: {}", addresses.join(", "));
+		}
+		nodes.set_sibling_endpoints(siblings);
+		// reserved nodes added in earlier runs are kept in the table, the ones given in the config are added below
+		let saved_reserved: HashSet<NodeId> = nodes.required_nodes().into_iter().collect();
+
 		let mut host = Host {
 			info: RwLock::new(HostInfo {
 				keys: keys,
@@ -291,22 +561,38 @@ impl Host {
 				protocol_version: PROTOCOL_VERSION,
 				capabilities: Vec::new(),
 				public_endpoint: None,
+				public_endpoint_since: None,
+				public_endpoint_changes: 0,
 				local_endpoint: local_endpoint,
 			}),
 			discovery: Mutex::new(None),
 			tcp_listener: Mutex::new(tcp_listener),
 			sessions: Arc::new(RwLock::new(Slab::new_starting_at(FIRST_SESSION, MAX_SESSIONS))),
-			nodes: RwLock::new(NodeTable::new(path)),
+			nodes: SharedNodeTable::new(nodes),
 			handlers: RwLock::new(HashMap::new()),
 			timers: RwLock::new(HashMap::new()),
 			timer_counter: RwLock::new(USER_TIMER),
-			reserved_nodes: RwLock::new(HashSet::new()),
+			reserved_nodes: RwLock::new(saved_reserved),
 			stopping: AtomicBool::new(false),
 			filter: filter,
+			last_eviction: Mutex::new(None),
+			dial_failures: Mutex::new(DialFailures::default()),
+			dial_plan: Mutex::new(None),
+			candidates: Mutex::new(None),
+			telemetry: Mutex::new(TelemetrySchedule::default()),
+			table_freeze: Mutex::new(None),
 		};
 
 		for n in boot_nodes {
-			host.add_node(&n);
+			host.add_boot_node(n);
+		}
+
+		let trusted_nodes = host.info.read().config.node_table.trusted_nodes.clone();
+		for url in trusted_nodes {
+			match parse_enode(&url) {
+				Ok(node) => host.nodes.write().ingest(NodeOrigin::Trusted, node),
+				Err(e) => warn!("Skipping trusted node {}: {}", url, e),
+			}
 		}
 
 		for n in reserved_nodes {
@@ -314,29 +600,36 @@ impl Host {
 				debug!(target: "network", "Error parsing node id: {}: {:?}", n, e);
 			}
 		}
+		if host.info.read().config.node_table.static_peers.is_some() {
+			if let Err(e) = host.reload_static_peers() {
+				warn!("Error loading the static peers: {}", e);
+			}
+		}
 		Ok(host)
 	}
 
-	pub fn add_node(&mut self, id: &str) {
-		match Node::from_str(id) {
-			Err(e) => { debug!(target: "network", "Could not add node {}: {:?}", id, e); },
-			Ok(n) => {
-				let entry = NodeEntry { endpoint: n.endpoint.clone(), id: n.id.clone() };
+	fn add_boot_node(&mut self, n: Node) {
+		let entry = NodeEntry::from(&n);
 
-				self.nodes.write().add_node(n);
-				if let Some(ref mut discovery) = *self.discovery.lock() {
-					discovery.add_node(entry);
-				}
-			}
+		if !n.id.is_zero() {
+			self.nodes.write().ingest(NodeOrigin::Boot, n);
+		}
+		if let Some(ref mut discovery) = *self.discovery.lock() {
+			discovery.add_node(entry);
 		}
 	}
 
 	pub fn add_reserved_node(&self, id: &str) -> Result<(), Error> {
 		let n = Node::from_str(id)?;
 
-		let entry = NodeEntry { endpoint: n.endpoint.clone(), id: n.id.clone() };
+		let entry = NodeEntry::from(&n);
 		self.reserved_nodes.write().insert(n.id.clone());
-		self.nodes.write().add_node(Node::new(entry.id.clone(), entry.endpoint.clone()));
+		let node = Node::from(entry.clone());
+		{
+			let mut nodes = self.nodes.write();
+			nodes.ingest(NodeOrigin::Reserved, node);
+			nodes.save();
+		}
 
 		if let Some(ref mut discovery) = *self.discovery.lock() {
 			discovery.add_node(entry);
@@ -351,6 +644,7 @@ impl Host {
 		if info.config.non_reserved_mode != mode {
 			info.config.non_reserved_mode = mode.clone();
 			drop(info);
+			self.nodes.write().set_selection_mode(selection_mode(&mode));
 			if let NonReservedPeerMode::Deny = mode {
 				// disconnect all non-reserved peers here.
 				let reserved: HashSet<NodeId> = self.reserved_nodes.read().clone();
@@ -375,35 +669,428 @@ impl Host {
 		}
 	}
 
-	pub fn remove_reserved_node(&self, id: &str) -> Result<(), Error> {
+	/// Reserve a node for `protocol` only, or release such a reservation. The node is added to the node table if it
+	/// is not known yet.
+	pub fn set_protocol_reserved_node(&self, id: &str, protocol: ProtocolId, reserved: bool) -> Result<(), Error> {
+		let n = Node::from_str(id)?;
+		let entry = NodeEntry::from(&n);
+		let peer_type = if reserved { PeerType::Required } else { PeerType::Optional };
+		{
+			let mut nodes = self.nodes.write();
+			if reserved && !nodes.contains(&n.id) {
+				let mut node = Node::from(entry.clone());
+				node.source = NodeSource::Reserved;
+				node.required_for.insert(protocol);
+				nodes.add_node(node);
+			}
+			nodes.set_peer_type_for(&n.id, protocol, peer_type);
+			nodes.save();
+		}
+
+		if reserved {
+			if let Some(ref mut discovery) = *self.discovery.lock() {
+				discovery.add_node(entry);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Stop treating a node as reserved. The node stays in the table as an ordinary node unless `forget` is set.
+	pub fn remove_reserved_node(&self, id: &str, forget: bool) -> Result<(), Error> {
 		let n = Node::from_str(id)?;
 		self.reserved_nodes.write().remove(&n.id);
+		let mut nodes = self.nodes.write();
+		nodes.release_required(&n.id, forget);
+		nodes.save();
 
 		Ok(())
 	}
 
+	/// Remove a node from the node table and disconnect it. Returns `false` if the node is not in the table.
+	pub fn remove_node(&self, id: &str, io: &IoContext<NetworkIoMessage>) -> Result<bool, Error> {
+		let id = parse_node_id(id)?;
+		let removed = self.nodes.write().remove_node(&id);
+		self.disconnect_node(&id, io);
+		Ok(removed)
+	}
+
+	/// Ban a node for `duration` and disconnect it. Nodes that are not in the table are banned as well, but `false`
+	/// is returned.
+	pub fn ban_node(&self, id: &str, duration: Duration, io: &IoContext<NetworkIoMessage>) -> Result<bool, Error> {
+		let id = parse_node_id(id)?;
+		let known = {
+			let mut nodes = self.nodes.write();
+			nodes.ban(&id, duration, "banned over RPC");
+			nodes.contains(&id)
+		};
+		self.disconnect_node(&id, io);
+		Ok(known)
+	}
+
+	/// Run a node table command like `nodetable list 10`, see `TableCommand`. Banned and removed nodes are
+	/// disconnected. Returns the output as plain text or a usage message if the command cannot be parsed.
+	pub fn node_table_command(&self, line: &str, io: &IoContext<NetworkIoMessage>) -> Result<String, String> {
+		let command: TableCommand = line.parse()?;
+		Ok(command.execute(&self.nodes, |id| self.disconnect_node(id, io)))
+	}
+
+	/// Read the static peers file again, see `TableState::apply_static_peers`. Required peers are reserved, peers
+	/// that are no longer required are not reserved anymore. The file is read before the node table is locked.
+	pub fn reload_static_peers(&self) -> Result<StaticPeersUpdate, Error> {
+		let path = match self.info.read().config.node_table.static_peers {
+			Some(ref path) => path.clone(),
+			None => return Err(io::Error::new(io::ErrorKind::NotFound, "No static peers file configured").into()),
+		};
+		let mut content = String::new();
+		fs::File::open(&path)?.read_to_string(&mut content)?;
+		let (update, added) = {
+			let mut nodes = self.nodes.write();
+			let update = nodes.apply_static_peers(&content);
+			let added: Vec<_> = update.added.iter()
+				.filter_map(|id| nodes.endpoint_of(id).map(|endpoint| NodeEntry::new(*id, endpoint)))
+				.collect();
+			(update, added)
+		};
+		for &(number, ref e) in &update.errors {
+			warn!("Skipping static peer at line {} of {}: {}", number, path.display(), e);
+		}
+		{
+			let mut reserved = self.reserved_nodes.write();
+			reserved.extend(update.required.iter().cloned());
+			for id in &update.downgraded {
+				reserved.remove(id);
+			}
+		}
+		if let Some(ref mut discovery) = *self.discovery.lock() {
+			for entry in added {
+				discovery.add_node(entry);
+			}
+		}
+		Ok(update)
+	}
+
+	/// Clear the useless and banned flags and the dial backoff of a node. Returns `false` if the node is not in the
+	/// table.
+	pub fn retry_node(&self, id: &str) -> Result<bool, Error> {
+		let id = parse_node_id(id)?;
+		Ok(self.nodes.write().retry_now(&id))
+	}
+
+	/// Returns the statistics of a node and why it is not dialled, see `TableState::entry_status_for`. `None` if the
+	/// node is neither in the table nor banned.
+	pub fn node_status(&self, id: &str) -> Result<Option<EntryStatus>, Error> {
+		let id = parse_node_id(id)?;
+		let (policy, filter) = {
+			let info = self.info.read();
+			(dial_policy(&info), info.config.ip_filter.clone())
+		};
+		Ok(self.nodes.read().entry_status_for(&id, &policy, &filter))
+	}
+
+	/// Take the claims of a node at face value or stop doing so, see `TableState::set_trusted`. Returns `false` if
+	/// the node is not in the table.
+	pub fn set_trusted(&self, id: &str, trusted: bool) -> Result<bool, Error> {
+		let id = parse_node_id(id)?;
+		Ok(self.nodes.write().set_trusted(&id, trusted))
+	}
+
+	/// Add a node given by an enode URL to the node table. If `connect_now` is set the node is dialed on the next
+	/// maintenance round regardless of its dial backoff. Returns the normalized enode URL and whether the node was
+	/// not in the table before.
+	pub fn add_node_url(&self, url: &str, connect_now: bool) -> Result<(String, bool), Error> {
+		let mut node = parse_enode(url)?;
+		node.source = NodeSource::Rpc;
+		let entry = NodeEntry::from(&node);
+		let url = node.to_string();
+		let added = {
+			let mut nodes = self.nodes.write();
+			let added = !nodes.contains(&entry.id);
+			nodes.add_node(node);
+			if connect_now {
+				nodes.force_dialable(&entry.id);
+			}
+			added
+		};
+		if let Some(ref mut discovery) = *self.discovery.lock() {
+			discovery.add_node(entry);
+		}
+		Ok((url, added))
+	}
+
+	/// Add the nodes of a list read from `reader` to the node table, see `NodeTable::ingest_list`. Added nodes are
+	/// also handed to discovery. The list is read up to `MAX_LIST_BYTES` before the node table is locked, so that a
+	/// slow reader does not block the table.
+	pub fn import_node_list<R: Read>(&self, reader: R, format: ListFormat) -> Result<IngestStats, Error> {
+		let ip_filter = self.info.read().config.ip_filter.clone();
+		let mut list = Vec::new();
+		reader.take(MAX_LIST_BYTES as u64).read_to_end(&mut list)?;
+		let (stats, added) = {
+			let mut nodes = self.nodes.write();
+			let stats = nodes.ingest_list(&list[..], format, &ip_filter)?;
+			let added: Vec<_> = stats.added().into_iter()
+				.filter_map(|id| nodes.endpoint_of(&id).map(|endpoint| NodeEntry::new(id, endpoint)))
+				.collect();
+			(stats, added)
+		};
+		if let Some(ref mut discovery) = *self.discovery.lock() {
+			for entry in added {
+				discovery.add_node(entry);
+			}
+		}
+		Ok(stats)
+	}
+
+	fn disconnect_node(&self, id: &NodeId, io: &IoContext<NetworkIoMessage>) {
+		let mut to_kill = Vec::new();
+		for e in self.sessions.read().iter() {
+			let mut s = e.lock();
+			if s.id() == Some(id) {
+				s.disconnect(io, DisconnectReason::DisconnectRequested);
+				to_kill.push(s.token());
+			}
+		}
+		for p in to_kill {
+			trace!(target: "network", "Disconnecting removed or banned node: {}", p);
+			self.kill_connection(p, io, false);
+		}
+	}
+
 	pub fn external_url(&self) -> Option<String> {
 		let info = self.info.read();
 		info.public_endpoint.as_ref().map(|e| format!("{}", Node::new(info.id().clone(), e.clone())))
 	}
 
+	/// Advertise `endpoint` as our public endpoint, e.g. after the NAT mapping of the external address changed.
+	/// Discovery advertises it in the next pings and the node table excludes it like the replaced one, which stays
+	/// excluded for a grace period. Returns `false` if it is advertised already.
+	pub fn set_public_endpoint(&self, endpoint: NodeEndpoint) -> bool {
+		let local_endpoint = {
+			let mut info = self.info.write();
+			if info.public_endpoint.as_ref() == Some(&endpoint) {
+				return false;
+			}
+			if let Some(ref previous) = info.public_endpoint {
+				info!("Public endpoint changed from {} to {}", previous.address, endpoint.address);
+				info.public_endpoint_changes += 1;
+			}
+			info.public_endpoint = Some(endpoint.clone());
+			info.public_endpoint_since = Some(Instant::now());
+			info.local_endpoint.clone()
+		};
+		self.nodes.write().set_local_endpoints(vec![local_endpoint, endpoint.clone()]);
+		if let Some(ref mut discovery) = *self.discovery.lock() {
+			discovery.set_public_endpoint(endpoint);
+		}
+		true
+	}
+
 	pub fn local_url(&self) -> String {
 		let info = self.info.read();
 		format!("{}", Node::new(info.id().clone(), info.local_endpoint.clone()))
 	}
 
+	pub fn node_table_entries(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry> {
+		self.nodes.read().entries_with_meta(limit, include_useless)
+	}
+
+	/// Enable or disable saving the node table. If `load_saved` is set the table saved by an earlier run is merged in,
+	/// otherwise it is overwritten on the next save.
+	pub fn set_node_persistence(&self, persist: bool, load_saved: bool) {
+		// once started the loaded nodes are checked right away, otherwise when starting
+		let filter = {
+			let info = self.info.read();
+			info.public_endpoint.as_ref().map(|_| info.config.ip_filter.clone())
+		};
+		let reserved = self.reserved_nodes.read().clone();
+		let mut nodes = self.nodes.write();
+		if load_saved {
+			nodes.load_saved();
+			if let Some(filter) = filter {
+				nodes.reconcile(&filter, &reserved);
+			}
+		}
+		nodes.set_persistent(persist);
+	}
+
+	/// Save the node table again after saving was given up, see `NodeTable::reset_persistence`.
+	pub fn reset_persistence(&self) -> bool {
+		self.nodes.write().reset_persistence()
+	}
+
+	/// Save the node table and keep it from being saved for `duration`, at most `MAX_FREEZE`, e.g. while the file is
+	/// backed up, see `NodeTable::freeze`. A new freeze replaces the previous one without saving in between, and a
+	/// zero `duration` ends it and saves the changes made meanwhile, as does the next maintenance tick once it
+	/// expired. Returns `true` if the file holds the current table.
+	pub fn freeze_node_table(&self, duration: Duration) -> bool {
+		let mut freeze = self.table_freeze.lock();
+		let mut nodes = self.nodes.write();
+		if duration == Duration::from_secs(0) {
+			match freeze.take() {
+				Some(guard) => nodes.thaw(guard),
+				None => nodes.save(),
+			}
+		} else {
+			// the previous freeze is dropped once the new one started, so that `freeze_for` does not save in between
+			*freeze = Some(if duration < MAX_FREEZE { nodes.freeze_for(duration) } else { nodes.freeze() });
+		}
+		nodes.is_saved()
+	}
+
+	/// Save the changes made while the node table was frozen once the freeze ended, also when it expired before
+	/// `freeze_node_table` ended it, see `NodeTable::save_thawed`.
+	fn save_thawed_table(&self) {
+		let mut freeze = self.table_freeze.lock();
+		let mut nodes = self.nodes.write();
+		if !nodes.is_frozen() {
+			freeze.take();
+		}
+		nodes.save_thawed();
+	}
+
+	/// Dial the nodes with an incompatible protocol again, see `NodeTable::bump_compat_epoch`.
+	pub fn bump_compat_epoch(&self) -> usize {
+		self.nodes.write().bump_compat_epoch()
+	}
+
+	/// Set the chain we are on, see `NodeTable::set_local_chain_info`.
+	pub fn set_local_chain_info(&self, info: PeerChainInfo) {
+		self.nodes.write().set_local_chain_info(info);
+	}
+
+	/// Install a policy for new nodes, see `TableState::set_deferred_admission_hook`. The hook is called on the
+	/// maintenance tick without the node table lock held, the new nodes are added once it accepted them.
+	pub fn set_admission_hook(&self, hook: AdmissionHook) {
+		self.nodes.write().set_deferred_admission_hook(hook);
+	}
+
+	/// Report the table quality to `callback` every `NodeTableConfig::telemetry_interval` from now on, replacing an
+	/// earlier callback. The reports only hold counts, see `TableTelemetry`.
+	pub fn set_table_telemetry(&self, callback: TelemetryCallback) {
+		let interval = self.info.read().config.node_table.telemetry_interval;
+		*self.telemetry.lock() = TelemetrySchedule::new(callback, interval, Instant::now());
+	}
+
+	/// Pass a telemetry report to the callback if one is due.
+	fn report_telemetry(&self) {
+		let mut telemetry = self.telemetry.lock();
+		if telemetry.due(Instant::now()) {
+			let report = self.nodes.read().telemetry();
+			telemetry.report(report);
+		}
+	}
+
+	/// Tag `key` of a node, see `NodeTable::set_tag`.
+	pub fn node_tag(&self, id: &NodeId, key: &str) -> Option<u32> {
+		self.nodes.read().get_tag(id, key)
+	}
+
+	/// Nodes with the lowest values of tag `key`, see `NodeTable::top_by_tag`.
+	pub fn top_by_tag(&self, key: &str, limit: usize) -> Vec<NodeId> {
+		self.nodes.read().top_by_tag(key, limit)
+	}
+
+	/// Ids of the nodes that are or were at `ip`, see `NodeTable::ids_seen_on`.
+	pub fn ids_seen_on(&self, ip: &IpAddr) -> Vec<NodeId> {
+		self.nodes.read().ids_seen_on(ip)
+	}
+
+	/// Nodes that look run by the same operator as `id`, see `NodeTable::cluster_of`.
+	pub fn cluster_of(&self, id: &NodeId) -> Option<Vec<NodeId>> {
+		self.nodes.read().cluster_of(id)
+	}
+
+	/// Returns the enode URLs of at most `limit` of the best public nodes allowed by the IP filter.
+	pub fn best_enodes(&self, limit: usize) -> Vec<String> {
+		let ip_filter = self.info.read().config.ip_filter.clone();
+		self.nodes.read().best_enodes(limit, &ip_filter)
+	}
+
+	/// Returns the node list written by `NodeTable::export_node_list` for at most `limit` nodes.
+	pub fn export_node_list(&self, limit: usize) -> String {
+		let ip_filter = self.info.read().config.ip_filter.clone();
+		let mut list = Vec::new();
+		self.nodes.read().export_node_list(&mut list, limit, &ip_filter).expect("writing to a vector does not fail; qed");
+		String::from_utf8(list).expect("enode URLs are ASCII; qed")
+	}
+
+	/// Returns a summary of the node table, discovery and session state.
+	pub fn net_health(&self) -> NetHealth {
+		let (ip_filter, min_peers) = {
+			let info = self.info.read();
+			(info.config.ip_filter.clone(), info.config.min_peers)
+		};
+		let mut sessions = SessionStats::default();
+		for e in self.sessions.read().iter() {
+			let s = e.lock();
+			if !s.is_ready() {
+				sessions.handshakes += 1;
+				continue;
+			}
+			if s.info.originated {
+				sessions.egress += 1;
+			} else {
+				sessions.ingress += 1;
+			}
+			for c in &s.info.capabilities {
+				*sessions.protocols.entry(String::from_utf8_lossy(&c.protocol).into_owned()).or_insert(0) += 1;
+			}
+		}
+		let discovery = self.discovery.lock().as_ref().map(Discovery::stats);
+		let (mut node_table, metrics, skipped_nodes, recent_evictions) = {
+			let nodes = self.nodes.read();
+			(nodes.stats(&ip_filter), node_table_metrics(&nodes), nodes.load_report().skipped_nodes.clone(), nodes.recent_evictions())
+		};
+		node_table.deferred_dropped = self.nodes.deferred_dropped();
+		let mut health = net_health_summary(node_table, discovery, sessions, &ip_filter, min_peers);
+		health.metrics = metrics;
+		health.skipped_nodes = skipped_nodes;
+		health.recent_evictions = recent_evictions;
+		health.advertised_endpoint = {
+			let info = self.info.read();
+			match (info.public_endpoint.clone(), info.public_endpoint_since) {
+				(Some(endpoint), Some(since)) => Some(AdvertisedEndpoint {
+					endpoint: endpoint,
+					age: since.elapsed(),
+					changes: info.public_endpoint_changes,
+				}),
+				_ => None,
+			}
+		};
+		health.dial_plan = self.dial_plan.lock().as_ref().map(|&(at, ref candidates)| DialPlan {
+			age: at.elapsed(),
+			candidates: candidates.clone(),
+		});
+		health.sibling_endpoints = self.nodes.read().sibling_endpoints().to_vec();
+		health
+	}
+
 	pub fn stop(&self, io: &IoContext<NetworkIoMessage>) -> Result<(), Error> {
 		self.stopping.store(true, AtomicOrdering::Release);
 		let mut to_kill = Vec::new();
+		let mut warm = Vec::new();
 		for e in self.sessions.read().iter() {
 			let mut s = e.lock();
+			if let Some(id) = s.id() {
+				if s.is_ready() && !s.expired() {
+					warm.push(id.clone());
+				}
+			}
 			s.disconnect(io, DisconnectReason::ClientQuit);
 			to_kill.push(s.token());
 		}
+		// dial the same peers first on the next start, the table is saved when it is dropped
+		self.nodes.write().mark_warm(&warm);
 		for p in to_kill {
 			trace!(target: "network", "Disconnecting on shutdown: {}", p);
 			self.kill_connection(p, io, true);
 		}
+		{
+			let info = self.info.read();
+			if let (true, Some(path)) = (info.config.node_table.sibling_registry, info.config.config_path.as_ref()) {
+				unregister_instance(Path::new(path), &info.local_endpoint.address);
+			}
+		}
 		io.unregister_handler()?;
 		Ok(())
 	}
@@ -448,7 +1135,11 @@ impl Host {
 			Some(addr) => NodeEndpoint { address: addr, udp_port: local_endpoint.udp_port }
 		};
 
-		self.info.write().public_endpoint = Some(public_endpoint.clone());
+		self.set_public_endpoint(public_endpoint.clone());
+		{
+			let reserved = self.reserved_nodes.read().clone();
+			self.nodes.write().reconcile(&allow_ips, &reserved);
+		}
 
 		if let Some(url) = self.external_url() {
 			io.message(NetworkIoMessage::NetworkStarted(url)).unwrap_or_else(|e| warn!("Error sending IO notification: {:?}", e));
@@ -460,26 +1151,222 @@ impl Host {
 			if info.config.discovery_enabled && info.config.non_reserved_mode == NonReservedPeerMode::Accept {
 				let mut udp_addr = local_endpoint.address.clone();
 				udp_addr.set_port(local_endpoint.udp_port);
-				Some(Discovery::new(&info.keys, udp_addr, public_endpoint, DISCOVERY, allow_ips))
+				Some(Discovery::new(&info.keys, udp_addr, public_endpoint, DISCOVERY, allow_ips.clone()))
 			} else { None }
 		};
 
 		if let Some(mut discovery) = discovery {
-			discovery.init_node_list(self.nodes.read().entries());
-			discovery.add_node_list(self.nodes.read().entries());
+			discovery.set_membership(self.nodes.read().membership());
+			let (entries, sweep) = {
+				let mut nodes = self.nodes.write();
+				let entries = nodes.entries_filtered(&allow_ips, usize::max_value());
+				(entries, nodes.sweep_batch(Instant::now(), &allow_ips, usize::max_value()))
+			};
+			discovery.init_node_list(entries.clone());
+			// pinged from the back, so the sweep of a stale table goes last with its most reliable node at the end
+			let sweep_ids: HashSet<NodeId> = sweep.iter().map(|e| e.id).collect();
+			let mut ping: Vec<NodeEntry> = entries.into_iter().filter(|e| !sweep_ids.contains(&e.id)).collect();
+			ping.extend(sweep.into_iter().rev());
+			discovery.add_node_list(ping);
 			*self.discovery.lock() = Some(discovery);
 			io.register_stream(DISCOVERY)?;
 			io.register_timer(DISCOVERY_REFRESH, DISCOVERY_REFRESH_TIMEOUT)?;
 			io.register_timer(DISCOVERY_ROUND, DISCOVERY_ROUND_TIMEOUT)?;
 		}
-		io.register_timer(NODE_TABLE, NODE_TABLE_TIMEOUT)?;
+		// starved at start, the maintenance slows down as peers connect
+		let first_maintenance = self.info.read().config.node_table.maintenance_min_interval;
+		io.register_timer_once(NODE_TABLE, millis(first_maintenance))?;
 		io.register_stream(TCP_ACCEPT)?;
 		Ok(())
 	}
 
+	/// Run the node table maintenance and schedule the next run after the delay it suggests, short while we lack
+	/// peers and long once we have enough, see `NodeTable::maintenance`. Each run queues a batch of the sweep of a
+	/// stale table for discovery to ping again and dials when dial backoffs ended.
+	fn maintain_node_table(&self, io: &IoContext<NetworkIoMessage>) {
+		let reserved = self.reserved_nodes.read().clone();
+		let (target, allow_ips) = {
+			let info = self.info.read();
+			(info.config.min_peers as usize + reserved.len(), info.config.ip_filter.clone())
+		};
+		let report = self.nodes.write().maintenance(Instant::now(), &reserved, target);
+		trace!(target: "network", "Node table maintenance: {:?}", report);
+		if report.stale_pending > 0 {
+			let batch = self.nodes.write().sweep_batch(Instant::now(), &allow_ips, SWEEP_BATCH);
+			if let Some(ref mut discovery) = *self.discovery.lock() {
+				discovery.queue_node_list(batch);
+			}
+		}
+		if report.backoff_expired > 0 {
+			self.connect_peers(io);
+		}
+		io.register_timer_once(NODE_TABLE, millis(report.next_delay))
+			.unwrap_or_else(|e| debug!("Error registering node table timer: {:?}", e));
+	}
+
 	fn maintain_network(&self, io: &IoContext<NetworkIoMessage>) {
+		self.nodes.apply_deferred();
+		let reserved = self.reserved_nodes.read().clone();
+		self.nodes.admit_awaiting(&reserved);
+		self.apply_loaded();
+		{
+			let mut nodes = self.nodes.write();
+			nodes.refresh_membership();
+			nodes.warn_useless_required();
+		}
+		self.save_thawed_table();
 		self.keep_alive(io);
+		self.record_inbound_sessions();
+		self.evict_peers(io);
+		self.dial_forced(io);
 		self.connect_peers(io);
+		self.report_telemetry();
+	}
+
+	/// Merge the saved nodes read since the last tick while the node table is still being loaded, see
+	/// `NodeTable::apply_loaded`. Reserved nodes of earlier runs among them are reserved again. Once the load
+	/// completed the loaded nodes are checked and added to discovery like the ones loaded at start.
+	fn apply_loaded(&self) {
+		let (completed, required) = {
+			let mut nodes = self.nodes.write();
+			if nodes.load_complete() {
+				return;
+			}
+			let completed = nodes.apply_loaded();
+			(completed, nodes.required_nodes())
+		};
+		self.reserved_nodes.write().extend(required);
+		if !completed {
+			return;
+		}
+		let filter = {
+			let info = self.info.read();
+			info.public_endpoint.as_ref().map(|_| info.config.ip_filter.clone())
+		};
+		if let Some(filter) = filter {
+			let reserved = self.reserved_nodes.read().clone();
+			let entries = {
+				let mut nodes = self.nodes.write();
+				nodes.reconcile(&filter, &reserved);
+				nodes.entries_filtered(&filter, usize::max_value())
+			};
+			if let Some(ref mut discovery) = *self.discovery.lock() {
+				discovery.init_node_list(entries);
+			}
+		}
+	}
+
+	/// Dial the nodes queued with `NodeTable::force_dialable`.
+	fn dial_forced(&self, io: &IoContext<NetworkIoMessage>) {
+		let forced = self.nodes.write().take_forced_dials();
+		if forced.is_empty() {
+			return;
+		}
+		let self_id = self.info.read().id().clone();
+		for id in forced.into_iter().filter(|id|
+				*id != self_id &&
+				self.filter.as_ref().map_or(true, |f| f.connection_allowed(&self_id, &id, ConnectionDirection::Outbound))
+			) {
+			self.connect_peer(&id, io);
+		}
+	}
+
+	/// Replace the worst connected optional peer with a better node table candidate once all slots are taken. Only
+	/// outbound sessions are evicted, so that the replacement dial takes over the freed slot.
+	fn evict_peers(&self, io: &IoContext<NetworkIoMessage>) {
+		let (margin, min_peers, allow_ips, self_id, policy) = {
+			let info = self.info.read();
+			if info.capabilities.is_empty() {
+				return;
+			}
+			let config = &info.config;
+			let margin = match config.eviction_margin {
+				Some(margin) => margin as usize,
+				None => return,
+			};
+			let policy = dial_policy(&info);
+			(margin, config.min_peers, config.ip_filter.clone(), info.id().clone(), policy)
+		};
+
+		let (_, egress_count, ingress_count) = self.session_count();
+		let reserved_nodes = self.reserved_nodes.read();
+		// `connect_peers` stops dialing at this point.
+		if egress_count + ingress_count < min_peers as usize + reserved_nodes.len() {
+			return;
+		}
+
+		let mut connected = HashSet::new();
+		let mut sessions = Vec::new();
+		for e in self.sessions.read().iter() {
+			let s = e.lock();
+			if let Some(id) = s.id() {
+				connected.insert(id.clone());
+				if s.is_ready() && s.info.originated && !s.expired() && !reserved_nodes.contains(id) {
+					sessions.push((s.token(), id.clone(), s.ready_duration().unwrap_or_default()));
+				}
+			}
+		}
+
+		let (peers, candidate) = {
+			let nodes = self.nodes.read();
+			let protocols = policy.protocols();
+			// peers reserved for one of our protocols are not evicted either
+			let peers: Vec<_> = sessions.into_iter()
+				.filter(|&(_, ref id, _)| !protocols.iter().any(|p| nodes.is_required_for(id, p)))
+				.filter_map(|(token, id, age)| nodes.score_of(&id).map(|score| EvictionPeer { token: token, score: score, age: age }))
+				.collect();
+			let candidate = nodes.select_for(&policy, &allow_ips, usize::max_value(), None).into_iter()
+				.find(|id|
+					!connected.contains(id) &&
+					*id != self_id &&
+					self.filter.as_ref().map_or(true, |f| f.connection_allowed(&self_id, &id, ConnectionDirection::Outbound))
+				)
+				.and_then(|id| nodes.score_of(&id).map(|score| (id, score)));
+			(peers, candidate)
+		};
+
+		let (candidate, candidate_score) = match candidate {
+			Some(candidate) => candidate,
+			None => return,
+		};
+		let since_last_eviction = self.last_eviction.lock().map(|t| t.elapsed());
+		if let Some(token) = select_eviction(&peers, candidate_score, margin, since_last_eviction) {
+			debug!(target: "network", "Evicting session {} in favour of {} (score {})", token, candidate, candidate_score);
+			let session = { self.sessions.read().get(token).cloned() };
+			if let Some(session) = session {
+				session.lock().disconnect(io, DisconnectReason::TooManyPeers);
+			}
+			*self.last_eviction.lock() = Some(Instant::now());
+			self.kill_connection(token, io, false);
+			self.connect_peer(&candidate, io);
+		}
+	}
+
+	/// Add inbound peers that stayed connected long enough to the node table.
+	fn record_inbound_sessions(&self) {
+		let mut recorded = Vec::new();
+		for e in self.sessions.read().iter() {
+			let mut s = e.lock();
+			if s.is_recorded() {
+				continue;
+			}
+			let endpoint = inbound_record_endpoint(s.info.originated, s.ready_duration(), s.remote_addr().ok(), s.listen_port());
+			if let (Some(endpoint), Some(id)) = (endpoint, s.id().cloned()) {
+				let meta = SessionMeta {
+					client_version: s.info.client_version.clone(),
+					capabilities: s.info.peer_capabilities.clone(),
+				};
+				recorded.push((id, endpoint, meta));
+				s.set_recorded();
+			}
+		}
+		for (id, endpoint, meta) in recorded {
+			let added = self.nodes.write().note_inbound(&id, endpoint.clone(), meta);
+			if added {
+				// discovery learns of the node once its UDP port is known, pinging the TCP port would likely miss
+				trace!(target: "network", "Recorded inbound node {} at {:?}", id, endpoint.address);
+			}
+		}
 	}
 
 	fn have_session(&self, id: &NodeId) -> bool {
@@ -507,13 +1394,20 @@ impl Host {
 
 	fn keep_alive(&self, io: &IoContext<NetworkIoMessage>) {
 		let mut to_kill = Vec::new();
+		let mut stalled = Vec::new();
 		for e in self.sessions.read().iter() {
 			let mut s = e.lock();
 			if !s.keep_alive(io) {
+				if let Some(id) = s.id() {
+					stalled.push((id.clone(), Outcome::Stalled));
+				}
 				s.disconnect(io, DisconnectReason::PingTimeout);
 				to_kill.push(s.token());
 			}
 		}
+		if !stalled.is_empty() {
+			self.nodes.try_note_outcomes(stalled);
+		}
 		for p in to_kill {
 			trace!(target: "network", "Ping timeout: {}", p);
 			self.kill_connection(p, io, true);
@@ -521,14 +1415,15 @@ impl Host {
 	}
 
 	fn connect_peers(&self, io: &IoContext<NetworkIoMessage>) {
-		let (min_peers, mut pin, max_handshakes, allow_ips, self_id) = {
+		let (min_peers, mut pin, max_handshakes, allow_ips, self_id, policy) = {
 			let info = self.info.read();
 			if info.capabilities.is_empty() {
 				return;
 			}
 			let config = &info.config;
+			let policy = dial_policy(&info);
 
-			(config.min_peers, config.non_reserved_mode == NonReservedPeerMode::Deny, config.max_handshakes as usize, config.ip_filter.clone(), info.id().clone())
+			(config.min_peers, config.non_reserved_mode == NonReservedPeerMode::Deny, config.max_handshakes as usize, config.ip_filter.clone(), info.id().clone(), policy)
 		};
 
 		let (handshake_count, egress_count, ingress_count) = self.session_count();
@@ -548,25 +1443,86 @@ impl Host {
 			return;
 		}
 
-		// iterate over all nodes, reserved ones coming first.
+		// iterate over all nodes, reserved ones coming first, then the peers of the last run during the warm start.
 		// if we are pinned to only reserved nodes, ignore all others.
-		let nodes = reserved_nodes.iter().cloned().chain(if !pin {
-			self.nodes.read().nodes(allow_ips)
-		} else {
-			Vec::new()
-		});
-
 		let max_handshakes_per_round = max_handshakes / 2;
-		let mut started: usize = 0;
-		for id in nodes.filter(|id|
+		let limit = min(max_handshakes_per_round, max_handshakes - handshake_count);
+		// after losing many peers at once, e.g. a network blip, the peers just lost are the quickest to get back
+		let recovering = !pin && egress_count + ingress_count < min_peers as usize / 2;
+		let mut candidates: Vec<DialCandidate> = {
+			let nodes = self.nodes.read();
+			let lost = if recovering { nodes.recently_lost(RECENTLY_LOST_AGE) } else { Vec::new() };
+			let lost_ids: HashSet<NodeId> = lost.iter().map(|e| e.id).collect();
+			let required = required_peers(&nodes, &reserved_nodes, &policy.protocols());
+			let required_ids: HashSet<NodeId> = required.iter().map(|e| e.id).collect();
+			let lost = lost.into_iter().filter(|e| !required_ids.contains(&e.id)).map(|entry| DialCandidate {
+				score: nodes.score_of(&entry.id),
+				id: entry.id,
+				endpoint: Some(entry.endpoint),
+				reason: SelectionReason::RecentlyLost,
+				dialled: false,
+			});
+			let reserved = required.into_iter().map(|entry| DialCandidate {
+				score: nodes.score_of(&entry.id),
+				id: entry.id,
+				endpoint: Some(entry.endpoint),
+				reason: SelectionReason::Required,
+				dialled: false,
+			});
+			// boot nodes are left out of the selection once the table is bootstrapped, unless we have no peers at all
+			let boot = if !pin && egress_count + ingress_count == 0 { nodes.boot_fallback(&allow_ips) } else { Vec::new() };
+			let boot = boot.into_iter().filter(|id| !required_ids.contains(id) && !lost_ids.contains(id)).map(|id| DialCandidate {
+				endpoint: nodes.endpoint_of(&id),
+				score: nodes.score_of(&id),
+				id: id,
+				reason: SelectionReason::BootFallback,
+				dialled: false,
+			});
+			let dialable = |id: &NodeId| {
 				!self.have_session(id) &&
 				!self.connecting_to(id) &&
 				*id != self_id &&
-				self.filter.as_ref().map_or(true, |f| f.connection_allowed(&self_id, &id, ConnectionDirection::Outbound))
-			).take(min(max_handshakes_per_round, max_handshakes - handshake_count)) {
-			self.connect_peer(&id, io);
+				self.filter.as_ref().map_or(true, |f| f.connection_allowed(&self_id, id, ConnectionDirection::Outbound))
+			};
+			let mut candidates: Vec<DialCandidate> = reserved.chain(lost).chain(boot).filter(|c| dialable(&c.id)).take(max(limit, MAX_DIAL_PLAN)).collect();
+			if !pin {
+				// the selected nodes fill the slots left, each is only dialled once per round
+				let selected = |entry: NodeEntry, reason| DialCandidate {
+					score: nodes.score_of(&entry.id),
+					id: entry.id,
+					endpoint: Some(entry.endpoint),
+					reason: reason,
+					dialled: false,
+				};
+				let mut stream = self.candidates.lock();
+				if !stream.as_ref().map_or(false, |s| s.is_for(&policy, &allow_ips)) {
+					*stream = Some(nodes.candidate_stream(policy, allow_ips));
+				}
+				let stream = stream.as_mut().expect("set above; qed");
+				while candidates.len() < limit {
+					match nodes.next_candidate(stream) {
+						Some((entry, reason)) => if !required_ids.contains(&entry.id) && !lost_ids.contains(&entry.id) && dialable(&entry.id) {
+							candidates.push(selected(entry, reason));
+						},
+						None => break,
+					}
+				}
+				let room = MAX_DIAL_PLAN.saturating_sub(candidates.len());
+				let upcoming = stream.upcoming(room).into_iter()
+					.filter(|&(ref entry, _)| !required_ids.contains(&entry.id) && !lost_ids.contains(&entry.id) && dialable(&entry.id))
+					.map(|(entry, reason)| selected(entry, reason));
+				candidates.extend(upcoming);
+			}
+			candidates
+		};
+
+		let mut started: usize = 0;
+		for candidate in candidates.iter_mut().take(limit) {
+			self.connect_peer(&candidate.id, io);
+			candidate.dialled = true;
 			started += 1;
 		}
+		*self.dial_plan.lock() = Some((Instant::now(), candidates));
 		debug!(target: "network", "Connecting peers: {} sessions, {} pending + {} started", egress_count + ingress_count, handshake_count, started);
 	}
 
@@ -582,14 +1538,13 @@ impl Host {
 
 		let socket = {
 			let address = {
-				let mut nodes = self.nodes.write();
-				if let Some(node) = nodes.get_mut(id) {
-					node.attempts += 1;
-					node.endpoint.address
-				}
-				else {
-					debug!(target: "network", "Connection to expired node aborted");
-					return;
+				let address = self.nodes.write().note_dial(id);
+				match address {
+					Some(address) => address,
+					None => {
+						debug!(target: "network", "Connection to expired node aborted");
+						return;
+					},
 				}
 			};
 			match TcpStream::connect(&address) {
@@ -611,11 +1566,12 @@ impl Host {
 
 	fn create_connection(&self, socket: TcpStream, id: Option<&NodeId>, io: &IoContext<NetworkIoMessage>) -> Result<(), Error> {
 		let nonce = self.info.write().next_nonce();
+		let hello = hello_version(&self.nodes.read(), id, self.info.read().protocol_version);
 		let mut sessions = self.sessions.write();
 
 		let token = sessions.insert_with_opt(|token| {
 			trace!(target: "network", "{}: Initiating session {:?}", token, id);
-			match Session::new(io, socket, token, id, &nonce, &self.info.read()) {
+			match Session::new(io, socket, token, id, &nonce, &self.info.read(), hello) {
 				Ok(s) => Some(Arc::new(Mutex::new(s))),
 				Err(e) => {
 					debug!(target: "network", "Session create error: {:?}", e);
@@ -687,10 +1643,16 @@ impl Host {
 							if let ErrorKind::Disconnect(DisconnectReason::IncompatibleProtocol) = *e.kind() {
 								if let Some(id) = s.id() {
 									if !self.reserved_nodes.read().contains(id) {
-										self.nodes.write().mark_as_useless(id);
+										self.nodes.write().mark_as_useless(id, INCOMPATIBLE_PROTOCOL).log_unknown(id, "incompatible session");
 									}
 								}
 							}
+							if let (&ErrorKind::Decompression(_), Some(id)) = (e.kind(), s.id()) {
+								// the peer announced compression but does not get it right, the next session is not compressed
+								if s.compression() {
+									self.nodes.write().set_negotiated(id, s.remote_protocol_version(), false);
+								}
+							}
 							kill = true;
 							break;
 						},
@@ -720,8 +1682,12 @@ impl Host {
 							if reserved_only ||
 								(s.info.originated && egress_count > min_peers) ||
 								(!s.info.originated && ingress_count > max_ingress) {
-								// only proceed if the connecting peer is reserved.
-								if !self.reserved_nodes.read().contains(&id) {
+								// only proceed if the connecting peer is reserved, globally or for one of its protocols.
+								let reserved = self.reserved_nodes.read().contains(&id) || {
+									let nodes = self.nodes.read();
+									s.info.capabilities.iter().any(|cap| nodes.is_required_for(&id, &cap.protocol))
+								};
+								if !reserved {
 									s.disconnect(io, DisconnectReason::TooManyPeers);
 									kill = true;
 									break;
@@ -735,24 +1701,34 @@ impl Host {
 								break;
 							}
 
-							ready_id = Some(id);
+							if self.nodes.read().is_banned(&id) && !self.reserved_nodes.read().contains(&id) {
+								trace!(target: "network", "Rejected banned node {:?}", id);
+								s.disconnect(io, DisconnectReason::UselessPeer);
+								kill = true;
+								break;
+							}
 
-							// Add it to the node table
-							if !s.info.originated {
-								if let Ok(address) = s.remote_addr() {
-									// We can't know remote listening ports, so just assume defaults and hope for the best.
-									let endpoint = NodeEndpoint { address: SocketAddr::new(address.ip(), DEFAULT_PORT), udp_port: DEFAULT_PORT };
-									let entry = NodeEntry { id: id, endpoint: endpoint };
-									let mut nodes = self.nodes.write();
-									if !nodes.contains(&entry.id) {
-										nodes.add_node(Node::new(entry.id.clone(), entry.endpoint.clone()));
-										let mut discovery = self.discovery.lock();
-										if let Some(ref mut discovery) = *discovery {
-											discovery.add_node(entry);
-										}
+							// Inbound peers are added to the node table by `record_inbound_sessions` once they stay connected.
+							{
+								let mut nodes = self.nodes.write();
+								let remote = s.remote_addr().ok();
+								if let Some(ref remote) = remote {
+									nodes.session_opened(&id, remote);
+								}
+								if s.info.originated {
+									nodes.note_success(&id);
+									self.dial_failures.lock().note_success();
+								} else if let Some(remote) = remote {
+									match nodes.match_inbound(&id, &remote) {
+										InboundMatch::Mismatch(known) => debug!(target: "network", "Inbound node {} connected from {}, where {} other nodes are known", id.short(), remote.ip(), known.len()),
+										InboundMatch::Confirmed => trace!(target: "network", "Inbound node {} confirmed at {}", id.short(), remote.ip()),
+										InboundMatch::Unknown => (),
 									}
 								}
+								nodes.note_capabilities(&id, s.info.peer_capabilities.clone());
+								nodes.set_negotiated(&id, s.remote_protocol_version(), s.compression());
 							}
+							ready_id = Some(id);
 							for (p, _) in self.handlers.read().iter() {
 								if s.have_capability(*p) {
 									ready_data.push(*p);
@@ -794,7 +1770,7 @@ impl Host {
 				for p in ready_data {
 					let reserved = self.reserved_nodes.read();
 					if let Some(h) = handlers.get(&p).clone() {
-						h.connected(&NetworkContext::new(io, p, Some(session.clone()), self.sessions.clone(), &reserved), &token);
+						h.connected(&NetworkContext::new(io, p, Some(session.clone()), self.sessions.clone(), &reserved, &self.nodes), &token);
 						// accumulate pending packets.
 						let mut session = session.lock();
 						packet_data.extend(session.mark_connected(p));
@@ -805,7 +1781,7 @@ impl Host {
 			for (p, packet_id, data) in packet_data {
 				let reserved = self.reserved_nodes.read();
 				if let Some(h) = handlers.get(&p).clone() {
-					h.read(&NetworkContext::new(io, p, Some(session.clone()), self.sessions.clone(), &reserved), &token, packet_id, &data);
+					h.read(&NetworkContext::new(io, p, Some(session.clone()), self.sessions.clone(), &reserved, &self.nodes), &token, packet_id, &data);
 				}
 			}
 		}
@@ -821,6 +1797,9 @@ impl Host {
 		let mut failure_id = None;
 		let mut deregister = false;
 		let mut expired_session = None;
+		let mut dial_failed = false;
+		let mut established = false;
+		let mut mismatched_id = None;
 		if let FIRST_SESSION ... LAST_SESSION = token {
 			let sessions = self.sessions.read();
 			if let Some(session) = sessions.get(token).cloned() {
@@ -834,21 +1813,45 @@ impl Host {
 							}
 						}
 					}
+					established = s.is_ready();
 					s.set_expired();
 					failure_id = s.id().cloned();
+					dial_failed = s.info.originated && !s.is_ready();
+					mismatched_id = s.mismatched_id().cloned();
 				}
 				deregister = remote || s.done();
 			}
 		}
+		if let (Some(expected), Some(announced)) = (failure_id.as_ref(), mismatched_id) {
+			// the session was already closed as a protocol violation, the dial counts as failed
+			self.nodes.write().note_id_mismatch(expected, announced);
+		}
 		if let Some(id) = failure_id {
-			if remote {
-				self.nodes.write().note_failure(&id);
+			if established {
+				record_session_closed(&self.nodes, &id, remote);
+			}
+			if remote && !dial_failed {
+				self.nodes.try_note_failure(&id, "session failure");
+			} else if remote {
+				// dial failures feed the outage detection, which needs the table right away
+				let (subnets, window) = {
+					let config = &self.info.read().config.node_table;
+					(config.outage_subnets, config.outage_window)
+				};
+				let mut nodes = self.nodes.write();
+				// the failure that completes the pattern is quarantined like the following ones
+				if let Some(subnet) = nodes.subnet_of(&id) {
+					if self.dial_failures.lock().note_failure(Instant::now(), subnet, subnets, window) {
+						nodes.note_global_outage_suspected();
+					}
+				}
+				nodes.note_failure(&id).log_unknown(&id, "dial failure");
 			}
 		}
 		for p in to_disconnect {
 			let reserved = self.reserved_nodes.read();
 			if let Some(h) = self.handlers.read().get(&p).clone() {
-				h.disconnected(&NetworkContext::new(io, p, expired_session.clone(), self.sessions.clone(), &reserved), &token);
+				h.disconnected(&NetworkContext::new(io, p, expired_session.clone(), self.sessions.clone(), &reserved, &self.nodes), &token);
 			}
 		}
 		if deregister {
@@ -872,20 +1875,35 @@ impl Host {
 		for i in to_remove {
 			trace!(target: "network", "Removed from node table: {}", i);
 		}
-		self.nodes.write().update(node_changes, &*self.reserved_nodes.read());
+		let outcome = self.nodes.write().update(node_changes, &*self.reserved_nodes.read());
+		if outcome.truncated_added > 0 || outcome.truncated_removed > 0 {
+			warn!(target: "network", "Discovery update too large, ignored {} added and {} removed nodes", outcome.truncated_added, outcome.truncated_removed);
+		}
+		if !outcome.rejected.is_empty() || outcome.evicted > 0 {
+			let mut reasons: BTreeMap<RejectReason, usize> = BTreeMap::new();
+			for &(_, reason) in &outcome.rejected {
+				*reasons.entry(reason).or_insert(0) += 1;
+			}
+			let reasons: Vec<String> = reasons.iter().map(|(reason, count)| format!("{} {}", count, reason)).collect();
+			debug!(target: "network", "Discovery update: {} nodes added, {} rejected ({}), {} evicted", outcome.added, outcome.rejected.len(), reasons.join(", "), outcome.evicted);
+		}
+		if !outcome.protected.is_empty() {
+			let ids: Vec<String> = outcome.protected.iter().map(|id| id.short().to_string()).collect();
+			debug!(target: "network", "Discovery update: kept {} reserved, required or boot nodes dropped by discovery: {}", ids.len(), ids.join(", "));
+		}
 	}
 
 	pub fn with_context<F>(&self, protocol: ProtocolId, io: &IoContext<NetworkIoMessage>, action: F) where F: FnOnce(&NetworkContextTrait) {
 		let reserved = { self.reserved_nodes.read() };
 
-		let context = NetworkContext::new(io, protocol, None, self.sessions.clone(), &reserved);
+		let context = NetworkContext::new(io, protocol, None, self.sessions.clone(), &reserved, &self.nodes);
 		action(&context);
 	}
 
 	pub fn with_context_eval<F, T>(&self, protocol: ProtocolId, io: &IoContext<NetworkIoMessage>, action: F) -> T where F: FnOnce(&NetworkContextTrait) -> T {
 		let reserved = { self.reserved_nodes.read() };
 
-		let context = NetworkContext::new(io, protocol, None, self.sessions.clone(), &reserved);
+		let context = NetworkContext::new(io, protocol, None, self.sessions.clone(), &reserved, &self.nodes);
 		action(&context)
 	}
 }
@@ -954,17 +1972,13 @@ impl IoHandler<NetworkIoMessage> for Host {
 				}
 				io.update_registration(DISCOVERY).unwrap_or_else(|e| debug!("Error updating discovery registration: {:?}", e));
 			},
-			NODE_TABLE => {
-				trace!(target: "network", "Refreshing node table");
-				self.nodes.write().clear_useless();
-				self.nodes.write().save();
-			},
+			NODE_TABLE => self.maintain_node_table(io),
 			_ => match self.timers.read().get(&token).cloned() {
 				Some(timer) => match self.handlers.read().get(&timer.protocol).cloned() {
 					None => { warn!(target: "network", "No handler found for protocol: {:?}", timer.protocol) },
 					Some(h) => {
 						let reserved = self.reserved_nodes.read();
-						h.timeout(&NetworkContext::new(io, timer.protocol, None, self.sessions.clone(), &reserved), timer.token);
+						h.timeout(&NetworkContext::new(io, timer.protocol, None, self.sessions.clone(), &reserved, &self.nodes), timer.token);
 					}
 				},
 				None => { warn!("Unknown timer token: {}", token); } // timer is not registerd through us
@@ -986,13 +2000,20 @@ impl IoHandler<NetworkIoMessage> for Host {
 				let h = handler.clone();
 				let reserved = self.reserved_nodes.read();
 				h.initialize(
-					&NetworkContext::new(io, *protocol, None, self.sessions.clone(), &reserved),
+					&NetworkContext::new(io, *protocol, None, self.sessions.clone(), &reserved, &self.nodes),
 					&*self.info.read(),
 				);
 				self.handlers.write().insert(*protocol, h);
-				let mut info = self.info.write();
-				for v in versions {
-					info.capabilities.push(CapabilityInfo { protocol: *protocol, version: *v, packet_count: *packet_count });
+				{
+					let mut info = self.info.write();
+					for v in versions {
+						info.capabilities.push(CapabilityInfo { protocol: *protocol, version: *v, packet_count: *packet_count });
+					}
+				}
+				// the protocols changed while connected, nodes found incompatible before may not be any more
+				let (_, egress_count, ingress_count) = self.session_count();
+				if egress_count + ingress_count > 0 {
+					self.bump_compat_epoch();
 				}
 			},
 			NetworkIoMessage::AddTimer {
@@ -1023,7 +2044,7 @@ impl IoHandler<NetworkIoMessage> for Host {
 				if let Some(session) = session {
 					session.lock().disconnect(io, DisconnectReason::DisconnectRequested);
 					if let Some(id) = session.lock().id() {
-						self.nodes.write().mark_as_useless(id)
+						self.nodes.write().mark_as_useless(id, "disabled by protocol handler").log_unknown(id, "disabled peer");
 					}
 				}
 				trace!(target: "network", "Disabling peer {}", peer);
@@ -1081,6 +2102,114 @@ impl IoHandler<NetworkIoMessage> for Host {
 	}
 }
 
+/// Returns the endpoint to record in the node table for an inbound session, if the session qualifies: it must have
+/// been ready for at least `INBOUND_RECORD_DELAY` and the peer must have advertised a listening port. The UDP port
+/// is not known until discovery verifies the node.
+fn inbound_record_endpoint(originated: bool, ready_duration: Option<Duration>, remote: Option<SocketAddr>, listen_port: u16) -> Option<NodeEndpoint> {
+	if originated || listen_port == 0 || ready_duration.map_or(true, |d| d < INBOUND_RECORD_DELAY) {
+		return None;
+	}
+	// the source port of the connection is ephemeral, use the one from hello instead.
+	remote.map(|address| NodeEndpoint { address: SocketAddr::new(address.ip(), listen_port), udp_port: 0 })
+}
+
+/// Returns the reserved peers and the nodes required for one of `protocols`, in the order of the table. They are
+/// dialled first and also in reserved-only mode. A reserved peer marked as useless, e.g. while it was restarting, is
+/// dialled all the same, and bans do not apply to reserved peers.
+fn required_peers(nodes: &NodeTable, reserved: &HashSet<NodeId>, protocols: &[ProtocolId]) -> Vec<NodeEntry> {
+	let options = EntryOptions { include_useless_required: true, include_banned: true };
+	nodes.entries_with(options).into_iter()
+		.filter(|e| reserved.contains(&e.id) || protocols.iter().any(|p| nodes.is_required_for(&e.id, p)))
+		.collect()
+}
+
+/// Record the end of an established session with `id` in the node table, `remote` if it was not ended by us. Each
+/// call ends one `TableState::session_opened`, also the one of a rejected duplicate connection.
+fn record_session_closed(nodes: &SharedNodeTable, id: &NodeId, remote: bool) {
+	nodes.try_session_closed(id);
+	nodes.try_note_disconnect(id, remote);
+}
+
+/// Connected optional peer considered for eviction.
+struct EvictionPeer {
+	token: StreamToken,
+	/// Node table score, lower is better.
+	score: usize,
+	/// Time since the session became ready.
+	age: Duration,
+}
+
+/// Returns the peer to evict in favour of a node table candidate with `candidate_score`, if any. The worst peer that
+/// has been connected for at least `MIN_EVICTION_SESSION_AGE` is evicted if its score is worse by at least `margin`
+/// and no other peer has been evicted within `EVICTION_INTERVAL`.
+fn select_eviction(peers: &[EvictionPeer], candidate_score: usize, margin: usize, since_last_eviction: Option<Duration>) -> Option<StreamToken> {
+	if since_last_eviction.map_or(false, |d| d < EVICTION_INTERVAL) {
+		return None;
+	}
+	peers.iter()
+		.filter(|p| p.age >= MIN_EVICTION_SESSION_AGE)
+		.max_by_key(|p| p.score)
+		.and_then(|p| if p.score >= candidate_score + margin { Some(p.token) } else { None })
+}
+
+/// Returns the devp2p version to announce in the Hello packet to the dialled node `id`: `ours`, or the highest one
+/// without compression if compression did not work with the node before, see `TableState::set_negotiated`. A node
+/// that upgraded meanwhile announces a higher version than recorded, which lifts the downgrade for the next session.
+fn hello_version(nodes: &NodeTable, id: Option<&NodeId>, ours: u32) -> u32 {
+	match id {
+		Some(id) if nodes.compression_failed(id) => min(ours, MIN_COMPRESSION_PROTOCOL_VERSION - 1),
+		_ => ours,
+	}
+}
+
+/// Node table selection mode matching the non-reserved peer mode.
+fn selection_mode(mode: &NonReservedPeerMode) -> SelectionMode {
+	match *mode {
+		NonReservedPeerMode::Accept => SelectionMode::Normal,
+		NonReservedPeerMode::Deny => SelectionMode::ReservedOnly,
+	}
+}
+
+/// Assembles the network health summary and computes its verdict.
+fn net_health_summary(node_table: NodeTableStats, discovery: Option<DiscoveryStats>, sessions: SessionStats, ip_filter: &IpFilter, min_peers: u32) -> NetHealth {
+	let verdict = if sessions.ingress + sessions.egress >= max(1, min_peers as usize / 2) {
+		NetHealthVerdict::Good
+	} else if node_table.outage {
+		NetHealthVerdict::Outage
+	} else if node_table.total > 0 && node_table.filtered == node_table.total {
+		NetHealthVerdict::AllFiltered
+	} else if discovery.as_ref().map_or(true, |d| d.nodes == 0) {
+		NetHealthVerdict::NoDiscovery
+	} else {
+		NetHealthVerdict::FewPeers
+	};
+	NetHealth {
+		verdict: verdict,
+		node_table: node_table,
+		discovery: discovery,
+		sessions: sessions,
+		ip_filter: ip_filter.to_string(),
+		metrics: None,
+		skipped_nodes: Vec::new(),
+		recent_evictions: Vec::new(),
+		advertised_endpoint: None,
+		dial_plan: None,
+		sibling_endpoints: Vec::new(),
+	}
+}
+
+#[cfg(feature = "metrics")]
+fn node_table_metrics(nodes: &NodeTable) -> Option<String> {
+	let mut metrics = String::new();
+	nodes.render_prometheus(&mut metrics);
+	Some(metrics)
+}
+
+#[cfg(not(feature = "metrics"))]
+fn node_table_metrics(_nodes: &NodeTable) -> Option<String> {
+	None
+}
+
 fn save_key(path: &Path, key: &Secret) {
 	let mut path_buf = PathBuf::from(path);
 	if let Err(e) = fs::create_dir_all(path_buf.as_path()) {
@@ -1104,6 +2233,20 @@ fn save_key(path: &Path, key: &Secret) {
 	}
 }
 
+/// Policy of the dial selection: prefer nodes running at least one of our protocols, but keep trying the ones we know
+/// nothing about.
+fn dial_policy(info: &HostInfo) -> DialPolicy {
+	DialPolicy {
+		required_capabilities: info.capabilities.iter().map(|c| (c.protocol, c.version)).collect(),
+		allow_unknown: true,
+		exclude_connected: info.config.node_table.exclude_connected,
+	}
+}
+
+fn millis(duration: Duration) -> u64 {
+	duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
 fn load_key(path: &Path) -> Option<Secret> {
 	let mut path_buf = PathBuf::from(path);
 	path_buf.push("key");
@@ -1131,6 +2274,66 @@ fn load_key(path: &Path) -> Option<Secret> {
 	}
 }
 
+/// File in the network configuration directory with the listen addresses of the instances sharing it, one per line.
+const INSTANCES_FILE: &str = "instances";
+
+fn read_instances(path: &Path) -> Vec<SocketAddr> {
+	let mut buf = String::new();
+	if let Err(e) = fs::File::open(path.join(INSTANCES_FILE)).and_then(|mut file| file.read_to_string(&mut buf)) {
+		if e.kind() != io::ErrorKind::NotFound {
+			warn!("Error reading instances file: {:?}", e);
+		}
+		return Vec::new();
+	}
+	buf.lines().filter_map(|line| line.trim().parse().ok()).collect()
+}
+
+fn write_instances(path: &Path, addresses: &[SocketAddr]) {
+	let contents: String = addresses.iter().map(|address| format!("{}\n", address)).collect();
+	let written = fs::create_dir_all(path)
+		.and_then(|_| fs::File::create(path.join(INSTANCES_FILE)))
+		.and_then(|mut file| file.write_all(contents.as_bytes()));
+	if let Err(e) = written {
+		warn!("Error writing instances file: {:?}", e);
+	}
+}
+
+/// Add `address` to the instances file in `path` and return the addresses registered by the other instances.
+/// Instances that did not stop cleanly stay registered until they are started again.
+fn register_instance(path: &Path, address: &SocketAddr) -> Vec<SocketAddr> {
+	let mut addresses = read_instances(path);
+	let others: Vec<SocketAddr> = addresses.iter().filter(|a| *a != address).cloned().collect();
+	if !addresses.contains(address) {
+		addresses.push(*address);
+		write_instances(path, &addresses);
+	}
+	others
+}
+
+/// Remove `address` from the instances file in `path`.
+fn unregister_instance(path: &Path, address: &SocketAddr) {
+	let mut addresses = read_instances(path);
+	if addresses.contains(address) {
+		addresses.retain(|a| a != address);
+		write_instances(path, &addresses);
+	}
+}
+
+#[test]
+fn instances_registered() {
+	use tempdir::TempDir;
+
+	let tempdir = TempDir::new("").unwrap();
+	let (first, second): (SocketAddr, SocketAddr) = ("0.0.0.0:30303".parse().unwrap(), "127.0.0.1:30304".parse().unwrap());
+	assert_eq!(register_instance(tempdir.path(), &first), vec![]);
+	assert_eq!(register_instance(tempdir.path(), &second), vec![first]);
+	// registered again after a crash
+	assert_eq!(register_instance(tempdir.path(), &first), vec![second]);
+	assert_eq!(read_instances(tempdir.path()), vec![first, second]);
+	unregister_instance(tempdir.path(), &first);
+	assert_eq!(register_instance(tempdir.path(), &second), vec![]);
+}
+
 #[test]
 fn key_save_load() {
 	use tempdir::TempDir;
@@ -1143,11 +2346,240 @@ fn key_save_load() {
 }
 
 
+#[test]
+fn inbound_session_recording() {
+	let remote = Some(SocketAddr::from_str("22.99.55.44:51234").unwrap());
+	let long_enough = Some(INBOUND_RECORD_DELAY + Duration::from_secs(1));
+
+	// disconnected right after handshake or not ready at all
+	assert!(inbound_record_endpoint(false, Some(Duration::from_secs(1)), remote, 30303).is_none());
+	assert!(inbound_record_endpoint(false, None, remote, 30303).is_none());
+	// outbound sessions are already in the table
+	assert!(inbound_record_endpoint(true, long_enough, remote, 30303).is_none());
+	// the peer does not accept connections
+	assert!(inbound_record_endpoint(false, long_enough, remote, 0).is_none());
+
+	let endpoint = inbound_record_endpoint(false, long_enough, remote, 30305).unwrap();
+	assert_eq!(endpoint.address, SocketAddr::from_str("22.99.55.44:30305").unwrap());
+	assert!(!endpoint.udp_known());
+	assert!(endpoint.is_valid_tcp() && !endpoint.is_valid());
+}
+
+#[test]
+fn scoped_reserved_peers_selected() {
+	let (eth, pip, other) = (*b"eth", *b"pip", *b"bbb");
+	let mut nodes = NodeTable::new(None);
+	for i in 1..6 {
+		nodes.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", 50 + i)).unwrap()));
+	}
+	nodes.set_peer_type_for(&NodeId::from(3), pip, PeerType::Required);
+	nodes.set_peer_type_for(&NodeId::from(4), other, PeerType::Required);
+	let reserved: HashSet<NodeId> = vec![NodeId::from(5)].into_iter().collect();
+	let policy = DialPolicy { required_capabilities: vec![(eth, 63), (pip, 1)], allow_unknown: true, ..Default::default() };
+	let ids = |entries: Vec<NodeEntry>| -> Vec<NodeId> { entries.into_iter().map(|e| e.id).collect() };
+
+	// the peers reserved for one of our protocols are dialled with the reserved ones, not the ones of other protocols
+	let mut required = ids(required_peers(&nodes, &reserved, &policy.protocols()));
+	required.sort();
+	assert_eq!(required, vec![NodeId::from(3), NodeId::from(5)]);
+
+	// they come first in the selection of the eviction candidate and the dial candidates
+	assert_eq!(nodes.select_for(&policy, &IpFilter::default(), 1, None), vec![NodeId::from(3)]);
+	let explained = nodes.explain_selection(&policy, &IpFilter::default(), 1);
+	assert_eq!((explained[0].0.id, explained[0].1), (NodeId::from(3), SelectionReason::Required));
+
+	// and they are the only ones selected in reserved-only mode
+	nodes.set_selection_mode(selection_mode(&NonReservedPeerMode::Deny));
+	assert_eq!(nodes.select_for(&policy, &IpFilter::default(), 10, None), vec![NodeId::from(3)]);
+	let mut stream = nodes.candidate_stream(policy, IpFilter::default());
+	assert_eq!(nodes.next_candidate(&mut stream).map(|(entry, _)| entry.id), Some(NodeId::from(3)));
+	assert!(nodes.next_candidate(&mut stream).is_none());
+}
+
+#[test]
+fn duplicate_session_keeps_connected() {
+	let (id, other) = (NodeId::from(1), NodeId::from(2));
+	let nodes = SharedNodeTable::new(NodeTable::new(None));
+	let remote = SocketAddr::from_str("22.99.55.44:51234").unwrap();
+	nodes.write().add_node(Node::new(id, NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+	nodes.write().add_node(Node::new(other, NodeEndpoint::from_str("22.99.55.44:7771").unwrap()));
+	let selected = |nodes: &SharedNodeTable| {
+		nodes.read().select_for(&DialPolicy::default(), &IpFilter::default(), 10, None)
+	};
+
+	// both connections get ready and are recorded, the second one is then rejected as a duplicate
+	nodes.write().session_opened(&id, &remote);
+	nodes.write().session_opened(&id, &remote);
+	record_session_closed(&nodes, &id, false);
+	assert_eq!(selected(&nodes), vec![]);
+	assert_eq!(nodes.read().stats(&IpFilter::default()).active_sessions, 1);
+
+	// the node and its IP are dialled again once the original session ends
+	record_session_closed(&nodes, &id, true);
+	assert_eq!(selected(&nodes).len(), 2);
+}
+
+#[test]
+fn peer_annotator_updates_node_table() {
+	use network::IpFilter;
+
+	struct FakeContext(HashMap<PeerId, NodeId>);
+
+	impl ResolveNodeId for FakeContext {
+		fn node_id(&self, peer: PeerId) -> Option<NodeId> {
+			self.0.get(&peer).cloned()
+		}
+	}
+
+	let id1 = NodeId::from(1);
+	let id2 = NodeId::from(2);
+	let nodes = SharedNodeTable::new(NodeTable::new(None));
+	for id in &[id1, id2] {
+		let endpoint = NodeEndpoint::from_str("22.99.55.44:7770").unwrap();
+		nodes.write().add_node(Node::new(id.clone(), endpoint));
+	}
+	let context = FakeContext(vec![(3, id1), (4, id2)].into_iter().collect());
+	let annotator = NodeAnnotator { resolver: &context, nodes: &nodes };
+
+	annotator.report_useful(3);
+	annotator.report_latency(3, Duration::from_millis(200));
+	annotator.report_misbehaviour(4, Severity::Major);
+	annotator.tag(4, "pip_served_ms", 120);
+	let chain = PeerChainInfo { network_id: 1, genesis: 5.into(), fork_hash: None };
+	annotator.set_chain_info(4, chain);
+	// unknown peers are ignored
+	annotator.report_misbehaviour(5, Severity::Critical);
+	annotator.tag(5, "pip_served_ms", 120);
+	annotator.set_chain_info(5, chain);
+
+	{
+		let nodes = nodes.read();
+		assert_eq!(nodes.get_tag(&id2, "pip_served_ms"), Some(120));
+		assert_eq!(nodes.get(&id2).unwrap().chain_info(), Some(&chain));
+		assert_eq!(nodes.get(&id1).unwrap().successes, 1);
+		assert_eq!(nodes.get(&id1).unwrap().latency, Some(Duration::from_millis(200)));
+		assert_eq!(nodes.get(&id2).unwrap().failures, 3);
+		assert_eq!(nodes.nodes(&IpFilter::default()), vec![id1, id2]);
+	}
+
+	annotator.report_misbehaviour(3, Severity::Critical);
+	assert!(nodes.read().is_banned(&id1));
+	assert_eq!(nodes.read().nodes(&IpFilter::default()), vec![id2]);
+}
+
+#[test]
+fn hello_version_preselection() {
+	use session::negotiate;
+
+	let id = NodeId::from(1);
+	let mut nodes = NodeTable::new(None);
+	nodes.add_node(Node::new(id, NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+	// dial a peer announcing `remote` and record the session like the host does, returns whether it compressed
+	let handshake = |nodes: &mut NodeTable, remote: u32, compression_works: bool| {
+		let (_, compression) = negotiate(hello_version(nodes, Some(&id), PROTOCOL_VERSION), remote);
+		nodes.set_negotiated(&id, remote, compression);
+		if compression && !compression_works {
+			nodes.set_negotiated(&id, remote, false);
+		}
+		compression
+	};
+
+	// inbound sessions and unknown nodes get our version
+	assert_eq!(hello_version(&nodes, None, PROTOCOL_VERSION), PROTOCOL_VERSION);
+	assert_eq!(hello_version(&nodes, Some(&NodeId::from(2)), PROTOCOL_VERSION), PROTOCOL_VERSION);
+	assert_eq!(hello_version(&nodes, Some(&id), PROTOCOL_VERSION), PROTOCOL_VERSION);
+
+	// a peer announcing compression that does not get it right is not compressed again
+	assert!(handshake(&mut nodes, 5, false));
+	assert_eq!(hello_version(&nodes, Some(&id), PROTOCOL_VERSION), MIN_COMPRESSION_PROTOCOL_VERSION - 1);
+	assert!(!handshake(&mut nodes, 5, false));
+	assert!(!handshake(&mut nodes, 5, true));
+
+	// an older peer that upgraded gets compression on the session after the one it announced the new version in
+	assert!(!handshake(&mut nodes, 4, true));
+	assert!(!handshake(&mut nodes, 5, true));
+	assert_eq!(hello_version(&nodes, Some(&id), PROTOCOL_VERSION), PROTOCOL_VERSION);
+	assert!(handshake(&mut nodes, 5, true));
+	assert_eq!((nodes.get(&id).unwrap().p2p_version, nodes.get(&id).unwrap().snappy), (Some(5), Some(true)));
+}
+
+#[test]
+fn peer_eviction() {
+	let age = MIN_EVICTION_SESSION_AGE;
+	let peers = vec![
+		EvictionPeer { token: 1, score: 10, age: age },
+		EvictionPeer { token: 2, score: 60, age: age },
+		EvictionPeer { token: 3, score: 40, age: age },
+		// worst, but too young
+		EvictionPeer { token: 4, score: 100, age: Duration::from_secs(10) },
+	];
+
+	assert_eq!(select_eviction(&peers, 0, 25, None), Some(2));
+	assert_eq!(select_eviction(&peers, 35, 25, None), Some(2));
+	// candidate not significantly better
+	assert_eq!(select_eviction(&peers, 36, 25, None), None);
+	// another peer was evicted recently
+	assert_eq!(select_eviction(&peers, 0, 25, Some(EVICTION_INTERVAL / 2)), None);
+	assert_eq!(select_eviction(&peers, 0, 25, Some(EVICTION_INTERVAL)), Some(2));
+	assert_eq!(select_eviction(&[], 0, 25, None), None);
+}
+
+#[test]
+fn net_health_verdict() {
+	let filter = IpFilter::default();
+	let table = NodeTableStats { total: 10, ..Default::default() };
+	let discovery = DiscoveryStats { nodes: 50, buckets: 5, pinging: 0 };
+	let peers = |n| SessionStats { egress: n, ..Default::default() };
+
+	let health = net_health_summary(table.clone(), Some(discovery.clone()), peers(13), &filter, 25);
+	assert_eq!(health.verdict, NetHealthVerdict::Good);
+	assert_eq!(health.ip_filter, "all");
+	assert_eq!(net_health_summary(table.clone(), Some(discovery.clone()), peers(0), &filter, 0).verdict, NetHealthVerdict::FewPeers);
+	assert_eq!(net_health_summary(table.clone(), Some(discovery.clone()), peers(11), &filter, 25).verdict, NetHealthVerdict::FewPeers);
+	assert_eq!(net_health_summary(table.clone(), None, peers(1), &filter, 25).verdict, NetHealthVerdict::NoDiscovery);
+	assert_eq!(net_health_summary(NodeTableStats::default(), Some(DiscoveryStats::default()), peers(0), &filter, 25).verdict, NetHealthVerdict::NoDiscovery);
+
+	let filter = IpFilter::parse("public -10.0.0.0/8").unwrap();
+	let table = NodeTableStats { total: 10, filtered: 10, ..Default::default() };
+	let health = net_health_summary(table, Some(discovery), peers(0), &filter, 25);
+	assert_eq!(health.verdict, NetHealthVerdict::AllFiltered);
+	assert_eq!(health.ip_filter, "public -10.0.0.0/8");
+	assert_eq!(IpFilter::parse(&health.ip_filter).unwrap(), filter);
+
+	let table = NodeTableStats { total: 10, outage: true, ..Default::default() };
+	assert_eq!(net_health_summary(table.clone(), None, peers(0), &filter, 25).verdict, NetHealthVerdict::Outage);
+	assert_eq!(net_health_summary(table, None, peers(13), &filter, 25).verdict, NetHealthVerdict::Good);
+}
+
+#[test]
+fn dial_failures_outage() {
+	let start = Instant::now();
+	let subnet = |i: u8| IpAddr::V4(Ipv4Addr::new(22, 99, i, 0));
+	let window = Duration::from_secs(30);
+	let mut failures = DialFailures::default();
+
+	// repeated failures in the same subnets are not enough
+	for i in 0..10 {
+		assert!(!failures.note_failure(start, subnet(i % 2), 3, window));
+	}
+	assert!(failures.note_failure(start, subnet(2), 3, window));
+
+	// failures spread wider than the window or interrupted by a success are not either
+	assert!(!failures.note_failure(start, subnet(1), 3, window));
+	assert!(!failures.note_failure(start + Duration::from_secs(20), subnet(2), 3, window));
+	assert!(!failures.note_failure(start + Duration::from_secs(40), subnet(3), 3, window));
+	failures.note_success();
+	assert!(!failures.note_failure(start + Duration::from_secs(41), subnet(4), 3, window));
+	assert!(!failures.note_failure(start + Duration::from_secs(42), subnet(5), 3, window));
+	assert!(failures.note_failure(start + Duration::from_secs(43), subnet(6), 3, window));
+	assert!(!failures.note_failure(start, subnet(7), 0, window));
+}
+
 #[test]
 fn host_client_url() {
 	let mut config = NetworkConfiguration::new_local();
 	let key = "6f7b0d801bc7b5ce7bbd930b84fd0369b3eb25d09be58d64ba811091046f3aa2".parse().unwrap();
 	config.use_secret = Some(key);
-	let host: Host = Host::new(config, None).unwrap();
+	let host: Host = Host::new(config, None, None).unwrap();
 	assert!(host.local_url().starts_with("enode://101b3ef5a4ea7a1c7928e24c4c75fd053c235d7b80c22ae5c03d145d0ac7396e2a4ffff9adee3133a7b05044a5cee08115fd65145e5165d646bde371010d803c@"));
 }
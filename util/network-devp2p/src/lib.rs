@@ -92,8 +92,13 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+#[cfg(feature = "kvdb-persistence")]
+extern crate kvdb;
+
 #[cfg(test)]
 extern crate tempdir;
+#[cfg(all(test, feature = "kvdb-persistence"))]
+extern crate kvdb_memorydb;
 
 mod host;
 mod connection;
@@ -102,14 +107,45 @@ mod session;
 mod discovery;
 mod service;
 mod node_table;
+mod persistence;
+mod shared_table;
+mod clock;
 mod ip_utils;
 mod connection_filter;
+mod table_commands;
+mod outcome_history;
+mod telemetry;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod simulator;
+#[cfg(any(test, feature = "test-helpers"))]
+mod mock_discovery;
 
 pub use service::NetworkService;
 pub use connection_filter::{ConnectionFilter, ConnectionDirection};
-pub use host::NetworkContext;
+pub use host::{NetworkContext, NetHealth, NetHealthVerdict, SessionStats, AdvertisedEndpoint, DialPlan, DialCandidate};
+pub use discovery::DiscoveryStats;
+pub use outcome_history::{OutcomeBucket, HISTORY_HOURS};
+pub use telemetry::{TableTelemetry, TelemetryCallback, no_telemetry};
+pub use persistence::{NodeTablePersistence, TableSink, PersistedNode, PersistedChain, PersistedExclusion, PersistedTable, FilePersistence, MemoryPersistence};
+#[cfg(feature = "kvdb-persistence")]
+pub use persistence::{KeyValueDBPersistence, EXCLUSIONS_KEY};
+#[cfg(feature = "test-helpers")]
+pub use node_table::{NodeTable, TableState, FixtureNode, MembershipHandle};
+#[cfg(feature = "test-helpers")]
+pub use clock::{Clock, ManualClock};
+#[cfg(feature = "test-helpers")]
+pub use mock_discovery::{MockDiscovery, assert_known, assert_unknown, assert_endpoint};
 
 pub use io::TimerToken;
-pub use node_table::{validate_node_url, NodeId};
+pub use node_table::{validate_node_url, normalize_node_url, parse_node_id, MAX_ENODE_LEN, NodeId, NodeSource, NodeTableEntry, NodeTableStats, ExclusionKind, MetaLimits, META_LIMITS};
+pub use node_table::{LoadReport, SkippedNode, SkipReason, DropReason, Eviction, EvictionReason, Outcome, NoteResult, UpdateOutcome, RejectReason};
+pub use node_table::{MaintenanceReport, maintenance_delay};
+pub use node_table::{Node, NodeBuilder, NodeEndpoint, NodeSources, PeerType, Admission, AdmissionHook, InboundMatch, NodeParseError, parse_bootnodes, SelectionReason};
+pub use node_table::{DialPolicy, CandidateStream};
+pub use node_table::{ListFormat, ListEntryResult, IngestStats, MAX_LIST_LINES, MAX_LIST_LINE_BYTES};
+pub use node_table::{StaticPeersUpdate, parse_static_peers, FreezeGuard, MAX_FREEZE, EntryStatus, DialExclusion};
+pub use node_table::{ShortId, NodeIdExt, PrefixLookupError};
 
 const PROTOCOL_VERSION: u32 = 5;
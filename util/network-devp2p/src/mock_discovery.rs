@@ -0,0 +1,226 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Scripted discovery for testing how the node table handles discovery updates.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use discovery::{TableUpdates, NodeEntry};
+use node_table::{TableState, NodeEndpoint, NodeId, UpdateOutcome};
+
+/// Stands in for the discovery protocol. Nodes are announced as heard of in neighbours packets, confirmed when they
+/// answer a ping and expired when they drop out of the buckets. Changes are collected and handed to the table as
+/// a single update by `flush`, like discovery does after each round.
+#[derive(Default)]
+pub struct MockDiscovery {
+	known: HashMap<NodeId, NodeEndpoint>,
+	added: HashMap<NodeId, NodeEntry>,
+	verified: HashSet<NodeId>,
+	removed: HashSet<NodeId>,
+}
+
+impl MockDiscovery {
+	/// Create a discovery that knows no nodes.
+	pub fn new() -> Self {
+		MockDiscovery::default()
+	}
+
+	/// Announce a node at `address` without verifying it. A known node announced at a new address reports the new
+	/// endpoint.
+	pub fn announce(&mut self, id: NodeId, address: &str) -> &mut Self {
+		let endpoint = NodeEndpoint::from_str(address).expect("invalid mock discovery address");
+		self.known.insert(id, endpoint.clone());
+		self.removed.remove(&id);
+		self.added.insert(id, NodeEntry::new(id, endpoint));
+		self
+	}
+
+	/// Announce `count` nodes with consecutive ids starting at `first`, each in its own subnet.
+	pub fn announce_range(&mut self, first: u64, count: u64) -> &mut Self {
+		for i in first..first + count {
+			self.announce(NodeId::from(i), &format!("22.{}.{}.1:30303", i / 256 % 256, i % 256));
+		}
+		self
+	}
+
+	/// Confirm a node at the endpoint it was last announced at, as if it answered a ping.
+	pub fn confirm(&mut self, id: NodeId) -> &mut Self {
+		let endpoint = self.known.get(&id).cloned().expect("confirmed node was never announced");
+		self.added.insert(id, NodeEntry::new(id, endpoint));
+		self.verified.insert(id);
+		self
+	}
+
+	/// Drop a node from the buckets, e.g. after it stopped answering pings.
+	pub fn expire(&mut self, id: NodeId) -> &mut Self {
+		self.known.remove(&id);
+		self.added.remove(&id);
+		self.verified.remove(&id);
+		self.removed.insert(id);
+		self
+	}
+
+	/// Apply the collected changes to `table` as a single update. No nodes are reserved.
+	pub fn flush(&mut self, table: &mut TableState) -> UpdateOutcome {
+		self.flush_with_reserved(table, &HashSet::new())
+	}
+
+	/// Like `flush`, but keep the `reserved` nodes from being removed or evicted.
+	pub fn flush_with_reserved(&mut self, table: &mut TableState, reserved: &HashSet<NodeId>) -> UpdateOutcome {
+		let updates = TableUpdates {
+			added: ::std::mem::replace(&mut self.added, HashMap::new()),
+			removed: ::std::mem::replace(&mut self.removed, HashSet::new()),
+			verified: ::std::mem::replace(&mut self.verified, HashSet::new()),
+		};
+		table.update(updates, reserved)
+	}
+}
+
+/// Assert that all of `ids` are in `table`.
+pub fn assert_known(table: &TableState, ids: &[NodeId]) {
+	for id in ids {
+		assert!(table.contains(id), "node {} is not in the table", id);
+	}
+}
+
+/// Assert that none of `ids` is in `table`.
+pub fn assert_unknown(table: &TableState, ids: &[NodeId]) {
+	for id in ids {
+		assert!(!table.contains(id), "node {} is in the table", id);
+	}
+}
+
+/// Assert the address of a node and the address discovery reported but that was not accepted yet, if any.
+pub fn assert_endpoint(table: &TableState, id: &NodeId, address: &str, candidate: Option<&str>) {
+	let entry = table.entries_with_meta(usize::max_value(), true).into_iter()
+		.find(|e| e.id == *id)
+		.unwrap_or_else(|| panic!("node {} is not in the table", id));
+	assert_eq!(entry.address.to_string(), address, "address of node {}", id);
+	assert_eq!(entry.candidate_address.map(|a| a.to_string()), candidate.map(Into::into), "candidate address of node {}", id);
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use network::NodeTableConfig;
+	use super::*;
+
+	fn ids(range: ::std::ops::Range<u64>) -> Vec<NodeId> {
+		range.map(NodeId::from).collect()
+	}
+
+	#[test]
+	fn announced_and_confirmed_nodes_are_added() {
+		let mut table = TableState::from_fixture(&[]);
+		let mut discovery = MockDiscovery::new();
+		discovery.announce_range(1, 20);
+		for i in 1..6 {
+			discovery.confirm(NodeId::from(i));
+		}
+		discovery.flush(&mut table);
+		assert_known(&table, &ids(1..21));
+
+		discovery.expire(NodeId::from(20)).flush(&mut table);
+		assert_unknown(&table, &ids(20..21));
+		assert_known(&table, &ids(1..20));
+	}
+
+	#[test]
+	fn endpoint_change_needs_verification() {
+		let mut table = TableState::from_fixture(&[]);
+		let mut discovery = MockDiscovery::new();
+		let id = NodeId::from(1);
+		discovery.announce(id, "22.99.55.44:30303").flush(&mut table);
+
+		// heard of at a new endpoint: kept as a candidate only
+		discovery.announce(id, "22.99.55.45:30303").flush(&mut table);
+		assert_endpoint(&table, &id, "22.99.55.44:30303", Some("22.99.55.45:30303"));
+
+		// the new endpoint answered a ping: moved
+		discovery.confirm(id).flush(&mut table);
+		assert_endpoint(&table, &id, "22.99.55.45:30303", None);
+
+		// heard of at the old endpoint again while the current one keeps failing: moved without verification
+		for _ in 0..3 {
+			table.note_failure(&id);
+		}
+		discovery.announce(id, "22.99.55.44:30303").flush(&mut table);
+		assert_endpoint(&table, &id, "22.99.55.44:30303", None);
+	}
+
+	#[test]
+	fn verified_endpoint_reveals_rotated_id() {
+		let mut table = TableState::from_fixture(&[]);
+		let mut discovery = MockDiscovery::new();
+		discovery.announce(NodeId::from(1), "22.99.55.44:30303").flush(&mut table);
+		table.note_success(&NodeId::from(1));
+
+		// once verified, another id at the same endpoint takes over the entry
+		discovery.announce(NodeId::from(2), "22.99.55.44:30303").confirm(NodeId::from(2)).flush(&mut table);
+		assert_known(&table, &ids(2..3));
+		assert_unknown(&table, &ids(1..2));
+		assert!(table.entries_with_meta(1, true)[0].last_contact.is_some());
+
+		// an unverified one is only a hint, several ids may share an endpoint
+		discovery.announce(NodeId::from(3), "22.99.55.44:30303").flush(&mut table);
+		assert_known(&table, &ids(2..4));
+	}
+
+	#[test]
+	fn unverified_announcements_stay_bounded() {
+		let config = NodeTableConfig { max_nodes: 32, ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[], config, &::clock::ManualClock::new());
+		let mut discovery = MockDiscovery::new();
+		let id = NodeId::from(1);
+		discovery.announce(id, "22.99.55.44:30303").confirm(id).flush(&mut table);
+
+		// a flood of heard of nodes is truncated and does not grow the table past its cap
+		let stats = discovery.announce_range(2, 1000).flush(&mut table);
+		assert_eq!((stats.added, stats.rejected.len(), stats.truncated_added), (31, 225, 744));
+		assert_eq!(table.stats(&::network::IpFilter::default()).total, 32);
+
+		// unverified endpoints of a known node replace each other, only the latest is kept
+		for i in 1..100 {
+			discovery.announce(id, &format!("22.98.{}.1:30303", i)).flush(&mut table);
+		}
+		assert_endpoint(&table, &id, "22.99.55.44:30303", Some("22.98.99.1:30303"));
+	}
+
+	#[test]
+	fn subnet_limit_caps_announcements() {
+		let config = NodeTableConfig { max_nodes_per_subnet: Some(3), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[], config, &::clock::ManualClock::new());
+		let mut discovery = MockDiscovery::new();
+		for i in 1..11 {
+			discovery.announce(NodeId::from(i), &format!("22.99.55.{}:30303", i));
+		}
+		discovery.announce_range(11, 5).flush(&mut table);
+		assert_eq!(ids(1..11).iter().filter(|id| table.contains(id)).count(), 3);
+		assert_known(&table, &ids(11..16));
+	}
+
+	#[test]
+	fn removed_and_banned_nodes_are_not_added_again() {
+		let mut table = TableState::from_fixture(&[]);
+		let mut discovery = MockDiscovery::new();
+		discovery.announce_range(1, 1).flush(&mut table);
+		table.remove_node(&NodeId::from(1));
+		table.ban(&NodeId::from(2), Duration::from_secs(60), "test");
+
+		discovery.announce_range(1, 2).confirm(NodeId::from(1)).confirm(NodeId::from(2)).flush(&mut table);
+		assert_unknown(&table, &ids(1..3));
+	}
+}
@@ -14,24 +14,103 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::{HashMap, HashSet};
+use std::cmp::{max, min, Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{self, Display, Formatter};
 use std::hash::{Hash, Hasher};
-use std::net::{SocketAddr, ToSocketAddrs, SocketAddrV4, SocketAddrV6, Ipv4Addr, Ipv6Addr};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::net::{SocketAddr, ToSocketAddrs, SocketAddrV4, SocketAddrV6, IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
-use std::{fs, mem, slice};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::{mem, thread};
 use ethereum_types::H512;
+use ethkey::public_is_valid;
+use rand::{self, Rng};
 use rlp::{UntrustedRlp, RlpStream, DecoderError};
-use network::{Error, ErrorKind, AllowIP, IpFilter};
+use network::{Error, ErrorKind, AllowIP, IpFilter, NodeTableConfig, PeerCapabilityInfo, PeerChainInfo, ProtocolId, Severity};
 use discovery::{TableUpdates, NodeEntry};
 use ip_utils::*;
-use serde_json;
+use persistence::{held_elsewhere, NodeTablePersistence, PersistedNode, PersistedChain, PersistedExclusion, PersistedTable, TableSink};
+use outcome_history::{OutcomeHistory, OutcomeBucket, HistoryEvent, HISTORY_HOURS};
+use telemetry::{TableTelemetry, FAILURE_RANGES};
+use clock::{Clock, SystemClock};
+use ansi_term::Colour;
+use hash::keccak;
+use parking_lot::{Mutex, MutexGuard, RwLock};
+#[cfg(any(test, feature = "test-helpers"))]
+use clock::ManualClock;
 
 /// Node public key
 pub type NodeId = H512;
 
-#[derive(Debug, Clone)]
+/// Displays the first 6 and the last 4 hex characters of a node id, e.g. `a979fb…163c`, for logs, listings and
+/// debug output. See `NodeIdExt::short`.
+pub struct ShortId<'a>(pub &'a NodeId);
+
+impl<'a> Display for ShortId<'a> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		for b in self.0.iter().take(3) {
+			write!(f, "{:02x}", b)?;
+		}
+		write!(f, "…")?;
+		for b in self.0.iter().skip(62) {
+			write!(f, "{:02x}", b)?;
+		}
+		Ok(())
+	}
+}
+
+impl<'a> fmt::Debug for ShortId<'a> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		Display::fmt(self, f)
+	}
+}
+
+/// Short forms of node ids, so that logs, table listings and RPC output abbreviate them the same way.
+pub trait NodeIdExt {
+	/// The id for display, see `ShortId`.
+	fn short<'a>(&'a self) -> ShortId<'a>;
+	/// Whether the hex form of the id starts with `prefix`, given in any case and with or without `0x`. An empty
+	/// prefix or one with other characters matches no id.
+	fn matches_prefix(&self, prefix: &str) -> bool;
+}
+
+impl NodeIdExt for NodeId {
+	fn short<'a>(&'a self) -> ShortId<'a> {
+		ShortId(self)
+	}
+
+	fn matches_prefix(&self, prefix: &str) -> bool {
+		let prefix = if prefix.starts_with("0x") { &prefix[2..] } else { prefix };
+		if prefix.is_empty() || prefix.len() > 128 || !prefix.chars().all(|c| c.is_digit(16)) {
+			return false;
+		}
+		format!("{:x}", self).starts_with(&prefix.to_lowercase())
+	}
+}
+
+/// Why `TableState::find_by_prefix` found no single node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefixLookupError {
+	/// No node id in the table starts with the prefix.
+	NotFound,
+	/// The given number of node ids start with the prefix.
+	Ambiguous(usize),
+}
+
+impl Display for PrefixLookupError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match *self {
+			PrefixLookupError::NotFound => write!(f, "No node id in the table starts with the prefix"),
+			PrefixLookupError::Ambiguous(count) => write!(f, "{} node ids in the table start with the prefix", count),
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Eq)]
 /// Node address info
 pub struct NodeEndpoint {
 	/// IP(V4 or V6) address
@@ -40,7 +119,22 @@ pub struct NodeEndpoint {
 	pub udp_port: u16
 }
 
+impl fmt::Debug for NodeEndpoint {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "{}", self.address)?;
+		if self.udp_port != self.address.port() {
+			write!(f, " (udp {})", self.udp_port)?;
+		}
+		Ok(())
+	}
+}
+
 impl NodeEndpoint {
+	/// Whether `addr` is on the same host, ignoring the port. IPv4-mapped IPv6 addresses match their IPv4 form.
+	pub fn same_host(&self, addr: &SocketAddr) -> bool {
+		canonical_address(self.address).ip() == canonical_address(*addr).ip()
+	}
+
 	pub fn udp_address(&self) -> SocketAddr {
 		match self.address {
 			SocketAddr::V4(a) => SocketAddr::V4(SocketAddrV4::new(a.ip().clone(), self.udp_port)),
@@ -49,12 +143,26 @@ impl NodeEndpoint {
 	}
 
 	pub fn is_allowed(&self, filter: &IpFilter) -> bool {
-		(self.is_allowed_by_predefined(&filter.predefined) || filter.custom_allow.iter().any(|ipnet| {
-			self.address.ip().is_within(ipnet)
-		}))
-		&& !filter.custom_block.iter().any(|ipnet| {
-			self.address.ip().is_within(ipnet)
-		})
+		self.blocking_rule(filter).is_none()
+	}
+
+	/// Returns the `--allow-ips` rule that keeps the address out, e.g. `public` or `-10.0.0.0/8`, `None` if the
+	/// address is allowed.
+	pub fn blocking_rule(&self, filter: &IpFilter) -> Option<String> {
+		let ip = self.address.ip();
+		if let Some(ipnet) = filter.custom_block.iter().find(|ipnet| ip.is_within(ipnet)) {
+			return Some(format!("-{}", ipnet));
+		}
+		if self.is_allowed_by_predefined(&filter.predefined) || filter.custom_allow.iter().any(|ipnet| ip.is_within(ipnet)) {
+			return None;
+		}
+		let rule = match filter.predefined {
+			AllowIP::All => "all",
+			AllowIP::Private => "private",
+			AllowIP::Public => "public",
+			AllowIP::None => "none",
+		};
+		Some(rule.into())
 	}
 
 	pub fn is_allowed_by_predefined(&self, filter: &AllowIP) -> bool {
@@ -66,16 +174,26 @@ impl NodeEndpoint {
 		}
 	}
 
+	/// Decode an endpoint like `from_rlp`, but reject a zero TCP or UDP port, e.g. for the endpoints announced in
+	/// neighbours packets that are meant to be dialled.
+	pub fn from_rlp_strict(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+		let endpoint = NodeEndpoint::from_rlp(rlp)?;
+		if endpoint.address.port() == 0 || endpoint.udp_port == 0 {
+			return Err(DecoderError::Custom("zero port"));
+		}
+		Ok(endpoint)
+	}
+
 	pub fn from_rlp(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
 		let tcp_port = rlp.val_at::<u16>(2)?;
 		let udp_port = rlp.val_at::<u16>(1)?;
 		let addr_bytes = rlp.at(0)?.data()?;
 		let address = match addr_bytes.len() {
 			4 => Ok(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(addr_bytes[0], addr_bytes[1], addr_bytes[2], addr_bytes[3]), tcp_port))),
-			16 => unsafe {
-				let o: *const u16 = mem::transmute(addr_bytes.as_ptr());
-				let o = slice::from_raw_parts(o, 8);
-				Ok(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::new(o[0], o[1], o[2], o[3], o[4], o[5], o[6], o[7]), tcp_port, 0, 0)))
+			16 => {
+				let mut octets = [0u8; 16];
+				octets.copy_from_slice(addr_bytes);
+				Ok(canonical_address(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(octets), tcp_port, 0, 0))))
 			},
 			_ => Err(DecoderError::RlpInconsistentLengthAndData)
 		}?;
@@ -87,9 +205,8 @@ impl NodeEndpoint {
 			SocketAddr::V4(a) => {
 				rlp.append(&(&a.ip().octets()[..]));
 			}
-			SocketAddr::V6(a) => unsafe {
-				let o: *const u8 = mem::transmute(a.ip().segments().as_ptr());
-				rlp.append(&slice::from_raw_parts(o, 16));
+			SocketAddr::V6(a) => {
+				rlp.append(&(&a.ip().octets()[..]));
 			}
 		};
 		rlp.append(&self.udp_port);
@@ -101,26 +218,56 @@ impl NodeEndpoint {
 		self.to_rlp(rlp);
 	}
 
-	/// Validates that the port is not 0 and address IP is specified
+	/// Validates that the ports are not 0 and address IP is specified
 	pub fn is_valid(&self) -> bool {
-		self.udp_port != 0 && self.address.port() != 0 &&
+		self.udp_known() && self.is_valid_tcp()
+	}
+
+	/// Validates that the TCP port is not 0 and address IP is specified. The UDP port may be unknown.
+	pub fn is_valid_tcp(&self) -> bool {
+		self.address.port() != 0 &&
 		match self.address {
 			SocketAddr::V4(a) => !a.ip().is_unspecified(),
 			SocketAddr::V6(a) => !a.ip().is_unspecified()
 		}
 	}
+
+	/// Check if the UDP port is known. Nodes learned from inbound sessions only have a TCP port until discovery
+	/// verifies them, such endpoints have a UDP port of 0 and are written with `?discport=0`.
+	pub fn udp_known(&self) -> bool {
+		self.udp_port != 0
+	}
 }
 
 impl FromStr for NodeEndpoint {
 	type Err = Error;
 
-	/// Create endpoint from string. Performs name resolution if given a host name.
+	/// Create endpoint from string. Performs name resolution if given a host name. A distinct discovery port may be
+	/// given with a `?discport=` suffix.
 	fn from_str(s: &str) -> Result<NodeEndpoint, Error> {
-		let address = s.to_socket_addrs().map(|mut i| i.next());
+		NodeEndpoint::parse(s, true)
+	}
+}
+
+impl NodeEndpoint {
+	/// Like `from_str`, but host names are only resolved if `resolve` is set.
+	pub fn parse(s: &str, resolve: bool) -> Result<NodeEndpoint, Error> {
+		let (s, udp_port) = match s.find("?discport=") {
+			Some(pos) => (&s[..pos], Some(s[pos + 10..].parse::<u16>().map_err(|_| ErrorKind::AddressResolve(None))?)),
+			None => (s, None),
+		};
+		if !resolve {
+			let address = canonical_address(s.parse::<SocketAddr>().map_err(|_| ErrorKind::AddressResolve(None))?);
+			return Ok(NodeEndpoint {
+				address: address,
+				udp_port: udp_port.unwrap_or(address.port()),
+			});
+		}
+		let address = s.to_socket_addrs().map(|mut i| i.next().map(canonical_address));
 		match address {
 			Ok(Some(a)) => Ok(NodeEndpoint {
 				address: a,
-				udp_port: a.port()
+				udp_port: udp_port.unwrap_or(a.port())
 			}),
 			Ok(_) => Err(ErrorKind::AddressResolve(None).into()),
 			Err(e) => Err(ErrorKind::AddressResolve(Some(e)).into())
@@ -128,21 +275,350 @@ impl FromStr for NodeEndpoint {
 	}
 }
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+/// Convert IPv4-mapped IPv6 addresses to IPv4, other clients write them in IPv4 form.
+fn canonical_address(address: SocketAddr) -> SocketAddr {
+	match address {
+		SocketAddr::V6(a) => match a.ip().segments() {
+			[0, 0, 0, 0, 0, 0xffff, _, _] => {
+				let o = a.ip().octets();
+				SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(o[12], o[13], o[14], o[15]), a.port()))
+			},
+			_ => address,
+		},
+		_ => address,
+	}
+}
+
+/// How a node is dialled.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum PeerType {
-	_Required,
+	/// Reserved peer, dialled before all other nodes for every protocol and never dropped from the table.
+	Required,
+	/// Dialled by quality as slots are free.
 	Optional
 }
 
+#[allow(non_upper_case_globals)]
+impl PeerType {
+	/// Former name of `PeerType::Required`.
+	#[deprecated(note = "use PeerType::Required")]
+	pub const _Required: PeerType = PeerType::Required;
+}
+
+/// Which node table entries are handed out for dialing and discovery.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SelectionMode {
+	/// All entries.
+	Normal,
+	/// Only entries of required peers. Other nodes are still recorded.
+	ReservedOnly,
+}
+
+/// Where a node table entry was learned from.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum NodeSource {
+	/// Unknown, e.g. loaded from a node table file that did not record sources.
+	Unknown,
+	/// Boot node from the chain specification or command line.
+	Boot,
+	/// Reserved node.
+	Reserved,
+	/// Node discovery.
+	Discovery,
+	/// Remote node that connected to us.
+	Inbound,
+	/// Added over RPC.
+	Rpc,
+	/// Read from a node list, see `NodeTable::ingest_list`.
+	Imported,
+	/// Read from the static peers file, see `TableState::apply_static_peers`.
+	StaticFile,
+}
+
+impl Default for NodeSource {
+	fn default() -> Self {
+		NodeSource::Unknown
+	}
+}
+
+/// How a node is excluded from dialing.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum ExclusionKind {
+	/// Banned for a while.
+	#[serde(rename="ban")]
+	Ban,
+	/// Marked as useless until the next refresh of the table.
+	#[serde(rename="useless")]
+	Useless,
+}
+
+/// Set of all sources a node was learned from.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct NodeSources(u8);
+
+impl NodeSources {
+	/// Add a source to the set.
+	pub fn insert(&mut self, source: NodeSource) {
+		self.0 |= 1 << source as u8;
+	}
+
+	/// Check if the set contains a source.
+	pub fn contains(&self, source: NodeSource) -> bool {
+		self.0 & (1 << source as u8) != 0
+	}
+
+	/// Add all sources of another set.
+	pub fn extend(&mut self, other: NodeSources) {
+		self.0 |= other.0;
+	}
+
+	/// Sources in the set, in declaration order.
+	pub fn to_vec(&self) -> Vec<NodeSource> {
+		[NodeSource::Unknown, NodeSource::Boot, NodeSource::Reserved, NodeSource::Discovery, NodeSource::Inbound, NodeSource::Rpc,
+			NodeSource::Imported, NodeSource::StaticFile].iter().cloned().filter(|s| self.contains(*s)).collect()
+	}
+}
+
+/// How a node being added relates to the known ones, see `NodeTable::identity_change`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum IdentityChange {
+	/// Known id at its known endpoint.
+	Same,
+	/// Known id at a different endpoint, the previous one is given.
+	Moved(NodeEndpoint),
+	/// Unknown id at the endpoint of the given known node, e.g. after a key rotation.
+	Rotated(NodeId),
+	/// Unknown id at an unknown endpoint.
+	New,
+}
+
+/// Startup and operator paths that put nodes into the table, see `NodeTable::ingest`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum NodeOrigin {
+	/// Boot node from the chain specification or command line.
+	Boot,
+	/// Reserved peer from the command line or RPC.
+	Reserved,
+	/// Trusted node from `NodeTableConfig::trusted_nodes`.
+	Trusted,
+	/// Entry of the saved node table file.
+	Saved,
+}
+
+/// Peer information learned during a session handshake.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionMeta {
+	/// Client version string.
+	pub client_version: String,
+	/// Advertised capabilities.
+	pub capabilities: Vec<PeerCapabilityInfo>,
+}
+
+/// Node table entry. Nodes are built with `Node::new` or `Node::builder`, the private fields are kept up by the
+/// table and can only be read.
+#[derive(Clone)]
 pub struct Node {
 	pub id: NodeId,
 	pub endpoint: NodeEndpoint,
 	pub peer_type: PeerType,
 	pub attempts: u32,
 	pub failures: u32,
+	/// Number of successfully established sessions and useful responses reported by protocol handlers.
+	pub successes: u32,
+	/// Time of the last successful contact.
+	pub last_contact: Option<SystemTime>,
+	/// Where the node was learned from.
+	pub source: NodeSource,
+	/// Client version reported by the node in its last hello packet.
+	pub client_version: Option<String>,
+	/// Capabilities advertised by the node in its last hello packet, if it was ever connected.
+	pub capabilities: Option<Vec<PeerCapabilityInfo>>,
+	/// Smoothed response latency reported by protocol handlers.
+	pub latency: Option<Duration>,
+	/// The last session with the node stopped answering pings. Cleared on the next successful contact.
+	pub stalled: bool,
+	/// Connected at the last clean shutdown, dialled first after the next start. Cleared on the next dial.
+	pub warm: bool,
+	/// Boot node. Boot nodes are never dropped from the table and are retried at least every boot retry interval.
+	pub is_boot: bool,
+	/// Protocols the node is required for in addition to the ones implied by `peer_type`.
+	pub required_for: HashSet<ProtocolId>,
+	/// Endpoint reported by discovery that has not been accepted yet.
+	candidate_endpoint: Option<NodeEndpoint>,
+	/// All sources the node was learned from. `source` is the first one.
+	sources: NodeSources,
+	/// Endpoint the node was known at before it last moved.
+	previous_endpoint: Option<NodeEndpoint>,
+	/// Addresses the node was known at before it moved, newest first, with the time it moved away. At most
+	/// `META_LIMITS.address_history` entries are kept.
+	address_history: VecDeque<(SocketAddr, SystemTime)>,
+	/// Values recorded by protocol handlers, see `NodeTable::set_tag`.
+	tags: BTreeMap<String, u32>,
+	/// Accepted as untrusted by the admission hook, dialled after all other nodes. Not saved.
+	untrusted: bool,
+	/// Operator infrastructure whose own claims are taken at face value: the endpoint it verifies itself is applied
+	/// right away even if it is required, its discovery entries are not limited and it is exempt from the admission
+	/// hook and the subnet limit. Announcements of it by other nodes are not trusted. Its connection stats are still
+	/// recorded. Unrelated to `peer_type`, which only decides how the node is dialled. See `TableState::set_trusted`.
+	pub trusted: bool,
+	/// devp2p version the node announced in its last Hello packet, see `TableState::set_negotiated`.
+	pub p2p_version: Option<u32>,
+	/// Whether snappy compression worked with the node: `Some(false)` if it failed or was not negotiated, nodes
+	/// that failed it are announced a version without compression. `None` if unknown.
+	pub snappy: Option<bool>,
+	/// Chain the node advertised last and the compatibility epoch it was advertised in, see
+	/// `TableState::set_chain_info`.
+	chain: Option<(PeerChainInfo, u32)>,
 }
 
 const DEFAULT_FAILURE_PERCENTAGE: usize = 50;
+/// Failure percentage added to stalled nodes when ordering.
+const STALLED_PENALTY_PERCENTAGE: usize = 10;
+/// Latency that adds one point to the node score.
+const LATENCY_PENALTY_STEP_MS: u64 = 50;
+/// Maximum score penalty for high latency.
+const MAX_LATENCY_PENALTY: usize = 50;
+/// Nodes reported for misbehaviour of this severity or above get banned.
+const BAN_SEVERITY: Severity = Severity::Critical;
+/// How long misbehaving nodes are banned for.
+const MISBEHAVIOUR_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+/// How long a replaced endpoint of our own is still excluded, nodes that learned it keep reporting it for a while.
+const FORMER_LOCAL_ENDPOINT_GRACE: Duration = Duration::from_secs(60 * 60);
+/// Consecutive failures after which discovery may move a node to an unverified endpoint.
+const ENDPOINT_CHANGE_FAILURES: u32 = 3;
+/// Number of skipped entries kept by `LoadReport`.
+const MAX_SKIPPED_NODES: usize = 32;
+/// Saves in a row failing with the same kind of error after which saving is given up.
+const PERSISTENT_SAVE_FAILURES: usize = 3;
+/// Number of evictions kept by `NodeTable::recent_evictions`.
+const MAX_RECENT_EVICTIONS: usize = 64;
+/// Maximum number of useless marks of nodes not in the table, more are likely bugs or a peer flooding us with ids.
+pub const MAX_UNKNOWN_USELESS: usize = 256;
+/// Number of disconnected sessions kept by `NodeTable::note_disconnect`.
+pub const MAX_RECENTLY_CONNECTED: usize = 64;
+/// Days since the last contact after which it no longer adds to `Node::reliability`.
+const RELIABILITY_CONTACT_DAYS: u64 = 30;
+/// Sessions beyond which more of them no longer add to `Node::reliability`.
+const RELIABILITY_SUCCESSES: u32 = 20;
+/// Number of the most reliable nodes of a stale file pinged again, see `TableState::reverify_sweep`.
+pub const REVERIFY_NODES: usize = 64;
+/// Connection stats changes of single nodes kept for the candidate streams, see `TableState::touch_node`. A stream
+/// further behind computes its candidates again.
+const MAX_NODE_CHANGES: usize = 1024;
+/// How long a stale table is ordered by reliability at most if the sweep does not finish.
+const REVERIFY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Nodes of the sweep of a stale table pinged again by each maintenance run, see `NodeTable::sweep_batch`.
+pub const SWEEP_BATCH: usize = 16;
+/// Time after which `NodeTable::sweep_batch` hands out a node of the sweep again.
+const SWEEP_REPING_INTERVAL: Duration = Duration::from_secs(30);
+/// Longest enode URL accepted, with room for a host name and a `?discport=`. Anything longer is rejected before it
+/// is parsed.
+pub const MAX_ENODE_LEN: usize = 512;
+/// How long a required node stays marked as useless before `TableState::warn_useless_required` logs it.
+const USELESS_REQUIRED_WARNING: Duration = Duration::from_secs(10 * 60);
+/// Longest a `NodeTable::freeze` suppresses saves if its guard is kept.
+pub const MAX_FREEZE: Duration = Duration::from_secs(60 * 60);
+/// Lines read by `NodeTable::ingest_list`, the rest of the input is ignored.
+pub const MAX_LIST_LINES: usize = 10_000;
+/// Longest line accepted by `NodeTable::ingest_list`, without the line break.
+pub const MAX_LIST_LINE_BYTES: usize = 1024;
+/// Bytes read by `NodeTable::ingest_list`, `MAX_LIST_LINES` of the longest lines with their line breaks.
+pub const MAX_LIST_BYTES: usize = MAX_LIST_LINES * (MAX_LIST_LINE_BYTES + 2);
+/// Upper bounds of the failure percentage histogram buckets, see `NodeTable::render_prometheus`, and of the ranges
+/// of `TableState::failure_distribution`.
+const FAILURE_PERCENTAGE_BUCKETS: [usize; FAILURE_RANGES - 1] = [0, 10, 25, 50, 75, 90];
+
+/// Limits on the metadata kept per node. Most of it is reported by the node itself and saved with the table, so a
+/// malicious node could otherwise bloat memory and the saved file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetaLimits {
+	/// Maximum length of a client version in bytes, longer ones are cut.
+	pub client_version: usize,
+	/// Maximum number of capabilities, only the first ones are kept.
+	pub capabilities: usize,
+	/// Maximum number of previous addresses, the oldest are dropped.
+	pub address_history: usize,
+	/// Maximum number of tags per node.
+	pub tags: usize,
+	/// Maximum length of a tag key in bytes, tags with longer keys are dropped.
+	pub tag_key: usize,
+	/// Maximum number of distinct tag keys in the table.
+	pub tag_keys: usize,
+}
+
+/// Limits applied to all node metadata.
+pub const META_LIMITS: MetaLimits = MetaLimits {
+	client_version: 256,
+	capabilities: 32,
+	address_history: 8,
+	tags: 8,
+	tag_key: 32,
+	tag_keys: 32,
+};
+
+impl MetaLimits {
+	/// Cut `version` to `client_version` bytes at a character boundary. Returns `true` if it was cut.
+	pub fn limit_client_version(&self, version: &mut String) -> bool {
+		if version.len() <= self.client_version {
+			return false;
+		}
+		let mut end = self.client_version;
+		while !version.is_char_boundary(end) {
+			end -= 1;
+		}
+		version.truncate(end);
+		true
+	}
+
+	/// Keep the first `capabilities` of `capabilities`. Returns `true` if any were dropped.
+	pub fn limit_capabilities(&self, capabilities: &mut Vec<PeerCapabilityInfo>) -> bool {
+		let cut = capabilities.len() > self.capabilities;
+		capabilities.truncate(self.capabilities);
+		cut
+	}
+
+	/// Drop the tags with keys longer than `tag_key` and keep the first `tags` of the rest. Returns `true` if any
+	/// were dropped.
+	pub fn limit_tags(&self, tags: &mut BTreeMap<String, u32>) -> bool {
+		let drop: Vec<String> = tags.keys()
+			.enumerate()
+			.filter(|&(i, key)| key.len() > self.tag_key || i >= self.tags)
+			.map(|(_, key)| key.clone())
+			.collect();
+		for key in &drop {
+			tags.remove(key);
+		}
+		!drop.is_empty()
+	}
+
+	/// Apply all limits to the metadata of `node`. Returns the number of values that were cut.
+	fn limit_node(&self, node: &mut Node) -> usize {
+		let mut cut = 0;
+		if node.client_version.as_mut().map_or(false, |v| self.limit_client_version(v)) {
+			cut += 1;
+		}
+		if node.capabilities.as_mut().map_or(false, |c| self.limit_capabilities(c)) {
+			cut += 1;
+		}
+		if node.address_history.len() > self.address_history {
+			node.address_history.truncate(self.address_history);
+			cut += 1;
+		}
+		if self.limit_tags(&mut node.tags) {
+			cut += 1;
+		}
+		cut
+	}
+}
+
+/// Number of failed connection attempts a misbehaviour report counts as.
+fn severity_weight(severity: Severity) -> u32 {
+	match severity {
+		Severity::Minor => 1,
+		Severity::Major => 3,
+		Severity::Critical => 10,
+	}
+}
 
 impl Node {
 	pub fn new(id: NodeId, endpoint: NodeEndpoint) -> Node {
@@ -152,48 +628,218 @@ impl Node {
 			peer_type: PeerType::Optional,
 			attempts: 0,
 			failures: 0,
+			successes: 0,
+			last_contact: None,
+			source: NodeSource::Unknown,
+			client_version: None,
+			capabilities: None,
+			latency: None,
+			stalled: false,
+			warm: false,
+			is_boot: false,
+			required_for: HashSet::new(),
+			candidate_endpoint: None,
+			sources: NodeSources::default(),
+			previous_endpoint: None,
+			address_history: VecDeque::new(),
+			tags: BTreeMap::new(),
+			untrusted: false,
+			trusted: false,
+			p2p_version: None,
+			snappy: None,
+			chain: None,
+		}
+	}
+
+	/// Start building a node with statistics and flags, e.g. to seed the table of an embedding crate.
+	pub fn builder(id: NodeId, endpoint: NodeEndpoint) -> NodeBuilder {
+		NodeBuilder { node: Node::new(id, endpoint) }
+	}
+
+	/// Endpoint reported by discovery that has not been accepted yet.
+	pub fn candidate_endpoint(&self) -> Option<&NodeEndpoint> {
+		self.candidate_endpoint.as_ref()
+	}
+
+	/// All sources the node was learned from.
+	pub fn sources(&self) -> NodeSources {
+		self.sources
+	}
+
+	/// Endpoint the node was known at before it last moved.
+	pub fn previous_endpoint(&self) -> Option<&NodeEndpoint> {
+		self.previous_endpoint.as_ref()
+	}
+
+	/// Addresses the node was known at before it moved, newest first, with the time it moved away.
+	pub fn address_history(&self) -> &VecDeque<(SocketAddr, SystemTime)> {
+		&self.address_history
+	}
+
+	/// Values recorded by protocol handlers, see `NodeTable::set_tag`.
+	pub fn tags(&self) -> &BTreeMap<String, u32> {
+		&self.tags
+	}
+
+	/// Whether the admission hook accepted the node as untrusted, see `Admission::AcceptUntrusted`.
+	pub fn is_untrusted(&self) -> bool {
+		self.untrusted
+	}
+
+	/// Chain the node advertised last, see `TableState::set_chain_info`.
+	pub fn chain_info(&self) -> Option<&PeerChainInfo> {
+		self.chain.as_ref().map(|&(ref info, _)| info)
+	}
+
+	/// Record that the node moved away from `address` at `time`, dropping the oldest entry if the history is full.
+	fn note_moved(&mut self, address: SocketAddr, time: SystemTime) {
+		self.address_history.push_front((address, time));
+		self.address_history.truncate(META_LIMITS.address_history);
+	}
+
+	/// Merge the address history of another entry for the same node, e.g. a saved one. Entries are ordered by time,
+	/// so the result does not depend on which entry is merged into which.
+	fn merge_address_history(&mut self, other: VecDeque<(SocketAddr, SystemTime)>) {
+		let mut history: Vec<_> = self.address_history.drain(..).chain(other).collect();
+		history.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| (a.0.ip(), a.0.port()).cmp(&(b.0.ip(), b.0.port()))));
+		history.dedup();
+		history.truncate(META_LIMITS.address_history);
+		self.address_history = history.into_iter().collect();
+	}
+
+	/// Check if the node is required, globally or for `protocol`.
+	pub fn is_required_for(&self, protocol: Option<&ProtocolId>) -> bool {
+		self.peer_type == PeerType::Required || protocol.map_or(false, |p| self.required_for.contains(p))
+	}
+
+	/// Check if the node is required, globally or for one of `protocols`.
+	fn is_required_for_any(&self, protocols: &[ProtocolId]) -> bool {
+		self.peer_type == PeerType::Required || protocols.iter().any(|p| self.required_for.contains(p))
+	}
+
+	/// Returns the fraction of the connection attempts to this node that failed (0..1). If there are 0 connection
+	/// attempts for this node the default failure ratio is returned (0.5).
+	pub fn failure_ratio(&self) -> f64 {
+		if self.attempts == 0 {
+			DEFAULT_FAILURE_PERCENTAGE as f64 / 100.0
+		} else {
+			(self.failures as f64 / self.attempts as f64).min(1.0)
 		}
 	}
 
-	/// Returns the node's failure percentage (0..100) in buckets of 5%. If there are 0 connection attempts for this
-	/// node the default failure percentage is returned (50%).
+	/// Returns the failure ratio as a percentage (0..100) rounded to the nearest integer, for display.
 	pub fn failure_percentage(&self) -> usize {
+		(self.failure_ratio() * 100.0).round() as usize
+	}
+
+	/// Returns the failure percentage (0..100) rounded down to a multiple of 5%, so that nodes with similar failure
+	/// rates rank equal and are ordered by their absolute numbers of failures and attempts. If there are 0
+	/// connection attempts for this node the default failure percentage is returned (50%).
+	pub fn failure_bucket(&self) -> usize {
 		if self.attempts == 0 {
 			DEFAULT_FAILURE_PERCENTAGE
 		} else {
-			(self.failures * 100 / self.attempts / 5 * 5) as usize
+			min(self.failures as u64 * 100 / self.attempts as u64 / 5 * 5, 100) as usize
+		}
+	}
+
+	/// Returns the failure bucket used for ordering, which includes the penalty for stalled nodes.
+	fn ordering_percentage(&self) -> usize {
+		self.failure_bucket() + if self.stalled { STALLED_PENALTY_PERCENTAGE } else { 0 }
+	}
+
+	/// Returns the node's score, lower is better. This is the ordering failure percentage plus a penalty for high
+	/// response latency.
+	pub fn score(&self) -> usize {
+		let latency_penalty = self.latency.map_or(0, |latency| {
+			let ms = latency.as_secs() * 1000 + (latency.subsec_nanos() / 1_000_000) as u64;
+			min(ms / LATENCY_PENALTY_STEP_MS, MAX_LATENCY_PENALTY as u64) as usize
+		});
+		self.ordering_percentage() + latency_penalty
+	}
+
+	/// Returns how likely the node is to answer after a long downtime (0..255), higher is better. It is saved with
+	/// the node and orders a stale table, see `NodeTableConfig::stale_file_age`. The failure bucket counts twice,
+	/// the time since the last contact once, nothing after `RELIABILITY_CONTACT_DAYS` days, and the sessions once
+	/// up to `RELIABILITY_SUCCESSES`, each on a scale of 0..100.
+	pub fn reliability(&self, now: SystemTime) -> u8 {
+		let failures = 100 - min(self.failure_bucket(), 100) as u64;
+		let contact = self.last_contact.map_or(0, |t| {
+			let days = now.duration_since(t).map(|d| d.as_secs() / (24 * 60 * 60)).unwrap_or(0);
+			100 - min(days, RELIABILITY_CONTACT_DAYS) * 100 / RELIABILITY_CONTACT_DAYS
+		});
+		let successes = min(self.successes, RELIABILITY_SUCCESSES) as u64 * 100 / RELIABILITY_SUCCESSES as u64;
+		((failures * 2 + contact + successes) * 255 / 400) as u8
+	}
+
+	/// Parse a boot node given either as an enode URL or as a bare `host:port` address. Bare addresses give a node
+	/// with a zero id that can only be pinged by discovery until its id is known, it is never added to the table.
+	pub fn from_boot_str(s: &str) -> Result<Node, Error> {
+		if s.starts_with("enode://") {
+			s.parse()
+		} else {
+			Ok(Node::new(NodeId::new(), NodeEndpoint::from_str(s)?))
+		}
+	}
+
+	/// Returns the enode URL in the form understood by other clients, a distinct discovery port is given as
+	/// `?discport=`.
+	pub fn enode_url(&self) -> String {
+		self.to_string()
+	}
+
+	/// Like `from_str`, but host names are only resolved if `resolve` is set. URLs longer than `MAX_ENODE_LEN` are
+	/// rejected.
+	pub fn parse(s: &str, resolve: bool) -> Result<Node, Error> {
+		if s.len() > MAX_ENODE_LEN {
+			return Err(ErrorKind::NodeUrlTooLong(MAX_ENODE_LEN).into());
+		}
+		if s.len() > 136 && s.starts_with("enode://") && s.as_bytes()[136] == b'@' {
+			let id = s[8..136].parse().map_err(|_| ErrorKind::InvalidNodeId)?;
+			Ok(Node::new(id, NodeEndpoint::parse(&s[137..], resolve)?))
+		} else {
+			Err(ErrorKind::InvalidNodeId.into())
 		}
 	}
 }
 
 impl Display for Node {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "enode://{:x}@{}", self.id, self.endpoint.address)?;
 		if self.endpoint.udp_port != self.endpoint.address.port() {
-			write!(f, "enode://{:x}@{}+{}", self.id, self.endpoint.address, self.endpoint.udp_port)?;
-		} else {
-			write!(f, "enode://{:x}@{}", self.id, self.endpoint.address)?;
+			write!(f, "?discport={}", self.endpoint.udp_port)?;
 		}
 		Ok(())
 	}
 }
 
+/// Short id, endpoint and dial statistics. Flags are only shown when set.
+impl fmt::Debug for Node {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		let mut s = f.debug_struct("Node");
+		s.field("id", &self.id.short())
+			.field("endpoint", &self.endpoint)
+			.field("peer_type", &self.peer_type)
+			.field("source", &self.source)
+			.field("attempts", &self.attempts)
+			.field("failures", &self.failures)
+			.field("successes", &self.successes);
+		if let Some(ref candidate) = self.candidate_endpoint {
+			s.field("candidate", candidate);
+		}
+		for &(name, set) in &[("boot", self.is_boot), ("stalled", self.stalled), ("warm", self.warm), ("untrusted", self.untrusted), ("trusted", self.trusted)] {
+			if set {
+				s.field(name, &true);
+			}
+		}
+		s.finish()
+	}
+}
+
 impl FromStr for Node {
 	type Err = Error;
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let (id, endpoint) = if s.len() > 136 && &s[0..8] == "enode://" && &s[136..137] == "@" {
-			(s[8..136].parse().map_err(|_| ErrorKind::InvalidNodeId)?, NodeEndpoint::from_str(&s[137..])?)
-		}
-		else {
-			(NodeId::new(), NodeEndpoint::from_str(s)?)
-		};
-
-		Ok(Node {
-			id: id,
-			endpoint: endpoint,
-			peer_type: PeerType::Optional,
-			attempts: 0,
-			failures: 0,
-		})
+		Node::parse(s, true)
 	}
 }
 
@@ -210,221 +856,4853 @@ impl Hash for Node {
 	}
 }
 
-const MAX_NODES: usize = 1024;
-const NODES_FILE: &str = "nodes.json";
-
-/// Node table backed by disk file.
-pub struct NodeTable {
-	nodes: HashMap<NodeId, Node>,
-	useless_nodes: HashSet<NodeId>,
-	path: Option<String>,
+/// Builder of a `Node`, see `Node::builder`. Everything not set starts as in `Node::new`.
+#[derive(Debug, Clone)]
+pub struct NodeBuilder {
+	node: Node,
 }
 
-impl NodeTable {
-	pub fn new(path: Option<String>) -> NodeTable {
-		NodeTable {
-			path: path.clone(),
-			nodes: NodeTable::load(path),
-			useless_nodes: HashSet::new(),
-		}
+impl NodeBuilder {
+	/// How the node is dialled.
+	pub fn peer_type(mut self, peer_type: PeerType) -> Self {
+		self.node.peer_type = peer_type;
+		self
 	}
 
-	/// Add a node to table
-	pub fn add_node(&mut self, mut node: Node) {
-		// preserve attempts and failure counter
-		let (attempts, failures) =
-			self.nodes.get(&node.id).map_or((0, 0), |n| (n.attempts, n.failures));
-
-		node.attempts = attempts;
-		node.failures = failures;
-
-		self.nodes.insert(node.id.clone(), node);
+	/// Where the node was learned from.
+	pub fn source(mut self, source: NodeSource) -> Self {
+		self.node.source = source;
+		self.node.sources.insert(source);
+		self
 	}
 
-	fn ordered_entries(&self) -> Vec<&Node> {
-		let mut refs: Vec<&Node> = self.nodes.values()
-			.filter(|n| !self.useless_nodes.contains(&n.id))
-			.collect();
-
-		refs.sort_by(|a, b| {
-			a.failure_percentage().cmp(&b.failure_percentage())
-				.then_with(|| a.failures.cmp(&b.failures))
-				.then_with(|| b.attempts.cmp(&a.attempts)) // we use reverse ordering for number of attempts
-		});
-
-		refs
+	/// Mark the node as a boot node.
+	pub fn boot(mut self) -> Self {
+		self.node.is_boot = true;
+		self
 	}
 
-	/// Returns node ids sorted by failure percentage, for nodes with the same failure percentage the absolute number of
-	/// failures is considered.
-	pub fn nodes(&self, filter: IpFilter) -> Vec<NodeId> {
-		self.ordered_entries().iter()
-			.filter(|n| n.endpoint.is_allowed(&filter))
-			.map(|n| n.id)
-			.collect()
+	/// Take the claims of the node at face value, see `Node::trusted`.
+	pub fn trusted(mut self) -> Self {
+		self.node.trusted = true;
+		self
 	}
 
-	/// Ordered list of all entries by failure percentage, for nodes with the same failure percentage the absolute
-	/// number of failures is considered.
-	pub fn entries(&self) -> Vec<NodeEntry> {
-		self.ordered_entries().iter().map(|n| NodeEntry {
-			endpoint: n.endpoint.clone(),
-			id: n.id.clone(),
-		}).collect()
+	/// Require the node for `protocol`.
+	pub fn required_for(mut self, protocol: ProtocolId) -> Self {
+		self.node.required_for.insert(protocol);
+		self
 	}
 
-	/// Get particular node
-	pub fn get_mut(&mut self, id: &NodeId) -> Option<&mut Node> {
-		self.nodes.get_mut(id)
+	/// Connection attempts, failed ones among them and successful sessions.
+	pub fn stats(mut self, attempts: u32, failures: u32, successes: u32) -> Self {
+		self.node.attempts = attempts;
+		self.node.failures = failures;
+		self.node.successes = successes;
+		self
 	}
 
-	/// Check if a node exists in the table.
-	pub fn contains(&self, id: &NodeId) -> bool {
-		self.nodes.contains_key(id)
+	/// Time of the last successful contact.
+	pub fn last_contact(mut self, time: SystemTime) -> Self {
+		self.node.last_contact = Some(time);
+		self
 	}
 
-	/// Apply table changes coming from discovery
-	pub fn update(&mut self, mut update: TableUpdates, reserved: &HashSet<NodeId>) {
-		for (_, node) in update.added.drain() {
-			let entry = self.nodes.entry(node.id.clone()).or_insert_with(|| Node::new(node.id.clone(), node.endpoint.clone()));
-			entry.endpoint = node.endpoint;
-		}
-		for r in update.removed {
-			if !reserved.contains(&r) {
-				self.nodes.remove(&r);
-			}
-		}
+	/// Client version and capabilities from the last hello packet.
+	pub fn hello(mut self, client_version: String, capabilities: Vec<PeerCapabilityInfo>) -> Self {
+		self.node.client_version = Some(client_version);
+		self.node.capabilities = Some(capabilities);
+		self
 	}
 
-	/// Increase failure counte for a node
-	pub fn note_failure(&mut self, id: &NodeId) {
-		if let Some(node) = self.nodes.get_mut(id) {
-			node.failures += 1;
-		}
+	/// Returns the node.
+	pub fn build(self) -> Node {
+		self.node
 	}
+}
 
-	/// Mark as useless, no further attempts to connect until next call to `clear_useless`.
-	pub fn mark_as_useless(&mut self, id: &NodeId) {
-		self.useless_nodes.insert(id.clone());
+/// Discovery entry without quality hints, see `TableState::entries` for one with them.
+impl<'a> From<&'a Node> for NodeEntry {
+	fn from(node: &'a Node) -> Self {
+		NodeEntry::new(node.id.clone(), node.endpoint.clone())
 	}
+}
 
-	/// Atempt to connect to useless nodes again.
-	pub fn clear_useless(&mut self) {
-		self.useless_nodes.clear();
+/// New node at the endpoint of a discovery entry, with no statistics. The quality hints are dropped.
+impl From<NodeEntry> for Node {
+	fn from(entry: NodeEntry) -> Self {
+		Node::new(entry.id, entry.endpoint)
 	}
+}
 
-	/// Save the nodes.json file.
-	pub fn save(&self) {
-		let mut path = match self.path {
-			Some(ref path) => PathBuf::from(path),
-			None => return,
-		};
-		if let Err(e) = fs::create_dir_all(&path) {
-			warn!("Error creating node table directory: {:?}", e);
-			return;
-		}
-		path.push(NODES_FILE);
-		let node_ids = self.nodes(IpFilter::default());
-		let nodes = node_ids.into_iter()
-			.map(|id| self.nodes.get(&id).expect("self.nodes() only returns node IDs from self.nodes"))
-			.take(MAX_NODES)
-			.map(|node| node.clone())
-			.map(Into::into)
-			.collect();
-		let table = json::NodeTable { nodes };
+/// Capability requirements for selecting nodes to connect to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialPolicy {
+	/// Protocols and versions run by the host. A node matches if it supports at least one of them.
+	/// An empty list matches any node.
+	pub required_capabilities: Vec<(ProtocolId, u8)>,
+	/// Include nodes with unknown capabilities, i.e. nodes we have never completed a handshake with.
+	pub allow_unknown: bool,
+	/// Skip nodes with an active session and nodes on the IP of one, see `TableState::session_opened`. Required
+	/// nodes are only skipped by id.
+	pub exclude_connected: bool,
+}
 
-		match fs::File::create(&path) {
-			Ok(file) => {
-				if let Err(e) = serde_json::to_writer_pretty(file, &table) {
-					warn!("Error writing node table file: {:?}", e);
-				}
-			},
-			Err(e) => {
-				warn!("Error creating node table file: {:?}", e);
-			}
+impl Default for DialPolicy {
+	fn default() -> Self {
+		DialPolicy {
+			required_capabilities: Vec::new(),
+			allow_unknown: false,
+			exclude_connected: true,
 		}
 	}
+}
 
-	fn load(path: Option<String>) -> HashMap<NodeId, Node> {
-		let path = match path {
-			Some(path) => PathBuf::from(path).join(NODES_FILE),
-			None => return Default::default(),
-		};
-
-		let file = match fs::File::open(&path) {
-			Ok(file) => file,
-			Err(e) => {
-				debug!("Error opening node table file: {:?}", e);
-				return Default::default();
-			},
-		};
-		let res: Result<json::NodeTable, _> = serde_json::from_reader(file);
-		match res {
-			Ok(table) => {
-				table.nodes.into_iter()
-					.filter_map(|n| n.into_node())
-					.map(|n| (n.id.clone(), n))
-					.collect()
-			},
-			Err(e) => {
-				warn!("Error reading node table file: {:?}", e);
-				Default::default()
-			},
+impl DialPolicy {
+	/// Check if a node advertising given capabilities satisfies the policy.
+	pub fn matches(&self, capabilities: Option<&[PeerCapabilityInfo]>) -> bool {
+		if self.required_capabilities.is_empty() {
+			return true;
+		}
+		match capabilities {
+			Some(caps) => caps.iter().any(|c| self.required_capabilities.iter().any(|&(protocol, version)| c.protocol == protocol && c.version == version)),
+			None => self.allow_unknown,
 		}
 	}
-}
 
-impl Drop for NodeTable {
-	fn drop(&mut self) {
-		self.save();
+	/// Protocols of `required_capabilities`, each once.
+	pub fn protocols(&self) -> Vec<ProtocolId> {
+		let mut protocols: Vec<ProtocolId> = self.required_capabilities.iter().map(|&(protocol, _)| protocol).collect();
+		protocols.sort();
+		protocols.dedup();
+		protocols
 	}
 }
 
-/// Check if node url is valid
-pub fn validate_node_url(url: &str) -> Option<Error> {
-	match Node::from_str(url) {
-		Ok(_) => None,
-		Err(e) => Some(e)
-	}
+/// Cursor over the dial candidates of `TableState::explain_selection`, created by `TableState::candidate_stream` and
+/// advanced by `TableState::next_candidate`. It does not borrow the table, so that it can be kept between connection
+/// rounds.
+#[derive(Debug, Clone)]
+pub struct CandidateStream {
+	policy: DialPolicy,
+	filter: IpFilter,
+	/// Candidates not yielded yet, in selection order.
+	pending: VecDeque<(NodeEntry, SelectionReason)>,
+	/// Nodes yielded in the current round.
+	yielded: HashSet<NodeId>,
+	/// Selection and session changes `pending` was computed for, see `TableState::reorder`.
+	version: Option<(u64, u64)>,
+	/// Latest node stats change when `pending` was computed, see `TableState::touch_node`.
+	seen: u64,
+	/// Earliest end of a dial backoff after `pending` was computed, it may make a node dialable.
+	wake: Option<Instant>,
+	/// The current round ran dry, the next candidate starts a new one.
+	exhausted: bool,
+	rounds: usize,
 }
 
-mod json {
-	use super::*;
-
-	#[derive(Serialize, Deserialize)]
-	pub struct NodeTable {
-		pub nodes: Vec<Node>,
+impl CandidateStream {
+	/// Whether the stream selects nodes with `policy` and `filter`.
+	pub fn is_for(&self, policy: &DialPolicy, filter: &IpFilter) -> bool {
+		self.policy == *policy && self.filter == *filter
 	}
 
-	#[derive(Serialize, Deserialize)]
-	pub struct Node {
-		pub url: String,
-		pub attempts: u32,
-		pub failures: u32,
+	/// At most `limit` candidates the stream yields next if the table does not change meanwhile.
+	pub fn upcoming(&self, limit: usize) -> Vec<(NodeEntry, SelectionReason)> {
+		self.pending.iter().take(limit).cloned().collect()
 	}
 
-	impl Node {
-		pub fn into_node(self) -> Option<super::Node> {
-			match super::Node::from_str(&self.url) {
-				Ok(mut node) => {
-					node.attempts = self.attempts;
-					node.failures = self.failures;
-					Some(node)
-				},
-				_ => None,
-			}
-		}
+	/// Rounds completed, i.e. the number of times the stream ran dry.
+	pub fn rounds(&self) -> usize {
+		self.rounds
 	}
+}
 
-	impl<'a> From<&'a super::Node> for Node {
-		fn from(node: &'a super::Node) -> Self {
-			Node {
-				url: format!("{}", node),
-				attempts: node.attempts,
-				failures: node.failures,
-			}
-		}
-	}
+/// Exclusions ignored by `TableState::entries_with`. By default it returns the same entries as `entries`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryOptions {
+	/// Include required nodes marked as useless, e.g. a reserved peer that was restarting when we connected.
+	pub include_useless_required: bool,
+	/// Include banned nodes.
+	pub include_banned: bool,
+}
+
+/// Why a node was selected for dialling, see `NodeTable::explain_selection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionReason {
+	/// Reserved or required for a protocol.
+	Required,
+	/// Connected at the last clean shutdown, dialled first during the warm start.
+	Warm,
+	/// Ranked by its record of successful contacts.
+	Quality,
+	/// Never contacted successfully, fills the remaining slots with peers we know nothing about yet.
+	DiverseFill,
+	/// Lost its session recently, dialled first after losing many peers at once, see `NodeTable::recently_lost`.
+	RecentlyLost,
+	/// Boot node dialled because no peers are connected, see `NodeTable::boot_fallback`.
+	BootFallback,
+}
+
+impl Display for SelectionReason {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			SelectionReason::Required => "required",
+			SelectionReason::Warm => "warm",
+			SelectionReason::Quality => "quality",
+			SelectionReason::DiverseFill => "diverse-fill",
+			SelectionReason::RecentlyLost => "recently-lost",
+			SelectionReason::BootFallback => "boot-fallback",
+		})
+	}
+}
+
+/// Node table entry with statistics, as returned by `NodeTable::entries_with_meta`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeTableEntry {
+	/// Node id.
+	pub id: NodeId,
+	/// Enode URL.
+	pub url: String,
+	/// TCP endpoint address.
+	pub address: SocketAddr,
+	/// Number of connection attempts.
+	pub attempts: u32,
+	/// Number of failed connection attempts.
+	pub failures: u32,
+	/// Failure percentage, see `Node::failure_percentage`.
+	pub failure_percentage: usize,
+	/// The node is marked as useless.
+	pub useless: bool,
+	/// The node is banned.
+	pub banned: bool,
+	/// The last session with the node stopped answering pings, see `Node::stalled`.
+	pub stalled: bool,
+	/// Time of the last successful contact.
+	pub last_contact: Option<SystemTime>,
+	/// Where the node was learned from.
+	pub source: NodeSource,
+	/// TCP address of an endpoint reported by discovery but not accepted yet.
+	pub candidate_address: Option<SocketAddr>,
+	/// TCP address the node used before its last endpoint change.
+	pub previous_address: Option<SocketAddr>,
+	/// Why the node is banned, if it is.
+	pub ban_reason: Option<String>,
+	/// Why the node is marked as useless, if it is.
+	pub useless_reason: Option<String>,
+	/// TCP addresses the node was known at before, newest first, with the time it moved away.
+	pub address_history: Vec<(SocketAddr, SystemTime)>,
+	/// devp2p version the node announced in its last Hello packet.
+	pub p2p_version: Option<u32>,
+	/// Whether snappy compression worked with the node, see `Node::snappy`.
+	pub snappy: Option<bool>,
+	/// The claims of the node are taken at face value, see `Node::trusted`.
+	pub trusted: bool,
+	/// Chain the node advertised last, see `TableState::set_chain_info`.
+	pub chain_info: Option<PeerChainInfo>,
+	/// The node advertised another chain than ours in the current compatibility epoch, it is dialled last.
+	pub other_chain: bool,
+}
+
+/// Ids of the nodes in a table that can be checked without locking the table, see `NodeTable::membership`.
+#[derive(Clone, Default)]
+pub struct MembershipHandle {
+	ids: Arc<RwLock<HashSet<NodeId>>>,
+}
+
+impl MembershipHandle {
+	/// Whether the node was in the table when the handles were last refreshed.
+	pub fn contains(&self, id: &NodeId) -> bool {
+		self.ids.read().contains(id)
+	}
+}
+
+/// Summary of the node table contents, as returned by `NodeTable::stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeTableStats {
+	/// Number of known nodes.
+	pub total: usize,
+	/// Nodes marked as useless.
+	pub useless: usize,
+	/// Active bans, including nodes not in the table.
+	pub banned: usize,
+	/// Nodes waiting for their dial backoff to expire.
+	pub backed_off: usize,
+	/// Nodes rejected by the IP filter.
+	pub filtered: usize,
+	/// Nodes contacted successfully at least once.
+	pub contacted: usize,
+	/// Nodes with an IPv4 address.
+	pub ipv4: usize,
+	/// Nodes with an IPv6 address.
+	pub ipv6: usize,
+	/// Recently removed nodes that discovery may not add again yet.
+	pub tombstones: usize,
+	/// Nodes rejected for having the zero id, our own id or our own endpoint.
+	pub rejected: usize,
+	/// Known nodes at one of our own endpoints, e.g. added before NAT detection found our external address. They are
+	/// skipped when selecting nodes.
+	pub local: usize,
+	/// New nodes ignored because their subnet reached the per-subnet limit.
+	pub subnet_rejected: usize,
+	/// New nodes ignored for a port below `NodeTableConfig::min_port`.
+	pub low_port_rejected: usize,
+	/// Nodes from discovery ignored because they were removed recently.
+	pub tombstone_rejected: usize,
+	/// Nodes from discovery ignored because they are banned.
+	pub ban_rejected: usize,
+	/// Nodes refused by the admission hook, by reason.
+	pub admission_rejected: BTreeMap<String, usize>,
+	/// Successful saves.
+	pub saves: usize,
+	/// Failed or timed out saves.
+	pub save_failures: usize,
+	/// Metadata values cut to `META_LIMITS`, e.g. overlong client versions reported by nodes.
+	pub meta_truncated: usize,
+	/// Last save error if saving was given up after repeated failures, see `NodeTable::reset_persistence`.
+	pub persistence_error: Option<String>,
+	/// Our own connectivity is suspected to be down, failures are quarantined until the next success.
+	pub outage: bool,
+	/// Nodes with failures quarantined during the current outage.
+	pub quarantined: usize,
+	/// Suspected outages since the start.
+	pub outages: usize,
+	/// Nodes dropped from the table since the start, by reason.
+	pub evictions: BTreeMap<EvictionReason, usize>,
+	/// Inbound connections from the address of known nodes that proved another id, see `NodeTable::match_inbound`.
+	pub inbound_mismatches: usize,
+	/// Mutations made while the table was locked and dropped because too many were queued. Set by the host.
+	pub deferred_dropped: usize,
+	/// Boot nodes are only dialled when no peers are connected, see `NodeTable::is_bootstrapped`.
+	pub bootstrapped: bool,
+	/// Times an IP reached `NodeTableConfig::max_ports_per_ip` dialled ports, its nodes on other ports are not
+	/// selected until the window passed.
+	pub port_capped: usize,
+	/// Known nodes at the endpoint of another instance on this host, see `NodeTableConfig::sibling_endpoints`. They
+	/// are skipped when selecting nodes.
+	pub sibling: usize,
+	/// Nodes rejected for the endpoint of another instance on this host, also counted in `rejected`.
+	pub sibling_rejected: usize,
+	/// Dials whose peer announced another id in its Hello packet than the one it proved, see
+	/// `NodeTable::note_id_mismatch`.
+	pub id_mismatches: usize,
+	/// Nodes whose last dial announced another id. The entries are not changed.
+	pub flagged_mismatches: usize,
+	/// Connection outcomes of the last `HISTORY_HOURS` hours, oldest first, see `TableState::outcome_history`.
+	pub history: Vec<OutcomeBucket>,
+	/// Open sessions the table was told about, see `TableState::session_opened`.
+	pub active_sessions: usize,
+	/// Times a node was skipped when selecting because it has an active session.
+	pub connected_skipped: usize,
+	/// Times a node was skipped when selecting because another node on its IP has an active session.
+	pub connected_ip_skipped: usize,
+	/// The saved table is still being loaded, see `NodeTable::load_complete`.
+	pub loading: bool,
+}
+
+/// Known nodes at the remote address of an inbound connection, see `NodeTable::match_inbound`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InboundMatch {
+	/// No node is known at the address.
+	Unknown,
+	/// The connecting node is known at the address.
+	Confirmed,
+	/// Only other nodes are known at the address. The node may have changed its key or share the address.
+	Mismatch(Vec<NodeId>),
+}
+
+/// Outcome of a connection to a node, see `NodeTable::note_outcomes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+	/// A session was established.
+	Success,
+	/// The connection attempt or the session failed.
+	Failure,
+	/// The session stopped answering pings.
+	Stalled,
+}
+
+/// Result of `NodeTable::note_failure` and `NodeTable::mark_as_useless`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteResult {
+	/// Recorded for a node in the table.
+	Applied,
+	/// The node is not in the table and nothing was recorded. For useless marks the limit of marks for unknown
+	/// nodes was reached.
+	UnknownNode,
+	/// The node is not in the table, the useless mark is kept in case it is added later, see
+	/// `MAX_UNKNOWN_USELESS`.
+	Tracked,
+	/// The node is required and is never marked as useless.
+	Required,
+}
+
+impl NoteResult {
+	/// Log an `UnknownNode` result at debug level, naming the caller in `site`.
+	pub fn log_unknown(self, id: &NodeId, site: &str) {
+		if self == NoteResult::UnknownNode {
+			debug!(target: "network", "{}: node {} is not in the node table", site, id.short());
+		}
+	}
+}
+
+/// Why a saved entry could not be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+	/// The URL is not an enode URL or its node id is not valid.
+	InvalidId,
+	/// The address in the URL is not valid.
+	BadAddress,
+}
+
+impl SkipReason {
+	fn from_error(e: &Error) -> SkipReason {
+		match *e.kind() {
+			ErrorKind::InvalidNodeId => SkipReason::InvalidId,
+			_ => SkipReason::BadAddress,
+		}
+	}
+}
+
+impl Display for SkipReason {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			SkipReason::InvalidId => "invalid-id",
+			SkipReason::BadAddress => "bad-address",
+		})
+	}
+}
+
+/// Why a saved node was dropped at start, see `NodeTable::reconcile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DropReason {
+	/// The address is not allowed by the IP filter.
+	Filtered,
+	/// The subnet already held `max_nodes_per_subnet` nodes.
+	Subnet,
+	/// Not contacted successfully for longer than `max_age`.
+	Expired,
+	/// Among the worst nodes while there were more than `max_nodes`.
+	Capacity,
+	/// The address is one of our own or of another instance on this host.
+	OwnAddress,
+}
+
+impl Display for DropReason {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			DropReason::Filtered => "filtered",
+			DropReason::Subnet => "subnet",
+			DropReason::Expired => "expired",
+			DropReason::Capacity => "capacity",
+			DropReason::OwnAddress => "own-address",
+		})
+	}
+}
+
+/// Why a node was dropped from the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EvictionReason {
+	/// Removed with `NodeTable::remove_node`, e.g. over RPC.
+	Removed,
+	/// Dropped from the discovery buckets after it stopped answering pings.
+	Discovery,
+	/// Not contacted successfully for longer than `max_age`.
+	Expired,
+	/// Among the worst nodes while the table held more than `max_nodes`.
+	Capacity,
+	/// Its endpoint was verified for a node with another id.
+	Rotated,
+	/// Saved at an address the IP filter does not allow or at our own address, see `NodeTable::reconcile`.
+	Filtered,
+}
+
+impl Display for EvictionReason {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			EvictionReason::Removed => "removed",
+			EvictionReason::Discovery => "discovery",
+			EvictionReason::Expired => "expired",
+			EvictionReason::Capacity => "capacity",
+			EvictionReason::Rotated => "rotated",
+			EvictionReason::Filtered => "filtered",
+		})
+	}
+}
+
+/// Node dropped from the table, as returned by `NodeTable::recent_evictions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Eviction {
+	/// Id of the node.
+	pub id: NodeId,
+	/// Endpoint of the node when it was dropped.
+	pub endpoint: NodeEndpoint,
+	/// Why it was dropped.
+	pub reason: EvictionReason,
+	/// When it was dropped.
+	pub time: SystemTime,
+}
+
+/// Saved entry that could not be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedNode {
+	/// Position of the entry in the saved table.
+	pub index: usize,
+	/// Saved URL.
+	pub url: String,
+	/// Why the entry was skipped.
+	pub reason: SkipReason,
+	/// Parse error of the URL.
+	pub error: String,
+}
+
+/// Why a new node offered by a discovery update was not added, see `UpdateOutcome::rejected`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RejectReason {
+	/// Removed within `NodeTableConfig::tombstone_grace`.
+	Tombstoned,
+	/// Banned.
+	Banned,
+	/// The zero id, our own id, our own address or the address of another instance on this host.
+	Local,
+	/// Its subnet already has `NodeTableConfig::max_nodes_per_subnet` nodes.
+	SubnetFull,
+	/// A port is below `NodeTableConfig::min_port`.
+	LowPort,
+	/// Refused by the admission hook, see `NodeTable::set_admission_hook`.
+	Admission,
+	/// Waiting for the deferred admission hook, see `TableState::set_deferred_admission_hook`.
+	AwaitingAdmission,
+	/// The table is full and the node does not rank before any node that may be evicted.
+	TableFull,
+	/// The endpoint cannot be dialled or the entry is keyed by another id.
+	Invalid,
+}
+
+impl Display for RejectReason {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			RejectReason::Tombstoned => "recently removed",
+			RejectReason::Banned => "banned",
+			RejectReason::Local => "own node",
+			RejectReason::SubnetFull => "subnet full",
+			RejectReason::LowPort => "port too low",
+			RejectReason::Admission => "refused by the admission hook",
+			RejectReason::AwaitingAdmission => "waiting for the admission hook",
+			RejectReason::TableFull => "table full",
+			RejectReason::Invalid => "invalid entry",
+		})
+	}
+}
+
+/// Periodic work done by `NodeTable::maintenance`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaintenanceReport {
+	/// Whether the table was refreshed, which happens every `NodeTableConfig::autosave_interval`.
+	pub refreshed: bool,
+	/// Useless marks cleared by the refresh.
+	pub cleared_useless: usize,
+	/// Nodes dropped by the refresh for not being contacted within `NodeTableConfig::max_age`.
+	pub expired: usize,
+	/// Nodes dropped by the refresh over `NodeTableConfig::max_nodes`.
+	pub evicted: usize,
+	/// Whether the table was saved, on the refresh or between the refreshes, see `autosave_delay`.
+	pub saved: bool,
+	/// Nodes whose dial backoff ended since the previous maintenance.
+	pub backoff_expired: usize,
+	/// Nodes of the sweep of a stale table not verified yet, see `TableState::reverify_sweep`.
+	pub stale_pending: usize,
+	/// Suggested delay until the next maintenance, see `maintenance_delay`.
+	pub next_delay: Duration,
+}
+
+/// Changes made by a discovery update, as returned by `NodeTable::update`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdateOutcome {
+	/// New nodes added to the table.
+	pub added: usize,
+	/// Known nodes moved to a new endpoint or given their UDP port.
+	pub updated_endpoints: usize,
+	/// Known nodes removed because discovery dropped them.
+	pub removed: usize,
+	/// Known nodes dropped to make room for better new ones.
+	pub evicted: usize,
+	/// Known nodes dropped by discovery but kept because they are reserved, required or boot nodes, by id.
+	pub protected: Vec<NodeId>,
+	/// New nodes not added and why, by id.
+	pub rejected: Vec<(NodeId, RejectReason)>,
+	/// Valid entries dropped over `NodeTableConfig::max_update_added`.
+	pub truncated_added: usize,
+	/// Removed nodes ignored over `NodeTableConfig::max_update_removed`.
+	pub truncated_removed: usize,
+}
+
+/// Outcome of the last load of the saved table, as returned by `NodeTable::load_report`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadReport {
+	/// Number of saved entries.
+	pub total: usize,
+	/// Number of skipped entries by reason.
+	pub skipped: BTreeMap<SkipReason, usize>,
+	/// The first `MAX_SKIPPED_NODES` skipped entries.
+	pub skipped_nodes: Vec<SkippedNode>,
+	/// Number of nodes in the table once the loaded ones were checked against the configuration, `None` until
+	/// `NodeTable::reconcile` ran.
+	pub kept: Option<usize>,
+	/// Number of loaded nodes dropped for breaking the configuration, by reason.
+	pub dropped: BTreeMap<DropReason, usize>,
+}
+
+impl LoadReport {
+	/// Number of skipped entries.
+	pub fn skipped_count(&self) -> usize {
+		self.skipped.values().sum()
+	}
+
+	/// Number of loaded nodes dropped for breaking the configuration.
+	pub fn dropped_count(&self) -> usize {
+		self.dropped.values().sum()
+	}
+
+	/// Record an entry that could not be loaded.
+	fn skip(&mut self, index: usize, url: String, error: &Error) {
+		let reason = SkipReason::from_error(error);
+		*self.skipped.entry(reason).or_insert(0) += 1;
+		if self.skipped_nodes.len() < MAX_SKIPPED_NODES {
+			self.skipped_nodes.push(SkippedNode { index: index, url: url, reason: reason, error: error.to_string() });
+		}
+	}
+}
+
+impl Display for LoadReport {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "skipped {} of {} persisted nodes", self.skipped_count(), self.total)?;
+		for (i, (reason, count)) in self.skipped.iter().enumerate() {
+			write!(f, "{}{} {}", if i == 0 { ": " } else { ", " }, count, reason)?;
+		}
+		Ok(())
+	}
+}
+
+/// Why a node is excluded from dialing or flagged, as returned by `NodeTable::entry_status`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntryStatus {
+	/// The entry with its statistics, `None` for a banned node that is not in the table.
+	pub entry: Option<NodeTableEntry>,
+	/// Reason of an active ban.
+	pub ban_reason: Option<String>,
+	/// End of an active ban.
+	pub banned_until: Option<SystemTime>,
+	/// Reason of a useless mark.
+	pub useless_reason: Option<String>,
+	/// Times the useless mark was repeated with the same reason, e.g. by a protocol handler for each failed request.
+	pub useless_repeats: u32,
+	/// Id announced in the Hello packet of the last dial of the node if it was another one, see
+	/// `NodeTable::note_id_mismatch`. Cleared by the next successful session.
+	pub mismatched_id: Option<NodeId>,
+	/// Every reason the selection skips the node, see `TableState::select_for`.
+	pub exclusions: Vec<DialExclusion>,
+	/// Whether the node is selected for dialing, unless `NodeTableConfig::operator_clusters` picks another node of
+	/// its cluster.
+	pub dialable: bool,
+	/// When the last of the exclusions ends if they all end by themselves, `None` if the node is dialable or one of
+	/// them lasts until something changes.
+	pub next_eligible: Option<SystemTime>,
+}
+
+/// Why `TableState::select_for` skips a node, see `EntryStatus::exclusions`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialExclusion {
+	/// Marked as useless. The marks of boot nodes end at `until`, see `NodeTableConfig::boot_retry_interval`.
+	Useless { reason: String, until: Option<SystemTime> },
+	/// Banned until `until`.
+	Banned { reason: String, until: SystemTime },
+	/// At our own public address or the one of another instance on this host.
+	OwnAddress,
+	/// Not required while only reserved peers are dialled.
+	ReservedOnly,
+	/// Boot node that is not needed once the table is bootstrapped, see `TableState::is_bootstrapped`.
+	Bootstrapped,
+	/// In dial backoff after failing, until `until`.
+	Backoff { until: SystemTime },
+	/// Address kept out by the `--allow-ips` rule `rule`, see `NodeEndpoint::blocking_rule`.
+	Filtered { rule: String },
+	/// None of our protocols among the capabilities of the node.
+	Capabilities,
+	/// We have a session with the node.
+	Connected,
+	/// The node is not required and shares the IP of a node we have a session with.
+	ConnectedIp,
+	/// `NodeTableConfig::max_ports_per_ip` other ports on the IP of the node were dialled recently.
+	PortCap,
+}
+
+impl DialExclusion {
+	/// End of the exclusion if it ends by itself.
+	pub fn until(&self) -> Option<SystemTime> {
+		match *self {
+			DialExclusion::Useless { until, .. } => until,
+			DialExclusion::Banned { until, .. } | DialExclusion::Backoff { until } => Some(until),
+			_ => None,
+		}
+	}
+}
+
+/// What `TableState::dial_exclusions` checks the nodes against, gathered once per selection.
+struct DialContext<'a> {
+	policy: &'a DialPolicy,
+	filter: &'a IpFilter,
+	/// Protocols the nodes required for count as required, see `TableState::select_for`.
+	protocols: Vec<ProtocolId>,
+	now: Instant,
+	bootstrapped: bool,
+	/// IPs of the nodes we have a session with if `DialPolicy::exclude_connected` is set.
+	connected_ips: HashSet<IpAddr>,
+	/// Ports dialled recently or selected on each IP, see `TableState::within_port_cap`.
+	ports: HashMap<IpAddr, HashSet<u16>>,
+}
+
+/// Dial backoff of a failing node.
+struct DialBackoff {
+	/// Consecutive failures.
+	failures: u32,
+	/// The node is not dialed before this time.
+	until: Instant,
+}
+
+/// Ban or useless mark with its reason.
+struct Exclusion {
+	/// End of a ban, or when the node was marked as useless.
+	time: Instant,
+	/// Why the node was excluded.
+	reason: String,
+	/// Compatibility epoch the node was excluded in.
+	epoch: u32,
+	/// Useless marks repeated with the same reason since the node was first marked, see `mark_as_useless`. Always
+	/// zero for bans.
+	repeats: u32,
+}
+
+/// Reason of the useless marks of peers that disconnected with an incompatible protocol. These marks only apply in
+/// the compatibility epoch they were made in, see `NodeTable::bump_compat_epoch`.
+pub const INCOMPATIBLE_PROTOCOL: &str = "incompatible protocol";
+
+/// Number of distinct admission hook reasons counted separately, see `NodeTableStats::admission_rejected`. Any
+/// further reason is counted as `other`.
+const MAX_ADMISSION_REASONS: usize = 16;
+/// New nodes waiting for a deferred admission hook at most, see `TableState::set_deferred_admission_hook`. Further
+/// nodes are not added, discovery finds them again.
+const MAX_AWAITING_ADMISSION: usize = 1024;
+
+/// Decision of an admission hook on a node about to be added, see `NodeTable::set_admission_hook`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+	/// Add the node.
+	Accept,
+	/// Do not add the node.
+	Reject {
+		/// Why the node was refused, counted in the stats.
+		reason: String,
+	},
+	/// Add the node but dial it only after all other nodes.
+	AcceptUntrusted,
+}
+
+/// Policy applied to new nodes on top of the built-in checks. Called with the node and the source it was learned
+/// from.
+pub type AdmissionHook = Box<Fn(&Node, NodeSource) -> Admission + Send + Sync>;
+
+/// Known nodes, their exclusions and dial state, with the selection logic. Kept in memory only, `NodeTable` loads
+/// it from and saves it to a persistence backend.
+pub struct TableState {
+	nodes: HashMap<NodeId, Node>,
+	useless_nodes: HashMap<NodeId, Exclusion>,
+	/// Required nodes logged by `warn_useless_required` as still marked useless.
+	useless_required_warned: HashSet<NodeId>,
+	banned: HashMap<NodeId, Exclusion>,
+	backoff: HashMap<NodeId, DialBackoff>,
+	tombstones: HashMap<NodeId, Instant>,
+	local_id: Option<NodeId>,
+	local_endpoints: Vec<NodeEndpoint>,
+	/// Endpoints of our own replaced by `set_local_endpoints`, excluded until the given time.
+	former_local_endpoints: Vec<(NodeEndpoint, Instant)>,
+	sibling_endpoints: Vec<SocketAddr>,
+	sibling_rejected: usize,
+	id_mismatches: usize,
+	/// Nodes whose last dial announced the given other id, see `note_id_mismatch`.
+	mismatch_flags: HashMap<NodeId, NodeId>,
+	rejected: usize,
+	subnet_rejected: usize,
+	low_port_rejected: usize,
+	tombstone_rejected: usize,
+	ban_rejected: usize,
+	inbound_mismatches: usize,
+	admission: Option<Arc<AdmissionHook>>,
+	/// The hook is called without the table lock, see `set_deferred_admission_hook`.
+	admission_deferred: bool,
+	/// New nodes waiting for the deferred hook with the source they were learned from.
+	awaiting_admission: Vec<(Node, NodeSource)>,
+	/// Decisions of the deferred hook being applied by `admit_decided`.
+	admitted: HashMap<NodeId, Admission>,
+	admission_rejected: BTreeMap<String, usize>,
+	meta_truncated: usize,
+	load_report: LoadReport,
+	forced_dials: Vec<NodeId>,
+	/// Bumped when the protocol versions or the chain rules change.
+	compat_epoch: u32,
+	/// Chain we are on, see `set_local_chain_info`.
+	local_chain: Option<PeerChainInfo>,
+	/// End of the warm start, while the nodes connected at the last clean shutdown are dialled first.
+	warm_until: Option<Instant>,
+	stale_start: Option<StaleStart>,
+	membership: MembershipHandle,
+	/// Generation the membership handles were last refreshed at.
+	membership_generation: Option<u64>,
+	/// Start of a suspected outage of our own connectivity, see `note_global_outage_suspected`.
+	outage_since: Option<Instant>,
+	/// Nodes that failed during the current outage.
+	quarantine: HashSet<NodeId>,
+	outages: usize,
+	history: OutcomeHistory,
+	/// Peers of the last load of the static peers file and whether they are required.
+	static_peers: HashMap<NodeId, bool>,
+	/// Canonicalized IPs of the nodes with an active session and the number of their sessions, see
+	/// `session_opened`.
+	active_sessions: HashMap<NodeId, (IpAddr, usize)>,
+	/// Bumped when `active_sessions` changes, which does not `touch` the table.
+	session_changes: u64,
+	/// Bumped by the changes that may change the dial selection of any node, see `reorder`.
+	selection_changes: u64,
+	/// Nodes whose connection stats changed, by sequence number, at most `MAX_NODE_CHANGES`, see `touch_node`.
+	node_changes: VecDeque<(u64, NodeId)>,
+	/// Sequence number of the latest of `node_changes`.
+	node_change_seq: u64,
+	/// Counted by `select_for`, which only borrows the table.
+	connected_skipped: AtomicUsize,
+	connected_ip_skipped: AtomicUsize,
+	/// Latest evictions, oldest first, at most `MAX_RECENT_EVICTIONS`.
+	evictions: VecDeque<Eviction>,
+	eviction_counts: BTreeMap<EvictionReason, usize>,
+	/// Latest ended sessions, oldest first, at most `MAX_RECENTLY_CONNECTED`, see `note_disconnect`.
+	recently_connected: VecDeque<Disconnect>,
+	/// Ports dialled on each IP within `NodeTableConfig::port_window` and when, kept only with
+	/// `NodeTableConfig::max_ports_per_ip`.
+	dialled_ports: HashMap<IpAddr, Vec<(u16, Instant)>>,
+	port_capped: usize,
+	/// The loaded nodes are still to be checked by `reconcile`.
+	reconcile_pending: bool,
+	/// Saved nodes already dropped while loading, to be reported by `reconcile`.
+	load_dropped: Vec<(DropReason, PersistedNode)>,
+	/// Changed since the last save.
+	dirty: bool,
+	/// Bumped on every change, see `touch` and `reorder`.
+	generation: u64,
+	/// Operator clusters of the generation they were computed for, see `cluster_of`.
+	clusters: Mutex<Option<Clusters>>,
+	/// Result of `nodes_default` for the generation it was computed for.
+	#[cfg(any(test, feature = "test-helpers"))]
+	default_nodes: Mutex<Option<DefaultNodes>>,
+	clock: Box<Clock>,
+	config: NodeTableConfig,
+	mode: SelectionMode,
+}
+
+/// Node table backed by disk file. Dereferences to the `TableState` it loads and saves.
+pub struct NodeTable {
+	state: TableState,
+	persistence: Option<Arc<NodeTablePersistence>>,
+	persist: bool,
+	saves: usize,
+	save_failures: usize,
+	/// Kind of the last save error and how many saves in a row failed with it.
+	save_errors: Option<(String, usize)>,
+	/// Set when saving was given up, the table is kept in memory only then.
+	persistence_error: Option<String>,
+	/// Set while another process holds the storage, see `lock_persistence`.
+	locked_out: bool,
+	/// Saved table still being read, see `with_backend_streaming`.
+	loading: Option<StreamingLoad>,
+	/// Saves are suppressed while one of these has not expired, see `freeze`.
+	freezes: Arc<Mutex<Freezes>>,
+	/// Time of the last `maintenance` run.
+	last_maintenance: Option<Instant>,
+	/// When `maintenance` refreshes the table next.
+	next_refresh: Option<Instant>,
+	/// When `maintenance` last saved the table, or first ran, see `autosave_delay`.
+	last_save: Option<Instant>,
+	/// When the nodes of the sweep of a stale table were last handed out by `sweep_batch`.
+	sweep_pings: HashMap<NodeId, Instant>,
+}
+
+impl Deref for NodeTable {
+	type Target = TableState;
+
+	fn deref(&self) -> &TableState {
+		&self.state
+	}
+}
+
+impl DerefMut for NodeTable {
+	fn deref_mut(&mut self) -> &mut TableState {
+		&mut self.state
+	}
+}
+
+/// Ordering of a table loaded from a stale file, see `NodeTableConfig::stale_file_age`.
+struct StaleStart {
+	/// Saved reliability of the loaded nodes.
+	hints: HashMap<NodeId, u8>,
+	/// Most reliable nodes not verified since the load.
+	pending: HashSet<NodeId>,
+	until: Instant,
+}
+
+/// Nodes that look run by one operator, see `TableState::cluster_of`.
+#[derive(Default)]
+struct Clusters {
+	generation: u64,
+	/// Index in `members` of the cluster of each clustered node.
+	of: HashMap<NodeId, usize>,
+	/// Clusters of at least two nodes, sorted by id.
+	members: Vec<Vec<NodeId>>,
+}
+
+/// Nodes allowed by the default filter in `nodes` order, see `TableState::nodes_default`.
+#[cfg(any(test, feature = "test-helpers"))]
+struct DefaultNodes {
+	generation: u64,
+	/// The order changes at this time even if the table does not, see `order_expiry`.
+	until: Option<Instant>,
+	ids: Vec<NodeId>,
+}
+
+/// Contents of a saved table as loaded by `NodeTable::load`.
+#[derive(Default)]
+struct SavedTable {
+	nodes: Vec<Node>,
+	exclusions: Vec<PersistedExclusion>,
+	compat_epoch: u32,
+	history: Vec<OutcomeBucket>,
+	report: LoadReport,
+	/// Time of the save, `None` for files saved before it was recorded.
+	saved_at: Option<SystemTime>,
+	/// Saved reliability of the nodes, see `Node::reliability`.
+	reliability: HashMap<NodeId, u8>,
+	/// Any saved node is warm.
+	warm: bool,
+}
+
+impl SavedTable {
+	/// Convert saved nodes and add them to `nodes`. The ones that do not parse are recorded in the report.
+	fn add_nodes(&mut self, entries: Vec<PersistedNode>) {
+		self.nodes.reserve(entries.len());
+		for entry in entries {
+			let index = self.report.total;
+			self.report.total += 1;
+			let (url, reliability) = (entry.url.clone(), entry.reliability);
+			match Node::from_persisted(entry) {
+				Ok(node) => {
+					if let Some(reliability) = reliability {
+						self.reliability.insert(node.id, reliability);
+					}
+					self.warm |= node.warm;
+					self.nodes.push(node);
+				},
+				Err(e) => self.report.skip(index, url, &e),
+			}
+		}
+	}
+
+	/// Take the fields of `table` besides the nodes.
+	fn set_fields(&mut self, table: PersistedTable) {
+		self.exclusions = table.exclusions;
+		self.compat_epoch = table.compat_epoch;
+		self.history = table.history;
+		self.saved_at = table.saved_at.map(|secs| UNIX_EPOCH + Duration::from_secs(min(secs, MAX_CONTACT_SECS)));
+	}
+}
+
+/// Number of saved nodes the loading thread of `NodeTable::with_backend_streaming` hands over at once.
+const LOAD_BATCH: usize = 64;
+
+/// Part of the saved table read by the loading thread.
+enum Loaded {
+	/// The bans and useless marks saved before the nodes.
+	Exclusions(Vec<PersistedExclusion>),
+	/// The next saved nodes in file order.
+	Nodes(Vec<PersistedNode>),
+	/// The table was saved on another network, nothing is loaded.
+	OtherNetwork(String),
+	/// The fields saved besides the nodes, or why the rest of the table could not be read.
+	Done(Result<PersistedTable, String>),
+}
+
+/// Sink of the loading thread, handing the nodes over in batches of `LOAD_BATCH`.
+struct LoadSender {
+	sender: mpsc::Sender<Loaded>,
+	batch: Vec<PersistedNode>,
+	network_id: Option<String>,
+	other_network: Option<String>,
+}
+
+impl LoadSender {
+	fn flush(&mut self) -> bool {
+		if self.batch.is_empty() {
+			return true;
+		}
+		let batch = mem::replace(&mut self.batch, Vec::with_capacity(LOAD_BATCH));
+		self.sender.send(Loaded::Nodes(batch)).is_ok()
+	}
+}
+
+impl TableSink for LoadSender {
+	fn header(&mut self, header: &PersistedTable) -> bool {
+		self.other_network = other_network(header, &self.network_id);
+		if self.other_network.is_some() {
+			return false;
+		}
+		// a closed channel means the table is gone
+		self.sender.send(Loaded::Exclusions(header.exclusions.clone())).is_ok()
+	}
+
+	fn node(&mut self, node: PersistedNode) -> bool {
+		self.batch.push(node);
+		self.batch.len() < LOAD_BATCH || self.flush()
+	}
+}
+
+/// Load started by `NodeTable::with_backend_streaming`.
+struct StreamingLoad {
+	receiver: Mutex<mpsc::Receiver<Loaded>>,
+	/// The saved fields and report so far, without the nodes already merged.
+	saved: SavedTable,
+	/// The saved bans and useless marks were restored already.
+	exclusions_restored: bool,
+}
+
+/// Freezes of a table by id with the time they expire at, see `NodeTable::freeze`.
+#[derive(Default)]
+struct Freezes {
+	deadlines: HashMap<usize, Instant>,
+	next_id: usize,
+	/// The last freeze was dropped or expired since the last `NodeTable::save_thawed`.
+	thawed: bool,
+}
+
+impl Freezes {
+	fn end(&mut self, id: usize) {
+		if self.deadlines.remove(&id).is_some() && self.deadlines.is_empty() {
+			self.thawed = true;
+		}
+	}
+}
+
+/// Keeps a node table from being saved while it is alive, e.g. while a backup copies the node table file. Dropping
+/// it lets the next save through, the changes made meanwhile are saved by `NodeTable::save_thawed`. See
+/// `NodeTable::freeze`.
+pub struct FreezeGuard {
+	id: usize,
+	freezes: Arc<Mutex<Freezes>>,
+}
+
+impl Drop for FreezeGuard {
+	fn drop(&mut self) {
+		self.freezes.lock().end(self.id);
+	}
+}
+
+/// Returns the network `table` was saved on if it is not the `expected` one.
+fn other_network(table: &PersistedTable, expected: &Option<String>) -> Option<String> {
+	match (table.network_id.as_ref(), expected.as_ref()) {
+		(Some(network), Some(expected)) if network != expected => Some(network.clone()),
+		_ => None,
+	}
+}
+
+/// Session recorded by `TableState::note_disconnect`.
+struct Disconnect {
+	id: NodeId,
+	time: Instant,
+	/// Ended by the remote node or the connection, not by us.
+	remote: bool,
+}
+
+/// Number of entries shown by the debug output of a node table.
+const DEBUG_ENTRIES: usize = 3;
+
+/// Entry counts and the first few entries in dial order.
+impl fmt::Debug for TableState {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		let entries = self.ordered_entries();
+		f.debug_struct("TableState")
+			.field("nodes", &self.nodes.len())
+			.field("useless", &self.useless_nodes.len())
+			.field("banned", &self.banned.len())
+			.field("backed_off", &self.backoff.len())
+			.field("first", &&entries[..min(DEBUG_ENTRIES, entries.len())])
+			.finish()
+	}
+}
+
+impl fmt::Debug for NodeTable {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.debug_struct("NodeTable")
+			.field("state", &self.state)
+			.field("persist", &self.persist)
+			.field("saves", &self.saves)
+			.field("persistence_error", &self.persistence_error)
+			.field("loading", &self.loading.is_some())
+			.finish()
+	}
+}
+
+impl NodeTable {
+	#[cfg(test)]
+	pub fn new(path: Option<String>) -> NodeTable {
+		NodeTable::with_config(path, true, NodeTableConfig::default())
+	}
+
+	/// Create a node table with the given tuning. The table is loaded from and saved to the `nodes.json` file in
+	/// `path` only if `persist` is set, otherwise it is kept in memory only. The file is not encrypted and an
+	/// encrypted one does not load, see `FilePersistence::with_key`.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn with_config(path: Option<String>, persist: bool, config: NodeTableConfig) -> NodeTable {
+		let persistence = path.map(|path| Arc::new(::persistence::FilePersistence::for_table(path, &config)) as Arc<NodeTablePersistence>);
+		NodeTable::with_backend(persistence, persist, config)
+	}
+
+	/// Create a node table loaded from and saved to `persistence`.
+	pub fn with_persistence(persistence: Box<NodeTablePersistence>, config: NodeTableConfig) -> NodeTable {
+		NodeTable::with_backend(Some(Arc::from(persistence)), true, config)
+	}
+
+	/// Create a node table kept in `persistence` if `persist` is set, otherwise in memory only until persistence is
+	/// enabled with `set_persistent`.
+	pub fn with_backend(persistence: Option<Arc<NodeTablePersistence>>, persist: bool, config: NodeTableConfig) -> NodeTable {
+		let mut table = NodeTable::wrap(TableState::new(config), persistence, persist);
+		if persist {
+			table.load_saved();
+			table.dirty = false;
+			table.refresh_membership();
+		}
+		table
+	}
+
+	/// Like `with_backend` with `persist` set, but read the saved table on a helper thread so that its first nodes
+	/// can be dialled while the rest is still read. Returns once `LOAD_BATCH` nodes or the whole table are loaded,
+	/// the rest is merged by `apply_loaded`. The table is not saved until the load completed. Only the nodes read
+	/// before an error are kept then.
+	pub fn with_backend_streaming(persistence: Arc<NodeTablePersistence>, config: NodeTableConfig) -> NodeTable {
+		let mut table = NodeTable::wrap(TableState::new(config), Some(persistence.clone()), true);
+		if !table.lock_persistence() {
+			table.dirty = false;
+			return table;
+		}
+		let (sender, receiver) = mpsc::channel();
+		let mut sink = LoadSender {
+			sender: sender,
+			batch: Vec::with_capacity(LOAD_BATCH),
+			network_id: table.config.network_id.clone(),
+			other_network: None,
+		};
+		let backend = persistence;
+		let spawned = thread::Builder::new().name("node-table-load".into()).spawn(move || {
+			let result = backend.load_streaming(&mut sink).map_err(|e| format!("{:?}", e));
+			drop(backend);
+			let done = match sink.other_network.take() {
+				Some(network) => Loaded::OtherNetwork(network),
+				None if sink.flush() => Loaded::Done(result),
+				None => return,
+			};
+			let _ = sink.sender.send(done);
+		});
+		if let Err(e) = spawned {
+			warn!("Error starting to load the node table in the background, loading it now: {}", e);
+			table.load_saved();
+			table.dirty = false;
+			table.refresh_membership();
+			return table;
+		}
+		table.loading = Some(StreamingLoad {
+			receiver: Mutex::new(receiver),
+			saved: SavedTable::default(),
+			exclusions_restored: false,
+		});
+		table.receive_loaded(LOAD_BATCH);
+		table.dirty = false;
+		table.refresh_membership();
+		table
+	}
+
+	/// Merge the saved nodes read since the last call, and restore the rest of the saved table like `load_saved`
+	/// once it is read. Does not wait for the loading thread. Returns `true` if the load completed with this call,
+	/// e.g. to check the loaded nodes with `reconcile`.
+	pub fn apply_loaded(&mut self) -> bool {
+		self.receive_loaded(0)
+	}
+
+	/// Whether the saved table is loaded completely, see `with_backend_streaming`. Always `true` for tables loaded
+	/// at once.
+	pub fn load_complete(&self) -> bool {
+		self.loading.is_none()
+	}
+
+	/// Apply what the loading thread read, waiting until at least `wait_for` nodes were handed over or the load
+	/// completed. Returns `true` if it completed.
+	fn receive_loaded(&mut self, wait_for: usize) -> bool {
+		let mut received = 0;
+		loop {
+			let message = match self.loading {
+				Some(ref loading) => {
+					let receiver = loading.receiver.lock();
+					let message = if received < wait_for {
+						receiver.recv().ok()
+					} else {
+						match receiver.try_recv() {
+							Ok(message) => Some(message),
+							Err(mpsc::TryRecvError::Empty) => return false,
+							Err(mpsc::TryRecvError::Disconnected) => None,
+						}
+					};
+					message.unwrap_or_else(|| Loaded::Done(Err("the loading thread stopped".into())))
+				},
+				None => return false,
+			};
+			if let Loaded::Nodes(ref batch) = message {
+				received += batch.len();
+			}
+			if self.apply_load_message(message) {
+				return true;
+			}
+		}
+	}
+
+	fn apply_load_message(&mut self, message: Loaded) -> bool {
+		match message {
+			Loaded::Exclusions(exclusions) => {
+				if let Some(ref mut loading) = self.loading {
+					loading.exclusions_restored = true;
+				}
+				self.state.restore_exclusions(exclusions);
+				false
+			},
+			Loaded::Nodes(batch) => {
+				let nodes = match self.loading {
+					Some(ref mut loading) => {
+						loading.saved.add_nodes(batch);
+						mem::replace(&mut loading.saved.nodes, Vec::new())
+					},
+					None => return false,
+				};
+				self.state.restore_nodes(nodes);
+				false
+			},
+			Loaded::OtherNetwork(network) => {
+				self.loading = None;
+				self.discard_other_network(&network);
+				true
+			},
+			Loaded::Done(result) => {
+				let (mut saved, exclusions_restored) = match self.loading.take() {
+					Some(loading) => (loading.saved, loading.exclusions_restored),
+					None => return false,
+				};
+				match result {
+					Ok(table) => saved.set_fields(table),
+					Err(e) => warn!("Error loading node table, keeping the {} saved nodes read: {}", saved.report.total, e),
+				}
+				if exclusions_restored {
+					saved.exclusions.clear();
+				}
+				self.state.restore(saved);
+				true
+			},
+		}
+	}
+
+	/// Keep a table saved on another network from being replaced, see `NodeTablePersistence::backup`.
+	fn discard_other_network(&self, network: &str) {
+		let expected = self.config.network_id.as_ref().map_or("", |id| id.as_str());
+		warn!("Node table was saved on network {}, not {}. Starting with an empty table.", network, expected);
+		if let Some(ref persistence) = self.persistence {
+			if let Err(e) = persistence.backup() {
+				warn!("{:?}", e);
+			}
+		}
+	}
+
+	fn wrap(state: TableState, persistence: Option<Arc<NodeTablePersistence>>, persist: bool) -> NodeTable {
+		NodeTable {
+			state: state,
+			persistence: persistence,
+			persist: persist,
+			saves: 0,
+			save_failures: 0,
+			save_errors: None,
+			persistence_error: None,
+			locked_out: false,
+			loading: None,
+			freezes: Arc::new(Mutex::new(Freezes::default())),
+			last_maintenance: None,
+			next_refresh: None,
+			last_save: None,
+			sweep_pings: HashMap::new(),
+		}
+	}
+
+	/// Enable or disable saving the table. Enabling it does not load a table saved by an earlier run, the file is
+	/// overwritten on the next save instead. Call `load_saved` to merge it first.
+	pub fn set_persistent(&mut self, persist: bool) {
+		if persist {
+			self.lock_persistence();
+		}
+		self.persist = persist;
+		self.dirty = true;
+	}
+
+	/// Merge the saved nodes, see `ingest`, and restore their bans and useless marks. Expired bans are dropped. If
+	/// any saved node is warm the warm start begins, see `warm_entries`, unless the file is stale, see
+	/// `reverify_sweep`.
+	pub fn load_saved(&mut self) {
+		if !self.lock_persistence() {
+			return;
+		}
+		let saved = self.load();
+		self.state.restore(saved);
+	}
+
+	/// Take the storage before it is read or written, see `NodeTablePersistence::lock`. If it fails, e.g. because
+	/// another instance uses the same `nodes.json`, the table is kept in memory only until `reset_persistence` takes
+	/// the storage. Returns `false` then.
+	fn lock_persistence(&mut self) -> bool {
+		let result = match self.persistence {
+			Some(ref persistence) => persistence.lock(),
+			None => return true,
+		};
+		match result {
+			Ok(()) => {
+				self.locked_out = false;
+				true
+			},
+			Err(e) => {
+				self.lock_out(e);
+				false
+			},
+		}
+	}
+
+	/// Keep the table in memory only while another process holds the storage, see `lock_persistence`.
+	fn lock_out(&mut self, e: Error) {
+		warn!("{}", Colour::Red.bold().paint(format!("Node table not loaded or saved, keeping it in memory only: {}. Is another instance running with the same data directory?", e)));
+		self.locked_out = true;
+		self.persistence_error = Some(e.to_string());
+	}
+
+	/// Returns a summary of the table contents, see `TableState::stats`, and of its saves.
+	pub fn stats(&self, filter: &IpFilter) -> NodeTableStats {
+		NodeTableStats {
+			saves: self.saves,
+			save_failures: self.save_failures,
+			persistence_error: self.persistence_error.clone(),
+			loading: self.loading.is_some(),
+			..self.state.stats(filter)
+		}
+	}
+
+	/// Returns the anonymized summary passed to the telemetry callback, see `Host::set_table_telemetry`.
+	pub fn telemetry(&self) -> TableTelemetry {
+		TableTelemetry::new(&self.stats(&IpFilter::default()), self.failure_distribution())
+	}
+
+	/// Append the table metrics to `w` in the Prometheus text exposition format: gauges for the table contents, a
+	/// histogram of the failure percentages of known nodes and counters for rejected nodes and saves.
+	#[cfg(feature = "metrics")]
+	pub fn render_prometheus(&self, w: &mut String) {
+		use std::fmt::Write;
+
+		fn header(w: &mut String, name: &str, kind: &str, help: &str) {
+			let _ = writeln!(w, "# HELP {} {}", name, help);
+			let _ = writeln!(w, "# TYPE {} {}", name, kind);
+		}
+
+		let stats = self.stats(&IpFilter::default());
+		let reserved = self.nodes.values().filter(|n| n.peer_type == PeerType::Required || !n.required_for.is_empty()).count();
+		let gauges = [
+			("devp2p_node_table_nodes", "Number of known nodes.", stats.total),
+			("devp2p_node_table_useless_nodes", "Nodes marked as useless.", stats.useless),
+			("devp2p_node_table_banned_nodes", "Active bans, including nodes not in the table.", stats.banned),
+			("devp2p_node_table_reserved_nodes", "Nodes required globally or for a protocol.", reserved),
+		];
+		for &(name, help, value) in &gauges {
+			header(w, name, "gauge", help);
+			let _ = writeln!(w, "{} {}", name, value);
+		}
+
+		let name = "devp2p_node_table_failure_percentage";
+		header(w, name, "histogram", "Failure percentage of known nodes.");
+		let percentages: Vec<usize> = self.nodes.values().map(Node::failure_percentage).collect();
+		for le in &FAILURE_PERCENTAGE_BUCKETS {
+			let _ = writeln!(w, "{}_bucket{{le=\"{}\"}} {}", name, le, percentages.iter().filter(|p| *p <= le).count());
+		}
+		let _ = writeln!(w, "{}_bucket{{le=\"+Inf\"}} {}", name, percentages.len());
+		let _ = writeln!(w, "{}_sum {}", name, percentages.iter().sum::<usize>());
+		let _ = writeln!(w, "{}_count {}", name, percentages.len());
+
+		let name = "devp2p_node_table_rejected_total";
+		header(w, name, "counter", "Nodes not added to the table, by cause.");
+		let rejections = [
+			("own", stats.rejected),
+			("subnet", stats.subnet_rejected),
+			("port", stats.low_port_rejected),
+			("tombstone", stats.tombstone_rejected),
+			("banned", stats.ban_rejected),
+		];
+		for &(cause, value) in &rejections {
+			let _ = writeln!(w, "{}{{cause=\"{}\"}} {}", name, cause, value);
+		}
+
+		let name = "devp2p_node_table_saves_total";
+		header(w, name, "counter", "Node table saves, by result.");
+		let _ = writeln!(w, "{}{{result=\"ok\"}} {}", name, stats.saves);
+		let _ = writeln!(w, "{}{{result=\"error\"}} {}", name, stats.save_failures);
+	}
+
+	/// Check the loaded nodes against the configuration, see `TableState::reconcile`, and keep the dropped ones with
+	/// `NodeTablePersistence::save_dropped`.
+	pub fn reconcile(&mut self, filter: &IpFilter, keep: &HashSet<NodeId>) {
+		let dropped = self.state.reconcile(filter, keep);
+		if let (false, Some(persistence)) = (dropped.is_empty(), self.persistence.as_ref()) {
+			if let Err(e) = persistence.save_dropped(&dropped) {
+				warn!("Error keeping the dropped nodes: {:?}", e);
+			}
+		}
+	}
+
+	/// Do the periodic work on the table in one call: count the dial backoffs that ended, end the sweep of a stale
+	/// table after `REVERIFY_TIMEOUT` and, every `NodeTableConfig::autosave_interval`, clear the useless marks, drop
+	/// the expired and excess nodes except the ones in `keep` and save. The first call starts the refresh interval.
+	/// The suggested delay until the next call follows the number of sessions against the `target`, but does not
+	/// pass the next refresh. Between the refreshes the table is saved more often the shorter that delay is, see
+	/// `autosave_delay`. The host dials again when backoffs ended and pings a `sweep_batch` on each run.
+	pub fn maintenance(&mut self, now: Instant, keep: &HashSet<NodeId>, target: usize) -> MaintenanceReport {
+		let mut report = MaintenanceReport::default();
+		let since = self.last_maintenance.unwrap_or(now);
+		report.backoff_expired = self.state.backoff.values().filter(|b| b.until > since && b.until <= now).count();
+		self.last_maintenance = Some(now);
+
+		if self.state.stale_start.as_ref().map_or(false, |stale| stale.until <= now) {
+			let pending = self.state.stale_start.take().map_or(0, |stale| stale.pending.len());
+			info!("Node table: {} of the most reliable saved nodes did not answer again, dialling by connection statistics", pending);
+		}
+		report.stale_pending = self.state.stale_start.as_ref().map_or(0, |stale| stale.pending.len());
+
+		if report.stale_pending == 0 {
+			self.sweep_pings.clear();
+		}
+
+		let interval = self.state.config.autosave_interval;
+		let delay = maintenance_delay(self.state.active_sessions.len(), target, &self.state.config);
+		let refresh = self.next_refresh.map_or(false, |at| at <= now);
+		if refresh {
+			report.refreshed = true;
+			report.cleared_useless = self.state.useless_nodes.len();
+			self.state.clear_useless();
+			report.expired = self.state.drop_expired(keep);
+			report.evicted = self.state.evict_excess(keep);
+			self.next_refresh = None;
+		}
+		let last_save = *self.last_save.get_or_insert(now);
+		if refresh || last_save + autosave_delay(delay, &self.state.config) <= now {
+			let saves = self.saves;
+			self.save();
+			report.saved = self.saves > saves;
+			self.last_save = Some(now);
+		}
+		let next_refresh = *self.next_refresh.get_or_insert(now + interval);
+		report.next_delay = min(delay, next_refresh.duration_since(now));
+		report
+	}
+
+	/// Returns at most `limit` nodes of `reverify_sweep` allowed by `filter` that were not returned within
+	/// `SWEEP_REPING_INTERVAL`, most reliable first, to be pinged through discovery again. Discovery drops the pings
+	/// that are not answered, so the nodes of the sweep are handed out again until they are verified.
+	pub fn sweep_batch(&mut self, now: Instant, filter: &IpFilter, limit: usize) -> Vec<NodeEntry> {
+		let batch: Vec<NodeEntry> = {
+			let pings = &self.sweep_pings;
+			self.state.reverify_sweep(filter).into_iter()
+				.filter(|e| pings.get(&e.id).map_or(true, |at| *at + SWEEP_REPING_INTERVAL <= now))
+				.take(limit)
+				.collect()
+		};
+		for entry in &batch {
+			self.sweep_pings.insert(entry.id, now);
+		}
+		batch
+	}
+
+	/// Save the table if anything changed since the last save.
+	pub fn save(&mut self) {
+		if let Some((persistence, table)) = self.serialize() {
+			match persistence.save(&table) {
+				Ok(()) => self.saved(table.nodes.len(), table.omitted),
+				Err(e) => self.save_failed(e),
+			}
+		}
+	}
+
+	/// Like `save`, but give up waiting if saving takes longer than `timeout`. Saving is done on a helper thread
+	/// which is left behind in that case, so a hanging disk cannot block the caller.
+	pub fn save_with_timeout(&mut self, timeout: Duration) {
+		let (persistence, table) = match self.serialize() {
+			Some(serialized) => serialized,
+			None => return,
+		};
+		let counts = (table.nodes.len(), table.omitted);
+		let (tx, rx) = mpsc::channel();
+		let spawned = thread::Builder::new().name("node-table-save".into()).spawn(move || {
+			let result = persistence.save(&table);
+			// released before the result is received, so that a table dropped after a save does not hold the storage
+			drop(persistence);
+			let _ = tx.send(result);
+		});
+		if let Err(e) = spawned {
+			warn!("Error starting node table save: {:?}", e);
+			self.save_failures += 1;
+			return;
+		}
+		match rx.recv_timeout(timeout) {
+			Ok(Ok(())) => self.saved(counts.0, counts.1),
+			Ok(Err(e)) => self.save_failed(e),
+			Err(_) => {
+				warn!("Node table not saved within {:?}, giving up", timeout);
+				self.save_failures += 1;
+			},
+		}
+	}
+
+	fn saved(&mut self, nodes: usize, omitted: Option<usize>) {
+		match omitted {
+			Some(omitted) => debug!(target: "network", "Saved {} nodes, {} omitted to stay within the file size limit", nodes, omitted),
+			None => trace!(target: "network", "Saved {} nodes", nodes),
+		}
+		self.dirty = false;
+		self.saves += 1;
+		self.save_errors = None;
+	}
+
+	/// Count a failed save. After `PERSISTENT_SAVE_FAILURES` saves in a row failed with the same kind of error, e.g.
+	/// because the data directory is read-only, saving is given up with a single warning instead of one per save.
+	fn save_failed(&mut self, e: Error) {
+		self.save_failures += 1;
+		// the storage may be taken on the first save, another instance that started meanwhile may have it
+		if held_elsewhere(&e) {
+			self.lock_out(e);
+			return;
+		}
+		let kind = match *e.kind() {
+			ErrorKind::Io(ref io) => format!("{:?}", io.kind()),
+			_ => e.to_string(),
+		};
+		let count = match self.save_errors {
+			Some((ref last, count)) if *last == kind => count + 1,
+			_ => 1,
+		};
+		self.save_errors = Some((kind, count));
+		if count < PERSISTENT_SAVE_FAILURES {
+			warn!("Error saving node table: {:?}", e);
+		} else {
+			warn!("Node table could not be saved {} times in a row, keeping it in memory only: {}", count, e);
+			self.persistence_error = Some(e.to_string());
+		}
+	}
+
+	/// Save again after saving was given up because of repeated errors, e.g. once the permissions of the data
+	/// directory are fixed. The table is saved on the next refresh. Returns `false` if saving was not given up, or
+	/// if the storage is still held by another process, see `lock_persistence`. Once it is taken the saved table is
+	/// merged first.
+	pub fn reset_persistence(&mut self) -> bool {
+		self.save_errors = None;
+		if self.persistence_error.take().is_none() {
+			return false;
+		}
+		let locked_out = self.locked_out;
+		if !self.lock_persistence() {
+			return false;
+		}
+		if locked_out {
+			// the nodes saved by the other instance are merged, not overwritten
+			let saved = self.load();
+			self.state.restore(saved);
+		}
+		self.dirty = true;
+		true
+	}
+
+	/// Save the table and keep it from being saved again until the returned guard is dropped or `MAX_FREEZE` passed,
+	/// so that the file can be copied consistently. The table keeps changing in memory meanwhile, the changes are
+	/// saved by the first save after the freeze ended, see `save_thawed`. See `freeze_for`.
+	pub fn freeze(&mut self) -> FreezeGuard {
+		self.freeze_for(MAX_FREEZE)
+	}
+
+	/// Like `freeze`, but the freeze expires after `max` even if the guard is kept, e.g. if a backup tool died
+	/// without thawing the table. Freezes overlap, saving resumes once the last one ended.
+	pub fn freeze_for(&mut self, max: Duration) -> FreezeGuard {
+		self.save();
+		let deadline = self.clock.now_instant() + max;
+		let mut freezes = self.freezes.lock();
+		let id = freezes.next_id;
+		freezes.next_id += 1;
+		freezes.deadlines.insert(id, deadline);
+		FreezeGuard {
+			id: id,
+			freezes: self.freezes.clone(),
+		}
+	}
+
+	/// End the freeze of `guard` and save the changes made meanwhile unless another freeze is still active.
+	pub fn thaw(&mut self, guard: FreezeGuard) {
+		drop(guard);
+		self.freezes.lock().thawed = false;
+		self.save();
+	}
+
+	/// Save the changes made while the table was frozen once the last freeze ended, because its guard was dropped
+	/// or it expired. The host calls it on each maintenance tick. Returns `true` if a freeze ended since the last
+	/// call.
+	pub fn save_thawed(&mut self) -> bool {
+		if self.is_frozen() {
+			return false;
+		}
+		let thawed = mem::replace(&mut self.freezes.lock().thawed, false);
+		if thawed {
+			self.save();
+		}
+		thawed
+	}
+
+	/// Check if saves are suppressed by a freeze that has not expired.
+	pub fn is_frozen(&self) -> bool {
+		let now = self.clock.now_instant();
+		let mut freezes = self.freezes.lock();
+		let expired: Vec<usize> = freezes.deadlines.iter()
+			.filter(|&(_, deadline)| *deadline <= now)
+			.map(|(id, _)| *id)
+			.collect();
+		for id in expired {
+			freezes.end(id);
+		}
+		!freezes.deadlines.is_empty()
+	}
+
+	/// Check if the saved table holds the current one, i.e. the table is persisted and nothing changed since the last
+	/// save.
+	pub fn is_saved(&self) -> bool {
+		self.persist && self.persistence.is_some() && !self.dirty && self.persistence_error.is_none()
+	}
+
+	/// Collect the nodes to save. Returns `None` if the table is not persisted, has not changed, is still being
+	/// loaded or is frozen.
+	fn serialize(&self) -> Option<(Arc<NodeTablePersistence>, PersistedTable)> {
+		if !self.persist || !self.dirty || self.persistence_error.is_some() || self.loading.is_some() {
+			return None;
+		}
+		if self.is_frozen() {
+			debug!(target: "network", "Node table is frozen, not saving it");
+			return None;
+		}
+		let persistence = self.persistence.clone()?;
+		Some((persistence, self.state.to_persisted()))
+	}
+
+	fn load(&self) -> SavedTable {
+		let mut saved = SavedTable::default();
+		let persistence = match self.persistence {
+			Some(ref persistence) => persistence,
+			None => return saved,
+		};
+		let mut table = match persistence.load() {
+			Ok(table) => table,
+			Err(e) => {
+				warn!("Error loading node table: {:?}", e);
+				return saved;
+			},
+		};
+		if let Some(network) = other_network(&table, &self.config.network_id) {
+			self.discard_other_network(&network);
+			return saved;
+		}
+		saved.add_nodes(mem::replace(&mut table.nodes, Vec::new()));
+		saved.set_fields(table);
+		saved
+	}
+}
+
+impl TableState {
+	/// Create an empty table with the given tuning.
+	pub fn new(config: NodeTableConfig) -> TableState {
+		TableState {
+			nodes: HashMap::new(),
+			useless_nodes: HashMap::new(),
+			useless_required_warned: HashSet::new(),
+			banned: HashMap::new(),
+			backoff: HashMap::new(),
+			tombstones: HashMap::new(),
+			local_id: None,
+			local_endpoints: Vec::new(),
+			former_local_endpoints: Vec::new(),
+			sibling_endpoints: config.sibling_endpoints.clone(),
+			sibling_rejected: 0,
+			id_mismatches: 0,
+			mismatch_flags: HashMap::new(),
+			rejected: 0,
+			subnet_rejected: 0,
+			low_port_rejected: 0,
+			tombstone_rejected: 0,
+			ban_rejected: 0,
+			inbound_mismatches: 0,
+			admission: None,
+			admission_deferred: false,
+			awaiting_admission: Vec::new(),
+			admitted: HashMap::new(),
+			admission_rejected: BTreeMap::new(),
+			meta_truncated: 0,
+			load_report: LoadReport::default(),
+			forced_dials: Vec::new(),
+			compat_epoch: 0,
+			local_chain: None,
+			warm_until: None,
+			stale_start: None,
+			membership: MembershipHandle::default(),
+			membership_generation: None,
+			outage_since: None,
+			quarantine: HashSet::new(),
+			outages: 0,
+			history: OutcomeHistory::default(),
+			static_peers: HashMap::new(),
+			active_sessions: HashMap::new(),
+			session_changes: 0,
+			selection_changes: 0,
+			node_changes: VecDeque::new(),
+			node_change_seq: 0,
+			connected_skipped: AtomicUsize::new(0),
+			connected_ip_skipped: AtomicUsize::new(0),
+			evictions: VecDeque::new(),
+			recently_connected: VecDeque::new(),
+			dialled_ports: HashMap::new(),
+			port_capped: 0,
+			eviction_counts: BTreeMap::new(),
+			reconcile_pending: false,
+			load_dropped: Vec::new(),
+			dirty: false,
+			generation: 0,
+			clusters: Mutex::new(None),
+			#[cfg(any(test, feature = "test-helpers"))]
+			default_nodes: Mutex::new(None),
+			clock: Box::new(SystemClock),
+			config: config,
+			mode: SelectionMode::Normal,
+		}
+	}
+
+	/// Returns the number of known nodes by failure percentage, in the ranges of the Prometheus histogram: 0%, up to
+	/// 10%, 25%, 50%, 75%, 90% and above 90%.
+	pub fn failure_distribution(&self) -> [usize; FAILURE_RANGES] {
+		let mut counts = [0; FAILURE_RANGES];
+		for node in self.nodes.values() {
+			let percentage = node.failure_percentage();
+			let range = FAILURE_PERCENTAGE_BUCKETS.iter().position(|le| percentage <= *le).unwrap_or(FAILURE_PERCENTAGE_BUCKETS.len());
+			counts[range] += 1;
+		}
+		counts
+	}
+
+	/// Returns a summary of the table contents. Nodes rejected by `filter` are counted as filtered. The save counters
+	/// are left at zero, see `NodeTable::stats`.
+	pub fn stats(&self, filter: &IpFilter) -> NodeTableStats {
+		let now = self.clock.now_instant();
+		NodeTableStats {
+			total: self.nodes.len(),
+			useless: self.nodes.values().filter(|n| self.is_useless(n)).count(),
+			banned: self.banned.values().filter(|ban| ban.time > now).count(),
+			backed_off: self.backoff.values().filter(|backoff| backoff.until > now).count(),
+			filtered: self.nodes.values().filter(|n| !n.endpoint.is_allowed(filter)).count(),
+			contacted: self.nodes.values().filter(|n| n.last_contact.is_some()).count(),
+			ipv4: self.nodes.values().filter(|n| n.endpoint.address.is_ipv4()).count(),
+			ipv6: self.nodes.values().filter(|n| n.endpoint.address.is_ipv6()).count(),
+			tombstones: self.tombstones.keys().filter(|id| self.is_tombstoned(id)).count(),
+			rejected: self.rejected,
+			local: self.nodes.values().filter(|n| self.is_local_endpoint(&n.endpoint)).count(),
+			subnet_rejected: self.subnet_rejected,
+			low_port_rejected: self.low_port_rejected,
+			tombstone_rejected: self.tombstone_rejected,
+			ban_rejected: self.ban_rejected,
+			admission_rejected: self.admission_rejected.clone(),
+			saves: 0,
+			save_failures: 0,
+			meta_truncated: self.meta_truncated,
+			persistence_error: None,
+			outage: self.outage_since.is_some(),
+			quarantined: self.quarantine.len(),
+			outages: self.outages,
+			evictions: self.eviction_counts.clone(),
+			inbound_mismatches: self.inbound_mismatches,
+			deferred_dropped: 0,
+			bootstrapped: self.is_bootstrapped(),
+			port_capped: self.port_capped,
+			sibling: self.nodes.values().filter(|n| self.is_sibling_endpoint(&n.endpoint)).count(),
+			sibling_rejected: self.sibling_rejected,
+			id_mismatches: self.id_mismatches,
+			flagged_mismatches: self.mismatch_flags.len(),
+			history: self.outcome_history(HISTORY_HOURS),
+			active_sessions: self.active_sessions.values().map(|&(_, count)| count).sum(),
+			connected_skipped: self.connected_skipped.load(AtomicOrdering::Relaxed),
+			connected_ip_skipped: self.connected_ip_skipped.load(AtomicOrdering::Relaxed),
+			loading: false,
+		}
+	}
+
+	/// Returns the dial attempts and connection outcomes of the last `hours` hours, at most `HISTORY_HOURS`, in one
+	/// bucket per wall clock hour, oldest first. The current hour is last and still filling.
+	pub fn outcome_history(&self, hours: usize) -> Vec<OutcomeBucket> {
+		self.history.last(self.clock.now_system(), hours)
+	}
+
+	/// Order the nodes of a stale file by their saved reliability and pick the most reliable ones for the sweep.
+	fn start_stale(&mut self, hints: HashMap<NodeId, u8>, age: Duration) {
+		let mut ranked: Vec<(Reverse<u8>, NodeId)> = hints.iter()
+			.filter(|&(id, _)| self.nodes.contains_key(id))
+			.map(|(id, reliability)| (Reverse(*reliability), *id))
+			.collect();
+		ranked.sort();
+		let pending: HashSet<NodeId> = ranked.into_iter().take(REVERIFY_NODES).map(|(_, id)| id).collect();
+		if pending.is_empty() {
+			return;
+		}
+		info!("Node table: saved {} days ago, dialling by saved reliability until {} nodes answer again", age.as_secs() / (24 * 60 * 60), pending.len());
+		self.stale_start = Some(StaleStart {
+			hints: hints,
+			pending: pending,
+			until: self.clock.now_instant() + REVERIFY_TIMEOUT,
+		});
+	}
+
+	/// Saved reliability of the nodes while a stale table is ordered by it.
+	fn stale_hints(&self) -> Option<&HashMap<NodeId, u8>> {
+		match self.stale_start {
+			Some(ref stale) if stale.until > self.clock.now_instant() => Some(&stale.hints),
+			_ => None,
+		}
+	}
+
+	/// End the ordering by reliability once all nodes of the sweep are verified.
+	fn check_stale_start(&mut self) {
+		if self.stale_start.as_ref().map_or(false, |stale| stale.pending.is_empty()) {
+			self.stale_start = None;
+			self.reorder();
+			info!("Node table: the most reliable saved nodes answered again, dialling by connection statistics");
+		}
+	}
+
+	/// Returns the nodes of a stale file to ping through discovery before their statistics are trusted again, the
+	/// most reliable first, see `NodeTableConfig::stale_file_age`. The nodes are verified by a discovery update
+	/// or a session. Empty once all of them are verified or after `REVERIFY_TIMEOUT`.
+	pub fn reverify_sweep(&self, filter: &IpFilter) -> Vec<NodeEntry> {
+		let pending = match (self.stale_hints(), self.stale_start.as_ref()) {
+			(Some(_), Some(stale)) => &stale.pending,
+			_ => return Vec::new(),
+		};
+		self.ordered_entries().iter()
+			.filter(|n| pending.contains(&n.id) && n.endpoint.is_allowed(filter))
+			.map(|n| self.node_entry(n))
+			.collect()
+	}
+
+	/// Merge the nodes of a saved table, see `ingest`, and restore their bans, useless marks and outcome history.
+	/// Expired bans are dropped. If the file is stale its nodes are ordered by their saved reliability until the
+	/// sweep is done, see `reverify_sweep`. Otherwise, if any saved node is warm the warm start begins, see
+	/// `warm_entries`.
+	fn restore(&mut self, saved: SavedTable) {
+		let SavedTable { nodes, exclusions, compat_epoch, history, report, saved_at, reliability, warm } = saved;
+		self.compat_epoch = max(self.compat_epoch, compat_epoch);
+		let now = self.clock.now_system();
+		self.history.restore(history, now);
+		if report.skipped_count() > 0 {
+			warn!("Node table: {}", report);
+		}
+		self.load_report = report;
+		let stale_age = saved_at.and_then(|t| now.duration_since(t).ok())
+			.and_then(|age| if self.config.stale_file_age > Duration::from_secs(0) && age > self.config.stale_file_age { Some(age) } else { None });
+		let reliability = match stale_age {
+			Some(_) if !reliability.is_empty() => Some(reliability),
+			_ => None,
+		};
+		if reliability.is_none() && warm && self.config.warm_start > Duration::from_secs(0) {
+			self.warm_until = Some(self.clock.now_instant() + self.config.warm_start);
+		}
+		self.restore_nodes(nodes);
+		if let (Some(hints), Some(age)) = (reliability, stale_age) {
+			self.start_stale(hints, age);
+		}
+		self.restore_exclusions(exclusions);
+	}
+
+	/// Merge saved nodes like `restore`, e.g. the ones a streaming load read so far, see
+	/// `NodeTable::with_backend_streaming`.
+	fn restore_nodes(&mut self, nodes: Vec<Node>) {
+		self.reconcile_pending = !self.config.raw_load;
+		for node in nodes {
+			// nodes over the subnet limit are never added, remember them for `reconcile`
+			let saved = if self.reconcile_pending && self.config.max_nodes_per_subnet.is_some() { Some(node.to_persisted()) } else { None };
+			let subnet_rejected = self.subnet_rejected;
+			self.ingest(NodeOrigin::Saved, node);
+			if let (Some(saved), true) = (saved, self.subnet_rejected > subnet_rejected) {
+				self.load_dropped.push((DropReason::Subnet, saved));
+			}
+		}
+	}
+
+	/// Restore saved bans and useless marks. Expired bans are dropped. A streaming load restores them before the
+	/// nodes, so the useless marks are not limited to `MAX_UNKNOWN_USELESS` like the marks of unknown nodes.
+	fn restore_exclusions(&mut self, exclusions: Vec<PersistedExclusion>) {
+		let now = self.clock.now_system();
+		for exclusion in exclusions {
+			let id = match NodeId::from_str(&exclusion.id) {
+				Ok(id) => id,
+				Err(_) => continue,
+			};
+			match (exclusion.kind, exclusion.until) {
+				(ExclusionKind::Ban, Some(until)) => {
+					let until = UNIX_EPOCH + Duration::from_secs(min(until, MAX_CONTACT_SECS));
+					match until.duration_since(now) {
+						Ok(left) if left > Duration::from_secs(0) => {
+							self.ban(&id, left, &exclusion.reason);
+							if let Some(ban) = self.banned.get_mut(&id) {
+								ban.epoch = exclusion.epoch;
+							}
+						},
+						_ => debug!(target: "network", "Dropping expired ban of {}", id.short()),
+					}
+				},
+				(ExclusionKind::Useless, _) => {
+					if self.is_required(&id) {
+						continue;
+					}
+					self.touch();
+					let time = self.clock.now_instant();
+					let mark = Exclusion { time: time, reason: exclusion.reason, epoch: exclusion.epoch, repeats: 0 };
+					self.useless_nodes.insert(id, mark);
+				},
+				(ExclusionKind::Ban, None) => {},
+			}
+		}
+	}
+
+	/// Check the nodes loaded by `load_saved` against the configuration once our own addresses are known: drop the
+	/// ones at our own address or not allowed by `filter`, then the expired ones and then the worst ones over
+	/// `max_nodes`, like `drop_expired` and `evict_excess` would later. Required and boot nodes and the ones in
+	/// `keep` stay. Nodes over the subnet limit were already left out while loading. The outcome is logged and
+	/// recorded in the load report. Returns the dropped nodes, `NodeTable::reconcile` hands them to
+	/// `NodeTablePersistence::save_dropped` so they are not lost. Does nothing with `NodeTableConfig::raw_load` or if nothing was loaded since the last call.
+	pub fn reconcile(&mut self, filter: &IpFilter, keep: &HashSet<NodeId>) -> Vec<PersistedNode> {
+		if !self.reconcile_pending {
+			return Vec::new();
+		}
+		self.reconcile_pending = false;
+		let mut dropped = mem::replace(&mut self.load_dropped, Vec::new());
+		let violating: Vec<(NodeId, DropReason)> = self.nodes.values()
+			.filter(|n| n.peer_type != PeerType::Required && n.required_for.is_empty() && !n.is_boot && !keep.contains(&n.id))
+			.filter_map(|n| if self.is_excluded_endpoint(&n.endpoint) {
+				Some((n.id.clone(), DropReason::OwnAddress))
+			} else if !n.endpoint.is_allowed(filter) {
+				Some((n.id.clone(), DropReason::Filtered))
+			} else {
+				None
+			})
+			.collect();
+		let mut record = |table: &mut TableState, id: &NodeId, reason: DropReason, eviction: EvictionReason| {
+			if let Some(node) = table.nodes.get(id) {
+				dropped.push((reason, node.to_persisted()));
+			}
+			table.forget(id, eviction);
+		};
+		for (id, reason) in violating {
+			record(self, &id, reason, EvictionReason::Filtered);
+		}
+		for id in self.expired(keep) {
+			record(self, &id, DropReason::Expired, EvictionReason::Expired);
+		}
+		for id in self.excess(keep) {
+			record(self, &id, DropReason::Capacity, EvictionReason::Capacity);
+		}
+
+		self.load_report.kept = Some(self.nodes.len());
+		for &(reason, _) in &dropped {
+			*self.load_report.dropped.entry(reason).or_insert(0) += 1;
+		}
+		if dropped.is_empty() {
+			return Vec::new();
+		}
+		self.touch();
+		let counts: Vec<String> = self.load_report.dropped.iter().map(|(reason, count)| format!("{} {}", count, reason)).collect();
+		info!("Node table: dropped {} saved nodes breaking the configuration ({}), kept {}", dropped.len(), counts.join(", "), self.nodes.len());
+		dropped.into_iter().map(|(_, node)| node).collect()
+	}
+
+	/// Collect the nodes, bans and useless marks to save, within `NodeTableConfig::max_file_bytes` if set.
+	fn to_persisted(&self) -> PersistedTable {
+		// save everything we know regardless of the selection mode, see `is_capped` for the nodes under the limit
+		let kept = self.sorted_entries(|n| !self.is_capped(n));
+		let optional = self.sorted_entries(|n| self.is_capped(n));
+		let now = (self.clock.now_instant(), self.clock.now_system());
+		let nodes = kept.into_iter()
+			.chain(self.within_cap(optional))
+			.map(|n| PersistedNode { reliability: Some(n.reliability(now.1)), ..n.to_persisted() })
+			.collect();
+
+		let bans = self.banned.iter()
+			.filter(|&(_, ban)| ban.time > now.0)
+			.map(|(id, ban)| PersistedExclusion {
+				id: format!("{:x}", id),
+				kind: ExclusionKind::Ban,
+				reason: ban.reason.clone(),
+				until: (now.1 + (ban.time - now.0)).duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+				epoch: ban.epoch,
+			});
+		let useless = self.nodes.values()
+			.filter(|n| self.is_useless(n))
+			.map(|n| PersistedExclusion {
+				id: format!("{:x}", n.id),
+				kind: ExclusionKind::Useless,
+				reason: self.useless_nodes[&n.id].reason.clone(),
+				until: None,
+				epoch: self.useless_nodes[&n.id].epoch,
+			});
+		let mut exclusions: Vec<PersistedExclusion> = bans.chain(useless).collect();
+		exclusions.sort_by(|a, b| (&a.id, a.kind as u8).cmp(&(&b.id, b.kind as u8)));
+		let mut table = PersistedTable {
+			network_id: self.config.network_id.clone(),
+			nodes: nodes,
+			exclusions: exclusions,
+			omitted: None,
+			compat_epoch: self.compat_epoch,
+			history: if self.config.save_outcome_history { self.history.to_persisted(now.1) } else { Vec::new() },
+			saved_at: now.1.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+		};
+		if let Some(max_bytes) = self.config.max_file_bytes {
+			table.truncate_to_size(max_bytes);
+		}
+		table
+	}
+
+	/// Set the source of time for backoffs, bans, useless marks, tombstones and contact times.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn set_clock(&mut self, clock: Box<Clock>) {
+		self.clock = clock;
+	}
+
+	/// Install a hook deciding on every node about to be added by `add_node`, `ingest`, `update` or `note_inbound`
+	/// once the built-in checks passed. Nodes already in the table are not checked again. The hook runs while the
+	/// table is borrowed, so it must not call back into the table. It is called before the table is changed for the
+	/// node, so a panicking hook leaves the table consistent. See `set_deferred_admission_hook` for a table shared
+	/// behind a lock.
+	pub fn set_admission_hook(&mut self, hook: AdmissionHook) {
+		self.admission = Some(Arc::new(hook));
+		self.admission_deferred = false;
+		self.awaiting_admission.clear();
+	}
+
+	/// Like `set_admission_hook`, but the new nodes are not added right away. They wait, up to
+	/// `MAX_AWAITING_ADMISSION`, until `SharedNodeTable::admit_awaiting` takes them with `take_awaiting_admission`,
+	/// calls the hook without holding the lock and adds the accepted ones with `admit_decided`. The hook may then
+	/// call back into the table or the network service.
+	pub fn set_deferred_admission_hook(&mut self, hook: AdmissionHook) {
+		self.set_admission_hook(hook);
+		self.admission_deferred = true;
+	}
+
+	/// Take the hook and the nodes waiting for it, see `set_deferred_admission_hook`. `None` if no node is waiting.
+	pub fn take_awaiting_admission(&mut self) -> Option<(Arc<AdmissionHook>, Vec<(Node, NodeSource)>)> {
+		if !self.admission_deferred || self.awaiting_admission.is_empty() {
+			return None;
+		}
+		let hook = self.admission.clone()?;
+		Some((hook, mem::replace(&mut self.awaiting_admission, Vec::new())))
+	}
+
+	/// Apply the decisions of the deferred hook on the nodes of `take_awaiting_admission`. The accepted nodes are
+	/// added the way they arrived, after the built-in checks are made again on the current table: discovered nodes
+	/// like `update` with the ones in `reserved` kept from eviction, inbound nodes with their session counted like
+	/// `note_inbound`. Returns the number of nodes added.
+	pub fn admit_decided(&mut self, decided: Vec<(Node, NodeSource, Admission)>, reserved: &HashSet<NodeId>) -> usize {
+		let mut added = 0;
+		for (mut node, source, admission) in decided {
+			let id = node.id;
+			if let Admission::Reject { .. } = admission {
+				self.apply_admission(&mut node, admission);
+				continue;
+			}
+			if self.contains(&id) {
+				continue;
+			}
+			self.admitted.insert(id, admission);
+			match source {
+				NodeSource::Discovery => {
+					let entry = NodeEntry::new(id, node.endpoint.clone());
+					self.add_discovered(vec![(entry, false)], reserved, &mut UpdateOutcome::default());
+				},
+				NodeSource::Inbound => {
+					node.attempts = 1;
+					self.add_node(node);
+					if self.contains(&id) {
+						self.note_success(&id);
+					}
+				},
+				_ => self.add_node(node),
+			}
+			self.admitted.remove(&id);
+			if self.contains(&id) {
+				added += 1;
+			}
+		}
+		added
+	}
+
+	/// Ask the admission hook about a new node, marking it as untrusted if so decided. Returns `false` if the node
+	/// is refused or waits for the deferred hook.
+	fn admits(&mut self, node: &mut Node, source: NodeSource) -> bool {
+		if let Some(admission) = self.admitted.remove(&node.id) {
+			return self.apply_admission(node, admission);
+		}
+		if self.admission.is_some() && self.admission_deferred {
+			if self.awaiting_admission.len() < MAX_AWAITING_ADMISSION && !self.is_awaiting_admission(&node.id) {
+				self.awaiting_admission.push((node.clone(), source));
+			}
+			return false;
+		}
+		let admission = match self.admission {
+			Some(ref hook) => hook(node, source),
+			None => return true,
+		};
+		self.apply_admission(node, admission)
+	}
+
+	fn is_awaiting_admission(&self, id: &NodeId) -> bool {
+		self.awaiting_admission.iter().any(|&(ref node, _)| node.id == *id)
+	}
+
+	/// Apply a decision of the admission hook on a new node. Returns `false` if the node is refused.
+	fn apply_admission(&mut self, node: &mut Node, admission: Admission) -> bool {
+		match admission {
+			Admission::Accept => true,
+			Admission::AcceptUntrusted => {
+				node.untrusted = true;
+				true
+			},
+			Admission::Reject { reason } => {
+				debug!(target: "network", "Ignoring node {}: refused by admission hook: {}", node.id.short(), reason);
+				let reason = if self.admission_rejected.contains_key(&reason) || self.admission_rejected.len() < MAX_ADMISSION_REASONS {
+					reason
+				} else {
+					"other".into()
+				};
+				*self.admission_rejected.entry(reason).or_insert(0) += 1;
+				false
+			},
+		}
+	}
+
+	/// Set our own node id. Nodes with this id are never added.
+	pub fn set_local_id(&mut self, id: NodeId) {
+		self.local_id = Some(id);
+	}
+
+	/// Set our own endpoints, e.g. the listen address and the external address found by NAT detection, replacing
+	/// the ones set before. Nodes at these addresses are never added or selected, whatever their id. Replaced
+	/// endpoints stay excluded for `FORMER_LOCAL_ENDPOINT_GRACE`.
+	pub fn set_local_endpoints(&mut self, endpoints: Vec<NodeEndpoint>) {
+		let now = self.clock.now_instant();
+		let replaced = mem::replace(&mut self.local_endpoints, endpoints);
+		let current = &self.local_endpoints;
+		self.former_local_endpoints.retain(|&(ref e, until)| until > now && !current.iter().any(|c| c.address == e.address));
+		for endpoint in replaced.into_iter().filter(|e| !current.iter().any(|c| c.address == e.address)) {
+			self.former_local_endpoints.retain(|&(ref e, _)| e.address != endpoint.address);
+			self.former_local_endpoints.push((endpoint, now + FORMER_LOCAL_ENDPOINT_GRACE));
+		}
+		self.reorder();
+	}
+
+	fn is_local_endpoint(&self, endpoint: &NodeEndpoint) -> bool {
+		self.local_endpoints.iter().any(|e| e.address == endpoint.address) || {
+			let now = self.clock.now_instant();
+			self.former_local_endpoints.iter().any(|&(ref e, until)| until > now && e.address == endpoint.address)
+		}
+	}
+
+	/// Set the endpoints of other instances on this host, replacing the ones set before or given by
+	/// `NodeTableConfig::sibling_endpoints`. Nodes at them are excluded like nodes at our own endpoints.
+	pub fn set_sibling_endpoints(&mut self, endpoints: Vec<SocketAddr>) {
+		self.sibling_endpoints = endpoints;
+		self.reorder();
+	}
+
+	/// Endpoints of other instances on this host, see `set_sibling_endpoints`.
+	pub fn sibling_endpoints(&self) -> &[SocketAddr] {
+		&self.sibling_endpoints
+	}
+
+	fn is_sibling_endpoint(&self, endpoint: &NodeEndpoint) -> bool {
+		let address = endpoint.address;
+		self.sibling_endpoints.iter().any(|sibling| sibling.port() == address.port() && (sibling.ip() == address.ip() ||
+			sibling.ip().is_unspecified() && (address.ip().is_loopback() || self.local_endpoints.iter().any(|e| e.address.ip() == address.ip()))))
+	}
+
+	/// Our own endpoints and the ones of other instances on this host.
+	fn is_excluded_endpoint(&self, endpoint: &NodeEndpoint) -> bool {
+		self.is_local_endpoint(endpoint) || self.is_sibling_endpoint(endpoint)
+	}
+
+	/// Check for nodes that must never be added: the zero id, our own id, our own public address and the address of
+	/// another instance on this host. Rejections are counted.
+	fn rejects(&mut self, id: &NodeId, endpoint: &NodeEndpoint) -> bool {
+		let reason = if id.is_zero() {
+			"zero id"
+		} else if self.local_id.as_ref() == Some(id) {
+			"own id"
+		} else if self.is_local_endpoint(endpoint) {
+			"own address"
+		} else if self.is_sibling_endpoint(endpoint) {
+			self.sibling_rejected += 1;
+			"address of another instance"
+		} else {
+			return false;
+		};
+		debug!(target: "network", "Rejecting node {} at {:?}: {}", id.short(), endpoint, reason);
+		self.rejected += 1;
+		true
+	}
+
+	/// Set which entries are returned by `nodes`, `entries`, `select_for` and `best_enodes`.
+	pub fn set_selection_mode(&mut self, mode: SelectionMode) {
+		self.mode = mode;
+		self.reorder();
+	}
+
+	/// Add a boot node, a reserved peer, a trusted node or a saved node. The result does not depend on the order in
+	/// which the same node arrives through the different paths: boot, required and trusted status are only ever
+	/// added, all sources are
+	/// recorded and the connection stats are merged.
+	pub fn ingest(&mut self, origin: NodeOrigin, mut node: Node) {
+		match origin {
+			NodeOrigin::Boot => {
+				node.source = NodeSource::Boot;
+				node.is_boot = true;
+			},
+			NodeOrigin::Reserved => {
+				node.source = NodeSource::Reserved;
+				node.peer_type = PeerType::Required;
+			},
+			NodeOrigin::Trusted => node.trusted = true,
+			// with `NodeTableConfig::raw_load` the saved entries are kept as they are, see `reconcile`
+			NodeOrigin::Saved if self.config.raw_load => return self.insert_node(node, false),
+			NodeOrigin::Saved => {},
+		}
+		self.add_node(node);
+	}
+
+	/// Classify a node with `id` arriving at `endpoint`:
+	/// - same id, same endpoint: nothing changes and all stats are kept.
+	/// - same id, new endpoint: the long-term counters are kept but the dial backoff is reset since it describes
+	///   the old address, which is recorded as the previous endpoint.
+	/// - new id, endpoint of a known node: once discovery verifies the endpoint, the known node's stats are
+	///   transferred to the new id and the old entry
+	///   is dropped, unless it is required or a boot node.
+	/// - new id, new endpoint: a new entry.
+	fn identity_change(&self, id: &NodeId, endpoint: &NodeEndpoint) -> IdentityChange {
+		match self.nodes.get(id) {
+			Some(node) if node.endpoint == *endpoint => IdentityChange::Same,
+			Some(node) => IdentityChange::Moved(node.endpoint.clone()),
+			None => match self.nodes.values().find(|n| n.endpoint == *endpoint) {
+				Some(n) if n.peer_type != PeerType::Required && n.required_for.is_empty() && !n.is_boot => IdentityChange::Rotated(n.id.clone()),
+				_ => IdentityChange::New,
+			},
+		}
+	}
+
+	/// Apply the rules of `identity_change` once the node with `id` has been inserted.
+	fn apply_identity_change(&mut self, id: &NodeId, change: IdentityChange) {
+		match change {
+			IdentityChange::Moved(previous) => {
+				debug!(target: "network", "Node {} moved from {:?}", id.short(), previous);
+				self.backoff.remove(id);
+				let now = self.clock.now_system();
+				if let Some(node) = self.nodes.get_mut(id) {
+					node.note_moved(previous.address, now);
+					node.previous_endpoint = Some(previous);
+					node.candidate_endpoint = None;
+				}
+			},
+			IdentityChange::Rotated(old) => {
+				debug!(target: "network", "Node {} replaces {} at the same endpoint", id.short(), old.short());
+				self.transfer_stats(&old, id);
+				self.forget(&old, EvictionReason::Rotated);
+			},
+			IdentityChange::Same | IdentityChange::New => {},
+		}
+	}
+
+	/// Add the connection stats of `from` to `to`, e.g. when a node at a known endpoint turns out to have a new id.
+	/// Counters are added, the latest contact and any known latency are kept. Returns `false` if either node is not known.
+	pub fn transfer_stats(&mut self, from: &NodeId, to: &NodeId) -> bool {
+		let (attempts, failures, successes, last_contact, latency) = match self.nodes.get(from) {
+			Some(n) => (n.attempts, n.failures, n.successes, n.last_contact, n.latency),
+			None => return false,
+		};
+		match self.nodes.get_mut(to) {
+			Some(node) => {
+				node.attempts = node.attempts.saturating_add(attempts);
+				node.failures = node.failures.saturating_add(failures);
+				node.successes = node.successes.saturating_add(successes);
+				node.last_contact = max(node.last_contact, last_contact);
+				node.latency = node.latency.or(latency);
+				self.touch();
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Record a dial of `expected` whose peer announced the id `announced` in its Hello packet. The handshake already
+	/// proved that the peer holds the key of `expected`, so the announced id is a protocol violation and not a sign
+	/// of rotated keys: no entry is moved, added or tombstoned, the node is only flagged until its next successful
+	/// session, see `EntryStatus::mismatched_id`. The dial is recorded as a failure by the caller. Returns `false` if
+	/// `expected` is not known.
+	pub fn note_id_mismatch(&mut self, expected: &NodeId, announced: NodeId) -> bool {
+		if !self.nodes.contains_key(expected) {
+			return false;
+		}
+		self.id_mismatches += 1;
+		debug!(target: "network", "Node {} announced the id {} in its hello", expected.short(), announced.short());
+		self.mismatch_flags.insert(expected.clone(), announced);
+		true
+	}
+
+	/// Check whether a new node at `endpoint` would exceed the per-subnet limit.
+	fn subnet_full(&self, endpoint: &NodeEndpoint) -> bool {
+		self.config.max_nodes_per_subnet.map_or(false, |limit| {
+			let target = subnet(&endpoint.address.ip());
+			self.nodes.values().filter(|n| subnet(&n.endpoint.address.ip()) == target).count() >= limit
+		})
+	}
+
+	/// Check whether a new node at `endpoint` uses a port below `NodeTableConfig::min_port`, counting it if so.
+	fn below_min_port(&mut self, id: &NodeId, endpoint: &NodeEndpoint) -> bool {
+		let min = self.config.min_port;
+		let low = endpoint.address.port() < min || (endpoint.udp_known() && endpoint.udp_port < min);
+		if low {
+			debug!(target: "network", "Ignoring node {} at {:?}: port below {}", id.short(), endpoint, min);
+			self.low_port_rejected += 1;
+		}
+		low
+	}
+
+	/// Add a node to table. New nodes exceeding the per-subnet limit or using a port below the minimum are ignored
+	/// unless they are required, as are new nodes refused by the admission hook. Trusted nodes are exempt from all
+	/// of them. Clears the tombstone of a recently removed node.
+	pub fn add_node(&mut self, node: Node) {
+		self.insert_node(node, true)
+	}
+
+	/// Add a node like `add_node`, checking the per-subnet limit and the minimum port only if `limits` is set.
+	fn insert_node(&mut self, mut node: Node, limits: bool) {
+		if self.rejects(&node.id, &node.endpoint) {
+			return;
+		}
+		let required = node.peer_type == PeerType::Required || !node.required_for.is_empty();
+		let known = self.nodes.contains_key(&node.id);
+		let limited = limits && !required && !known && !node.trusted;
+		if limited && self.below_min_port(&node.id, &node.endpoint) {
+			return;
+		}
+		if limited && self.subnet_full(&node.endpoint) {
+			debug!(target: "network", "Ignoring node {:?}: subnet limit reached", node);
+			self.subnet_rejected += 1;
+			return;
+		}
+		let source = node.source;
+		if !known && !node.trusted && !self.admits(&mut node, source) {
+			return;
+		}
+		self.touch();
+		self.tombstones.remove(&node.id);
+		if node.source != NodeSource::Unknown {
+			let source = node.source;
+			node.sources.insert(source);
+		}
+		self.meta_truncated += META_LIMITS.limit_node(&mut node);
+		let change = self.identity_change(&node.id, &node.endpoint);
+		// merge connection stats and metadata learned earlier
+		if let Some(existing) = self.nodes.remove(&node.id) {
+			node.attempts = max(existing.attempts, node.attempts);
+			node.failures = max(existing.failures, node.failures);
+			node.successes = max(existing.successes, node.successes);
+			node.last_contact = max(existing.last_contact, node.last_contact);
+			node.latency = existing.latency.or(node.latency);
+			node.stalled |= existing.stalled;
+			node.warm |= existing.warm;
+			node.is_boot |= existing.is_boot;
+			node.untrusted |= existing.untrusted;
+			node.trusted |= existing.trusted;
+			node.sources.extend(existing.sources);
+			node.required_for.extend(existing.required_for);
+			if existing.peer_type == PeerType::Required {
+				node.peer_type = PeerType::Required;
+			}
+			if existing.source != NodeSource::Unknown {
+				node.source = existing.source;
+			}
+			if node.client_version.is_none() {
+				node.client_version = existing.client_version;
+			}
+			if node.capabilities.is_none() {
+				node.capabilities = existing.capabilities;
+			}
+			node.p2p_version = node.p2p_version.or(existing.p2p_version);
+			node.snappy = node.snappy.or(existing.snappy);
+			node.chain = node.chain.or(existing.chain);
+			node.candidate_endpoint = existing.candidate_endpoint;
+			node.previous_endpoint = existing.previous_endpoint;
+			node.merge_address_history(existing.address_history);
+			for (key, value) in existing.tags {
+				node.tags.entry(key).or_insert(value);
+			}
+		}
+		if !node.tags.is_empty() {
+			self.meta_truncated += self.limit_tag_keys(&mut node);
+		}
+
+		let id = node.id.clone();
+		if node.peer_type == PeerType::Required {
+			self.useless_nodes.remove(&id);
+		}
+		self.nodes.insert(id.clone(), node);
+		// several ids may legitimately share an endpoint here, e.g. behind a NAT; only discovery can prove a rotation
+		if let IdentityChange::Moved(_) = change {
+			self.apply_identity_change(&id, change);
+		}
+	}
+
+	/// Record a remote node that connected to us and stayed connected for a while. `endpoint` must use the TCP port
+	/// advertised in the node's hello packet, not the source port of the connection, its UDP port may be unknown.
+	/// Known nodes keep their endpoint. The accepted session counts as a successful connection attempt and clears the tombstone of a recently removed
+	/// node. Returns `true` if the node was added.
+	pub fn note_inbound(&mut self, id: &NodeId, endpoint: NodeEndpoint, meta: SessionMeta) -> bool {
+		self.touch();
+		let added = !self.contains(id);
+		if added && !endpoint.is_valid_tcp() {
+			return false;
+		}
+		if added && self.below_min_port(id, &endpoint) {
+			return false;
+		}
+		if added && self.subnet_full(&endpoint) {
+			self.subnet_rejected += 1;
+			return false;
+		}
+		if added && self.rejects(id, &endpoint) {
+			return false;
+		}
+		if added {
+			let mut node = Node::new(id.clone(), endpoint);
+			node.source = NodeSource::Inbound;
+			node.sources.insert(NodeSource::Inbound);
+			if !self.admits(&mut node, NodeSource::Inbound) {
+				return false;
+			}
+			self.nodes.insert(id.clone(), node);
+		}
+		self.tombstones.remove(id);
+		{
+			let node = self.nodes.get_mut(id).expect("added above if not known");
+			let (mut client_version, mut capabilities) = (meta.client_version, meta.capabilities);
+			if META_LIMITS.limit_client_version(&mut client_version) {
+				self.meta_truncated += 1;
+			}
+			if META_LIMITS.limit_capabilities(&mut capabilities) {
+				self.meta_truncated += 1;
+			}
+			node.attempts += 1;
+			node.client_version = Some(client_version);
+			node.capabilities = Some(capabilities);
+		}
+		self.note_success(id);
+		added
+	}
+
+	fn ordered_entries(&self) -> Vec<&Node> {
+		self.ordered_entries_for(&[])
+	}
+
+	/// Like `ordered_entries`, but nodes required for one of `protocols` come first and count as required in
+	/// reserved-only mode.
+	fn ordered_entries_for(&self, protocols: &[ProtocolId]) -> Vec<&Node> {
+		self.ordered_entries_with(protocols, EntryOptions::default())
+	}
+
+	/// Like `ordered_entries_for`, but the exclusions in `options` are ignored.
+	fn ordered_entries_with(&self, protocols: &[ProtocolId], options: EntryOptions) -> Vec<&Node> {
+		let reserved_only = self.mode == SelectionMode::ReservedOnly;
+		let mut refs = self.sorted_entries(|n| {
+			(!self.is_useless(n) || options.include_useless_required && (n.is_required_for(None) || !n.required_for.is_empty())) &&
+				(!self.is_banned(&n.id) || options.include_banned) &&
+				!self.is_excluded_endpoint(&n.endpoint) && (!reserved_only || n.is_required_for_any(protocols))
+		});
+		if let Some(hints) = self.stale_hints() {
+			refs.sort_by_key(|n| (n.untrusted, Reverse(hints.get(&n.id).cloned().unwrap_or(0))));
+		}
+		// nodes on another chain are not left out, they may have upgraded since they said so
+		if self.local_chain.is_some() {
+			refs.sort_by_key(|n| self.on_other_chain(n));
+		}
+		if !protocols.is_empty() {
+			refs.sort_by_key(|n| !n.is_required_for_any(protocols));
+		}
+		refs
+	}
+
+	fn sorted_entries<F>(&self, include: F) -> Vec<&Node> where F: Fn(&Node) -> bool {
+		let mut refs: Vec<&Node> = self.nodes.values()
+			.filter(|n| include(n))
+			.collect();
+
+		let now = self.clock.now_system();
+		refs.sort_by(|a, b| self.compare(a, b, now).then_with(|| a.id.cmp(&b.id)));
+		if self.config.shuffle_ties {
+			let mut rng = rand::thread_rng();
+			let mut start = 0;
+			while start < refs.len() {
+				let end = start + refs[start..].iter().take_while(|n| self.compare(refs[start], n, now) == Ordering::Equal).count();
+				rng.shuffle(&mut refs[start..end]);
+				start = end;
+			}
+		}
+		refs
+	}
+
+	/// Order of the table entries, see `compare_nodes`. Untrusted nodes come last, recently contacted nodes first if
+	/// configured.
+	fn compare(&self, a: &Node, b: &Node, now: SystemTime) -> Ordering {
+		let recent = |n: &Node| self.config.recent_contact.map_or(false, |window| n.last_contact.map_or(false, |t| {
+			now.duration_since(t).map_or(true, |age| age <= window)
+		}));
+		a.untrusted.cmp(&b.untrusted)
+			.then_with(|| recent(b).cmp(&recent(a)))
+			.then_with(|| compare_nodes(a, b))
+	}
+
+	/// Returns node ids sorted by failure percentage, for nodes with the same failure percentage the absolute number of
+	/// failures is considered. Nodes that still compare equal are listed by id, so the order only changes with the
+	/// table state, unless `NodeTableConfig::shuffle_ties` is set.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn nodes(&self, filter: &IpFilter) -> Vec<NodeId> {
+		self.ordered_entries().iter()
+			.filter(|n| n.endpoint.is_allowed(filter))
+			.map(|n| n.id)
+			.collect()
+	}
+
+	/// Like `nodes` with the default filter, but the result is computed once per generation and only recomputed
+	/// early when a ban, a boot node retry interval, the stale start ordering or a recent contact window expires.
+	/// With `NodeTableConfig::shuffle_ties` every call computes a new order.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn nodes_default(&self) -> Vec<NodeId> {
+		if self.config.shuffle_ties {
+			return self.nodes(&IpFilter::default());
+		}
+		let now = self.clock.now_instant();
+		let mut cache = self.default_nodes.lock();
+		let fresh = cache.as_ref().map_or(false, |c| c.generation == self.generation && c.until.map_or(true, |until| now < until));
+		if !fresh {
+			*cache = Some(DefaultNodes {
+				generation: self.generation,
+				until: self.order_expiry(),
+				ids: self.nodes(&IpFilter::default()),
+			});
+		}
+		cache.as_ref().expect("set above; qed").ids.clone()
+	}
+
+	/// Earliest time the entries or their order change without the table changing: a ban or a replaced local
+	/// endpoint expires, a boot node may be retried, the stale start ordering ends or a contact is no longer recent.
+	#[cfg(any(test, feature = "test-helpers"))]
+	fn order_expiry(&self) -> Option<Instant> {
+		let now = self.clock.now_instant();
+		let system_now = self.clock.now_system();
+		let bans = self.banned.values().map(|ban| ban.time);
+		let endpoints = self.former_local_endpoints.iter().map(|&(_, until)| until);
+		let boot = self.useless_nodes.iter()
+			.filter(|&(id, _)| self.nodes.get(id).map_or(false, |n| n.is_boot))
+			.map(|(_, mark)| mark.time + self.config.boot_retry_interval);
+		let stale = self.stale_start.as_ref().map(|stale| stale.until);
+		let recent = self.config.recent_contact.into_iter().flat_map(|window| self.nodes.values()
+			.filter_map(|n| n.last_contact)
+			.filter_map(move |contact| (contact + window).duration_since(system_now).ok())
+			.map(move |left| now + left));
+		bans.chain(endpoints).chain(boot).chain(stale).chain(recent).filter(|time| *time > now).min()
+	}
+
+	/// Returns the enode URLs of at most `limit` nodes with public addresses allowed by `filter`, in the same order as
+	/// `nodes`, for sharing as boot nodes. Nodes from distinct /24 (IPv4) or /48 (IPv6) subnets are preferred.
+	pub fn best_enodes(&self, limit: usize, filter: &IpFilter) -> Vec<String> {
+		self.best_public(filter, |_| true).into_iter().take(limit).map(Node::enode_url).collect()
+	}
+
+	/// Writes the enode URLs of at most `limit` nodes for a published node list, e.g. the leaves of a DNS discovery
+	/// tree, one per line in the order of `best_enodes`. Only nodes with at least
+	/// `NodeTableConfig::export_min_successes` sessions and a failure bucket of at most
+	/// `NodeTableConfig::export_max_failure_bucket` are written. Returns the number of nodes written.
+	pub fn export_node_list<W: Write>(&self, w: &mut W, limit: usize, filter: &IpFilter) -> io::Result<usize> {
+		let min_successes = max(self.config.export_min_successes, 1);
+		let max_failure_bucket = self.config.export_max_failure_bucket;
+		let nodes = self.best_public(filter, |n| n.successes >= min_successes && n.failure_bucket() <= max_failure_bucket);
+		let mut written = 0;
+		for node in nodes.into_iter().take(limit) {
+			writeln!(w, "{}", node.enode_url())?;
+			written += 1;
+		}
+		Ok(written)
+	}
+
+	/// Add the nodes of a list in `format` read from `reader`, with the source `NodeSource::Imported`. Enode URLs are
+	/// checked with the strict parser and must be allowed by `filter`. At most `MAX_LIST_LINES` lines of at most
+	/// `MAX_LIST_LINE_BYTES` each are read. Returns the result of every entry, errors only for failed reads.
+	pub fn ingest_list<R: Read>(&mut self, reader: R, format: ListFormat, filter: &IpFilter) -> io::Result<IngestStats> {
+		// bound the input, a line break is not required to stop reading
+		let mut reader = BufReader::new(reader.take(MAX_LIST_BYTES as u64));
+		let mut stats = IngestStats::default();
+		let mut line = Vec::new();
+		let mut number = 0;
+		loop {
+			line.clear();
+			if reader.read_until(b'\n', &mut line)? == 0 {
+				break;
+			}
+			number += 1;
+			if number > MAX_LIST_LINES {
+				stats.truncated = true;
+				break;
+			}
+			while line.last().map_or(false, |b| *b == b'\n' || *b == b'\r') {
+				line.pop();
+			}
+			let result = match ::std::str::from_utf8(&line).map(str::trim) {
+				Ok(entry) if entry.is_empty() || entry.starts_with('#') => continue,
+				_ if line.len() > MAX_LIST_LINE_BYTES => ListEntryResult::TooLong,
+				Ok(entry) => match format {
+					ListFormat::EnodeLines => self.ingest_enode(entry, filter),
+					ListFormat::EnrBase64Lines => ListEntryResult::Unsupported,
+				},
+				Err(_) => ListEntryResult::Invalid("Not valid UTF-8".into()),
+			};
+			stats.entries.push((number, result));
+		}
+		Ok(stats)
+	}
+
+	fn ingest_enode(&mut self, url: &str, filter: &IpFilter) -> ListEntryResult {
+		let mut node = match parse_enode(url) {
+			Ok(ref node) if !node.endpoint.is_valid() => return ListEntryResult::Invalid("Address cannot be dialled".into()),
+			Ok(node) => node,
+			Err(e) => return ListEntryResult::Invalid(e.to_string()),
+		};
+		if !node.endpoint.is_allowed(filter) {
+			return ListEntryResult::Filtered;
+		}
+		if self.nodes.contains_key(&node.id) {
+			return ListEntryResult::Known;
+		}
+		let id = node.id;
+		node.source = NodeSource::Imported;
+		self.add_node(node);
+		if self.nodes.contains_key(&id) {
+			ListEntryResult::Added(id)
+		} else {
+			ListEntryResult::Refused
+		}
+	}
+
+	/// Add the peers of the static peers file `content`, see `parse_static_peers`, with the source
+	/// `NodeSource::StaticFile`. Compared to the previous load, peers no longer in the file or no longer marked as
+	/// required are made optional but kept in the table, unless they are reserved peers. The host reads the file
+	/// before locking the table, see `Host::reload_static_peers`.
+	pub fn apply_static_peers(&mut self, content: &str) -> StaticPeersUpdate {
+		let (peers, errors) = parse_static_peers(content);
+		let mut update = StaticPeersUpdate { loaded: peers.len(), errors: errors, ..Default::default() };
+		let mut loaded = HashMap::with_capacity(peers.len());
+		for (mut node, required) in peers {
+			let id = node.id;
+			if !self.nodes.contains_key(&id) {
+				update.added.push(id);
+			}
+			node.source = NodeSource::StaticFile;
+			if required {
+				node.peer_type = PeerType::Required;
+				update.required.push(id);
+			}
+			self.add_node(node);
+			if self.nodes.contains_key(&id) {
+				loaded.insert(id, required);
+			} else {
+				update.added.retain(|added| *added != id);
+			}
+		}
+		let previous = mem::replace(&mut self.static_peers, loaded);
+		for (id, was_required) in previous {
+			let required = self.static_peers.get(&id).cloned();
+			if required.is_none() {
+				update.removed += 1;
+			}
+			if !was_required || required == Some(true) {
+				continue;
+			}
+			if let Some(node) = self.nodes.get_mut(&id) {
+				if node.peer_type == PeerType::Required && !node.sources.contains(NodeSource::Reserved) {
+					node.peer_type = PeerType::Optional;
+					update.downgraded.push(id);
+				}
+			}
+		}
+		self.touch();
+		info!(target: "network", "Loaded {} static peers, {} new, {} no longer required", update.loaded, update.added.len(), update.downgraded.len());
+		update
+	}
+
+	/// Nodes with public addresses allowed by `filter` and matching `f`, in the same order as `nodes` but with nodes
+	/// from distinct /24 (IPv4) or /48 (IPv6) subnets first.
+	fn best_public<F>(&self, filter: &IpFilter, f: F) -> Vec<&Node> where F: Fn(&Node) -> bool {
+		let mut subnets = HashSet::new();
+		let (mut best, rest): (Vec<_>, Vec<_>) = self.ordered_entries().into_iter()
+			.filter(|n| n.endpoint.address.ip().is_usable_public() && n.endpoint.is_allowed(filter) && f(n))
+			.partition(|n| subnets.insert(subnet(&n.endpoint.address.ip())));
+		best.extend(rest);
+		best
+	}
+
+	/// Returns at most `limit` node ids allowed by `filter` and satisfying the capability `policy`, in the same
+	/// order as `nodes`. The nodes required for `protocol` come first, or if it is `None` the ones required for one
+	/// of the protocols of `policy`, they also count as required in reserved-only mode. Nodes in dial backoff are
+	/// skipped, as are boot nodes that are not required once the table is bootstrapped, see `boot_fallback`. Nodes
+	/// on an IP with `NodeTableConfig::max_ports_per_ip` other ports dialled recently or selected before them are
+	/// deferred. With `DialPolicy::exclude_connected` nodes we have a session with or on the IP of one are skipped.
+	/// With `NodeTableConfig::operator_clusters` at most one node of each cluster is selected, see `cluster_of`.
+	pub fn select_for(&self, policy: &DialPolicy, filter: &IpFilter, limit: usize, protocol: Option<&ProtocolId>) -> Vec<NodeId> {
+		let mut context = self.dial_context(policy, filter, protocol);
+		let clusters = if self.config.operator_clusters { Some(self.clusters()) } else { None };
+		let clusters = clusters.as_ref().and_then(|c| c.as_ref());
+		let mut taken = HashSet::new();
+		let ordered = self.ordered_entries_for(&context.protocols);
+		ordered.iter()
+			.filter(|n| {
+				// checked before `dial_exclusions` takes the port slot of the node
+				if clusters.map_or(false, |c| !self.first_of_cluster(n, c, &mut taken, false)) {
+					return false;
+				}
+				let exclusions = self.dial_exclusions(n, &mut context);
+				// skipped for a session only, other exclusions are not counted
+				match exclusions.first() {
+					Some(&DialExclusion::Connected) => { self.connected_skipped.fetch_add(1, AtomicOrdering::Relaxed); },
+					Some(&DialExclusion::ConnectedIp) => { self.connected_ip_skipped.fetch_add(1, AtomicOrdering::Relaxed); },
+					_ => {},
+				}
+				exclusions.is_empty() && clusters.map_or(true, |c| self.first_of_cluster(n, c, &mut taken, true))
+			})
+			.take(limit)
+			.map(|n| n.id)
+			.collect()
+	}
+
+	fn dial_context<'a>(&self, policy: &'a DialPolicy, filter: &'a IpFilter, protocol: Option<&'a ProtocolId>) -> DialContext<'a> {
+		DialContext {
+			policy: policy,
+			filter: filter,
+			protocols: match protocol {
+				Some(protocol) => vec![*protocol],
+				None => policy.protocols(),
+			},
+			now: self.clock.now_instant(),
+			bootstrapped: self.is_bootstrapped(),
+			connected_ips: match policy.exclude_connected {
+				true => self.active_sessions.values().map(|&(ip, _)| ip).collect(),
+				false => HashSet::new(),
+			},
+			ports: self.recent_ports(),
+		}
+	}
+
+	/// Every reason `select_for` skips a node, in the order they are checked. A node without any is selected and its
+	/// port is added to `context.ports`, so `select_for` leaves out the other nodes of a cluster before, see
+	/// `NodeTableConfig::operator_clusters`. `entry_status` reports the same, so that it always explains what the
+	/// selection does.
+	fn dial_exclusions(&self, node: &Node, context: &mut DialContext) -> Vec<DialExclusion> {
+		let now = context.now;
+		let required = node.is_required_for_any(&context.protocols);
+		let mut exclusions = Vec::new();
+		if let Some(mark) = self.useless_mark(node) {
+			let until = if node.is_boot { Some(self.system_time(mark.time + self.config.boot_retry_interval, now)) } else { None };
+			exclusions.push(DialExclusion::Useless { reason: mark.reason.clone(), until: until });
+		}
+		if let Some(ban) = self.active_ban(&node.id, now) {
+			exclusions.push(DialExclusion::Banned { reason: ban.reason.clone(), until: self.system_time(ban.time, now) });
+		}
+		if self.is_excluded_endpoint(&node.endpoint) {
+			exclusions.push(DialExclusion::OwnAddress);
+		}
+		if self.mode == SelectionMode::ReservedOnly && !required {
+			exclusions.push(DialExclusion::ReservedOnly);
+		}
+		if context.bootstrapped && node.is_boot && !required {
+			exclusions.push(DialExclusion::Bootstrapped);
+		}
+		match self.backoff.get(&node.id) {
+			Some(backoff) if backoff.until > now => exclusions.push(DialExclusion::Backoff { until: self.system_time(backoff.until, now) }),
+			_ => {},
+		}
+		if let Some(rule) = node.endpoint.blocking_rule(context.filter) {
+			exclusions.push(DialExclusion::Filtered { rule: rule });
+		}
+		if !context.policy.matches(node.capabilities.as_ref().map(|c| &c[..])) {
+			exclusions.push(DialExclusion::Capabilities);
+		}
+		if context.policy.exclude_connected {
+			let required = required || !node.required_for.is_empty();
+			if self.active_sessions.contains_key(&node.id) {
+				exclusions.push(DialExclusion::Connected);
+			} else if !required && context.connected_ips.contains(&canonical_address(node.endpoint.address).ip()) {
+				exclusions.push(DialExclusion::ConnectedIp);
+			}
+		}
+		let take = exclusions.is_empty();
+		if !self.within_port_cap(node, &mut context.ports, take) {
+			exclusions.push(DialExclusion::PortCap);
+		}
+		exclusions
+	}
+
+	/// Wall clock time of `at`, a time after `now`.
+	fn system_time(&self, at: Instant, now: Instant) -> SystemTime {
+		self.clock.now_system() + if at > now { at - now } else { Duration::from_secs(0) }
+	}
+
+	/// Ports dialled on each IP within `NodeTableConfig::port_window`.
+	fn recent_ports(&self) -> HashMap<IpAddr, HashSet<u16>> {
+		let now = self.clock.now_instant();
+		self.dialled_ports.iter()
+			.map(|(ip, ports)| (*ip, ports.iter().filter(|&&(_, time)| time + self.config.port_window > now).map(|&(port, _)| port).collect()))
+			.collect()
+	}
+
+	/// Whether a node may be dialled under `NodeTableConfig::max_ports_per_ip` given the `ports` already dialled or
+	/// selected on each IP. Its port is added if so and `take` is set. Nodes required for any protocol are exempt.
+	fn within_port_cap(&self, node: &Node, ports: &mut HashMap<IpAddr, HashSet<u16>>, take: bool) -> bool {
+		let cap = match self.config.max_ports_per_ip {
+			Some(cap) if !node.is_required_for(None) && node.required_for.is_empty() => cap,
+			_ => return true,
+		};
+		let address = node.endpoint.address;
+		let ports = ports.entry(address.ip()).or_insert_with(HashSet::new);
+		ports.contains(&address.port()) || (ports.len() < cap && (!take || ports.insert(address.port())))
+	}
+
+	/// Whether the table holds at least `NodeTableConfig::bootstrapped_nodes` tried nodes besides the boot nodes
+	/// with a failure bucket of at most `NodeTableConfig::bootstrapped_max_failure`. Boot nodes are only meant to
+	/// get a node started, they are left out of `select_for` then so that they are not overloaded.
+	pub fn is_bootstrapped(&self) -> bool {
+		let needed = self.config.bootstrapped_nodes;
+		needed > 0 && self.nodes.values()
+			.filter(|n| !n.is_boot && n.attempts > 0 && n.failure_bucket() <= self.config.bootstrapped_max_failure)
+			.filter(|n| !self.is_useless(n) && !self.is_banned(&n.id))
+			.take(needed)
+			.count() == needed
+	}
+
+	/// Returns the boot nodes allowed by `filter` that `select_for` leaves out because the table is bootstrapped, in
+	/// the same order as `nodes`, to be dialled when no peers are connected. Nodes in dial backoff are skipped.
+	pub fn boot_fallback(&self, filter: &IpFilter) -> Vec<NodeId> {
+		if !self.is_bootstrapped() {
+			return Vec::new();
+		}
+		let now = self.clock.now_instant();
+		self.ordered_entries().iter()
+			.filter(|n| n.is_boot && !n.is_required_for(None))
+			.filter(|n| self.backoff.get(&n.id).map_or(true, |b| b.until <= now))
+			.filter(|n| n.endpoint.is_allowed(filter))
+			.map(|n| n.id)
+			.collect()
+	}
+
+	/// Returns the warm nodes allowed by `filter` while the warm start lasts, to be dialled before the ones from
+	/// `select_for`. Their rank, capabilities and dial backoff do not matter, useless and banned nodes are still
+	/// skipped.
+	pub fn warm_entries(&self, filter: &IpFilter) -> Vec<NodeId> {
+		match self.warm_until {
+			Some(until) if until > self.clock.now_instant() => {},
+			_ => return Vec::new(),
+		}
+		self.ordered_entries().iter()
+			.filter(|n| n.warm && n.endpoint.is_allowed(filter))
+			.map(|n| n.id)
+			.collect()
+	}
+
+	/// Returns at most `limit` nodes in the order the host dials them, the warm nodes followed by `select_for`,
+	/// with the reason each one was selected.
+	pub fn explain_selection(&self, policy: &DialPolicy, filter: &IpFilter, limit: usize) -> Vec<(NodeEntry, SelectionReason)> {
+		let warm = self.warm_entries(filter);
+		let mut seen = HashSet::new();
+		let ordered: Vec<NodeId> = warm.iter().cloned()
+			.chain(self.select_for(policy, filter, usize::max_value(), None))
+			.filter(|id| seen.insert(*id))
+			.take(limit)
+			.collect();
+		ordered.iter().filter_map(|id| self.nodes.get(id)).map(|node| {
+			let reason = if node.is_required_for(None) || !node.required_for.is_empty() {
+				SelectionReason::Required
+			} else if warm.contains(&node.id) {
+				SelectionReason::Warm
+			} else if node.last_contact.is_some() {
+				SelectionReason::Quality
+			} else {
+				SelectionReason::DiverseFill
+			};
+			(self.node_entry(node), reason)
+		}).collect()
+	}
+
+	/// Returns a stream of the candidates of `explain_selection` with `policy` and `filter`, see `next_candidate`.
+	pub fn candidate_stream(&self, policy: DialPolicy, filter: IpFilter) -> CandidateStream {
+		let mut stream = CandidateStream {
+			policy: policy,
+			filter: filter,
+			pending: VecDeque::new(),
+			yielded: HashSet::new(),
+			version: None,
+			seen: 0,
+			wake: None,
+			exhausted: false,
+			rounds: 0,
+		};
+		self.refresh_candidates(&mut stream);
+		stream
+	}
+
+	/// Returns the best candidate of `stream` not yielded in the current round, with the reason it was selected.
+	/// The candidates are computed again only when the selection changed, the stats of a node not yielded yet
+	/// changed, a session was opened or closed or a dial backoff ended since, so that a better node added meanwhile
+	/// is yielded next. Dialling a yielded node and its outcome do not compute them again. The round ends when no candidate
+	/// is left: `None` is returned once and the following call starts a new round, in which the nodes yielded before
+	/// may be yielded again.
+	pub fn next_candidate(&self, stream: &mut CandidateStream) -> Option<(NodeEntry, SelectionReason)> {
+		if stream.exhausted {
+			stream.exhausted = false;
+			stream.yielded.clear();
+			stream.version = None;
+		}
+		let now = self.clock.now_instant();
+		let stale = stream.version != Some((self.selection_changes, self.session_changes)) ||
+			self.node_changed_since(stream.seen, |id| !stream.yielded.contains(id)) ||
+			stream.wake.map_or(false, |wake| wake <= now);
+		if stale {
+			self.refresh_candidates(stream);
+		}
+		match stream.pending.pop_front() {
+			Some(candidate) => {
+				stream.yielded.insert(candidate.0.id);
+				Some(candidate)
+			},
+			None => {
+				stream.exhausted = true;
+				stream.rounds += 1;
+				None
+			},
+		}
+	}
+
+	fn refresh_candidates(&self, stream: &mut CandidateStream) {
+		let now = self.clock.now_instant();
+		let pending = self.explain_selection(&stream.policy, &stream.filter, usize::max_value()).into_iter()
+			.filter(|&(ref entry, _)| !stream.yielded.contains(&entry.id))
+			.collect();
+		stream.pending = pending;
+		stream.version = Some((self.selection_changes, self.session_changes));
+		stream.seen = self.node_change_seq;
+		stream.wake = self.backoff.values().map(|b| b.until).filter(|until| *until > now).min();
+	}
+
+	/// Ordered list of all entries by failure percentage, for nodes with the same failure percentage the absolute
+	/// number of failures is considered. The entries carry the quality hints for discovery.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn entries(&self) -> Vec<NodeEntry> {
+		self.ordered_entries().iter().map(|n| self.node_entry(n)).collect()
+	}
+
+	/// Like `entries`, but the exclusions in `options` are ignored, e.g. to keep reconnecting to reserved peers.
+	pub fn entries_with(&self, options: EntryOptions) -> Vec<NodeEntry> {
+		self.ordered_entries_with(&[], options).iter().map(|n| self.node_entry(n)).collect()
+	}
+
+	/// Returns at most `limit` entries allowed by `filter`, in the same order as `entries`.
+	pub fn entries_filtered(&self, filter: &IpFilter, limit: usize) -> Vec<NodeEntry> {
+		self.ordered_entries().iter()
+			.filter(|n| n.endpoint.is_allowed(filter))
+			.take(limit)
+			.map(|n| self.node_entry(n))
+			.collect()
+	}
+
+	/// Discovery entry of a node with the hints on how it worked for us.
+	fn node_entry(&self, node: &Node) -> NodeEntry {
+		let now = self.clock.now_system();
+		NodeEntry {
+			failure_bucket: Some(node.failure_bucket()),
+			last_contact_age: node.last_contact.map(|t| now.duration_since(t).map(|d| d.as_secs()).unwrap_or(0)),
+			..NodeEntry::from(node)
+		}
+	}
+
+	/// Returns at most `limit` entries with their statistics in the same order as `nodes`. Banned nodes are included
+	/// and flagged, useless nodes only if `include_useless` is set.
+	pub fn entries_with_meta(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry> {
+		self.sorted_entries(|n| include_useless || !self.is_useless(n)).into_iter()
+			.take(limit)
+			.map(|n| self.table_entry(n))
+			.collect()
+	}
+
+	/// Returns the entry of a node with its statistics if it is in the table.
+	pub fn entry_with_meta(&self, id: &NodeId) -> Option<NodeTableEntry> {
+		self.nodes.get(id).map(|n| self.table_entry(n))
+	}
+
+	fn table_entry(&self, n: &Node) -> NodeTableEntry {
+		let ban_reason = self.active_ban(&n.id, self.clock.now_instant()).map(|ban| ban.reason.clone());
+		let useless_reason = self.useless_mark(n).map(|mark| mark.reason.clone());
+		NodeTableEntry {
+			id: n.id,
+			url: n.to_string(),
+			address: n.endpoint.address,
+			attempts: n.attempts,
+			failures: n.failures,
+			failure_percentage: n.failure_percentage(),
+			useless: useless_reason.is_some(),
+			banned: ban_reason.is_some(),
+			stalled: n.stalled,
+			last_contact: n.last_contact,
+			source: n.source,
+			candidate_address: n.candidate_endpoint.as_ref().map(|e| e.address),
+			previous_address: n.previous_endpoint.as_ref().map(|e| e.address),
+			ban_reason: ban_reason,
+			useless_reason: useless_reason,
+			address_history: n.address_history.iter().cloned().collect(),
+			p2p_version: n.p2p_version,
+			snappy: n.snappy,
+			trusted: n.trusted,
+			chain_info: n.chain_info().cloned(),
+			other_chain: self.on_other_chain(n),
+		}
+	}
+
+	/// Ids of the nodes that are or were at `ip`, on any port. Earlier addresses are looked up in the address
+	/// history of the nodes, nodes no longer in the table are not found.
+	pub fn ids_seen_on(&self, ip: &IpAddr) -> Vec<NodeId> {
+		let mut ids: Vec<NodeId> = self.nodes.values()
+			.filter(|n| n.endpoint.address.ip() == *ip || n.address_history.iter().any(|&(address, _)| address.ip() == *ip))
+			.map(|n| n.id.clone())
+			.collect();
+		ids.sort();
+		ids
+	}
+
+	/// Returns the statistics of a node, why it is excluded from dialing and whether its id is flagged, see
+	/// `entry_status_for`. The exclusions are the ones of a selection with the default `DialPolicy` and `IpFilter`.
+	pub fn entry_status(&self, id: &NodeId) -> Option<EntryStatus> {
+		self.entry_status_for(id, &DialPolicy::default(), &IpFilter::default())
+	}
+
+	/// Like `entry_status`, with the exclusions of `select_for` with `policy` and `filter`. Bans also apply to nodes
+	/// that are not in the table, their status has no entry. Returns `None` for other nodes not in the table.
+	pub fn entry_status_for(&self, id: &NodeId, policy: &DialPolicy, filter: &IpFilter) -> Option<EntryStatus> {
+		let now = self.clock.now_instant();
+		let ban = self.active_ban(id, now);
+		let node = self.nodes.get(id);
+		if node.is_none() && ban.is_none() {
+			return None;
+		}
+		let useless = node.and_then(|node| self.useless_mark(node));
+		let exclusions = match node {
+			Some(node) => self.dial_exclusions(node, &mut self.dial_context(policy, filter, None)),
+			None => ban.map(|ban| DialExclusion::Banned { reason: ban.reason.clone(), until: self.system_time(ban.time, now) }).into_iter().collect(),
+		};
+		let next_eligible = match exclusions.iter().map(DialExclusion::until).collect::<Option<Vec<_>>>() {
+			Some(untils) => untils.into_iter().max(),
+			None => None,
+		};
+		Some(EntryStatus {
+			entry: self.entry_with_meta(id),
+			ban_reason: ban.map(|ban| ban.reason.clone()),
+			banned_until: ban.map(|ban| self.system_time(ban.time, now)),
+			useless_reason: useless.map(|mark| mark.reason.clone()),
+			useless_repeats: useless.map_or(0, |mark| mark.repeats),
+			mismatched_id: self.mismatch_flags.get(id).cloned(),
+			dialable: node.is_some() && exclusions.is_empty(),
+			next_eligible: if node.is_some() { next_eligible } else { None },
+			exclusions: exclusions,
+		})
+	}
+
+	/// Ban of a node that has not ended at `now`.
+	fn active_ban(&self, id: &NodeId, now: Instant) -> Option<&Exclusion> {
+		match self.banned.get(id) {
+			Some(ban) if ban.time > now => Some(ban),
+			_ => None,
+		}
+	}
+
+	/// Useless mark of a node that is in effect, see `is_useless`.
+	fn useless_mark(&self, node: &Node) -> Option<&Exclusion> {
+		if self.is_useless(node) { self.useless_nodes.get(&node.id) } else { None }
+	}
+
+	/// Record an established session with a node at `endpoint`, the remote address of the connection. Until
+	/// `session_closed` the node and other nodes on its IP are skipped by `select_for`, see
+	/// `DialPolicy::exclude_connected`. A later session with the same node replaces the endpoint. Sessions are
+	/// counted, the host rejects a duplicate connection only after recording it.
+	pub fn session_opened(&mut self, id: &NodeId, endpoint: &SocketAddr) {
+		let ip = canonical_address(*endpoint).ip();
+		let session = self.active_sessions.entry(id.clone()).or_insert((ip, 0));
+		session.0 = ip;
+		session.1 += 1;
+		self.session_changes = self.session_changes.wrapping_add(1);
+	}
+
+	/// Record the end of a session recorded by `session_opened`. The node stays connected while it has another one.
+	pub fn session_closed(&mut self, id: &NodeId) {
+		let last = match self.active_sessions.get_mut(id) {
+			Some(&mut (_, ref mut count)) => {
+				*count -= 1;
+				*count == 0
+			},
+			None => return,
+		};
+		if last {
+			self.active_sessions.remove(id);
+			self.session_changes = self.session_changes.wrapping_add(1);
+		}
+	}
+
+	/// Record the end of an established session with a node, `remote` if it was not ended by us. A node is kept
+	/// once, with its latest session.
+	pub fn note_disconnect(&mut self, id: &NodeId, remote: bool) {
+		self.recently_connected.retain(|d| d.id != *id);
+		if self.recently_connected.len() >= MAX_RECENTLY_CONNECTED {
+			self.recently_connected.pop_front();
+		}
+		self.recently_connected.push_back(Disconnect {
+			id: id.clone(),
+			time: self.clock.now_instant(),
+			remote: remote,
+		});
+	}
+
+	/// Returns the nodes whose sessions ended within `max_age`, latest first, to be dialled again after losing many
+	/// peers at once. Sessions we ended are left out, we only do so for a cause such as a ban, a useless peer or an
+	/// eviction. Nodes no longer in the table, useless or banned are left out as well.
+	pub fn recently_lost(&self, max_age: Duration) -> Vec<NodeEntry> {
+		let now = self.clock.now_instant();
+		self.recently_connected.iter().rev()
+			.filter(|d| d.remote && now.duration_since(d.time) <= max_age)
+			.filter_map(|d| self.nodes.get(&d.id))
+			.filter(|n| !self.is_useless(n) && !self.is_banned(&n.id))
+			.map(|n| self.node_entry(n))
+			.collect()
+	}
+
+	/// Returns the latest nodes dropped from the table, oldest first. They are kept in memory only.
+	pub fn recent_evictions(&self) -> Vec<Eviction> {
+		self.evictions.iter().cloned().collect()
+	}
+
+	/// Get particular node
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn get(&self, id: &NodeId) -> Option<&Node> {
+		self.nodes.get(id)
+	}
+
+	/// Get particular node for changing it in place. The table does not see what changed, e.g. a new endpoint is
+	/// not recorded in the address history and may leave a stale candidate endpoint behind.
+	#[cfg(any(test, feature = "test-helpers"))]
+	#[deprecated(since = "1.11.0", note = "bypasses the table bookkeeping, use `update_node`")]
+	pub fn get_mut(&mut self, id: &NodeId) -> Option<&mut Node> {
+		self.touch();
+		self.nodes.get_mut(id)
+	}
+
+	/// Change a node through `f` and update the table state that depends on it. An endpoint change counts as a
+	/// move, like one reported by discovery: the old endpoint goes into the address history and the dial backoff is
+	/// reset. Changes of the id and metadata over the limits are undone. Returns `None` if the node is not known.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn update_node<F, R>(&mut self, id: &NodeId, f: F) -> Option<R> where F: FnOnce(&mut Node) -> R {
+		let (result, change) = {
+			let node = self.nodes.get_mut(id)?;
+			let endpoint = node.endpoint.clone();
+			let result = f(node);
+			node.id = id.clone();
+			self.meta_truncated += META_LIMITS.limit_node(node);
+			if node.source != NodeSource::Unknown {
+				node.sources.insert(node.source);
+			}
+			if node.endpoint == endpoint {
+				(result, IdentityChange::Same)
+			} else {
+				(result, IdentityChange::Moved(endpoint))
+			}
+		};
+		self.touch();
+		self.apply_identity_change(id, change);
+		Some(result)
+	}
+
+	/// Returns the score of a node, see `Node::score`.
+	pub fn score_of(&self, id: &NodeId) -> Option<usize> {
+		self.nodes.get(id).map(Node::score)
+	}
+
+	/// Returns the endpoint of a node.
+	pub fn endpoint_of(&self, id: &NodeId) -> Option<NodeEndpoint> {
+		self.nodes.get(id).map(|n| n.endpoint.clone())
+	}
+
+	/// Check if a node exists in the table.
+	pub fn contains(&self, id: &NodeId) -> bool {
+		self.nodes.contains_key(id)
+	}
+
+	/// Nodes known at `ip` on any port, in id order.
+	pub fn find_by_ip(&self, ip: &IpAddr) -> Vec<NodeId> {
+		let addr = SocketAddr::new(*ip, 0);
+		let mut ids: Vec<NodeId> = self.nodes.values().filter(|n| n.endpoint.same_host(&addr)).map(|n| n.id).collect();
+		ids.sort();
+		ids
+	}
+
+	/// Find the node whose id starts with `prefix`, see `NodeIdExt::matches_prefix`, so that users can abbreviate
+	/// ids. A full id is a prefix as well.
+	pub fn find_by_prefix(&self, prefix: &str) -> Result<&Node, PrefixLookupError> {
+		let mut found = self.nodes.values().filter(|n| n.id.matches_prefix(prefix));
+		match (found.next(), found.count()) {
+			(None, _) => Err(PrefixLookupError::NotFound),
+			(Some(node), 0) => Ok(node),
+			(Some(_), more) => Err(PrefixLookupError::Ambiguous(more + 1)),
+		}
+	}
+
+	/// Match the node `id` that connected to us from `remote` against the nodes known at that IP. The source port of
+	/// inbound connections is ephemeral and ignored. Mismatches are counted in the stats.
+	pub fn match_inbound(&mut self, id: &NodeId, remote: &SocketAddr) -> InboundMatch {
+		let known = self.find_by_ip(&remote.ip());
+		if known.is_empty() {
+			InboundMatch::Unknown
+		} else if known.contains(id) {
+			InboundMatch::Confirmed
+		} else {
+			self.inbound_mismatches += 1;
+			InboundMatch::Mismatch(known)
+		}
+	}
+
+	/// Handle for checking whether nodes are in the table without locking it, e.g. for every discovery packet. It is
+	/// eventually consistent: it sees the nodes as of the last `refresh_membership`, which the host calls every
+	/// maintenance round.
+	pub fn membership(&self) -> MembershipHandle {
+		self.membership.clone()
+	}
+
+	/// Update the membership handles to the nodes now in the table. Returns `false` if they were up to date. The
+	/// nodes are only compared if the table changed since the last refresh.
+	pub fn refresh_membership(&mut self) -> bool {
+		if self.membership_generation == Some(self.generation) {
+			return false;
+		}
+		self.membership_generation = Some(self.generation);
+		let changed = {
+			let ids = self.membership.ids.read();
+			ids.len() != self.nodes.len() || self.nodes.keys().any(|id| !ids.contains(id))
+		};
+		if changed {
+			let ids = self.nodes.keys().cloned().collect();
+			*self.membership.ids.write() = ids;
+		}
+		changed
+	}
+
+	/// Apply table changes coming from discovery. Banned and recently removed nodes are not added. A known node is
+	/// only moved to a different endpoint if the endpoint was verified or the current one keeps failing, otherwise
+	/// the endpoint is kept as a candidate. Required nodes are never moved, nodes that are neither trusted nor in
+	/// `reserved` are not moved to a port below `NodeTableConfig::min_port`. Trusted nodes are moved right away to the
+	/// endpoint they verified themselves, even if they are required, and they are not removed. Other nodes announcing
+	/// a trusted node are not trusted, their endpoint is only a candidate unless the current one keeps failing.
+	///
+	/// New nodes fill the room left under `max_nodes`, best first: verified before heard of, then spread over as
+	/// many subnets as possible, then closest to our id. The rest only get in by displacing a known node that
+	/// ranks after them and is not in `reserved`.
+	///
+	/// The update is checked and bounded before anything is changed, see `checked_update`.
+	pub fn update(&mut self, update: TableUpdates, reserved: &HashSet<NodeId>) -> UpdateOutcome {
+		let mut outcome = UpdateOutcome::default();
+		let (entries, removed) = self.checked_update(update, &mut outcome);
+		self.touch();
+		let mut added = Vec::new();
+		for (node, verified) in entries {
+			if self.is_tombstoned(&node.id) {
+				self.tombstone_rejected += 1;
+				outcome.rejected.push((node.id, RejectReason::Tombstoned));
+				continue;
+			}
+			if self.is_banned(&node.id) {
+				self.ban_rejected += 1;
+				outcome.rejected.push((node.id, RejectReason::Banned));
+				continue;
+			}
+			if self.rejects(&node.id, &node.endpoint) {
+				outcome.rejected.push((node.id, RejectReason::Local));
+				continue;
+			}
+			if let (true, Some(stale)) = (verified, self.stale_start.as_mut()) {
+				stale.pending.remove(&node.id);
+			}
+			let failing = self.backoff.get(&node.id).map_or(false, |b| b.failures >= ENDPOINT_CHANGE_FAILURES);
+			let change = self.identity_change(&node.id, &node.endpoint);
+			let min_port = self.config.min_port;
+			let below_min = |e: &NodeEndpoint| e.address.port() < min_port || (e.udp_known() && e.udp_port < min_port);
+			let mut low_port = false;
+			if let Some(entry) = self.nodes.get_mut(&node.id) {
+				if !entry.endpoint.udp_known() && entry.endpoint.address == node.endpoint.address {
+					// a verified endpoint at the known address gives the missing UDP port
+					if verified {
+						entry.endpoint.udp_port = node.endpoint.udp_port;
+						entry.candidate_endpoint = None;
+						outcome.updated_endpoints += 1;
+					} else {
+						entry.candidate_endpoint = Some(node.endpoint);
+					}
+					continue;
+				}
+				if entry.endpoint == node.endpoint {
+					entry.candidate_endpoint = None;
+					continue;
+				}
+				let pinned = entry.peer_type == PeerType::Required || !entry.required_for.is_empty();
+				if !(entry.trusted && verified) && (pinned || !(verified || failing)) {
+					entry.candidate_endpoint = Some(node.endpoint);
+					continue;
+				}
+				// a new endpoint on a low port is ignored like a new node on one
+				if !entry.trusted && !reserved.contains(&node.id) && below_min(&node.endpoint) {
+					low_port = true;
+				} else {
+					entry.endpoint = node.endpoint.clone();
+					outcome.updated_endpoints += 1;
+				}
+			} else {
+				added.push((node, verified));
+				continue;
+			}
+			if low_port {
+				self.below_min_port(&node.id, &node.endpoint);
+				continue;
+			}
+			self.apply_identity_change(&node.id, change);
+		}
+		self.add_discovered(added, reserved, &mut outcome);
+		for r in removed {
+			if reserved.contains(&r) || self.is_required(&r) || self.nodes.get(&r).map_or(false, |n| n.is_boot || n.trusted) {
+				if self.nodes.contains_key(&r) {
+					outcome.protected.push(r);
+				}
+			} else if self.forget(&r, EvictionReason::Discovery) {
+				outcome.removed += 1;
+			}
+		}
+		self.check_stale_start();
+		outcome.protected.sort();
+		outcome.rejected.sort();
+		outcome
+	}
+
+	/// Returns the added entries of a discovery update with whether they were verified, and the removed ids. Entries
+	/// with an endpoint that cannot be dialled or keyed by another id are rejected, the addresses of the others are
+	/// canonicalized. Entries of trusted nodes are all kept, of the others at most `NodeTableConfig::max_update_added`,
+	/// verified first and then by id. At most `max_update_removed` removed ids are kept, by id. The table is not
+	/// changed.
+	fn checked_update(&self, update: TableUpdates, outcome: &mut UpdateOutcome) -> (Vec<(NodeEntry, bool)>, Vec<NodeId>) {
+		let TableUpdates { added, removed, verified } = update;
+		let mut entries = Vec::with_capacity(min(added.len(), self.config.max_update_added));
+		for (id, mut node) in added {
+			node.endpoint.address = canonical_address(node.endpoint.address);
+			if node.id != id || !node.endpoint.is_valid() {
+				outcome.rejected.push((id, RejectReason::Invalid));
+				continue;
+			}
+			let verified = verified.contains(&id);
+			entries.push((node, verified));
+		}
+		let (mut kept, mut entries): (Vec<_>, Vec<_>) = entries.into_iter().partition(|&(ref node, _)| self.is_trusted(&node.id));
+		entries.sort_by_key(|&(ref node, verified)| (!verified, node.id));
+		if entries.len() > self.config.max_update_added {
+			outcome.truncated_added = entries.len() - self.config.max_update_added;
+			entries.truncate(self.config.max_update_added);
+		}
+		kept.extend(entries);
+		let mut removed: Vec<NodeId> = removed.into_iter().collect();
+		removed.sort();
+		if removed.len() > self.config.max_update_removed {
+			outcome.truncated_removed = removed.len() - self.config.max_update_removed;
+			removed.truncate(self.config.max_update_removed);
+		}
+		(kept, removed)
+	}
+
+	/// Add the new nodes of a discovery update while there is room, see `update`.
+	fn add_discovered(&mut self, mut added: Vec<(NodeEntry, bool)>, reserved: &HashSet<NodeId>, outcome: &mut UpdateOutcome) {
+		if added.is_empty() {
+			return;
+		}
+		let local = self.local_id.as_ref().map(keccak);
+		added.sort_by_key(|&(ref node, verified)| (!verified, local.map(|local| local ^ keccak(&node.id)), node.id.clone()));
+		let mut subnets: HashMap<IpAddr, usize> = HashMap::new();
+		for node in self.nodes.values() {
+			*subnets.entry(subnet(&node.endpoint.address.ip())).or_insert(0) += 1;
+		}
+		// the n-th node of a subnet goes after the (n-1)-th nodes of all the others, order is kept otherwise
+		let mut ranked: Vec<(bool, usize, NodeEntry)> = added.into_iter().map(|(node, verified)| {
+			let seen = subnets.entry(subnet(&node.endpoint.address.ip())).or_insert(0);
+			*seen += 1;
+			(!verified, *seen, node)
+		}).collect();
+		ranked.sort_by_key(|&(unverified, seen, _)| (unverified, seen));
+
+		let now = self.clock.now_system();
+		let capped = self.nodes.values().filter(|n| self.is_capped(n)).count();
+		let mut free = self.config.max_nodes.saturating_sub(capped);
+		// worst last
+		let mut evictable: Vec<NodeId> = self.sorted_entries(|n| self.is_capped(n) && !reserved.contains(&n.id)).into_iter()
+			.map(|n| n.id.clone())
+			.collect();
+		for (unverified, _, node) in ranked {
+			if !reserved.contains(&node.id) && self.below_min_port(&node.id, &node.endpoint) {
+				outcome.rejected.push((node.id, RejectReason::LowPort));
+				continue;
+			}
+			if self.subnet_full(&node.endpoint) {
+				self.subnet_rejected += 1;
+				outcome.rejected.push((node.id, RejectReason::SubnetFull));
+				continue;
+			}
+			let mut entry = Node::from(node.clone());
+			entry.source = NodeSource::Discovery;
+			entry.sources.insert(NodeSource::Discovery);
+			if !self.admits(&mut entry, NodeSource::Discovery) {
+				let reason = match self.is_awaiting_admission(&node.id) {
+					true => RejectReason::AwaitingAdmission,
+					false => RejectReason::Admission,
+				};
+				outcome.rejected.push((node.id, reason));
+				continue;
+			}
+			// only a verified endpoint is proof enough that a known node changed its id, it then takes the place of
+			// the old entry
+			let change = match self.identity_change(&node.id, &node.endpoint) {
+				IdentityChange::Rotated(_) if unverified => IdentityChange::New,
+				change => change,
+			};
+			let replaces = match change {
+				IdentityChange::Rotated(_) => true,
+				_ => false,
+			};
+			if !replaces && free > 0 {
+				free -= 1;
+			} else if !replaces {
+				while evictable.last().map_or(false, |id| !self.nodes.contains_key(id)) {
+					evictable.pop();
+				}
+				let displaces = match evictable.last().and_then(|id| self.nodes.get(id)) {
+					Some(worst) => self.compare(&entry, worst, now) == Ordering::Less,
+					None => false,
+				};
+				if !displaces {
+					outcome.rejected.push((node.id, RejectReason::TableFull));
+					continue;
+				}
+				let worst = evictable.pop().expect("checked above");
+				self.forget(&worst, EvictionReason::Capacity);
+				outcome.evicted += 1;
+			}
+			self.nodes.insert(node.id.clone(), entry);
+			self.apply_identity_change(&node.id, change);
+			outcome.added += 1;
+		}
+	}
+
+	/// Record capabilities advertised by a node in its hello packet.
+	pub fn note_capabilities(&mut self, id: &NodeId, mut capabilities: Vec<PeerCapabilityInfo>) {
+		self.touch();
+		if let Some(node) = self.nodes.get_mut(id) {
+			if META_LIMITS.limit_capabilities(&mut capabilities) {
+				self.meta_truncated += 1;
+			}
+			node.capabilities = Some(capabilities);
+		}
+	}
+
+	/// Record the devp2p version a node announced in its Hello packet and whether snappy compression worked with
+	/// it, `false` if it failed or was not negotiated. Once `false`, the node is announced a version without
+	/// compression, see `host::hello_version`, so the flag is only set again after the node announced a higher
+	/// version than before, e.g. after an upgrade. Returns `false` if the node is not in the table.
+	pub fn set_negotiated(&mut self, id: &NodeId, p2p_version: u32, snappy: bool) -> bool {
+		let changed = match self.nodes.get_mut(id) {
+			Some(node) => {
+				let upgraded = node.p2p_version.map_or(false, |v| p2p_version > v);
+				let snappy = if snappy { Some(true) } else if upgraded { None } else { Some(false) };
+				let changed = node.p2p_version != Some(p2p_version) || node.snappy != snappy;
+				node.p2p_version = Some(p2p_version);
+				node.snappy = snappy;
+				changed
+			},
+			None => return false,
+		};
+		if changed {
+			self.touch();
+		}
+		true
+	}
+
+	/// Take the claims of a node at face value or stop doing so, see `Node::trusted`. Whether the node is required
+	/// does not change. Returns `false` if the node is not in the table.
+	pub fn set_trusted(&mut self, id: &NodeId, trusted: bool) -> bool {
+		let changed = match self.nodes.get_mut(id) {
+			Some(node) => mem::replace(&mut node.trusted, trusted) != trusted,
+			None => return false,
+		};
+		if changed {
+			self.touch();
+		}
+		true
+	}
+
+	/// Check if the claims of a node are taken at face value, see `Node::trusted`.
+	pub fn is_trusted(&self, id: &NodeId) -> bool {
+		self.nodes.get(id).map_or(false, |n| n.trusted)
+	}
+
+	/// Check if snappy compression failed with a node or was not negotiated the last time, see `set_negotiated`.
+	pub fn compression_failed(&self, id: &NodeId) -> bool {
+		self.nodes.get(id).map_or(false, |n| n.snappy == Some(false))
+	}
+
+	/// Mark the nodes with established sessions at a clean shutdown as warm, see `warm_entries`. Useless, banned
+	/// and stalled nodes are left out.
+	pub fn mark_warm(&mut self, ids: &[NodeId]) {
+		self.touch();
+		for id in ids {
+			let skip = match self.nodes.get(id) {
+				Some(node) => node.stalled || self.is_useless(node) || self.is_banned(id),
+				None => true,
+			};
+			if !skip {
+				self.nodes.get_mut(id).expect("checked above").warm = true;
+			}
+		}
+	}
+
+	/// Record a connection attempt to a node and return the address to dial, `None` if the node is not known. The
+	/// node is no longer warm, whatever the outcome.
+	pub fn note_dial(&mut self, id: &NodeId) -> Option<SocketAddr> {
+		self.touch_node(id);
+		let address = self.nodes.get_mut(id).map(|node| {
+			node.attempts += 1;
+			node.warm = false;
+			node.endpoint.address
+		});
+		if address.is_some() {
+			self.history.note(self.clock.now_system(), HistoryEvent::Attempt);
+		}
+		if let (Some(address), Some(cap)) = (address, self.config.max_ports_per_ip) {
+			self.note_dialled_port(address, cap);
+		}
+		address
+	}
+
+	/// Record a dialled port for `NodeTableConfig::max_ports_per_ip`, forgetting the ones dialled before the window.
+	fn note_dialled_port(&mut self, address: SocketAddr, cap: usize) {
+		// the cap may exclude the other nodes at the address
+		self.reorder();
+		let now = self.clock.now_instant();
+		let window = self.config.port_window;
+		self.dialled_ports.retain(|_, ports| {
+			ports.retain(|&(_, time)| time + window > now);
+			!ports.is_empty()
+		});
+		let ports = self.dialled_ports.entry(address.ip()).or_insert_with(Vec::new);
+		let known = ports.iter().any(|&(port, _)| port == address.port());
+		ports.retain(|&(port, _)| port != address.port());
+		ports.push((address.port(), now));
+		if !known && ports.len() == cap {
+			debug!(target: "network", "Dialled {} ports on {} within {:?}, deferring its other nodes", cap, address.ip(), window);
+			self.port_capped += 1;
+		}
+	}
+
+	/// Record a successfully established session with a node.
+	pub fn note_success(&mut self, id: &NodeId) {
+		self.note_outcomes(Some((id.clone(), Outcome::Success)));
+	}
+
+	/// Record that a session with a node stopped answering pings. This is a softer signal than a failure, the node
+	/// keeps its entry and only drops in ordering until the next successful contact.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn note_stalled(&mut self, id: &NodeId) {
+		self.note_outcomes(Some((id.clone(), Outcome::Stalled)));
+	}
+
+	/// Increase failure counte for a node and back off dialing it. During a suspected outage the failure is only
+	/// quarantined, see `note_global_outage_suspected`. Returns `UnknownNode` if the node is not in the table.
+	pub fn note_failure(&mut self, id: &NodeId) -> NoteResult {
+		match self.note_outcomes(Some((id.clone(), Outcome::Failure))) {
+			0 => NoteResult::Applied,
+			_ => NoteResult::UnknownNode,
+		}
+	}
+
+	/// Record the outcomes of several connections at once, in order, like the single node methods do. Returns the
+	/// number of outcomes for nodes not in the table, which are ignored.
+	pub fn note_outcomes<I>(&mut self, outcomes: I) -> usize where I: IntoIterator<Item = (NodeId, Outcome)> {
+		let now = self.clock.now_instant();
+		let now_system = self.clock.now_system();
+		let mut unknown = 0;
+		for (id, outcome) in outcomes {
+			if !self.nodes.contains_key(&id) {
+				unknown += 1;
+				continue;
+			}
+			self.touch_node(&id);
+			let node = self.nodes.get_mut(&id).expect("checked above; qed");
+			match outcome {
+				Outcome::Success => {
+					node.successes += 1;
+					node.last_contact = Some(now_system);
+					node.stalled = false;
+					self.backoff.remove(&id);
+					self.mismatch_flags.remove(&id);
+					if let Some(ref mut stale) = self.stale_start {
+						stale.pending.remove(&id);
+					}
+					self.history.note(now_system, HistoryEvent::Success);
+					if self.outage_since.take().is_some() {
+						info!(target: "network", "Connectivity is back, discarding the failures of {} nodes", self.quarantine.len());
+						for id in self.quarantine.drain() {
+							self.backoff.remove(&id);
+						}
+						self.selection_changes = self.selection_changes.wrapping_add(1);
+					}
+				},
+				Outcome::Stalled => {
+					node.stalled = true;
+					self.history.note(now_system, HistoryEvent::Stalled);
+				},
+				Outcome::Failure => {
+					let quarantined = match self.outage_since {
+						Some(since) if now < since + self.config.outage_max => true,
+						Some(_) => {
+							debug!(target: "network", "No success since the suspected outage, counting failures again");
+							self.outage_since = None;
+							self.quarantine.clear();
+							false
+						},
+						None => false,
+					};
+					if quarantined {
+						// the attempt counted by `note_dial` goes too, or the outage would improve the failure ratio
+						node.attempts = node.attempts.saturating_sub(1);
+						self.quarantine.insert(id.clone());
+						self.history.note(now_system, HistoryEvent::Quarantined);
+					} else {
+						node.failures += 1;
+						self.history.note(now_system, HistoryEvent::Failure);
+					}
+					let backoff = self.backoff.entry(id).or_insert_with(|| DialBackoff { failures: 0, until: now });
+					backoff.failures += 1;
+					let delay = self.config.initial_backoff * 2u32.pow(min(backoff.failures - 1, 16));
+					let max_delay = if node.is_boot { min(self.config.max_backoff, self.config.boot_retry_interval) } else { self.config.max_backoff };
+					backoff.until = now + min(delay, max_delay);
+				},
+			}
+		}
+		self.check_stale_start();
+		unknown
+	}
+
+	/// Record that dials to many distinct subnets failed in a row, which more likely means that our own connectivity
+	/// is down than that all those nodes are. Until the next success the failures of nodes only back them off and are
+	/// discarded once a success shows that the connectivity is back. Has no effect while an outage is already
+	/// suspected. Failures count again if there is no success within `outage_max`.
+	pub fn note_global_outage_suspected(&mut self) {
+		if self.outage_since.is_none() {
+			info!(target: "network", "Dials to many subnets failed, suspecting an outage of our own connectivity");
+			self.outage_since = Some(self.clock.now_instant());
+			self.outages += 1;
+		}
+	}
+
+	/// Returns the /24 (IPv4) or /48 (IPv6) subnet of the address of a node.
+	pub fn subnet_of(&self, id: &NodeId) -> Option<IpAddr> {
+		self.nodes.get(id).map(|n| subnet(&n.endpoint.address.ip()))
+	}
+
+	/// Returns the nodes that look run by the same operator as a node, including it, by id: the ones in its /24
+	/// (IPv4) or /48 (IPv6) subnet that reported the same client version. `None` if no other node matches. The
+	/// clusters are computed on the first call after the table changed.
+	pub fn cluster_of(&self, id: &NodeId) -> Option<Vec<NodeId>> {
+		let clusters = self.clusters();
+		let clusters = clusters.as_ref().expect("computed by clusters; qed");
+		clusters.of.get(id).map(|index| clusters.members[*index].clone())
+	}
+
+	/// The clusters of the current generation, computed if the table changed since they were last.
+	fn clusters<'a>(&'a self) -> MutexGuard<'a, Option<Clusters>> {
+		let mut cache = self.clusters.lock();
+		if cache.as_ref().map_or(true, |c| c.generation != self.generation) {
+			*cache = Some(self.compute_clusters());
+		}
+		cache
+	}
+
+	fn compute_clusters(&self) -> Clusters {
+		let mut groups: HashMap<(IpAddr, &str), Vec<NodeId>> = HashMap::new();
+		for node in self.nodes.values() {
+			match node.client_version {
+				Some(ref version) if !version.is_empty() => {
+					groups.entry((subnet(&node.endpoint.address.ip()), &version[..])).or_insert_with(Vec::new).push(node.id);
+				},
+				_ => {},
+			}
+		}
+		let mut clusters = Clusters { generation: self.generation, ..Default::default() };
+		for (_, mut members) in groups.into_iter().filter(|&(_, ref members)| members.len() > 1) {
+			members.sort();
+			for id in &members {
+				clusters.of.insert(*id, clusters.members.len());
+			}
+			clusters.members.push(members);
+		}
+		clusters
+	}
+
+	/// Whether a node is the first of its cluster among the ones `select_for` took so far, see
+	/// `NodeTableConfig::operator_clusters`. Its cluster is added to `taken` if so and `take` is set. Nodes required
+	/// for any protocol are always taken and do not count.
+	fn first_of_cluster(&self, node: &Node, clusters: &Clusters, taken: &mut HashSet<usize>, take: bool) -> bool {
+		if node.is_required_for(None) || !node.required_for.is_empty() {
+			return true;
+		}
+		clusters.of.get(&node.id).map_or(true, |index| !taken.contains(index) && (!take || taken.insert(*index)))
+	}
+
+	/// Record a change of the table, to be saved and to recompute what is derived from it.
+	fn touch(&mut self) {
+		self.dirty = true;
+		self.reorder();
+	}
+
+	/// Start a new generation without marking the table for saving, for changes of the selection that are not saved.
+	/// The candidate streams compute their candidates again.
+	fn reorder(&mut self) {
+		self.generation = self.generation.wrapping_add(1);
+		self.selection_changes = self.selection_changes.wrapping_add(1);
+	}
+
+	/// Record a change of the connection stats of a single node. Unlike after `touch`, a candidate stream that
+	/// yielded the node already keeps its candidates, see `next_candidate`.
+	fn touch_node(&mut self, id: &NodeId) {
+		self.dirty = true;
+		self.generation = self.generation.wrapping_add(1);
+		self.node_change_seq += 1;
+		if self.node_changes.len() >= MAX_NODE_CHANGES {
+			self.node_changes.pop_front();
+		}
+		self.node_changes.push_back((self.node_change_seq, id.clone()));
+	}
+
+	/// Whether the stats of a node matching `affects` changed after the change `seen`, see `touch_node`. Also
+	/// `true` if some of the changes after it are no longer kept.
+	fn node_changed_since<F>(&self, seen: u64, affects: F) -> bool where F: Fn(&NodeId) -> bool {
+		if self.node_changes.front().map_or(false, |&(seq, _)| seq > seen + 1) {
+			return true;
+		}
+		self.node_changes.iter().rev().take_while(|&&(seq, _)| seq > seen).any(|&(_, ref id)| affects(id))
+	}
+
+	/// Record misbehaviour reported by a protocol handler. The report counts as a number of failed connection
+	/// attempts depending on `severity`, critical misbehaviour also bans the node for a while.
+	pub fn note_misbehaviour(&mut self, id: &NodeId, severity: Severity) {
+		self.touch();
+		if let Some(node) = self.nodes.get_mut(id) {
+			let weight = severity_weight(severity);
+			node.attempts += weight;
+			node.failures += weight;
+		}
+		if severity >= BAN_SEVERITY {
+			self.ban(id, MISBEHAVIOUR_BAN_DURATION, "critical misbehaviour");
+		}
+	}
+
+	/// Set the tag `key` of a node to `value`, e.g. how fast it served a kind of request. Tags are saved with the
+	/// table. Returns `false` if the node is not known, the key is too long or the node or the table has too many
+	/// distinct tags already, see `META_LIMITS`.
+	pub fn set_tag(&mut self, id: &NodeId, key: &str, value: u32) -> bool {
+		if !self.nodes.contains_key(id) {
+			return false;
+		}
+		let new_key = !self.nodes.values().any(|n| n.tags.contains_key(key));
+		let node_full = {
+			let tags = &self.nodes[id].tags;
+			!tags.contains_key(key) && tags.len() >= META_LIMITS.tags
+		};
+		if key.len() > META_LIMITS.tag_key || node_full || (new_key && self.tag_keys().len() >= META_LIMITS.tag_keys) {
+			self.meta_truncated += 1;
+			return false;
+		}
+		self.touch();
+		self.nodes.get_mut(id).expect("checked above").tags.insert(key.to_owned(), value);
+		true
+	}
+
+	/// Returns the tag `key` of a node.
+	pub fn get_tag(&self, id: &NodeId, key: &str) -> Option<u32> {
+		self.nodes.get(id).and_then(|n| n.tags.get(key).cloned())
+	}
+
+	/// Returns at most `limit` nodes with the tag `key`, lowest values first. Nodes with the same value are in the
+	/// same order as `nodes`.
+	pub fn top_by_tag(&self, key: &str, limit: usize) -> Vec<NodeId> {
+		let mut tagged: Vec<(u32, NodeId)> = self.ordered_entries().iter()
+			.filter_map(|n| n.tags.get(key).map(|value| (*value, n.id)))
+			.collect();
+		tagged.sort_by_key(|&(value, _)| value);
+		tagged.into_iter().take(limit).map(|(_, id)| id).collect()
+	}
+
+	fn tag_keys(&self) -> BTreeSet<String> {
+		self.nodes.values().flat_map(|n| n.tags.keys().cloned()).collect()
+	}
+
+	/// Drop the tags of `node`, which is not in the table, over the per node limit and the ones with new keys once
+	/// the table has `META_LIMITS.tag_keys` distinct keys. Returns the number of limits hit.
+	fn limit_tag_keys(&self, node: &mut Node) -> usize {
+		let mut cut = if META_LIMITS.limit_tags(&mut node.tags) { 1 } else { 0 };
+		let mut keys = self.tag_keys();
+		let mut drop = Vec::new();
+		for key in node.tags.keys() {
+			if keys.contains(key) {
+				continue;
+			}
+			if keys.len() < META_LIMITS.tag_keys {
+				keys.insert(key.clone());
+			} else {
+				drop.push(key.clone());
+			}
+		}
+		if !drop.is_empty() {
+			cut += 1;
+		}
+		for key in &drop {
+			node.tags.remove(key);
+		}
+		cut
+	}
+
+	/// Record response latency reported by a protocol handler.
+	pub fn note_latency(&mut self, id: &NodeId, latency: Duration) {
+		self.touch_node(id);
+		if let Some(node) = self.nodes.get_mut(id) {
+			node.latency = Some(match node.latency {
+				Some(average) => (average * 3 + latency) / 4,
+				None => latency,
+			});
+		}
+	}
+
+	/// Exclude a node from dial candidates and reject its sessions for `duration`. The `reason` is reported by
+	/// `entry_status` and saved with the table.
+	pub fn ban(&mut self, id: &NodeId, duration: Duration, reason: &str) {
+		self.touch();
+		let until = self.clock.now_instant() + duration;
+		self.banned.insert(id.clone(), Exclusion { time: until, reason: reason.to_owned(), epoch: self.compat_epoch, repeats: 0 });
+	}
+
+	/// Remove a node from the table. Discovery will not add it again for the tombstone grace period. Returns
+	/// `false` if the node is not known.
+	pub fn remove_node(&mut self, id: &NodeId) -> bool {
+		self.touch();
+		self.banned.remove(id);
+		let now = self.clock.now_instant();
+		self.tombstones.insert(id.clone(), now);
+		self.forget(id, EvictionReason::Removed)
+	}
+
+	/// Drop a node together with its useless mark, dial backoff and pending forced dial and record the eviction.
+	/// Bans are kept. Returns `false` if the node is not known.
+	fn forget(&mut self, id: &NodeId, reason: EvictionReason) -> bool {
+		self.useless_nodes.remove(id);
+		self.backoff.remove(id);
+		self.mismatch_flags.remove(id);
+		self.forced_dials.retain(|f| f != id);
+		let node = match self.nodes.remove(id) {
+			Some(node) => node,
+			None => return false,
+		};
+		debug!(target: "network", "Dropped node {} from the table: {}", id.short(), reason);
+		if self.evictions.len() == MAX_RECENT_EVICTIONS {
+			self.evictions.pop_front();
+		}
+		self.evictions.push_back(Eviction {
+			id: id.clone(),
+			endpoint: node.endpoint,
+			reason: reason,
+			time: self.clock.now_system(),
+		});
+		*self.eviction_counts.entry(reason).or_insert(0) += 1;
+		true
+	}
+
+	fn is_tombstoned(&self, id: &NodeId) -> bool {
+		self.tombstones.get(id).map_or(false, |removed| self.clock.now_instant().duration_since(*removed) < self.config.tombstone_grace)
+	}
+
+	/// Make a node dialable right away: clears the useless and banned flags and the dial backoff. Returns `false` if
+	/// the node is not known.
+	pub fn retry_now(&mut self, id: &NodeId) -> bool {
+		self.touch();
+		self.useless_nodes.remove(id);
+		self.banned.remove(id);
+		self.backoff.remove(id);
+		self.contains(id)
+	}
+
+	/// Ids of the required nodes.
+	pub fn required_nodes(&self) -> Vec<NodeId> {
+		self.nodes.values().filter(|n| n.peer_type == PeerType::Required).map(|n| n.id.clone()).collect()
+	}
+
+	/// Check if a node is required.
+	pub fn is_required(&self, id: &NodeId) -> bool {
+		self.nodes.get(id).map_or(false, |n| n.peer_type == PeerType::Required)
+	}
+
+	/// Check if a node is required for `protocol`, either globally or for that protocol only.
+	pub fn is_required_for(&self, id: &NodeId, protocol: &ProtocolId) -> bool {
+		self.nodes.get(id).map_or(false, |n| n.is_required_for(Some(protocol)))
+	}
+
+	/// Set the peer type of a node for `protocol` only. Returns `false` if the node is not known.
+	pub fn set_peer_type_for(&mut self, id: &NodeId, protocol: ProtocolId, peer_type: PeerType) -> bool {
+		self.touch();
+		match self.nodes.get_mut(id) {
+			Some(node) => {
+				match peer_type {
+					PeerType::Required => node.required_for.insert(protocol),
+					PeerType::Optional => node.required_for.remove(&protocol),
+				};
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Make a required node optional again, or remove it from the table if `forget` is set. Returns `false` if the
+	/// node is not known.
+	pub fn release_required(&mut self, id: &NodeId, forget: bool) -> bool {
+		self.touch();
+		if forget {
+			return self.remove_node(id);
+		}
+		match self.nodes.get_mut(id) {
+			Some(node) => {
+				node.peer_type = PeerType::Optional;
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Have the host dial a node on its next maintenance round regardless of the dial backoff.
+	pub fn force_dialable(&mut self, id: &NodeId) {
+		self.backoff.remove(id);
+		if self.contains(id) && !self.forced_dials.contains(id) {
+			self.forced_dials.push(id.clone());
+		}
+	}
+
+	/// Take the nodes queued by `force_dialable`.
+	pub fn take_forced_dials(&mut self) -> Vec<NodeId> {
+		mem::replace(&mut self.forced_dials, Vec::new())
+	}
+
+	/// Check if a node is currently banned.
+	pub fn is_banned(&self, id: &NodeId) -> bool {
+		self.banned.get(id).map_or(false, |ban| ban.time > self.clock.now_instant())
+	}
+
+	/// Mark as useless, no further attempts to connect until next call to `clear_useless`. Required nodes are never
+	/// marked, boot nodes are considered useless for the boot retry interval only. The `reason` is reported by
+	/// `entry_status` and saved with the table. Marks of nodes not in the table are kept for when they are added,
+	/// up to `MAX_UNKNOWN_USELESS`, but not saved. Marking a node again with the same reason within
+	/// `NodeTableConfig::useless_cooldown` is only counted, see `EntryStatus::useless_repeats`.
+	pub fn mark_as_useless(&mut self, id: &NodeId, reason: &str) -> NoteResult {
+		let result = match (self.contains(id), self.is_required(id)) {
+			(true, true) => return NoteResult::Required,
+			(true, false) => NoteResult::Applied,
+			(false, _) if self.useless_nodes.contains_key(id) || self.unknown_useless() < MAX_UNKNOWN_USELESS => NoteResult::Tracked,
+			(false, _) => return NoteResult::UnknownNode,
+		};
+		let now = self.clock.now_instant();
+		let (cooldown, epoch) = (self.config.useless_cooldown, self.compat_epoch);
+		let repeats = match self.useless_nodes.get_mut(id) {
+			Some(mark) if mark.reason == reason && mark.epoch == epoch => {
+				mark.repeats = mark.repeats.saturating_add(1);
+				if now.duration_since(mark.time) < cooldown {
+					return result;
+				}
+				mark.repeats
+			},
+			_ => 0,
+		};
+		self.touch();
+		debug!(target: "network", "{}: Marked as useless: {}", id.short(), reason);
+		self.useless_nodes.insert(id.clone(), Exclusion { time: now, reason: reason.to_owned(), epoch: epoch, repeats: repeats });
+		result
+	}
+
+	fn unknown_useless(&self) -> usize {
+		self.useless_nodes.keys().filter(|id| !self.nodes.contains_key(id)).count()
+	}
+
+	fn is_useless(&self, node: &Node) -> bool {
+		self.useless_nodes.get(&node.id).map_or(false, |mark| {
+			(mark.epoch == self.compat_epoch || mark.reason != INCOMPATIBLE_PROTOCOL) &&
+				(!node.is_boot || self.clock.now_instant().duration_since(mark.time) < self.config.boot_retry_interval)
+		})
+	}
+
+	/// Log the required nodes marked as useless for longer than `USELESS_REQUIRED_WARNING`, once per mark. They are
+	/// still dialled, but the mark hides them from the selection until `clear_useless`. Returns the nodes logged.
+	pub fn warn_useless_required(&mut self) -> Vec<NodeId> {
+		let now = self.clock.now_instant();
+		let stuck: HashSet<NodeId> = self.nodes.values()
+			.filter(|n| (n.is_required_for(None) || !n.required_for.is_empty()) && self.is_useless(n))
+			.filter(|n| self.useless_nodes.get(&n.id).map_or(false, |mark| now.duration_since(mark.time) >= USELESS_REQUIRED_WARNING))
+			.map(|n| n.id)
+			.collect();
+		let mut logged: Vec<NodeId> = stuck.difference(&self.useless_required_warned).cloned().collect();
+		logged.sort();
+		for id in &logged {
+			warn!(target: "network", "Reserved peer {} has been marked as useless for over {} minutes: {}", id,
+				USELESS_REQUIRED_WARNING.as_secs() / 60, self.useless_nodes[id].reason);
+		}
+		self.useless_required_warned = stuck;
+		logged
+	}
+
+	/// Start a new compatibility epoch, e.g. when a fork activates or the protocol versions change. Nodes marked
+	/// as useless for an incompatible protocol in an earlier epoch may have upgraded and are dialled again, other
+	/// useless marks and bans are kept. Nodes that advertised another chain in an earlier epoch are no longer dialled
+	/// last. Returns the number of nodes that can be dialled again.
+	pub fn bump_compat_epoch(&mut self) -> usize {
+		let before = self.nodes.values().filter(|n| self.is_useless(n)).count();
+		self.compat_epoch = self.compat_epoch.wrapping_add(1);
+		self.touch();
+		let eligible = before - self.nodes.values().filter(|n| self.is_useless(n)).count();
+		debug!(target: "network", "Compatibility epoch {}: {} nodes can be dialled again", self.compat_epoch, eligible);
+		eligible
+	}
+
+	/// Record the chain a node advertised, stamped with the current compatibility epoch. Returns `false` if the node
+	/// is not in the table.
+	pub fn set_chain_info(&mut self, id: &NodeId, info: PeerChainInfo) -> bool {
+		let chain = Some((info, self.compat_epoch));
+		match self.nodes.get_mut(id) {
+			Some(ref node) if node.chain == chain => return true,
+			Some(node) => node.chain = chain,
+			None => return false,
+		}
+		if self.local_chain.map_or(false, |ours| ours.mismatches(&info)) {
+			debug!(target: "network", "{}: On another chain, network id {}, genesis {:x}", id.short(), info.network_id, info.genesis);
+		}
+		self.touch();
+		true
+	}
+
+	/// Set the chain we are on. Nodes that advertised another one in the current compatibility epoch are dialled
+	/// after all other nodes. A change, e.g. of our fork hash at a fork, starts a new compatibility epoch, see
+	/// `bump_compat_epoch`.
+	pub fn set_local_chain_info(&mut self, info: PeerChainInfo) {
+		match mem::replace(&mut self.local_chain, Some(info)) {
+			Some(previous) if previous != info => { self.bump_compat_epoch(); },
+			Some(_) => {},
+			None => self.touch(),
+		}
+	}
+
+	/// Whether `node` advertised another chain than ours in the current compatibility epoch.
+	fn on_other_chain(&self, node: &Node) -> bool {
+		match (self.local_chain, node.chain) {
+			(Some(ours), Some((theirs, epoch))) => epoch == self.compat_epoch && ours.mismatches(&theirs),
+			_ => false,
+		}
+	}
+
+	/// Atempt to connect to useless nodes again. Also forgets expired bans and tombstones.
+	pub fn clear_useless(&mut self) {
+		self.touch();
+		self.useless_nodes.clear();
+		let now = self.clock.now_instant();
+		self.banned.retain(|_, ban| ban.time > now);
+		let grace = self.config.tombstone_grace;
+		self.tombstones.retain(|_, removed| now.duration_since(*removed) < grace);
+	}
+
+	/// Drop nodes that have not been contacted successfully for longer than the configured maximum age, except
+	/// required and boot nodes and the ones in `keep`. Returns the number of dropped nodes.
+	pub fn drop_expired(&mut self, keep: &HashSet<NodeId>) -> usize {
+		self.touch();
+		let expired = self.expired(keep);
+		for id in &expired {
+			self.forget(id, EvictionReason::Expired);
+		}
+		expired.len()
+	}
+
+	/// Nodes `drop_expired` drops.
+	fn expired(&self, keep: &HashSet<NodeId>) -> Vec<NodeId> {
+		let max_age = match self.config.max_age {
+			Some(max_age) => max_age,
+			None => return Vec::new(),
+		};
+		let now = self.clock.now_system();
+		self.nodes.iter()
+			.filter(|&(id, node)| !keep.contains(id) && node.peer_type != PeerType::Required && !node.is_boot && node.last_contact.map_or(false, |t| {
+				now.duration_since(t).map_or(false, |age| age > max_age)
+			}))
+			.map(|(id, _)| id.clone())
+			.collect()
+	}
+
+	/// Drop the nodes that would not be saved because the table holds more than the configured maximum, except the
+	/// ones in `keep`. Returns the number of dropped nodes.
+	pub fn evict_excess(&mut self, keep: &HashSet<NodeId>) -> usize {
+		let evicted = self.excess(keep);
+		if !evicted.is_empty() {
+			self.touch();
+		}
+		for id in &evicted {
+			self.forget(id, EvictionReason::Capacity);
+		}
+		evicted.len()
+	}
+
+	/// Nodes `evict_excess` drops.
+	fn excess(&self, keep: &HashSet<NodeId>) -> Vec<NodeId> {
+		let optional = self.sorted_entries(|n| self.is_capped(n));
+		let kept: HashSet<NodeId> = self.within_cap(optional.clone()).into_iter().map(|n| n.id.clone()).collect();
+		optional.into_iter()
+			.filter(|n| !kept.contains(&n.id) && !keep.contains(&n.id))
+			.map(|n| n.id.clone())
+			.collect()
+	}
+
+	/// Whether a node counts towards `max_nodes`. Required and boot nodes do not, and excluded nodes are kept as
+	/// well so their exclusion survives a restart.
+	fn is_capped(&self, node: &Node) -> bool {
+		let protected = node.peer_type == PeerType::Required || !node.required_for.is_empty() || node.is_boot;
+		!protected && !self.is_useless(node) && !self.is_banned(&node.id)
+	}
+
+	/// Pick the nodes to keep out of `candidates`, sorted best first, under `max_nodes`. The best nodes of each
+	/// address family fill its `family_quota` share of the limit first, or all of them if there are fewer, the
+	/// remaining slots go to the best nodes of either family. The order of `candidates` is preserved.
+	fn within_cap<'a>(&self, candidates: Vec<&'a Node>) -> Vec<&'a Node> {
+		let cap = self.config.max_nodes;
+		if candidates.len() <= cap {
+			return candidates;
+		}
+		let quota = cap * min(self.config.family_quota, 50) / 100;
+		let (mut ipv4, mut ipv6) = (0, 0);
+		let mut keep: Vec<bool> = candidates.iter().map(|n| {
+			let count = if n.endpoint.address.is_ipv4() { &mut ipv4 } else { &mut ipv6 };
+			*count += 1;
+			*count <= quota
+		}).collect();
+		let mut free = cap - keep.iter().filter(|k| **k).count();
+		for k in keep.iter_mut().filter(|k| !**k) {
+			if free == 0 {
+				break;
+			}
+			*k = true;
+			free -= 1;
+		}
+		candidates.into_iter().zip(keep).filter(|&(_, k)| k).map(|(n, _)| n).collect()
+	}
+
+	/// Outcome of the last load of the saved table. Skipped entries are kept in memory only, the next save drops
+	/// them for good.
+	pub fn load_report(&self) -> &LoadReport {
+		&self.load_report
+	}
+
+	/// Check the consistency of the table state. Fails with a description of the first violation found.
+	#[cfg(test)]
+	fn check_invariants(&self) -> Result<(), String> {
+		for (id, node) in &self.nodes {
+			if node.id != *id {
+				return Err(format!("node {} stored as {}", node.id, id));
+			}
+			if id.is_zero() {
+				return Err("node with the zero id".into());
+			}
+			if node.candidate_endpoint.as_ref() == Some(&node.endpoint) {
+				return Err(format!("node {} has its own endpoint as candidate", id));
+			}
+			if node.previous_endpoint.as_ref() == Some(&node.endpoint) {
+				return Err(format!("node {} has its own endpoint as previous endpoint", id));
+			}
+			if node.address_history.len() > META_LIMITS.address_history {
+				return Err(format!("node {} has {} history entries", id, node.address_history.len()));
+			}
+			if node.source != NodeSource::Unknown && !node.sources.contains(node.source) {
+				return Err(format!("node {} source {:?} not in its sources", id, node.source));
+			}
+			if node.peer_type == PeerType::Required && self.useless_nodes.contains_key(id) {
+				return Err(format!("required node {} marked as useless", id));
+			}
+			if self.is_tombstoned(id) {
+				return Err(format!("node {} is tombstoned", id));
+			}
+		}
+		// bans may name nodes that are not in the table, see `Host::ban_node`, useless marks only a few
+		if self.unknown_useless() > MAX_UNKNOWN_USELESS {
+			return Err(format!("{} useless marks of unknown nodes", self.unknown_useless()));
+		}
+		if let Some(id) = self.backoff.keys().find(|id| !self.nodes.contains_key(id)) {
+			return Err(format!("dial backoff of unknown node {}", id));
+		}
+		if let Some((id, _)) = self.backoff.iter().find(|&(_, b)| b.failures == 0) {
+			return Err(format!("dial backoff of node {} without failures", id));
+		}
+		if let Some(id) = self.forced_dials.iter().find(|id| !self.nodes.contains_key(id)) {
+			return Err(format!("forced dial of unknown node {}", id));
+		}
+
+		let sorted = self.sorted_entries(|_| true);
+		let now = self.clock.now_system();
+		for pair in sorted.windows(2) {
+			if self.compare(pair[0], pair[1], now) == Ordering::Greater || self.compare(pair[1], pair[0], now) == Ordering::Less {
+				return Err(format!("nodes {} and {} out of order", pair[0].id, pair[1].id));
+			}
+		}
+		let ordered: Vec<NodeId> = self.entries_with_meta(usize::max_value(), true).into_iter().map(|e| e.id).collect();
+		if ordered != sorted.iter().map(|n| n.id).collect::<Vec<_>>() {
+			return Err("entries not in table order".into());
+		}
+
+		let stats = self.stats(&IpFilter::default());
+		let useful = self.entries_with_meta(usize::max_value(), false).len();
+		if stats.total != self.nodes.len() || stats.total != sorted.len() {
+			return Err(format!("total {} for {} nodes", stats.total, self.nodes.len()));
+		}
+		if stats.useless != stats.total - useful {
+			return Err(format!("{} useless of {} nodes with {} useful", stats.useless, stats.total, useful));
+		}
+		if stats.backed_off > stats.total || stats.contacted > stats.total || stats.filtered > stats.total {
+			return Err(format!("stats exceed the {} nodes: {:?}", stats.total, stats));
+		}
+		if stats.tombstones > self.tombstones.len() || stats.banned > self.banned.len() {
+			return Err(format!("stats exceed the tombstones or bans: {:?}", stats));
+		}
+		Ok(())
+	}
+}
+
+impl Drop for NodeTable {
+	fn drop(&mut self) {
+		let timeout = self.config.final_save_timeout;
+		self.save_with_timeout(timeout);
+	}
+}
+
+
+/// How long `NodeTable::from_fixture` bans nodes marked as banned.
+#[cfg(any(test, feature = "test-helpers"))]
+const FIXTURE_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Compact description of a node table entry for building tables in tests and comparing their state, see
+/// `NodeTable::from_fixture` and `NodeTable::to_fixture`.
+#[cfg(any(test, feature = "test-helpers"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureNode {
+	/// Node id in hex without leading zeros, e.g. `a1` for `0x00..00a1`.
+	pub id: String,
+	/// TCP and UDP address.
+	pub address: String,
+	/// Number of connection attempts.
+	pub attempts: u32,
+	/// Number of failed connection attempts.
+	pub failures: u32,
+	/// Number of established sessions.
+	pub successes: u32,
+	/// Time since the last successful contact, relative to the clock of the table.
+	pub last_contact: Option<Duration>,
+	/// Where the node was learned from.
+	pub source: NodeSource,
+	/// Capabilities in `<protocol>/<version>` form, `None` if the node was never connected.
+	pub capabilities: Option<Vec<String>>,
+	/// Reserved for all protocols.
+	pub required: bool,
+	/// Boot node.
+	pub boot: bool,
+	/// The last session stopped answering pings.
+	pub stalled: bool,
+	/// Marked as useless.
+	pub useless: bool,
+	/// Banned.
+	pub banned: bool,
+	/// Earlier addresses, newest first.
+	pub address_history: Vec<String>,
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl FixtureNode {
+	/// A node that was never contacted.
+	pub fn new(id: &str, address: &str) -> Self {
+		FixtureNode {
+			id: id.into(),
+			address: address.into(),
+			attempts: 0,
+			failures: 0,
+			successes: 0,
+			last_contact: None,
+			source: NodeSource::Unknown,
+			capabilities: None,
+			required: false,
+			boot: false,
+			stalled: false,
+			useless: false,
+			banned: false,
+			address_history: Vec::new(),
+		}
+	}
+
+	/// Returns the full node id.
+	pub fn node_id(&self) -> NodeId {
+		NodeId::from_str(&format!("{:0>128}", self.id)).expect("invalid fixture node id")
+	}
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl Display for FixtureNode {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "{} {} attempts={} failures={} successes={}", self.id, self.address, self.attempts, self.failures, self.successes)?;
+		if let Some(age) = self.last_contact {
+			write!(f, " contact={}s", age.as_secs())?;
+		}
+		if let Some(ref caps) = self.capabilities {
+			write!(f, " caps={}", caps.join(","))?;
+		}
+		let flags = [(self.required, "required"), (self.boot, "boot"), (self.stalled, "stalled"), (self.useless, "useless"), (self.banned, "banned")];
+		for &(set, flag) in flags.iter() {
+			if set {
+				write!(f, " {}", flag)?;
+			}
+		}
+		if !self.address_history.is_empty() {
+			write!(f, " history={}", self.address_history.join(","))?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl NodeTable {
+	/// Build an in-memory table with the default config from `nodes`, using a manual clock that starts now.
+	pub fn from_fixture(nodes: &[FixtureNode]) -> NodeTable {
+		NodeTable::from_fixture_with(nodes, NodeTableConfig::default(), &ManualClock::new())
+	}
+
+	/// Build an in-memory table with `config` from `nodes`, see `TableState::from_fixture_with`.
+	pub fn from_fixture_with(nodes: &[FixtureNode], config: NodeTableConfig, clock: &ManualClock) -> NodeTable {
+		NodeTable::wrap(TableState::from_fixture_with(nodes, config, clock), None, false)
+	}
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl TableState {
+	/// Build a table with the default config from `nodes`, using a manual clock that starts now.
+	pub fn from_fixture(nodes: &[FixtureNode]) -> TableState {
+		TableState::from_fixture_with(nodes, NodeTableConfig::default(), &ManualClock::new())
+	}
+
+	/// Build a table with `config` from `nodes`, using `clock`. Contact times are relative to the current time of
+	/// `clock`.
+	pub fn from_fixture_with(nodes: &[FixtureNode], config: NodeTableConfig, clock: &ManualClock) -> TableState {
+		let mut table = TableState::new(config);
+		table.set_clock(Box::new(clock.clone()));
+		for fixture in nodes {
+			let id = fixture.node_id();
+			let mut node = Node::new(id.clone(), NodeEndpoint::from_str(&fixture.address).expect("invalid fixture address"));
+			node.attempts = fixture.attempts;
+			node.failures = fixture.failures;
+			node.successes = fixture.successes;
+			node.last_contact = fixture.last_contact.map(|age| clock.now_system() - age);
+			node.source = fixture.source;
+			node.capabilities = fixture.capabilities.as_ref().map(|caps| caps.iter().filter_map(|c| parse_capability(c)).collect());
+			if fixture.required {
+				node.peer_type = PeerType::Required;
+			}
+			node.is_boot = fixture.boot;
+			node.stalled = fixture.stalled;
+			node.address_history = fixture.address_history.iter()
+				.map(|a| (SocketAddr::from_str(a).expect("invalid fixture address"), clock.now_system()))
+				.collect();
+			table.add_node(node);
+			if fixture.useless {
+				table.useless_nodes.insert(id.clone(), Exclusion { time: clock.now_instant(), reason: "fixture".into(), epoch: 0, repeats: 0 });
+			}
+			if fixture.banned {
+				table.ban(&id, FIXTURE_BAN_DURATION, "fixture");
+			}
+		}
+		table
+	}
+
+	/// Describe all nodes, including useless and banned ones, in the order used for selection. Nodes that compare
+	/// equal are listed by id.
+	pub fn to_fixture(&self) -> Vec<FixtureNode> {
+		let mut nodes: Vec<&Node> = self.nodes.values().collect();
+		nodes.sort_by_key(|n| n.id);
+		nodes.sort_by(|a, b| compare_nodes(a, b));
+		let now = self.clock.now_system();
+		nodes.into_iter().map(|n| {
+			let hex = format!("{:x}", n.id);
+			FixtureNode {
+				id: hex.find(|c| c != '0').map_or("0", |i| &hex[i..]).to_owned(),
+				address: n.endpoint.address.to_string(),
+				attempts: n.attempts,
+				failures: n.failures,
+				successes: n.successes,
+				last_contact: n.last_contact.map(|t| now.duration_since(t).unwrap_or(Duration::from_secs(0))),
+				source: n.source,
+				capabilities: n.capabilities.as_ref().map(|caps| caps.iter().map(|c| c.to_string()).collect()),
+				required: n.peer_type == PeerType::Required,
+				boot: n.is_boot,
+				stalled: n.stalled,
+				useless: self.is_useless(n),
+				banned: self.is_banned(&n.id),
+				address_history: n.address_history.iter().map(|&(address, _)| address.to_string()).collect(),
+			}
+		}).collect()
+	}
+}
+
+/// Order in which nodes are tried: by failure percentage, then by the absolute number of failures and attempts.
+fn compare_nodes(a: &Node, b: &Node) -> Ordering {
+	a.ordering_percentage().cmp(&b.ordering_percentage())
+		.then_with(|| a.failures.cmp(&b.failures))
+		.then_with(|| b.attempts.cmp(&a.attempts)) // we use reverse ordering for number of attempts
+}
+
+/// Returns the /24 (IPv4) or /48 (IPv6) subnet of an address.
+fn subnet(ip: &IpAddr) -> IpAddr {
+	match *ip {
+		IpAddr::V4(ref ip) => {
+			let o = ip.octets();
+			IpAddr::V4(Ipv4Addr::new(o[0], o[1], o[2], 0))
+		},
+		IpAddr::V6(ref ip) => {
+			let s = ip.segments();
+			IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0))
+		},
+	}
+}
+
+/// Strict enode URL parser. Unlike `Node::from_str` it only accepts the `enode://<id>@<address>` form and checks
+/// that the node id is a valid secp256k1 public key.
+pub fn parse_enode(url: &str) -> Result<Node, Error> {
+	if !url.starts_with("enode://") {
+		return Err(ErrorKind::InvalidNodeId.into());
+	}
+	let node = Node::from_str(url)?;
+	if !public_is_valid(&node.id) {
+		return Err(ErrorKind::InvalidNodeId.into());
+	}
+	Ok(node)
+}
+
+/// Validate an enode URL with the strict parser and return it in canonical form.
+pub fn normalize_node_url(url: &str) -> Result<String, Error> {
+	parse_enode(url).map(|node| node.to_string())
+}
+
+/// Why a boot node or static peer entry was not used, see `parse_bootnodes` and `parse_static_peers`.
+#[derive(Debug)]
+pub enum NodeParseError {
+	/// Not a valid enode URL or address.
+	Invalid(Error),
+	/// The endpoint cannot be dialled, e.g. an unspecified address or port 0.
+	BadEndpoint,
+	/// Longer than `MAX_ENODE_LEN`.
+	TooLong,
+	/// Same id as the entry at the given index, but another endpoint.
+	Duplicate(usize),
+}
+
+impl Display for NodeParseError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match *self {
+			NodeParseError::Invalid(ref e) => write!(f, "{}", e),
+			NodeParseError::BadEndpoint => write!(f, "Address cannot be dialled"),
+			NodeParseError::TooLong => write!(f, "Longer than {} bytes", MAX_ENODE_LEN),
+			NodeParseError::Duplicate(index) => write!(f, "Same node id as entry #{} with another address", index),
+		}
+	}
+}
+
+/// Format of a node list read by `NodeTable::ingest_list`. Blank lines and lines starting with `#` are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+	/// One enode URL per line, e.g. a boot node file.
+	EnodeLines,
+	/// One base64 encoded ENR per line, as published in DNS discovery trees.
+	EnrBase64Lines,
+}
+
+/// What became of a node list entry, see `IngestStats`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListEntryResult {
+	/// Added to the table with the given id.
+	Added(NodeId),
+	/// Already in the table, the statistics are kept.
+	Known,
+	/// Not a valid entry in the list format.
+	Invalid(String),
+	/// Longer than `MAX_LIST_LINE_BYTES`.
+	TooLong,
+	/// The address is not allowed by the IP filter.
+	Filtered,
+	/// Not taken by the table, e.g. our own id, a full subnet or refused by the admission hook.
+	Refused,
+	/// The list format cannot be checked by this node.
+	Unsupported,
+}
+
+impl Display for ListEntryResult {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match *self {
+			ListEntryResult::Added(_) => write!(f, "added"),
+			ListEntryResult::Known => write!(f, "already known"),
+			ListEntryResult::Invalid(ref e) => write!(f, "{}", e),
+			ListEntryResult::TooLong => write!(f, "Line longer than {} bytes", MAX_LIST_LINE_BYTES),
+			ListEntryResult::Filtered => write!(f, "Address not allowed by the IP filter"),
+			ListEntryResult::Refused => write!(f, "Refused by the node table"),
+			ListEntryResult::Unsupported => write!(f, "ENR records are not supported"),
+		}
+	}
+}
+
+/// Outcome of `NodeTable::ingest_list`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IngestStats {
+	/// Result of each entry with its line number, starting at 1.
+	pub entries: Vec<(usize, ListEntryResult)>,
+	/// The input had more than `MAX_LIST_LINES` lines.
+	pub truncated: bool,
+}
+
+impl IngestStats {
+	/// Ids of the nodes added to the table.
+	pub fn added(&self) -> Vec<NodeId> {
+		self.entries.iter().filter_map(|&(_, ref r)| match *r {
+			ListEntryResult::Added(id) => Some(id),
+			_ => None,
+		}).collect()
+	}
+
+	/// Entries that were neither added nor known, with their line numbers.
+	pub fn failures(&self) -> Vec<&(usize, ListEntryResult)> {
+		self.entries.iter().filter(|&&(_, ref r)| match *r {
+			ListEntryResult::Added(_) | ListEntryResult::Known => false,
+			_ => true,
+		}).collect()
+	}
+}
+
+/// Outcome of `TableState::apply_static_peers`.
+#[derive(Debug, Default)]
+pub struct StaticPeersUpdate {
+	/// Peers in the file.
+	pub loaded: usize,
+	/// Peers that were not in the table before.
+	pub added: Vec<NodeId>,
+	/// Peers marked as required in the file.
+	pub required: Vec<NodeId>,
+	/// Peers of the previous load no longer in the file. They are kept in the table.
+	pub removed: usize,
+	/// Peers no longer required by the file that were made optional. Reserved peers stay required.
+	pub downgraded: Vec<NodeId>,
+	/// Entries that could not be parsed, with their line numbers, starting at 1.
+	pub errors: Vec<(usize, NodeParseError)>,
+}
+
+/// Parse a static peers file, one enode URL per line. A `!` before the URL marks the peer as required. Blank lines
+/// and lines starting with `#` are skipped. Entries are checked like boot nodes, see `parse_bootnodes`, but must
+/// name the node id, and only the first entry of a repeated peer counts. Returns the peers with whether they are
+/// required, and the errors with their line numbers, starting at 1.
+pub fn parse_static_peers(content: &str) -> (Vec<(Node, bool)>, Vec<(usize, NodeParseError)>) {
+	let lines: Vec<(usize, bool, &str)> = content.lines().enumerate()
+		.map(|(index, line)| (index + 1, line.trim()))
+		.filter(|&(_, line)| !line.is_empty() && !line.starts_with('#'))
+		.map(|(number, line)| if line.starts_with('!') { (number, true, line[1..].trim()) } else { (number, false, line) })
+		.collect();
+	let entries: Vec<&str> = lines.iter().map(|&(_, _, entry)| entry).collect();
+	let (nodes, errors) = parse_entries(&entries);
+	let mut errors: Vec<(usize, NodeParseError)> = errors.into_iter().map(|(index, e)| match e {
+		NodeParseError::Duplicate(known) => (lines[index].0, NodeParseError::Duplicate(lines[known].0)),
+		e => (lines[index].0, e),
+	}).collect();
+	let mut peers = Vec::with_capacity(nodes.len());
+	for (index, node) in nodes {
+		if node.id.is_zero() {
+			errors.push((lines[index].0, NodeParseError::Invalid(ErrorKind::InvalidNodeId.into())));
+		} else {
+			peers.push((node, lines[index].1));
+		}
+	}
+	errors.sort_by_key(|&(number, _)| number);
+	(peers, errors)
+}
+
+/// Parse boot nodes, e.g. from a chain specification. Enode URLs are checked with the strict parser, bare addresses
+/// are pinged by discovery to learn the id. Entries repeating an earlier one are left out. All problems are
+/// returned with the index of the entry, not just the first one.
+pub fn parse_bootnodes(entries: &[String]) -> (Vec<Node>, Vec<(usize, NodeParseError)>) {
+	let entries: Vec<&str> = entries.iter().map(|entry| &entry[..]).collect();
+	let (nodes, errors) = parse_entries(&entries);
+	(nodes.into_iter().map(|(_, node)| node).collect(), errors)
+}
+
+/// See `parse_bootnodes`, the nodes are returned with the index of their entry.
+fn parse_entries(entries: &[&str]) -> (Vec<(usize, Node)>, Vec<(usize, NodeParseError)>) {
+	let mut nodes: Vec<(usize, Node)> = Vec::new();
+	let mut errors = Vec::new();
+	for (index, entry) in entries.iter().enumerate() {
+		let entry = entry.trim();
+		if entry.len() > MAX_ENODE_LEN {
+			errors.push((index, NodeParseError::TooLong));
+			continue;
+		}
+		let parsed = if entry.starts_with("enode://") {
+			parse_enode(entry)
+		} else {
+			NodeEndpoint::from_str(entry).map(|endpoint| Node::new(NodeId::new(), endpoint))
+		};
+		let node = match parsed {
+			Ok(ref node) if !node.endpoint.is_valid() => {
+				errors.push((index, NodeParseError::BadEndpoint));
+				continue;
+			},
+			Ok(node) => node,
+			Err(e) => {
+				errors.push((index, NodeParseError::Invalid(e)));
+				continue;
+			},
+		};
+		// bare addresses all have the zero id, they only repeat each other at the same address
+		if let Some(&(known, ref n)) = nodes.iter().find(|&&(_, ref n)| n.id == node.id && (!n.id.is_zero() || n.endpoint == node.endpoint)) {
+			if n.endpoint != node.endpoint {
+				errors.push((index, NodeParseError::Duplicate(known)));
+			}
+			continue;
+		}
+		nodes.push((index, node));
+	}
+	(nodes, errors)
+}
+
+/// Delay until the next table maintenance with `sessions` of the `target` sessions connected. It is
+/// `NodeTableConfig::maintenance_min_interval` without sessions and grows with the square of the share of the target
+/// reached, so that maintenance stays frequent until the node is almost fully peered, up to
+/// `NodeTableConfig::maintenance_max_interval` once the target is reached.
+pub fn maintenance_delay(sessions: usize, target: usize, config: &NodeTableConfig) -> Duration {
+	let lower = config.maintenance_min_interval;
+	let upper = max(config.maintenance_max_interval, lower);
+	if sessions >= target {
+		return upper;
+	}
+	let share = sessions as f64 / target as f64;
+	let span = upper - lower;
+	let span_ms = span.as_secs() as f64 * 1000.0 + span.subsec_nanos() as f64 / 1_000_000.0;
+	lower + Duration::from_millis((span_ms * share * share) as u64)
+}
+
+/// Interval of the saves between the refreshes after a maintenance `delay`: `NodeTableConfig::autosave_interval`
+/// shortened by the share of `NodeTableConfig::maintenance_max_interval` the delay takes, but not below the delay.
+/// A starved host learns and dials many nodes, which are then saved within seconds instead of minutes.
+fn autosave_delay(delay: Duration, config: &NodeTableConfig) -> Duration {
+	let upper = max(config.maintenance_max_interval, config.maintenance_min_interval);
+	let millis = |d: Duration| d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0;
+	if millis(upper) <= 0.0 {
+		return delay;
+	}
+	let share = millis(delay) / millis(upper);
+	max(Duration::from_millis((millis(config.autosave_interval) * share) as u64), delay)
+}
+
+/// Parse a node id given either as an enode URL or as a hex string.
+pub fn parse_node_id(s: &str) -> Result<NodeId, Error> {
+	if s.starts_with("enode://") {
+		Node::from_str(s).map(|n| n.id)
+	} else {
+		let hex = if s.starts_with("0x") { &s[2..] } else { s };
+		hex.parse().map_err(|_| ErrorKind::InvalidNodeId.into())
+	}
+}
+
+/// Check if node url is valid
+pub fn validate_node_url(url: &str) -> Option<Error> {
+	if url.len() > MAX_ENODE_LEN {
+		return Some(ErrorKind::NodeUrlTooLong(MAX_ENODE_LEN).into());
+	}
+	match Node::from_boot_str(url) {
+		Ok(_) => None,
+		Err(e) => Some(e)
+	}
+}
+
+/// Latest last contact time accepted from a saved table, in seconds since UNIX epoch.
+const MAX_CONTACT_SECS: u64 = ::std::u32::MAX as u64;
+
+impl Node {
+	/// Returns the node as it is saved. The latency, the candidate and previous endpoints and the boot and untrusted
+	/// flags only apply to the current run and are not saved.
+	pub fn to_persisted(&self) -> PersistedNode {
+		PersistedNode {
+			url: format!("{}", self),
+			attempts: self.attempts,
+			failures: self.failures,
+			successes: self.successes,
+			last_contact: self.last_contact.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()),
+			source: self.source,
+			client_version: self.client_version.clone(),
+			capabilities: self.capabilities.as_ref().map(|caps| caps.iter().map(|c| c.to_string()).collect()),
+			stalled: self.stalled,
+			warm: self.warm,
+			required: self.peer_type == PeerType::Required,
+			required_for: {
+				let mut protocols: Vec<String> = self.required_for.iter().map(|p| String::from_utf8_lossy(p).into_owned()).collect();
+				protocols.sort();
+				protocols
+			},
+			sources: self.sources.to_vec(),
+			address_history: self.address_history.iter()
+				.map(|&(address, time)| (address.to_string(), time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)))
+				.collect(),
+			tags: self.tags.clone(),
+			reliability: None,
+			p2p_version: self.p2p_version,
+			snappy: self.snappy,
+			trusted: self.trusted,
+			chain: self.chain.map(|(info, epoch)| PersistedChain {
+				network_id: info.network_id,
+				genesis: format!("{:x}", info.genesis),
+				fork_hash: info.fork_hash,
+				epoch: epoch,
+			}),
+		}
+	}
+
+	/// Restore a saved node. Saved URLs are not resolved, fails if the URL is not valid.
+	pub fn from_persisted(saved: PersistedNode) -> Result<Node, Error> {
+		let mut node = Node::parse(&saved.url, false)?;
+		node.attempts = saved.attempts;
+		node.failures = saved.failures;
+		node.successes = saved.successes;
+		node.last_contact = saved.last_contact.map(|secs| UNIX_EPOCH + Duration::from_secs(min(secs, MAX_CONTACT_SECS)));
+		node.source = saved.source;
+		node.client_version = saved.client_version;
+		node.capabilities = saved.capabilities.map(|caps| caps.iter().filter_map(|c| parse_capability(c)).collect());
+		node.stalled = saved.stalled;
+		node.warm = saved.warm;
+		if saved.required {
+			node.peer_type = PeerType::Required;
+		}
+		node.required_for = saved.required_for.iter().filter_map(|p| parse_protocol(p)).collect();
+		for source in saved.sources {
+			node.sources.insert(source);
+		}
+		node.address_history = saved.address_history.iter()
+			.filter_map(|&(ref address, secs)| address.parse().ok().map(|a| (a, UNIX_EPOCH + Duration::from_secs(min(secs, MAX_CONTACT_SECS)))))
+			.take(META_LIMITS.address_history)
+			.collect();
+		node.tags = saved.tags;
+		node.p2p_version = saved.p2p_version;
+		node.snappy = saved.snappy;
+		node.trusted = saved.trusted;
+		// an unreadable genesis hash only loses the chain info
+		node.chain = saved.chain.and_then(|chain| chain.genesis.parse().ok().map(|genesis| (PeerChainInfo {
+			network_id: chain.network_id,
+			genesis: genesis,
+			fork_hash: chain.fork_hash,
+		}, chain.epoch)));
+		META_LIMITS.limit_node(&mut node);
+		Ok(node)
+	}
+}
+
+impl PersistedNode {
+	/// Convert to a node table entry, see `Node::from_persisted`.
+	pub fn into_node(self) -> Result<Node, Error> {
+		Node::from_persisted(self)
+	}
+}
+
+impl<'a> From<&'a Node> for PersistedNode {
+	fn from(node: &'a Node) -> Self {
+		node.to_persisted()
+	}
+}
+
+/// Parse a three letter protocol id, e.g. `eth`.
+fn parse_protocol(s: &str) -> Option<ProtocolId> {
+	if s.len() != 3 {
+		return None;
+	}
+	let mut protocol: ProtocolId = [0u8; 3];
+	protocol.clone_from_slice(s.as_bytes());
+	Some(protocol)
+}
+
+/// Parse capability in `<protocol>/<version>` form, e.g. `eth/63`.
+fn parse_capability(s: &str) -> Option<PeerCapabilityInfo> {
+	let mut parts = s.splitn(2, '/');
+	let protocol = parse_protocol(parts.next()?)?;
+	let version = parts.next()?.parse().ok()?;
+	Some(PeerCapabilityInfo { protocol: protocol, version: version })
 }
 
 #[cfg(test)]
@@ -433,98 +5711,4246 @@ mod tests {
 	use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr};
 	use ethereum_types::H512;
 	use std::str::FromStr;
+	use std::fs::{self, File};
 	use tempdir::TempDir;
 	use ipnetwork::IpNetwork;
+	use persistence::{NODES_FILE, MemoryPersistence, FilePersistence};
+	use clock::ManualClock;
+	use rand::{Rng, SeedableRng, XorShiftRng};
+	use ethkey::{Generator, Random};
+	use discovery::Discovery;
+
+	#[test]
+	fn endpoint_parse() {
+		let endpoint = NodeEndpoint::from_str("123.99.55.44:7770");
+		assert!(endpoint.is_ok());
+		let v4 = match endpoint.unwrap().address {
+			SocketAddr::V4(v4address) => v4address,
+			_ => panic!("should ve v4 address")
+		};
+		assert_eq!(SocketAddrV4::new(Ipv4Addr::new(123, 99, 55, 44), 7770), v4);
+	}
+
+	#[test]
+	fn endpoint_from_rlp_strict() {
+		let encoded = |address: &str, udp_port: u16| {
+			let mut endpoint = NodeEndpoint::from_str(address).unwrap();
+			endpoint.udp_port = udp_port;
+			let mut rlp = RlpStream::new();
+			endpoint.to_rlp_list(&mut rlp);
+			rlp.out()
+		};
+		let valid = encoded("22.99.55.44:7770", 7771);
+		assert_eq!(NodeEndpoint::from_rlp_strict(&UntrustedRlp::new(&valid)).unwrap().udp_port, 7771);
+		for data in &[encoded("22.99.55.44:0", 7771), encoded("22.99.55.44:7770", 0)] {
+			assert!(NodeEndpoint::from_rlp(&UntrustedRlp::new(data)).is_ok());
+			assert_eq!(NodeEndpoint::from_rlp_strict(&UntrustedRlp::new(data)), Err(DecoderError::Custom("zero port")));
+		}
+		// privileged ports are left to the node table
+		assert!(NodeEndpoint::from_rlp_strict(&UntrustedRlp::new(&encoded("22.99.55.44:22", 25))).is_ok());
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn peer_type_alias() {
+		assert_eq!(PeerType::_Required, PeerType::Required);
+		let node = Node::builder(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()).peer_type(PeerType::_Required).build();
+		assert!(node.is_required_for(None));
+	}
+
+	#[test]
+	fn node_parse() {
+		assert!(validate_node_url("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").is_none());
+		let node = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770");
+		assert!(node.is_ok());
+		let node = node.unwrap();
+		let v4 = match node.endpoint.address {
+			SocketAddr::V4(v4address) => v4address,
+			_ => panic!("should ve v4 address")
+		};
+		assert_eq!(SocketAddrV4::new(Ipv4Addr::new(22, 99, 55, 44), 7770), v4);
+		assert_eq!(
+			H512::from_str("a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap(),
+			node.id);
+	}
+
+	#[test]
+	fn geth_enodes() {
+		let vectors = include_str!("../res/geth/enodes.txt").lines()
+			.filter(|line| !line.is_empty() && !line.starts_with('#'))
+			.map(|line| {
+				let mut parts = line.split(' ');
+				(parts.next().unwrap(), parts.next().unwrap())
+			});
+		for (url, expected) in vectors {
+			if expected == "invalid" {
+				assert!(normalize_node_url(url).is_err(), "{} accepted", url);
+				continue;
+			}
+			assert!(validate_node_url(url).is_none(), "{} rejected", url);
+			assert_eq!(normalize_node_url(url).unwrap(), expected);
+			let node = Node::from_str(url).unwrap();
+			assert_eq!(node.to_string(), expected);
+			// the canonical form is stable
+			assert_eq!(Node::from_str(expected).unwrap().to_string(), expected);
+			assert_eq!(Node::parse(expected, false).unwrap().endpoint, node.endpoint);
+		}
+	}
+
+	#[test]
+	fn ingest_enode_list() {
+		let mut table = TableState::from_fixture(&[]);
+		let filter = IpFilter::parse("public").unwrap();
+		let list = include_bytes!("../res/node_lists/enodes.txt");
+		let stats = table.ingest_list(&list[..], ListFormat::EnodeLines, &filter).unwrap();
+		let first = NodeId::from_str("d860a01f9722d78051619d1e2351aba3f43f943f6f00718d1b9baa4101932a1f5011f16bb2b1bb35db20d6fe28fa0bf09636d26a87d31de9ec6203eeedb1f666").unwrap();
+		let second = NodeId::from_str("22a8232c3abc76a16ae9d6c3b164f98775fe226f0917b0ca871128a74a8e9630b458460865bab457221f1d448dd9791d24c4e5d88786180ac185df813a68d4de").unwrap();
+		let results: Vec<(usize, String)> = stats.entries.iter().map(|&(line, ref r)| (line, r.to_string())).collect();
+		assert_eq!(results, vec![
+			(2, "added".to_owned()),
+			(4, "added".to_owned()),
+			(5, "Invalid node id".to_owned()),
+			(6, "already known".to_owned()),
+			(7, "Address cannot be dialled".to_owned()),
+			(8, "Address not allowed by the IP filter".to_owned()),
+			(9, format!("Line longer than {} bytes", MAX_LIST_LINE_BYTES)),
+			(10, "Invalid node id".to_owned()),
+		]);
+		assert_eq!(stats.added(), vec![first, second]);
+		assert_eq!(stats.failures().len(), 5);
+		assert!(!stats.truncated);
+		assert_eq!(table.get(&first).unwrap().source, NodeSource::Imported);
+		assert_eq!(table.get(&second).unwrap().endpoint.udp_port, 30301);
+
+		// nodes the table does not take are reported as such
+		let mut table = TableState::from_fixture(&[]);
+		table.set_local_id(first);
+		let stats = table.ingest_list(&list[..], ListFormat::EnodeLines, &filter).unwrap();
+		assert_eq!(stats.entries[0], (2, ListEntryResult::Refused));
+		assert_eq!(stats.added(), vec![second]);
+	}
+
+	#[test]
+	fn ingest_list_bounds() {
+		let mut table = TableState::from_fixture(&[]);
+		let list = include_bytes!("../res/node_lists/enrs.txt");
+		let stats = table.ingest_list(&list[..], ListFormat::EnrBase64Lines, &IpFilter::default()).unwrap();
+		assert_eq!(stats.entries, vec![(2, ListEntryResult::Unsupported), (4, ListEntryResult::Unsupported)]);
+		assert_eq!(table.stats(&IpFilter::default()).total, 0);
+
+		// lines past the limit are not read
+		let mut list = "#\n".repeat(MAX_LIST_LINES - 1);
+		list.push_str("enode://d860a01f9722d78051619d1e2351aba3f43f943f6f00718d1b9baa4101932a1f5011f16bb2b1bb35db20d6fe28fa0bf09636d26a87d31de9ec6203eeedb1f666@18.138.108.67:30303\r\n");
+		list.push_str("enode://22a8232c3abc76a16ae9d6c3b164f98775fe226f0917b0ca871128a74a8e9630b458460865bab457221f1d448dd9791d24c4e5d88786180ac185df813a68d4de@3.209.45.79:30303\n");
+		let stats = table.ingest_list(list.as_bytes(), ListFormat::EnodeLines, &IpFilter::default()).unwrap();
+		assert_eq!(stats.added().len(), 1);
+		assert_eq!(stats.entries[0].0, MAX_LIST_LINES);
+		assert!(stats.truncated);
+
+		// so is a line without an end
+		let list = vec![b'a'; MAX_LIST_LINES * (MAX_LIST_LINE_BYTES + 2) + 10];
+		let stats = table.ingest_list(&list[..], ListFormat::EnodeLines, &IpFilter::default()).unwrap();
+		assert_eq!(stats.entries, vec![(1, ListEntryResult::TooLong)]);
+	}
+
+	/// Enode URLs of nodes with valid ids, the strict parser checks them.
+	fn static_peers(count: usize) -> (Vec<NodeId>, Vec<String>) {
+		let ids: Vec<NodeId> = (0..count).map(|_| Random.generate().unwrap().public().clone()).collect();
+		let urls = ids.iter().enumerate().map(|(i, id)| format!("enode://{:x}@22.99.55.{}:30303", id, i)).collect();
+		(ids, urls)
+	}
+
+	#[test]
+	fn parse_static_peers_file() {
+		let (ids, urls) = static_peers(4);
+		let content = format!("# curated peers\n{}\n\n ! {}\n!{}\n22.99.55.9:30303\nenode://1234@22.99.55.8:30303\n{}\n{}\n",
+			urls[1], urls[2], urls[3], urls[1], urls[2].replace("22.99.55.2", "22.99.55.7"));
+		let (peers, errors) = parse_static_peers(&content);
+		let peers: Vec<(NodeId, bool)> = peers.into_iter().map(|(node, required)| (node.id, required)).collect();
+		assert_eq!(peers, vec![(ids[1], false), (ids[2], true), (ids[3], true)]);
+		let errors: Vec<(usize, String)> = errors.into_iter().map(|(line, e)| (line, e.to_string())).collect();
+		assert_eq!(errors, vec![
+			(6, "Invalid node id".to_owned()),
+			(7, "Invalid node id".to_owned()),
+			(9, "Same node id as entry #4 with another address".to_owned()),
+		]);
+	}
+
+	#[test]
+	fn apply_static_peers() {
+		let (ids, urls) = static_peers(6);
+		let mut table = TableState::from_fixture(&[]);
+		table.add_node(Node::new(ids[1], NodeEndpoint::from_str("22.99.55.1:30303").unwrap()));
+		table.ingest(NodeOrigin::Reserved, Node::new(ids[4], NodeEndpoint::from_str("22.99.55.4:30303").unwrap()));
+
+		// initial load
+		let content = [
+			urls[1].clone(), format!("!{}", urls[2]), format!("!{}", urls[3]), format!("!{}", urls[4]), "bogus".into(),
+		];
+		let update = table.apply_static_peers(&content.join("\n"));
+		assert_eq!((update.loaded, update.removed, update.errors.len()), (4, 0, 1));
+		assert_eq!(update.added, vec![ids[2], ids[3]]);
+		assert_eq!(update.required, vec![ids[2], ids[3], ids[4]]);
+		assert!(table.get(&ids[1]).unwrap().sources.contains(NodeSource::StaticFile));
+		assert_eq!(table.get(&ids[2]).unwrap().source, NodeSource::StaticFile);
+		assert!(table.is_required(&ids[2]) && !table.is_required(&ids[1]));
+
+		// peer 2 is no longer required, 3 and the reserved 4 are gone, 5 is new
+		let update = table.apply_static_peers(&[urls[1].clone(), urls[2].clone(), urls[5].clone()].join("\n"));
+		assert_eq!((update.loaded, update.removed), (3, 2));
+		assert_eq!(update.added, vec![ids[5]]);
+		let (mut downgraded, mut expected) = (update.downgraded, vec![ids[2], ids[3]]);
+		downgraded.sort();
+		expected.sort();
+		assert_eq!(downgraded, expected);
+		assert!(table.contains(&ids[3]) && !table.is_required(&ids[3]));
+		assert!(table.is_required(&ids[4]));
+		table.check_invariants().unwrap();
+	}
+
+	#[test]
+	fn geth_static_nodes() {
+		let nodes = ::persistence::decode_table(include_bytes!("../res/geth/static-nodes.json")).unwrap().nodes;
+		let urls: Vec<String> = nodes.into_iter().filter_map(|n| n.into_node().ok()).map(|n| n.to_string()).collect();
+		assert_eq!(urls, vec![
+			"enode://d860a01f9722d78051619d1e2351aba3f43f943f6f00718d1b9baa4101932a1f5011f16bb2b1bb35db20d6fe28fa0bf09636d26a87d31de9ec6203eeedb1f666@18.138.108.67:30303",
+			"enode://6f8a80d14311c39f35f516fa664deaaaa13e85b2f7493f37f6144d86991ec012937307647bd3b9a82abe2974e1407241d54947bbb39763a4cac9f77166ad92a0@10.3.58.6:30303?discport=30301",
+			"enode://22a8232c3abc76a16ae9d6c3b164f98775fe226f0917b0ca871128a74a8e9630b458460865bab457221f1d448dd9791d24c4e5d88786180ac185df813a68d4de@3.209.45.79:30303",
+		]);
+	}
+
+	/// Full ids of the fixture nodes with the given short ids.
+	fn fixture_ids(ids: &[&str]) -> Vec<NodeId> {
+		ids.iter().map(|id| FixtureNode::new(id, "22.99.55.44:7770").node_id()).collect()
+	}
+
+	#[test]
+	fn table_failure_percentage_order() {
+		let table = TableState::from_fixture(&[
+			// 100%
+			FixtureNode { attempts: 2, failures: 2, ..FixtureNode::new("a1", "22.99.55.44:7770") },
+			// 33%
+			FixtureNode { attempts: 3, failures: 1, ..FixtureNode::new("b1", "22.99.55.44:7770") },
+			// 0%
+			FixtureNode { attempts: 1, ..FixtureNode::new("c1", "22.99.55.44:7770") },
+			// 50% (default when no attempts)
+			FixtureNode::new("d1", "22.99.55.44:7770"),
+		]);
+		assert_eq!(table.nodes(&IpFilter::default()), fixture_ids(&["c1", "b1", "d1", "a1"]));
+	}
+
+	#[test]
+	fn entries_carry_quality_hints() {
+		let clock = ManualClock::new();
+		let table = TableState::from_fixture_with(&[
+			FixtureNode { attempts: 4, failures: 1, successes: 3, last_contact: Some(Duration::from_secs(90)), ..FixtureNode::new("a1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 3, failures: 2, ..FixtureNode::new("b1", "22.99.56.44:7770") },
+			FixtureNode::new("c1", "22.99.57.44:7770"),
+		], NodeTableConfig::default(), &clock);
+		let meta = table.entries_with_meta(usize::max_value(), true);
+		let entries = table.entries_filtered(&IpFilter::default(), usize::max_value());
+		let hints: Vec<_> = entries.iter().map(|e| (e.id, e.failure_bucket, e.last_contact_age)).collect();
+		assert_eq!(hints, vec![
+			(fixture_ids(&["a1"])[0], Some(25), Some(90)),
+			(fixture_ids(&["c1"])[0], Some(50), None),
+			(fixture_ids(&["b1"])[0], Some(65), None),
+		]);
+		for entry in &entries {
+			let meta = meta.iter().find(|m| m.id == entry.id).unwrap();
+			let age = meta.last_contact.map(|t| clock.now_system().duration_since(t).unwrap().as_secs());
+			assert_eq!(entry.last_contact_age, age);
+			assert_eq!(entry.failure_bucket.unwrap(), meta.failure_percentage / 5 * 5);
+		}
+	}
+
+	#[test]
+	fn node_failure_buckets() {
+		// (attempts, failures, bucket, percentage)
+		let matrix = [
+			(0, 0, 50, 50),
+			(0, 3, 50, 50),
+			(1, 0, 0, 0),
+			(1, 1, 100, 100),
+			(2, 1, 50, 50),
+			(3, 1, 30, 33),
+			(3, 2, 65, 67),
+			(6, 1, 15, 17),
+			(7, 1, 10, 14),
+			(19, 1, 5, 5),
+			(21, 1, 0, 5),
+			(40, 39, 95, 98),
+			(100, 99, 95, 99),
+			(2, 5, 100, 100),
+			(u32::max_value(), u32::max_value() - 1, 95, 100),
+		];
+		for &(attempts, failures, bucket, percentage) in &matrix {
+			let mut node = Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap());
+			node.attempts = attempts;
+			node.failures = failures;
+			assert_eq!(node.failure_bucket(), bucket, "bucket of {}/{}", failures, attempts);
+			assert_eq!(node.failure_percentage(), percentage, "percentage of {}/{}", failures, attempts);
+			let ratio = if attempts == 0 { 0.5 } else { (failures as f64 / attempts as f64).min(1.0) };
+			assert_eq!(node.failure_ratio(), ratio);
+		}
+	}
+
+	#[test]
+	fn node_reliability() {
+		let now = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+		let day = 24 * 60 * 60;
+		// (attempts, failures, successes, days since the last contact, reliability)
+		let matrix = [
+			(0, 0, 0, None, 63),
+			(10, 10, 0, None, 0),
+			(10, 0, 20, Some(0), 255),
+			(10, 0, 40, Some(0), 255),
+			(10, 0, 20, Some(30), 191),
+			(10, 0, 20, Some(300), 191),
+			(10, 5, 2, Some(15), 102),
+			(1, 0, 0, None, 127),
+		];
+		for &(attempts, failures, successes, days, reliability) in &matrix {
+			let mut node = Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap());
+			node.attempts = attempts;
+			node.failures = failures;
+			node.successes = successes;
+			node.last_contact = days.map(|days: u64| now - Duration::from_secs(days * day));
+			assert_eq!(node.reliability(now), reliability, "reliability of {}/{}, {} sessions", failures, attempts, successes);
+		}
+		// a contact in the future counts as now
+		let mut node = Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap());
+		node.last_contact = Some(now + Duration::from_secs(day));
+		assert_eq!(node.reliability(now), 127);
+	}
+
+	#[test]
+	fn table_recent_contact_order() {
+		let nodes = [
+			FixtureNode { attempts: 1, ..FixtureNode::new("a1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 4, failures: 3, successes: 1, last_contact: Some(Duration::from_secs(60)), ..FixtureNode::new("b1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 2, failures: 1, successes: 1, last_contact: Some(Duration::from_secs(7200)), ..FixtureNode::new("c1", "22.99.55.44:7770") },
+		];
+		let table = TableState::from_fixture(&nodes);
+		assert_eq!(table.nodes(&IpFilter::default()), fixture_ids(&["a1", "c1", "b1"]));
+
+		let config = NodeTableConfig { recent_contact: Some(Duration::from_secs(3600)), ..Default::default() };
+		let clock = ManualClock::new();
+		let table = TableState::from_fixture_with(&nodes, config, &clock);
+		assert_eq!(table.nodes(&IpFilter::default()), fixture_ids(&["b1", "a1", "c1"]));
+		clock.advance(Duration::from_secs(3600));
+		assert_eq!(table.nodes(&IpFilter::default()), fixture_ids(&["a1", "c1", "b1"]));
+	}
+
+	#[test]
+	fn nodes_default_cached() {
+		let nodes = [
+			FixtureNode { attempts: 1, ..FixtureNode::new("a1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 4, failures: 3, successes: 1, last_contact: Some(Duration::from_secs(60)), ..FixtureNode::new("b1", "22.99.55.45:7770") },
+			FixtureNode { attempts: 2, failures: 1, successes: 1, ..FixtureNode::new("c1", "22.99.55.46:7770") },
+		];
+		let config = NodeTableConfig { recent_contact: Some(Duration::from_secs(3600)), ..Default::default() };
+		let clock = ManualClock::new();
+		let mut table = TableState::from_fixture_with(&nodes, config, &clock);
+		let all = IpFilter::default();
+		assert_eq!(table.nodes_default(), fixture_ids(&["b1", "a1", "c1"]));
+
+		// a change that bypasses `touch` is not seen, the cached result is returned
+		let ids = fixture_ids(&["a1", "c1"]);
+		let (a1, c1) = (ids[0], ids[1]);
+		table.nodes.get_mut(&a1).unwrap().failures = 1;
+		assert_eq!(table.nodes(&all), fixture_ids(&["b1", "c1", "a1"]));
+		assert_eq!(table.nodes_default(), fixture_ids(&["b1", "a1", "c1"]));
+
+		// mutations start a new generation
+		table.note_failure(&a1);
+		assert_eq!(table.nodes_default(), table.nodes(&all));
+		table.ban(&c1, Duration::from_secs(600), "test");
+		assert_eq!(table.nodes_default(), fixture_ids(&["b1", "a1"]));
+		table.set_sibling_endpoints(vec!["22.99.55.45:7770".parse().unwrap()]);
+		assert_eq!(table.nodes_default(), fixture_ids(&["a1"]));
+		table.set_sibling_endpoints(Vec::new());
+
+		// and so does time, for the selection changes it makes by itself
+		clock.advance(Duration::from_secs(600));
+		assert_eq!(table.nodes_default(), fixture_ids(&["b1", "c1", "a1"]));
+		clock.advance(Duration::from_secs(3000));
+		assert_eq!(table.nodes_default(), table.nodes(&all));
+		assert_eq!(table.nodes_default(), fixture_ids(&["c1", "b1", "a1"]));
+	}
+
+	/// Two groups of nodes with identical records, added in reverse id order: ids 1 to 20 never failed, 21 to 40
+	/// failed once.
+	fn tied_fixture() -> Vec<FixtureNode> {
+		(1..41).rev().map(|i| FixtureNode {
+			attempts: 2,
+			failures: if i > 20 { 1 } else { 0 },
+			..FixtureNode::new(&format!("{:x}", i), &format!("22.99.{}.1:7770", i))
+		}).collect()
+	}
+
+	#[test]
+	fn table_ties_ordered_by_id() {
+		let table = TableState::from_fixture(&tied_fixture());
+		let expected: Vec<NodeId> = (1..41).map(NodeId::from).collect();
+		for _ in 0..10 {
+			assert_eq!(table.nodes(&IpFilter::default()), expected);
+			assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 40, None), expected);
+		}
+	}
+
+	#[test]
+	fn table_shuffle_ties() {
+		let config = NodeTableConfig { shuffle_ties: true, ..Default::default() };
+		let table = TableState::from_fixture_with(&tied_fixture(), config, &ManualClock::new());
+		let by_id: Vec<NodeId> = (1..41).map(NodeId::from).collect();
+		let orders: Vec<Vec<NodeId>> = (0..10).map(|_| table.nodes(&IpFilter::default())).collect();
+		for order in &orders {
+			// the groups stay in place, only the order within them changes
+			let mut first = order[..20].to_vec();
+			let mut second = order[20..].to_vec();
+			first.sort();
+			second.sort();
+			assert_eq!(first, &by_id[..20]);
+			assert_eq!(second, &by_id[20..]);
+		}
+		assert!(orders.iter().any(|o| o[..20] != by_id[..20]));
+		assert!(orders.iter().any(|o| o[20..] != by_id[20..]));
+		assert!(orders.iter().any(|o| *o != orders[0]));
+	}
+
+	#[test]
+	fn table_order_golden() {
+		let table = TableState::from_fixture(&[
+			FixtureNode { attempts: 10, ..FixtureNode::new("1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 20, ..FixtureNode::new("2", "22.99.55.44:7770") },
+			FixtureNode { attempts: 20, failures: 1, ..FixtureNode::new("3", "22.99.55.44:7770") },
+			FixtureNode { attempts: 10, stalled: true, ..FixtureNode::new("4", "22.99.55.44:7770") },
+			FixtureNode { attempts: 4, failures: 1, successes: 3, last_contact: Some(Duration::from_secs(30)), ..FixtureNode::new("5", "22.99.55.44:7770") },
+			FixtureNode { attempts: 8, failures: 2, ..FixtureNode::new("6", "22.99.55.44:7770") },
+			FixtureNode::new("7", "22.99.55.44:7770"),
+			FixtureNode { attempts: 2, failures: 1, boot: true, ..FixtureNode::new("8", "22.99.55.45:7770") },
+			FixtureNode { attempts: 2, failures: 2, useless: true, ..FixtureNode::new("9", "22.99.55.44:7770") },
+			FixtureNode { attempts: 3, banned: true, capabilities: Some(vec!["eth/63".into()]), ..FixtureNode::new("a", "22.99.55.44:7770") },
+			FixtureNode { required: true, ..FixtureNode::new("b", "22.99.55.46:7770") },
+		]);
+		let state: String = table.to_fixture().iter().map(|n| format!("{}\n", n)).collect();
+		assert_eq!(state, include_str!("../res/node_table_order.txt"));
+
+		// the description builds the same table again
+		assert_eq!(TableState::from_fixture(&table.to_fixture()).to_fixture(), table.to_fixture());
+	}
+
+	#[test]
+	fn table_save_load() {
+		let tempdir = TempDir::new("").unwrap();
+		let node1 = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
+		let node2 = Node::from_str("enode://b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
+		let id1 = H512::from_str("a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
+		let id2 = H512::from_str("b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
+		{
+			let mut table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
+			table.add_node(node1);
+			table.add_node(node2);
+
+			table.update_node(&id1, |n| n.attempts = 1).unwrap();
+			table.update_node(&id2, |n| n.attempts = 1).unwrap();
+			table.note_failure(&id2);
+		}
+
+		{
+			let table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
+			let r = table.nodes(&IpFilter::default());
+			assert_eq!(r[0][..], id1[..]);
+			assert_eq!(r[1][..], id2[..]);
+		}
+	}
+
+	#[test]
+	fn streaming_load() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().to_str().unwrap().to_owned();
+		let config = NodeTableConfig::default();
+		{
+			let mut table = NodeTable::with_config(Some(path.clone()), true, config.clone());
+			for i in 1..1001 {
+				let mut node = Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.{}.{}.1:30303", i / 256, i % 256)).unwrap());
+				node.attempts = (i % 7) as u32;
+				node.failures = (i % 3) as u32;
+				table.add_node(node);
+			}
+			table.note_success(&NodeId::from(10));
+			for i in (1..1001).filter(|i| i % 10 == 3) {
+				table.mark_as_useless(&NodeId::from(i), "test");
+			}
+			table.ban(&NodeId::from(5000), Duration::from_secs(3600), "test");
+		}
+		let filter = IpFilter::default();
+		let streamed = |config: &NodeTableConfig| {
+			let persistence = Arc::new(::persistence::FilePersistence::for_table(path.clone(), config));
+			let mut table = NodeTable::with_backend_streaming(persistence, config.clone());
+			// the first batch is there right away, the useless nodes in it are not selected
+			assert!(table.stats(&filter).total >= LOAD_BATCH);
+			let useless: Vec<NodeId> = table.nodes.keys().filter(|id| id.low_u64() % 10 == 3).cloned().collect();
+			assert!(!useless.is_empty());
+			let selected = table.select_for(&DialPolicy::default(), &filter, usize::max_value(), None);
+			assert!(useless.iter().all(|id| !selected.contains(id)));
+			assert!(table.receive_loaded(usize::max_value()) || table.load_complete());
+			assert!(table.load_complete() && !table.stats(&filter).loading);
+			table
+		};
+
+		// the same table as loading it at once
+		let loaded = NodeTable::with_config(Some(path.clone()), true, config.clone());
+		let (stats, nodes) = (loaded.stats(&filter), loaded.nodes(&filter));
+		drop(loaded);
+		let table = streamed(&config);
+		assert_eq!(table.stats(&filter), stats);
+		assert_eq!(table.nodes(&filter), nodes);
+		assert!(table.is_banned(&NodeId::from(5000)) && table.entry_status(&NodeId::from(3)).unwrap().useless_reason.is_some());
+		assert_eq!(table.load_report().total, 1000);
+		drop(table);
+
+		// a truncated file keeps the nodes before the cut, loading it at once keeps none
+		let file = tempdir.path().join(::persistence::NODES_FILE);
+		let mut data = Vec::new();
+		File::open(&file).unwrap().read_to_end(&mut data).unwrap();
+		File::create(&file).unwrap().write_all(&data[..data.len() / 2]).unwrap();
+		let table = streamed(&config);
+		let total = table.stats(&filter).total;
+		assert!(total > 100 && total < 900, "{} nodes", total);
+		assert!(table.is_banned(&NodeId::from(5000)));
+		let saved = ::persistence::decode_table(&data).unwrap();
+		assert!(saved.nodes[..total].iter().all(|n| table.get(&Node::from_str(&n.url).unwrap().id).is_some()));
+		drop(table);
+		File::create(&file).unwrap().write_all(&data[..data.len() / 2]).unwrap();
+		assert_eq!(NodeTable::with_config(Some(path.clone()), true, config.clone()).stats(&filter).total, 0);
+	}
+
+	fn capability(protocol: &[u8; 3], version: u8) -> PeerCapabilityInfo {
+		PeerCapabilityInfo { protocol: *protocol, version: version }
+	}
+
+	fn capabilities_table() -> NodeTable {
+		// node 1 runs light protocols, node 2 runs eth only, node 3 was never connected.
+		NodeTable::from_fixture(&[
+			FixtureNode { capabilities: Some(vec!["eth/63".into(), "pip/1".into()]), ..FixtureNode::new("1", "22.99.55.44:7770") },
+			FixtureNode { capabilities: Some(vec!["eth/62".into(), "eth/63".into()]), ..FixtureNode::new("2", "22.99.55.44:7770") },
+			FixtureNode::new("3", "22.99.55.44:7770"),
+		])
+	}
+
+	#[test]
+	fn select_for_matching_capabilities() {
+		let table = capabilities_table();
+		let policy = DialPolicy { required_capabilities: vec![(*b"pip", 1)], allow_unknown: false, ..Default::default() };
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None), vec![NodeId::from(1)]);
+
+		let policy = DialPolicy { required_capabilities: vec![(*b"eth", 63)], allow_unknown: false, ..Default::default() };
+		let mut r = table.select_for(&policy, &IpFilter::default(), 10, None);
+		r.sort();
+		assert_eq!(r, vec![NodeId::from(1), NodeId::from(2)]);
+	}
+
+	#[test]
+	fn select_for_skips_non_matching_capabilities() {
+		let table = capabilities_table();
+		// version mismatch does not count as matching.
+		let policy = DialPolicy { required_capabilities: vec![(*b"pip", 2), (*b"eth", 64)], allow_unknown: false, ..Default::default() };
+		assert!(table.select_for(&policy, &IpFilter::default(), 10, None).is_empty());
+	}
+
+	#[test]
+	fn select_for_unknown_capabilities() {
+		let table = capabilities_table();
+		let policy = DialPolicy { required_capabilities: vec![(*b"pip", 1)], allow_unknown: true, ..Default::default() };
+		let mut r = table.select_for(&policy, &IpFilter::default(), 10, None);
+		r.sort();
+		assert_eq!(r, vec![NodeId::from(1), NodeId::from(3)]);
+
+		let policy = DialPolicy { required_capabilities: vec![(*b"pip", 1)], allow_unknown: false, ..Default::default() };
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None), vec![NodeId::from(1)]);
+
+		// empty requirements match everything regardless of `allow_unknown`.
+		assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 10, None).len(), 3);
+		assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 2, None).len(), 2);
+	}
+
+	#[test]
+	fn table_save_load_capabilities() {
+		let tempdir = TempDir::new("").unwrap();
+		{
+			let mut table = capabilities_table();
+			table.persistence = Some(Arc::new(FilePersistence::new(tempdir.path())));
+			table.set_persistent(true);
+		}
+		let table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
+		let policy = DialPolicy { required_capabilities: vec![(*b"pip", 1)], allow_unknown: false, ..Default::default() };
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None), vec![NodeId::from(1)]);
+		assert!(table.nodes.get(&NodeId::from(3)).unwrap().capabilities.is_none());
+	}
+
+	#[test]
+	fn table_custom_persistence() {
+		let persistence = MemoryPersistence::new();
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+			table.note_failure(&NodeId::from(1));
+		}
+		let saved = persistence.nodes();
+		assert_eq!(saved.len(), 1);
+		assert_eq!(saved[0].failures, 1);
+
+		let table = NodeTable::with_persistence(Box::new(persistence), NodeTableConfig::default());
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(1)]);
+	}
+
+	#[test]
+	fn table_load_report() {
+		let good = PersistedNode::from(&Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		let id = format!("{:x}", NodeId::from(2));
+		let broken = |url: String| PersistedNode { url: url, ..good.clone() };
+		let nodes = vec![
+			broken(format!("enode://{}@22.99.55.45", id)),
+			good.clone(),
+			broken(format!("enode://{}@22.99.55.46:7770?discport=x", id)),
+			broken("22.99.55.47:7770".into()),
+			broken(format!("enode://{}@22.99.55.48:7770", "z".repeat(128))),
+		];
+		let table = NodeTable::with_persistence(Box::new(MemoryPersistence::with_nodes(nodes)), NodeTableConfig::default());
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(1)]);
+
+		let report = table.load_report();
+		assert_eq!(report.total, 5);
+		assert_eq!(report.skipped_count(), 4);
+		assert_eq!(report.to_string(), "skipped 4 of 5 persisted nodes: 2 invalid-id, 2 bad-address");
+		let skipped: Vec<(usize, SkipReason)> = report.skipped_nodes.iter().map(|s| (s.index, s.reason)).collect();
+		assert_eq!(skipped, vec![(0, SkipReason::BadAddress), (2, SkipReason::BadAddress), (3, SkipReason::InvalidId), (4, SkipReason::InvalidId)]);
+		assert_eq!(report.skipped_nodes[2].url, "22.99.55.47:7770");
+
+		// counted in full, but only the first entries are kept
+		let nodes = (0..MAX_SKIPPED_NODES + 8).map(|i| broken(format!("node {}", i))).collect();
+		let table = NodeTable::with_persistence(Box::new(MemoryPersistence::with_nodes(nodes)), NodeTableConfig::default());
+		let report = table.load_report();
+		assert_eq!(report.skipped.get(&SkipReason::InvalidId), Some(&(MAX_SKIPPED_NODES + 8)));
+		assert_eq!(report.skipped_nodes.len(), MAX_SKIPPED_NODES);
+		assert_eq!(report.skipped_nodes.last().unwrap().url, format!("node {}", MAX_SKIPPED_NODES - 1));
+
+		// a clean load reports nothing
+		let table = NodeTable::with_persistence(Box::new(MemoryPersistence::with_nodes(vec![good])), NodeTableConfig::default());
+		assert_eq!(table.load_report().skipped_count(), 0);
+		assert_eq!(table.load_report().to_string(), "skipped 0 of 1 persisted nodes");
+	}
+
+	#[test]
+	fn table_exclusions_persist() {
+		let persistence = MemoryPersistence::new();
+		let (banned, useless, removed) = (NodeId::from(1), NodeId::from(2), NodeId::from(3));
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			for (i, id) in [banned, useless, removed].iter().enumerate() {
+				table.add_node(Node::new(id.clone(), NodeEndpoint::from_str(&format!("22.99.55.{}:7770", i + 1)).unwrap()));
+			}
+			table.ban(&banned, Duration::from_secs(3600), "invalid block");
+			table.mark_as_useless(&useless, "incompatible protocol");
+			// removed nodes keep no exclusion record
+			table.ban(&removed, Duration::from_secs(3600), "invalid block");
+			table.mark_as_useless(&removed, "incompatible protocol");
+			table.remove_node(&removed);
+			table.save();
+		}
+		let saved: Vec<_> = persistence.exclusions().into_iter().map(|e| (e.id, e.kind, e.reason, e.until.is_some())).collect();
+		assert_eq!(saved, vec![
+			(format!("{:x}", banned), ExclusionKind::Ban, "invalid block".to_owned(), true),
+			(format!("{:x}", useless), ExclusionKind::Useless, "incompatible protocol".to_owned(), false),
+		]);
+
+		let table = NodeTable::with_persistence(Box::new(persistence), NodeTableConfig::default());
+		let status = table.entry_status(&banned).unwrap();
+		assert_eq!(status.ban_reason, Some("invalid block".to_owned()));
+		assert!(status.banned_until.unwrap() > SystemTime::now() + Duration::from_secs(3500));
+		assert_eq!(status.useless_reason, None);
+		assert_eq!(table.entry_status(&useless).unwrap().useless_reason, Some("incompatible protocol".to_owned()));
+		assert_eq!(table.entry_status(&removed), None);
+		assert!(!table.contains(&removed));
+
+		let entries = table.entries_with_meta(10, true);
+		let entry = entries.iter().find(|e| e.id == banned).unwrap();
+		assert!(entry.banned);
+		assert_eq!(entry.ban_reason, Some("invalid block".to_owned()));
+	}
+
+	#[test]
+	fn retried_exclusions_not_restored() {
+		let persistence = MemoryPersistence::new();
+		let (banned, useless) = (NodeId::from(1), NodeId::from(2));
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			table.add_node(Node::new(banned.clone(), NodeEndpoint::from_str("22.99.55.1:7770").unwrap()));
+			table.add_node(Node::new(useless.clone(), NodeEndpoint::from_str("22.99.55.2:7770").unwrap()));
+			table.ban(&banned, Duration::from_secs(3600), "invalid block");
+			table.mark_as_useless(&useless, "incompatible protocol");
+			table.save();
+			assert_eq!(persistence.exclusions().len(), 2);
+
+			// nothing else changed since the save, the retry alone has to make the table save again
+			assert!(table.retry_now(&banned));
+			assert!(table.retry_now(&useless));
+		}
+		assert!(persistence.exclusions().is_empty());
+		let table = NodeTable::with_persistence(Box::new(persistence), NodeTableConfig::default());
+		assert!(!table.is_banned(&banned));
+		assert_eq!(table.entry_status(&useless).unwrap().useless_reason, None);
+	}
+
+	#[test]
+	fn compat_epoch_bump() {
+		let persistence = MemoryPersistence::new();
+		let ids: Vec<NodeId> = (1..6).map(NodeId::from).collect();
+		let all = IpFilter::default();
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			for (i, id) in ids.iter().enumerate() {
+				table.add_node(Node::new(id.clone(), NodeEndpoint::from_str(&format!("22.99.55.{}:7770", i + 1)).unwrap()));
+			}
+			table.mark_as_useless(&ids[0], INCOMPATIBLE_PROTOCOL);
+			table.mark_as_useless(&ids[1], INCOMPATIBLE_PROTOCOL);
+			table.mark_as_useless(&ids[2], "disabled by protocol handler");
+			table.ban(&ids[3], Duration::from_secs(3600), "critical misbehaviour");
+			table.save();
+		}
+
+		// the epoch of the marks survives a restart
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		assert_eq!(table.nodes(&all), vec![ids[4]]);
+		assert_eq!(table.bump_compat_epoch(), 2);
+		let mut eligible = table.nodes(&all);
+		eligible.sort();
+		assert_eq!(eligible, vec![ids[0], ids[1], ids[4]]);
+		assert_eq!(table.entry_status(&ids[2]).unwrap().useless_reason, Some("disabled by protocol handler".to_owned()));
+		assert!(table.is_banned(&ids[3]));
+
+		// still incompatible in the new epoch
+		table.mark_as_useless(&ids[0], INCOMPATIBLE_PROTOCOL);
+		table.save();
+		let saved: Vec<_> = persistence.exclusions().into_iter().map(|e| (e.id, e.epoch)).collect();
+		assert_eq!(saved, vec![(format!("{:x}", ids[0]), 1), (format!("{:x}", ids[2]), 0), (format!("{:x}", ids[3]), 0)]);
+		drop(table);
+		let table = NodeTable::with_persistence(Box::new(persistence), NodeTableConfig::default());
+		assert_eq!(table.entry_status(&ids[0]).unwrap().useless_reason, Some(INCOMPATIBLE_PROTOCOL.to_owned()));
+		assert_eq!(table.entry_status(&ids[1]).unwrap().useless_reason, None);
+	}
+
+	#[test]
+	fn membership_staleness() {
+		let mut table = TableState::from_fixture(&[FixtureNode::new("a1", "22.99.55.44:7770")]);
+		let a1 = fixture_ids(&["a1"])[0];
+		let b1 = fixture_ids(&["b1"])[0];
+		let membership = table.membership();
+		assert!(!membership.contains(&a1));
+		assert!(table.refresh_membership());
+		assert!(membership.contains(&a1));
+
+		// changes show after the next refresh only
+		table.add_node(Node::new(b1, NodeEndpoint::from_str("22.99.56.44:7770").unwrap()));
+		table.remove_node(&a1);
+		assert!(membership.contains(&a1) && !membership.contains(&b1));
+		assert!(table.refresh_membership());
+		assert!(!membership.contains(&a1) && membership.contains(&b1));
+		assert!(!table.refresh_membership());
+
+		// the nodes are not compared again until the table changes
+		table.nodes.clear();
+		assert!(!table.refresh_membership());
+		assert!(membership.contains(&b1));
+		table.ban(&b1, Duration::from_secs(60), "test");
+		assert!(table.refresh_membership());
+		assert!(!membership.contains(&b1));
+
+		// a loaded table starts refreshed
+		let persistence = MemoryPersistence::new();
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			table.add_node(Node::new(b1, NodeEndpoint::from_str("22.99.56.44:7770").unwrap()));
+			table.save();
+		}
+		let loaded = NodeTable::with_persistence(Box::new(persistence), NodeTableConfig::default());
+		assert!(loaded.membership().contains(&b1));
+	}
+
+	#[test]
+	fn membership_reads_during_writes() {
+		use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+		let endpoint = |i: u64| NodeEndpoint::from_str(&format!("22.{}.{}.1:7770", i / 256, i % 256)).unwrap();
+		let table = Arc::new(RwLock::new(TableState::from_fixture(&[])));
+		let pinned: Vec<NodeId> = (1..11).map(NodeId::from).collect();
+		{
+			let mut table = table.write();
+			for id in &pinned {
+				table.add_node(Node::new(id.clone(), endpoint(id.low_u64())));
+			}
+			table.refresh_membership();
+		}
+		let stop = Arc::new(AtomicBool::new(false));
+		let readers: Vec<_> = (0..4).map(|_| {
+			let membership = table.read().membership();
+			let (stop, pinned) = (stop.clone(), pinned.clone());
+			thread::spawn(move || {
+				let mut reads = 0;
+				while reads == 0 || !stop.load(AtomicOrdering::SeqCst) {
+					assert!(pinned.iter().all(|id| membership.contains(id)));
+					assert!(!membership.contains(&NodeId::from(1_000_000)));
+					reads += 1;
+				}
+				reads
+			})
+		}).collect();
+
+		// nodes come and go while the readers do not take the table lock
+		for round in 0..200u64 {
+			let mut table = table.write();
+			let ids: Vec<NodeId> = (0..20).map(|i| NodeId::from(100 + round * 20 + i)).collect();
+			for id in &ids {
+				table.add_node(Node::new(id.clone(), endpoint(id.low_u64())));
+			}
+			table.refresh_membership();
+			for id in &ids {
+				table.remove_node(id);
+			}
+			table.refresh_membership();
+		}
+		stop.store(true, AtomicOrdering::SeqCst);
+		for reader in readers {
+			assert!(reader.join().unwrap() > 0);
+		}
+		let membership = table.read().membership();
+		assert!(!membership.contains(&NodeId::from(100)));
+		assert_eq!(pinned.iter().filter(|id| membership.contains(id)).count(), pinned.len());
+	}
+
+	#[test]
+	fn outage_quarantines_failures() {
+		let clock = ManualClock::new();
+		let mut table = TableState::from_fixture_with(&[], NodeTableConfig::default(), &clock);
+		let ids: Vec<NodeId> = (1..21).map(NodeId::from).collect();
+		for (i, id) in ids.iter().enumerate() {
+			table.add_node(Node::new(id.clone(), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i + 1)).unwrap()));
+		}
+		table.note_success(&ids[0]);
+		// at 1 of 2 failed, a ratio that quarantined dials must not improve
+		table.note_dial(&ids[19]);
+		table.note_failure(&ids[19]);
+		table.note_dial(&ids[19]);
+		table.note_success(&ids[19]);
+		let counters = |table: &TableState| table.to_fixture().into_iter().map(|n| (n.attempts, n.failures, n.successes)).collect::<Vec<_>>();
+		let before = counters(&table);
+
+		// a burst of failed dials during the outage leaves the counters alone but still backs off the nodes
+		table.note_global_outage_suspected();
+		for _ in 0..3 {
+			clock.advance(Duration::from_secs(60));
+			for id in &ids {
+				table.note_dial(id);
+				table.note_failure(id);
+			}
+		}
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.outage, stats.quarantined, stats.outages, stats.backed_off), (true, 20, 1, 20));
+		assert_eq!(counters(&table), before);
+
+		// the first success ends the outage and discards the quarantined failures
+		table.note_success(&ids[5]);
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.outage, stats.quarantined, stats.outages, stats.backed_off), (false, 0, 1, 0));
+		table.note_failure(&ids[1]);
+		assert_eq!(table.to_fixture().iter().map(|n| n.failures).sum::<u32>(), 2);
+
+		// without a success the failures count again after `outage_max`
+		table.note_global_outage_suspected();
+		table.note_failure(&ids[2]);
+		clock.advance(Duration::from_secs(10 * 60));
+		table.note_failure(&ids[3]);
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.outage, stats.quarantined, stats.outages), (false, 0, 2));
+		assert_eq!(table.to_fixture().iter().map(|n| n.failures).sum::<u32>(), 3);
+	}
+
+	#[test]
+	fn table_expired_ban_not_restored() {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+		let ban = |id: u64, until: u64| PersistedExclusion {
+			id: format!("{:x}", NodeId::from(id)),
+			kind: ExclusionKind::Ban,
+			reason: "invalid block".into(),
+			until: Some(until),
+			epoch: 0,
+		};
+		let persistence = MemoryPersistence::with_table(PersistedTable {
+			network_id: None,
+			nodes: Vec::new(),
+			exclusions: vec![ban(1, now - 60), ban(2, now + 3600)],
+			omitted: None,
+			compat_epoch: 0,
+			history: Vec::new(),
+			saved_at: None,
+		});
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		assert_eq!(table.entry_status(&NodeId::from(1)), None);
+		assert_eq!(table.entry_status(&NodeId::from(2)).unwrap().ban_reason, Some("invalid block".to_owned()));
+
+		table.set_persistent(true);
+		table.save();
+		let saved: Vec<_> = persistence.exclusions().into_iter().map(|e| e.id).collect();
+		assert_eq!(saved, vec![format!("{:x}", NodeId::from(2))]);
+	}
+
+	#[test]
+	fn table_save_only_when_dirty() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let file = tempdir.path().join(NODES_FILE);
+		let mut table = NodeTable::new(path);
+		table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		table.save();
+		assert!(file.exists());
+
+		fs::remove_file(&file).unwrap();
+		table.save();
+		assert!(!file.exists());
+
+		table.note_success(&NodeId::from(1));
+		table.save();
+		assert!(file.exists());
+	}
+
+	#[test]
+	fn table_empty_touches_nothing() {
+		let tempdir = TempDir::new("").unwrap();
+		let dir = tempdir.path().join("network");
+		{
+			let mut table = NodeTable::new(Some(dir.to_str().unwrap().to_owned()));
+			table.load_saved();
+			table.set_persistent(true);
+			table.save();
+			assert_eq!(table.stats(&IpFilter::default()).persistence_error, None);
+		}
+		assert!(!dir.exists());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn table_locked_by_another_instance() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let mut first = NodeTable::new(path.clone());
+		first.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		first.save();
+
+		// the second instance keeps its table in memory only
+		let mut second = NodeTable::new(path);
+		let error = second.stats(&IpFilter::default()).persistence_error.unwrap();
+		assert!(error.contains("is in use by process"), "{}", error);
+		assert!(!second.contains(&NodeId::from(1)));
+		second.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.45:7770").unwrap()));
+		second.save();
+		assert!(!second.reset_persistence());
+		assert_eq!(FilePersistence::new(tempdir.path()).load().unwrap().nodes.len(), 1);
+
+		// until the first one stopped, the nodes it saved are merged then
+		drop(first);
+		assert!(second.reset_persistence());
+		assert!(second.contains(&NodeId::from(1)));
+		second.save();
+		assert_eq!(FilePersistence::new(tempdir.path()).load().unwrap().nodes.len(), 2);
+
+		// an instance that started before anything was saved learns about the other one on its first save
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let (mut first, mut second) = (NodeTable::new(path.clone()), NodeTable::new(path));
+		first.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		second.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.45:7770").unwrap()));
+		first.save();
+		second.save();
+		let stats = second.stats(&IpFilter::default());
+		assert!(stats.persistence_error.unwrap().contains("is in use by process"));
+		assert_eq!(FilePersistence::new(tempdir.path()).load().unwrap().nodes.len(), 1);
+		drop(first);
+		assert!(second.reset_persistence());
+		second.save();
+		assert_eq!(FilePersistence::new(tempdir.path()).load().unwrap().nodes.len(), 2);
+	}
+
+	#[test]
+	fn table_freeze() {
+		let persistence = MemoryPersistence::new();
+		let clock = ManualClock::new();
+		let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+		let node = |i: u64| Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i)).unwrap());
+		table.add_node(node(1));
+
+		// the table is saved when frozen, changes made meanwhile are kept in memory only
+		let guard = table.freeze();
+		assert!(table.is_frozen() && table.is_saved());
+		assert_eq!(persistence.nodes().len(), 1);
+		table.add_node(node(2));
+		table.save();
+		table.save_with_timeout(Duration::from_secs(5));
+		assert_eq!(persistence.nodes().len(), 1);
+		assert!(!table.is_saved());
+
+		// overlapping freezes, saving resumes after the last one
+		let other = table.freeze();
+		table.thaw(guard);
+		assert_eq!(persistence.nodes().len(), 1);
+		table.thaw(other);
+		assert!(!table.is_frozen() && table.is_saved());
+		assert_eq!(persistence.nodes().len(), 2);
+
+		assert!(!table.save_thawed());
+
+		// the changes are saved once a dropped guard ended the freeze
+		let guard = table.freeze();
+		table.add_node(node(3));
+		assert!(!table.save_thawed());
+		drop(guard);
+		assert!(table.save_thawed());
+		assert_eq!(persistence.nodes().len(), 3);
+		assert!(!table.save_thawed());
+
+		// and once a kept guard expired
+		let guard = table.freeze_for(Duration::from_secs(60));
+		table.add_node(node(4));
+		clock.advance(Duration::from_secs(59));
+		table.save();
+		assert!(!table.save_thawed());
+		assert_eq!(persistence.nodes().len(), 3);
+		clock.advance(Duration::from_secs(1));
+		assert!(table.save_thawed());
+		assert!(!table.is_frozen() && table.is_saved());
+		assert_eq!(persistence.nodes().len(), 4);
+		drop(guard);
+		assert!(!table.save_thawed());
+		let _guard = table.freeze();
+		clock.advance(MAX_FREEZE);
+		assert!(!table.is_frozen());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn table_final_save_deadline() {
+		use std::ffi::CString;
+		use std::io::Read;
+
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let config = NodeTableConfig { final_save_timeout: Duration::from_millis(100), ..Default::default() };
+		let mut table = NodeTable::with_config(path, true, config);
+		table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+
+		// opening a fifo for writing blocks until there is a reader
+		let file = tempdir.path().join(NODES_FILE);
+		let fifo = CString::new(file.to_str().unwrap()).unwrap();
+		assert_eq!(unsafe { ::libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+		let start = Instant::now();
+		drop(table);
+		assert!(start.elapsed() < Duration::from_secs(5));
+
+		// the abandoned write completes once someone reads
+		let mut contents = String::new();
+		fs::File::open(&file).unwrap().read_to_string(&mut contents).unwrap();
+		assert!(contents.contains("enode://"));
+	}
+
+	#[test]
+	fn table_persistent_save_failure() {
+		// permissions do not stop root, a file in place of the data directory fails for everyone
+		let tempdir = TempDir::new("").unwrap();
+		let dir = tempdir.path().join("data");
+		fs::File::create(&dir).unwrap();
+		let mut table = NodeTable::with_config(Some(dir.join("network").to_str().unwrap().to_owned()), true, NodeTableConfig::default());
+		for i in 1..6 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+			table.save();
+		}
+		// given up after the third failure, later saves are not attempted
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.saves, stats.save_failures), (0, 3));
+		let error = stats.persistence_error.unwrap();
+		assert!(error.contains(dir.to_str().unwrap()), "{}", error);
+
+		// writable again
+		fs::remove_file(&dir).unwrap();
+		assert!(table.reset_persistence());
+		assert!(!table.reset_persistence());
+		table.save();
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.saves, stats.save_failures, stats.persistence_error), (1, 3, None));
+		assert!(dir.join("network").join(NODES_FILE).exists());
+	}
+
+	#[test]
+	fn table_in_memory() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		{
+			let mut table = NodeTable::with_config(path.clone(), false, NodeTableConfig::default());
+			table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+			table.mark_as_useless(&NodeId::from(1), "test");
+			assert!(table.nodes(&IpFilter::default()).is_empty());
+			table.clear_useless();
+			assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(1)]);
+			table.save();
+		}
+		assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+
+		{
+			let mut table = NodeTable::new(path.clone());
+			table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		}
+		let table = NodeTable::with_config(path, false, NodeTableConfig::default());
+		assert!(table.nodes.is_empty());
+	}
+
+	#[test]
+	fn table_enable_persistence() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		{
+			let mut table = NodeTable::new(path.clone());
+			table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		}
+
+		let mut table = NodeTable::with_config(path.clone(), false, NodeTableConfig::default());
+		table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		table.set_persistent(true);
+		assert!(!table.contains(&NodeId::from(1)));
+		table.save();
+		drop(table);
+		assert_eq!(NodeTable::new(path.clone()).nodes(&IpFilter::default()), vec![NodeId::from(2)]);
+
+		let mut table = NodeTable::with_config(path, false, NodeTableConfig::default());
+		table.add_node(Node::new(NodeId::from(3), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		table.load_saved();
+		assert!(table.contains(&NodeId::from(2)) && table.contains(&NodeId::from(3)));
+	}
+
+	#[test]
+	fn table_config_max_nodes() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let config = NodeTableConfig { max_nodes: 2, ..Default::default() };
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, config.clone());
+			for i in 1..4 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+				table.update_node(&NodeId::from(i), |n| n.attempts = 1).unwrap();
+			}
+			table.note_failure(&NodeId::from(2));
+		}
+		let table = NodeTable::with_config(path, true, config);
+		let mut nodes = table.nodes(&IpFilter::default());
+		nodes.sort();
+		assert_eq!(nodes, vec![NodeId::from(1), NodeId::from(3)]);
+	}
+
+	#[test]
+	fn table_config_max_file_bytes() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let config = NodeTableConfig { max_file_bytes: Some(1024), ..Default::default() };
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, config.clone());
+			for i in 1..21 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+				table.update_node(&NodeId::from(i), |n| n.attempts = 1).unwrap();
+				if i != 20 {
+					table.note_failure(&NodeId::from(i));
+				}
+			}
+		}
+		let file = tempdir.path().join(NODES_FILE);
+		assert!(fs::metadata(&file).unwrap().len() <= 1024);
+		let saved = FilePersistence::new(tempdir.path()).load().unwrap();
+		assert!(saved.nodes.len() > 0);
+		assert_eq!(saved.omitted, Some(20 - saved.nodes.len()));
+
+		// the best node is kept
+		let table = NodeTable::with_config(path, true, config);
+		assert!(table.contains(&NodeId::from(20)));
+		assert_eq!(table.nodes.len(), saved.nodes.len());
+	}
+
+	#[test]
+	fn table_network_mismatch() {
+		use std::io::Read;
+
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let network = |id: &str| NodeTableConfig { network_id: Some(id.into()), ..Default::default() };
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, network("1-a"));
+			table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		}
+		assert_eq!(FilePersistence::new(tempdir.path()).load().unwrap().network_id, Some("1-a".into()));
+
+		// loaded on the same network, and by a table not told its network
+		assert!(NodeTable::with_config(path.clone(), true, network("1-a")).contains(&NodeId::from(1)));
+		assert!(NodeTable::with_config(path.clone(), true, NodeTableConfig::default()).contains(&NodeId::from(1)));
+
+		// another network starts empty and the file is kept aside
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, network("3-b"));
+			assert_eq!(table.nodes.len(), 0);
+			table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.45:7770").unwrap()));
+		}
+		let mut contents = Vec::new();
+		fs::File::open(tempdir.path().join(::persistence::NODES_BACKUP_FILE)).unwrap().read_to_end(&mut contents).unwrap();
+		let backup = ::persistence::decode_table(&contents).unwrap();
+		assert_eq!(backup.network_id, Some("1-a".into()));
+		assert_eq!(backup.nodes.len(), 1);
+		let saved = FilePersistence::new(tempdir.path()).load().unwrap();
+		assert_eq!(saved.network_id, Some("3-b".into()));
+		assert_eq!(saved.nodes.len(), 1);
+	}
+
+	#[test]
+	fn table_per_network_files() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let network = |id: &str| NodeTableConfig { network_id: Some(id.into()), per_network_files: true, ..Default::default() };
+		for (i, id) in ["1-a", "3-b"].iter().enumerate() {
+			let mut table = NodeTable::with_config(path.clone(), true, network(id));
+			table.add_node(Node::new(NodeId::from(i as u64 + 1), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		}
+		assert!(tempdir.path().join("nodes.1-a.json").exists());
+		assert!(tempdir.path().join("nodes.3-b.json").exists());
+		assert!(!tempdir.path().join(NODES_FILE).exists());
+
+		// switching back keeps the table of each network
+		let table = NodeTable::with_config(path.clone(), true, network("1-a"));
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(1)]);
+		let table = NodeTable::with_config(path, true, network("3-b"));
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(2)]);
+	}
+
+	#[test]
+	fn table_family_quota() {
+		let config = NodeTableConfig { max_nodes: 20, ..Default::default() };
+		let mut table = NodeTable::with_persistence(Box::new(MemoryPersistence::new()), config.clone());
+		for i in 1..21 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+			table.update_node(&NodeId::from(i), |n| n.attempts = 1).unwrap();
+			table.note_success(&NodeId::from(i));
+		}
+		// the new IPv6 nodes rank after all the contacted IPv4 nodes
+		for i in 21..26 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("[2001:db8:{:x}::1]:7770", i)).unwrap()));
+		}
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.ipv4, stats.ipv6), (20, 5));
+
+		// the save keeps the same nodes as the eviction
+		table.save();
+		let saved: HashSet<String> = table.persistence.as_ref().unwrap().load().unwrap().nodes.into_iter().map(|n| n.url).collect();
+		assert_eq!(table.evict_excess(&HashSet::new()), 5);
+		let kept: HashSet<String> = table.entries_with_meta(usize::max_value(), true).into_iter().map(|e| e.url).collect();
+		assert_eq!(saved, kept);
+
+		// 10% of the limit is reserved for each family
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.ipv4, stats.ipv6), (18, 2));
+		assert_eq!(table.evict_excess(&HashSet::new()), 0);
+
+		// without a quota the best nodes are kept
+		let config = NodeTableConfig { family_quota: 0, ..config };
+		let mut table = NodeTable::with_persistence(Box::new(MemoryPersistence::new()), config);
+		for i in 1..21 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+			table.update_node(&NodeId::from(i), |n| n.attempts = 1).unwrap();
+			table.note_success(&NodeId::from(i));
+		}
+		table.add_node(Node::new(NodeId::from(21), NodeEndpoint::from_str("[2001:db8::1]:7770").unwrap()));
+		let keep = vec![NodeId::from(21)].into_iter().collect();
+		assert_eq!(table.evict_excess(&keep), 0);
+		assert_eq!(table.evict_excess(&HashSet::new()), 1);
+		assert_eq!(table.stats(&IpFilter::default()).ipv6, 0);
+	}
+
+	#[test]
+	fn table_config_subnet_limit() {
+		let config = NodeTableConfig { max_nodes_per_subnet: Some(2), ..Default::default() };
+		let mut table = TableState::new(config);
+		for i in 1..4 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:7770", i)).unwrap()));
+		}
+		assert!(!table.contains(&NodeId::from(3)));
+		// known nodes can still be updated
+		table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.20:7770").unwrap()));
+		assert!(table.contains(&NodeId::from(2)));
+
+		let mut added = HashMap::new();
+		for i in 4..6 {
+			added.insert(NodeId::from(i), NodeEntry::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+		}
+		added.insert(NodeId::from(6), NodeEntry::new(NodeId::from(6), NodeEndpoint::from_str("22.99.55.6:7770").unwrap()));
+		table.update(TableUpdates { added: added, removed: HashSet::new(), verified: HashSet::new() }, &HashSet::new());
+		assert!(table.contains(&NodeId::from(4)) && table.contains(&NodeId::from(5)));
+		assert!(!table.contains(&NodeId::from(6)));
+
+		let meta = SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![] };
+		assert!(!table.note_inbound(&NodeId::from(7), NodeEndpoint::from_str("22.99.55.7:7770").unwrap(), meta));
+		assert!(!table.contains(&NodeId::from(7)));
+	}
+
+	#[test]
+	fn maintenance_follows_peer_pressure() {
+		let clock = ManualClock::new();
+		let nodes = vec![FixtureNode::new("1", "22.99.55.1:7770"), FixtureNode::new("2", "22.99.55.2:7770")];
+		let mut table = NodeTable::from_fixture_with(&nodes, NodeTableConfig::default(), &clock);
+		table.persistence = Some(Arc::new(MemoryPersistence::new()));
+		table.set_persistent(true);
+		let keep = HashSet::new();
+		let run = |table: &mut NodeTable| table.maintenance(clock.now_instant(), &keep, 4);
+		let connect = |table: &mut NodeTable, id: u64| table.session_opened(&NodeId::from(id), &format!("22.99.56.{}:7770", id).parse().unwrap());
+
+		// starved: frequent runs, the first one starts the refresh interval
+		assert_eq!(run(&mut table), MaintenanceReport { next_delay: Duration::from_secs(2), ..Default::default() });
+
+		// a backoff ending between two runs is counted once
+		table.note_failure(&NodeId::from(1));
+		table.mark_as_useless(&NodeId::from(2), "test");
+		clock.advance(Duration::from_secs(2));
+		assert_eq!(run(&mut table).backoff_expired, 0);
+		clock.advance(Duration::from_secs(4));
+		assert_eq!(run(&mut table).backoff_expired, 1);
+		clock.advance(Duration::from_secs(2));
+		assert_eq!(run(&mut table).backoff_expired, 0);
+
+		// starved, the table is saved every ten seconds instead of five minutes
+		clock.advance(Duration::from_secs(2));
+		let report = run(&mut table);
+		assert!(report.saved && !report.refreshed);
+		assert_eq!(report.next_delay, Duration::from_secs(2));
+		clock.advance(Duration::from_secs(2));
+		assert!(!run(&mut table).saved);
+
+		// half of the target connected: a quarter of the way to the healthy delay
+		connect(&mut table, 3);
+		connect(&mut table, 4);
+		assert_eq!(run(&mut table).next_delay, Duration::from_millis(2_000 + 58_000 / 4));
+
+		// healthy: lazy runs that do not pass the refresh
+		connect(&mut table, 5);
+		connect(&mut table, 6);
+		assert_eq!(run(&mut table).next_delay, Duration::from_secs(60));
+		clock.advance(Duration::from_secs(280 - 12));
+		let report = run(&mut table);
+		assert!(!report.refreshed && !report.saved);
+		assert_eq!(report.next_delay, Duration::from_secs(20));
+		clock.advance(Duration::from_secs(20));
+		assert_eq!(run(&mut table), MaintenanceReport {
+			refreshed: true,
+			cleared_useless: 1,
+			saved: true,
+			next_delay: Duration::from_secs(60),
+			..Default::default()
+		});
+		assert!(!table.is_useless(table.get(&NodeId::from(2)).unwrap()));
+
+		// the bounds are configurable
+		let config = NodeTableConfig { maintenance_min_interval: Duration::from_secs(1), maintenance_max_interval: Duration::from_secs(5), ..Default::default() };
+		assert_eq!(maintenance_delay(0, 4, &config), Duration::from_secs(1));
+		assert_eq!(maintenance_delay(3, 4, &config), Duration::from_millis(1_000 + 4_000 * 9 / 16));
+		assert_eq!(maintenance_delay(9, 4, &config), Duration::from_secs(5));
+		assert_eq!(maintenance_delay(0, 0, &config), Duration::from_secs(5));
+		assert_eq!(autosave_delay(Duration::from_secs(1), &config), Duration::from_secs(60));
+		assert_eq!(autosave_delay(Duration::from_secs(5), &config), Duration::from_secs(300));
+		let config = NodeTableConfig { autosave_interval: Duration::from_secs(1), ..config };
+		assert_eq!(autosave_delay(Duration::from_secs(2), &config), Duration::from_secs(2));
+	}
+
+	#[test]
+	fn table_config_min_port() {
+		let node = |id: u64, address: &str| Node::new(NodeId::from(id), NodeEndpoint::from_str(address).unwrap());
+		let entry = |id: u64, address: &str| (NodeId::from(id), NodeEntry::new(NodeId::from(id), NodeEndpoint::from_str(address).unwrap()));
+		let meta = || SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![] };
+
+		let mut table = TableState::new(NodeTableConfig::default());
+		table.add_node(node(1, "22.99.55.1:22"));
+		table.add_node(node(2, "22.99.55.2:30303?discport=25"));
+		table.add_node(node(3, "22.99.55.3:1024"));
+		assert!(!table.note_inbound(&NodeId::from(4), NodeEndpoint::from_str("22.99.55.4:443").unwrap(), meta()));
+		let added = vec![entry(5, "22.99.55.5:80"), entry(6, "22.99.55.6:110"), entry(7, "22.99.55.7:30303")].into_iter().collect();
+		let reserved = vec![NodeId::from(6)].into_iter().collect();
+		let outcome = table.update(TableUpdates { added: added, removed: HashSet::new(), verified: HashSet::new() }, &reserved);
+		assert_eq!(outcome.rejected, vec![(NodeId::from(5), RejectReason::LowPort)]);
+		assert_eq!((1..8).filter(|&i| table.contains(&NodeId::from(i))).collect::<Vec<_>>(), vec![3, 6, 7]);
+		assert_eq!(table.low_port_rejected, 4);
+
+		// reserved and trusted nodes are exempt
+		table.ingest(NodeOrigin::Reserved, node(1, "22.99.55.1:22"));
+		table.ingest(NodeOrigin::Trusted, node(8, "22.99.55.8:443"));
+		assert!(table.contains(&NodeId::from(1)) && table.contains(&NodeId::from(8)));
+		assert_eq!(table.low_port_rejected, 4);
+
+		// known nodes are not moved to a low port either, unless they are reserved or trusted
+		let moves = vec![
+			entry(3, "22.99.55.3:25"), entry(6, "22.99.55.6:111"),
+			entry(7, "22.99.56.7:30303?discport=53"), entry(8, "22.99.55.8:444"),
+		];
+		let verified = moves.iter().map(|&(id, _)| id).collect();
+		let updates = TableUpdates { added: moves.into_iter().collect(), removed: HashSet::new(), verified: verified };
+		assert_eq!(table.update(updates, &reserved).updated_endpoints, 2);
+		let port = |table: &TableState, id: u64| table.get(&NodeId::from(id)).unwrap().endpoint.address.port();
+		assert_eq!((port(&table, 3), port(&table, 6), port(&table, 8)), (1024, 111, 444));
+		assert_eq!(table.get(&NodeId::from(7)).unwrap().endpoint.udp_port, 30303);
+		assert_eq!(table.low_port_rejected, 6);
+
+		// lowered for a private network
+		let config = NodeTableConfig { min_port: 1, ..Default::default() };
+		let mut table = TableState::new(config);
+		table.add_node(node(1, "22.99.55.1:22"));
+		assert!(table.note_inbound(&NodeId::from(4), NodeEndpoint::from_str("22.99.55.4:443").unwrap(), meta()));
+		assert!(table.contains(&NodeId::from(1)) && table.low_port_rejected == 0);
+	}
+
+	#[test]
+	fn table_config_backoff() {
+		let config = NodeTableConfig { initial_backoff: Duration::from_secs(0), ..Default::default() };
+		let mut table = TableState::new(config);
+		let id = NodeId::from(1);
+		table.add_node(Node::new(id.clone(), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		table.note_failure(&id);
+		assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 10, None), vec![id]);
+	}
+
+	#[test]
+	fn table_manual_clock() {
+		let config = NodeTableConfig { max_age: Some(Duration::from_secs(60 * 60)), ..Default::default() };
+		let mut table = TableState::new(config);
+		let clock = ManualClock::new();
+		table.set_clock(Box::new(clock.clone()));
+		let policy = DialPolicy { required_capabilities: Vec::new(), allow_unknown: true, ..Default::default() };
+		let id = NodeId::from(1);
+		table.add_node(Node::new(id.clone(), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+
+		// contact times come from the clock
+		table.note_success(&id);
+		assert_eq!(table.get(&id).unwrap().last_contact, Some(clock.now_system()));
+
+		// backoff and bans expire as the clock advances
+		table.note_failure(&id);
+		table.ban(&id, Duration::from_secs(60), "test");
+		assert!(table.select_for(&policy, &IpFilter::default(), 10, None).is_empty());
+		assert!(table.is_banned(&id));
+		clock.advance(Duration::from_secs(30));
+		assert!(table.is_banned(&id));
+		assert_eq!(table.stats(&IpFilter::default()).backed_off, 0);
+		clock.advance(Duration::from_secs(31));
+		assert!(!table.is_banned(&id));
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None), vec![id.clone()]);
+
+		// and so does the last contact
+		assert_eq!(table.drop_expired(&HashSet::new()), 0);
+		clock.advance(Duration::from_secs(60 * 60));
+		assert_eq!(table.drop_expired(&HashSet::new()), 1);
+	}
+
+	#[test]
+	fn table_drop_expired() {
+		let config = NodeTableConfig { max_age: Some(Duration::from_secs(60 * 60)), ..Default::default() };
+		let old = Some(Duration::from_secs(2 * 60 * 60));
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode { last_contact: old, ..FixtureNode::new("1", "22.99.55.44:7770") },
+			FixtureNode { last_contact: old, ..FixtureNode::new("2", "22.99.55.44:7770") },
+			FixtureNode { last_contact: Some(Duration::from_secs(0)), ..FixtureNode::new("3", "22.99.55.44:7770") },
+			FixtureNode::new("4", "22.99.55.44:7770"),
+		], config, &ManualClock::new());
+
+		let keep = vec![NodeId::from(2)].into_iter().collect();
+		assert_eq!(table.drop_expired(&keep), 1);
+		assert!(!table.contains(&NodeId::from(1)));
+		assert_eq!(table.nodes.len(), 3);
+		assert_eq!(TableState::new(NodeTableConfig::default()).drop_expired(&HashSet::new()), 0);
+	}
+
+	#[test]
+	fn node_debug_format() {
+		let mut node = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
+		node.attempts = 3;
+		node.failures = 1;
+		assert_eq!(format!("{:?}", node), "Node { id: a979fb…163c, endpoint: 22.99.55.44:7770, peer_type: Optional, source: Unknown, attempts: 3, failures: 1, successes: 0 }");
+		node.endpoint.udp_port = 30301;
+		node.is_boot = true;
+		node.stalled = true;
+		assert_eq!(format!("{:?}", node), "Node { id: a979fb…163c, endpoint: 22.99.55.44:7770 (udp 30301), peer_type: Optional, source: Unknown, attempts: 3, failures: 1, successes: 0, boot: true, stalled: true }");
+	}
+
+	#[test]
+	fn table_debug_format() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode::new("1", "22.99.55.1:30303"),
+			FixtureNode { failures: 1, attempts: 1, ..FixtureNode::new("2", "22.99.55.2:30303") },
+			FixtureNode::new("3", "22.99.55.3:30303"),
+			FixtureNode::new("4", "22.99.55.4:30303"),
+			FixtureNode::new("5", "22.99.55.5:30303"),
+		]);
+		table.ban(&NodeId::from(5), Duration::from_secs(60), "test");
+		let debug = format!("{:?}", table);
+		assert!(debug.starts_with("TableState { nodes: 5, useless: 0, banned: 1, backed_off: 0, first: [Node { id: 000000…"), "{}", debug);
+		assert_eq!(debug.matches("Node {").count(), DEBUG_ENTRIES);
+		assert!(!debug.contains("failures: 1"), "{}", debug);
+	}
+
+	#[test]
+	fn table_records_evictions() {
+		let config = NodeTableConfig { max_nodes: 2, max_age: Some(Duration::from_secs(60 * 60)), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode::new("2", "22.99.55.2:7770"),
+			FixtureNode::new("3", "22.99.55.3:7770"),
+			FixtureNode::new("4", "22.99.55.4:7770"),
+			FixtureNode { last_contact: Some(Duration::from_secs(2 * 60 * 60)), ..FixtureNode::new("5", "22.99.55.5:7770") },
+			FixtureNode::new("6", "22.99.55.6:7770"),
+		], config, &ManualClock::new());
+
+		assert!(table.remove_node(&NodeId::from(3)));
+		let updates = TableUpdates { added: HashMap::new(), removed: vec![NodeId::from(4)].into_iter().collect(), verified: HashSet::new() };
+		table.update(updates, &HashSet::new());
+		assert_eq!(table.drop_expired(&HashSet::new()), 1);
+		// another id verified at the endpoint of a contacted node
+		table.note_success(&NodeId::from(1));
+		let id = NodeId::from(7);
+		let entry = NodeEntry::new(id.clone(), NodeEndpoint::from_str("22.99.55.1:7770").unwrap());
+		let updates = TableUpdates { added: vec![(id.clone(), entry)].into_iter().collect(), removed: HashSet::new(), verified: vec![id].into_iter().collect() };
+		table.update(updates, &HashSet::new());
+		assert_eq!(table.evict_excess(&HashSet::new()), 1);
+		// unknown nodes are not recorded
+		assert!(!table.remove_node(&NodeId::from(3)));
+
+		let evictions = table.recent_evictions();
+		let reasons: Vec<_> = evictions.iter().map(|e| e.reason).collect();
+		assert_eq!(reasons, vec![EvictionReason::Removed, EvictionReason::Discovery, EvictionReason::Expired, EvictionReason::Rotated, EvictionReason::Capacity]);
+		let ids: Vec<_> = evictions.iter().take(4).map(|e| e.id).collect();
+		assert_eq!(ids, fixture_ids(&["3", "4", "5", "1"]));
+		assert_eq!(evictions[0].endpoint, NodeEndpoint::from_str("22.99.55.3:7770").unwrap());
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!(stats.evictions.values().cloned().collect::<Vec<_>>(), vec![1; 5]);
+		assert_eq!(stats.evictions.get(&EvictionReason::Rotated), Some(&1));
+	}
+
+	/// Discovery update adding nodes `(id, address, verified)`.
+	fn discovered(nodes: &[(u64, &str, bool)]) -> TableUpdates {
+		TableUpdates {
+			added: nodes.iter().map(|&(id, address, _)| (NodeId::from(id), NodeEntry::new(NodeId::from(id), NodeEndpoint::from_str(address).unwrap()))).collect(),
+			removed: HashSet::new(),
+			verified: nodes.iter().filter(|&&(_, _, verified)| verified).map(|&(id, _, _)| NodeId::from(id)).collect(),
+		}
+	}
+
+	#[test]
+	fn table_update_ranks_additions() {
+		let config = NodeTableConfig { max_nodes: 5, ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode::new("2", "22.99.55.2:7770"),
+		], config, &ManualClock::new());
+
+		// three free slots: the verified node despite its crowded subnet, then one node per new subnet
+		let stats = table.update(discovered(&[
+			(10, "22.99.55.10:7770", true),
+			(11, "22.1.1.1:7770", false),
+			(12, "22.1.1.2:7770", false),
+			(13, "22.2.2.1:7770", false),
+			(14, "22.99.55.14:7770", false),
+		]), &HashSet::new());
+		assert_eq!((stats.added, stats.rejected.len(), stats.evicted), (3, 2, 0));
+		let mut ids: Vec<u64> = table.nodes.keys().map(|id| id.low_u64()).collect();
+		ids.sort();
+		assert_eq!(ids, vec![1, 2, 10, 11, 13]);
+
+		// known nodes are still updated in a full table
+		let stats = table.update(discovered(&[(11, "22.1.1.1:7771", true)]), &HashSet::new());
+		assert_eq!(stats, UpdateOutcome { updated_endpoints: 1, ..Default::default() });
+		assert_eq!(table.get(&NodeId::from(11)).unwrap().endpoint.address, "22.1.1.1:7771".parse().unwrap());
+	}
+
+	#[test]
+	fn table_update_prefers_closer_ids() {
+		let config = NodeTableConfig { max_nodes: 1, ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[], config, &ManualClock::new());
+		let local = NodeId::from(100);
+		table.set_local_id(local.clone());
+		let distance = |id: u64| keccak(&local) ^ keccak(&NodeId::from(id));
+		let closest = (1..5).min_by_key(|id| distance(*id)).unwrap();
+
+		let stats = table.update(discovered(&[
+			(1, "22.1.1.1:7770", false),
+			(2, "22.2.2.1:7770", false),
+			(3, "22.3.3.1:7770", false),
+			(4, "22.4.4.1:7770", false),
+		]), &HashSet::new());
+		assert_eq!((stats.added, stats.rejected.len(), stats.evicted), (1, 3, 0));
+		assert_eq!(table.nodes.keys().cloned().collect::<Vec<_>>(), vec![NodeId::from(closest)]);
+	}
+
+	#[test]
+	fn table_update_displaces_worse_entries() {
+		let config = NodeTableConfig { max_nodes: 3, ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode { attempts: 4, failures: 4, ..FixtureNode::new("1", "22.99.55.1:7770") },
+			FixtureNode { attempts: 4, failures: 3, ..FixtureNode::new("2", "22.99.55.2:7770") },
+			FixtureNode { attempts: 2, successes: 2, ..FixtureNode::new("3", "22.99.55.3:7770") },
+		], config, &ManualClock::new());
+		let reserved: HashSet<NodeId> = vec![NodeId::from(2)].into_iter().collect();
+
+		// failing nodes make room unless reserved, the contacted node does not
+		let stats = table.update(discovered(&[
+			(11, "22.1.1.1:7770", true),
+			(12, "22.2.2.1:7770", false),
+			(13, "22.3.3.1:7770", false),
+		]), &reserved);
+		assert_eq!((stats.added, stats.rejected.len(), stats.evicted), (1, 2, 1));
+		let mut ids: Vec<u64> = table.nodes.keys().map(|id| id.low_u64()).collect();
+		ids.sort();
+		assert_eq!(ids, vec![2, 3, 11]);
+		assert_eq!(table.recent_evictions()[0].reason, EvictionReason::Capacity);
+
+		// a verified new id at a known endpoint replaces the old entry without another eviction
+		table.note_success(&NodeId::from(3));
+		let stats = table.update(discovered(&[(14, "22.99.55.3:7770", true)]), &reserved);
+		assert_eq!(stats, UpdateOutcome { added: 1, ..Default::default() });
+		assert!(table.contains(&NodeId::from(14)) && !table.contains(&NodeId::from(3)));
+	}
+
+	#[test]
+	fn table_update_outcome() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode { required: true, ..FixtureNode::new("2", "22.99.55.2:7770") },
+			FixtureNode { boot: true, ..FixtureNode::new("3", "22.99.55.3:7770") },
+			FixtureNode::new("4", "22.99.55.4:7770"),
+			FixtureNode::new("5", "22.99.55.5:7770"),
+			FixtureNode { banned: true, ..FixtureNode::new("6", "22.99.55.6:7770") },
+			FixtureNode::new("7", "22.99.55.7:7770"),
+		]);
+		table.remove_node(&NodeId::from(7));
+		table.set_local_id(NodeId::from(8));
+		let reserved: HashSet<NodeId> = vec![NodeId::from(1)].into_iter().collect();
+
+		// node 4 moves to a verified endpoint, 6 is banned, 7 was just removed, 8 is us and 9 is new
+		let mut updates = discovered(&[
+			(4, "22.99.56.4:7770", true),
+			(6, "22.1.1.6:7770", false),
+			(7, "22.1.1.7:7770", false),
+			(8, "22.1.1.8:7770", false),
+			(9, "22.1.1.9:7770", false),
+		]);
+		// the reserved, required and boot nodes stay, 10 was never known
+		updates.removed = [1, 2, 3, 5, 10].iter().map(|&id| NodeId::from(id)).collect();
+		let outcome = table.update(updates, &reserved);
+		assert_eq!(outcome, UpdateOutcome {
+			added: 1,
+			updated_endpoints: 1,
+			removed: 1,
+			evicted: 0,
+			protected: vec![NodeId::from(1), NodeId::from(2), NodeId::from(3)],
+			rejected: vec![
+				(NodeId::from(6), RejectReason::Banned),
+				(NodeId::from(7), RejectReason::Tombstoned),
+				(NodeId::from(8), RejectReason::Local),
+			],
+			truncated_added: 0,
+			truncated_removed: 0,
+		});
+		::mock_discovery::assert_known(&table, &fixture_ids(&["1", "2", "3", "4", "9"]));
+		::mock_discovery::assert_unknown(&table, &fixture_ids(&["5", "7", "8"]));
+
+		// over the subnet limit and refused by the admission hook
+		let config = NodeTableConfig { max_nodes_per_subnet: Some(1), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[FixtureNode::new("1", "22.99.55.1:7770")], config, &ManualClock::new());
+		table.set_admission_hook(Box::new(|node: &Node, _| if node.id.low_u64() == 3 {
+			Admission::Reject { reason: "test".into() }
+		} else {
+			Admission::Accept
+		}));
+		let outcome = table.update(discovered(&[(2, "22.99.55.2:7770", false), (3, "22.1.1.3:7770", false)]), &HashSet::new());
+		assert_eq!(outcome.rejected, vec![(NodeId::from(2), RejectReason::SubnetFull), (NodeId::from(3), RejectReason::Admission)]);
+	}
+
+
+	#[test]
+	fn oversized_update_truncated() {
+		let config = NodeTableConfig { max_update_added: 10, max_update_removed: 5, ..Default::default() };
+		let fixture: Vec<FixtureNode> = (1..21).map(|i| FixtureNode::new(&format!("{:x}", i), &format!("22.99.{}.1:7770", i))).collect();
+		let mut table = TableState::from_fixture_with(&fixture, config, &ManualClock::new());
+
+		// the verified nodes are in the middle of the id range
+		let addresses: Vec<String> = (100..130).map(|i| format!("22.98.{}.1:7770", i)).collect();
+		let nodes: Vec<(u64, &str, bool)> = (100..130).map(|i| (i, &addresses[i as usize - 100][..], i >= 110 && i < 115)).collect();
+		let mut updates = discovered(&nodes);
+		updates.removed = (1..21).map(NodeId::from).collect();
+		let outcome = table.update(updates, &HashSet::new());
+
+		// the verified ones are kept first, then the lowest ids
+		assert_eq!((outcome.added, outcome.truncated_added), (10, 20));
+		let kept: Vec<NodeId> = (100..105).chain(110..115).map(NodeId::from).collect();
+		::mock_discovery::assert_known(&table, &kept);
+		assert_eq!((100..130).map(NodeId::from).filter(|id| table.contains(id)).count(), 10);
+		assert_eq!((outcome.removed, outcome.truncated_removed), (5, 15));
+		::mock_discovery::assert_unknown(&table, &(1..6).map(NodeId::from).collect::<Vec<_>>());
+		::mock_discovery::assert_known(&table, &(6..21).map(NodeId::from).collect::<Vec<_>>());
+		table.check_invariants().unwrap();
+	}
+
+	#[test]
+	fn invalid_update_entries_rejected() {
+		let mut table = TableState::from_fixture(&[FixtureNode::new("1", "22.99.55.1:7770")]);
+		let mut updates = discovered(&[(2, "22.99.55.2:7770", true), (3, "22.99.55.3:7770", false)]);
+		let endpoint = |address: &str| NodeEndpoint { address: address.parse().unwrap(), udp_port: 7770 };
+		// an unspecified address, no TCP port and an entry keyed by another id are rejected
+		updates.added.insert(NodeId::from(4), NodeEntry::new(NodeId::from(4), endpoint("0.0.0.0:7770")));
+		updates.added.insert(NodeId::from(5), NodeEntry::new(NodeId::from(5), endpoint("22.99.55.5:0")));
+		updates.added.insert(NodeId::from(6), NodeEntry::new(NodeId::from(7), endpoint("22.99.55.6:7770")));
+		// an IPv4-mapped address is added in IPv4 form
+		updates.added.insert(NodeId::from(8), NodeEntry::new(NodeId::from(8), endpoint("[::ffff:22.99.55.8]:7770")));
+		let outcome = table.update(updates, &HashSet::new());
+
+		assert_eq!(outcome.added, 3);
+		assert_eq!(outcome.rejected, vec![
+			(NodeId::from(4), RejectReason::Invalid),
+			(NodeId::from(5), RejectReason::Invalid),
+			(NodeId::from(6), RejectReason::Invalid),
+		]);
+		::mock_discovery::assert_unknown(&table, &[NodeId::from(4), NodeId::from(5), NodeId::from(6), NodeId::from(7)]);
+		::mock_discovery::assert_endpoint(&table, &NodeId::from(8), "22.99.55.8:7770", None);
+		table.check_invariants().unwrap();
+	}
+	#[test]
+	fn table_admission_hook() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		table.set_admission_hook(Box::new(|node: &Node, source: NodeSource| {
+			if node.id.low_u64() % 2 == 1 {
+				Admission::Reject { reason: format!("odd {:?}", source) }
+			} else if source == NodeSource::Inbound {
+				Admission::AcceptUntrusted
+			} else {
+				Admission::Accept
+			}
+		}));
+		let node = |id: u64| Node::new(NodeId::from(id), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", id)).unwrap());
+		let meta = || SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![] };
+
+		for id in 1..3 {
+			table.add_node(node(id));
+		}
+		table.ingest(NodeOrigin::Boot, node(3));
+		table.ingest(NodeOrigin::Reserved, node(4));
+		table.update(discovered(&[(5, "22.99.5.1:7770", true), (6, "22.99.6.1:7770", false)]), &HashSet::new());
+		assert!(!table.note_inbound(&NodeId::from(7), node(7).endpoint, meta()));
+		assert!(table.note_inbound(&NodeId::from(8), node(8).endpoint, meta()));
+
+		let mut ids: Vec<u64> = table.nodes.keys().map(|id| id.low_u64()).collect();
+		ids.sort();
+		assert_eq!(ids, vec![2, 4, 6, 8]);
+		let rejected = table.stats(&IpFilter::default()).admission_rejected;
+		let expected: BTreeMap<String, usize> = vec![
+			("odd Unknown".to_owned(), 1), ("odd Boot".to_owned(), 1), ("odd Discovery".to_owned(), 1), ("odd Inbound".to_owned(), 1),
+		].into_iter().collect();
+		assert_eq!(rejected, expected);
+
+		// untrusted nodes are dialled last, known nodes are not checked again
+		assert!(table.get(&NodeId::from(8)).unwrap().untrusted);
+		assert_eq!(table.nodes(&IpFilter::default()).last(), Some(&NodeId::from(8)));
+		table.set_admission_hook(Box::new(|_: &Node, _| Admission::Reject { reason: "all".into() }));
+		table.add_node(node(2));
+		assert!(!table.note_inbound(&NodeId::from(8), node(8).endpoint, meta()));
+		assert!(table.contains(&NodeId::from(2)) && table.contains(&NodeId::from(8)));
+		assert!(!table.stats(&IpFilter::default()).admission_rejected.contains_key("all"));
+	}
+
+	#[test]
+	fn table_admission_reasons_bounded() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		table.set_admission_hook(Box::new(|node: &Node, _| Admission::Reject { reason: format!("reason {}", node.id.low_u64()) }));
+		for id in 1..(MAX_ADMISSION_REASONS as u64 + 5) {
+			table.add_node(Node::new(NodeId::from(id), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		}
+		let rejected = table.stats(&IpFilter::default()).admission_rejected;
+		assert_eq!(rejected.len(), MAX_ADMISSION_REASONS + 1);
+		assert_eq!(rejected.get("other"), Some(&4));
+		assert!(table.nodes.is_empty());
+	}
+
+	#[test]
+	fn table_recent_evictions_bounded() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		for i in 1..(MAX_RECENT_EVICTIONS as u64 + 11) {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+			table.remove_node(&NodeId::from(i));
+		}
+		let evictions = table.recent_evictions();
+		assert_eq!(evictions.len(), MAX_RECENT_EVICTIONS);
+		assert_eq!(evictions[0].id, NodeId::from(11));
+		assert_eq!(evictions[MAX_RECENT_EVICTIONS - 1].id, NodeId::from(MAX_RECENT_EVICTIONS as u64 + 10));
+		assert_eq!(table.stats(&IpFilter::default()).evictions.get(&EvictionReason::Removed), Some(&(MAX_RECENT_EVICTIONS + 10)));
+	}
+
+	#[test]
+	fn boot_nodes_left_out_once_bootstrapped() {
+		let config = NodeTableConfig { bootstrapped_nodes: 3, bootstrapped_max_failure: 25, ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode { boot: true, attempts: 1, successes: 1, ..FixtureNode::new("1", "22.99.55.1:7770") },
+			FixtureNode { attempts: 4, failures: 1, successes: 3, ..FixtureNode::new("2", "22.99.55.2:7770") },
+			FixtureNode { attempts: 1, successes: 1, ..FixtureNode::new("3", "22.99.55.3:7770") },
+			// failing too often or never tried, they do not count
+			FixtureNode { attempts: 2, failures: 1, successes: 1, ..FixtureNode::new("4", "22.99.55.4:7770") },
+			FixtureNode::new("5", "22.99.55.5:7770"),
+		], config, &ManualClock::new());
+		let all = IpFilter::default();
+		let selected = |table: &TableState| table.select_for(&DialPolicy::default(), &all, 10, None);
+
+		// below the threshold boot nodes are dialled like any other node
+		assert!(!table.is_bootstrapped());
+		assert!(selected(&table).contains(&NodeId::from(1)));
+		assert!(table.boot_fallback(&all).is_empty());
+
+		table.update_node(&NodeId::from(5), |node| node.attempts += 1);
+		table.note_success(&NodeId::from(5));
+		assert!(table.is_bootstrapped());
+		assert!(table.stats(&all).bootstrapped);
+		assert!(!selected(&table).contains(&NodeId::from(1)));
+		assert_eq!(selected(&table).len(), 4);
+		// still handed to discovery and dialled when no peers are connected
+		assert!(table.entries_filtered(&all, 10).iter().any(|e| e.id == NodeId::from(1)));
+		assert_eq!(table.boot_fallback(&all), vec![NodeId::from(1)]);
+
+		// unless required
+		table.update_node(&NodeId::from(1), |node| node.peer_type = PeerType::Required);
+		assert!(selected(&table).contains(&NodeId::from(1)));
+		assert!(table.boot_fallback(&all).is_empty());
+	}
+
+	#[test]
+	fn dialled_ports_per_ip_capped() {
+		let config = NodeTableConfig { max_ports_per_ip: Some(3), port_window: Duration::from_secs(60), ..Default::default() };
+		let clock = ManualClock::new();
+		let mut nodes: Vec<FixtureNode> = (1..11).map(|i| FixtureNode::new(&format!("{:x}", i), &format!("22.99.55.1:{}", 7770 + i))).collect();
+		nodes.push(FixtureNode { required: true, ..FixtureNode::new("b", "22.99.55.1:7790") });
+		nodes.push(FixtureNode::new("c", "22.99.56.1:7770"));
+		let mut table = TableState::from_fixture_with(&nodes, config, &clock);
+		let same_ip: Vec<NodeId> = (1..11).map(NodeId::from).collect();
+		let selected = |table: &TableState| -> Vec<NodeId> {
+			table.select_for(&DialPolicy::default(), &IpFilter::default(), 20, None).into_iter().filter(|id| same_ip.contains(id)).collect()
+		};
+
+		// three ports of the IP are selected, the required node and the other IP are not limited
+		let first = selected(&table);
+		assert_eq!(first.len(), 3);
+		let all = table.select_for(&DialPolicy::default(), &IpFilter::default(), 20, None);
+		assert!(all.contains(&NodeId::from(11)) && all.contains(&NodeId::from(12)));
+
+		// the dialled ports count for the window, the other nodes on the IP are deferred
+		for id in &first {
+			table.note_dial(id);
+			table.note_failure(id);
+		}
+		clock.advance(Duration::from_secs(30));
+		assert_eq!(selected(&table), first);
+		assert_eq!(table.stats(&IpFilter::default()).port_capped, 1);
+
+		// and selected once it passed
+		clock.advance(Duration::from_secs(31));
+		let next = selected(&table);
+		assert_eq!(next.len(), 3);
+		assert!(next.iter().all(|id| !first.contains(id)));
+		assert_eq!(table.nodes.len(), 12);
+	}
+
+	#[test]
+	fn entry_status_explains_selection() {
+		let clock = ManualClock::new();
+		let nodes = vec![
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode { banned: true, ..FixtureNode::new("2", "22.99.55.2:7770") },
+			FixtureNode { useless: true, ..FixtureNode::new("3", "22.99.55.3:7770") },
+			FixtureNode::new("4", "22.99.55.4:7770"),
+			FixtureNode::new("5", "10.0.0.1:7770"),
+			FixtureNode::new("6", "22.99.57.1:7770"),
+			FixtureNode::new("7", "22.99.58.1:7770"),
+			FixtureNode::new("8", "22.99.58.1:7771"),
+			FixtureNode::new("9", "22.99.59.1:7770"),
+			FixtureNode::new("a", "22.99.59.1:7771"),
+			FixtureNode { banned: true, ..FixtureNode::new("b", "22.99.55.11:7770") },
+		];
+		let config = NodeTableConfig { max_ports_per_ip: Some(1), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&nodes, config, &clock);
+		table.note_failure(&NodeId::from(4));
+		table.note_failure(&NodeId::from(0xb));
+		table.session_opened(&NodeId::from(7), &"22.99.58.1:7770".parse().unwrap());
+		table.note_dial(&NodeId::from(9));
+		let filter = IpFilter { predefined: AllowIP::Public, custom_allow: Vec::new(), custom_block: vec!["22.99.57.0/24".parse().unwrap()] };
+		let policy = DialPolicy::default();
+		let status = |table: &TableState, id: u64| table.entry_status_for(&NodeId::from(id), &policy, &filter).unwrap();
+
+		// the status matches the selection
+		let mut selected = table.select_for(&policy, &filter, 100, None);
+		for id in 1..12 {
+			assert_eq!(status(&table, id).dialable, selected.contains(&NodeId::from(id)), "node {}", id);
+		}
+		selected.sort();
+		assert_eq!(selected, vec![NodeId::from(1), NodeId::from(9)]);
+
+		let now = clock.now_system();
+		assert_eq!((status(&table, 1).exclusions, status(&table, 1).next_eligible), (Vec::new(), None));
+		let banned = status(&table, 2);
+		let until = banned.banned_until.unwrap();
+		assert_eq!(banned.exclusions, vec![DialExclusion::Banned { reason: "fixture".into(), until: until }]);
+		assert_eq!(banned.next_eligible, Some(until));
+		assert_eq!(status(&table, 3).exclusions, vec![DialExclusion::Useless { reason: "fixture".into(), until: None }]);
+		assert_eq!(status(&table, 3).next_eligible, None);
+		match status(&table, 4).exclusions[..] {
+			[DialExclusion::Backoff { until }] => assert!(until > now && status(&table, 4).next_eligible == Some(until)),
+			ref other => panic!("{:?}", other),
+		}
+		assert_eq!(status(&table, 5).exclusions, vec![DialExclusion::Filtered { rule: "public".into() }]);
+		assert_eq!(status(&table, 6).exclusions, vec![DialExclusion::Filtered { rule: "-22.99.57.0/24".into() }]);
+		assert_eq!(status(&table, 7).exclusions, vec![DialExclusion::Connected]);
+		assert_eq!(status(&table, 8).exclusions, vec![DialExclusion::ConnectedIp]);
+		assert_eq!(status(&table, 0xa).exclusions, vec![DialExclusion::PortCap]);
+		assert!(status(&table, 0xa).entry.is_some());
+
+		// eligible once the last of the exclusions that end by themselves ended
+		let both = status(&table, 0xb);
+		assert_eq!(both.exclusions.len(), 2);
+		assert_eq!(both.next_eligible, both.banned_until);
+
+		// a banned node that is not in the table has a status without an entry, other unknown nodes have none
+		table.ban(&NodeId::from(0xc), Duration::from_secs(60), "test");
+		let unknown = status(&table, 0xc);
+		assert!(unknown.entry.is_none() && !unknown.dialable && unknown.next_eligible.is_none());
+		assert_eq!(unknown.exclusions.len(), 1);
+		assert_eq!(table.entry_status(&NodeId::from(0xd)), None);
+	}
+
+	#[test]
+	fn select_skips_connected() {
+		let nodes = vec![
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode::new("2", "22.99.55.1:7771"),
+			FixtureNode::new("3", "22.99.56.1:7770"),
+			FixtureNode { required: true, ..FixtureNode::new("4", "22.99.55.1:7772") },
+		];
+		let mut table = TableState::from_fixture(&nodes);
+		let selected = |table: &TableState, policy: &DialPolicy| -> HashSet<NodeId> {
+			table.select_for(policy, &IpFilter::default(), 10, None).into_iter().collect()
+		};
+		let ids = |ids: &[u64]| -> HashSet<NodeId> { ids.iter().cloned().map(NodeId::from).collect() };
+
+		// a connected node is skipped by id, whatever address its session came from
+		table.session_opened(&NodeId::from(3), &"22.99.57.1:40000".parse().unwrap());
+		assert_eq!(selected(&table, &DialPolicy::default()), ids(&[1, 2, 4]));
+
+		// a session from an IPv4-mapped address skips the other nodes on the IP, except the required one
+		table.session_opened(&NodeId::from(5), &"[::ffff:22.99.55.1]:40000".parse().unwrap());
+		assert_eq!(selected(&table, &DialPolicy::default()), ids(&[4]));
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.active_sessions, stats.connected_skipped, stats.connected_ip_skipped), (2, 2, 2));
+
+		// the exclusion can be turned off
+		let all = DialPolicy { exclude_connected: false, ..Default::default() };
+		assert_eq!(selected(&table, &all), ids(&[1, 2, 3, 4]));
+		assert_eq!(table.stats(&IpFilter::default()).connected_skipped, 2);
+
+		// and ends with the last session of a node
+		table.session_opened(&NodeId::from(5), &"22.99.55.1:40001".parse().unwrap());
+		assert_eq!(table.stats(&IpFilter::default()).active_sessions, 3);
+		table.session_closed(&NodeId::from(5));
+		assert_eq!(selected(&table, &DialPolicy::default()), ids(&[4]));
+		table.session_closed(&NodeId::from(5));
+		assert_eq!(selected(&table, &DialPolicy::default()), ids(&[1, 2, 4]));
+		table.session_closed(&NodeId::from(3));
+		assert_eq!(selected(&table, &DialPolicy::default()), ids(&[1, 2, 3, 4]));
+		assert_eq!(table.stats(&IpFilter::default()).active_sessions, 0);
+	}
+
+	#[test]
+	fn candidate_stream_yields_once_per_round() {
+		let fixture = |id: u32| FixtureNode {
+			attempts: 10,
+			failures: id,
+			successes: 10 - id,
+			last_contact: Some(Duration::from_secs(60)),
+			..FixtureNode::new(&id.to_string(), &format!("22.99.{}.1:30303", id))
+		};
+		let mut table = TableState::from_fixture(&[fixture(1), fixture(2), fixture(3)]);
+		let mut stream = table.candidate_stream(DialPolicy::default(), IpFilter::default());
+		let next = |table: &TableState, stream: &mut CandidateStream| table.next_candidate(stream).map(|(entry, _)| entry.id);
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(1)));
+		assert_eq!(stream.upcoming(10).len(), 2);
+
+		// a better node added meanwhile comes next, without starting over
+		let better = Node::builder(NodeId::from(4), NodeEndpoint::from_str("22.99.4.1:30303").unwrap())
+			.stats(10, 0, 10)
+			.last_contact(SystemTime::now())
+			.build();
+		table.add_node(better);
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(4)));
+
+		// a node already yielded is not yielded again when the table changes
+		table.note_success(&NodeId::from(1));
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(2)));
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(3)));
+		assert_eq!((next(&table, &mut stream), stream.rounds()), (None, 1));
+
+		// the round ran dry, the next one starts over
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(4)));
+		assert!(stream.is_for(&DialPolicy::default(), &IpFilter::default()));
+		assert!(!stream.is_for(&DialPolicy { exclude_connected: false, ..Default::default() }, &IpFilter::default()));
+	}
+
+	#[test]
+	fn candidate_stream_exhaustion() {
+		let nodes = vec![FixtureNode::new("1", "22.99.55.1:30303"), FixtureNode::new("2", "22.99.56.1:30303")];
+		let clock = ManualClock::new();
+		let mut table = TableState::from_fixture_with(&nodes, NodeTableConfig::default(), &clock);
+		let policy = DialPolicy { required_capabilities: Vec::new(), allow_unknown: true, ..Default::default() };
+		let next = |table: &TableState, stream: &mut CandidateStream| table.next_candidate(stream).map(|(entry, _)| entry.id);
+
+		// a backoff ending during the round makes its node a candidate, though the table did not change
+		table.note_failure(&NodeId::from(2));
+		let mut stream = table.candidate_stream(policy, IpFilter::default());
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(1)));
+		assert!(stream.upcoming(10).is_empty());
+		clock.advance(table.config.initial_backoff);
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(2)));
+		assert_eq!(next(&table, &mut stream), None);
+
+		// with every node excluded each call is a round of its own that yields nothing
+		table.note_failure(&NodeId::from(1));
+		table.note_failure(&NodeId::from(2));
+		for rounds in 2..5 {
+			assert_eq!(next(&table, &mut stream), None);
+			assert_eq!(stream.rounds(), rounds);
+		}
+		assert!(stream.upcoming(10).is_empty());
+		clock.advance(table.config.max_backoff);
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(1)));
+	}
+
+	#[test]
+	fn candidate_stream_kept_on_dial() {
+		let fixture = |id: u32| FixtureNode {
+			attempts: 10,
+			failures: id,
+			successes: 10 - id,
+			..FixtureNode::new(&id.to_string(), &format!("22.99.{}.1:30303", id))
+		};
+		let mut table = TableState::from_fixture(&[fixture(1), fixture(2), fixture(3), fixture(4)]);
+		let mut stream = table.candidate_stream(DialPolicy::default(), IpFilter::default());
+		let next = |table: &TableState, stream: &mut CandidateStream| table.next_candidate(stream).map(|(entry, _)| entry.id);
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(1)));
+
+		// made the best node behind the back of the table, only seen once the candidates are computed again
+		table.nodes.get_mut(&NodeId::from(4)).unwrap().failures = 0;
+
+		// the outcome of a yielded node does not compute them again
+		table.note_dial(&NodeId::from(1));
+		table.note_failure(&NodeId::from(1));
+		table.note_latency(&NodeId::from(1), Duration::from_millis(100));
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(2)));
+
+		// a change of a node not yielded yet does
+		table.note_latency(&NodeId::from(3), Duration::from_millis(100));
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(4)));
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(3)));
+
+		// and so does a stream too far behind the changes
+		let mut stream = table.candidate_stream(DialPolicy::default(), IpFilter::default());
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(4)));
+		table.nodes.get_mut(&NodeId::from(3)).unwrap().failures = 0;
+		for _ in 0..MAX_NODE_CHANGES + 1 {
+			table.note_latency(&NodeId::from(4), Duration::from_millis(100));
+		}
+		assert_eq!(next(&table, &mut stream), Some(NodeId::from(3)));
+	}
+
+	#[test]
+	fn select_spreads_over_operator_clusters() {
+		// two operators with five nodes each on sequential ports, and three independent nodes
+		let mut nodes = Vec::new();
+		for i in 0..5 {
+			nodes.push(FixtureNode::new(&format!("{:x}", 1 + i), &format!("22.99.55.{}:{}", 10 + i, 30303 + i)));
+			nodes.push(FixtureNode::new(&format!("{:x}", 11 + i), &format!("22.99.66.20:{}", 30303 + i)));
+		}
+		for i in 21..24 {
+			nodes.push(FixtureNode::new(&format!("{:x}", i), &format!("22.99.{}.1:30303", i)));
+		}
+		let config = NodeTableConfig { operator_clusters: true, ..Default::default() };
+		let mut table = TableState::from_fixture_with(&nodes, config, &ManualClock::new());
+		for i in 1..6 {
+			table.update_node(&NodeId::from(i), |n| n.client_version = Some("Parity/v1.11.0".into()));
+			table.update_node(&NodeId::from(10 + i), |n| n.client_version = Some("Geth/v1.8.2".into()));
+		}
+		// same subnet, another client: not in the cluster
+		table.update_node(&NodeId::from(21), |n| {
+			n.endpoint = NodeEndpoint::from_str("22.99.55.99:30303").unwrap();
+			n.client_version = Some("Geth/v1.8.2".into());
+		});
+
+		assert_eq!(table.cluster_of(&NodeId::from(3)), Some((1..6).map(NodeId::from).collect()));
+		assert_eq!(table.cluster_of(&NodeId::from(12)), Some((11..16).map(NodeId::from).collect()));
+		assert_eq!(table.cluster_of(&NodeId::from(21)), None);
+		assert_eq!(table.cluster_of(&NodeId::from(22)), None);
+
+		// one node per cluster per round, the other nodes fill the rest
+		let selected: HashSet<NodeId> = table.select_for(&DialPolicy::default(), &IpFilter::default(), 10, None).into_iter().collect();
+		assert_eq!(selected.len(), 5);
+		assert_eq!(selected.iter().filter(|id| table.cluster_of(id).is_some()).count(), 2);
+		assert!((21..24).map(NodeId::from).all(|id| selected.contains(&id)));
+
+		// recomputed once the table changes
+		table.update_node(&NodeId::from(2), |n| n.client_version = Some("Parity/v1.11.1".into()));
+		assert_eq!(table.cluster_of(&NodeId::from(2)), None);
+		assert_eq!(table.cluster_of(&NodeId::from(1)).map(|c| c.len()), Some(4));
+		assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 10, None).len(), 6);
+
+		// off by default
+		table.config.operator_clusters = false;
+		assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 20, None).len(), 13);
+
+		// the nodes of a cluster that are not selected do not take the port slot of their IP, only the selected one
+		table.config.operator_clusters = true;
+		table.config.max_ports_per_ip = Some(1);
+		table.update_node(&NodeId::from(2), |n| n.client_version = Some("Parity/v1.11.0".into()));
+		for i in 0..5 {
+			let endpoint = NodeEndpoint::from_str(&format!("22.99.55.{}:40000", 10 + i)).unwrap();
+			table.add_node(Node::new(NodeId::from(31 + i), endpoint));
+		}
+		let selected = table.select_for(&DialPolicy::default(), &IpFilter::default(), 20, None);
+		assert_eq!(selected.iter().filter(|id| id.low_u64() > 30).count(), 4);
+	}
+
+	#[test]
+	fn recently_connected_bounded() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let count = MAX_RECENTLY_CONNECTED as u64 + 10;
+		for i in 1..count + 1 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+			table.note_disconnect(&NodeId::from(i), true);
+		}
+		// a node is kept once, with its latest session
+		table.note_disconnect(&NodeId::from(20), true);
+		let lost: Vec<NodeId> = table.recently_lost(Duration::from_secs(60)).into_iter().map(|e| e.id).collect();
+		assert_eq!(lost.len(), MAX_RECENTLY_CONNECTED);
+		assert_eq!(lost[0], NodeId::from(20));
+		assert_eq!(lost[1], NodeId::from(count));
+		assert_eq!(lost[MAX_RECENTLY_CONNECTED - 1], NodeId::from(11));
+		assert_eq!(lost.iter().filter(|id| **id == NodeId::from(20)).count(), 1);
+	}
+
+	#[test]
+	fn recently_lost_skips_disconnects_for_cause() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode::new("2", "22.99.55.2:7770"),
+			FixtureNode::new("3", "22.99.55.3:7770"),
+			FixtureNode::new("4", "22.99.55.4:7770"),
+		]);
+		table.note_disconnect(&NodeId::from(1), true);
+		// ended by us
+		table.note_disconnect(&NodeId::from(2), false);
+		// banned or removed after the session ended
+		table.note_disconnect(&NodeId::from(3), true);
+		table.ban(&NodeId::from(3), Duration::from_secs(60), "test");
+		table.note_disconnect(&NodeId::from(4), true);
+		table.remove_node(&NodeId::from(4));
+		let lost: Vec<NodeId> = table.recently_lost(Duration::from_secs(60)).into_iter().map(|e| e.id).collect();
+		assert_eq!(lost, vec![NodeId::from(1)]);
+
+		// a later session ended by us replaces the earlier one
+		table.note_disconnect(&NodeId::from(1), false);
+		assert!(table.recently_lost(Duration::from_secs(60)).is_empty());
+	}
+
+	#[test]
+	fn recently_lost_age() {
+		let clock = ManualClock::new();
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode::new("1", "22.99.55.1:7770"),
+			FixtureNode::new("2", "22.99.55.2:7770"),
+		], NodeTableConfig::default(), &clock);
+		table.note_disconnect(&NodeId::from(1), true);
+		clock.advance(Duration::from_secs(30));
+		table.note_disconnect(&NodeId::from(2), true);
+		let lost = |table: &TableState| table.recently_lost(Duration::from_secs(60)).into_iter().map(|e| e.id).collect::<Vec<_>>();
+		assert_eq!(lost(&table), vec![NodeId::from(2), NodeId::from(1)]);
+		clock.advance(Duration::from_secs(30));
+		assert_eq!(lost(&table), vec![NodeId::from(2), NodeId::from(1)]);
+		clock.advance(Duration::from_secs(1));
+		assert_eq!(lost(&table), vec![NodeId::from(2)]);
+		clock.advance(Duration::from_secs(30));
+		assert!(lost(&table).is_empty());
+	}
+
+	#[test]
+	fn table_reconcile_on_load() {
+		let persistence = MemoryPersistence::new();
+		let address = |i: u64| match i {
+			1 | 2 => format!("22.99.55.{}:7770", i),
+			3 => "10.0.0.3:7770".to_owned(),
+			_ => format!("22.99.{}.1:7770", 60 + i),
+		};
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			for i in 1..8 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&address(i)).unwrap()));
+			}
+			table.note_success(&NodeId::from(1));
+			table.note_failure(&NodeId::from(2));
+			table.update_node(&NodeId::from(4), |n| n.last_contact = Some(SystemTime::now() - Duration::from_secs(2 * 60 * 60))).unwrap();
+			table.save();
+		}
+
+		// 1 and 2 share a subnet, 3 is private, 4 is too old, 5 is our own address, 6 and 7 are one too many
+		let config = NodeTableConfig { max_nodes: 2, max_nodes_per_subnet: Some(1), max_age: Some(Duration::from_secs(60 * 60)), ..Default::default() };
+		let filter = IpFilter { predefined: AllowIP::Public, custom_allow: vec![], custom_block: vec![] };
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), config.clone());
+		assert_eq!(table.load_report().kept, None);
+		table.set_local_endpoints(vec![NodeEndpoint::from_str(&address(5)).unwrap()]);
+		table.reconcile(&filter, &HashSet::new());
+
+		let expected: BTreeMap<DropReason, usize> = vec![
+			(DropReason::Filtered, 1), (DropReason::Subnet, 1), (DropReason::Expired, 1), (DropReason::Capacity, 1), (DropReason::OwnAddress, 1),
+		].into_iter().collect();
+		assert_eq!(table.load_report().dropped, expected);
+		assert_eq!(table.load_report().kept, Some(2));
+		let mut ids: Vec<u64> = table.nodes.keys().map(|id| id.low_u64()).collect();
+		ids.sort();
+		assert_eq!(ids, vec![1, 6]);
+		let mut dropped: Vec<u64> = persistence.dropped().into_iter().map(|n| n.into_node().unwrap().id.low_u64()).collect();
+		dropped.sort();
+		assert_eq!(dropped, vec![2, 3, 4, 5, 7]);
+
+		// once only
+		table.reconcile(&filter, &HashSet::new());
+		assert_eq!(table.load_report().dropped_count(), 5);
+
+		// raw loading keeps everything, also over the subnet limit
+		let config = NodeTableConfig { raw_load: true, ..config };
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), config.clone());
+		table.set_local_endpoints(vec![NodeEndpoint::from_str(&address(5)).unwrap()]);
+		table.reconcile(&filter, &HashSet::new());
+		assert_eq!(table.nodes.len(), 7);
+		assert_eq!(table.subnet_rejected, 0);
+		assert_eq!(table.load_report().kept, None);
+		assert_eq!(persistence.dropped().len(), 5);
+
+		// and below the minimum port
+		let persistence = MemoryPersistence::new();
+		{
+			let config = NodeTableConfig { min_port: 0, ..Default::default() };
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), config);
+			table.add_node(Node::new(NodeId::from(8), NodeEndpoint::from_str("22.99.55.8:22").unwrap()));
+			table.save();
+		}
+		let table = NodeTable::with_persistence(Box::new(persistence.clone()), config);
+		assert!(table.contains(&NodeId::from(8)));
+		assert_eq!(table.low_port_rejected, 0);
+	}
+
+	#[test]
+	fn repeated_useless_marks_are_counted() {
+		let clock = ManualClock::new();
+		let config = NodeTableConfig { useless_cooldown: Duration::from_secs(60), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[FixtureNode::new("1", "22.99.55.44:7770")], config, &clock);
+		let id = NodeId::from(1);
+		let generation = table.generation;
+		for _ in 0..50 {
+			assert_eq!(table.mark_as_useless(&id, "bad block"), NoteResult::Applied);
+		}
+		// one change of the table, the other marks are counted
+		assert_eq!(table.generation, generation + 1);
+		let status = table.entry_status(&id).unwrap();
+		assert_eq!((status.useless_reason, status.useless_repeats), (Some("bad block".to_owned()), 49));
+
+		// another reason is processed right away and starts over
+		table.mark_as_useless(&id, "timeout");
+		assert_eq!(table.generation, generation + 2);
+		assert_eq!(table.entry_status(&id).unwrap().useless_repeats, 0);
+
+		// past the window the mark is made again, the count is kept
+		table.mark_as_useless(&id, "timeout");
+		clock.advance(Duration::from_secs(60));
+		table.mark_as_useless(&id, "timeout");
+		assert_eq!(table.generation, generation + 3);
+		assert_eq!(table.entry_status(&id).unwrap().useless_repeats, 2);
+
+		// every mark is processed without a window
+		let config = NodeTableConfig { useless_cooldown: Duration::from_secs(0), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[FixtureNode::new("1", "22.99.55.44:7770")], config, &clock);
+		let generation = table.generation;
+		for _ in 0..5 {
+			table.mark_as_useless(&id, "bad block");
+		}
+		assert_eq!(table.generation, generation + 5);
+		assert_eq!(table.entry_status(&id).unwrap().useless_repeats, 4);
+	}
+
+	#[test]
+	fn entries_with_useless_required() {
+		let clock = ManualClock::new();
+		let mut table = TableState::from_fixture_with(&[
+			FixtureNode::new("1", "22.99.55.44:7770"),
+			FixtureNode::new("2", "22.99.55.45:7770"),
+			FixtureNode::new("3", "22.99.55.46:7770"),
+		], NodeTableConfig::default(), &clock);
+		let ids = |entries: Vec<NodeEntry>| entries.into_iter().map(|e| e.id).collect::<Vec<_>>();
+		// marked before it became required, e.g. while it was restarting, and a node required for one protocol only
+		table.mark_as_useless(&NodeId::from(1), INCOMPATIBLE_PROTOCOL);
+		table.update_node(&NodeId::from(1), |n| n.peer_type = PeerType::Required);
+		table.set_peer_type_for(&NodeId::from(2), *b"par", PeerType::Required);
+		table.mark_as_useless(&NodeId::from(2), "disabled by protocol handler");
+		table.mark_as_useless(&NodeId::from(3), INCOMPATIBLE_PROTOCOL);
+		table.ban(&NodeId::from(3), Duration::from_secs(60), "test");
+
+		assert!(table.entries().is_empty());
+		assert!(table.entries_with(EntryOptions::default()).is_empty());
+		let mut required = ids(table.entries_with(EntryOptions { include_useless_required: true, ..Default::default() }));
+		required.sort();
+		assert_eq!(required, vec![NodeId::from(1), NodeId::from(2)]);
+		// useless optional nodes stay out
+		assert_eq!(ids(table.entries_with(EntryOptions { include_useless_required: true, include_banned: true })).len(), 2);
+		table.clear_useless();
+		assert_eq!(ids(table.entries_with(EntryOptions { include_banned: true, ..Default::default() })).len(), 3);
+
+		// logged once when they stay useless for too long
+		table.mark_as_useless(&NodeId::from(3), INCOMPATIBLE_PROTOCOL);
+		table.set_peer_type_for(&NodeId::from(3), *b"par", PeerType::Required);
+		table.set_peer_type_for(&NodeId::from(2), *b"par", PeerType::Optional);
+		table.mark_as_useless(&NodeId::from(2), INCOMPATIBLE_PROTOCOL);
+		table.set_peer_type_for(&NodeId::from(2), *b"par", PeerType::Required);
+		assert!(table.warn_useless_required().is_empty());
+		clock.advance(USELESS_REQUIRED_WARNING);
+		assert_eq!(table.warn_useless_required(), vec![NodeId::from(2), NodeId::from(3)]);
+		assert!(table.warn_useless_required().is_empty());
+		table.clear_useless();
+		assert!(table.warn_useless_required().is_empty());
+	}
+
+	#[test]
+	fn table_reserved_only_mode() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode::new("1", "22.99.55.44:7770"),
+			FixtureNode { required: true, ..FixtureNode::new("2", "22.99.55.44:7770") },
+			FixtureNode::new("3", "22.99.55.44:7770"),
+		]);
+		let policy = DialPolicy { required_capabilities: Vec::new(), allow_unknown: true, ..Default::default() };
+		assert_eq!(table.nodes(&IpFilter::default()).len(), 3);
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None).len(), 3);
+
+		table.set_selection_mode(SelectionMode::ReservedOnly);
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(2)]);
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None), vec![NodeId::from(2)]);
+		assert_eq!(table.entries().into_iter().map(|e| e.id).collect::<Vec<_>>(), vec![NodeId::from(2)]);
+
+		// discovery results are still recorded
+		let id = NodeId::from(4);
+		let entry = NodeEntry::new(id.clone(), NodeEndpoint::from_str("22.99.55.45:7770").unwrap());
+		let updates = TableUpdates { added: vec![(id.clone(), entry)].into_iter().collect(), removed: HashSet::new(), verified: HashSet::new() };
+		table.update(updates, &HashSet::new());
+		assert!(table.contains(&id));
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(2)]);
+
+		table.set_selection_mode(SelectionMode::Normal);
+		assert_eq!(table.nodes(&IpFilter::default()).len(), 4);
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None).len(), 4);
+	}
+
+	#[test]
+	fn table_required_nodes_persist() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let config = NodeTableConfig { max_nodes: 1, ..Default::default() };
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, config.clone());
+			for i in 1..4 {
+				let mut node = Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap());
+				if i != 1 {
+					node.peer_type = PeerType::Required;
+				}
+				table.add_node(node);
+			}
+			// required nodes are neither marked useless nor expired nor dropped by discovery
+			table.mark_as_useless(&NodeId::from(2), "test");
+			assert_eq!(table.useless_nodes.len(), 0);
+			let updates = TableUpdates { added: HashMap::new(), removed: vec![NodeId::from(2)].into_iter().collect(), verified: HashSet::new() };
+			table.update(updates, &HashSet::new());
+			assert!(table.contains(&NodeId::from(2)));
+			table.save();
+		}
+
+		let table = NodeTable::with_config(path, true, config);
+		let mut required = table.required_nodes();
+		required.sort();
+		assert_eq!(required, vec![NodeId::from(2), NodeId::from(3)]);
+		// required nodes do not count towards `max_nodes`
+		assert_eq!(table.nodes.len(), 3);
+	}
+
+	#[test]
+	fn table_release_required() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		for i in 1..3 {
+			let mut node = Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap());
+			node.peer_type = PeerType::Required;
+			table.add_node(node);
+		}
+
+		// downgrade keeps the node and its stats
+		table.note_success(&NodeId::from(1));
+		assert!(table.release_required(&NodeId::from(1), false));
+		assert!(!table.is_required(&NodeId::from(1)));
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().successes, 1);
+
+		// forget removes it
+		assert!(table.release_required(&NodeId::from(2), true));
+		assert!(!table.contains(&NodeId::from(2)));
+		assert!(!table.release_required(&NodeId::from(2), false));
+		assert_eq!(table.required_nodes(), vec![]);
+	}
+
+	#[test]
+	fn table_boot_nodes_survive() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let config = NodeTableConfig { max_nodes: 2, max_age: Some(Duration::from_secs(60)), ..Default::default() };
+		let boot = NodeId::from(5);
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, config.clone());
+			for i in 1..5 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+				table.note_success(&NodeId::from(i));
+			}
+			let mut node = Node::new(boot.clone(), NodeEndpoint::from_str("22.99.55.45:7770").unwrap());
+			node.is_boot = true;
+			table.add_node(node);
+			table.update_node(&boot, |n| n.attempts = 10).unwrap();
+			table.update_node(&boot, |n| n.failures = 10).unwrap();
+			table.update_node(&boot, |n| n.last_contact = Some(SystemTime::now() - Duration::from_secs(2 * 60))).unwrap();
+
+			// a later add without the flag keeps it
+			table.add_node(Node::new(boot.clone(), NodeEndpoint::from_str("22.99.55.45:7770").unwrap()));
+			assert!(table.get(&boot).unwrap().is_boot);
+			assert_eq!(table.drop_expired(&HashSet::new()), 0);
+			let updates = TableUpdates { added: HashMap::new(), removed: vec![boot.clone()].into_iter().collect(), verified: HashSet::new() };
+			table.update(updates, &HashSet::new());
+			assert!(table.contains(&boot));
+			table.mark_as_useless(&boot, "test");
+			table.save();
+		}
+
+		let table = NodeTable::with_config(path, true, config);
+		assert_eq!(table.nodes.len(), 3);
+		assert!(table.contains(&boot));
+	}
+
+	#[test]
+	fn table_boot_node_retry() {
+		let interval = Duration::from_secs(60);
+		let config = NodeTableConfig { boot_retry_interval: interval, ..Default::default() };
+		let mut table = TableState::new(config);
+		let clock = ManualClock::new();
+		table.set_clock(Box::new(clock.clone()));
+		let boot = NodeId::from(1);
+		let mut node = Node::new(boot.clone(), NodeEndpoint::from_str("22.99.55.44:7770").unwrap());
+		node.is_boot = true;
+		table.add_node(node);
+		table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		let policy = DialPolicy { required_capabilities: Vec::new(), allow_unknown: true, ..Default::default() };
+
+		table.mark_as_useless(&boot, "test");
+		table.mark_as_useless(&NodeId::from(2), "test");
+		assert!(table.select_for(&policy, &IpFilter::default(), 10, None).is_empty());
+		assert_eq!(table.stats(&IpFilter::default()).useless, 2);
+
+		// the boot node returns to selection once the retry interval has passed
+		clock.advance(interval + Duration::from_secs(1));
+		assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, None), vec![boot.clone()]);
+
+		// its backoff is capped by the retry interval but failures are still recorded
+		for _ in 0..20 {
+			table.note_failure(&boot);
+		}
+		assert!(table.backoff[&boot].until <= clock.now_instant() + interval);
+		assert_eq!(table.get(&boot).unwrap().failures, 20);
+	}
+
+	#[test]
+	fn table_protocol_required_nodes() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let policy = DialPolicy { required_capabilities: Vec::new(), allow_unknown: true, ..Default::default() };
+		let scoped = NodeId::from(3);
+		{
+			let mut table = NodeTable::with_config(path.clone(), true, NodeTableConfig::default());
+			for i in 1..4 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+			}
+			// make the scoped node the worst one by ordinary ordering
+			table.update_node(&scoped, |n| n.attempts = 10).unwrap();
+			table.update_node(&scoped, |n| n.failures = 10).unwrap();
+			assert!(table.set_peer_type_for(&scoped, *b"poa", PeerType::Required));
+			assert!(!table.set_peer_type_for(&NodeId::from(4), *b"poa", PeerType::Required));
+
+			assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, Some(b"poa"))[0], scoped);
+			assert_eq!(*table.select_for(&policy, &IpFilter::default(), 10, Some(b"eth")).last().unwrap(), scoped);
+			assert_eq!(*table.select_for(&policy, &IpFilter::default(), 10, None).last().unwrap(), scoped);
+			assert!(table.is_required_for(&scoped, b"poa"));
+			assert!(!table.is_required_for(&scoped, b"eth"));
+			assert!(!table.is_required(&scoped));
+
+			// reserved-only mode keeps the scoped node for its protocol only
+			table.set_selection_mode(SelectionMode::ReservedOnly);
+			assert_eq!(table.select_for(&policy, &IpFilter::default(), 10, Some(b"poa")), vec![scoped.clone()]);
+			assert!(table.select_for(&policy, &IpFilter::default(), 10, Some(b"eth")).is_empty());
+
+			// global required status implies all protocols
+			table.update_node(&NodeId::from(1), |n| n.peer_type = PeerType::Required).unwrap();
+			assert!(table.is_required_for(&NodeId::from(1), b"poa"));
+			table.set_peer_type_for(&NodeId::from(2), *b"poa", PeerType::Required);
+			table.set_peer_type_for(&NodeId::from(2), *b"poa", PeerType::Optional);
+			assert!(!table.is_required_for(&NodeId::from(2), b"poa"));
+			table.save();
+		}
+
+		let table = NodeTable::with_config(path, true, NodeTableConfig::default());
+		assert!(table.is_required_for(&scoped, b"poa"));
+		assert!(!table.is_required_for(&scoped, b"eth"));
+	}
+
+	#[test]
+	fn table_meta_limits() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let id = NodeId::from(1);
+		let endpoint = NodeEndpoint::from_str("22.99.55.44:7770").unwrap();
+		let capabilities: Vec<PeerCapabilityInfo> = (0..40).map(|v| capability(b"eth", v)).collect();
+
+		// the cut falls inside a two byte character, which is dropped as a whole
+		let meta = SessionMeta { client_version: format!("P{}", "é".repeat(200)), capabilities: capabilities.clone() };
+		table.note_inbound(&id, endpoint.clone(), meta);
+		{
+			let node = table.get(&id).unwrap();
+			assert_eq!(node.client_version.as_ref().unwrap(), &format!("P{}", "é".repeat(127)));
+			assert_eq!(node.capabilities.as_ref().unwrap()[..], capabilities[..32]);
+		}
+		assert_eq!(table.stats(&IpFilter::default()).meta_truncated, 2);
+
+		// values within the limits are kept as they are
+		table.note_capabilities(&id, capabilities[..32].to_vec());
+		assert_eq!(table.stats(&IpFilter::default()).meta_truncated, 2);
+		table.note_capabilities(&id, capabilities.clone());
+		assert_eq!(table.get(&id).unwrap().capabilities.as_ref().unwrap().len(), 32);
+		assert_eq!(table.stats(&IpFilter::default()).meta_truncated, 3);
+
+		let mut node = Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.56.44:7770").unwrap());
+		node.client_version = Some("x".repeat(1000));
+		node.address_history = (0..20).map(|i| (SocketAddr::from_str(&format!("22.99.57.{}:7770", i)).unwrap(), SystemTime::now())).collect();
+		table.add_node(node);
+		{
+			let node = table.get(&NodeId::from(2)).unwrap();
+			assert_eq!(node.client_version.as_ref().unwrap().len(), META_LIMITS.client_version);
+			assert_eq!(node.address_history.len(), META_LIMITS.address_history);
+		}
+		assert_eq!(table.stats(&IpFilter::default()).meta_truncated, 5);
+
+		// a hand edited file is cut on load
+		let mut persisted = PersistedNode::from(table.get(&NodeId::from(2)).unwrap());
+		persisted.client_version = Some("x".repeat(1000));
+		persisted.capabilities = Some((0..100).map(|v| format!("eth/{}", v)).collect());
+		let loaded = persisted.into_node().unwrap();
+		assert_eq!(loaded.client_version.unwrap().len(), META_LIMITS.client_version);
+		assert_eq!(loaded.capabilities.unwrap().len(), META_LIMITS.capabilities);
+	}
+
+	#[test]
+	fn table_save_size_with_max_meta() {
+		let persistence = MemoryPersistence::new();
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		for i in 1..1001u64 {
+			let id = NodeId::from(i);
+			let endpoint = NodeEndpoint::from_str(&format!("22.{}.{}.1:7770", i / 256, i % 256)).unwrap();
+			let meta = SessionMeta {
+				client_version: "x".repeat(10_000),
+				capabilities: (0..1000).map(|v| capability(b"eth", v as u8)).collect(),
+			};
+			table.note_inbound(&id, endpoint, meta);
+			table.update_node(&id, |node| for j in 0..20 {
+				node.note_moved(SocketAddr::from_str(&format!("[2001:db8::{:x}:{:x}]:65535", i, j)).unwrap(), SystemTime::now());
+			}).unwrap();
+		}
+		table.save();
+		let size = ::persistence::encode_table(&persistence.load().unwrap()).unwrap().len();
+		assert!(size < 1000 * 4096, "{} bytes", size);
+	}
+
+	#[test]
+	fn update_node_bookkeeping() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode { attempts: 2, ..FixtureNode::new("1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 4, failures: 1, ..FixtureNode::new("2", "22.99.55.45:7770") },
+		]);
+		let (a, b) = (NodeEndpoint::from_str("22.99.55.44:7770").unwrap(), NodeEndpoint::from_str("22.99.56.44:7770").unwrap());
+		let mut discovery = ::mock_discovery::MockDiscovery::new();
+		discovery.announce(NodeId::from(1), "22.99.56.44:7770").flush(&mut table);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().candidate_endpoint, Some(b.clone()));
+		table.note_failure(&NodeId::from(1));
+		table.dirty = false;
+
+		// an endpoint change is recorded as a move
+		assert_eq!(table.update_node(&NodeId::from(1), |n| { n.endpoint = b.clone(); n.attempts }), Some(2));
+		assert!(table.dirty);
+		{
+			let node = table.get(&NodeId::from(1)).unwrap();
+			assert_eq!(node.candidate_endpoint, None);
+			assert_eq!(node.previous_endpoint, Some(a.clone()));
+			assert_eq!(node.address_history.iter().map(|h| h.0).collect::<Vec<_>>(), vec![a.address]);
+		}
+		assert!(!table.backoff.contains_key(&NodeId::from(1)));
+		table.check_invariants().unwrap();
+
+		// the order follows the new counters, changes of the id and oversized metadata are undone
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(2), NodeId::from(1)]);
+		table.update_node(&NodeId::from(1), |n| {
+			n.failures = 0;
+			n.id = NodeId::from(3);
+			n.client_version = Some("x".repeat(1000));
+		}).unwrap();
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(1), NodeId::from(2)]);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().client_version.as_ref().unwrap().len(), META_LIMITS.client_version);
+		table.check_invariants().unwrap();
+		assert_eq!(table.update_node(&NodeId::from(9), |n| n.attempts), None);
+
+		// changes through `get_mut` are not seen by the table
+		#[allow(deprecated)]
+		{
+			discovery.announce(NodeId::from(2), "22.99.56.45:7770").flush(&mut table);
+			let node = table.get_mut(&NodeId::from(2)).unwrap();
+			node.endpoint = node.candidate_endpoint.clone().unwrap();
+		}
+		assert!(table.check_invariants().unwrap_err().contains("own endpoint as candidate"));
+	}
+
+	#[test]
+	fn note_inbound_unknown_udp_port() {
+		let persistence = MemoryPersistence::new();
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		let id = NodeId::from(1);
+		let tcp_only = NodeEndpoint { address: SocketAddr::from_str("22.99.55.44:30303").unwrap(), udp_port: 0 };
+		let meta = SessionMeta { client_version: "Parity".into(), capabilities: Vec::new() };
+		assert!(!table.note_inbound(&NodeId::from(2), NodeEndpoint { address: SocketAddr::from_str("0.0.0.0:30303").unwrap(), udp_port: 0 }, meta.clone()));
+		assert!(table.note_inbound(&id, tcp_only.clone(), meta));
+
+		// dialable, but not handed to discovery
+		assert_eq!(table.select_for(&DialPolicy::default(), &IpFilter::default(), 10, None), vec![id]);
+		let key = Random.generate().unwrap();
+		let local = NodeEndpoint::from_str("127.0.0.1:40450").unwrap();
+		let mut discovery = Discovery::new(&key, local.address, local.clone(), 0, IpFilter::default());
+		discovery.init_node_list(table.entries_filtered(&IpFilter::default(), usize::max_value()));
+		assert_eq!(discovery.stats().nodes, 0);
+
+		// the unknown port is saved as such
+		table.save();
+		assert_eq!(persistence.nodes()[0].url, format!("enode://{:x}@22.99.55.44:30303?discport=0", id));
+		let loaded = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		assert_eq!(loaded.get(&id).unwrap().endpoint, tcp_only);
+
+		// an unverified announcement is only a candidate, a verified one fills in the port
+		let mut discovery = ::mock_discovery::MockDiscovery::new();
+		discovery.announce(id, "22.99.55.44:30303?discport=30301").flush(&mut table);
+		assert!(!table.get(&id).unwrap().endpoint.udp_known());
+		discovery.confirm(id).flush(&mut table);
+		{
+			let node = table.get(&id).unwrap();
+			assert_eq!(node.endpoint.udp_port, 30301);
+			assert_eq!(node.endpoint.address, tcp_only.address);
+			assert_eq!((node.candidate_endpoint.clone(), node.previous_endpoint.clone()), (None, None));
+			assert!(node.address_history.is_empty());
+		}
+		table.check_invariants().unwrap();
+	}
+
+	#[test]
+	fn endpoint_same_host() {
+		let v4 = NodeEndpoint::from_str("22.99.55.44:30303").unwrap();
+		assert!(v4.same_host(&"22.99.55.44:51234".parse().unwrap()));
+		assert!(v4.same_host(&"[::ffff:22.99.55.44]:51234".parse().unwrap()));
+		assert!(!v4.same_host(&"22.99.55.45:30303".parse().unwrap()));
+		assert!(!v4.same_host(&"[2001:db8::1]:30303".parse().unwrap()));
+
+		let v6 = NodeEndpoint::from_str("[2001:db8::1]:30303").unwrap();
+		assert!(v6.same_host(&"[2001:db8::1]:40000".parse().unwrap()));
+		assert!(!v6.same_host(&"[2001:db8::2]:30303".parse().unwrap()));
+		assert!(!v6.same_host(&"22.99.55.44:30303".parse().unwrap()));
+	}
+
+	#[test]
+	fn match_inbound_by_ip() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode::new("1", "22.99.55.44:30303"),
+			FixtureNode::new("2", "22.99.55.44:30304"),
+			FixtureNode::new("3", "22.99.55.45:30303"),
+		]);
+		assert_eq!(table.find_by_ip(&"22.99.55.44".parse().unwrap()), vec![NodeId::from(1), NodeId::from(2)]);
+
+		let remote = "[::ffff:22.99.55.45]:51234".parse().unwrap();
+		assert_eq!(table.match_inbound(&NodeId::from(3), &remote), InboundMatch::Confirmed);
+		assert_eq!(table.match_inbound(&NodeId::from(3), &"22.99.55.46:51234".parse().unwrap()), InboundMatch::Unknown);
+		assert_eq!(table.stats(&IpFilter::default()).inbound_mismatches, 0);
+
+		// another id at the address of node 3
+		assert_eq!(table.match_inbound(&NodeId::from(4), &remote), InboundMatch::Mismatch(vec![NodeId::from(3)]));
+		assert_eq!(table.stats(&IpFilter::default()).inbound_mismatches, 1);
+	}
+
+	#[test]
+	fn note_inbound_records_node() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let id = NodeId::from(1);
+		let endpoint = NodeEndpoint::from_str("22.99.55.44:30305").unwrap();
+		let meta = SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![capability(b"eth", 63)] };
+		assert!(table.note_inbound(&id, endpoint, meta.clone()));
+
+		{
+			let node = table.get(&id).unwrap();
+			assert_eq!(node.source, NodeSource::Inbound);
+			assert_eq!(node.endpoint.address, SocketAddr::from_str("22.99.55.44:30305").unwrap());
+			assert_eq!(node.client_version, Some(meta.client_version.clone()));
+			assert_eq!(node.capabilities, Some(meta.capabilities.clone()));
+			assert_eq!((node.attempts, node.failures, node.successes), (1, 0, 1));
+			assert!(node.last_contact.is_some());
+		}
+
+		// a known node keeps its endpoint and source
+		let known = NodeId::from(2);
+		let mut node = Node::new(known, NodeEndpoint::from_str("22.99.55.45:30303").unwrap());
+		node.source = NodeSource::Discovery;
+		table.add_node(node);
+		assert!(!table.note_inbound(&known, NodeEndpoint::from_str("22.99.55.46:30303").unwrap(), meta));
+		let node = table.get(&known).unwrap();
+		assert_eq!(node.source, NodeSource::Discovery);
+		assert_eq!(node.endpoint.address, SocketAddr::from_str("22.99.55.45:30303").unwrap());
+		assert_eq!(node.successes, 1);
+	}
+
+	#[test]
+	fn note_outcomes_batch() {
+		let nodes: Vec<FixtureNode> = (1..6).map(|i| FixtureNode { attempts: 3, ..FixtureNode::new(&i.to_string(), &format!("22.99.55.{}:7770", i)) }).collect();
+		let outcomes = vec![
+			(NodeId::from(1), Outcome::Failure),
+			(NodeId::from(2), Outcome::Stalled),
+			(NodeId::from(3), Outcome::Success),
+			(NodeId::from(1), Outcome::Failure),
+			(NodeId::from(9), Outcome::Failure),
+			(NodeId::from(2), Outcome::Success),
+			(NodeId::from(4), Outcome::Stalled),
+			(NodeId::from(10), Outcome::Success),
+		];
+		let clock = ManualClock::new();
+		let mut single = TableState::from_fixture_with(&nodes, NodeTableConfig::default(), &clock);
+		for &(ref id, outcome) in &outcomes {
+			match outcome {
+				Outcome::Success => single.note_success(id),
+				Outcome::Failure => { single.note_failure(id); },
+				Outcome::Stalled => single.note_stalled(id),
+			}
+		}
+		let mut batch = TableState::from_fixture_with(&nodes, NodeTableConfig::default(), &clock);
+		batch.dirty = false;
+		assert_eq!(batch.note_outcomes(outcomes), 2);
+		assert!(batch.dirty);
+		batch.check_invariants().unwrap();
+
+		assert_eq!(batch.to_fixture(), single.to_fixture());
+		assert_eq!(batch.backoff.get(&NodeId::from(1)).map(|b| b.failures), Some(2));
+		assert_eq!(batch.backoff.get(&NodeId::from(1)).map(|b| b.until), single.backoff.get(&NodeId::from(1)).map(|b| b.until));
+		let policy = DialPolicy::default();
+		assert_eq!(batch.select_for(&policy, &IpFilter::default(), 10, None), single.select_for(&policy, &IpFilter::default(), 10, None));
+	}
+
+	#[test]
+	fn note_misbehaviour_and_latency() {
+		let mut table = capabilities_table();
+		let id1 = NodeId::from(1);
+		let id2 = NodeId::from(2);
+
+		table.note_latency(&id1, Duration::from_millis(100));
+		table.note_latency(&id1, Duration::from_millis(500));
+		assert_eq!(table.get(&id1).unwrap().latency, Some(Duration::from_millis(200)));
+
+		table.note_misbehaviour(&id1, Severity::Minor);
+		{
+			let node = table.get(&id1).unwrap();
+			assert_eq!((node.attempts, node.failures), (1, 1));
+		}
+		assert!(!table.is_banned(&id1));
+
+		table.note_misbehaviour(&id2, Severity::Critical);
+		assert!(table.is_banned(&id2));
+		assert!(!table.nodes(&IpFilter::default()).contains(&id2));
+
+		// expired bans are forgotten
+		table.ban(&id1, Duration::from_secs(0), "test");
+		assert!(!table.is_banned(&id1));
+		table.clear_useless();
+		assert_eq!(table.banned.len(), 1);
+	}
+
+	#[test]
+	fn stalled_node_order() {
+		// 0%, 5%, 20% and 50% (no attempts)
+		let mut table = TableState::from_fixture(&[
+			FixtureNode { attempts: 10, ..FixtureNode::new("1", "22.99.55.44:7770") },
+			FixtureNode { attempts: 20, failures: 1, ..FixtureNode::new("2", "22.99.55.44:7770") },
+			FixtureNode { attempts: 10, failures: 2, ..FixtureNode::new("3", "22.99.55.44:7770") },
+			FixtureNode::new("4", "22.99.55.44:7770"),
+		]);
+		let ids: Vec<NodeId> = (1..5).map(NodeId::from).collect();
+		assert_eq!(table.nodes(&IpFilter::default()), ids);
+
+		table.note_stalled(&ids[0]);
+		assert!(table.get(&ids[0]).unwrap().stalled);
+		assert_eq!(table.nodes(&IpFilter::default()), vec![ids[1], ids[0], ids[2], ids[3]]);
+
+		table.note_success(&ids[0]);
+		assert!(!table.get(&ids[0]).unwrap().stalled);
+		assert_eq!(table.nodes(&IpFilter::default()), ids);
+	}
+
+	#[test]
+	fn node_score() {
+		let mut table = capabilities_table();
+		let id = NodeId::from(1);
+		table.update_node(&id, |n| n.attempts = 10).unwrap();
+		assert_eq!(table.score_of(&id), Some(0));
+
+		table.note_latency(&id, Duration::from_millis(250));
+		assert_eq!(table.score_of(&id), Some(5));
+		table.note_stalled(&id);
+		assert_eq!(table.score_of(&id), Some(15));
+
+		table.update_node(&id, |n| n.latency = Some(Duration::from_secs(60))).unwrap();
+		assert_eq!(table.score_of(&id), Some(60));
+		assert_eq!(table.score_of(&NodeId::from(4)), None);
+	}
+
+	#[test]
+	fn table_entries_with_meta() {
+		let mut table = capabilities_table();
+		let id1 = NodeId::from(1);
+		let id2 = NodeId::from(2);
+		let id3 = NodeId::from(3);
+		table.update_node(&id1, |n| n.attempts = 2).unwrap();
+		table.note_failure(&id1);
+		table.update_node(&id2, |n| n.attempts = 1).unwrap();
+		table.note_success(&id2);
+		table.mark_as_useless(&id3, "test");
+		table.ban(&id2, Duration::from_secs(60), "test");
+
+		let entries = table.entries_with_meta(usize::max_value(), false);
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].id, id2);
+		assert_eq!(entries[0].url, format!("enode://{:x}@22.99.55.44:7770", id2));
+		assert_eq!(entries[0].address, SocketAddr::from_str("22.99.55.44:7770").unwrap());
+		assert!(entries[0].banned);
+		assert!(entries[0].last_contact.is_some());
+		assert_eq!(entries[1].id, id1);
+		assert_eq!((entries[1].attempts, entries[1].failures, entries[1].failure_percentage), (2, 1, 50));
+		assert!(!entries[1].banned && !entries[1].useless);
+
+		let entries = table.entries_with_meta(usize::max_value(), true);
+		assert_eq!(entries.len(), 3);
+		assert_eq!(entries[1].id, id3);
+		assert!(entries[1].useless);
+
+		assert_eq!(table.entries_with_meta(1, true).len(), 1);
+	}
+
+	#[test]
+	fn dial_backoff() {
+		let mut table = capabilities_table();
+		let id1 = NodeId::from(1);
+		let policy = DialPolicy::default();
+		let filter = IpFilter::default();
+		table.note_failure(&id1);
+		assert!(!table.select_for(&policy, &filter, usize::max_value(), None).contains(&id1));
+		// still in the table
+		assert!(table.nodes(&filter).contains(&id1));
+
+		table.note_success(&id1);
+		assert!(table.select_for(&policy, &filter, usize::max_value(), None).contains(&id1));
+	}
+
+	#[test]
+	fn retry_now_and_remove_node() {
+		let mut table = capabilities_table();
+		let id1 = NodeId::from(1);
+		let id2 = NodeId::from(2);
+		let id3 = NodeId::from(3);
+		let policy = DialPolicy::default();
+		let filter = IpFilter::default();
+
+		table.note_failure(&id1);
+		table.ban(&id2, Duration::from_secs(60), "test");
+		table.mark_as_useless(&id3, "test");
+		assert!(table.select_for(&policy, &filter, usize::max_value(), None).is_empty());
+
+		for id in &[id1, id2, id3] {
+			assert!(table.retry_now(id));
+		}
+		assert_eq!(table.select_for(&policy, &filter, usize::max_value(), None).len(), 3);
+		assert!(!table.retry_now(&NodeId::from(4)));
+
+		assert!(table.remove_node(&id1));
+		assert!(!table.remove_node(&id1));
+		assert!(!table.contains(&id1));
+	}
+
+	#[test]
+	fn parse_node_ids() {
+		let id = "a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c";
+		let expected = NodeId::from_str(id).unwrap();
+		assert_eq!(parse_node_id(id).unwrap(), expected);
+		assert_eq!(parse_node_id(&format!("0x{}", id)).unwrap(), expected);
+		assert_eq!(parse_node_id(&format!("enode://{}@22.99.55.44:7770", id)).unwrap(), expected);
+		assert!(parse_node_id("a979fb").is_err());
+		assert!(parse_node_id("enode://a979fb@22.99.55.44:7770").is_err());
+	}
+
+	#[test]
+	fn short_ids_and_prefixes() {
+		let id = NodeId::from_str("a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
+		assert_eq!(id.short().to_string(), "a979fb…163c");
+		assert_eq!(format!("{:?}", NodeId::from(1).short()), "000000…0001");
+
+		assert!(id.matches_prefix("a979fb"));
+		assert!(id.matches_prefix("0xA979FB5"));
+		assert!(id.matches_prefix(&format!("{:x}", id)));
+		assert!(!id.matches_prefix("a979fc"));
+		for prefix in &["", "0x", "a979fg", "a979fb…163c"] {
+			assert!(!id.matches_prefix(prefix), "{:?}", prefix);
+		}
+	}
+
+	#[test]
+	fn find_by_prefix() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		for (i, id) in ["a979fb57", "a979fc57", "b979fb57"].iter().enumerate() {
+			let id = NodeId::from_str(&format!("{:0<128}", id)).unwrap();
+			table.add_node(Node::new(id, NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+		}
+		let found = |prefix: &str| table.find_by_prefix(prefix).map(|n| format!("{:x}", n.id)[..8].to_owned());
+		assert_eq!(found("a979fb"), Ok("a979fb57".to_owned()));
+		assert_eq!(found("0xB"), Ok("b979fb57".to_owned()));
+		assert_eq!(found("a979f"), Err(PrefixLookupError::Ambiguous(2)));
+		assert_eq!(found(""), Err(PrefixLookupError::NotFound));
+		assert_eq!(found("c"), Err(PrefixLookupError::NotFound));
+		assert_eq!(PrefixLookupError::Ambiguous(2).to_string(), "2 node ids in the table start with the prefix");
+	}
+
+	#[test]
+	fn parse_enode_strict() {
+		let id = "101b3ef5a4ea7a1c7928e24c4c75fd053c235d7b80c22ae5c03d145d0ac7396e2a4ffff9adee3133a7b05044a5cee08115fd65145e5165d646bde371010d803c";
+		let url = format!("enode://{}@22.99.55.44:7770", id);
+		assert_eq!(parse_enode(&url).unwrap().id, NodeId::from_str(id).unwrap());
+		assert_eq!(normalize_node_url(&url).unwrap(), url);
+		assert!(parse_enode(id).is_err());
+		assert!(parse_enode("22.99.55.44:7770").is_err());
+		assert!(parse_enode(&format!("enode://{:x}@22.99.55.44:7770", NodeId::from(1))).is_err());
+	}
+
+	#[test]
+	fn duplicate_add_keeps_stats() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let id = NodeId::from(1);
+		table.add_node(Node::new(id.clone(), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		table.update_node(&id, |n| n.attempts = 2).unwrap();
+		table.note_failure(&id);
+		table.note_success(&id);
+
+		let mut node = Node::new(id.clone(), NodeEndpoint::from_str("22.99.55.45:7771").unwrap());
+		node.source = NodeSource::Rpc;
+		table.add_node(node);
+		let node = table.get(&id).unwrap();
+		assert_eq!((node.attempts, node.failures, node.successes), (2, 1, 1));
+		assert_eq!(node.endpoint.address, SocketAddr::from_str("22.99.55.45:7771").unwrap());
+	}
+
+	#[test]
+	fn best_enodes() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode { attempts: 2, ..FixtureNode::new("1", "22.99.55.1:7770") },
+			FixtureNode { attempts: 1, ..FixtureNode::new("2", "22.99.55.2:7770") },
+			FixtureNode::new("3", "81.2.3.4:30303"),
+			FixtureNode::new("4", "10.0.0.1:30303"),
+			FixtureNode { useless: true, ..FixtureNode::new("5", "81.2.4.1:30303") },
+			FixtureNode { banned: true, ..FixtureNode::new("6", "81.2.5.1:30303") },
+		]);
+		table.update_node(&NodeId::from(3), |n| n.endpoint.udp_port = 30301).unwrap();
+
+		let enodes = table.best_enodes(10, &IpFilter::default());
+		let expected = vec![
+			format!("enode://{:x}@22.99.55.1:7770", NodeId::from(1)),
+			format!("enode://{:x}@81.2.3.4:30303?discport=30301", NodeId::from(3)),
+			format!("enode://{:x}@22.99.55.2:7770", NodeId::from(2)),
+		];
+		assert_eq!(enodes, expected);
+		assert_eq!(table.best_enodes(2, &IpFilter::default()), expected[..2].to_vec());
+		assert_eq!(table.best_enodes(10, &IpFilter::parse("all -81.2.3.0/24").unwrap()), vec![expected[0].clone(), expected[2].clone()]);
+
+		let node = Node::from_str(&enodes[1]).unwrap();
+		assert_eq!(node.id, NodeId::from(3));
+		assert_eq!(node.endpoint.address, SocketAddr::from_str("81.2.3.4:30303").unwrap());
+		assert_eq!(node.endpoint.udp_port, 30301);
+	}
+
+	#[test]
+	fn export_node_list() {
+		let config = NodeTableConfig { export_min_successes: 2, export_max_failure_bucket: 20, ..Default::default() };
+		let table = TableState::from_fixture_with(&[
+			FixtureNode { attempts: 10, failures: 1, successes: 9, ..FixtureNode::new("1", "22.99.55.1:7770") },
+			FixtureNode { attempts: 2, successes: 2, ..FixtureNode::new("2", "81.2.3.4:30303") },
+			// too few sessions, failing too often, private or useless
+			FixtureNode { attempts: 1, successes: 1, ..FixtureNode::new("3", "22.99.56.1:7770") },
+			FixtureNode { attempts: 10, failures: 3, successes: 7, ..FixtureNode::new("4", "22.99.57.1:7770") },
+			FixtureNode { attempts: 2, successes: 2, ..FixtureNode::new("5", "10.0.0.1:30303") },
+			FixtureNode { attempts: 2, successes: 2, useless: true, ..FixtureNode::new("6", "81.2.4.1:30303") },
+		], config, &ManualClock::new());
+
+		let mut list = Vec::new();
+		assert_eq!(table.export_node_list(&mut list, 10, &IpFilter::default()).unwrap(), 2);
+		let list = String::from_utf8(list).unwrap();
+		let nodes: Vec<Node> = list.lines().map(|l| Node::from_str(l).unwrap()).collect();
+		assert_eq!(nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![NodeId::from(2), NodeId::from(1)]);
+		assert_eq!(nodes[0].endpoint.address, SocketAddr::from_str("81.2.3.4:30303").unwrap());
+		assert!(list.ends_with('\n'));
+
+		let mut list = Vec::new();
+		assert_eq!(table.export_node_list(&mut list, 1, &IpFilter::default()).unwrap(), 1);
+	}
+
+	#[test]
+	fn table_stats() {
+		let mut table = capabilities_table();
+		table.add_node(Node::new(NodeId::from(4), NodeEndpoint::from_str("10.0.0.1:7770").unwrap()));
+		table.note_success(&NodeId::from(1));
+		table.note_failure(&NodeId::from(2));
+		table.mark_as_useless(&NodeId::from(3), "test");
+		table.ban(&NodeId::from(5), Duration::from_secs(60), "test");
+
+		let stats = table.stats(&IpFilter::parse("public").unwrap());
+		let history = table.outcome_history(HISTORY_HOURS);
+		assert_eq!((history[HISTORY_HOURS - 1].successes, history[HISTORY_HOURS - 1].failures), (1, 1));
+		assert_eq!(stats, NodeTableStats { total: 4, useless: 1, banned: 1, backed_off: 1, filtered: 1, contacted: 1, ipv4: 4, tombstones: 0, rejected: 0, history: history, ..Default::default() });
+	}
+
+	#[test]
+	fn table_tombstones() {
+		let mut table = capabilities_table();
+		let id = NodeId::from(1);
+		let banned = NodeId::from(5);
+		let endpoint = NodeEndpoint::from_str("22.99.55.44:7770").unwrap();
+		let updates = || TableUpdates {
+			added: vec![
+				(id.clone(), NodeEntry::new(id.clone(), endpoint.clone())),
+				(banned.clone(), NodeEntry::new(banned.clone(), endpoint.clone())),
+			].into_iter().collect(),
+			removed: HashSet::new(),
+			verified: HashSet::new(),
+		};
+
+		assert!(table.remove_node(&id));
+		table.ban(&banned, Duration::from_secs(60), "test");
+		table.update(updates(), &HashSet::new());
+		assert!(!table.contains(&id));
+		assert!(!table.contains(&banned));
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.tombstones, stats.tombstone_rejected, stats.ban_rejected), (1, 1, 1));
+
+		// explicit additions clear the tombstone
+		let mut node = Node::new(id.clone(), endpoint.clone());
+		node.source = NodeSource::Rpc;
+		table.add_node(node);
+		assert!(table.contains(&id));
+		assert_eq!(table.stats(&IpFilter::default()).tombstones, 0);
+
+		// tombstones expire after the grace period
+		let config = NodeTableConfig { tombstone_grace: Duration::from_secs(0), ..Default::default() };
+		let mut table = TableState::new(config);
+		table.remove_node(&id);
+		table.update(updates(), &HashSet::new());
+		assert!(table.contains(&id));
+		table.clear_useless();
+		assert!(table.tombstones.is_empty());
+	}
+
+	#[test]
+	#[cfg(feature = "metrics")]
+	fn table_render_prometheus() {
+		let nodes = [
+			FixtureNode { attempts: 2, failures: 2, required: true, ..FixtureNode::new("a1", "22.99.55.1:7770") },
+			FixtureNode { attempts: 4, failures: 1, ..FixtureNode::new("b1", "22.99.55.2:7770") },
+			FixtureNode { attempts: 1, useless: true, ..FixtureNode::new("c1", "22.99.56.1:7770") },
+			FixtureNode { banned: true, ..FixtureNode::new("d1", "22.99.57.1:7770") },
+			// over the subnet limit
+			FixtureNode::new("e1", "22.99.55.3:7770"),
+			FixtureNode::new("f1", "22.99.58.1:7770"),
+		];
+		let config = NodeTableConfig { max_nodes_per_subnet: Some(2), ..Default::default() };
+		let mut table = NodeTable::from_fixture_with(&nodes, config, &ManualClock::new());
+		table.add_node(Node::new(NodeId::new(), NodeEndpoint::from_str("22.99.59.1:7770").unwrap()));
+		let removed = FixtureNode::new("f1", "").node_id();
+		table.remove_node(&removed);
+		let banned = FixtureNode::new("d1", "").node_id();
+		let updates = TableUpdates {
+			added: [removed, banned].iter()
+				.map(|id| (id.clone(), NodeEntry::new(id.clone(), NodeEndpoint::from_str("22.99.60.1:7770").unwrap())))
+				.collect(),
+			removed: HashSet::new(),
+			verified: HashSet::new(),
+		};
+		table.update(updates, &HashSet::new());
+
+		table.persistence = Some(Arc::new(MemoryPersistence::new()));
+		table.set_persistent(true);
+		table.save();
+		let tempdir = TempDir::new("").unwrap();
+		let not_a_dir = tempdir.path().join("file");
+		fs::File::create(&not_a_dir).unwrap();
+		table.persistence = Some(Arc::new(FilePersistence::new(not_a_dir)));
+		table.set_persistent(true);
+		table.save();
+
+		let mut metrics = String::new();
+		table.render_prometheus(&mut metrics);
+		assert_eq!(metrics, r#"# HELP devp2p_node_table_nodes Number of known nodes.
+# TYPE devp2p_node_table_nodes gauge
+devp2p_node_table_nodes 4
+# HELP devp2p_node_table_useless_nodes Nodes marked as useless.
+# TYPE devp2p_node_table_useless_nodes gauge
+devp2p_node_table_useless_nodes 1
+# HELP devp2p_node_table_banned_nodes Active bans, including nodes not in the table.
+# TYPE devp2p_node_table_banned_nodes gauge
+devp2p_node_table_banned_nodes 1
+# HELP devp2p_node_table_reserved_nodes Nodes required globally or for a protocol.
+# TYPE devp2p_node_table_reserved_nodes gauge
+devp2p_node_table_reserved_nodes 1
+# HELP devp2p_node_table_failure_percentage Failure percentage of known nodes.
+# TYPE devp2p_node_table_failure_percentage histogram
+devp2p_node_table_failure_percentage_bucket{le="0"} 1
+devp2p_node_table_failure_percentage_bucket{le="10"} 1
+devp2p_node_table_failure_percentage_bucket{le="25"} 2
+devp2p_node_table_failure_percentage_bucket{le="50"} 3
+devp2p_node_table_failure_percentage_bucket{le="75"} 3
+devp2p_node_table_failure_percentage_bucket{le="90"} 3
+devp2p_node_table_failure_percentage_bucket{le="+Inf"} 4
+devp2p_node_table_failure_percentage_sum 175
+devp2p_node_table_failure_percentage_count 4
+# HELP devp2p_node_table_rejected_total Nodes not added to the table, by cause.
+# TYPE devp2p_node_table_rejected_total counter
+devp2p_node_table_rejected_total{cause="own"} 1
+devp2p_node_table_rejected_total{cause="subnet"} 1
+devp2p_node_table_rejected_total{cause="port"} 0
+devp2p_node_table_rejected_total{cause="tombstone"} 1
+devp2p_node_table_rejected_total{cause="banned"} 1
+# HELP devp2p_node_table_saves_total Node table saves, by result.
+# TYPE devp2p_node_table_saves_total counter
+devp2p_node_table_saves_total{result="ok"} 1
+devp2p_node_table_saves_total{result="error"} 1
+"#);
+	}
 
 	#[test]
-	fn endpoint_parse() {
-		let endpoint = NodeEndpoint::from_str("123.99.55.44:7770");
-		assert!(endpoint.is_ok());
-		let v4 = match endpoint.unwrap().address {
-			SocketAddr::V4(v4address) => v4address,
-			_ => panic!("should ve v4 address")
+	fn table_address_history_bounds() {
+		let clock = ManualClock::new();
+		let mut table = TableState::from_fixture_with(&[], NodeTableConfig::default(), &clock);
+		let id = NodeId::from(1);
+		let address = |i: usize| format!("22.99.{}.1:7770", i);
+		for i in 0..12 {
+			table.add_node(Node::new(id.clone(), NodeEndpoint::from_str(&address(i)).unwrap()));
+			clock.advance(Duration::from_secs(60));
+		}
+		// adding the node at its current endpoint is not a move
+		table.add_node(Node::new(id.clone(), NodeEndpoint::from_str(&address(11)).unwrap()));
+		table.check_invariants().unwrap();
+
+		let entry = table.entries_with_meta(1, true).remove(0);
+		let history: Vec<String> = entry.address_history.iter().map(|&(a, _)| a.to_string()).collect();
+		assert_eq!(history, (3..11).rev().map(address).collect::<Vec<_>>());
+		assert!(entry.address_history.windows(2).all(|w| w[0].1 > w[1].1));
+		assert_eq!(table.to_fixture()[0].address_history, history);
+
+		// saved with the node, loading keeps at most `META_LIMITS.address_history` entries
+		let mut persisted = PersistedNode::from(table.nodes.get(&id).unwrap());
+		let loaded = persisted.clone().into_node().unwrap();
+		assert_eq!(loaded.address_history.iter().map(|&(a, _)| a.to_string()).collect::<Vec<_>>(), history);
+		persisted.address_history = (0..12).map(|i| (address(i), 1_500_000_000)).collect();
+		assert_eq!(persisted.into_node().unwrap().address_history.len(), META_LIMITS.address_history);
+	}
+
+	#[test]
+	fn table_ids_seen_on() {
+		let mut table = TableState::from_fixture(&[
+			FixtureNode { address_history: vec!["22.99.55.44:7770".into()], ..FixtureNode::new("a1", "22.99.55.45:7770") },
+			FixtureNode::new("b1", "22.99.55.44:30303"),
+			FixtureNode::new("c1", "22.99.55.46:7770"),
+		]);
+		let ip = |s: &str| IpAddr::from_str(s).unwrap();
+		assert_eq!(table.ids_seen_on(&ip("22.99.55.44")), fixture_ids(&["a1", "b1"]));
+		assert_eq!(table.ids_seen_on(&ip("22.99.55.45")), fixture_ids(&["a1"]));
+		assert_eq!(table.ids_seen_on(&ip("22.99.55.47")), vec![]);
+
+		// moves are recorded, removed nodes are forgotten
+		let c1 = fixture_ids(&["c1"]).remove(0);
+		table.add_node(Node::new(c1, NodeEndpoint::from_str("22.99.55.44:7771").unwrap()));
+		assert_eq!(table.ids_seen_on(&ip("22.99.55.46")), fixture_ids(&["c1"]));
+		table.remove_node(&fixture_ids(&["a1"])[0]);
+		assert_eq!(table.ids_seen_on(&ip("22.99.55.44")), fixture_ids(&["b1", "c1"]));
+	}
+
+	#[test]
+	fn table_endpoint_change() {
+		let mut table = capabilities_table();
+		let old = NodeEndpoint::from_str("22.99.55.44:7770").unwrap();
+		let new = NodeEndpoint::from_str("22.99.55.45:7770").unwrap();
+		let update = |id: u64, verified: bool| {
+			let id = NodeId::from(id);
+			TableUpdates {
+				added: vec![(id.clone(), NodeEntry::new(id.clone(), new.clone()))].into_iter().collect(),
+				removed: HashSet::new(),
+				verified: if verified { vec![id].into_iter().collect() } else { HashSet::new() },
+			}
 		};
-		assert_eq!(SocketAddrV4::new(Ipv4Addr::new(123, 99, 55, 44), 7770), v4);
+
+		// an unverified change is kept as a candidate
+		table.update(update(1, false), &HashSet::new());
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().endpoint, old);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().candidate_endpoint, Some(new.clone()));
+		let entry = table.entries_with_meta(10, false).into_iter().find(|e| e.id == NodeId::from(1)).unwrap();
+		assert_eq!(entry.candidate_address, Some(new.address));
+
+		// a verified one is accepted
+		table.update(update(1, true), &HashSet::new());
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().endpoint, new);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().candidate_endpoint, None);
+
+		// so is an unverified one once the current endpoint keeps failing
+		for _ in 1..ENDPOINT_CHANGE_FAILURES {
+			table.note_failure(&NodeId::from(2));
+		}
+		table.update(update(2, false), &HashSet::new());
+		assert_eq!(table.get(&NodeId::from(2)).unwrap().endpoint, old);
+		table.note_failure(&NodeId::from(2));
+		table.update(update(2, false), &HashSet::new());
+		assert_eq!(table.get(&NodeId::from(2)).unwrap().endpoint, new);
+
+		// required nodes keep their endpoint
+		table.update_node(&NodeId::from(3), |n| n.peer_type = PeerType::Required).unwrap();
+		table.update(update(3, true), &HashSet::new());
+		assert_eq!(table.get(&NodeId::from(3)).unwrap().endpoint, old);
+		assert_eq!(table.get(&NodeId::from(3)).unwrap().candidate_endpoint, Some(new.clone()));
 	}
 
 	#[test]
-	fn node_parse() {
-		assert!(validate_node_url("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").is_none());
-		let node = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770");
-		assert!(node.is_ok());
-		let node = node.unwrap();
-		let v4 = match node.endpoint.address {
-			SocketAddr::V4(v4address) => v4address,
-			_ => panic!("should ve v4 address")
+	fn trusted_nodes_skip_admission_and_subnet_limit() {
+		let node = |id: u64, address: &str| Node::new(NodeId::from(id), NodeEndpoint::from_str(address).unwrap());
+
+		// refused by the admission hook
+		let mut table = TableState::from_fixture(&[]);
+		table.set_admission_hook(Box::new(|_: &Node, _| Admission::Reject { reason: "all".into() }));
+		table.add_node(node(1, "22.99.55.1:7770"));
+		table.ingest(NodeOrigin::Trusted, node(2, "22.99.55.2:7770"));
+		assert!(!table.contains(&NodeId::from(1)) && table.is_trusted(&NodeId::from(2)));
+		assert_eq!(table.admission_rejected.values().sum::<usize>(), 1);
+
+		// over the subnet limit
+		let config = NodeTableConfig { max_nodes_per_subnet: Some(1), ..Default::default() };
+		let mut table = TableState::from_fixture_with(&[FixtureNode::new("1", "22.99.55.1:7770")], config, &ManualClock::new());
+		table.ingest(NodeOrigin::Trusted, node(2, "22.99.55.2:7770"));
+		table.add_node(node(3, "22.99.55.3:7770"));
+		assert!(table.contains(&NodeId::from(2)) && !table.contains(&NodeId::from(3)));
+		assert_eq!(table.subnet_rejected, 1);
+
+		// trusted is not required, nor the other way round
+		assert_eq!(table.get(&NodeId::from(2)).unwrap().peer_type, PeerType::Optional);
+		assert!(!table.is_required(&NodeId::from(2)));
+		table.ingest(NodeOrigin::Reserved, node(1, "22.99.55.1:7770"));
+		assert!(table.is_required(&NodeId::from(1)) && !table.is_trusted(&NodeId::from(1)));
+		assert!(table.set_trusted(&NodeId::from(1), true));
+		assert!(table.is_required(&NodeId::from(1)) && table.is_trusted(&NodeId::from(1)));
+		assert!(!table.set_trusted(&NodeId::from(3), true));
+	}
+
+	#[test]
+	fn trusted_announcements_taken_at_face_value() {
+		use mock_discovery::{MockDiscovery, assert_endpoint};
+
+		let (trusted, other) = (NodeId::from(1), NodeId::from(2));
+		let config = NodeTableConfig { max_update_added: 2, ..Default::default() };
+		let fixture = [FixtureNode::new("1", "22.99.55.1:7770"), FixtureNode::new("2", "22.99.55.2:7770")];
+		let mut table = TableState::from_fixture_with(&fixture, config, &ManualClock::new());
+		let generation = table.generation;
+		assert!(table.set_trusted(&trusted, true));
+		assert!(table.generation > generation);
+		let mut discovery = MockDiscovery::new();
+
+		// another node announcing it at a new endpoint is not trusted, the endpoint is only a candidate
+		discovery.announce(trusted, "22.99.56.1:7770").announce(other, "22.99.56.2:7770").flush(&mut table);
+		assert_endpoint(&table, &trusted, "22.99.55.1:7770", Some("22.99.56.1:7770"));
+		assert_endpoint(&table, &other, "22.99.55.2:7770", Some("22.99.56.2:7770"));
+
+		// the endpoint it verified itself is applied right away, also if it is required
+		table.ingest(NodeOrigin::Reserved, Node::new(trusted, NodeEndpoint::from_str("22.99.55.1:7770").unwrap()));
+		discovery.announce(trusted, "22.99.57.1:7770").flush(&mut table);
+		assert_endpoint(&table, &trusted, "22.99.55.1:7770", Some("22.99.57.1:7770"));
+		discovery.confirm(trusted).flush(&mut table);
+		assert_endpoint(&table, &trusted, "22.99.57.1:7770", None);
+		table.update_node(&trusted, |n| n.peer_type = PeerType::Optional).unwrap();
+
+		// not counted against the update limit
+		discovery.announce_range(10, 3).announce(trusted, "22.99.58.1:7770").confirm(trusted);
+		let outcome = discovery.flush(&mut table);
+		assert_eq!((outcome.added, outcome.truncated_added), (2, 1));
+		assert_endpoint(&table, &trusted, "22.99.58.1:7770", None);
+
+		// not removed when discovery drops it, its connection stats are still recorded
+		discovery.expire(trusted).expire(other).flush(&mut table);
+		assert!(table.contains(&trusted) && !table.contains(&other));
+		table.note_failure(&trusted);
+		assert_eq!(table.get(&trusted).unwrap().failures, 1);
+
+		// a required node is pinned again once no longer trusted
+		assert!(table.set_trusted(&trusted, false));
+		table.ingest(NodeOrigin::Reserved, Node::new(trusted, NodeEndpoint::from_str("22.99.58.1:7770").unwrap()));
+		discovery.announce(trusted, "22.99.59.1:7770").confirm(trusted).flush(&mut table);
+		assert_endpoint(&table, &trusted, "22.99.58.1:7770", Some("22.99.59.1:7770"));
+	}
+
+	#[test]
+	fn table_identity_changes() {
+		let a = NodeEndpoint::from_str("22.99.55.44:7770").unwrap();
+		let b = NodeEndpoint::from_str("22.99.55.45:7770").unwrap();
+		let seeded = || {
+			let mut table = TableState::new(NodeTableConfig::default());
+			table.add_node(Node::new(NodeId::from(1), a.clone()));
+			table.update_node(&NodeId::from(1), |n| n.attempts = 2).unwrap();
+			table.note_success(&NodeId::from(1));
+			table.note_failure(&NodeId::from(1));
+			table
 		};
-		assert_eq!(SocketAddrV4::new(Ipv4Addr::new(22, 99, 55, 44), 7770), v4);
-		assert_eq!(
-			H512::from_str("a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap(),
-			node.id);
+		let discovered = |id: u64, endpoint: &NodeEndpoint| {
+			let id = NodeId::from(id);
+			TableUpdates {
+				added: vec![(id.clone(), NodeEntry::new(id.clone(), endpoint.clone()))].into_iter().collect(),
+				removed: HashSet::new(),
+				verified: vec![id].into_iter().collect(),
+			}
+		};
+
+		// same id, same endpoint
+		let mut table = seeded();
+		table.add_node(Node::new(NodeId::from(1), a.clone()));
+		{
+			let node = table.get(&NodeId::from(1)).unwrap();
+			assert_eq!((node.attempts, node.failures, node.successes), (2, 1, 1));
+			assert_eq!(node.previous_endpoint, None);
+		}
+		assert!(table.backoff.contains_key(&NodeId::from(1)));
+
+		// same id, new endpoint
+		let mut table = seeded();
+		table.add_node(Node::new(NodeId::from(1), b.clone()));
+		{
+			let node = table.get(&NodeId::from(1)).unwrap();
+			assert_eq!(node.endpoint, b);
+			assert_eq!((node.attempts, node.failures, node.successes), (2, 1, 1));
+			assert_eq!(node.previous_endpoint, Some(a.clone()));
+		}
+		assert!(!table.backoff.contains_key(&NodeId::from(1)));
+		let entry = table.entries_with_meta(10, false).into_iter().next().unwrap();
+		assert_eq!(entry.previous_address, Some(a.address));
+
+		// new id, known endpoint: only a verified discovery update transfers the stats
+		let mut table = seeded();
+		table.add_node(Node::new(NodeId::from(2), a.clone()));
+		assert!(table.get(&NodeId::from(1)).is_some());
+		table.remove_node(&NodeId::from(2));
+		table.update(discovered(3, &a), &HashSet::new());
+		assert!(table.get(&NodeId::from(1)).is_none());
+		{
+			let node = table.get(&NodeId::from(3)).unwrap();
+			assert_eq!((node.attempts, node.failures, node.successes), (2, 1, 1));
+			assert!(node.last_contact.is_some());
+		}
+
+		// new id, new endpoint
+		let mut table = seeded();
+		table.update(discovered(2, &b), &HashSet::new());
+		assert_eq!(table.get(&NodeId::from(2)).unwrap().attempts, 0);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().attempts, 2);
+
+		// transfer_stats adds up the counters
+		let mut table = seeded();
+		table.add_node(Node::new(NodeId::from(2), b.clone()));
+		table.update_node(&NodeId::from(2), |n| n.attempts = 1).unwrap();
+		table.note_failure(&NodeId::from(2));
+		assert!(table.transfer_stats(&NodeId::from(1), &NodeId::from(2)));
+		let node = table.get(&NodeId::from(2)).unwrap();
+		assert_eq!((node.attempts, node.failures, node.successes), (3, 2, 1));
+		assert!(!table.transfer_stats(&NodeId::from(1), &NodeId::from(9)));
 	}
 
 	#[test]
-	fn table_failure_percentage_order() {
-		let node1 = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
-		let node2 = Node::from_str("enode://b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
-		let node3 = Node::from_str("enode://c979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
-		let node4 = Node::from_str("enode://d979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
-		let id1 = H512::from_str("a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
-		let id2 = H512::from_str("b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
-		let id3 = H512::from_str("c979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
-		let id4 = H512::from_str("d979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
-		let mut table = NodeTable::new(None);
+	fn table_flags_mismatched_ids() {
+		let a = NodeEndpoint::from_str("22.99.55.44:30303").unwrap();
+		let b = NodeEndpoint::from_str("22.99.55.45:30303").unwrap();
+		let mut table = TableState::new(NodeTableConfig::default());
+		table.add_node(Node::new(NodeId::from(1), a.clone()));
+		table.update_node(&NodeId::from(1), |n| n.attempts = 3).unwrap();
+		table.note_success(&NodeId::from(1));
+		table.add_node(Node::new(NodeId::from(2), b.clone()));
 
-		table.add_node(node1);
-		table.add_node(node2);
-		table.add_node(node3);
-		table.add_node(node4);
+		// the dial of 1 announced the known id 2: neither entry moves, nothing is added or tombstoned
+		assert!(table.note_id_mismatch(&NodeId::from(1), NodeId::from(2)));
+		assert!(table.note_id_mismatch(&NodeId::from(1), NodeId::from(3)));
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(1), NodeId::from(2)]);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().endpoint, a);
+		assert_eq!(table.get(&NodeId::from(1)).unwrap().successes, 1);
+		assert_eq!(table.get(&NodeId::from(2)).unwrap().endpoint, b);
+		assert!(!table.is_tombstoned(&NodeId::from(1)));
+		assert_eq!(table.entry_status(&NodeId::from(1)).unwrap().mismatched_id, Some(NodeId::from(3)));
+		assert!(!table.note_id_mismatch(&NodeId::from(4), NodeId::from(1)));
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.id_mismatches, stats.flagged_mismatches), (2, 1));
 
-		// node 1 - failure percentage 100%
-		table.get_mut(&id1).unwrap().attempts = 2;
-		table.note_failure(&id1);
-		table.note_failure(&id1);
+		// the same for reserved nodes, once however often it happens
+		table.ingest(NodeOrigin::Reserved, Node::new(NodeId::from(5), NodeEndpoint::from_str("22.99.55.46:30303").unwrap()));
+		for _ in 0..3 {
+			assert!(table.note_id_mismatch(&NodeId::from(5), NodeId::from(2)));
+		}
+		assert!(table.is_required(&NodeId::from(5)));
+		let stats = table.stats(&IpFilter::default());
+		assert_eq!((stats.id_mismatches, stats.flagged_mismatches), (5, 2));
+
+		// cleared once the node answers with its id again
+		table.note_success(&NodeId::from(1));
+		assert_eq!(table.entry_status(&NodeId::from(1)).unwrap().mismatched_id, None);
+		assert_eq!(table.stats(&IpFilter::default()).flagged_mismatches, 1);
+		table.check_invariants().unwrap();
+	}
+
+	#[test]
+	fn note_results() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let endpoint = NodeEndpoint::from_str("22.99.55.44:30303").unwrap();
+		table.add_node(Node::new(NodeId::from(1), endpoint.clone()));
+		table.ingest(NodeOrigin::Reserved, Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.45:30303").unwrap()));
+
+		assert_eq!(table.note_failure(&NodeId::from(1)), NoteResult::Applied);
+		assert_eq!(table.note_failure(&NodeId::from(3)), NoteResult::UnknownNode);
+		assert_eq!(table.mark_as_useless(&NodeId::from(1), "test"), NoteResult::Applied);
+		assert_eq!(table.mark_as_useless(&NodeId::from(2), "test"), NoteResult::Required);
+		assert_eq!(table.entry_status(&NodeId::from(2)).unwrap().useless_reason, None);
+
+		// a node marked before it is added is useless once it is
+		assert_eq!(table.mark_as_useless(&NodeId::from(3), "test"), NoteResult::Tracked);
+		table.add_node(Node::new(NodeId::from(3), NodeEndpoint::from_str("22.99.55.46:30303").unwrap()));
+		assert_eq!(table.entry_status(&NodeId::from(3)).unwrap().useless_reason, Some("test".to_owned()));
+		table.check_invariants().unwrap();
+	}
+
+	#[test]
+	fn unknown_useless_marks_capped() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		for i in 0..MAX_UNKNOWN_USELESS as u64 {
+			assert_eq!(table.mark_as_useless(&NodeId::from(i + 10), "test"), NoteResult::Tracked);
+		}
+		assert_eq!(table.mark_as_useless(&NodeId::from(1), "test"), NoteResult::UnknownNode);
+		// marking a tracked node again is not refused, nodes in the table are not limited
+		assert_eq!(table.mark_as_useless(&NodeId::from(10), "other"), NoteResult::Tracked);
+		table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:30303").unwrap()));
+		assert_eq!(table.mark_as_useless(&NodeId::from(1), "test"), NoteResult::Applied);
+		table.check_invariants().unwrap();
 
-		// node2 - failure percentage 33%
-		table.get_mut(&id2).unwrap().attempts = 3;
-		table.note_failure(&id2);
+		table.useless_nodes.insert(NodeId::from(2), Exclusion { time: Instant::now(), reason: "test".into(), epoch: 0, repeats: 0 });
+		assert!(table.check_invariants().is_err());
+		table.clear_useless();
+		assert_eq!(table.mark_as_useless(&NodeId::from(2), "test"), NoteResult::Tracked);
+	}
+
+	#[test]
+	fn table_keeps_hourly_outcomes() {
+		let clock = ManualClock::new();
+		let nodes = [FixtureNode::new("a1", "22.99.55.44:7770"), FixtureNode::new("b1", "22.99.55.45:7770")];
+		let mut table = TableState::from_fixture_with(&nodes, NodeTableConfig::default(), &clock);
+		let (a, b) = (nodes[0].node_id(), nodes[1].node_id());
+		// start at the beginning of an hour
+		let into_hour = clock.now_system().duration_since(UNIX_EPOCH).unwrap().as_secs() % 3600;
+		clock.advance(Duration::from_secs(3600 - into_hour));
 
-		// node3 - failure percentage 0%
-		table.get_mut(&id3).unwrap().attempts = 1;
+		table.note_dial(&a);
+		table.note_success(&a);
+		table.note_dial(&b);
+		table.note_dial(&NodeId::from(3));
+		clock.advance(Duration::from_secs(3599));
+		table.note_failure(&b);
+		table.note_stalled(&a);
 
-		// node4 - failure percentage 50% (default when no attempts)
+		// the next hour starts a new bucket, failures during an outage are told apart
+		clock.advance(Duration::from_secs(1));
+		table.note_dial(&b);
+		table.note_global_outage_suspected();
+		table.note_failure(&b);
 
-		let r = table.nodes(IpFilter::default());
+		let history = table.outcome_history(3);
+		assert_eq!(history.len(), 3);
+		assert_eq!(history[2].start - history[1].start, 3600);
+		assert_eq!(history[0], OutcomeBucket { start: history[1].start - 3600, ..Default::default() });
+		assert_eq!(history[1], OutcomeBucket { start: history[1].start, attempts: 2, successes: 1, failures: 1, stalled: 1, quarantined: 0 });
+		assert_eq!(history[2], OutcomeBucket { start: history[2].start, attempts: 1, quarantined: 1, ..Default::default() });
+		assert_eq!(table.stats(&IpFilter::default()).history.len(), HISTORY_HOURS);
 
-		assert_eq!(r[0][..], id3[..]);
-		assert_eq!(r[1][..], id2[..]);
-		assert_eq!(r[2][..], id4[..]);
-		assert_eq!(r[3][..], id1[..]);
+		// a day later the hours rolled out
+		clock.advance(Duration::from_secs(24 * 3600));
+		assert!(table.outcome_history(HISTORY_HOURS).iter().all(|bucket| bucket.attempts == 0));
 	}
 
 	#[test]
-	fn table_save_load() {
-		let tempdir = TempDir::new("").unwrap();
-		let node1 = Node::from_str("enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
-		let node2 = Node::from_str("enode://b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770").unwrap();
-		let id1 = H512::from_str("a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
-		let id2 = H512::from_str("b979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c").unwrap();
+	fn outcome_history_persisted() {
+		let persistence = MemoryPersistence::new();
+		let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:30303").unwrap()));
+		table.note_dial(&NodeId::from(1));
+		table.note_success(&NodeId::from(1));
+		table.save();
+
+		let loaded = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		let current = *loaded.outcome_history(1).last().unwrap();
+		assert_eq!((current.attempts, current.successes), (1, 1));
+
+		// not saved when disabled
+		let config = NodeTableConfig { save_outcome_history: false, ..Default::default() };
+		let table = NodeTable::with_persistence(Box::new(persistence.clone()), config);
+		assert!(table.to_persisted().history.is_empty());
+	}
+
+	#[test]
+	fn table_rejects_own_and_zero_ids() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let local = NodeId::from(10);
+		let endpoint = NodeEndpoint::from_str("22.99.55.44:7770").unwrap();
+		let public = NodeEndpoint::from_str("22.99.55.50:30303").unwrap();
+		table.set_local_id(local.clone());
+		table.set_local_endpoints(vec![public.clone()]);
+
+		table.add_node(Node::new(NodeId::new(), endpoint.clone()));
+		table.add_node(Node::new(local.clone(), endpoint.clone()));
+		table.add_node(Node::new(NodeId::from(1), public.clone()));
+		let updates = TableUpdates {
+			added: vec![
+				(local.clone(), NodeEntry::new(local.clone(), endpoint.clone())),
+				(NodeId::from(2), NodeEntry::new(NodeId::from(2), public.clone())),
+				(NodeId::from(3), NodeEntry::new(NodeId::from(3), endpoint.clone())),
+			].into_iter().collect(),
+			removed: HashSet::new(),
+			verified: HashSet::new(),
+		};
+		table.update(updates, &HashSet::new());
+		let meta = SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![] };
+		assert!(!table.note_inbound(&NodeId::new(), endpoint.clone(), meta));
+
+		assert_eq!(table.nodes(&IpFilter::default()), vec![NodeId::from(3)]);
+		assert_eq!(table.stats(&IpFilter::default()).rejected, 6);
+	}
+
+	/// Table loaded from `persistence` on the time of `clock`.
+	fn warm_table(persistence: &MemoryPersistence, config: NodeTableConfig, clock: &ManualClock) -> NodeTable {
+		let mut table = NodeTable::with_backend(Some(Arc::new(persistence.clone())), false, config);
+		table.set_clock(Box::new(clock.clone()));
+		table.load_saved();
+		table.set_persistent(true);
+		table
+	}
+
+	#[test]
+	fn warm_flag_lifecycle() {
+		let persistence = MemoryPersistence::new();
+		let clock = ManualClock::new();
+		let all = IpFilter::default();
 		{
-			let mut table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
-			table.add_node(node1);
-			table.add_node(node2);
+			let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+			for i in 1..6 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i)).unwrap()));
+			}
+			table.note_stalled(&NodeId::from(3));
+			table.ban(&NodeId::from(4), Duration::from_secs(60), "test");
+			// no warm start without warm nodes
+			assert!(table.warm_entries(&all).is_empty());
+			table.mark_warm(&[NodeId::from(1), NodeId::from(2), NodeId::from(3), NodeId::from(4), NodeId::from(6)]);
+		}
+		let warm: Vec<_> = persistence.nodes().into_iter().filter(|n| n.warm).map(|n| n.url).collect();
+		assert_eq!(warm.len(), 2);
 
-			table.get_mut(&id1).unwrap().attempts = 1;
-			table.get_mut(&id2).unwrap().attempts = 1;
-			table.note_failure(&id2);
+		let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+		let mut warm = table.warm_entries(&all);
+		warm.sort();
+		assert_eq!(warm, vec![NodeId::from(1), NodeId::from(2)]);
+		// filters still apply
+		let filter = IpFilter { predefined: AllowIP::None, custom_allow: vec!["22.99.55.2/32".parse().unwrap()], custom_block: vec![] };
+		assert_eq!(table.warm_entries(&filter), vec![NodeId::from(2)]);
+
+		// cleared by the first dial whatever the outcome, also on disk
+		assert_eq!(table.note_dial(&NodeId::from(1)), Some("22.99.55.1:30303".parse().unwrap()));
+		table.note_failure(&NodeId::from(1));
+		assert_eq!(table.warm_entries(&all), vec![NodeId::from(2)]);
+		assert_eq!(table.note_dial(&NodeId::from(7)), None);
+		table.save();
+		assert_eq!(persistence.nodes().iter().filter(|n| n.warm).count(), 1);
+	}
+
+	#[test]
+	fn warm_start_window() {
+		let persistence = MemoryPersistence::new();
+		let clock = ManualClock::new();
+		let all = IpFilter::default();
+		{
+			let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+			table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:30303").unwrap()));
+			table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.45:30303").unwrap()));
+			for _ in 0..3 {
+				table.note_failure(&NodeId::from(2));
+			}
+			table.mark_warm(&[NodeId::from(2)]);
 		}
+		let config = NodeTableConfig { warm_start: Duration::from_secs(60), ..Default::default() };
+
+		// dialled first in spite of its rank, until the window ends
+		let table = warm_table(&persistence, config.clone(), &clock);
+		assert_eq!(table.select_for(&DialPolicy::default(), &all, 10, None), vec![NodeId::from(1), NodeId::from(2)]);
+		assert_eq!(table.warm_entries(&all), vec![NodeId::from(2)]);
+		clock.advance(Duration::from_secs(59));
+		assert_eq!(table.warm_entries(&all), vec![NodeId::from(2)]);
+		clock.advance(Duration::from_secs(1));
+		assert!(table.warm_entries(&all).is_empty());
+		assert!(table.get(&NodeId::from(2)).unwrap().warm);
+		drop(table);
+
+		// disabled
+		let table = warm_table(&persistence, NodeTableConfig { warm_start: Duration::from_secs(0), ..config }, &clock);
+		assert!(table.warm_entries(&all).is_empty());
+	}
 
+	#[test]
+	fn stale_file_ordered_by_reliability() {
+		let persistence = MemoryPersistence::new();
+		let clock = ManualClock::new();
+		let all = IpFilter::default();
+		let endpoint = |i: u64| NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i)).unwrap();
 		{
-			let table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
-			let r = table.nodes(IpFilter::default());
-			assert_eq!(r[0][..], id1[..]);
-			assert_eq!(r[1][..], id2[..]);
+			let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+			for i in 1..4 {
+				table.add_node(Node::new(NodeId::from(i), endpoint(i)));
+			}
+			// 1 never failed but never connected, 2 failed a few times in many sessions
+			table.update_node(&NodeId::from(1), |n| n.attempts = 1);
+			table.update_node(&NodeId::from(2), |n| { n.attempts = 20; n.failures = 2; });
+			for _ in 0..18 {
+				table.note_success(&NodeId::from(2));
+			}
+			table.mark_warm(&[NodeId::from(3)]);
+		}
+		let saved: Vec<_> = persistence.nodes().into_iter().map(|n| n.reliability).collect();
+		assert_eq!(saved, vec![Some(127), Some(235), Some(63)]);
+
+		// saved a week and a day ago: ordered by reliability, no warm start, the nodes are pinged again
+		clock.advance(Duration::from_secs(8 * 24 * 60 * 60));
+		let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+		assert_eq!(table.nodes(&all), vec![NodeId::from(2), NodeId::from(1), NodeId::from(3)]);
+		assert!(table.warm_entries(&all).is_empty());
+		let sweep: Vec<_> = table.reverify_sweep(&all).into_iter().map(|e| e.id).collect();
+		assert_eq!(sweep, vec![NodeId::from(2), NodeId::from(1), NodeId::from(3)]);
+
+		// handed out to discovery a batch at a time, again if they do not answer for a while
+		let batch = |table: &mut NodeTable, limit: usize| -> Vec<_> {
+			table.sweep_batch(clock.now_instant(), &all, limit).into_iter().map(|e| e.id).collect()
+		};
+		assert_eq!(batch(&mut table, 2), vec![NodeId::from(2), NodeId::from(1)]);
+		assert_eq!(batch(&mut table, 2), vec![NodeId::from(3)]);
+		assert!(batch(&mut table, 2).is_empty());
+		clock.advance(SWEEP_REPING_INTERVAL);
+		assert_eq!(batch(&mut table, 5), vec![NodeId::from(2), NodeId::from(1), NodeId::from(3)]);
+
+		// answering discovery or connecting verifies them, the statistics are used once all are verified
+		let verify = |id: u64| TableUpdates {
+			added: vec![(NodeId::from(id), NodeEntry::new(NodeId::from(id), endpoint(id)))].into_iter().collect(),
+			removed: HashSet::new(),
+			verified: vec![NodeId::from(id)].into_iter().collect(),
+		};
+		table.update(verify(2), &HashSet::new());
+		table.note_success(&NodeId::from(3));
+		assert_eq!(table.reverify_sweep(&all).into_iter().map(|e| e.id).collect::<Vec<_>>(), vec![NodeId::from(1)]);
+		assert_eq!(table.nodes(&all), vec![NodeId::from(2), NodeId::from(1), NodeId::from(3)]);
+		table.update(verify(1), &HashSet::new());
+		assert!(table.reverify_sweep(&all).is_empty());
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1), NodeId::from(2), NodeId::from(3)]);
+		drop(table);
+
+		// saved just now: the statistics are trusted and the warm start begins
+		let table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1), NodeId::from(2), NodeId::from(3)]);
+		assert!(table.reverify_sweep(&all).is_empty());
+		assert_eq!(table.warm_entries(&all), vec![NodeId::from(3)]);
+		drop(table);
+
+		// a sweep that does not finish ends after a while
+		clock.advance(Duration::from_secs(8 * 24 * 60 * 60));
+		let table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+		assert_eq!(table.reverify_sweep(&all).len(), 3);
+		clock.advance(REVERIFY_TIMEOUT);
+		assert!(table.reverify_sweep(&all).is_empty());
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1), NodeId::from(2), NodeId::from(3)]);
+	}
+
+	#[test]
+	fn explain_selection_reasons() {
+		let persistence = MemoryPersistence::new();
+		let clock = ManualClock::new();
+		{
+			let mut table = warm_table(&persistence, NodeTableConfig::default(), &clock);
+			for i in 1..5 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i)).unwrap()));
+			}
+			table.update_node(&NodeId::from(1), |node| node.peer_type = PeerType::Required);
+			table.note_success(&NodeId::from(3));
+			table.mark_warm(&[NodeId::from(2)]);
+		}
+		let config = NodeTableConfig { warm_start: Duration::from_secs(60), ..Default::default() };
+		let table = warm_table(&persistence, config, &clock);
+		let reasons: Vec<_> = table.explain_selection(&DialPolicy::default(), &IpFilter::default(), 10).into_iter()
+			.map(|(entry, reason)| (entry.id, reason))
+			.collect();
+		assert_eq!(reasons, vec![
+			(NodeId::from(2), SelectionReason::Warm),
+			(NodeId::from(1), SelectionReason::Required),
+			(NodeId::from(3), SelectionReason::Quality),
+			(NodeId::from(4), SelectionReason::DiverseFill),
+		]);
+		assert_eq!(table.explain_selection(&DialPolicy::default(), &IpFilter::default(), 2).len(), 2);
+		assert_eq!(SelectionReason::DiverseFill.to_string(), "diverse-fill");
+	}
+
+	#[test]
+	fn tags_persist_and_rank() {
+		let persistence = MemoryPersistence::new();
+		{
+			let mut table = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+			for i in 1..6 {
+				table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:7770", i)).unwrap()));
+			}
+			table.note_failure(&NodeId::from(2));
+			assert!(table.set_tag(&NodeId::from(1), "served_ms", 300));
+			assert!(table.set_tag(&NodeId::from(2), "served_ms", 100));
+			assert!(table.set_tag(&NodeId::from(3), "served_ms", 300));
+			assert!(table.set_tag(&NodeId::from(4), "other", 1));
+			assert!(!table.set_tag(&NodeId::from(6), "served_ms", 1));
+			table.mark_as_useless(&NodeId::from(3), "test");
+		}
+		let table = NodeTable::with_persistence(Box::new(persistence), NodeTableConfig::default());
+		assert_eq!(table.get_tag(&NodeId::from(1), "served_ms"), Some(300));
+		assert_eq!(table.get_tag(&NodeId::from(5), "served_ms"), None);
+		// by value, then by quality; useless nodes are left out
+		assert_eq!(table.top_by_tag("served_ms", 10), vec![NodeId::from(2), NodeId::from(1)]);
+		assert_eq!(table.top_by_tag("served_ms", 1), vec![NodeId::from(2)]);
+		assert_eq!(table.top_by_tag("other", 10), vec![NodeId::from(4)]);
+	}
+
+	#[test]
+	fn tag_limits() {
+		let mut table = TableState::new(NodeTableConfig::default());
+		let id = NodeId::from(1);
+		table.add_node(Node::new(id.clone(), NodeEndpoint::from_str("22.99.55.44:7770").unwrap()));
+		assert!(!table.set_tag(&id, &"k".repeat(META_LIMITS.tag_key + 1), 1));
+		for i in 0..META_LIMITS.tags {
+			assert!(table.set_tag(&id, &format!("key{}", i), 1));
+		}
+		// full node: existing tags can still change
+		assert!(!table.set_tag(&id, "extra", 1));
+		assert!(table.set_tag(&id, "key0", 2));
+		assert_eq!(table.stats(&IpFilter::default()).meta_truncated, 2);
+
+		// full table: only known keys are accepted, also from nodes added with tags
+		for i in 2..(META_LIMITS.tag_keys as u64) {
+			let id = NodeId::from(i);
+			table.add_node(Node::new(id.clone(), NodeEndpoint::from_str(&format!("22.99.{}.1:7770", i)).unwrap()));
+			table.set_tag(&id, &format!("node{}", i), 1);
+		}
+		assert_eq!(table.tag_keys().len(), META_LIMITS.tag_keys);
+		assert!(!table.set_tag(&NodeId::from(2), "new", 1));
+		assert!(table.set_tag(&NodeId::from(2), "key1", 1));
+		let mut node = Node::new(NodeId::from(100), NodeEndpoint::from_str("22.98.1.1:7770").unwrap());
+		node.tags.insert("key1".into(), 5);
+		node.tags.insert("unknown".into(), 5);
+		table.add_node(node);
+		assert_eq!(table.get_tag(&NodeId::from(100), "key1"), Some(5));
+		assert_eq!(table.get_tag(&NodeId::from(100), "unknown"), None);
+		table.check_invariants().unwrap();
+	}
+
+	#[test]
+	fn table_skips_local_endpoints() {
+		let listen = NodeEndpoint::from_str("10.0.0.2:30303").unwrap();
+		let external = NodeEndpoint::from_str("22.99.55.50:30303").unwrap();
+		let mut table = TableState::new(NodeTableConfig::default());
+		table.set_local_id(NodeId::from(10));
+		table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:30303").unwrap()));
+		// learned before NAT detection, e.g. our own stale id reported back by discovery
+		table.add_node(Node::new(NodeId::from(2), external.clone()));
+		table.add_node(Node::new(NodeId::from(3), NodeEndpoint::from_str("22.99.55.51:30303").unwrap()));
+		table.set_local_endpoints(vec![listen.clone(), external.clone()]);
+
+		let all = IpFilter::default();
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1), NodeId::from(3)]);
+		assert_eq!(table.select_for(&DialPolicy::default(), &all, 10, None), vec![NodeId::from(1), NodeId::from(3)]);
+		assert_eq!(table.entries_filtered(&all, 10).len(), 2);
+		assert!(!table.best_enodes(10, &all).iter().any(|url| url.contains("22.99.55.50")));
+		assert_eq!(table.stats(&all).local, 1);
+
+		// matched on the internal and the external address, whatever the id and the discovery port
+		table.add_node(Node::new(NodeId::from(4), NodeEndpoint::from_str("10.0.0.2:30303?discport=30301").unwrap()));
+		let mut discovery = ::mock_discovery::MockDiscovery::new();
+		discovery.announce(NodeId::from(5), "22.99.55.50:30303").confirm(NodeId::from(5)).flush(&mut table);
+		assert!(!table.contains(&NodeId::from(4)) && !table.contains(&NodeId::from(5)));
+		assert_eq!(table.stats(&all).rejected, 2);
+
+		// the external address changed: the new one is skipped, the old one only for a grace period
+		let clock = ManualClock::new();
+		table.set_clock(Box::new(clock.clone()));
+		table.set_local_endpoints(vec![listen.clone(), NodeEndpoint::from_str("22.99.55.51:30303").unwrap()]);
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1)]);
+		assert_eq!(table.stats(&all).local, 2);
+		discovery.announce(NodeId::from(6), "22.99.55.50:30303").confirm(NodeId::from(6)).flush(&mut table);
+		assert!(!table.contains(&NodeId::from(6)));
+
+		clock.advance(FORMER_LOCAL_ENDPOINT_GRACE);
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1), NodeId::from(2)]);
+		assert_eq!(table.stats(&all).local, 1);
+
+		// switching back ends the grace period of the endpoint in use again
+		table.set_local_endpoints(vec![listen.clone(), external.clone()]);
+		table.set_local_endpoints(vec![listen, NodeEndpoint::from_str("22.99.55.51:30303").unwrap()]);
+		table.set_local_endpoints(vec![NodeEndpoint::from_str("10.0.0.2:30303").unwrap(), external]);
+		assert_eq!(table.former_local_endpoints.len(), 1);
+	}
+
+	#[test]
+	fn table_skips_sibling_endpoints() {
+		let config = NodeTableConfig { sibling_endpoints: vec!["22.99.55.60:30304".parse().unwrap()], ..Default::default() };
+		let mut table = TableState::new(config);
+		table.set_local_endpoints(vec![NodeEndpoint::from_str("10.0.0.2:30303").unwrap(), NodeEndpoint::from_str("22.99.55.50:30303").unwrap()]);
+		table.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.44:30303").unwrap()));
+		table.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.55.60:30304").unwrap()));
+		assert!(!table.contains(&NodeId::from(2)));
+
+		// learned before the registry was read: kept but not selected
+		table.add_node(Node::new(NodeId::from(3), NodeEndpoint::from_str("127.0.0.1:30305").unwrap()));
+		table.add_node(Node::new(NodeId::from(4), NodeEndpoint::from_str("22.99.55.50:30305").unwrap()));
+		table.add_node(Node::new(NodeId::from(5), NodeEndpoint::from_str("22.99.55.51:30305").unwrap()));
+		table.set_sibling_endpoints(vec!["22.99.55.60:30304".parse().unwrap(), "0.0.0.0:30305".parse().unwrap()]);
+		let all = IpFilter::default();
+		// an unspecified IP matches the port on the loopback and on our own addresses only
+		assert_eq!(table.nodes(&all), vec![NodeId::from(1), NodeId::from(5)]);
+		assert_eq!(table.select_for(&DialPolicy::default(), &all, 10, None), vec![NodeId::from(1), NodeId::from(5)]);
+
+		let mut discovery = ::mock_discovery::MockDiscovery::new();
+		discovery.announce(NodeId::from(6), "10.0.0.2:30305").announce(NodeId::from(7), "22.99.55.60:30304").confirm(NodeId::from(7));
+		let outcome = discovery.flush(&mut table);
+		assert_eq!(outcome.rejected, vec![(NodeId::from(6), RejectReason::Local), (NodeId::from(7), RejectReason::Local)]);
+		let stats = table.stats(&all);
+		assert_eq!((stats.sibling, stats.sibling_rejected, stats.rejected, stats.local), (2, 3, 3, 0));
+	}
+
+	#[test]
+	fn parse_bootnodes_reports_all_errors() {
+		let id = "a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c";
+		let entries: Vec<String> = vec![
+			format!("enode://{}@[::ffff:22.99.55.44]:7770", id.to_uppercase()),
+			"enode://00@22.99.55.44:7770".into(),
+			format!("enode://{}@0.0.0.0:7770", id),
+			format!("enode://{}@22.99.55.45:7770", "1".repeat(128)),
+			" 22.99.55.46:30303 ".into(),
+			"not a node".into(),
+		];
+		let (nodes, errors) = parse_bootnodes(&entries);
+		assert_eq!(nodes.iter().map(|n| n.to_string()).collect::<Vec<_>>(), vec![
+			format!("enode://{}@22.99.55.44:7770", id),
+			format!("enode://{:x}@22.99.55.46:30303", NodeId::new()),
+		]);
+		let errors: Vec<(usize, String)> = errors.into_iter().map(|(i, e)| (i, e.to_string())).collect();
+		assert_eq!(errors, vec![
+			(1, "Invalid node id".to_owned()),
+			(2, "Address cannot be dialled".to_owned()),
+			(3, "Invalid node id".to_owned()),
+			(5, "Failed to resolve network address invalid socket address".to_owned()),
+		]);
+	}
+
+	#[test]
+	fn parse_bootnodes_deduplicates() {
+		let id = "a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c";
+		let entries: Vec<String> = vec![
+			format!("enode://{}@22.99.55.44:7770", id),
+			"22.99.55.46:30303".into(),
+			format!("enode://{}@22.99.55.44:7770", id.to_uppercase()),
+			"22.99.55.46:30303".into(),
+			"22.99.55.47:30303".into(),
+			format!("enode://{}@22.99.55.45:7770", id),
+		];
+		let (nodes, errors) = parse_bootnodes(&entries);
+		assert_eq!(nodes.iter().map(|n| n.endpoint.address.to_string()).collect::<Vec<_>>(), vec!["22.99.55.44:7770", "22.99.55.46:30303", "22.99.55.47:30303"]);
+		// repeating an entry is harmless, another address for the same id is not
+		assert_eq!(errors.len(), 1);
+		match errors[0] {
+			(5, NodeParseError::Duplicate(0)) => {},
+			ref e => panic!("unexpected error {:?}", e),
+		}
+	}
+
+	/// Node with every field set to something other than its default.
+	fn populated_node() -> Node {
+		let mut sources = NodeSources::default();
+		sources.insert(NodeSource::Discovery);
+		sources.insert(NodeSource::Rpc);
+		let mut tags = BTreeMap::new();
+		tags.insert("archive".to_owned(), 3);
+		let contact = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+		Node {
+			id: NodeId::from(1),
+			endpoint: NodeEndpoint::from_str("22.99.55.44:7770?discport=7771").unwrap(),
+			peer_type: PeerType::Required,
+			attempts: 10,
+			failures: 3,
+			successes: 7,
+			last_contact: Some(contact),
+			source: NodeSource::Rpc,
+			client_version: Some("Parity/v1.11.0".into()),
+			capabilities: Some(vec![PeerCapabilityInfo { protocol: *b"eth", version: 63 }]),
+			latency: Some(Duration::from_millis(120)),
+			stalled: true,
+			warm: true,
+			is_boot: true,
+			required_for: vec![*b"par"].into_iter().collect(),
+			candidate_endpoint: Some(NodeEndpoint::from_str("22.99.55.45:7770").unwrap()),
+			sources: sources,
+			previous_endpoint: Some(NodeEndpoint::from_str("22.99.55.46:7770").unwrap()),
+			address_history: vec![("22.99.55.46:7770".parse().unwrap(), contact)].into_iter().collect(),
+			tags: tags,
+			untrusted: true,
+			trusted: true,
+			p2p_version: Some(5),
+			snappy: Some(false),
+			chain: Some((PeerChainInfo { network_id: 1, genesis: 5.into(), fork_hash: Some(0xfc64ec04) }, 2)),
+		}
+	}
+
+	#[test]
+	fn conversions_cover_every_field() {
+		let node = populated_node();
+
+		// the fields are listed without `..`, so that a new field does not compile until it is handled here
+		let Node {
+			id, endpoint, peer_type, attempts, failures, successes, last_contact, source, client_version, capabilities,
+			latency, stalled, warm, is_boot, required_for, candidate_endpoint, sources, previous_endpoint,
+			address_history, tags, untrusted, trusted, p2p_version, snappy, chain,
+		} = Node::from_persisted(node.to_persisted()).unwrap();
+		assert_eq!((id, endpoint, peer_type), (node.id, node.endpoint.clone(), node.peer_type));
+		assert_eq!((attempts, failures, successes, last_contact), (node.attempts, node.failures, node.successes, node.last_contact));
+		assert_eq!((source, client_version, capabilities), (node.source, node.client_version.clone(), node.capabilities.clone()));
+		assert_eq!((stalled, warm, required_for, sources), (node.stalled, node.warm, node.required_for.clone(), node.sources));
+		assert_eq!((address_history, tags), (node.address_history.clone(), node.tags.clone()));
+		assert_eq!((p2p_version, snappy, trusted, chain), (node.p2p_version, node.snappy, node.trusted, node.chain));
+		// intentionally not saved, they only apply to the current run
+		assert_eq!((latency, is_boot, candidate_endpoint, previous_endpoint, untrusted), (None, false, None, None, false));
+
+		// discovery entries only carry the id and the endpoint
+		let NodeEntry { id, endpoint, failure_bucket, last_contact_age } = NodeEntry::from(&node);
+		assert_eq!((id, endpoint.clone(), failure_bucket, last_contact_age), (node.id, node.endpoint.clone(), None, None));
+		let back = Node::from(NodeEntry { failure_bucket: Some(3), last_contact_age: Some(10), ..NodeEntry::new(id, endpoint) });
+		assert_eq!((back.id, back.endpoint.clone()), (node.id, node.endpoint.clone()));
+		assert_eq!(back.to_persisted(), Node::new(node.id, node.endpoint.clone()).to_persisted());
+
+		// the metadata returned over RPC and by the `list` and `info` commands
+		let mut table = TableState::from_fixture(&[]);
+		table.add_node(populated_node());
+		let NodeTableEntry {
+			id, url, address, attempts, failures, failure_percentage, useless, banned, stalled, last_contact, source,
+			candidate_address, previous_address, ban_reason, useless_reason, address_history, p2p_version, snappy,
+			trusted, chain_info, other_chain,
+		} = table.entry_with_meta(&node.id).unwrap();
+		assert_eq!((id, url, address), (node.id, node.to_string(), node.endpoint.address));
+		assert_eq!((attempts, failures, failure_percentage), (node.attempts, node.failures, node.failure_percentage()));
+		assert_eq!((useless, banned, ban_reason, useless_reason), (false, false, None, None));
+		assert_eq!(stalled, node.stalled);
+		assert_eq!((last_contact, source, address_history), (node.last_contact, node.source, node.address_history.iter().cloned().collect()));
+		assert_eq!(candidate_address, node.candidate_endpoint.as_ref().map(|e| e.address));
+		assert_eq!(previous_address, node.previous_endpoint.as_ref().map(|e| e.address));
+		assert_eq!((p2p_version, snappy, trusted), (node.p2p_version, node.snappy, node.trusted));
+		assert_eq!((chain_info.as_ref(), other_chain), (node.chain_info(), false));
+	}
+
+	#[test]
+	fn negotiated_features() {
+		let mut table = TableState::from_fixture(&[FixtureNode::new("1", "22.99.55.44:7770")]);
+		let id = NodeId::from(1);
+		let negotiated = |table: &TableState| { let n = table.get(&id).unwrap(); (n.p2p_version, n.snappy) };
+		assert!(!table.set_negotiated(&NodeId::from(2), 5, true));
+		assert!(table.set_negotiated(&id, 5, true));
+		assert_eq!(negotiated(&table), (Some(5), Some(true)));
+
+		// a failed compression sticks while the node announces the same version
+		table.set_negotiated(&id, 5, false);
+		let generation = table.generation;
+		table.set_negotiated(&id, 5, false);
+		assert_eq!(negotiated(&table), (Some(5), Some(false)));
+		assert_eq!(table.generation, generation);
+
+		// a higher version is tried with compression again
+		table.set_negotiated(&id, 4, false);
+		assert_eq!(negotiated(&table), (Some(4), Some(false)));
+		table.set_negotiated(&id, 5, false);
+		assert_eq!(negotiated(&table), (Some(5), None));
+
+		// saved with the table
+		table.set_negotiated(&id, 5, false);
+		let saved = table.get(&id).unwrap().to_persisted();
+		assert_eq!((saved.p2p_version, saved.snappy), (Some(5), Some(false)));
+		let json = ::serde_json::to_string(&saved).unwrap();
+		assert!(json.contains(r#""p2p_version":5,"snappy":false"#), "{}", json);
+		let restored = Node::from_persisted(::serde_json::from_str(&json).unwrap()).unwrap();
+		assert_eq!((restored.p2p_version, restored.snappy), (Some(5), Some(false)));
+		let old = Node::from_persisted(PersistedNode { p2p_version: None, snappy: None, ..saved }).unwrap();
+		assert_eq!((old.p2p_version, old.snappy), (None, None));
+	}
+
+	#[test]
+	fn chain_info_recorded() {
+		let mut table = TableState::from_fixture(&[FixtureNode::new("1", "22.99.55.44:7770")]);
+		let id = NodeId::from(1);
+		let info = PeerChainInfo { network_id: 1, genesis: 5.into(), fork_hash: Some(0xfc64ec04) };
+		assert!(!table.set_chain_info(&NodeId::from(2), info));
+		assert!(table.set_chain_info(&id, info));
+		assert_eq!(table.get(&id).unwrap().chain_info(), Some(&info));
+		let generation = table.generation;
+		table.set_chain_info(&id, info);
+		assert_eq!(table.generation, generation);
+
+		// saved with the table and the epoch it was advertised in
+		table.bump_compat_epoch();
+		table.set_chain_info(&id, info);
+		let saved = table.get(&id).unwrap().to_persisted();
+		let json = ::serde_json::to_string(&saved).unwrap();
+		let genesis = format!("{:x}", ::ethereum_types::H256::from(5));
+		assert!(json.contains(&format!(r#""chain":{{"network_id":1,"genesis":"{}","fork_hash":4234472452,"epoch":1}}"#, genesis)), "{}", json);
+		let restored = Node::from_persisted(::serde_json::from_str(&json).unwrap()).unwrap();
+		assert_eq!(restored.chain, Some((info, 1)));
+		let broken = PersistedChain { genesis: "forty-two".into(), ..saved.chain.clone().unwrap() };
+		assert_eq!(Node::from_persisted(PersistedNode { chain: Some(broken), ..saved.clone() }).unwrap().chain, None);
+		assert_eq!(Node::from_persisted(PersistedNode { chain: None, ..saved }).unwrap().chain, None);
+	}
+
+	#[test]
+	fn other_chain_dialled_last() {
+		let nodes: Vec<FixtureNode> = (1..4).map(|i: u32| FixtureNode {
+			attempts: 10,
+			failures: i,
+			successes: 10 - i,
+			last_contact: Some(Duration::from_secs(60)),
+			..FixtureNode::new(&i.to_string(), &format!("22.99.{}.1:30303", i))
+		}).collect();
+		let mut table = TableState::from_fixture(&nodes);
+		let selected = |table: &TableState| -> Vec<u64> {
+			table.select_for(&DialPolicy::default(), &IpFilter::default(), 10, None).into_iter().map(|id| id.low_u64()).collect()
+		};
+		let ours = PeerChainInfo { network_id: 1, genesis: 5.into(), fork_hash: Some(0xfc64ec04) };
+		let classic = PeerChainInfo { network_id: 61, ..ours };
+		table.set_chain_info(&NodeId::from(1), classic);
+
+		// without our own chain nothing is compared
+		assert_eq!(selected(&table), vec![1, 2, 3]);
+		table.set_local_chain_info(ours);
+		assert_eq!(selected(&table), vec![2, 3, 1]);
+		assert!(table.entry_with_meta(&NodeId::from(1)).unwrap().other_chain);
+
+		// fork hashes only count if both are known
+		table.set_chain_info(&NodeId::from(2), PeerChainInfo { fork_hash: None, ..ours });
+		table.set_chain_info(&NodeId::from(3), PeerChainInfo { fork_hash: Some(0x3edd5b10), ..ours });
+		assert_eq!(selected(&table), vec![2, 1, 3]);
+
+		// a new epoch gives them another chance until they advertise the other chain again
+		table.bump_compat_epoch();
+		assert_eq!(selected(&table), vec![1, 2, 3]);
+		assert!(!table.entry_with_meta(&NodeId::from(1)).unwrap().other_chain);
+		table.set_chain_info(&NodeId::from(1), classic);
+		assert_eq!(selected(&table), vec![2, 3, 1]);
+
+		// a change of our own chain, e.g. our fork hash at a fork, starts a new epoch
+		let epoch = table.compat_epoch;
+		table.set_local_chain_info(PeerChainInfo { fork_hash: Some(0x3edd5b10), ..ours });
+		table.set_local_chain_info(PeerChainInfo { fork_hash: Some(0x3edd5b10), ..ours });
+		assert_eq!(table.compat_epoch, epoch + 1);
+		assert_eq!(selected(&table), vec![1, 2, 3]);
+	}
+
+	#[test]
+	fn enode_length_limit() {
+		let id = "a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c";
+		// valid URLs, padded with leading zeros of the discovery port
+		let url = |len: usize| {
+			let prefix = format!("enode://{}@22.99.55.44:7770?discport=", id);
+			format!("{}{}7771", prefix, "0".repeat(len - prefix.len() - 4))
+		};
+		let (under, over) = (url(MAX_ENODE_LEN), url(MAX_ENODE_LEN + 1));
+		assert_eq!((under.len(), over.len()), (MAX_ENODE_LEN, MAX_ENODE_LEN + 1));
+		let too_long = format!("Enode URL longer than {} bytes", MAX_ENODE_LEN);
+
+		assert_eq!(Node::from_str(&under).unwrap().endpoint.udp_port, 7771);
+		assert_eq!(Node::from_str(&over).unwrap_err().to_string(), too_long);
+		assert!(validate_node_url(&under).is_none());
+		assert_eq!(validate_node_url(&over).map(|e| e.to_string()), Some(too_long.clone()));
+		assert_eq!(normalize_node_url(&under).unwrap(), format!("enode://{}@22.99.55.44:7770?discport=7771", id));
+		// bare addresses are bounded as well
+		assert_eq!(validate_node_url(&format!("{}:30303", "a".repeat(MAX_ENODE_LEN))).map(|e| e.to_string()), Some(too_long.clone()));
+
+		let (nodes, errors) = parse_bootnodes(&[under.clone(), over.clone()]);
+		assert_eq!(nodes.len(), 1);
+		match errors[..] {
+			[(1, NodeParseError::TooLong)] => {},
+			ref e => panic!("unexpected errors {:?}", e),
+		}
+		let (peers, errors) = parse_static_peers(&format!("{}\n!{}\n", over, under));
+		assert_eq!(peers.len(), 1);
+		assert_eq!(errors.iter().map(|&(line, ref e)| (line, e.to_string())).collect::<Vec<_>>(), vec![(1, format!("Longer than {} bytes", MAX_ENODE_LEN))]);
+
+		let mut table = TableState::from_fixture(&[]);
+		let list = format!("{}\n{}\n", over, under);
+		let stats = table.ingest_list(list.as_bytes(), ListFormat::EnodeLines, &IpFilter::default()).unwrap();
+		assert_eq!(stats.entries[0], (1, ListEntryResult::Invalid(too_long.clone())));
+		assert_eq!(stats.added().len(), 1);
+
+		// saved tables
+		let saved = PersistedNode::from(&Node::from_str(&under).unwrap());
+		assert!(PersistedNode { url: under, ..saved.clone() }.into_node().is_ok());
+		assert_eq!(PersistedNode { url: over, ..saved }.into_node().unwrap_err().to_string(), too_long);
+	}
+
+	#[test]
+	fn boot_node_parse() {
+		let url = "enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770";
+		assert_eq!(Node::from_boot_str(url).unwrap().id, Node::from_str(url).unwrap().id);
+		assert!(Node::from_str("22.99.55.44:7770").is_err());
+		assert!(Node::from_boot_str("22.99.55.44:7770").unwrap().id.is_zero());
+		assert!(validate_node_url("22.99.55.44:7770").is_none());
+	}
+
+	#[test]
+	fn ingest_order_independent() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = Some(tempdir.path().to_str().unwrap().to_owned());
+		let url = "enode://a979fb575495b8d6db44f750317d0f4622bf4c2aa3365d6af7c284339968eef29b69ad0dce72a4d8db5ebb4968de0e3bec910127f134779fbcb0cb6d3331163c@22.99.55.44:7770";
+		let id = Node::from_str(url).unwrap().id;
+		{
+			let mut table = NodeTable::new(path.clone());
+			let mut node = Node::from_str(url).unwrap();
+			node.source = NodeSource::Discovery;
+			node.capabilities = Some(vec![capability(b"eth", 63)]);
+			table.add_node(node);
+			table.note_success(&id);
+			table.note_failure(&id);
+			table.update_node(&id, |n| n.attempts = 2).unwrap();
+			table.save();
+		}
+
+		let origins = [NodeOrigin::Boot, NodeOrigin::Reserved, NodeOrigin::Saved, NodeOrigin::Trusted];
+		let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+		let states: Vec<_> = orders.iter().enumerate().map(|(k, order)| {
+			// trusted nodes come in at every position, they do not set a source
+			let mut order = order.to_vec();
+			order.insert(k % 4, 3);
+			let mut table = NodeTable::with_config(path.clone(), false, NodeTableConfig::default());
+			for &i in &order {
+				match origins[i] {
+					NodeOrigin::Saved => table.load_saved(),
+					origin => table.ingest(origin, Node::from_str(url).unwrap()),
+				}
+			}
+			assert_eq!(table.nodes.len(), 1);
+			let node = table.get(&id).unwrap();
+			let first = match origins[order[if order[0] == 3 { 1 } else { 0 }]] {
+				NodeOrigin::Boot => NodeSource::Boot,
+				NodeOrigin::Reserved => NodeSource::Reserved,
+				NodeOrigin::Saved => NodeSource::Discovery,
+				NodeOrigin::Trusted => unreachable!(),
+			};
+			assert_eq!(node.source, first);
+			(node.is_boot, node.peer_type == PeerType::Required, node.trusted, node.sources, node.attempts, node.failures,
+				node.successes, node.last_contact, node.capabilities.clone())
+		}).collect();
+
+		assert!(states.iter().all(|s| *s == states[0]));
+		let (is_boot, required, trusted, sources, attempts, failures, successes, last_contact, capabilities) = states[0].clone();
+		assert!(is_boot && required && trusted);
+		assert_eq!(sources.to_vec(), vec![NodeSource::Boot, NodeSource::Reserved, NodeSource::Discovery]);
+		assert_eq!((attempts, failures, successes), (2, 1, 1));
+		assert!(last_contact.is_some());
+		assert_eq!(capabilities, Some(vec![capability(b"eth", 63)]));
+	}
+
+	#[test]
+	fn forced_dial_handoff() {
+		let mut table = capabilities_table();
+		let id1 = NodeId::from(1);
+		let policy = DialPolicy::default();
+		let filter = IpFilter::default();
+
+		table.note_failure(&id1);
+		assert!(!table.select_for(&policy, &filter, usize::max_value(), None).contains(&id1));
+		table.force_dialable(&id1);
+		table.force_dialable(&id1);
+		table.force_dialable(&NodeId::from(4));
+		assert!(table.select_for(&policy, &filter, usize::max_value(), None).contains(&id1));
+
+		assert_eq!(table.take_forced_dials(), vec![id1]);
+		assert!(table.take_forced_dials().is_empty());
+	}
+
+	#[test]
+	fn table_save_load_metadata() {
+		let tempdir = TempDir::new("").unwrap();
+		let id = NodeId::from(1);
+		let meta = SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![capability(b"eth", 63)] };
+		{
+			let mut table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
+			table.note_inbound(&id, NodeEndpoint::from_str("22.99.55.44:30305").unwrap(), meta.clone());
+			table.note_stalled(&id);
 		}
+		let table = NodeTable::new(Some(tempdir.path().to_str().unwrap().to_owned()));
+		let node = table.get(&id).unwrap();
+		assert_eq!(node.source, NodeSource::Inbound);
+		assert_eq!(node.client_version, Some(meta.client_version));
+		assert_eq!(node.capabilities, Some(meta.capabilities));
+		assert_eq!((node.attempts, node.successes), (1, 1));
+		assert!(node.last_contact.is_some());
+		assert!(node.stalled);
 	}
 
 	#[test]
@@ -572,4 +9998,106 @@ mod tests {
 		assert!(!NodeEndpoint::from_str("[fc00::]:5550").unwrap().is_allowed(&filter));
 		assert!(NodeEndpoint::from_str("[fd00::]:5550").unwrap().is_allowed(&filter));
 	}
+
+	fn random_node_op(table: &mut NodeTable, clock: &ManualClock, rng: &mut XorShiftRng) -> &'static str {
+		let id = NodeId::from(rng.gen_range(1u64, 11));
+		let endpoint = NodeEndpoint::from_str(&format!("22.99.{}.{}:7770", rng.gen_range(0, 3), rng.gen_range(1, 4))).unwrap();
+		match rng.gen_range(0, 23) {
+			0 => {
+				let mut node = Node::new(id, endpoint);
+				node.source = *rng.choose(&[NodeSource::Unknown, NodeSource::Discovery, NodeSource::Rpc]).unwrap();
+				table.add_node(node);
+				"add_node"
+			},
+			1 => { table.ingest(NodeOrigin::Boot, Node::new(id, endpoint)); "ingest boot" },
+			2 => { table.ingest(NodeOrigin::Reserved, Node::new(id, endpoint)); "ingest reserved" },
+			3 => {
+				let mut updates = TableUpdates { added: HashMap::new(), removed: HashSet::new(), verified: HashSet::new() };
+				updates.added.insert(id, NodeEntry::new(id, endpoint));
+				if rng.gen() {
+					updates.verified.insert(id);
+				}
+				if rng.gen() {
+					updates.removed.insert(NodeId::from(rng.gen_range(1u64, 11)));
+				}
+				table.update(updates, &HashSet::new());
+				"update"
+			},
+			4 => { table.note_failure(&id); "note_failure" },
+			5 => { table.note_success(&id); "note_success" },
+			6 => { table.note_stalled(&id); "note_stalled" },
+			7 => {
+				let severity = *rng.choose(&[Severity::Minor, Severity::Major, Severity::Critical]).unwrap();
+				table.note_misbehaviour(&id, severity);
+				"note_misbehaviour"
+			},
+			8 => { table.mark_as_useless(&id, "test"); "mark_as_useless" },
+			9 => { table.clear_useless(); "clear_useless" },
+			10 => { table.remove_node(&id); "remove_node" },
+			11 => { table.ban(&id, Duration::from_secs(rng.gen_range(1, 600)), "test"); "ban" },
+			12 => { table.retry_now(&id); "retry_now" },
+			13 => { table.release_required(&id, rng.gen()); "release_required" },
+			14 => {
+				let peer_type = if rng.gen() { PeerType::Required } else { PeerType::Optional };
+				table.set_peer_type_for(&id, *b"eth", peer_type);
+				"set_peer_type_for"
+			},
+			15 => { table.force_dialable(&id); "force_dialable" },
+			16 => { table.take_forced_dials(); "take_forced_dials" },
+			17 => {
+				let meta = SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![] };
+				table.note_inbound(&id, endpoint, meta);
+				"note_inbound"
+			},
+			18 => { clock.advance(Duration::from_secs(rng.gen_range(0, 900))); "advance clock" },
+			19 => { table.drop_expired(&HashSet::new()); "drop_expired" },
+			20 => { table.note_latency(&id, Duration::from_millis(rng.gen_range(1, 1000))); "note_latency" },
+			21 => { table.evict_excess(&HashSet::new()); "evict_excess" },
+			_ => {
+				table.save();
+				let saved = table.persistence.as_ref().unwrap().load().unwrap();
+				let mut loaded = NodeTable::with_persistence(Box::new(MemoryPersistence::with_table(saved.clone())), table.config.clone());
+				loaded.set_persistent(false);
+				if let Err(e) = loaded.check_invariants() {
+					panic!("loaded table: {}", e);
+				}
+				// the subnet limit applies again on load, so nodes over the limit may be dropped
+				if table.config.max_nodes_per_subnet.is_none() {
+					assert_eq!(loaded.nodes.len(), saved.nodes.len());
+				}
+				for entry in saved.nodes {
+					let node = entry.clone().into_node().unwrap();
+					assert!(table.contains(&node.id));
+					// the reliability is computed when saving only
+					assert!(entry.reliability.is_some());
+					if let Some(loaded) = loaded.nodes.get(&node.id) {
+						assert_eq!(PersistedNode { reliability: entry.reliability, ..PersistedNode::from(loaded) }, entry);
+					}
+				}
+				"save and load"
+			},
+		}
+	}
+
+	#[test]
+	fn table_random_ops_keep_invariants() {
+		for seed in 1..17 {
+			let config = NodeTableConfig {
+				max_nodes: 6,
+				max_nodes_per_subnet: if seed % 2 == 0 { Some(3) } else { None },
+				max_age: Some(Duration::from_secs(30 * 60)),
+				..Default::default()
+			};
+			let clock = ManualClock::new();
+			let mut table = NodeTable::with_persistence(Box::new(MemoryPersistence::new()), config.clone());
+			table.set_clock(Box::new(clock.clone()));
+			let mut rng = XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05]);
+			for step in 0..500 {
+				let op = random_node_op(&mut table, &clock, &mut rng);
+				if let Err(e) = table.check_invariants() {
+					panic!("seed {}, step {} ({}): {}", seed, step, op, e);
+				}
+			}
+		}
+	}
 }
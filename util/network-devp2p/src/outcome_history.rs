@@ -0,0 +1,196 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Hourly totals of the connection outcomes recorded by the node table.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of hourly buckets kept, older hours are overwritten.
+pub const HISTORY_HOURS: usize = 24;
+
+/// Length of a bucket in seconds.
+pub const BUCKET_SECS: u64 = 60 * 60;
+
+/// Connection outcomes within one hour.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutcomeBucket {
+	/// Start of the hour in seconds since UNIX epoch.
+	pub start: u64,
+	/// Dial attempts.
+	pub attempts: u32,
+	/// Established sessions.
+	pub successes: u32,
+	/// Failed dials and sessions counted against the nodes.
+	#[serde(default)]
+	pub failures: u32,
+	/// Failures during a suspected outage of our own connectivity, not counted against the nodes.
+	#[serde(default)]
+	pub quarantined: u32,
+	/// Sessions that stopped answering pings.
+	#[serde(default)]
+	pub stalled: u32,
+}
+
+impl OutcomeBucket {
+	fn is_empty(&self) -> bool {
+		self.attempts == 0 && self.successes == 0 && self.failures == 0 && self.quarantined == 0 && self.stalled == 0
+	}
+}
+
+/// Kind of an outcome counted by `OutcomeHistory::note`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryEvent {
+	Attempt,
+	Success,
+	Failure,
+	Quarantined,
+	Stalled,
+}
+
+/// Ring of the last `HISTORY_HOURS` hourly buckets. Buckets are aligned to the wall clock hour so that saved ones
+/// line up after a restart.
+pub struct OutcomeHistory {
+	buckets: [OutcomeBucket; HISTORY_HOURS],
+}
+
+impl Default for OutcomeHistory {
+	fn default() -> Self {
+		OutcomeHistory { buckets: [OutcomeBucket::default(); HISTORY_HOURS] }
+	}
+}
+
+fn hour_start(time: SystemTime) -> u64 {
+	let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+	secs - secs % BUCKET_SECS
+}
+
+impl OutcomeHistory {
+	/// Count an outcome in the bucket of the hour of `now`.
+	pub fn note(&mut self, now: SystemTime, event: HistoryEvent) {
+		let bucket = self.bucket_mut(hour_start(now));
+		let count = match event {
+			HistoryEvent::Attempt => &mut bucket.attempts,
+			HistoryEvent::Success => &mut bucket.successes,
+			HistoryEvent::Failure => &mut bucket.failures,
+			HistoryEvent::Quarantined => &mut bucket.quarantined,
+			HistoryEvent::Stalled => &mut bucket.stalled,
+		};
+		*count = count.saturating_add(1);
+	}
+
+	fn bucket_mut(&mut self, start: u64) -> &mut OutcomeBucket {
+		let bucket = &mut self.buckets[(start / BUCKET_SECS) as usize % HISTORY_HOURS];
+		if bucket.start != start {
+			*bucket = OutcomeBucket { start: start, ..Default::default() };
+		}
+		bucket
+	}
+
+	/// Returns the buckets of the last `hours` hours up to the one of `now`, oldest first. Hours without outcomes
+	/// are returned as empty buckets. At most `HISTORY_HOURS` are returned.
+	pub fn last(&self, now: SystemTime, hours: usize) -> Vec<OutcomeBucket> {
+		let current = hour_start(now);
+		(0..hours.min(HISTORY_HOURS) as u64).rev()
+			.filter_map(|age| current.checked_sub(age * BUCKET_SECS))
+			.map(|start| {
+				let bucket = self.buckets[(start / BUCKET_SECS) as usize % HISTORY_HOURS];
+				if bucket.start == start { bucket } else { OutcomeBucket { start: start, ..Default::default() } }
+			})
+			.collect()
+	}
+
+	/// Returns the buckets with outcomes within the last `HISTORY_HOURS` hours, for saving.
+	pub fn to_persisted(&self, now: SystemTime) -> Vec<OutcomeBucket> {
+		self.last(now, HISTORY_HOURS).into_iter().filter(|b| !b.is_empty()).collect()
+	}
+
+	/// Add up saved buckets with the current ones. Buckets older than `HISTORY_HOURS` hours, in the future or not
+	/// aligned to an hour are ignored.
+	pub fn restore(&mut self, buckets: Vec<OutcomeBucket>, now: SystemTime) {
+		let current = hour_start(now);
+		let oldest = current.saturating_sub((HISTORY_HOURS as u64 - 1) * BUCKET_SECS);
+		for saved in buckets.into_iter().filter(|b| b.start % BUCKET_SECS == 0 && b.start >= oldest && b.start <= current) {
+			let bucket = self.bucket_mut(saved.start);
+			bucket.attempts = bucket.attempts.saturating_add(saved.attempts);
+			bucket.successes = bucket.successes.saturating_add(saved.successes);
+			bucket.failures = bucket.failures.saturating_add(saved.failures);
+			bucket.quarantined = bucket.quarantined.saturating_add(saved.quarantined);
+			bucket.stalled = bucket.stalled.saturating_add(saved.stalled);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use super::*;
+
+	fn at(secs: u64) -> SystemTime {
+		UNIX_EPOCH + Duration::from_secs(secs)
+	}
+
+	#[test]
+	fn buckets_follow_the_hour() {
+		let mut history = OutcomeHistory::default();
+		let hour = 400_000 * BUCKET_SECS;
+		history.note(at(hour), HistoryEvent::Attempt);
+		history.note(at(hour + BUCKET_SECS - 1), HistoryEvent::Success);
+		history.note(at(hour + BUCKET_SECS), HistoryEvent::Failure);
+		history.note(at(hour + BUCKET_SECS + 1), HistoryEvent::Quarantined);
+
+		let last = history.last(at(hour + 2 * BUCKET_SECS), 3);
+		assert_eq!(last, vec![
+			OutcomeBucket { start: hour, attempts: 1, successes: 1, ..Default::default() },
+			OutcomeBucket { start: hour + BUCKET_SECS, failures: 1, quarantined: 1, ..Default::default() },
+			OutcomeBucket { start: hour + 2 * BUCKET_SECS, ..Default::default() },
+		]);
+		assert_eq!(history.last(at(hour), 100).len(), HISTORY_HOURS);
+	}
+
+	#[test]
+	fn old_buckets_are_overwritten() {
+		let mut history = OutcomeHistory::default();
+		let hour = 400_000 * BUCKET_SECS;
+		history.note(at(hour), HistoryEvent::Attempt);
+		// the same slot a day later starts over
+		let day_later = hour + HISTORY_HOURS as u64 * BUCKET_SECS;
+		history.note(at(day_later), HistoryEvent::Stalled);
+		assert_eq!(history.to_persisted(at(day_later)), vec![OutcomeBucket { start: day_later, stalled: 1, ..Default::default() }]);
+		// and hours that passed without outcomes read as empty
+		assert_eq!(history.last(at(day_later + 3 * BUCKET_SECS), 4)[0].stalled, 1);
+		assert!(history.to_persisted(at(day_later + HISTORY_HOURS as u64 * BUCKET_SECS)).is_empty());
+	}
+
+	#[test]
+	fn saved_buckets_are_restored() {
+		let hour = 400_000 * BUCKET_SECS;
+		let mut saved = OutcomeHistory::default();
+		saved.note(at(hour), HistoryEvent::Attempt);
+		saved.note(at(hour + BUCKET_SECS), HistoryEvent::Success);
+		let mut buckets = saved.to_persisted(at(hour + BUCKET_SECS));
+		buckets.push(OutcomeBucket { start: hour + 10, attempts: 5, ..Default::default() });
+		buckets.push(OutcomeBucket { start: hour - HISTORY_HOURS as u64 * BUCKET_SECS, attempts: 5, ..Default::default() });
+		buckets.push(OutcomeBucket { start: hour + 5 * BUCKET_SECS, attempts: 5, ..Default::default() });
+
+		let mut history = OutcomeHistory::default();
+		history.note(at(hour + BUCKET_SECS), HistoryEvent::Success);
+		history.restore(buckets, at(hour + BUCKET_SECS));
+		assert_eq!(history.to_persisted(at(hour + BUCKET_SECS)), vec![
+			OutcomeBucket { start: hour, attempts: 1, ..Default::default() },
+			OutcomeBucket { start: hour + BUCKET_SECS, successes: 2, ..Default::default() },
+		]);
+	}
+}
@@ -15,13 +15,20 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use network::{Error, NetworkConfiguration, NetworkProtocolHandler, NonReservedPeerMode};
-use network::{NetworkContext, PeerId, ProtocolId, NetworkIoMessage};
-use host::Host;
+use network::{NetworkContext, PeerId, PeerChainInfo, ProtocolId, NetworkIoMessage};
+use host::{Host, NetHealth};
+use node_table::{normalize_node_url, AdmissionHook, EntryStatus, NodeId, NodeTableEntry, ListFormat, IngestStats, StaticPeersUpdate};
 use io::*;
 use parking_lot::RwLock;
+use std::fs::File;
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use ansi_term::Colour;
 use connection_filter::ConnectionFilter;
+use persistence::NodeTablePersistence;
+use telemetry::TelemetryCallback;
 
 struct HostHandler {
 	public_url: RwLock<Option<String>>
@@ -48,11 +55,21 @@ pub struct NetworkService {
 	host_handler: Arc<HostHandler>,
 	config: NetworkConfiguration,
 	filter: Option<Arc<ConnectionFilter>>,
+	persistence: Option<Arc<NodeTablePersistence>>,
 }
 
 impl NetworkService {
 	/// Starts IO event loop
 	pub fn new(config: NetworkConfiguration, filter: Option<Arc<ConnectionFilter>>) -> Result<NetworkService, Error> {
+		NetworkService::build(config, filter, None)
+	}
+
+	/// Starts IO event loop, keeping the node table in `persistence` instead of the network directory.
+	pub fn with_persistence(config: NetworkConfiguration, filter: Option<Arc<ConnectionFilter>>, persistence: Arc<NodeTablePersistence>) -> Result<NetworkService, Error> {
+		NetworkService::build(config, filter, Some(persistence))
+	}
+
+	fn build(config: NetworkConfiguration, filter: Option<Arc<ConnectionFilter>>, persistence: Option<Arc<NodeTablePersistence>>) -> Result<NetworkService, Error> {
 		let host_handler = Arc::new(HostHandler { public_url: RwLock::new(None) });
 		let io_service = IoService::<NetworkIoMessage>::start()?;
 
@@ -63,6 +80,7 @@ impl NetworkService {
 			config: config,
 			host_handler: host_handler,
 			filter: filter,
+			persistence: persistence,
 		})
 	}
 
@@ -108,7 +126,7 @@ impl NetworkService {
 	pub fn start(&self) -> Result<(), Error> {
 		let mut host = self.host.write();
 		if host.is_none() {
-			let h = Arc::new(Host::new(self.config.clone(), self.filter.clone())?);
+			let h = Arc::new(Host::new(self.config.clone(), self.filter.clone(), self.persistence.clone())?);
 			self.io_service.register_handler(h.clone())?;
 			*host = Some(h);
 		}
@@ -136,6 +154,11 @@ impl NetworkService {
 		self.host.read().as_ref().map(|h| h.connected_peers()).unwrap_or_else(Vec::new)
 	}
 
+	/// Get at most `limit` node table entries with statistics.
+	pub fn node_table_entries(&self, limit: usize, include_useless: bool) -> Vec<NodeTableEntry> {
+		self.host.read().as_ref().map(|h| h.node_table_entries(limit, include_useless)).unwrap_or_else(Vec::new)
+	}
+
 	/// Try to add a reserved peer.
 	pub fn add_reserved_peer(&self, peer: &str) -> Result<(), Error> {
 		let host = self.host.read();
@@ -146,16 +169,241 @@ impl NetworkService {
 		}
 	}
 
-	/// Try to remove a reserved peer.
-	pub fn remove_reserved_peer(&self, peer: &str) -> Result<(), Error> {
+	/// Try to remove a reserved peer. The peer is kept as an ordinary node unless `forget` is set.
+	pub fn remove_reserved_peer(&self, peer: &str, forget: bool) -> Result<(), Error> {
 		let host = self.host.read();
 		if let Some(ref host) = *host {
-			host.remove_reserved_node(peer)
+			host.remove_reserved_node(peer, forget)
 		} else {
 			Ok(())
 		}
 	}
 
+	/// Reserve a peer for `protocol` only, or release such a reservation.
+	pub fn set_protocol_reserved_peer(&self, peer: &str, protocol: ProtocolId, reserved: bool) -> Result<(), Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			host.set_protocol_reserved_node(peer, protocol, reserved)
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Remove a node from the node table and disconnect it. Returns `false` if the node is not known.
+	pub fn remove_node(&self, node: &str) -> Result<bool, Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			let io_ctxt = IoContext::new(self.io_service.channel(), 0);
+			host.remove_node(node, &io_ctxt)
+		} else {
+			Ok(false)
+		}
+	}
+
+	/// Ban a node for `duration` and disconnect it. Returns `false` if the node is not known.
+	pub fn ban_node(&self, node: &str, duration: Duration) -> Result<bool, Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			let io_ctxt = IoContext::new(self.io_service.channel(), 0);
+			host.ban_node(node, duration, &io_ctxt)
+		} else {
+			Ok(false)
+		}
+	}
+
+	/// Run a node table command, see `Host::node_table_command`.
+	pub fn node_table_command(&self, line: &str) -> Result<String, String> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			let io_ctxt = IoContext::new(self.io_service.channel(), 0);
+			host.node_table_command(line, &io_ctxt)
+		} else {
+			Err("Network is not running".into())
+		}
+	}
+
+	/// Read the static peers file again, see `Host::reload_static_peers`.
+	pub fn reload_static_peers(&self) -> Result<StaticPeersUpdate, Error> {
+		let host = self.host.read();
+		match *host {
+			Some(ref host) => host.reload_static_peers(),
+			None => Err(::std::io::Error::new(::std::io::ErrorKind::Other, "Network is not running").into()),
+		}
+	}
+
+	/// Save the node table and suppress further saves for `duration`, see `Host::freeze_node_table`.
+	pub fn freeze_node_table(&self, duration: Duration) -> Result<bool, String> {
+		let host = self.host.read();
+		match *host {
+			Some(ref host) => Ok(host.freeze_node_table(duration)),
+			None => Err("Network is not running".into()),
+		}
+	}
+
+	/// Make a node dialable right away. Returns `false` if the node is not known.
+	pub fn retry_node(&self, node: &str) -> Result<bool, Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			host.retry_node(node)
+		} else {
+			Ok(false)
+		}
+	}
+
+	/// Returns the statistics of a node and why it is not dialled, see `Host::node_status`.
+	pub fn node_status(&self, node: &str) -> Result<Option<EntryStatus>, Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			host.node_status(node)
+		} else {
+			Ok(None)
+		}
+	}
+
+	/// Take the claims of a node at face value or stop doing so, see `Host::set_trusted`. Returns `false` if the node
+	/// is not known.
+	pub fn set_trusted(&self, node: &str, trusted: bool) -> Result<bool, Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			host.set_trusted(node, trusted)
+		} else {
+			Ok(false)
+		}
+	}
+
+	/// Enable or disable saving the node table, see `Host::set_node_persistence`.
+	pub fn set_node_persistence(&self, persist: bool, load_saved: bool) {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			host.set_node_persistence(persist, load_saved);
+		}
+	}
+
+	/// Save the node table again after saving was given up because of repeated errors, e.g. once the permissions of
+	/// the data directory are fixed. Returns `false` if saving was not given up.
+	pub fn reset_persistence(&self) -> bool {
+		let host = self.host.read();
+		host.as_ref().map_or(false, |h| h.reset_persistence())
+	}
+
+	/// Start a new compatibility epoch, e.g. when a fork activates. Nodes found to run an incompatible protocol
+	/// before are dialled again. Returns their number.
+	pub fn bump_compat_epoch(&self) -> usize {
+		let host = self.host.read();
+		host.as_ref().map_or(0, |h| h.bump_compat_epoch())
+	}
+
+	/// Set the chain we are on, nodes that advertised another one are dialled last, see
+	/// `NodeTable::set_local_chain_info`. Returns `false` if the network is not started.
+	pub fn set_local_chain_info(&self, info: PeerChainInfo) -> bool {
+		let host = self.host.read();
+		match *host {
+			Some(ref h) => {
+				h.set_local_chain_info(info);
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Install a policy deciding on the nodes added to the node table from now on, see
+	/// `NodeTable::set_admission_hook`. Saved nodes loaded at start are not checked. The hook is called on the
+	/// maintenance tick without the node table locked, so it may call back into the service. New nodes are added
+	/// once it accepted them. Returns `false` if the network is not started.
+	pub fn set_admission_hook(&self, hook: AdmissionHook) -> bool {
+		let host = self.host.read();
+		match *host {
+			Some(ref h) => {
+				h.set_admission_hook(hook);
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Report the node table quality to `callback` periodically, see `Host::set_table_telemetry`. Returns `false` if
+	/// the network is not started.
+	pub fn set_table_telemetry(&self, callback: TelemetryCallback) -> bool {
+		let host = self.host.read();
+		match *host {
+			Some(ref h) => {
+				h.set_table_telemetry(callback);
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Tag `key` of a node recorded by a protocol handler through `PeerAnnotator::tag`.
+	pub fn node_tag(&self, id: &NodeId, key: &str) -> Option<u32> {
+		let host = self.host.read();
+		host.as_ref().and_then(|h| h.node_tag(id, key))
+	}
+
+	/// At most `limit` of the good nodes with tag `key`, lowest values first, e.g. the fastest servers of a
+	/// protocol.
+	pub fn top_by_tag(&self, key: &str, limit: usize) -> Vec<NodeId> {
+		let host = self.host.read();
+		host.as_ref().map_or_else(Vec::new, |h| h.top_by_tag(key, limit))
+	}
+
+	/// Ids of the known nodes that are or were at `ip`, including earlier addresses of nodes that moved.
+	pub fn ids_seen_on(&self, ip: &IpAddr) -> Vec<NodeId> {
+		let host = self.host.read();
+		host.as_ref().map_or_else(Vec::new, |h| h.ids_seen_on(ip))
+	}
+
+	/// Ids of the known nodes in the subnet of a node that reported the same client version, including it, which
+	/// are likely run by one operator. `None` if there are none or the network is not started.
+	pub fn cluster_of(&self, id: &NodeId) -> Option<Vec<NodeId>> {
+		let host = self.host.read();
+		host.as_ref().and_then(|h| h.cluster_of(id))
+	}
+
+	/// Returns the enode URLs of at most `limit` of the best public nodes, for sharing as boot nodes.
+	pub fn best_enodes(&self, limit: usize) -> Vec<String> {
+		let host = self.host.read();
+		host.as_ref().map_or_else(Vec::new, |h| h.best_enodes(limit))
+	}
+
+	/// Returns a newline-separated list of the enode URLs of at most `limit` reliable public nodes, for publishing.
+	pub fn export_node_list(&self, limit: usize) -> String {
+		let host = self.host.read();
+		host.as_ref().map_or_else(String::new, |h| h.export_node_list(limit))
+	}
+
+	/// Returns a summary of the node table, discovery and session state, `None` if the network is not running.
+	pub fn net_health(&self) -> Option<NetHealth> {
+		let host = self.host.read();
+		host.as_ref().map(|h| h.net_health())
+	}
+
+	/// Add a node to the node table, optionally dialing it right away. Returns the normalized enode URL and whether
+	/// the node is new to the table.
+	pub fn add_node(&self, node: &str, connect_now: bool) -> Result<(String, bool), Error> {
+		let host = self.host.read();
+		if let Some(ref host) = *host {
+			host.add_node_url(node, connect_now)
+		} else {
+			normalize_node_url(node).map(|url| (url, false))
+		}
+	}
+
+	/// Add the nodes of a list in `format` to the node table. `source` is the path of a file if one exists there,
+	/// the list itself otherwise. Nothing is added while the network is not running.
+	pub fn import_node_list(&self, source: &str, format: ListFormat) -> Result<IngestStats, Error> {
+		let host = self.host.read();
+		let host = match *host {
+			Some(ref host) => host,
+			None => return Ok(IngestStats::default()),
+		};
+		if Path::new(source).is_file() {
+			host.import_node_list(File::open(source)?, format)
+		} else {
+			host.import_node_list(source.as_bytes(), format)
+		}
+	}
+
 	/// Set the non-reserved peer mode.
 	pub fn set_non_reserved_mode(&self, mode: NonReservedPeerMode) {
 		let host = self.host.read();
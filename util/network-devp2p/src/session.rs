@@ -37,7 +37,15 @@ use snappy;
 const PING_TIMEOUT_SEC: Duration = Duration::from_secs(60);
 const PING_INTERVAL_SEC: Duration = Duration::from_secs(120);
 const MIN_PROTOCOL_VERSION: u32 = 4;
-const MIN_COMPRESSION_PROTOCOL_VERSION: u32 = 5;
+/// Lowest devp2p version that compresses packets with snappy.
+pub const MIN_COMPRESSION_PROTOCOL_VERSION: u32 = 5;
+
+/// Returns the devp2p version of a session where we announced `announced` and the peer `remote`, and whether its
+/// packets are compressed.
+pub fn negotiate(announced: u32, remote: u32) -> (u32, bool) {
+	let protocol = ::std::cmp::min(announced, remote);
+	(protocol, protocol >= MIN_COMPRESSION_PROTOCOL_VERSION)
+}
 
 #[derive(Debug, Clone)]
 enum ProtocolState {
@@ -55,6 +63,14 @@ pub struct Session {
 	pub info: SessionInfo,
 	/// Session ready flag. Set after successfull Hello packet exchange
 	had_hello: bool,
+	/// Time of the successfull Hello packet exchange
+	ready_time: Option<Instant>,
+	/// TCP port the peer advertised in the Hello packet. Zero if the peer is not listening.
+	listen_port: u16,
+	/// Set once the host has recorded this inbound session in the node table.
+	recorded: bool,
+	/// Id announced in the Hello packet of a dialled peer if it was not the one the handshake proved.
+	mismatched_id: Option<NodeId>,
 	/// Session is no longer active flag.
 	expired: bool,
 	ping_time: Instant,
@@ -63,6 +79,10 @@ pub struct Session {
 	// Protocol states -- accumulates pending packets until signaled as ready.
 	protocol_states: HashMap<ProtocolId, ProtocolState>,
 	compression: bool,
+	/// devp2p version announced in our Hello packet.
+	hello_version: u32,
+	/// devp2p version announced in the Hello packet of the peer.
+	remote_version: u32,
 }
 
 enum State {
@@ -99,9 +119,10 @@ const PACKET_LAST: u8 = 0x7f;
 
 impl Session {
 	/// Create a new session out of comepleted handshake. This clones the handshake connection object
-	/// and leaves the handhsake in limbo to be deregistered from the event loop.
+	/// and leaves the handhsake in limbo to be deregistered from the event loop. `hello_version` is the devp2p
+	/// version announced to the peer, at most the one of the host.
 	pub fn new<Message>(io: &IoContext<Message>, socket: TcpStream, token: StreamToken, id: Option<&NodeId>,
-		nonce: &H256, host: &HostInfo) -> Result<Session, Error>
+		nonce: &H256, host: &HostInfo, hello_version: u32) -> Result<Session, Error>
 		where Message: Send + Clone + Sync + 'static {
 		let originated = id.is_some();
 		let mut handshake = Handshake::new(token, id, socket, nonce).expect("Can't create handshake");
@@ -110,6 +131,10 @@ impl Session {
 		Ok(Session {
 			state: State::Handshake(handshake),
 			had_hello: false,
+			ready_time: None,
+			listen_port: 0,
+			recorded: false,
+			mismatched_id: None,
 			info: SessionInfo {
 				id: id.cloned(),
 				client_version: String::new(),
@@ -126,6 +151,8 @@ impl Session {
 			expired: false,
 			protocol_states: HashMap::new(),
 			compression: false,
+			hello_version: ::std::cmp::min(hello_version, host.protocol_version),
+			remote_version: 0,
 		})
 	}
 
@@ -159,6 +186,42 @@ impl Session {
 		self.had_hello
 	}
 
+	/// Returns the devp2p version the peer announced in its Hello packet, zero before it arrived.
+	pub fn remote_protocol_version(&self) -> u32 {
+		self.remote_version
+	}
+
+	/// Check if the packets of the session are compressed.
+	pub fn compression(&self) -> bool {
+		self.compression
+	}
+
+	/// Returns the time elapsed since the session became ready.
+	pub fn ready_duration(&self) -> Option<Duration> {
+		self.ready_time.map(|t| t.elapsed())
+	}
+
+	/// Returns the TCP port the peer advertised in its Hello packet. Zero if unknown or the peer is not listening.
+	pub fn listen_port(&self) -> u16 {
+		self.listen_port
+	}
+
+	/// Returns the id announced by a dialled peer if it was not the one dialled. The handshake proved the dialled
+	/// id, so this is a protocol violation.
+	pub fn mismatched_id(&self) -> Option<&NodeId> {
+		self.mismatched_id.as_ref()
+	}
+
+	/// Check if the session has been recorded in the node table.
+	pub fn is_recorded(&self) -> bool {
+		self.recorded
+	}
+
+	/// Mark the session as recorded in the node table.
+	pub fn set_recorded(&mut self) {
+		self.recorded = true;
+	}
+
 	/// Mark this session as inactive to be deleted lated.
 	pub fn set_expired(&mut self) {
 		self.expired = true;
@@ -413,7 +476,7 @@ impl Session {
 		let mut rlp = RlpStream::new();
 		rlp.append_raw(&[PACKET_HELLO as u8], 0);
 		rlp.begin_list(5)
-			.append(&host.protocol_version)
+			.append(&self.hello_version)
 			.append(&host.client_version())
 			.append_list(&host.capabilities)
 			.append(&host.local_endpoint.address.port())
@@ -426,7 +489,13 @@ impl Session {
 		let protocol = rlp.val_at::<u32>(0)?;
 		let client_version = rlp.val_at::<String>(1)?;
 		let peer_caps: Vec<PeerCapabilityInfo> = rlp.list_at(2)?;
+		let listen_port = rlp.val_at::<u16>(3).unwrap_or(0);
 		let id = rlp.val_at::<NodeId>(4)?;
+		if self.info.originated && self.info.id.as_ref().map_or(false, |expected| *expected != id) {
+			debug!(target: "network", "Dialled {:?} but the peer announced {}", self.info.id, id);
+			self.mismatched_id = Some(id);
+			return Err(From::from(self.disconnect(io, DisconnectReason::UnexpectedIdentity)));
+		}
 
 		// Intersect with host capabilities
 		// Leave only highset mutually supported capability version
@@ -463,11 +532,13 @@ impl Session {
 			i += 1;
 		}
 		debug!(target: "network", "Hello: {} v{} {} {:?}", client_version, protocol, id, caps);
-		let protocol = ::std::cmp::min(protocol, host.protocol_version);
+		self.remote_version = protocol;
+		let (protocol, compression) = negotiate(self.hello_version, protocol);
 		self.info.protocol_version = protocol;
 		self.info.client_version = client_version;
 		self.info.capabilities = caps;
 		self.info.peer_capabilities = peer_caps;
+		self.listen_port = listen_port;
 		if self.info.capabilities.is_empty() {
 			trace!(target: "network", "No common capabilities with peer.");
 			return Err(From::from(self.disconnect(io, DisconnectReason::UselessPeer)));
@@ -476,9 +547,10 @@ impl Session {
 			trace!(target: "network", "Peer protocol version mismatch: {}", protocol);
 			return Err(From::from(self.disconnect(io, DisconnectReason::UselessPeer)));
 		}
-		self.compression = protocol >= MIN_COMPRESSION_PROTOCOL_VERSION;
+		self.compression = compression;
 		self.send_ping(io)?;
 		self.had_hello = true;
+		self.ready_time = Some(Instant::now());
 		Ok(())
 	}
 
@@ -0,0 +1,333 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Node table shared by the IO handlers, with mutations that do not wait for the lock.
+
+use std::collections::{HashSet, VecDeque};
+use std::mem;
+use std::time::Duration;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(any(test, feature = "test-helpers"))]
+use node_table::Node;
+use node_table::{NodeTable, NodeId, Outcome};
+
+/// Maximum number of mutations waiting for the table lock. The oldest are dropped beyond it.
+pub const MAX_DEFERRED_MUTATIONS: usize = 1024;
+
+type Mutation = Box<FnMut(&mut NodeTable) + Send>;
+
+/// Whether a `try_*` mutation was applied right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryMutation {
+	/// The lock was free. The queued mutations were applied first.
+	Applied,
+	/// The lock was held. The mutation is applied by the next `apply_deferred` or `try_*` call that gets the lock.
+	Deferred,
+}
+
+#[derive(Default)]
+struct Deferred {
+	queue: VecDeque<Mutation>,
+	dropped: usize,
+}
+
+/// Node table behind a lock. The IO threads use the `try_*` mutations so that a save in progress or another slow lock
+/// holder does not stall packet processing. On contention they are queued and applied in the order they were made,
+/// also before any blocking `write`.
+pub struct SharedNodeTable {
+	table: RwLock<NodeTable>,
+	deferred: Mutex<Deferred>,
+}
+
+impl SharedNodeTable {
+	pub fn new(table: NodeTable) -> Self {
+		SharedNodeTable {
+			table: RwLock::new(table),
+			deferred: Mutex::new(Deferred::default()),
+		}
+	}
+
+	/// Wait for read access. Queued mutations are only applied by the next write.
+	pub fn read<'a>(&'a self) -> RwLockReadGuard<'a, NodeTable> {
+		self.table.read()
+	}
+
+	/// Wait for write access. The queued mutations are applied first, so that they keep their order with the
+	/// mutations made through the returned guard.
+	pub fn write<'a>(&'a self) -> RwLockWriteGuard<'a, NodeTable> {
+		let mut table = self.table.write();
+		self.apply_queued(&mut table);
+		table
+	}
+
+	/// Apply `f` if the table is not locked, queue it otherwise.
+	pub fn try_mutate<F>(&self, mut f: F) -> TryMutation where F: FnMut(&mut NodeTable) + Send + 'static {
+		match self.table.try_write() {
+			Some(mut table) => {
+				self.apply_queued(&mut table);
+				f(&mut table);
+				TryMutation::Applied
+			},
+			None => {
+				let mut deferred = self.deferred.lock();
+				if deferred.queue.len() >= MAX_DEFERRED_MUTATIONS {
+					deferred.queue.pop_front();
+					deferred.dropped += 1;
+				}
+				deferred.queue.push_back(Box::new(f));
+				TryMutation::Deferred
+			},
+		}
+	}
+
+	/// See `NodeTable::note_failure`. Failures of nodes not in the table are logged with `site`, whenever the
+	/// mutation is applied.
+	pub fn try_note_failure(&self, id: &NodeId, site: &'static str) -> TryMutation {
+		let id = id.clone();
+		self.try_mutate(move |table| table.note_failure(&id).log_unknown(&id, site))
+	}
+
+	/// See `NodeTable::note_success`.
+	pub fn try_note_success(&self, id: &NodeId) -> TryMutation {
+		let id = id.clone();
+		self.try_mutate(move |table| table.note_success(&id))
+	}
+
+	/// See `NodeTable::note_outcomes`.
+	pub fn try_note_outcomes(&self, outcomes: Vec<(NodeId, Outcome)>) -> TryMutation {
+		let mut outcomes = Some(outcomes);
+		self.try_mutate(move |table| {
+			if let Some(outcomes) = outcomes.take() {
+				table.note_outcomes(outcomes);
+			}
+		})
+	}
+
+	/// See `NodeTable::note_disconnect`.
+	pub fn try_note_disconnect(&self, id: &NodeId, remote: bool) -> TryMutation {
+		let id = id.clone();
+		self.try_mutate(move |table| table.note_disconnect(&id, remote))
+	}
+
+	/// See `NodeTable::session_closed`.
+	pub fn try_session_closed(&self, id: &NodeId) -> TryMutation {
+		let id = id.clone();
+		self.try_mutate(move |table| table.session_closed(&id))
+	}
+
+	/// See `NodeTable::note_latency`.
+	pub fn try_note_latency(&self, id: &NodeId, latency: Duration) -> TryMutation {
+		let id = id.clone();
+		self.try_mutate(move |table| table.note_latency(&id, latency))
+	}
+
+	/// See `NodeTable::update_node`.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn try_update<F>(&self, id: &NodeId, f: F) -> TryMutation where F: FnOnce(&mut Node) + Send + 'static {
+		let id = id.clone();
+		let mut f = Some(f);
+		self.try_mutate(move |table| {
+			if let Some(f) = f.take() {
+				table.update_node(&id, f);
+			}
+		})
+	}
+
+	/// Wait for the lock and apply the queued mutations, e.g. on the maintenance tick. Returns the number applied.
+	pub fn apply_deferred(&self) -> usize {
+		self.apply_queued(&mut self.table.write())
+	}
+
+	/// Call the deferred admission hook on the nodes waiting for it without holding the lock, so that the hook may
+	/// use the table, and add the accepted ones, see `TableState::set_deferred_admission_hook`. The ones in
+	/// `reserved` are kept from eviction. Returns the number of nodes added.
+	pub fn admit_awaiting(&self, reserved: &HashSet<NodeId>) -> usize {
+		let (hook, awaiting) = match self.write().take_awaiting_admission() {
+			Some(awaiting) => awaiting,
+			None => return 0,
+		};
+		let decided = awaiting.into_iter()
+			.map(|(node, source)| {
+				let admission = hook(&node, source);
+				(node, source, admission)
+			})
+			.collect();
+		self.write().admit_decided(decided, reserved)
+	}
+
+	/// Mutations dropped because the queue was full.
+	pub fn deferred_dropped(&self) -> usize {
+		self.deferred.lock().dropped
+	}
+
+	fn apply_queued(&self, table: &mut NodeTable) -> usize {
+		// taken out so that mutations made meanwhile are queued without waiting
+		let queue = mem::replace(&mut self.deferred.lock().queue, VecDeque::new());
+		let count = queue.len();
+		for mut f in queue {
+			f(table);
+		}
+		count
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{mpsc, Arc};
+	use std::thread;
+	use std::str::FromStr;
+	use network::IpFilter;
+	use discovery::{NodeEntry, TableUpdates};
+	use node_table::{Admission, Node, NodeEndpoint, NodeSource, RejectReason, SessionMeta};
+	use super::*;
+
+	fn shared_table(count: u64) -> Arc<SharedNodeTable> {
+		let mut table = NodeTable::new(None);
+		for i in 1..count + 1 {
+			table.add_node(Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i)).unwrap()));
+		}
+		Arc::new(SharedNodeTable::new(table))
+	}
+
+	/// Hold the write lock from another thread until the returned sender is dropped.
+	fn hold_lock(shared: &Arc<SharedNodeTable>) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+		let (locked_tx, locked_rx) = mpsc::channel();
+		let (release_tx, release_rx) = mpsc::channel::<()>();
+		let shared = shared.clone();
+		let handle = thread::spawn(move || {
+			let _table = shared.table.write();
+			locked_tx.send(()).unwrap();
+			let _ = release_rx.recv();
+		});
+		locked_rx.recv().unwrap();
+		(release_tx, handle)
+	}
+
+	#[test]
+	fn mutations_applied_when_unlocked() {
+		let shared = shared_table(1);
+		let id = NodeId::from(1);
+		assert_eq!(shared.try_note_success(&id), TryMutation::Applied);
+		assert_eq!(shared.try_note_latency(&id, Duration::from_millis(100)), TryMutation::Applied);
+		let table = shared.read();
+		assert_eq!(table.get(&id).unwrap().successes, 1);
+		assert_eq!(table.get(&id).unwrap().latency, Some(Duration::from_millis(100)));
+	}
+
+	#[test]
+	fn deferred_mutations_keep_order() {
+		let shared = shared_table(2);
+		let (id1, id2) = (NodeId::from(1), NodeId::from(2));
+		let (release, handle) = hold_lock(&shared);
+		for i in 0..10 {
+			assert_eq!(shared.try_update(&id1, move |node| node.attempts = i), TryMutation::Deferred);
+			shared.try_update(&id2, move |node| {
+				let version = format!("{}{}", node.client_version.take().unwrap_or_default(), i);
+				node.client_version = Some(version);
+			});
+		}
+		shared.try_note_failure(&id1, "test");
+		drop(release);
+		handle.join().unwrap();
+
+		// the next mutation that gets the lock applies the queued ones first
+		assert_eq!(shared.try_update(&id1, |node| node.attempts += 1), TryMutation::Applied);
+		assert_eq!(shared.apply_deferred(), 0);
+		let table = shared.read();
+		assert_eq!((table.get(&id1).unwrap().attempts, table.get(&id1).unwrap().failures), (10, 1));
+		assert_eq!(table.get(&id2).unwrap().client_version, Some("0123456789".to_owned()));
+	}
+
+	#[test]
+	fn deferred_mutations_before_blocking_write() {
+		let shared = shared_table(1);
+		let id = NodeId::from(1);
+		let (release, handle) = hold_lock(&shared);
+		assert_eq!(shared.try_update(&id, |node| node.attempts = 5), TryMutation::Deferred);
+		shared.try_note_failure(&id, "test");
+		drop(release);
+		handle.join().unwrap();
+
+		// a blocking mutation made after the queued ones is applied after them
+		shared.write().update_node(&id, |node| node.attempts += 1);
+		shared.write().note_success(&id);
+		assert_eq!(shared.apply_deferred(), 0);
+		let table = shared.read();
+		let node = table.get(&id).unwrap();
+		assert_eq!((node.attempts, node.failures, node.successes), (6, 1, 1));
+		assert_eq!(table.stats(&IpFilter::default()).backed_off, 0);
+	}
+
+	#[test]
+	fn admission_hook_called_without_lock() {
+		let shared = shared_table(1);
+		let table = Arc::downgrade(&shared);
+		// the hook reads the table, which would not return if it were called with the lock held
+		shared.write().set_deferred_admission_hook(Box::new(move |node: &Node, _| {
+			let known = table.upgrade().map_or(0, |table| table.read().stats(&IpFilter::default()).total);
+			match node.id.low_u64() % 2 == 0 && known > 0 {
+				true => Admission::Accept,
+				false => Admission::Reject { reason: "odd".into() },
+			}
+		}));
+		let node = |i: u64| Node::new(NodeId::from(i), NodeEndpoint::from_str(&format!("22.99.{}.1:30303", i)).unwrap());
+
+		// the new nodes wait for the hook
+		shared.write().add_node(node(2));
+		shared.write().add_node(node(3));
+		let entry = NodeEntry::new(NodeId::from(4), node(4).endpoint);
+		let updates = TableUpdates {
+			added: vec![(NodeId::from(4), entry)].into_iter().collect(),
+			removed: HashSet::new(),
+			verified: HashSet::new(),
+		};
+		let outcome = shared.write().update(updates, &HashSet::new());
+		assert_eq!(outcome.rejected, vec![(NodeId::from(4), RejectReason::AwaitingAdmission)]);
+		let meta = SessionMeta { client_version: "Parity/v1.11.0".into(), capabilities: vec![] };
+		assert!(!shared.write().note_inbound(&NodeId::from(6), node(6).endpoint, meta));
+		assert_eq!(shared.read().stats(&IpFilter::default()).total, 1);
+
+		// and are added once it accepted them
+		assert_eq!(shared.admit_awaiting(&HashSet::new()), 3);
+		assert_eq!(shared.admit_awaiting(&HashSet::new()), 0);
+		let table = shared.read();
+		assert!([2, 4, 6].iter().all(|&i| table.contains(&NodeId::from(i))) && !table.contains(&NodeId::from(3)));
+		assert_eq!(table.get(&NodeId::from(4)).unwrap().source, NodeSource::Discovery);
+		assert_eq!(table.get(&NodeId::from(6)).unwrap().successes, 1);
+		assert_eq!(table.stats(&IpFilter::default()).admission_rejected.get("odd"), Some(&1));
+	}
+
+	#[test]
+	fn deferred_mutations_bounded() {
+		let shared = shared_table(1);
+		let id = NodeId::from(1);
+		let applied = Arc::new(Mutex::new(Vec::new()));
+		let (release, handle) = hold_lock(&shared);
+		for i in 0..MAX_DEFERRED_MUTATIONS + 10 {
+			let applied = applied.clone();
+			shared.try_mutate(move |_| applied.lock().push(i));
+		}
+		shared.try_note_success(&id);
+		drop(release);
+		handle.join().unwrap();
+
+		// the oldest are dropped
+		assert_eq!(shared.deferred_dropped(), 11);
+		assert_eq!(shared.apply_deferred(), MAX_DEFERRED_MUTATIONS);
+		assert_eq!(*applied.lock(), (11..MAX_DEFERRED_MUTATIONS + 10).collect::<Vec<_>>());
+		assert_eq!(shared.read().get(&id).unwrap().successes, 1);
+	}
+}
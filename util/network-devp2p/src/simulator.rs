@@ -0,0 +1,213 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Peer selection simulator for evaluating node table configurations.
+//!
+//! A synthetic population of peers with known reliability is added to a node table. Every round the table picks
+//! dial candidates, the outcome of each dial is sampled from the ground truth and fed back to the table, and the
+//! simulated time moves on. Peers may go away for good between rounds.
+
+use std::cmp::min;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use network::{IpFilter, NodeTableConfig};
+use node_table::{NodeTable, Node, NodeEndpoint, NodeId, NodeSource, DialPolicy};
+use clock::ManualClock;
+
+/// Peers answering at least this fraction of dials count as good.
+pub const GOOD_RELIABILITY: f64 = 0.9;
+
+/// Synthetic population and dialing behaviour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+	/// Number of peers known to the table.
+	pub peers: usize,
+	/// Fraction of the peers that never answer, e.g. stale discovery entries.
+	pub dead: f64,
+	/// Reliability of the answering peers is uniformly distributed between this and 1.
+	pub min_reliability: f64,
+	/// Probability that an answering peer goes away for good before each round.
+	pub churn: f64,
+	/// Probability that an established session ends before each round.
+	pub session_drop: f64,
+	/// Number of sessions to keep, dials are only made while there are fewer.
+	pub target_sessions: usize,
+	/// Number of rounds to simulate.
+	pub rounds: usize,
+	/// Maximum number of dials per round.
+	pub dials_per_round: usize,
+	/// Simulated time between rounds.
+	pub round_interval: Duration,
+	/// Seed for the population and the dial outcomes.
+	pub seed: u32,
+}
+
+impl Default for Scenario {
+	fn default() -> Self {
+		Scenario {
+			peers: 200,
+			dead: 0.3,
+			min_reliability: 0.5,
+			churn: 0.0,
+			session_drop: 0.1,
+			target_sessions: 25,
+			rounds: 200,
+			dials_per_round: 5,
+			round_interval: Duration::from_secs(60),
+			seed: 1,
+		}
+	}
+}
+
+/// Outcome of a simulation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+	/// Number of dials.
+	pub dials: usize,
+	/// Dials of peers that are gone.
+	pub wasted_dials: usize,
+	/// Dials that established a session.
+	pub successes: usize,
+	/// Simulated time until the first session with a good peer, `None` if there was none.
+	pub first_good_peer: Option<Duration>,
+}
+
+impl Metrics {
+	/// Fraction of the dials spent on peers that are gone.
+	pub fn wasted_fraction(&self) -> f64 {
+		if self.dials == 0 {
+			0.0
+		} else {
+			self.wasted_dials as f64 / self.dials as f64
+		}
+	}
+}
+
+struct Peer {
+	id: NodeId,
+	reliability: f64,
+	alive: bool,
+	connected: bool,
+}
+
+/// Run `scenario` against a table using `config`.
+pub fn simulate(scenario: &Scenario, config: NodeTableConfig) -> Metrics {
+	let mut rng = XorShiftRng::from_seed([scenario.seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05]);
+	let clock = ManualClock::new();
+	let mut table = NodeTable::with_config(None, false, config);
+	table.set_clock(Box::new(clock.clone()));
+
+	let mut peers = Vec::with_capacity(scenario.peers);
+	for i in 0..scenario.peers {
+		let alive = rng.next_f64() >= scenario.dead;
+		let reliability = if alive { rng.gen_range(scenario.min_reliability, 1.0) } else { 0.0 };
+		let id = NodeId::from(i as u64 + 1);
+		let endpoint = NodeEndpoint::from_str(&format!("22.{}.{}.1:30303", i / 256, i % 256)).expect("valid address");
+		let mut node = Node::new(id, endpoint);
+		node.source = NodeSource::Discovery;
+		table.add_node(node);
+		peers.push(Peer { id: id, reliability: reliability, alive: alive, connected: false });
+	}
+	let index: HashMap<NodeId, usize> = peers.iter().enumerate().map(|(i, p)| (p.id, i)).collect();
+
+	let policy = DialPolicy::default();
+	let filter = IpFilter::default();
+	let mut metrics = Metrics::default();
+	for round in 0..scenario.rounds {
+		for peer in peers.iter_mut().filter(|p| p.alive) {
+			if rng.next_f64() < scenario.churn {
+				peer.alive = false;
+			}
+			if !peer.alive || rng.next_f64() < scenario.session_drop {
+				peer.connected = false;
+			}
+		}
+		let sessions = peers.iter().filter(|p| p.connected).count();
+		let dials = min(scenario.dials_per_round, scenario.target_sessions.saturating_sub(sessions));
+		let candidates: Vec<NodeId> = table.select_for(&policy, &filter, usize::max_value(), None).into_iter()
+			.filter(|id| !peers[index[id]].connected)
+			.take(dials)
+			.collect();
+		for id in candidates {
+			let peer = &mut peers[index[&id]];
+			table.update_node(&id, |node| node.attempts += 1);
+			metrics.dials += 1;
+			if !peer.alive {
+				metrics.wasted_dials += 1;
+				table.note_failure(&id);
+			} else if rng.next_f64() < peer.reliability {
+				metrics.successes += 1;
+				peer.connected = true;
+				table.note_success(&id);
+				if peer.reliability >= GOOD_RELIABILITY && metrics.first_good_peer.is_none() {
+					metrics.first_good_peer = Some(scenario.round_interval * round as u32);
+				}
+			} else {
+				table.note_failure(&id);
+			}
+		}
+		clock.advance(scenario.round_interval);
+	}
+	metrics
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Sum of the metrics over several seeds, the first good peer is the latest one.
+	fn run(scenario: &Scenario, config: &NodeTableConfig) -> Metrics {
+		let mut total = Metrics::default();
+		for seed in 1..21 {
+			let metrics = simulate(&Scenario { seed: seed, ..scenario.clone() }, config.clone());
+			total.dials += metrics.dials;
+			total.wasted_dials += metrics.wasted_dials;
+			total.successes += metrics.successes;
+			total.first_good_peer = match (total.first_good_peer, metrics.first_good_peer) {
+				(Some(a), Some(b)) => Some(::std::cmp::max(a, b)),
+				(a, b) => a.or(b),
+			};
+		}
+		total
+	}
+
+	fn recency_aware() -> NodeTableConfig {
+		NodeTableConfig { recent_contact: Some(Duration::from_secs(30 * 60)), ..Default::default() }
+	}
+
+	#[test]
+	fn recency_avoids_dead_entries() {
+		// mostly stale discovery entries and flaky peers, a failure of a flaky peer ranks it after the never tried
+		// entries unless its recent sessions count
+		let scenario = Scenario { dead: 0.6, min_reliability: 0.2, session_drop: 0.05, ..Default::default() };
+		let default = run(&scenario, &NodeTableConfig::default());
+		let recency = run(&scenario, &recency_aware());
+		assert!(default.first_good_peer.is_some() && recency.first_good_peer.is_some());
+		assert!(recency.wasted_fraction() < default.wasted_fraction(), "default {:?}, recency aware {:?}", default, recency);
+	}
+
+	#[test]
+	fn recency_with_session_turnover() {
+		// short sessions and slow churn, peers that answered recently are worth dialing again
+		let scenario = Scenario { dead: 0.5, min_reliability: 0.3, churn: 0.005, session_drop: 0.3, ..Default::default() };
+		let default = run(&scenario, &NodeTableConfig::default());
+		let recency = run(&scenario, &recency_aware());
+		assert!(default.first_good_peer.is_some() && recency.first_good_peer.is_some());
+		assert!(recency.wasted_fraction() < default.wasted_fraction(), "default {:?}, recency aware {:?}", default, recency);
+	}
+}
@@ -0,0 +1,378 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Plain-text node table commands for operators of headless nodes, see `Host::node_table_command`.
+
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use network::IpFilter;
+use node_table::{parse_node_id, DialExclusion, EntryStatus, NodeId, NodeIdExt, NodeTableEntry, NodeTableStats, TableState};
+use shared_table::SharedNodeTable;
+
+/// Number of entries listed by `nodetable list` without a count.
+pub const DEFAULT_LIST_LIMIT: usize = 20;
+
+/// Usage shown for a command that cannot be parsed.
+pub const USAGE: &str = "Usage: nodetable list [n] | nodetable info <id> | nodetable ban <id> <secs> | nodetable remove <id> | nodetable save";
+
+/// Node given to a command, as a full id or enode URL or as an abbreviated id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeRef {
+	/// Full id, which may be unknown to the table.
+	Id(NodeId),
+	/// Prefix of the id of a node in the table, see `TableState::find_by_prefix`.
+	Prefix(String),
+}
+
+impl FromStr for NodeRef {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let hex = if s.starts_with("0x") { &s[2..] } else { s };
+		match parse_node_id(s) {
+			Ok(id) => Ok(NodeRef::Id(id)),
+			Err(_) if !hex.is_empty() && hex.len() < 128 && hex.chars().all(|c| c.is_digit(16)) => Ok(NodeRef::Prefix(s.to_owned())),
+			Err(_) => Err(format!("Invalid node id: {}", s)),
+		}
+	}
+}
+
+impl NodeRef {
+	/// The full id, looked up in `table` for a prefix.
+	pub fn resolve(&self, table: &TableState) -> Result<NodeId, String> {
+		match *self {
+			NodeRef::Id(id) => Ok(id),
+			NodeRef::Prefix(ref prefix) => table.find_by_prefix(prefix).map(|node| node.id).map_err(|e| format!("{}: {}", e, prefix)),
+		}
+	}
+}
+
+/// Command parsed from a line like `nodetable ban <id> <secs>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableCommand {
+	/// List the first entries in dial order, useless ones included.
+	List(usize),
+	/// Show everything known about a node.
+	Info(NodeRef),
+	/// Ban a node and disconnect it.
+	Ban(NodeRef, Duration),
+	/// Remove a node from the table and disconnect it.
+	Remove(NodeRef),
+	/// Save the table if it changed.
+	Save,
+}
+
+impl FromStr for TableCommand {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let words: Vec<&str> = s.split_whitespace().collect();
+		let id = |word: &str| word.parse::<NodeRef>();
+		let command = match words.as_slice() {
+			["nodetable", "list"] => TableCommand::List(DEFAULT_LIST_LIMIT),
+			["nodetable", "list", count] => TableCommand::List(count.parse().map_err(|_| format!("Invalid count: {}", count))?),
+			["nodetable", "info", node] => TableCommand::Info(id(node)?),
+			["nodetable", "ban", node, secs] => {
+				let secs = secs.parse().map_err(|_| format!("Invalid number of seconds: {}", secs))?;
+				TableCommand::Ban(id(node)?, Duration::from_secs(secs))
+			},
+			["nodetable", "remove", node] => TableCommand::Remove(id(node)?),
+			["nodetable", "save"] => TableCommand::Save,
+			_ => return Err(USAGE.into()),
+		};
+		Ok(command)
+	}
+}
+
+impl TableCommand {
+	/// Run the command against `table`. `disconnect` is called for a banned or removed node after the table lock is
+	/// released.
+	pub fn execute<F>(&self, table: &SharedNodeTable, mut disconnect: F) -> String where F: FnMut(&NodeId) {
+		let now = SystemTime::now();
+		match *self {
+			TableCommand::List(limit) => format_entries(&table.read().entries_with_meta(limit, true), now),
+			TableCommand::Info(ref node) => {
+				let table = table.read();
+				let id = match node.resolve(&table) {
+					Ok(id) => id,
+					Err(e) => return format!("{}\n", e),
+				};
+				let status = table.entry_status(&id).unwrap_or_default();
+				match status.entry {
+					Some(ref entry) => format_info(entry, &status, now),
+					None => format!("Node {} is not in the table\n", id.short()),
+				}
+			},
+			TableCommand::Ban(ref node, duration) => {
+				let id = match node.resolve(&table.read()) {
+					Ok(id) => id,
+					Err(e) => return format!("{}\n", e),
+				};
+				let known = {
+					let mut table = table.write();
+					table.ban(&id, duration, "banned by the node table command");
+					table.contains(&id)
+				};
+				disconnect(&id);
+				if known {
+					format!("Banned {} for {}s\n", id.short(), duration.as_secs())
+				} else {
+					format!("Banned {} for {}s, it is not in the table\n", id.short(), duration.as_secs())
+				}
+			},
+			TableCommand::Remove(ref node) => {
+				let id = match node.resolve(&table.read()) {
+					Ok(id) => id,
+					Err(e) => return format!("{}\n", e),
+				};
+				let removed = table.write().remove_node(&id);
+				disconnect(&id);
+				if removed {
+					format!("Removed {}\n", id.short())
+				} else {
+					format!("Node {} is not in the table\n", id.short())
+				}
+			},
+			TableCommand::Save => {
+				let mut table = table.write();
+				let before = table.stats(&IpFilter::default());
+				table.save();
+				format_save(&before, &table.stats(&IpFilter::default()))
+			},
+		}
+	}
+}
+
+/// Time relative to `now`, e.g. `120s ago`.
+fn age(time: SystemTime, now: SystemTime) -> String {
+	format!("{}s ago", now.duration_since(time).unwrap_or(Duration::from_secs(0)).as_secs())
+}
+
+fn seconds_left(time: SystemTime, now: SystemTime) -> u64 {
+	time.duration_since(now).unwrap_or(Duration::from_secs(0)).as_secs()
+}
+
+fn describe_exclusion(exclusion: &DialExclusion, now: SystemTime) -> String {
+	match *exclusion {
+		DialExclusion::Useless { .. } => "useless".into(),
+		DialExclusion::Banned { .. } => "banned".into(),
+		DialExclusion::OwnAddress => "own address".into(),
+		DialExclusion::ReservedOnly => "reserved peers only".into(),
+		DialExclusion::Bootstrapped => "boot node not needed".into(),
+		DialExclusion::Backoff { until } => format!("backoff for {}s more", seconds_left(until, now)),
+		DialExclusion::Filtered { ref rule } => format!("filtered by --allow-ips {}", rule),
+		DialExclusion::Capabilities => "no common protocol".into(),
+		DialExclusion::Connected => "connected".into(),
+		DialExclusion::ConnectedIp => "on the IP of a connected peer".into(),
+		DialExclusion::PortCap => "too many ports dialled on its IP".into(),
+	}
+}
+
+/// One line per entry: failure percentage, attempts, flags and URL.
+pub fn format_entries(entries: &[NodeTableEntry], now: SystemTime) -> String {
+	let mut out = String::new();
+	for entry in entries {
+		let _ = write!(out, "{:>3}% {:>4}/{:<4} {:?}", entry.failure_percentage, entry.failures, entry.attempts, entry.source);
+		if entry.banned {
+			out.push_str(" banned");
+		}
+		if entry.useless {
+			out.push_str(" useless");
+		}
+		if let Some(time) = entry.last_contact {
+			let _ = write!(out, " contacted {}", age(time, now));
+		}
+		let _ = writeln!(out, " {}", entry.url);
+	}
+	if entries.is_empty() {
+		out.push_str("No nodes\n");
+	}
+	out
+}
+
+/// All fields of an entry, one per line.
+pub fn format_info(entry: &NodeTableEntry, status: &EntryStatus, now: SystemTime) -> String {
+	let mut out = String::new();
+	let _ = writeln!(out, "id: {:x}", entry.id);
+	let _ = writeln!(out, "url: {}", entry.url);
+	let _ = writeln!(out, "source: {:?}", entry.source);
+	let _ = writeln!(out, "attempts: {}, failures: {} ({}%)", entry.attempts, entry.failures, entry.failure_percentage);
+	let _ = writeln!(out, "last contact: {}", entry.last_contact.map_or_else(|| "never".into(), |time| age(time, now)));
+	if let Some(address) = entry.candidate_address {
+		let _ = writeln!(out, "candidate address: {}", address);
+	}
+	if let Some(address) = entry.previous_address {
+		let _ = writeln!(out, "previous address: {}", address);
+	}
+	for &(address, time) in &entry.address_history {
+		let _ = writeln!(out, "moved from {} {}", address, age(time, now));
+	}
+	if !status.exclusions.is_empty() {
+		let reasons: Vec<String> = status.exclusions.iter().map(|e| describe_exclusion(e, now)).collect();
+		let _ = write!(out, "not dialled: {}", reasons.join(", "));
+		match status.next_eligible {
+			Some(time) => { let _ = writeln!(out, ", eligible in {}s", seconds_left(time, now)); },
+			None => out.push('\n'),
+		}
+	}
+	if let Some(ref reason) = status.ban_reason {
+		let _ = writeln!(out, "banned for {}s more: {}", status.banned_until.map_or(0, |until| seconds_left(until, now)), reason);
+	}
+	if let Some(ref reason) = status.useless_reason {
+		match status.useless_repeats {
+			0 => { let _ = writeln!(out, "useless: {}", reason); },
+			repeats => { let _ = writeln!(out, "useless: {} (repeated {} times)", reason, repeats); },
+		}
+	}
+	if let Some(ref id) = status.mismatched_id {
+		let _ = writeln!(out, "last dial announced another id: {}", id.short());
+	}
+	out
+}
+
+/// Outcome of `nodetable save` given the stats before and after saving.
+pub fn format_save(before: &NodeTableStats, after: &NodeTableStats) -> String {
+	if let Some(ref error) = after.persistence_error {
+		format!("Saving was given up: {}\n", error)
+	} else if after.save_failures > before.save_failures {
+		"Saving failed, see the logs\n".into()
+	} else if after.saves > before.saves {
+		format!("Saved {} nodes\n", after.total)
+	} else {
+		"Not saved, the table is not persisted or did not change\n".into()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::SocketAddr;
+	use network::NodeTableConfig;
+	use node_table::{Node, NodeEndpoint, NodeSource, NodeTable};
+	use persistence::MemoryPersistence;
+	use super::*;
+
+	fn entry() -> NodeTableEntry {
+		let address: SocketAddr = "22.99.55.44:7770".parse().unwrap();
+		NodeTableEntry {
+			id: NodeId::from(1),
+			url: format!("enode://{:x}@{}", NodeId::from(1), address),
+			address: address,
+			attempts: 4,
+			failures: 1,
+			failure_percentage: 25,
+			useless: false,
+			banned: true,
+			stalled: false,
+			last_contact: None,
+			source: NodeSource::Discovery,
+			candidate_address: Some("22.99.55.45:7770".parse().unwrap()),
+			previous_address: None,
+			ban_reason: Some("test".into()),
+			useless_reason: None,
+			address_history: Vec::new(),
+			p2p_version: None,
+			snappy: None,
+			trusted: false,
+			chain_info: None,
+			other_chain: false,
+		}
+	}
+
+	#[test]
+	fn parse_commands() {
+		let id = format!("{:x}", NodeId::from(1));
+		assert_eq!("nodetable list".parse(), Ok(TableCommand::List(DEFAULT_LIST_LIMIT)));
+		assert_eq!("  nodetable   list 5 ".parse(), Ok(TableCommand::List(5)));
+		let node = NodeRef::Id(NodeId::from(1));
+		assert_eq!(format!("nodetable info 0x{}", id).parse(), Ok(TableCommand::Info(node.clone())));
+		assert_eq!(format!("nodetable info enode://{}@22.99.55.44:7770", id).parse(), Ok(TableCommand::Info(node.clone())));
+		assert_eq!(format!("nodetable ban {} 60", id).parse(), Ok(TableCommand::Ban(node.clone(), Duration::from_secs(60))));
+		assert_eq!(format!("nodetable remove {}", id).parse(), Ok(TableCommand::Remove(node)));
+		assert_eq!("nodetable info 0xA979fb".parse(), Ok(TableCommand::Info(NodeRef::Prefix("0xA979fb".into()))));
+		assert_eq!("nodetable save".parse(), Ok(TableCommand::Save));
+	}
+
+	#[test]
+	fn parse_errors() {
+		let id = format!("{:x}", NodeId::from(1));
+		assert_eq!("nodetable list many".parse::<TableCommand>(), Err("Invalid count: many".into()));
+		assert_eq!("nodetable info a979fg".parse::<TableCommand>(), Err("Invalid node id: a979fg".into()));
+		assert_eq!("nodetable info 0x".parse::<TableCommand>(), Err("Invalid node id: 0x".into()));
+		assert_eq!(format!("nodetable ban {} soon", id).parse::<TableCommand>(), Err("Invalid number of seconds: soon".into()));
+		for line in &["", "nodetable", "nodetable drop", "nodetable save now", "nodetable ban", "table list"] {
+			assert_eq!(line.parse::<TableCommand>(), Err(USAGE.into()), "{:?}", line);
+		}
+	}
+
+	#[test]
+	fn format_output() {
+		let now = SystemTime::now();
+		let mut contacted = entry();
+		contacted.banned = false;
+		contacted.last_contact = Some(now - Duration::from_secs(120));
+		let url = entry().url;
+		assert_eq!(format_entries(&[entry(), contacted], now), format!(" 25%    1/4    Discovery banned {}\n 25%    1/4    Discovery contacted 120s ago {}\n", url, url));
+		assert_eq!(format_entries(&[], now), "No nodes\n");
+
+		let status = EntryStatus { ban_reason: Some("test".into()), banned_until: Some(now + Duration::from_secs(60)), ..Default::default() };
+		assert_eq!(format_info(&entry(), &status, now), format!("id: {:x}\nurl: {}\nsource: Discovery\nattempts: 4, failures: 1 (25%)\nlast contact: never\n\
+			candidate address: 22.99.55.45:7770\nbanned for 60s more: test\n", NodeId::from(1), url));
+		let status = EntryStatus { useless_reason: Some("bad block".into()), useless_repeats: 3, ..Default::default() };
+		assert!(format_info(&entry(), &status, now).ends_with("\nuseless: bad block (repeated 3 times)\n"));
+		let exclusions = vec![DialExclusion::Backoff { until: now + Duration::from_secs(30) }, DialExclusion::Filtered { rule: "public".into() }];
+		let status = EntryStatus { exclusions: exclusions, ..Default::default() };
+		assert!(format_info(&entry(), &status, now).ends_with("\nnot dialled: backoff for 30s more, filtered by --allow-ips public\n"));
+		let status = EntryStatus { next_eligible: Some(now + Duration::from_secs(30)), exclusions: vec![DialExclusion::Useless { reason: "test".into(), until: Some(now + Duration::from_secs(30)) }], ..Default::default() };
+		assert!(format_info(&entry(), &status, now).ends_with("\nnot dialled: useless, eligible in 30s\n"));
+
+		let before = NodeTableStats { total: 3, ..Default::default() };
+		assert_eq!(format_save(&before, &NodeTableStats { saves: 1, ..before.clone() }), "Saved 3 nodes\n");
+		assert_eq!(format_save(&before, &before), "Not saved, the table is not persisted or did not change\n");
+		assert_eq!(format_save(&before, &NodeTableStats { save_failures: 1, ..before.clone() }), "Saving failed, see the logs\n");
+	}
+
+	#[test]
+	fn execute_commands() {
+		let persistence = MemoryPersistence::new();
+		let mut persisted = NodeTable::with_persistence(Box::new(persistence.clone()), NodeTableConfig::default());
+		persisted.add_node(Node::new(NodeId::from(1), NodeEndpoint::from_str("22.99.55.1:7770").unwrap()));
+		persisted.add_node(Node::new(NodeId::from(2), NodeEndpoint::from_str("22.99.56.1:7770").unwrap()));
+		let table = SharedNodeTable::new(persisted);
+		let (id1, id2, id3) = (NodeId::from(1), NodeId::from(2), NodeId::from(3));
+		let mut disconnected = Vec::new();
+		let mut run = |line: &str| line.parse::<TableCommand>().unwrap().execute(&table, |id| disconnected.push(*id));
+
+		assert_eq!(run("nodetable save"), "Saved 2 nodes\n");
+		assert_eq!(run("nodetable save"), "Not saved, the table is not persisted or did not change\n");
+		assert_eq!(run("nodetable list 1").lines().count(), 1);
+		assert!(run(&format!("nodetable info {:x}", id2)).starts_with(&format!("id: {:x}\n", id2)));
+		assert_eq!(run(&format!("nodetable info {:x}", id3)), format!("Node {} is not in the table\n", id3.short()));
+		assert_eq!(run(&format!("nodetable ban {:x} 60", id1)), format!("Banned {} for 60s\n", id1.short()));
+		assert!(run(&format!("nodetable info {:x}", id1)).ends_with("banned by the node table command\n"));
+		assert_eq!(run(&format!("nodetable ban {:x} 60", id3)), format!("Banned {} for 60s, it is not in the table\n", id3.short()));
+		// abbreviated ids are looked up in the table
+		assert_eq!(run("nodetable info 0000"), "2 node ids in the table start with the prefix: 0000\n");
+		assert_eq!(run("nodetable remove ff"), "No node id in the table starts with the prefix: ff\n");
+		assert_eq!(run(&format!("nodetable remove {:x}", id2)), format!("Removed {}\n", id2.short()));
+		assert!(run("nodetable info 0x0000").starts_with(&format!("id: {:x}\n", id1)));
+		assert_eq!(run(&format!("nodetable remove {:x}", id2)), format!("Node {} is not in the table\n", id2.short()));
+		assert_eq!(run("nodetable list").lines().count(), 1);
+		assert_eq!(run("nodetable save"), "Saved 1 nodes\n");
+		assert_eq!(disconnected, vec![id1, id3, id2, id2]);
+		assert_eq!(persistence.nodes().len(), 1);
+	}
+}
@@ -0,0 +1,228 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Anonymized node table quality reports for an opt-in telemetry callback.
+
+use std::time::{Duration, Instant};
+use serde_json;
+use node_table::NodeTableStats;
+
+/// Number of failure percentage ranges in `TableTelemetry::failure_distribution`.
+pub const FAILURE_RANGES: usize = 7;
+
+/// Node table quality as reported to the telemetry callback, see `Host::set_table_telemetry`. It only holds counts,
+/// so that no field can carry a node id, an address, a client version or an error message.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TableTelemetry {
+	/// Number of known nodes.
+	pub total: usize,
+	/// Nodes contacted successfully at least once.
+	pub contacted: usize,
+	/// Nodes marked as useless.
+	pub useless: usize,
+	/// Active bans.
+	pub banned: usize,
+	/// Nodes waiting for their dial backoff to expire.
+	pub backed_off: usize,
+	/// Nodes with an IPv4 address.
+	pub ipv4: usize,
+	/// Nodes with an IPv6 address.
+	pub ipv6: usize,
+	/// Known nodes by failure percentage: 0%, up to 10%, 25%, 50%, 75%, 90% and above 90%.
+	pub failure_distribution: [usize; FAILURE_RANGES],
+	/// Open sessions.
+	pub active_sessions: usize,
+	/// Nodes not added to the table for any reason.
+	pub rejected: usize,
+	/// Nodes dropped from the table since the start.
+	pub evicted: usize,
+	/// Our own connectivity is suspected to be down.
+	pub outage: bool,
+	/// Suspected outages since the start.
+	pub outages: usize,
+	/// Successful saves.
+	pub saves: usize,
+	/// Failed or timed out saves.
+	pub save_failures: usize,
+}
+
+impl TableTelemetry {
+	/// Summarize `stats` and the failure distribution of the known nodes, see
+	/// `TableState::failure_distribution`.
+	pub fn new(stats: &NodeTableStats, failure_distribution: [usize; FAILURE_RANGES]) -> Self {
+		TableTelemetry {
+			total: stats.total,
+			contacted: stats.contacted,
+			useless: stats.useless,
+			banned: stats.banned,
+			backed_off: stats.backed_off,
+			ipv4: stats.ipv4,
+			ipv6: stats.ipv6,
+			failure_distribution: failure_distribution,
+			active_sessions: stats.active_sessions,
+			rejected: stats.rejected + stats.subnet_rejected + stats.low_port_rejected + stats.tombstone_rejected + stats.ban_rejected
+				+ stats.admission_rejected.values().sum::<usize>(),
+			evicted: stats.evictions.values().sum(),
+			outage: stats.outage,
+			outages: stats.outages,
+			saves: stats.saves,
+			save_failures: stats.save_failures,
+		}
+	}
+
+	/// Returns the report as a JSON object.
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).expect("the report only holds numbers; qed")
+	}
+}
+
+/// Callback receiving the telemetry reports. It is called on an IO thread and should return quickly.
+pub type TelemetryCallback = Box<Fn(TableTelemetry) + Send>;
+
+/// The callback used until one is registered, it drops the reports.
+pub fn no_telemetry() -> TelemetryCallback {
+	Box::new(|_| {})
+}
+
+/// Telemetry callback with the time of its next report, see `NodeTableConfig::telemetry_interval`.
+pub struct TelemetrySchedule {
+	callback: TelemetryCallback,
+	interval: Duration,
+	next: Option<Instant>,
+}
+
+impl Default for TelemetrySchedule {
+	fn default() -> Self {
+		TelemetrySchedule {
+			callback: no_telemetry(),
+			interval: Duration::from_secs(0),
+			next: None,
+		}
+	}
+}
+
+impl TelemetrySchedule {
+	/// Report to `callback` every `interval` from `now` on. A zero interval never reports.
+	pub fn new(callback: TelemetryCallback, interval: Duration, now: Instant) -> Self {
+		TelemetrySchedule {
+			callback: callback,
+			interval: interval,
+			next: if interval > Duration::from_secs(0) { Some(now + interval) } else { None },
+		}
+	}
+
+	/// Check if a report is due at `now` and schedule the next one if so. Reports missed while the host was busy
+	/// are not made up for.
+	pub fn due(&mut self, now: Instant) -> bool {
+		match self.next {
+			Some(next) if next <= now => {
+				self.next = Some(now + self.interval);
+				true
+			},
+			_ => false,
+		}
+	}
+
+	/// Pass a report to the callback.
+	pub fn report(&self, telemetry: TableTelemetry) {
+		(self.callback)(telemetry)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::str::FromStr;
+	use serde_json::Value;
+	use clock::{Clock, ManualClock};
+	use node_table::{NodeTable, Node, NodeEndpoint, NodeId};
+	use super::*;
+
+	#[test]
+	fn reports_follow_the_interval() {
+		let clock = ManualClock::new();
+		let reports = Arc::new(AtomicUsize::new(0));
+		let counter = reports.clone();
+		let callback: TelemetryCallback = Box::new(move |_| { counter.fetch_add(1, Ordering::SeqCst); });
+		let mut schedule = TelemetrySchedule::new(callback, Duration::from_secs(60), clock.now_instant());
+		let tick = |schedule: &mut TelemetrySchedule| if schedule.due(clock.now_instant()) {
+			schedule.report(TableTelemetry::default());
+		};
+
+		tick(&mut schedule);
+		clock.advance(Duration::from_secs(59));
+		tick(&mut schedule);
+		assert_eq!(reports.load(Ordering::SeqCst), 0);
+		clock.advance(Duration::from_secs(1));
+		tick(&mut schedule);
+		tick(&mut schedule);
+		assert_eq!(reports.load(Ordering::SeqCst), 1);
+
+		// a late tick starts the next interval, missed reports are not made up for
+		clock.advance(Duration::from_secs(150));
+		tick(&mut schedule);
+		clock.advance(Duration::from_secs(59));
+		tick(&mut schedule);
+		assert_eq!(reports.load(Ordering::SeqCst), 2);
+		clock.advance(Duration::from_secs(1));
+		tick(&mut schedule);
+		assert_eq!(reports.load(Ordering::SeqCst), 3);
+
+		// disabled
+		let mut schedule = TelemetrySchedule::default();
+		clock.advance(Duration::from_secs(365 * 24 * 60 * 60));
+		assert!(!schedule.due(clock.now_instant()));
+		let mut schedule = TelemetrySchedule::new(no_telemetry(), Duration::from_secs(0), clock.now_instant());
+		assert!(!schedule.due(clock.now_instant()));
+	}
+
+	#[test]
+	fn built_from_the_table() {
+		let mut table = NodeTable::new(None);
+		for (i, &(attempts, failures)) in [(0, 0), (10, 0), (10, 1), (10, 3), (10, 10), (20, 19)].iter().enumerate() {
+			let mut node = Node::new(NodeId::from(i as u64 + 1), NodeEndpoint::from_str(&format!("22.99.55.{}:30303", i + 1)).unwrap());
+			node.attempts = attempts;
+			node.failures = failures;
+			table.add_node(node);
+		}
+		table.add_node(Node::new(NodeId::from(7), NodeEndpoint::from_str("[2001:db8::1]:30303").unwrap()));
+		table.note_success(&NodeId::from(2));
+
+		let telemetry = table.telemetry();
+		assert_eq!((telemetry.total, telemetry.contacted, telemetry.ipv4, telemetry.ipv6), (7, 1, 6, 1));
+		assert_eq!(telemetry.failure_distribution, [1, 1, 0, 3, 0, 0, 2]);
+	}
+
+	#[test]
+	fn json_holds_only_counts() {
+		let telemetry = TableTelemetry { total: 10, ipv4: 9, ipv6: 1, failure_distribution: [1, 2, 3, 4, 0, 0, 0], outage: true, ..Default::default() };
+		let json: Value = serde_json::from_str(&telemetry.to_json()).unwrap();
+		let object = json.as_object().unwrap();
+		let fields: Vec<&str> = object.keys().map(|k| k.as_str()).collect();
+		let mut expected = vec![
+			"total", "contacted", "useless", "banned", "backed_off", "ipv4", "ipv6", "failure_distribution",
+			"active_sessions", "rejected", "evicted", "outage", "outages", "saves", "save_failures",
+		];
+		expected.sort();
+		assert_eq!(fields, expected);
+		for (key, value) in object {
+			let counts = value.as_array().map_or(false, |values| values.iter().all(Value::is_u64));
+			assert!(value.is_u64() || value.is_boolean() || counts, "{} is not a count", key);
+		}
+		assert_eq!(json["failure_distribution"], serde_json::to_value(&[1, 2, 3, 4, 0, 0, 0]).unwrap());
+	}
+}
@@ -29,7 +29,7 @@ use std::time::*;
 use parking_lot::Mutex;
 use ethcore_bytes::Bytes;
 use ethcore_network::*;
-use ethcore_network_devp2p::NetworkService;
+use ethcore_network_devp2p::{NetworkService, Node, NodeEndpoint, NodeId, NodeSource, PeerType};
 use ethkey::{Random, Generator};
 use io::TimerToken;
 
@@ -147,3 +147,38 @@ fn net_timeout() {
 		thread::sleep(Duration::from_millis(50));
 	}
 }
+
+#[test]
+fn node_built_externally() {
+	let endpoint: NodeEndpoint = "22.99.55.44:7770".parse().unwrap();
+	let capabilities = vec![PeerCapabilityInfo { protocol: *b"eth", version: 63 }];
+	let node = Node::builder(NodeId::from(1), endpoint.clone())
+		.peer_type(PeerType::Required)
+		.source(NodeSource::Rpc)
+		.trusted()
+		.required_for(*b"les")
+		.stats(10, 2, 8)
+		.hello("Parity/v1.11.0".into(), capabilities.clone())
+		.build();
+
+	let copy = node.clone();
+	assert_eq!(copy, node);
+	assert_eq!(copy.endpoint, endpoint);
+	match copy.peer_type {
+		PeerType::Required => {},
+		PeerType::Optional => panic!("{:?} is not required", copy),
+	}
+	assert!(copy.trusted && copy.is_required_for(Some(b"les")));
+	assert_eq!((copy.attempts, copy.failures, copy.successes, copy.failure_percentage()), (10, 2, 8, 20));
+	assert_eq!(copy.capabilities, Some(capabilities));
+
+	// the state kept up by the table can only be read
+	assert!(copy.sources().contains(NodeSource::Rpc) && !copy.is_untrusted());
+	assert!(copy.candidate_endpoint().is_none() && copy.previous_endpoint().is_none());
+	assert!(copy.address_history().is_empty() && copy.tags().is_empty());
+
+	// plain nodes start optional and unknown
+	let plain = Node::new(NodeId::from(2), endpoint);
+	assert_eq!((plain.peer_type, plain.source), (PeerType::Optional, NodeSource::Unknown));
+	assert_eq!(plain.to_string(), "enode://00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002@22.99.55.44:7770");
+}
@@ -131,6 +131,18 @@ error_chain! {
 			display("Invalid node id"),
 		}
 
+		#[doc = "Enode URL is longer than the given limit"]
+		NodeUrlTooLong(max: usize) {
+			description("Enode URL is too long"),
+			display("Enode URL longer than {} bytes", max),
+		}
+
+		#[doc = "Invalid boot nodes"]
+		InvalidBootNodes(errors: Vec<String>) {
+			description("Invalid boot nodes"),
+			display("Invalid boot nodes: {}", errors.join("; ")),
+		}
+
 		#[doc = "Packet size is over the protocol limit"]
 		OversizedPacket {
 			description("Packet is too large"),
@@ -33,10 +33,13 @@ pub use io::TimerToken;
 pub use error::{Error, ErrorKind, DisconnectReason};
 
 use std::cmp::Ordering;
+use std::fmt;
 use std::collections::HashMap;
 use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr};
+use std::path::PathBuf;
 use std::str::{self, FromStr};
 use std::sync::Arc;
+use std::time::Duration;
 use ipnetwork::{IpNetwork, IpNetworkError};
 use io::IoChannel;
 use ethkey::Secret;
@@ -199,6 +202,13 @@ pub struct NetworkConfiguration {
 	pub ip_filter: IpFilter,
 	/// Client identifier
 	pub client_version: String,
+	/// Minimum score difference for a node table candidate to replace a connected optional peer when all slots are
+	/// taken. `None` disables eviction.
+	pub eviction_margin: Option<u32>,
+	/// Save the node table to `net_config_path`. When disabled the node table is kept in memory only.
+	pub persist_nodes: bool,
+	/// Node table tuning.
+	pub node_table: NodeTableConfig,
 }
 
 impl Default for NetworkConfiguration {
@@ -228,6 +238,9 @@ impl NetworkConfiguration {
 			reserved_nodes: Vec::new(),
 			non_reserved_mode: NonReservedPeerMode::Accept,
 			client_version: "Parity-network".into(),
+			eviction_margin: Some(25),
+			persist_nodes: true,
+			node_table: NodeTableConfig::default(),
 		}
 	}
 
@@ -247,6 +260,58 @@ impl NetworkConfiguration {
 	}
 }
 
+/// Chain a peer advertised in its status message, see `PeerAnnotator::set_chain_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerChainInfo {
+	/// Network id.
+	pub network_id: u64,
+	/// Hash of the genesis block.
+	pub genesis: H256,
+	/// EIP-2124 fork hash, `None` if the protocol does not advertise one.
+	pub fork_hash: Option<u32>,
+}
+
+impl PeerChainInfo {
+	/// Whether `other` is on another chain. Fork hashes are only compared if both are known.
+	pub fn mismatches(&self, other: &PeerChainInfo) -> bool {
+		self.network_id != other.network_id || self.genesis != other.genesis || match (self.fork_hash, other.fork_hash) {
+			(Some(ours), Some(theirs)) => ours != theirs,
+			_ => false,
+		}
+	}
+}
+
+/// Severity of peer misbehaviour observed by a protocol handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	/// Minor issue, e.g. a timed out or empty response.
+	Minor,
+	/// Protocol violation, e.g. a malformed or unrequested response.
+	Major,
+	/// Malicious behaviour, e.g. serving invalid data. The node gets banned for a while.
+	Critical,
+}
+
+/// Reports peer quality observed by a protocol handler to the node table.
+pub trait PeerAnnotator {
+	/// Report misbehaviour of a peer.
+	fn report_misbehaviour(&self, peer: PeerId, severity: Severity);
+
+	/// Report that a peer has served useful data.
+	fn report_useful(&self, peer: PeerId);
+
+	/// Report the time a peer took to respond to a request.
+	fn report_latency(&self, peer: PeerId, latency: Duration);
+
+	/// Record a value for a peer under `key`, e.g. how fast it served a kind of request. It is saved with the node
+	/// table and kept across restarts.
+	fn tag(&self, peer: PeerId, key: &str, value: u32);
+
+	/// Record the chain a peer advertised, e.g. in its status message. It is saved with the node table, nodes on
+	/// another chain than ours are dialled last.
+	fn set_chain_info(&self, peer: PeerId, info: PeerChainInfo);
+}
+
 /// IO access point. This is passed to all IO handlers and provides an interface to the IO subsystem.
 pub trait NetworkContext {
 	/// Send a packet over the network to another peer.
@@ -284,6 +349,9 @@ pub trait NetworkContext {
 
 	/// Returns this object's subprotocol name.
 	fn subprotocol_name(&self) -> ProtocolId;
+
+	/// Returns a handle to annotate peers of this session in the node table.
+	fn peer_annotator(&self) -> &PeerAnnotator;
 }
 
 impl<'a, T> NetworkContext for &'a T where T: ?Sized + NetworkContext {
@@ -334,6 +402,10 @@ impl<'a, T> NetworkContext for &'a T where T: ?Sized + NetworkContext {
 	fn subprotocol_name(&self) -> ProtocolId {
 		(**self).subprotocol_name()
 	}
+
+	fn peer_annotator(&self) -> &PeerAnnotator {
+		(**self).peer_annotator()
+	}
 }
 
 pub trait HostInfo {
@@ -423,6 +495,197 @@ impl IpFilter {
     }
 }
 
+impl fmt::Display for IpFilter {
+    /// Formats the filter in the form accepted by `IpFilter::parse`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let predefined = match self.predefined {
+            AllowIP::All => "all",
+            AllowIP::Private => "private",
+            AllowIP::Public => "public",
+            AllowIP::None => "none",
+        };
+        write!(f, "{}", predefined)?;
+        for network in &self.custom_allow {
+            write!(f, " {}", network)?;
+        }
+        for network in &self.custom_block {
+            write!(f, " -{}", network)?;
+        }
+        Ok(())
+    }
+}
+
+/// Node table tuning
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NodeTableConfig {
+	/// Maximum number of nodes kept when the table is refreshed or saved.
+	pub max_nodes: usize,
+	/// Percentage of `max_nodes` reserved for the IPv4 and for the IPv6 nodes each, so that one address family cannot
+	/// push out the other. Only applies while both families are present.
+	pub family_quota: usize,
+	/// Maximum size of the saved table in bytes, the worst nodes are left out to stay within it. `None` means no
+	/// limit.
+	pub max_file_bytes: Option<usize>,
+	/// Encrypt the saved table to the node key so that it does not reveal the addresses of known nodes. Plain and
+	/// encrypted files are both loaded, the file is converted on the next save.
+	pub encrypt_file: bool,
+	/// Maximum number of nodes from the same /24 (IPv4) or /48 (IPv6) subnet. `None` means no limit.
+	pub max_nodes_per_subnet: Option<usize>,
+	/// Dial delay after the first failure, doubled on each consecutive failure.
+	pub initial_backoff: Duration,
+	/// Maximum dial delay for failing nodes.
+	pub max_backoff: Duration,
+	/// Nodes not contacted successfully for this long are dropped when the table is refreshed. Nodes that were never
+	/// contacted are kept. `None` keeps all nodes.
+	pub max_age: Option<Duration>,
+	/// How often the table is refreshed and saved.
+	pub autosave_interval: Duration,
+	/// Longest dial delay for failing boot nodes, and how long a boot node marked useless stays excluded.
+	pub boot_retry_interval: Duration,
+	/// How long discovery may not add a node again after it was removed.
+	pub tombstone_grace: Duration,
+	/// How long the final save on shutdown may take before it is abandoned.
+	pub final_save_timeout: Duration,
+	/// Nodes contacted successfully within this long are ordered before all others regardless of their failure
+	/// rate. `None` orders by failure rate only.
+	pub recent_contact: Option<Duration>,
+	/// Order nodes that rank equal randomly on each selection instead of by node id, to spread the dials of nodes
+	/// sharing a table over the same peers.
+	pub shuffle_ties: bool,
+	/// How long after a start the nodes connected at the last clean shutdown are dialled before any other. Zero
+	/// disables the warm start.
+	pub warm_start: Duration,
+	/// Network the table is used on, e.g. the network id and genesis hash. It is saved with the table and a table
+	/// saved on another network is not loaded. `None` loads any table.
+	pub network_id: Option<String>,
+	/// Keep the table of each network in its own file, named after `network_id`, so that switching networks keeps
+	/// the table of each.
+	pub per_network_files: bool,
+	/// Dial failures in this many distinct /24 (IPv4) or /48 (IPv6) subnets within `outage_window` without a
+	/// success in between are taken for an outage of our own connectivity. The failures during an outage are not
+	/// counted against the nodes. Zero disables the detection.
+	pub outage_subnets: usize,
+	/// Window of the dial failures counted for `outage_subnets`.
+	pub outage_window: Duration,
+	/// Longest time failures are not counted during an outage without a success, they count again after it.
+	pub outage_max: Duration,
+	/// Keep the saved entries that break the current IP filter, subnet limit, minimum port, maximum age or size limit,
+	/// or that point at our own address, instead of dropping them once at start.
+	pub raw_load: bool,
+	/// Refuse to start if any boot node is not a valid enode URL or address, instead of skipping it.
+	pub strict_boot_nodes: bool,
+	/// Nodes written by `export_node_list` need at least this many established sessions.
+	pub export_min_successes: u32,
+	/// Nodes written by `export_node_list` need a failure percentage of at most this, see `Node::failure_bucket`.
+	pub export_max_failure_bucket: usize,
+	/// Boot nodes are only dialled when no peers are connected once the table holds this many tried nodes with a
+	/// failure percentage of at most `bootstrapped_max_failure`. 0 keeps dialling them.
+	pub bootstrapped_nodes: usize,
+	/// See `bootstrapped_nodes`.
+	pub bootstrapped_max_failure: usize,
+	/// Maximum number of distinct ports dialled on the same IP within `port_window`, so that many entries on one
+	/// host do not make us look like a port scanner. Nodes on further ports are dialled once the window passed,
+	/// required nodes are not limited. `None` means no limit.
+	pub max_ports_per_ip: Option<usize>,
+	/// See `max_ports_per_ip`.
+	pub port_window: Duration,
+	/// TCP endpoints of other instances on this host, e.g. running another chain with an overlapping node table.
+	/// Nodes at them are never added or selected, like nodes at our own endpoints. An unspecified IP matches the
+	/// port on the loopback addresses and on our own IPs.
+	pub sibling_endpoints: Vec<SocketAddr>,
+	/// Register our listen address in the instances file in the network configuration directory and take the
+	/// addresses registered by the other instances as `sibling_endpoints`.
+	pub sibling_registry: bool,
+	/// Save the hourly connection outcomes of the last day with the table, see `NodeTableStats::history`.
+	pub save_outcome_history: bool,
+	/// File of curated peers, one enode URL per line, `!` in front for required ones. Loaded at start and again on
+	/// `reload_static_peers`.
+	pub static_peers: Option<PathBuf>,
+	/// Do not select nodes we have a session with or that share the IP of one. Turn off when many nodes
+	/// legitimately run behind one IP.
+	pub exclude_connected: bool,
+	/// Maximum number of new or moved nodes taken from one discovery update, verified ones first. The rest is
+	/// dropped so that a flood from a broken discovery does not hold the table lock for long.
+	pub max_update_added: usize,
+	/// Maximum number of nodes removed by one discovery update, see `max_update_added`.
+	pub max_update_removed: usize,
+	/// A table saved longer ago than this is stale: its nodes are dialled by the reliability saved with them instead
+	/// of their statistics until the most reliable ones answer discovery pings again, and the warm start is skipped.
+	/// Zero disables it.
+	pub stale_file_age: Duration,
+	/// How often the table quality is reported to a telemetry callback registered by the application. Nothing is
+	/// reported without one. Zero disables the reports.
+	pub telemetry_interval: Duration,
+	/// Select at most one node of each operator cluster per dial round: nodes in one /24 (IPv4) or /48 (IPv6)
+	/// subnet that report the same client version, which add little diversity when connected together.
+	pub operator_clusters: bool,
+	/// Marking a node as useless again with the same reason within this window, e.g. by a protocol handler for each
+	/// failed request, is only counted and does not change the table. Zero processes every mark.
+	pub useless_cooldown: Duration,
+	/// Enode URLs of nodes whose own claims are taken at face value, e.g. the own infrastructure of a private network.
+	/// They are added to the table at start, unlike reserved peers they are not dialled preferentially.
+	pub trusted_nodes: Vec<String>,
+	/// Lowest TCP and UDP port of new nodes, so that nodes announcing e.g. SSH or SMTP ports are not dialled.
+	/// Reserved and trusted nodes are exempt. Private networks may lower it, zero ports are never dialled.
+	pub min_port: u16,
+	/// Delay between the maintenance runs of the table while no peers are connected, see
+	/// `NodeTable::maintenance`. These runs dial the nodes whose backoff ended, ping a batch of a stale sweep and
+	/// save the table more often than `autosave_interval`, the refresh keeps to `autosave_interval`.
+	pub maintenance_min_interval: Duration,
+	/// Delay between the maintenance runs of the table once enough peers are connected.
+	pub maintenance_max_interval: Duration,
+}
+
+impl Default for NodeTableConfig {
+	fn default() -> Self {
+		NodeTableConfig {
+			max_nodes: 1024,
+			family_quota: 10,
+			max_file_bytes: None,
+			encrypt_file: false,
+			max_nodes_per_subnet: None,
+			initial_backoff: Duration::from_secs(5),
+			max_backoff: Duration::from_secs(10 * 60),
+			max_age: None,
+			autosave_interval: Duration::from_secs(5 * 60),
+			boot_retry_interval: Duration::from_secs(2 * 60),
+			tombstone_grace: Duration::from_secs(10 * 60),
+			final_save_timeout: Duration::from_secs(2),
+			recent_contact: None,
+			shuffle_ties: false,
+			warm_start: Duration::from_secs(5 * 60),
+			network_id: None,
+			per_network_files: false,
+			outage_subnets: 5,
+			outage_window: Duration::from_secs(30),
+			outage_max: Duration::from_secs(10 * 60),
+			raw_load: false,
+			strict_boot_nodes: false,
+			export_min_successes: 1,
+			export_max_failure_bucket: 20,
+			bootstrapped_nodes: 30,
+			bootstrapped_max_failure: 25,
+			max_ports_per_ip: None,
+			port_window: Duration::from_secs(10 * 60),
+			sibling_endpoints: Vec::new(),
+			sibling_registry: false,
+			save_outcome_history: true,
+			static_peers: None,
+			exclude_connected: true,
+			max_update_added: 256,
+			max_update_removed: 1024,
+			stale_file_age: Duration::from_secs(7 * 24 * 60 * 60),
+			telemetry_interval: Duration::from_secs(60 * 60),
+			operator_clusters: false,
+			useless_cooldown: Duration::from_secs(60),
+			trusted_nodes: Vec::new(),
+			min_port: 1024,
+			maintenance_min_interval: Duration::from_secs(2),
+			maintenance_max_interval: Duration::from_secs(60),
+		}
+	}
+}
+
 /// IP fiter
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AllowIP {